@@ -9,17 +9,51 @@ import {IMLDSAVerifier} from "./interfaces/IMLDSAVerifier.sol";
 /// @title PQValidatorModule
 /// @notice ERC-7579 validator module that delegates signature verification
 ///         to an Arbitrum Stylus contract performing ML-DSA (FIPS 204) verification.
+/// @dev A userOp signature is either a bare ML-DSA-65 signature by the stored key,
+///      or a delegated envelope (`pq-delegate`): `0x03 || abi.encode(Delegation,
+///      bytes ownerSig, uint8 schemeId, bytes robotKey, bytes robotSig)`, where the
+///      stored key granted a robot key the right to sign until `validUntil`.
+///      A time-boxed or time-locked signature (`pq-userop sign --valid-for/--valid-after`)
+///      is `validAfter (6) || validUntil (6) || signature`, over the userOpHash bound to
+///      the chain and the window.
 contract PQValidatorModule is IValidator {
     error InvalidMLDSAPublicKeyLength(uint256 actual, uint256 expected);
     bytes4 internal constant ERC1271_VALID = 0x1626ba7e;
     bytes4 internal constant ERC1271_INVALID = 0xffffffff;
 
+    uint256 internal constant MLDSA_SIGNATURE_LENGTH = 3309;
+    uint256 internal constant WINDOW_PREFIX_LENGTH = 12;
+    uint8 internal constant DELEGATED_ENVELOPE = 3;
+    uint8 internal constant SCHEME_ML_DSA_65 = 1;
+
+    bytes32 public constant DELEGATION_TYPEHASH = keccak256(
+        "PQDelegation(address account,uint256 chainId,bytes32 robotKeyHash,uint48 validAfter,uint48 validUntil,bytes32 policyHash)"
+    );
+
+    /// @notice An owner key's grant to a robot key
+    /// @param chainId 0 for any chain
+    /// @param robotKeyHash keccak256 of the robot's ML-DSA-65 public key
+    /// @param policyHash Signing policy the robot's signer enforces off chain; not checked here
+    struct Delegation {
+        address account;
+        uint256 chainId;
+        bytes32 robotKeyHash;
+        uint48 validAfter;
+        uint48 validUntil;
+        bytes32 policyHash;
+    }
+
+    event DelegationRevoked(address indexed account, bytes32 indexed delegationHash);
+
     /// @notice The Stylus ML-DSA verifier contract
     IMLDSAVerifier public immutable verifier;
 
     /// @notice Stored ML-DSA public keys, keyed by smart account address
     mapping(address => bytes) internal publicKeys;
 
+    /// @notice Delegations each smart account has revoked, by `delegationHash`
+    mapping(address => mapping(bytes32 => bool)) public revokedDelegations;
+
     constructor(address _verifier) {
         verifier = IMLDSAVerifier(_verifier);
     }
@@ -44,6 +78,17 @@ contract PQValidatorModule is IValidator {
         return moduleTypeId == MODULE_TYPE_VALIDATOR;
     }
 
+    /// @notice What the owner key signs for a delegation, and the ID that revokes it
+    function delegationHash(Delegation memory d) public pure returns (bytes32) {
+        return keccak256(abi.encode(DELEGATION_TYPEHASH, d));
+    }
+
+    /// @notice Stop accepting the caller's delegation `hash` before it expires
+    function revokeDelegation(bytes32 hash) external {
+        revokedDelegations[msg.sender][hash] = true;
+        emit DelegationRevoked(msg.sender, hash);
+    }
+
     function validateUserOp(
         PackedUserOperation calldata userOp,
         bytes32 userOpHash
@@ -51,10 +96,58 @@ contract PQValidatorModule is IValidator {
         if (!isInitialized(msg.sender)) return VALIDATION_FAILED;
         bytes memory mlDSAPubKey = publicKeys[msg.sender];
         bytes calldata userSig = userOp.signature;
+        if (userSig.length == WINDOW_PREFIX_LENGTH + MLDSA_SIGNATURE_LENGTH) {
+            return _validateWindowed(mlDSAPubKey, userSig, userOpHash);
+        }
+        if (
+            userSig.length != MLDSA_SIGNATURE_LENGTH &&
+            userSig.length > 0 &&
+            uint8(userSig[0]) == DELEGATED_ENVELOPE
+        ) {
+            return _validateDelegated(mlDSAPubKey, userSig[1:], userOpHash);
+        }
         bool isVerified = verifier.verify(mlDSAPubKey, userOpHash, userSig);
         return isVerified ? VALIDATION_SUCCESS : VALIDATION_FAILED;
     }
 
+    /// @dev The EntryPoint enforces the window from the returned validationData, so a
+    ///      time-locked op is refused until `validAfter`.
+    function _validateWindowed(
+        bytes memory key,
+        bytes calldata userSig,
+        bytes32 userOpHash
+    ) internal view returns (uint256) {
+        uint48 validAfter = uint48(bytes6(userSig[0:6]));
+        uint48 validUntil = uint48(bytes6(userSig[6:12]));
+        bytes32 hash = keccak256(abi.encodePacked(userOpHash, block.chainid, validAfter, validUntil));
+        if (!verifier.verify(key, hash, userSig[WINDOW_PREFIX_LENGTH:])) return VALIDATION_FAILED;
+        return (uint256(validAfter) << 208) | (uint256(validUntil) << 160);
+    }
+
+    /// @dev Both signatures must verify and the grant must cover this account and
+    ///      chain; the EntryPoint enforces the window from the returned validationData.
+    function _validateDelegated(
+        bytes memory ownerKey,
+        bytes calldata body,
+        bytes32 userOpHash
+    ) internal view returns (uint256) {
+        (
+            Delegation memory d,
+            bytes memory ownerSig,
+            uint8 schemeId,
+            bytes memory robotKey,
+            bytes memory robotSig
+        ) = abi.decode(body, (Delegation, bytes, uint8, bytes, bytes));
+        if (schemeId != SCHEME_ML_DSA_65 || d.account != msg.sender) return VALIDATION_FAILED;
+        if (d.chainId != 0 && d.chainId != block.chainid) return VALIDATION_FAILED;
+        if (keccak256(robotKey) != d.robotKeyHash) return VALIDATION_FAILED;
+        bytes32 hash = delegationHash(d);
+        if (revokedDelegations[msg.sender][hash]) return VALIDATION_FAILED;
+        if (!verifier.verify(ownerKey, hash, ownerSig)) return VALIDATION_FAILED;
+        if (!verifier.verify(robotKey, userOpHash, robotSig)) return VALIDATION_FAILED;
+        return (uint256(d.validAfter) << 208) | (uint256(d.validUntil) << 160);
+    }
+
     // Bind sender-aware ERC-1271 checks to module/account/chain context so the
     // same signature cannot be replayed across different callers/protocols.
     function isValidSignatureWithSender(
@@ -218,4 +218,107 @@ contract PQValidatorStubTest is Test {
         assertTrue(validator.isInitialized(account1));
         assertTrue(validator.isInitialized(account2));
     }
+
+    // ─── Time-locked signatures ────────────────────────────────────────
+
+    function test_validateUserOp_windowed_returnsWindow() public {
+        address smartAccount = makeAddr("account1");
+        vm.prank(smartAccount);
+        validator.onInstall(dummyPubKey);
+        bytes32 userOpHash = bytes32(uint256(1));
+        bytes memory sig = new bytes(3309);
+        uint48 validAfter = 90_000;
+        uint48 validUntil = type(uint48).max;
+
+        PackedUserOperation memory userOp;
+        userOp.signature = abi.encodePacked(validAfter, validUntil, sig);
+        bytes32 hash = keccak256(abi.encodePacked(userOpHash, block.chainid, validAfter, validUntil));
+        bytes memory call = abi.encodeWithSelector(IMLDSAVerifier.verify.selector, dummyPubKey, hash, sig);
+        vm.expectCall(mockVerifier, call);
+        vm.mockCall(mockVerifier, call, abi.encode(true));
+
+        vm.prank(smartAccount);
+        uint256 result = validator.validateUserOp(userOp, userOpHash);
+        assertEq(result, (uint256(validAfter) << 208) | (uint256(validUntil) << 160));
+
+        vm.mockCall(mockVerifier, call, abi.encode(false));
+        vm.prank(smartAccount);
+        assertEq(validator.validateUserOp(userOp, userOpHash), VALIDATION_FAILED);
+    }
+
+    // ─── Delegated robot keys ──────────────────────────────────────────
+
+    function _delegatedOp(address account, bytes memory robotKey, uint256 chainId)
+        internal
+        view
+        returns (PackedUserOperation memory userOp, bytes32 hash)
+    {
+        PQValidatorModule.Delegation memory d = PQValidatorModule.Delegation({
+            account: account,
+            chainId: chainId,
+            robotKeyHash: keccak256(robotKey),
+            validAfter: 1_000,
+            validUntil: 2_000,
+            policyHash: bytes32(0)
+        });
+        hash = validator.delegationHash(d);
+        userOp.signature = abi.encodePacked(
+            uint8(3),
+            abi.encode(d, hex"0e", uint8(1), robotKey, hex"0f")
+        );
+    }
+
+    function test_validateUserOp_delegated_returnsWindow() public {
+        address smartAccount = makeAddr("account1");
+        vm.prank(smartAccount);
+        validator.onInstall(dummyPubKey);
+        bytes memory robotKey = hex"0102";
+        (PackedUserOperation memory userOp, bytes32 hash) =
+            _delegatedOp(smartAccount, robotKey, block.chainid);
+        bytes32 userOpHash = bytes32(uint256(1));
+
+        bytes memory ownerCall =
+            abi.encodeWithSelector(IMLDSAVerifier.verify.selector, dummyPubKey, hash, hex"0e");
+        bytes memory robotCall =
+            abi.encodeWithSelector(IMLDSAVerifier.verify.selector, robotKey, userOpHash, hex"0f");
+        vm.expectCall(mockVerifier, ownerCall);
+        vm.expectCall(mockVerifier, robotCall);
+        vm.mockCall(mockVerifier, ownerCall, abi.encode(true));
+        vm.mockCall(mockVerifier, robotCall, abi.encode(true));
+
+        vm.prank(smartAccount);
+        uint256 result = validator.validateUserOp(userOp, userOpHash);
+        assertEq(result, (uint256(1_000) << 208) | (uint256(2_000) << 160));
+    }
+
+    function test_validateUserOp_delegated_rejectsRevokedAndForeign() public {
+        address smartAccount = makeAddr("account1");
+        vm.prank(smartAccount);
+        validator.onInstall(dummyPubKey);
+        vm.mockCall(
+            mockVerifier,
+            abi.encodeWithSelector(IMLDSAVerifier.verify.selector),
+            abi.encode(true)
+        );
+
+        (PackedUserOperation memory otherChain,) =
+            _delegatedOp(smartAccount, hex"0102", block.chainid + 1);
+        vm.prank(smartAccount);
+        assertEq(validator.validateUserOp(otherChain, bytes32(0)), VALIDATION_FAILED);
+
+        (PackedUserOperation memory otherAccount,) =
+            _delegatedOp(makeAddr("account2"), hex"0102", 0);
+        vm.prank(smartAccount);
+        assertEq(validator.validateUserOp(otherAccount, bytes32(0)), VALIDATION_FAILED);
+
+        (PackedUserOperation memory userOp, bytes32 hash) = _delegatedOp(smartAccount, hex"0102", 0);
+        vm.prank(smartAccount);
+        assertTrue(validator.validateUserOp(userOp, bytes32(0)) != VALIDATION_FAILED);
+
+        vm.prank(smartAccount);
+        validator.revokeDelegation(hash);
+        assertTrue(validator.revokedDelegations(smartAccount, hash));
+        vm.prank(smartAccount);
+        assertEq(validator.validateUserOp(userOp, bytes32(0)), VALIDATION_FAILED);
+    }
 }
@@ -0,0 +1,22 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use pq_cli::bench::{Fixture, Stage};
+use pq_cli::keystore::KdfParams;
+
+fn benchmarks(c: &mut Criterion) {
+    let fixture = Fixture::new(KdfParams::default()).unwrap();
+
+    let mut group = c.benchmark_group("pipeline");
+    for stage in Stage::ALL {
+        // Argon2id at production cost takes tens of milliseconds per run.
+        if stage == Stage::KeystoreDecrypt {
+            group.sample_size(10);
+        }
+        group.bench_function(stage.to_string(), |b| {
+            b.iter(|| stage.run(&fixture).unwrap());
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmarks);
+criterion_main!(benches);
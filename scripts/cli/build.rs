@@ -0,0 +1,13 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // protox parses the .proto files itself, so no protoc is needed.
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto");
+        let fds = protox::compile(["pq/signer/v1/signer.proto"], ["proto"])
+            .expect("proto/pq/signer/v1/signer.proto");
+        tonic_prost_build::configure()
+            .compile_fds(fds)
+            .expect("generate the pq.signer.v1 service");
+    }
+}
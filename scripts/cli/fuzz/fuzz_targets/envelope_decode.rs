@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pq_cli::fuzz::EnvelopeInput;
+
+fuzz_target!(|input: EnvelopeInput| {
+    pq_cli::fuzz::envelope_decode(&input);
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pq_cli::fuzz::KeystoreBlob;
+
+fuzz_target!(|input: (KeystoreBlob, Vec<u8>)| {
+    let (blob, password) = input;
+    pq_cli::fuzz::keystore_decrypt(&blob, &password);
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use alloy_primitives::{Address, U256};
+use libfuzzer_sys::fuzz_target;
+use pq_cli::userop::PackedUserOperation;
+
+fuzz_target!(|input: (PackedUserOperation, [u8; 20], u64)| {
+    let (op, entry_point, chain_id) = input;
+    pq_cli::fuzz::user_op_hash(&op, Address::from(entry_point), U256::from(chain_id));
+});
@@ -0,0 +1,80 @@
+/*
+ * C interface to pq-cli for mobile host apps (`--features ffi`).
+ * See src/ffi.rs for the unlock flow; keep this file in sync with it.
+ */
+
+#ifndef PQ_WALLET_H
+#define PQ_WALLET_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define PQ_OK 0
+/* A null pointer, invalid UTF-8 or malformed account JSON. */
+#define PQ_ERR_ARGUMENT 1
+/* The user dismissed or failed the biometric prompt. */
+#define PQ_ERR_CANCELLED 2
+/* The unwrapped key does not open the entry. */
+#define PQ_ERR_DECRYPT 3
+/* The OS keystore failed, or the entry belongs to another platform. */
+#define PQ_ERR_KEYSTORE 4
+/* An output buffer is too small; wrap sets *out_len to the size needed. */
+#define PQ_ERR_BUFFER 5
+
+#define PQ_PUBLIC_KEY_LEN 1952
+#define PQ_SIGNATURE_LEN 3309
+
+/* The host's OS keystore (Secure Enclave, Android Keystore). */
+typedef struct PqPlatform {
+    /* Passed back to the callbacks unchanged. */
+    void *ctx;
+    /* e.g. "secure-enclave" or "android-keystore". */
+    const char *name;
+    /* Wrap the 32 bytes at key with the OS key alias, creating it if it does
+     * not exist. Write at most *out_len bytes to out, and set *out_len to the
+     * length written. */
+    int32_t (*wrap)(void *ctx, const char *alias, const uint8_t *key,
+                    uint8_t *out, size_t *out_len);
+    /* Authenticate the user, showing reason, then unwrap wrapped with the OS
+     * key alias into the 32 bytes at out. Return PQ_ERR_CANCELLED if the user
+     * dismisses the prompt. */
+    int32_t (*unwrap)(void *ctx, const char *alias, const uint8_t *wrapped,
+                      size_t wrapped_len, const char *reason, uint8_t *out);
+} PqPlatform;
+
+/* An unlocked account. */
+typedef struct PqSession PqSession;
+
+/* Create a keystore entry for a new seed; free *out_json with
+ * pq_string_free. */
+int32_t pq_account_create(const PqPlatform *platform, const char *name,
+                          const char *alias, char **out_json);
+
+/* Unlock an entry; the platform's unwrap prompts the user with reason. */
+int32_t pq_unlock(const PqPlatform *platform, const char *account_json,
+                  const char *reason, PqSession **out_session);
+
+/* Sign the 32 bytes at hash into PQ_SIGNATURE_LEN bytes at out_signature. */
+int32_t pq_session_sign_hash(const PqSession *session, const uint8_t *hash,
+                             uint8_t *out_signature);
+
+/* Write PQ_PUBLIC_KEY_LEN bytes of public key to out. */
+int32_t pq_session_public_key(const PqSession *session, uint8_t *out);
+
+/* Zeroize and free a session. */
+void pq_session_free(PqSession *session);
+
+void pq_string_free(char *s);
+
+/* The last failure on this thread; valid until the next failing call. */
+const char *pq_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* PQ_WALLET_H */
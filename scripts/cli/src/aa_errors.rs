@@ -0,0 +1,449 @@
+//! Human-readable diagnostics for EntryPoint `AAxx` failures and the revert
+//! selectors a PQ UserOperation can hit.
+//!
+//! Bundlers pass EntryPoint reasons through as bare strings ("AA21 didn't
+//! pay prefund") or hex revert data. [`diagnose_message`] and
+//! [`diagnose_revert`] turn either into a [`Diagnostic`] with a description
+//! and a suggested fix; given the op, the fix can be specific (signature
+//! length, missing initCode, gas limits).
+
+use std::fmt;
+
+use alloy_primitives::keccak256;
+use serde::Serialize;
+
+use crate::envelope::PACKED_ML_DSA_65_LEN;
+use crate::error::WalletError;
+use crate::keys::SIGNATURE_LEN;
+use crate::rpc;
+use crate::simulate::{Revert, Simulation};
+use crate::userop::PackedUserOperation;
+
+/// One EntryPoint v0.7 `FailedOp` reason code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AaCode {
+    pub code: &'static str,
+    /// The reason string the EntryPoint reverts with.
+    pub reason: &'static str,
+    pub description: &'static str,
+    pub fix: &'static str,
+}
+
+macro_rules! aa {
+    ($code:literal, $reason:literal, $description:literal, $fix:literal) => {
+        AaCode {
+            code: $code,
+            reason: $reason,
+            description: $description,
+            fix: $fix,
+        }
+    };
+}
+
+/// Every `AAxx` code EntryPoint v0.7 emits.
+pub const AA_CODES: &[AaCode] = &[
+    aa!(
+        "AA10",
+        "sender already constructed",
+        "initCode was set but the sender already has code",
+        "clear factory/factoryData for a deployed account"
+    ),
+    aa!(
+        "AA13",
+        "initCode failed or OOG",
+        "the factory call reverted or ran out of gas",
+        "check factoryData and raise verificationGasLimit"
+    ),
+    aa!(
+        "AA14",
+        "initCode must return sender",
+        "the factory deployed a different address than sender",
+        "recompute sender from the factory and salt"
+    ),
+    aa!(
+        "AA15",
+        "initCode must create sender",
+        "the factory returned without deploying code at sender",
+        "check the factory implementation and salt"
+    ),
+    aa!(
+        "AA20",
+        "account not deployed",
+        "sender has no code and no initCode was supplied",
+        "set factory/factoryData for the first op"
+    ),
+    aa!(
+        "AA21",
+        "didn't pay prefund",
+        "the account's deposit plus payment does not cover the maximum gas cost",
+        "fund the account or its EntryPoint deposit, or lower gas limits/fees"
+    ),
+    aa!(
+        "AA22",
+        "expired or not due",
+        "validateUserOp returned a validUntil/validAfter window that excludes now",
+        "re-sign with a current validity window"
+    ),
+    aa!(
+        "AA23",
+        "reverted",
+        "validateUserOp reverted or ran out of gas",
+        "check the validator module is installed and raise verificationGasLimit (ML-DSA verification needs ~2M on Stylus)"
+    ),
+    aa!(
+        "AA24",
+        "signature error",
+        "validateUserOp reported SIG_VALIDATION_FAILED",
+        "check the signature is over this userOpHash, chain ID and EntryPoint, with the installed public key"
+    ),
+    aa!(
+        "AA25",
+        "invalid account nonce",
+        "the nonce key/sequence does not match EntryPoint.getNonce",
+        "fetch the nonce for the validator's nonce key"
+    ),
+    aa!(
+        "AA26",
+        "over verificationGasLimit",
+        "account validation used more gas than verificationGasLimit",
+        "raise verificationGasLimit"
+    ),
+    aa!(
+        "AA30",
+        "paymaster not deployed",
+        "paymasterAndData names an address with no code",
+        "check the paymaster address and chain"
+    ),
+    aa!(
+        "AA31",
+        "paymaster deposit too low",
+        "the paymaster's EntryPoint deposit cannot cover the op",
+        "top up the paymaster deposit"
+    ),
+    aa!(
+        "AA32",
+        "paymaster expired or not due",
+        "the paymaster's validity window excludes now",
+        "request fresh paymaster data"
+    ),
+    aa!(
+        "AA33",
+        "reverted",
+        "validatePaymasterUserOp reverted or ran out of gas",
+        "raise paymasterVerificationGasLimit or check paymasterData"
+    ),
+    aa!(
+        "AA34",
+        "signature error",
+        "the paymaster rejected its signature",
+        "request fresh paymaster data for this exact op"
+    ),
+    aa!(
+        "AA36",
+        "over paymasterVerificationGasLimit",
+        "paymaster validation used more gas than allotted",
+        "raise paymasterVerificationGasLimit"
+    ),
+    aa!(
+        "AA40",
+        "over verificationGasLimit",
+        "total validation gas exceeded verificationGasLimit",
+        "raise verificationGasLimit"
+    ),
+    aa!(
+        "AA41",
+        "too little verificationGas",
+        "not enough verification gas left for the paymaster",
+        "raise verificationGasLimit"
+    ),
+    aa!(
+        "AA50",
+        "postOp reverted",
+        "the paymaster's postOp reverted",
+        "check the paymaster's postOp conditions (token allowance, balance)"
+    ),
+    aa!(
+        "AA51",
+        "prefund below actualGasCost",
+        "the actual gas cost exceeded what was prefunded",
+        "raise gas limits so prefund covers execution"
+    ),
+    aa!(
+        "AA90",
+        "invalid beneficiary",
+        "handleOps was called with beneficiary address(0)",
+        "pass a non-zero beneficiary"
+    ),
+    aa!(
+        "AA91",
+        "failed send to beneficiary",
+        "the EntryPoint could not pay the beneficiary",
+        "use a beneficiary that accepts ETH"
+    ),
+    aa!(
+        "AA92",
+        "internal call only",
+        "innerHandleOp was called externally",
+        "call handleOps instead"
+    ),
+    aa!(
+        "AA93",
+        "invalid paymasterAndData",
+        "paymasterAndData is shorter than 52 bytes",
+        "include paymaster, both gas limits and paymasterData"
+    ),
+    aa!(
+        "AA94",
+        "gas values overflow",
+        "a gas field exceeds 120 bits",
+        "use realistic gas limits and fees"
+    ),
+    aa!(
+        "AA95",
+        "out of gas",
+        "the bundler's handleOps transaction ran out of gas",
+        "lower callGasLimit or let the bundler estimate"
+    ),
+    aa!(
+        "AA96",
+        "invalid aggregator",
+        "the aggregator address is invalid",
+        "use handleAggregatedOps with a valid aggregator"
+    ),
+];
+
+/// Custom errors from the EntryPoint, the PQ validator module and the Stylus
+/// verifier, by Solidity signature.
+pub const KNOWN_ERRORS: &[(&str, &str)] = &[
+    ("FailedOp(uint256,string)", "EntryPoint rejected the op"),
+    (
+        "FailedOpWithRevert(uint256,string,bytes)",
+        "EntryPoint rejected the op; inner revert attached",
+    ),
+    (
+        "SignatureValidationFailed(address)",
+        "aggregated signature check failed",
+    ),
+    ("PostOpReverted(bytes)", "paymaster postOp reverted"),
+    (
+        "SenderAddressResult(address)",
+        "getSenderAddress result (not a failure)",
+    ),
+    ("Error(string)", "require/revert with a message"),
+    (
+        "Panic(uint256)",
+        "Solidity panic (assert, overflow, bad array index)",
+    ),
+    (
+        "InvalidSignature()",
+        "Stylus verifier could not decode the ML-DSA-65 signature (must be exactly 3309 bytes)",
+    ),
+    (
+        "InvalidPublicKey()",
+        "Stylus verifier could not decode the stored ML-DSA-65 public key (must be 1952 bytes)",
+    ),
+    (
+        "InvalidMLDSAPublicKeyLength(uint256,uint256)",
+        "PQValidatorModule.onInstall got a public key of the wrong length",
+    ),
+    (
+        "AlreadyInitialized(address)",
+        "the validator module is already installed for this account",
+    ),
+    (
+        "NotInitialized(address)",
+        "the validator module is not installed for this account",
+    ),
+];
+
+/// Look up an `AAxx` code.
+pub fn lookup(code: &str) -> Option<&'static AaCode> {
+    AA_CODES.iter().find(|c| c.code == code)
+}
+
+/// First `AAxx` code mentioned anywhere in `text`.
+pub fn find_code(text: &str) -> Option<&'static AaCode> {
+    text.as_bytes()
+        .windows(4)
+        .filter(|w| w.starts_with(b"AA") && w[2..].iter().all(u8::is_ascii_digit))
+        .find_map(|w| lookup(std::str::from_utf8(w).ok()?))
+}
+
+/// Name and description of a known custom error by 4-byte selector.
+pub fn known_error(selector: [u8; 4]) -> Option<(&'static str, &'static str)> {
+    KNOWN_ERRORS
+        .iter()
+        .copied()
+        .find(|(sig, _)| keccak256(sig.as_bytes())[..4] == selector)
+}
+
+/// A decoded failure with a suggested fix.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    /// `AAxx`, when the EntryPoint produced one.
+    pub code: Option<String>,
+    /// What the EntryPoint/bundler said.
+    pub reason: String,
+    pub description: Option<String>,
+    pub fix: Option<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.reason)?;
+        if let Some(description) = &self.description {
+            write!(f, "\n  what: {description}")?;
+        }
+        if let Some(fix) = &self.fix {
+            write!(f, "\n  fix:  {fix}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Op-specific advice that beats the generic table entry.
+fn specific_fix(code: &str, op: &PackedUserOperation) -> Option<String> {
+    let sig_len = op.signature.len();
+    match code {
+        "AA24" | "AA23" if sig_len == PACKED_ML_DSA_65_LEN => Some(format!(
+            "signature is a {PACKED_ML_DSA_65_LEN}-byte packed envelope (pk || sig); \
+             PQValidatorModule reads the public key from storage and expects the raw \
+             {SIGNATURE_LEN}-byte signature"
+        )),
+        "AA24" | "AA23" if sig_len != SIGNATURE_LEN => Some(format!(
+            "signature length mismatch: ML-DSA-65 signature expected {SIGNATURE_LEN} bytes, got {sig_len}"
+        )),
+        "AA20" if op.init_code.is_empty() => Some(format!(
+            "{} has no code; set factory/factoryData to deploy it in this op",
+            op.sender
+        )),
+        "AA26" | "AA40" => Some(format!(
+            "verificationGasLimit is {}; ML-DSA-65 verification on Stylus needs ~2,000,000",
+            op.verification_gas_limit()
+        )),
+        _ => None,
+    }
+}
+
+fn from_reason(reason: &str, op: Option<&PackedUserOperation>) -> Diagnostic {
+    match find_code(reason) {
+        Some(aa) => Diagnostic {
+            code: Some(aa.code.to_string()),
+            reason: reason.to_string(),
+            description: Some(aa.description.to_string()),
+            fix: op
+                .and_then(|op| specific_fix(aa.code, op))
+                .or_else(|| Some(aa.fix.to_string())),
+        },
+        None => Diagnostic {
+            code: None,
+            reason: reason.to_string(),
+            description: None,
+            fix: None,
+        },
+    }
+}
+
+/// Describe raw revert data that isn't an EntryPoint `FailedOp`.
+fn describe_data(data: &[u8]) -> Option<String> {
+    let selector: [u8; 4] = data.get(..4)?.try_into().ok()?;
+    known_error(selector).map(|(sig, desc)| format!("{sig}: {desc}"))
+}
+
+/// Diagnose a decoded simulation revert.
+pub fn diagnose_revert(revert: &Revert, op: Option<&PackedUserOperation>) -> Diagnostic {
+    let mut diag = from_reason(&revert.to_string(), op);
+    match revert {
+        Revert::FailedOp {
+            inner: Some(inner), ..
+        } => {
+            if let Some(inner_desc) = describe_data(inner) {
+                diag.description = Some(match diag.description {
+                    Some(d) => format!("{d}; inner revert {inner_desc}"),
+                    None => inner_desc,
+                });
+            }
+        }
+        Revert::Unknown { data } => diag.description = describe_data(data),
+        _ => {}
+    }
+    diag
+}
+
+/// Diagnose a bundler/node error string, e.g. Alto's
+/// "UserOperation reverted during simulation with reason: AA24 signature error".
+pub fn diagnose_message(message: &str, op: Option<&PackedUserOperation>) -> Diagnostic {
+    from_reason(message, op)
+}
+
+/// Diagnose a failed RPC call: revert data if present, else the message.
+pub fn diagnose_error(err: &WalletError, op: Option<&PackedUserOperation>) -> Diagnostic {
+    if let Some(data) = rpc::revert_data(err) {
+        return diagnose_revert(&Revert::decode(&data), op);
+    }
+    match err {
+        WalletError::RpcError { message, .. } => diagnose_message(message, op),
+        other => diagnose_message(&other.to_string(), op),
+    }
+}
+
+/// Diagnose why a simulation would reject `op`, if it would.
+pub fn diagnose_simulation(sim: &Simulation, op: &PackedUserOperation) -> Option<Diagnostic> {
+    match sim {
+        Simulation::Reverted { revert } => Some(diagnose_revert(revert, Some(op))),
+        _ => sim.rejection().map(|reason| from_reason(&reason, Some(op))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::userop::ENTRY_POINT_V07;
+
+    fn op_with_signature(len: usize) -> PackedUserOperation {
+        let mut op: PackedUserOperation = serde_json::from_value(serde_json::json!({
+            "sender": ENTRY_POINT_V07,
+            "nonce": "0x0",
+            "callData": "0x",
+            "callGasLimit": "0x0",
+            "verificationGasLimit": "0x186a0",
+            "preVerificationGas": "0x0",
+            "maxFeePerGas": "0x0",
+            "maxPriorityFeePerGas": "0x0",
+            "signature": "0x"
+        }))
+        .unwrap();
+        op.signature = vec![0; len];
+        op
+    }
+
+    #[test]
+    fn every_code_is_well_formed_and_unique() {
+        for (i, c) in AA_CODES.iter().enumerate() {
+            assert_eq!(find_code(c.code), Some(c));
+            assert!(AA_CODES[i + 1..].iter().all(|d| d.code != c.code));
+        }
+    }
+
+    #[test]
+    fn bundler_message_gets_signature_length_fix() {
+        let msg = "UserOperation reverted during simulation with reason: AA24 signature error";
+        let diag = diagnose_message(msg, Some(&op_with_signature(PACKED_ML_DSA_65_LEN)));
+        assert_eq!(diag.code.as_deref(), Some("AA24"));
+        assert!(diag.fix.unwrap().contains("packed envelope"));
+
+        let diag = diagnose_message(msg, Some(&op_with_signature(100)));
+        assert!(diag.fix.unwrap().contains("expected 3309 bytes, got 100"));
+
+        let diag = diagnose_message(msg, Some(&op_with_signature(SIGNATURE_LEN)));
+        assert_eq!(diag.fix.as_deref(), Some(lookup("AA24").unwrap().fix));
+    }
+
+    #[test]
+    fn names_pq_verifier_selectors() {
+        let selector = keccak256(b"InvalidSignature()");
+        let desc = describe_data(&selector[..4]).unwrap();
+        assert!(desc.starts_with("InvalidSignature()"), "{desc}");
+        assert!(describe_data(&[1, 2, 3, 4]).is_none());
+    }
+}
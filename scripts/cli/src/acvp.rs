@@ -0,0 +1,610 @@
+//! NIST ACVP (FIPS 204) vector runner.
+//!
+//! Loads keyGen, sigGen or sigVer vector files in the ACVP-Server JSON layout
+//! (`internalProjection.json`, which carries expected results), runs them
+//! against a chosen [`Backend`] for any ML-DSA parameter set, and produces a
+//! serializable [`AcvpReport`].
+//!
+//! Hedged sigGen cases sign with the `rnd` the vector gives, so their
+//! signatures are reproduced exactly. Groups a backend cannot express
+//! (HashML-DSA `preHash`, external μ, fips204's internal interface) are
+//! reported as skipped rather than failed.
+
+use std::path::Path;
+
+use ml_dsa::{
+    B32, EncodedSignature, EncodedVerifyingKey, ExpandedSigningKey, KeyGen, MlDsa44, MlDsa65,
+    MlDsa87, MlDsaParams, Signature, SigningKey, VerifyingKey,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+use crate::ct;
+use crate::error::{Result, WalletError};
+
+/// Where `fetch` pulls vector sets from by default.
+pub const ACVP_SERVER_BASE: &str =
+    "https://raw.githubusercontent.com/usnistgov/ACVP-Server/master/gen-val/json-files";
+
+/// ACVP test modes we understand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mode {
+    #[serde(rename = "keyGen")]
+    KeyGen,
+    #[serde(rename = "sigGen")]
+    SigGen,
+    #[serde(rename = "sigVer")]
+    SigVer,
+}
+
+impl Mode {
+    /// URL of the vector set with expected results on the ACVP-Server repo.
+    pub fn default_url(self) -> String {
+        let dir = match self {
+            Mode::KeyGen => "ML-DSA-keyGen-FIPS204",
+            Mode::SigGen => "ML-DSA-sigGen-FIPS204",
+            Mode::SigVer => "ML-DSA-sigVer-FIPS204",
+        };
+        format!("{ACVP_SERVER_BASE}/{dir}/internalProjection.json")
+    }
+}
+
+impl std::str::FromStr for Mode {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "keyGen" | "keygen" => Ok(Mode::KeyGen),
+            "sigGen" | "siggen" => Ok(Mode::SigGen),
+            "sigVer" | "sigver" => Ok(Mode::SigVer),
+            other => Err(WalletError::Acvp(format!("unknown mode {other:?}"))),
+        }
+    }
+}
+
+/// ML-DSA parameter sets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParameterSet {
+    #[serde(rename = "ML-DSA-44")]
+    MlDsa44,
+    #[serde(rename = "ML-DSA-65")]
+    MlDsa65,
+    #[serde(rename = "ML-DSA-87")]
+    MlDsa87,
+}
+
+impl std::str::FromStr for ParameterSet {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ML-DSA-44" => Ok(ParameterSet::MlDsa44),
+            "ML-DSA-65" => Ok(ParameterSet::MlDsa65),
+            "ML-DSA-87" => Ok(ParameterSet::MlDsa87),
+            other => Err(WalletError::Acvp(format!(
+                "unknown parameter set {other:?}"
+            ))),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VectorFile {
+    mode: Mode,
+    test_groups: Vec<Group>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Group {
+    tg_id: u32,
+    parameter_set: ParameterSet,
+    #[serde(default)]
+    signature_interface: Option<String>,
+    #[serde(default)]
+    pre_hash: Option<String>,
+    #[serde(default)]
+    external_mu: bool,
+    #[serde(default)]
+    deterministic: Option<bool>,
+    tests: Vec<TestCase>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TestCase {
+    tc_id: u32,
+    seed: Option<String>,
+    pk: Option<String>,
+    sk: Option<String>,
+    message: Option<String>,
+    context: Option<String>,
+    /// Hedged sigGen's per-signature randomness.
+    rnd: Option<String>,
+    signature: Option<String>,
+    test_passed: Option<bool>,
+}
+
+/// How the message reaches the signer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Interface {
+    /// ML-DSA.Sign / ML-DSA.Verify with a context string.
+    External,
+    /// ML-DSA.Sign_internal / Verify_internal on M' directly.
+    Internal,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Failure {
+    pub tg_id: u32,
+    pub tc_id: u32,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedGroup {
+    pub tg_id: u32,
+    pub tests: usize,
+    pub reason: String,
+}
+
+/// Machine-readable result of an ACVP run.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AcvpReport {
+    pub mode: Mode,
+    pub backend: Backend,
+    pub total: usize,
+    pub passed: usize,
+    pub failures: Vec<Failure>,
+    pub skipped: Vec<SkippedGroup>,
+}
+
+impl AcvpReport {
+    pub fn is_clean(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Options narrowing an ACVP run.
+#[derive(Clone, Debug, Default)]
+pub struct RunOptions {
+    /// Only run groups for these parameter sets; empty means all.
+    pub parameter_sets: Vec<ParameterSet>,
+}
+
+/// Download a vector set to `dest`. `url` defaults to [`Mode::default_url`].
+pub fn fetch(mode: Mode, url: Option<&str>, dest: &Path) -> Result<()> {
+    let url = url.map(str::to_owned).unwrap_or_else(|| mode.default_url());
+    let body = ureq::get(&url)
+        .call()
+        .and_then(|mut resp| {
+            resp.body_mut()
+                .with_config()
+                .limit(64 << 20)
+                .read_to_string()
+        })
+        .map_err(|e| WalletError::Acvp(format!("fetching {url}: {e}")))?;
+    // Refuse to save something we could not run afterwards.
+    let file: VectorFile = serde_json::from_str(&body)
+        .map_err(|e| WalletError::Acvp(format!("{url} is not an ACVP vector file: {e}")))?;
+    if file.mode != mode {
+        return Err(WalletError::Acvp(format!(
+            "{url} contains {:?} vectors, expected {mode:?}",
+            file.mode
+        )));
+    }
+    std::fs::write(dest, body).map_err(|e| WalletError::Io(dest.display().to_string(), e))
+}
+
+/// Load and run an ACVP vector file.
+pub fn run_file(path: &Path, backend: Backend, opts: &RunOptions) -> Result<AcvpReport> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| WalletError::Io(path.display().to_string(), e))?;
+    run_json(&json, backend, opts)
+}
+
+/// Run ACVP vectors given as a JSON string.
+pub fn run_json(json: &str, backend: Backend, opts: &RunOptions) -> Result<AcvpReport> {
+    let file: VectorFile =
+        serde_json::from_str(json).map_err(|e| WalletError::Acvp(e.to_string()))?;
+
+    let mut report = AcvpReport {
+        mode: file.mode,
+        backend,
+        total: 0,
+        passed: 0,
+        failures: Vec::new(),
+        skipped: Vec::new(),
+    };
+
+    for group in &file.test_groups {
+        if !opts.parameter_sets.is_empty() && !opts.parameter_sets.contains(&group.parameter_set) {
+            continue;
+        }
+        let interface = match unsupported_reason(file.mode, group, backend) {
+            Some(reason) => {
+                report.skipped.push(SkippedGroup {
+                    tg_id: group.tg_id,
+                    tests: group.tests.len(),
+                    reason,
+                });
+                continue;
+            }
+            None => match group.signature_interface.as_deref() {
+                Some("internal") => Interface::Internal,
+                _ => Interface::External,
+            },
+        };
+
+        for tc in &group.tests {
+            report.total += 1;
+            let outcome = match file.mode {
+                Mode::KeyGen => check_keygen(group.parameter_set, backend, tc),
+                Mode::SigGen => {
+                    let hedged = group.deterministic == Some(false);
+                    check_siggen(group.parameter_set, backend, interface, hedged, tc)
+                }
+                Mode::SigVer => check_sigver(group.parameter_set, backend, interface, tc),
+            };
+            match outcome {
+                Ok(()) => report.passed += 1,
+                Err(detail) => report.failures.push(Failure {
+                    tg_id: group.tg_id,
+                    tc_id: tc.tc_id,
+                    detail,
+                }),
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg_attr(not(feature = "fips204"), allow(unused_variables))]
+fn unsupported_reason(mode: Mode, group: &Group, backend: Backend) -> Option<String> {
+    if mode == Mode::KeyGen {
+        return None;
+    }
+    if group.pre_hash.as_deref() == Some("preHash") {
+        return Some("HashML-DSA (preHash) is not supported".into());
+    }
+    if group.external_mu {
+        return Some("external mu is not supported".into());
+    }
+    #[cfg(feature = "fips204")]
+    if backend == Backend::Fips204 && group.signature_interface.as_deref() == Some("internal") {
+        return Some("fips204 does not expose the internal interface".into());
+    }
+    None
+}
+
+fn field(
+    tc: &TestCase,
+    value: &Option<String>,
+    name: &str,
+) -> std::result::Result<Vec<u8>, String> {
+    let hex = value
+        .as_deref()
+        .ok_or_else(|| format!("tcId {} missing {name}", tc.tc_id))?;
+    ct::decode_hex(hex).map_err(|_| format!("bad {name} hex"))
+}
+
+fn check_keygen(
+    ps: ParameterSet,
+    backend: Backend,
+    tc: &TestCase,
+) -> std::result::Result<(), String> {
+    let seed: [u8; 32] = field(tc, &tc.seed, "seed")?
+        .try_into()
+        .map_err(|_| "seed is not 32 bytes".to_string())?;
+    let (pk, sk) = ops::keygen(ps, backend, &seed);
+    let pk_ok = ct::eq(&pk, &field(tc, &tc.pk, "pk")?);
+    let sk_ok = ct::eq(&sk, &field(tc, &tc.sk, "sk")?);
+    if pk_ok && sk_ok {
+        Ok(())
+    } else {
+        Err(format!("pk_match={pk_ok} sk_match={sk_ok}"))
+    }
+}
+
+fn check_siggen(
+    ps: ParameterSet,
+    backend: Backend,
+    interface: Interface,
+    hedged: bool,
+    tc: &TestCase,
+) -> std::result::Result<(), String> {
+    let sk = field(tc, &tc.sk, "sk")?;
+    let msg = field(tc, &tc.message, "message")?;
+    let ctx = match &tc.context {
+        Some(_) => field(tc, &tc.context, "context")?,
+        None => Vec::new(),
+    };
+    // Deterministic ML-DSA is the hedged variant with rnd = 0^32.
+    let rnd: [u8; 32] = if hedged {
+        field(tc, &tc.rnd, "rnd")?
+            .try_into()
+            .map_err(|_| "rnd is not 32 bytes".to_string())?
+    } else {
+        [0; 32]
+    };
+    let expected = field(tc, &tc.signature, "signature")?;
+    let sig = ops::sign(ps, backend, interface, &sk, &msg, &ctx, &rnd)
+        .ok_or_else(|| "signing failed".to_string())?;
+    if sig == expected {
+        Ok(())
+    } else {
+        Err("signature mismatch".into())
+    }
+}
+
+fn check_sigver(
+    ps: ParameterSet,
+    backend: Backend,
+    interface: Interface,
+    tc: &TestCase,
+) -> std::result::Result<(), String> {
+    let pk = field(tc, &tc.pk, "pk")?;
+    let msg = field(tc, &tc.message, "message")?;
+    let ctx = match &tc.context {
+        Some(_) => field(tc, &tc.context, "context")?,
+        None => Vec::new(),
+    };
+    let sig = field(tc, &tc.signature, "signature")?;
+    let expected = tc
+        .test_passed
+        .ok_or_else(|| format!("tcId {} missing testPassed", tc.tc_id))?;
+    let actual = ops::verify(ps, backend, interface, &pk, &msg, &ctx, &sig);
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!("expected={expected} actual={actual}"))
+    }
+}
+
+/// Per-backend, per-parameter-set dispatch.
+mod ops {
+    use super::*;
+
+    pub fn keygen(ps: ParameterSet, backend: Backend, seed: &[u8; 32]) -> (Vec<u8>, Vec<u8>) {
+        match (backend, ps) {
+            (Backend::RustCrypto, ParameterSet::MlDsa44) => rc_keygen::<MlDsa44>(seed),
+            (Backend::RustCrypto, ParameterSet::MlDsa65) => rc_keygen::<MlDsa65>(seed),
+            (Backend::RustCrypto, ParameterSet::MlDsa87) => rc_keygen::<MlDsa87>(seed),
+            #[cfg(feature = "fips204")]
+            (Backend::Fips204, ParameterSet::MlDsa44) => fips44::keygen(seed),
+            #[cfg(feature = "fips204")]
+            (Backend::Fips204, ParameterSet::MlDsa65) => fips65::keygen(seed),
+            #[cfg(feature = "fips204")]
+            (Backend::Fips204, ParameterSet::MlDsa87) => fips87::keygen(seed),
+        }
+    }
+
+    pub fn sign(
+        ps: ParameterSet,
+        backend: Backend,
+        interface: Interface,
+        sk: &[u8],
+        msg: &[u8],
+        ctx: &[u8],
+        rnd: &[u8; 32],
+    ) -> Option<Vec<u8>> {
+        match (backend, ps) {
+            (Backend::RustCrypto, ParameterSet::MlDsa44) => {
+                rc_sign::<MlDsa44>(interface, sk, msg, ctx, rnd)
+            }
+            (Backend::RustCrypto, ParameterSet::MlDsa65) => {
+                rc_sign::<MlDsa65>(interface, sk, msg, ctx, rnd)
+            }
+            (Backend::RustCrypto, ParameterSet::MlDsa87) => {
+                rc_sign::<MlDsa87>(interface, sk, msg, ctx, rnd)
+            }
+            #[cfg(feature = "fips204")]
+            (Backend::Fips204, ParameterSet::MlDsa44) => fips44::sign(sk, msg, ctx, rnd),
+            #[cfg(feature = "fips204")]
+            (Backend::Fips204, ParameterSet::MlDsa65) => fips65::sign(sk, msg, ctx, rnd),
+            #[cfg(feature = "fips204")]
+            (Backend::Fips204, ParameterSet::MlDsa87) => fips87::sign(sk, msg, ctx, rnd),
+        }
+    }
+
+    pub fn verify(
+        ps: ParameterSet,
+        backend: Backend,
+        interface: Interface,
+        pk: &[u8],
+        msg: &[u8],
+        ctx: &[u8],
+        sig: &[u8],
+    ) -> bool {
+        match (backend, ps) {
+            (Backend::RustCrypto, ParameterSet::MlDsa44) => {
+                rc_verify::<MlDsa44>(interface, pk, msg, ctx, sig)
+            }
+            (Backend::RustCrypto, ParameterSet::MlDsa65) => {
+                rc_verify::<MlDsa65>(interface, pk, msg, ctx, sig)
+            }
+            (Backend::RustCrypto, ParameterSet::MlDsa87) => {
+                rc_verify::<MlDsa87>(interface, pk, msg, ctx, sig)
+            }
+            #[cfg(feature = "fips204")]
+            (Backend::Fips204, ParameterSet::MlDsa44) => fips44::verify(pk, msg, ctx, sig),
+            #[cfg(feature = "fips204")]
+            (Backend::Fips204, ParameterSet::MlDsa65) => fips65::verify(pk, msg, ctx, sig),
+            #[cfg(feature = "fips204")]
+            (Backend::Fips204, ParameterSet::MlDsa87) => fips87::verify(pk, msg, ctx, sig),
+        }
+    }
+
+    fn rc_keygen<P: MlDsaParams>(seed: &[u8; 32]) -> (Vec<u8>, Vec<u8>) {
+        let kp = <P as KeyGen>::from_seed(&(*seed).into());
+        // ACVP still publishes the expanded key, so compare against it even
+        // though we only ever persist seeds.
+        #[allow(deprecated)]
+        let sk = kp.signing_key().to_expanded();
+        (kp.verifying_key().encode().to_vec(), sk.to_vec())
+    }
+
+    fn rc_sign<P: MlDsaParams>(
+        interface: Interface,
+        sk: &[u8],
+        msg: &[u8],
+        ctx: &[u8],
+        rnd: &[u8; 32],
+    ) -> Option<Vec<u8>> {
+        let enc = ExpandedSigningKey::<P>::try_from(sk).ok()?;
+        #[allow(deprecated)]
+        let sk = SigningKey::<P>::from_expanded(&enc);
+        let rnd = B32::from(*rnd);
+        let sig = match interface {
+            Interface::Internal => sk.sign_internal(&[msg], &rnd),
+            Interface::External => {
+                if ctx.len() > 255 {
+                    return None;
+                }
+                let header = [0u8, ctx.len() as u8];
+                sk.sign_internal(&[&header, ctx, msg], &rnd)
+            }
+        };
+        Some(sig.encode().to_vec())
+    }
+
+    fn rc_verify<P: MlDsaParams>(
+        interface: Interface,
+        pk: &[u8],
+        msg: &[u8],
+        ctx: &[u8],
+        sig: &[u8],
+    ) -> bool {
+        let Ok(pk) = EncodedVerifyingKey::<P>::try_from(pk) else {
+            return false;
+        };
+        let Ok(sig) = EncodedSignature::<P>::try_from(sig) else {
+            return false;
+        };
+        let Some(sig) = Signature::<P>::decode(&sig) else {
+            return false;
+        };
+        let vk = VerifyingKey::<P>::decode(&pk);
+        match interface {
+            Interface::Internal => vk.verify_internal(msg, &sig),
+            Interface::External => vk.verify_with_context(msg, ctx, &sig),
+        }
+    }
+
+    #[cfg(feature = "fips204")]
+    macro_rules! fips204_ops {
+        ($name:ident, $module:ident) => {
+            mod $name {
+                use fips204::traits::{KeyGen as _, SerDes as _, Signer as _, Verifier as _};
+                use fips204::$module as m;
+
+                pub fn keygen(seed: &[u8; 32]) -> (Vec<u8>, Vec<u8>) {
+                    let (pk, sk) = m::KG::keygen_from_seed(seed);
+                    (pk.into_bytes().to_vec(), sk.into_bytes().to_vec())
+                }
+
+                pub fn sign(sk: &[u8], msg: &[u8], ctx: &[u8], rnd: &[u8; 32]) -> Option<Vec<u8>> {
+                    let sk = <[u8; m::SK_LEN]>::try_from(sk).ok()?;
+                    let sk = m::PrivateKey::try_from_bytes(sk).ok()?;
+                    sk.try_sign_with_seed(rnd, msg, ctx)
+                        .ok()
+                        .map(|s| s.to_vec())
+                }
+
+                pub fn verify(pk: &[u8], msg: &[u8], ctx: &[u8], sig: &[u8]) -> bool {
+                    let (Ok(pk), Ok(sig)) = (
+                        <[u8; m::PK_LEN]>::try_from(pk),
+                        <[u8; m::SIG_LEN]>::try_from(sig),
+                    ) else {
+                        return false;
+                    };
+                    match m::PublicKey::try_from_bytes(pk) {
+                        Ok(pk) => pk.verify(msg, &sig, ctx),
+                        Err(_) => false,
+                    }
+                }
+            }
+        };
+    }
+
+    #[cfg(feature = "fips204")]
+    fips204_ops!(fips44, ml_dsa_44);
+    #[cfg(feature = "fips204")]
+    fips204_ops!(fips65, ml_dsa_65);
+    #[cfg(feature = "fips204")]
+    fips204_ops!(fips87, ml_dsa_87);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn repo_file(rel: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../..")
+            .join(rel)
+    }
+
+    #[test]
+    fn keygen_vectors_pass_on_both_backends() {
+        let path = repo_file("archive/ml-dsa-test/test-vectors/keyGen.json");
+        for &backend in Backend::ALL {
+            let report = run_file(&path, backend, &RunOptions::default()).unwrap();
+            assert_eq!(report.mode, Mode::KeyGen);
+            assert!(report.total > 0);
+            assert!(report.is_clean(), "{backend}: {:?}", report.failures);
+        }
+    }
+
+    #[test]
+    fn sigver_ml_dsa_65_passes_on_both_backends() {
+        let path = repo_file("archive/ml-dsa-test/test-vectors/sigVer.json");
+        let opts = RunOptions {
+            parameter_sets: vec![ParameterSet::MlDsa65],
+        };
+        for &backend in Backend::ALL {
+            let report = run_file(&path, backend, &opts).unwrap();
+            assert!(report.total > 0);
+            assert!(report.is_clean(), "{backend}: {:?}", report.failures);
+        }
+    }
+
+    #[test]
+    fn siggen_deterministic_vectors_pass() {
+        let path = repo_file("pq-snap/test/kat/siggen.json");
+        let report = run_file(&path, Backend::RustCrypto, &RunOptions::default()).unwrap();
+        assert_eq!(report.mode, Mode::SigGen);
+        assert!(report.total > 0);
+        assert!(report.is_clean(), "{:?}", report.failures);
+        assert!(report.skipped.iter().any(|s| s.reason.contains("preHash")));
+    }
+
+    #[test]
+    fn hedged_siggen_vectors_pass_with_their_rnd() {
+        let path = repo_file("pq-snap/test/kat/siggen.json");
+        for &backend in Backend::ALL {
+            let report = run_file(&path, backend, &RunOptions::default()).unwrap();
+            assert!(report.is_clean(), "{backend}: {:?}", report.failures);
+            // Hedged groups are 13..=24; only preHash and external μ are
+            // out of reach for both backends.
+            let skipped: Vec<u32> = report.skipped.iter().map(|s| s.tg_id).collect();
+            assert!(skipped.iter().all(|&id| ![13, 15, 17].contains(&id)));
+            assert!(skipped.contains(&14) && skipped.contains(&19));
+        }
+        let report = run_file(&path, Backend::RustCrypto, &RunOptions::default()).unwrap();
+        assert_eq!(report.total, 12 * 15);
+    }
+
+    #[test]
+    fn rejects_unknown_parameter_set() {
+        let json =
+            r#"{"mode":"keyGen","testGroups":[{"tgId":1,"parameterSet":"ML-DSA-99","tests":[]}]}"#;
+        assert!(run_json(json, Backend::RustCrypto, &RunOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+//! ERC-4337 signature aggregation (`IAggregator`) for PQ signatures.
+//!
+//! An account that delegates to an aggregator returns the aggregator's
+//! address from `validateUserOp`; the EntryPoint then checks all of that
+//! aggregator's ops in one `validateSignatures(ops, signature)` call, so the
+//! verifier's fixed costs are paid once per bundle instead of once per op.
+//!
+//! The wallet still signs each op on its own. Its `signature` field is the
+//! same bytes it would carry without aggregation, and it goes to the bundler
+//! with `eth_sendUserOperation` naming the aggregator
+//! ([`crate::bundler::send_user_operation`]). When the bundler builds the
+//! bundle it moves the signatures out of the ops:
+//!
+//! - each op's `signature` becomes the aggregator's `validateUserOpSignature`
+//!   result, which for the PQ aggregator is empty ([`SIG_FOR_USER_OP`]);
+//! - the bundle-level signature is `aggregateSignatures(ops)`, which the PQ
+//!   aggregator defines as `abi.encode(bytes[] signatures)` in op order
+//!   ([`aggregate_signatures`]).
+//!
+//! [`bundle`] does both, and [`encode_handle_aggregated_ops`] builds the
+//! `handleAggregatedOps` call.
+
+use alloy_primitives::{Address, Bytes};
+use alloy_sol_types::{SolCall, SolValue};
+use serde::Serialize;
+
+use crate::error::{Result, WalletError};
+use crate::rpc::Transport;
+use crate::simulate::{self, abi};
+use crate::userop::PackedUserOperation;
+
+/// What the PQ aggregator's `validateUserOpSignature` returns, and so what
+/// each op's `signature` holds inside `handleAggregatedOps`.
+pub const SIG_FOR_USER_OP: &[u8] = &[];
+
+/// The PQ aggregator's `aggregateSignatures`: every op's `signature`,
+/// in order, as `abi.encode(bytes[])`.
+pub fn aggregate_signatures(ops: &[PackedUserOperation]) -> Vec<u8> {
+    let signatures: Vec<Bytes> = ops
+        .iter()
+        .map(|op| Bytes::copy_from_slice(&op.signature))
+        .collect();
+    signatures.abi_encode()
+}
+
+/// Split an aggregated signature back into per-op signatures.
+pub fn split_aggregate(aggregated: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let signatures = Vec::<Bytes>::abi_decode_validate(aggregated)
+        .map_err(|e| WalletError::InvalidEnvelope(format!("aggregated signature: {e}")))?;
+    Ok(signatures.into_iter().map(|s| s.to_vec()).collect())
+}
+
+/// One aggregator's share of a bundle (`UserOpsPerAggregator`).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregatedOps {
+    /// The ops with their signatures replaced by [`SIG_FOR_USER_OP`].
+    pub user_ops: Vec<PackedUserOperation>,
+    pub aggregator: Address,
+    pub signature: Bytes,
+}
+
+/// Group signed `ops` under `aggregator` the way a bundler would.
+pub fn bundle(aggregator: Address, ops: &[PackedUserOperation]) -> AggregatedOps {
+    let signature = aggregate_signatures(ops).into();
+    let user_ops = ops
+        .iter()
+        .map(|op| PackedUserOperation {
+            signature: SIG_FOR_USER_OP.to_vec(),
+            ..op.clone()
+        })
+        .collect();
+    AggregatedOps {
+        user_ops,
+        aggregator,
+        signature,
+    }
+}
+
+/// `handleAggregatedOps(opsPerAggregator, beneficiary)` calldata.
+pub fn encode_handle_aggregated_ops(groups: &[AggregatedOps], beneficiary: Address) -> Vec<u8> {
+    abi::handleAggregatedOpsCall {
+        opsPerAggregator: groups
+            .iter()
+            .map(|g| abi::UserOpsPerAggregator {
+                userOps: g.user_ops.iter().map(Into::into).collect(),
+                aggregator: g.aggregator,
+                signature: g.signature.clone(),
+            })
+            .collect(),
+        beneficiary,
+    }
+    .abi_encode()
+}
+
+/// `aggregator.aggregateSignatures(ops)` via `eth_call`, to check
+/// [`aggregate_signatures`] against a deployed aggregator.
+pub fn aggregate_on_chain<T: Transport>(
+    rpc: &T,
+    aggregator: Address,
+    ops: &[PackedUserOperation],
+) -> Result<Bytes> {
+    let data = abi::aggregateSignaturesCall {
+        userOps: ops.iter().map(Into::into).collect(),
+    }
+    .abi_encode();
+    match simulate::eth_call(rpc, aggregator, data, None)? {
+        Ok(ret) => abi::aggregateSignaturesCall::abi_decode_returns(&ret)
+            .map_err(|e| WalletError::Rpc(format!("aggregateSignatures: undecodable result: {e}"))),
+        Err(revert) => Err(WalletError::Rpc(format!("aggregateSignatures: {revert}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{B256, U256, address};
+
+    fn op(nonce: u64, signature: &[u8]) -> PackedUserOperation {
+        PackedUserOperation {
+            sender: address!("0x1111111111111111111111111111111111111111"),
+            nonce: U256::from(nonce),
+            init_code: vec![],
+            call_data: vec![0xde, 0xad],
+            account_gas_limits: B256::ZERO,
+            pre_verification_gas: U256::from(50_000),
+            gas_fees: B256::ZERO,
+            paymaster_and_data: vec![],
+            signature: signature.to_vec(),
+        }
+    }
+
+    #[test]
+    fn bundle_moves_signatures_into_the_aggregate() {
+        let aggregator = address!("0x2222222222222222222222222222222222222222");
+        let ops = [op(0, &[1; 3309]), op(1, &[2; 3309])];
+        let group = bundle(aggregator, &ops);
+        assert!(group.user_ops.iter().all(|o| o.signature.is_empty()));
+        assert_eq!(group.user_ops[1].nonce, U256::from(1));
+        assert_eq!(
+            split_aggregate(&group.signature).unwrap(),
+            vec![vec![1; 3309], vec![2; 3309]]
+        );
+
+        let data = encode_handle_aggregated_ops(std::slice::from_ref(&group), Address::ZERO);
+        let call = abi::handleAggregatedOpsCall::abi_decode(&data).unwrap();
+        assert_eq!(call.opsPerAggregator[0].aggregator, aggregator);
+        assert_eq!(call.opsPerAggregator[0].signature, group.signature);
+        assert_eq!(call.opsPerAggregator[0].userOps.len(), 2);
+    }
+}
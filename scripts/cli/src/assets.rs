@@ -0,0 +1,391 @@
+//! "This op will move X": run a UserOperation's calls on a node and net what
+//! the account sends and receives, for the review before signing.
+//!
+//! The op's `callData` is run the way the EntryPoint runs it, as a call from
+//! the EntryPoint to the account. Validation is skipped, so the preview needs
+//! no signature. `eth_simulateV1` is tried first. For an undeployed account,
+//! it first runs the factory, called from the v0.7 SenderCreator, in the
+//! same simulated block. It also reports native value as transfer logs.
+//! Nodes without `eth_simulateV1` fall back to `debug_traceCall` with the
+//! `callTracer`, which only works for an account that is already deployed.
+//! Gas is left out; the review's max cost covers it. See [`crate::trace`]
+//! for how transfers are read.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use alloy_primitives::{Address, Bytes, U256, address};
+use alloy_sol_types::{SolCall, sol};
+use serde::{Deserialize, Serialize};
+
+use crate::balance;
+use crate::decode;
+use crate::error::{Result, WalletError};
+use crate::rpc::Transport;
+use crate::simulate::Revert;
+use crate::trace::{self, Asset, CallFrame, Transfer};
+use crate::userop::PackedUserOperation;
+
+/// The contract EntryPoint v0.7 deploys accounts through.
+pub const SENDER_CREATOR_V07: Address = address!("EFC2c1444eBCC4Db75e7613d20C6a62fF67A167C");
+
+sol! {
+    function symbol() returns (string);
+}
+
+/// An amount of one asset, ready to print.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Change {
+    /// `None` for native value.
+    pub token: Option<Address>,
+    /// Set for an ERC-721.
+    pub token_id: Option<U256>,
+    pub symbol: String,
+    pub decimals: u8,
+    pub amount: U256,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.token_id {
+            Some(id) => write!(f, "{} #{id}", self.symbol),
+            None => write!(
+                f,
+                "{} {}",
+                decode::format_units(self.amount, self.decimals),
+                self.symbol
+            ),
+        }
+    }
+}
+
+/// What the simulated op moves in and out of the account.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetChanges {
+    pub sent: Vec<Change>,
+    pub received: Vec<Change>,
+    /// Why the calls revert, if they do; then nothing moves.
+    pub reverted: Option<String>,
+}
+
+fn list(changes: &[Change]) -> String {
+    let shown: Vec<String> = changes.iter().map(Change::to_string).collect();
+    match shown.as_slice() {
+        [] => String::new(),
+        [one] => one.clone(),
+        [init @ .., last] => format!("{} and {last}", init.join(", ")),
+    }
+}
+
+impl fmt::Display for AssetChanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(reason) = &self.reverted {
+            return write!(f, "the calls revert ({reason}); nothing will move");
+        }
+        match (self.sent.is_empty(), self.received.is_empty()) {
+            (true, true) => f.write_str("no ETH or tokens will move"),
+            (false, true) => write!(f, "you will send {}", list(&self.sent)),
+            (true, false) => write!(f, "you will receive {}", list(&self.received)),
+            (false, false) => write!(
+                f,
+                "you will send {} and receive {}",
+                list(&self.sent),
+                list(&self.received)
+            ),
+        }
+    }
+}
+
+/// Amounts of assets, one entry per asset.
+pub type Flows = Vec<(Asset, U256)>;
+
+/// `account`'s net outflows and inflows in `transfers`. Fungible amounts
+/// are netted per asset; NFTs are listed as they move.
+pub fn net(transfers: &[Transfer], account: Address) -> (Flows, Flows) {
+    // asset -> (in, out)
+    let mut flows: BTreeMap<Asset, (U256, U256)> = BTreeMap::new();
+    for t in transfers {
+        if t.from == t.to {
+            continue;
+        }
+        let flow = flows.entry(t.asset).or_default();
+        if t.to == account {
+            flow.0 = flow.0.saturating_add(t.amount);
+        } else if t.from == account {
+            flow.1 = flow.1.saturating_add(t.amount);
+        }
+    }
+    let (mut sent, mut received) = (Vec::new(), Vec::new());
+    for (asset, (inflow, outflow)) in flows {
+        if inflow > outflow {
+            received.push((asset, inflow - outflow));
+        } else if outflow > inflow {
+            sent.push((asset, outflow - inflow));
+        }
+    }
+    (sent, received)
+}
+
+fn describe<T: Transport>(rpc: &T, chain_id: u64, asset: Asset, amount: U256) -> Change {
+    match asset {
+        Asset::Native => Change {
+            token: None,
+            token_id: None,
+            symbol: "ETH".to_string(),
+            decimals: 18,
+            amount,
+        },
+        Asset::Erc20(token) => {
+            let (symbol, decimals) = balance::describe(rpc, chain_id, token)
+                .unwrap_or_else(|_| (format!("units of token {token}"), 0));
+            Change {
+                token: Some(token),
+                token_id: None,
+                symbol,
+                decimals,
+                amount,
+            }
+        }
+        Asset::Erc721 { token, id } => {
+            let data = Bytes::from(symbolCall {}.abi_encode());
+            let symbol = rpc
+                .call::<Bytes>(
+                    "eth_call",
+                    serde_json::json!([{ "to": token, "data": data }, "latest"]),
+                )
+                .ok()
+                .and_then(|ret| symbolCall::abi_decode_returns(&ret).ok())
+                .unwrap_or_else(|| format!("NFT {token}"));
+            Change {
+                token: Some(token),
+                token_id: Some(id),
+                symbol,
+                decimals: 0,
+                amount,
+            }
+        }
+    }
+}
+
+/// [`net`] `transfers` for `account`, with symbols and decimals looked up on
+/// `rpc`.
+pub fn changes<T: Transport>(
+    rpc: &T,
+    chain_id: u64,
+    account: Address,
+    transfers: &[Transfer],
+) -> AssetChanges {
+    let (sent, received) = net(transfers, account);
+    let describe_all = |moves: Flows| {
+        moves
+            .into_iter()
+            .map(|(asset, amount)| describe(rpc, chain_id, asset, amount))
+            .collect()
+    };
+    AssetChanges {
+        sent: describe_all(sent),
+        received: describe_all(received),
+        reverted: None,
+    }
+}
+
+#[derive(Deserialize)]
+struct SimulatedBlock {
+    calls: Vec<SimulatedCall>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SimulatedCall {
+    status: alloy_primitives::U64,
+    #[serde(default)]
+    return_data: Bytes,
+    #[serde(default)]
+    logs: Vec<trace::Log>,
+}
+
+fn simulate_v1<T: Transport>(
+    rpc: &T,
+    op: &PackedUserOperation,
+    entry_point: Address,
+) -> Result<std::result::Result<Vec<Transfer>, String>> {
+    let mut calls = Vec::new();
+    if op.init_code.len() >= 20 {
+        calls.push(serde_json::json!({
+            "from": SENDER_CREATOR_V07,
+            "to": Address::from_slice(&op.init_code[..20]),
+            "data": Bytes::copy_from_slice(&op.init_code[20..]),
+        }));
+    }
+    calls.push(serde_json::json!({
+        "from": entry_point,
+        "to": op.sender,
+        "data": Bytes::copy_from_slice(&op.call_data),
+    }));
+    let blocks: Vec<SimulatedBlock> = rpc.call(
+        "eth_simulateV1",
+        serde_json::json!([
+            { "blockStateCalls": [{ "calls": calls }], "traceTransfers": true, "validation": false },
+            "latest",
+        ]),
+    )?;
+    let results = blocks.into_iter().flat_map(|b| b.calls);
+    let mut logs = Vec::new();
+    for (i, call) in results.enumerate() {
+        if call.status.is_zero() {
+            let what = if i == 0 && op.init_code.len() >= 20 {
+                "account deployment"
+            } else {
+                "execution"
+            };
+            return Ok(Err(format!(
+                "{what}: {}",
+                Revert::decode(&call.return_data)
+            )));
+        }
+        logs.extend(call.logs);
+    }
+    Ok(Ok(trace::decode_logs(&logs)))
+}
+
+fn trace_call<T: Transport>(
+    rpc: &T,
+    op: &PackedUserOperation,
+    entry_point: Address,
+) -> Result<std::result::Result<Vec<Transfer>, String>> {
+    if !op.init_code.is_empty() {
+        return Err(WalletError::Rpc(
+            "the node has no eth_simulateV1, and debug_traceCall cannot deploy the account \
+             first"
+                .into(),
+        ));
+    }
+    let frame: CallFrame = rpc.call(
+        "debug_traceCall",
+        serde_json::json!([
+            { "from": entry_point, "to": op.sender, "data": Bytes::copy_from_slice(&op.call_data) },
+            "latest",
+            { "tracer": "callTracer", "tracerConfig": { "withLog": true } },
+        ]),
+    )?;
+    Ok(match &frame.error {
+        Some(error) => Err(error.clone()),
+        None => Ok(frame.transfers()),
+    })
+}
+
+/// Simulate `op`'s calls on `rpc` and report what they move for the sender.
+pub fn simulate<T: Transport>(
+    rpc: &T,
+    op: &PackedUserOperation,
+    entry_point: Address,
+    chain_id: u64,
+) -> Result<AssetChanges> {
+    let traced = match simulate_v1(rpc, op, entry_point) {
+        Err(WalletError::RpcError { code: -32601, .. }) => trace_call(rpc, op, entry_point),
+        result => result,
+    }
+    .map_err(|e| match e {
+        WalletError::RpcError { code: -32601, .. } => {
+            WalletError::Rpc("the node serves neither eth_simulateV1 nor debug_traceCall".into())
+        }
+        e => e,
+    })?;
+    Ok(match traced {
+        Ok(transfers) => changes(rpc, chain_id, op.sender, &transfers),
+        Err(reason) => AssetChanges {
+            reverted: Some(reason),
+            ..AssetChanges::default()
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_sol_types::SolValue;
+    use serde_json::Value;
+
+    const ACCOUNT: Address = Address::repeat_byte(0xaa);
+    const POOL: Address = Address::repeat_byte(0xbb);
+    /// Arbitrum One USDC, which the bundled registry knows.
+    const USDC: Address = address!("af88d065e77c8cC2239327C5EDb3A432268e5831");
+
+    fn transfer(asset: Asset, from: Address, to: Address, amount: u64) -> Transfer {
+        Transfer {
+            asset,
+            from,
+            to,
+            amount: U256::from(amount),
+        }
+    }
+
+    /// `eth_simulateV1` answering with a swap: 1.2 ETH out, 3000 USDC in.
+    struct Node;
+
+    impl Transport for Node {
+        fn request(&self, method: &str, params: Value) -> Result<Value> {
+            assert_eq!(method, "eth_simulateV1");
+            let calls = &params[0]["blockStateCalls"][0]["calls"];
+            assert_eq!(calls.as_array().unwrap().len(), 1, "deployed account");
+            let log = |token: Address, from: Address, to: Address, amount: U256| {
+                serde_json::json!({
+                    "address": token,
+                    "topics": [trace::TRANSFER_TOPIC, from.into_word(), to.into_word()],
+                    "data": Bytes::from(amount.abi_encode()),
+                })
+            };
+            let wei = U256::from(12u64) * U256::from(10u64).pow(U256::from(17));
+            Ok(serde_json::json!([{ "calls": [{
+                "status": "0x1",
+                "returnData": "0x",
+                "logs": [
+                    log(trace::NATIVE_PSEUDO_TOKEN, ACCOUNT, POOL, wei),
+                    log(USDC, POOL, ACCOUNT, U256::from(3_000_000_000u64)),
+                ],
+            }]}]))
+        }
+    }
+
+    #[test]
+    fn nets_flows_per_asset() {
+        let transfers = [
+            transfer(Asset::Native, ACCOUNT, POOL, 10),
+            transfer(Asset::Native, POOL, ACCOUNT, 4),
+            transfer(Asset::Erc20(USDC), POOL, ACCOUNT, 7),
+            transfer(Asset::Erc20(USDC), POOL, POOL, 100),
+            transfer(Asset::Erc20(POOL), ACCOUNT, POOL, 5),
+            transfer(Asset::Erc20(POOL), POOL, ACCOUNT, 5),
+        ];
+        let (sent, received) = net(&transfers, ACCOUNT);
+        assert_eq!(sent, [(Asset::Native, U256::from(6))]);
+        assert_eq!(received, [(Asset::Erc20(USDC), U256::from(7))]);
+    }
+
+    #[test]
+    fn simulation_reads_as_a_sentence() {
+        let op = PackedUserOperation {
+            sender: ACCOUNT,
+            nonce: U256::ZERO,
+            init_code: Vec::new(),
+            call_data: vec![0x01],
+            account_gas_limits: Default::default(),
+            pre_verification_gas: U256::ZERO,
+            gas_fees: Default::default(),
+            paymaster_and_data: Vec::new(),
+            signature: Vec::new(),
+        };
+        let changes = simulate(&Node, &op, Address::ZERO, 42161).unwrap();
+        assert_eq!(
+            changes.to_string(),
+            "you will send 1.2 ETH and receive 3000 USDC"
+        );
+        let reverted = AssetChanges {
+            reverted: Some("execution: reverted".into()),
+            ..AssetChanges::default()
+        };
+        assert!(reverted.to_string().contains("nothing will move"));
+    }
+}
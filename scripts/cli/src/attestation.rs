@@ -0,0 +1,230 @@
+//! Attestation records: a statement, signed by a signer host's own ML-DSA-65
+//! key, that a given signing key lives on that host.
+//!
+//! A host issues one record per signing key. It then attaches the record to
+//! every sign response (`pq-sign --attestation`). Custody integrations decide
+//! which hosts they trust by the fingerprint of the host key, and check the
+//! record before letting a signature into a userOp (`pq-userop attach`).
+//!
+//! A host in a TEE can carry its quote (`platform`, `quote`, and the
+//! enclave `measurement`) in the record. The host signature covers the
+//! quote's hash, so the quote cannot be swapped. The quote itself is opaque
+//! here: check it with the vendor's verifier, and check that its report
+//! data commits to [`Attestation::statement`].
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use alloy_primitives::{B256, Bytes, keccak256};
+use ml_dsa::signature::Signer;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+use crate::error::{Result, WalletError};
+use crate::keys::{self, PUBLIC_KEY_LEN, SEED_LEN};
+use crate::message::HashAlg;
+
+pub const ATTESTATION_VERSION: u32 = 1;
+/// Domain separator for the signed statement.
+pub const DOMAIN: &[u8] = b"pq-wallet/attestation/v1";
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::Attestation(reason.into())
+}
+
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("clock before 1970")
+        .as_secs()
+}
+
+/// What a host asserts about itself when issuing a record.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HostClaims {
+    /// e.g. `aws-nitro`, `sgx`, `tdx`; `None` for a plain signer host.
+    pub platform: Option<String>,
+    /// Enclave or image measurement (PCR, MRENCLAVE, ...).
+    pub measurement: Option<B256>,
+    /// Raw TEE quote.
+    pub quote: Option<Bytes>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Attestation {
+    pub version: u32,
+    /// Fingerprint (keccak256) of the attested signing key.
+    pub key: B256,
+    /// The host's ML-DSA-65 public key.
+    pub host_key: Bytes,
+    pub issued_at: u64,
+    pub expires_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub measurement: Option<B256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote: Option<Bytes>,
+    /// Host signature over [`Attestation::statement`].
+    pub signature: Bytes,
+}
+
+impl Attestation {
+    /// Issue a record for `key` (a public key), signed with the host seed.
+    pub fn issue(
+        host_seed: &[u8; SEED_LEN],
+        key: &[u8; PUBLIC_KEY_LEN],
+        issued_at: u64,
+        expires_at: u64,
+        claims: HostClaims,
+    ) -> Self {
+        let host = keys::keygen_from_seed(host_seed);
+        let mut record = Attestation {
+            version: ATTESTATION_VERSION,
+            key: keys::fingerprint(key, HashAlg::Keccak256),
+            host_key: Bytes::copy_from_slice(&host.verifying_key().encode()),
+            issued_at,
+            expires_at,
+            platform: claims.platform,
+            measurement: claims.measurement,
+            quote: claims.quote,
+            signature: Bytes::new(),
+        };
+        let sig = host.signing_key().sign(record.statement().as_slice());
+        record.signature = Bytes::copy_from_slice(&sig.encode());
+        record
+    }
+
+    /// Fingerprint of the host key, which is what relying parties pin.
+    pub fn host_fingerprint(&self) -> B256 {
+        keys::fingerprint(&self.host_key, HashAlg::Keccak256)
+    }
+
+    /// The 32-byte digest the host signs.
+    pub fn statement(&self) -> B256 {
+        let mut preimage = DOMAIN.to_vec();
+        preimage.extend_from_slice(&self.version.to_be_bytes());
+        preimage.extend_from_slice(self.key.as_slice());
+        preimage.extend_from_slice(self.host_fingerprint().as_slice());
+        preimage.extend_from_slice(&self.issued_at.to_be_bytes());
+        preimage.extend_from_slice(&self.expires_at.to_be_bytes());
+        let platform = self.platform.as_deref().unwrap_or("");
+        let quote: &[u8] = self.quote.as_ref().map_or(&[], |q| q.as_ref());
+        preimage.extend_from_slice(keccak256(platform).as_slice());
+        preimage.extend_from_slice(self.measurement.unwrap_or_default().as_slice());
+        preimage.extend_from_slice(keccak256(quote).as_slice());
+        keccak256(preimage)
+    }
+
+    /// Check the record attests `key`, comes from one of `trusted_hosts`
+    /// (host key fingerprints), is inside its validity window at `now`, and
+    /// carries a valid host signature.
+    pub fn verify(
+        &self,
+        key: &[u8; PUBLIC_KEY_LEN],
+        trusted_hosts: &[B256],
+        now: u64,
+    ) -> Result<()> {
+        if self.version != ATTESTATION_VERSION {
+            return Err(err(format!("unsupported version {}", self.version)));
+        }
+        if self.key != keys::fingerprint(key, HashAlg::Keccak256) {
+            return Err(err(format!("attests key {}, not this one", self.key)));
+        }
+        let host = self.host_fingerprint();
+        if !trusted_hosts.contains(&host) {
+            return Err(err(format!("host {host} is not trusted")));
+        }
+        if now < self.issued_at || now >= self.expires_at {
+            return Err(err(format!(
+                "valid from {} until {}, now {now}",
+                self.issued_at, self.expires_at
+            )));
+        }
+        if !Backend::RustCrypto.verify(
+            &self.host_key,
+            self.statement().as_slice(),
+            &[],
+            &self.signature,
+        ) {
+            return Err(err("host signature does not verify"));
+        }
+        Ok(())
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).map_err(|e| err(format!("invalid record: {e}")))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("attestation serializes")
+    }
+}
+
+/// A sign response as `pq-sign --json` prints it, read back by the party
+/// that accepts the signature.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SignResponse {
+    pub signature: Bytes,
+    #[serde(default)]
+    pub attestation: Option<Attestation>,
+}
+
+impl SignResponse {
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).map_err(|e| err(format!("invalid sign response: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pk(seed: u8) -> [u8; PUBLIC_KEY_LEN] {
+        keys::keygen_from_seed(&[seed; SEED_LEN])
+            .verifying_key()
+            .encode()
+            .into()
+    }
+
+    #[test]
+    fn issued_record_verifies_for_trusted_host() {
+        let host_seed = [1u8; SEED_LEN];
+        let key = pk(2);
+        let claims = HostClaims {
+            platform: Some("aws-nitro".into()),
+            measurement: Some(B256::repeat_byte(9)),
+            quote: Some(Bytes::from_static(b"quote")),
+        };
+        let record = Attestation::issue(&host_seed, &key, 100, 200, claims);
+        let host = record.host_fingerprint();
+        assert_eq!(host, keys::fingerprint(&pk(1), HashAlg::Keccak256));
+        record.verify(&key, &[host], 150).unwrap();
+
+        let parsed = Attestation::from_json(record.to_json().as_bytes()).unwrap();
+        assert_eq!(parsed, record);
+        assert!(record.verify(&key, &[], 150).is_err());
+        assert!(record.verify(&key, &[host], 200).is_err());
+        assert!(record.verify(&pk(3), &[host], 150).is_err());
+    }
+
+    #[test]
+    fn altered_claims_break_the_signature() {
+        let key = pk(2);
+        let mut record =
+            Attestation::issue(&[1u8; SEED_LEN], &key, 100, 200, HostClaims::default());
+        let host = record.host_fingerprint();
+        record.expires_at = u64::MAX;
+        assert!(record.verify(&key, &[host], 150).is_err());
+
+        let mut record =
+            Attestation::issue(&[1u8; SEED_LEN], &key, 100, 200, HostClaims::default());
+        record.quote = Some(Bytes::from_static(b"other"));
+        assert!(record.verify(&key, &[host], 150).is_err());
+
+        let response =
+            SignResponse::from_json(br#"{"signature":"0x01","length":1,"scheme":1}"#).unwrap();
+        assert!(response.attestation.is_none());
+    }
+}
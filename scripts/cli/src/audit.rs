@@ -0,0 +1,240 @@
+//! Append-only audit log of every signature the CLI produces.
+//!
+//! One JSON entry per line in `~/.pq-wallet/audit.jsonl` (or
+//! `$PQ_WALLET_AUDIT_LOG`). Each entry carries `prev`, the previous entry's
+//! `hash`, and its own `hash`: keccak256 of the entry's JSON without that
+//! field. Editing, removing or reordering an entry breaks the chain from
+//! there on; `pq-audit verify` walks it. Cutting entries off the end cannot
+//! be detected from the file alone, so record the head hash elsewhere and
+//! pass it back with `--expect-head`.
+//!
+//! The log is written after signing and before the signature is output: a
+//! tool that cannot log refuses to hand the signature out.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::{Address, B256, keccak256};
+use serde::{Deserialize, Serialize};
+
+use crate::attestation;
+use crate::error::{Result, WalletError};
+use crate::keystore::Keystore;
+
+/// Overrides the default log path.
+pub const AUDIT_LOG_ENV: &str = "PQ_WALLET_AUDIT_LOG";
+
+fn audit_err(reason: impl Into<String>) -> WalletError {
+    WalletError::Audit(reason.into())
+}
+
+/// What was signed, as the signing tool reports it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Record {
+    /// Binary that produced the signature, e.g. `pq-userop sign`.
+    pub tool: String,
+    /// Keystore account, if the key came from one.
+    pub account: Option<String>,
+    /// Address derived from the signing key.
+    pub key_id: Address,
+    /// The 32-byte message signed (the userOpHash for UserOperations);
+    /// `None` for raw-message signatures.
+    pub digest: Option<B256>,
+    pub chain_id: Option<u64>,
+    /// keccak256 of the encoded signature.
+    pub signature: B256,
+    /// Human-readable description, e.g. [`crate::decode::preview`] lines.
+    pub summary: Vec<String>,
+    pub backend: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub seq: u64,
+    /// Unix seconds.
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub record: Record,
+    pub prev: B256,
+    pub hash: B256,
+}
+
+#[derive(Serialize)]
+struct Body<'a> {
+    seq: u64,
+    timestamp: u64,
+    #[serde(flatten)]
+    record: &'a Record,
+    prev: B256,
+}
+
+impl Entry {
+    fn compute_hash(&self) -> B256 {
+        let body = Body {
+            seq: self.seq,
+            timestamp: self.timestamp,
+            record: &self.record,
+            prev: self.prev,
+        };
+        keccak256(serde_json::to_vec(&body).expect("entry serializes"))
+    }
+}
+
+/// Result of walking a log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Report {
+    pub entries: u64,
+    /// Hash of the last entry; zero for an empty log.
+    pub head: B256,
+}
+
+#[derive(Clone, Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    /// `$PQ_WALLET_AUDIT_LOG`, else `audit.jsonl` in the keystore root.
+    pub fn default_path() -> Result<PathBuf> {
+        match std::env::var_os(AUDIT_LOG_ENV) {
+            Some(path) => Ok(PathBuf::from(path)),
+            None => Ok(Keystore::default_root()?.join("audit.jsonl")),
+        }
+    }
+
+    pub fn open_default() -> Result<Self> {
+        Ok(Self::open(Self::default_path()?))
+    }
+
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        AuditLog { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Every entry, checking the chain as it goes.
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        let file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(WalletError::Io(self.path.display().to_string(), e)),
+        };
+        let mut entries: Vec<Entry> = Vec::new();
+        for (n, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| WalletError::Io(self.path.display().to_string(), e))?;
+            let at = |reason: String| audit_err(format!("line {}: {reason}", n + 1));
+            let entry: Entry = serde_json::from_str(&line).map_err(|e| at(e.to_string()))?;
+            let (seq, prev) = entries
+                .last()
+                .map_or((0, B256::ZERO), |last| (last.seq + 1, last.hash));
+            if entry.seq != seq {
+                return Err(at(format!("seq {} where {seq} was expected", entry.seq)));
+            }
+            if entry.prev != prev {
+                return Err(at("does not chain to the previous entry".into()));
+            }
+            if entry.hash != entry.compute_hash() {
+                return Err(at("hash does not match contents (entry was edited)".into()));
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    pub fn verify(&self) -> Result<Report> {
+        let entries = self.entries()?;
+        Ok(Report {
+            entries: entries.len() as u64,
+            head: entries.last().map_or(B256::ZERO, |e| e.hash),
+        })
+    }
+
+    /// Chain `record` onto the log. The existing log is verified first, so
+    /// nothing is appended to a broken chain.
+    pub fn append(&self, record: Record) -> Result<Entry> {
+        let head = self.verify()?;
+        let mut entry = Entry {
+            seq: head.entries,
+            timestamp: attestation::now(),
+            record,
+            prev: head.head,
+            hash: B256::ZERO,
+        };
+        entry.hash = entry.compute_hash();
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| WalletError::Io(dir.display().to_string(), e))?;
+        }
+        let io_err = |e| WalletError::Io(self.path.display().to_string(), e);
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(io_err)?;
+        let line = serde_json::to_string(&entry).expect("entry serializes");
+        writeln!(file, "{line}").map_err(io_err)?;
+        file.sync_all().map_err(io_err)?;
+        Ok(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_log(name: &str) -> AuditLog {
+        let path =
+            std::env::temp_dir().join(format!("pq-audit-{name}-{}.jsonl", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        AuditLog::open(path)
+    }
+
+    fn record(n: u8) -> Record {
+        Record {
+            tool: "pq-sign".into(),
+            account: Some("alice".into()),
+            key_id: Address::repeat_byte(n),
+            digest: Some(B256::repeat_byte(n)),
+            chain_id: None,
+            signature: B256::repeat_byte(n + 1),
+            summary: vec![format!("sign digest {n}")],
+            backend: "rustcrypto".into(),
+        }
+    }
+
+    #[test]
+    fn appended_entries_chain() {
+        let log = temp_log("chain");
+        assert_eq!(log.verify().unwrap().entries, 0);
+        let first = log.append(record(1)).unwrap();
+        let second = log.append(record(2)).unwrap();
+        assert_eq!(first.prev, B256::ZERO);
+        assert_eq!(second.prev, first.hash);
+        let report = log.verify().unwrap();
+        assert_eq!((report.entries, report.head), (2, second.hash));
+        std::fs::remove_file(log.path()).ok();
+    }
+
+    #[test]
+    fn edits_and_deletions_are_detected() {
+        let log = temp_log("tamper");
+        for n in 1..=3 {
+            log.append(record(n)).unwrap();
+        }
+        let text = std::fs::read_to_string(log.path()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        let edited = text.replace("sign digest 2", "sign digest 9");
+        std::fs::write(log.path(), edited).unwrap();
+        let err = log.verify().unwrap_err().to_string();
+        assert!(err.contains("line 2") && err.contains("edited"), "{err}");
+
+        std::fs::write(log.path(), format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        assert!(log.verify().unwrap_err().to_string().contains("line 2"));
+        assert!(log.append(record(4)).is_err());
+        std::fs::remove_file(log.path()).ok();
+    }
+}
@@ -0,0 +1,340 @@
+//! Who may ask `pq-signerd` for signatures (`async` feature).
+//!
+//! The daemon's config file (`pq-signerd --config signerd.toml`) lists
+//! principals, each identified by a bearer token, a client certificate, or
+//! both, and scoped to the accounts it may sign with:
+//!
+//! ```toml
+//! [[principal]]
+//! name = "app-server"
+//! # sha256 of the token, never the token itself
+//! token_sha256 = "0x9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+//! # sha256 of the DER client certificate (needs --client-ca)
+//! client_cert_sha256 = "0x..."
+//! accounts = ["alice"]
+//! requests_per_minute = 60
+//! burst = 10
+//! ```
+//!
+//! A principal with both credentials needs both on every request. `accounts
+//! = ["*"]` allows every account the daemon serves. Requests past a
+//! principal's rate are refused until its bucket refills; `burst` (default
+//! one minute's worth) is how many may arrive at once.
+
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use alloy_primitives::B256;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::ct;
+use crate::error::{Result, WalletError};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    #[serde(default, rename = "principal")]
+    pub principals: Vec<PrincipalConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrincipalConfig {
+    pub name: String,
+    pub token_sha256: Option<B256>,
+    pub client_cert_sha256: Option<B256>,
+    pub accounts: Vec<String>,
+    pub requests_per_minute: Option<u32>,
+    pub burst: Option<u32>,
+}
+
+impl AuthConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| WalletError::Io(path.display().to_string(), e))?;
+        Self::from_toml(&text)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let config: AuthConfig =
+            toml::from_str(text).map_err(|e| WalletError::Config(e.to_string()))?;
+        for p in &config.principals {
+            if p.token_sha256.is_none() && p.client_cert_sha256.is_none() {
+                return Err(WalletError::Config(format!(
+                    "principal {} needs token_sha256 or client_cert_sha256",
+                    p.name
+                )));
+            }
+            if p.requests_per_minute == Some(0) {
+                return Err(WalletError::Config(format!(
+                    "principal {}: requests_per_minute must be positive",
+                    p.name
+                )));
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Why a request was turned away.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Refusal {
+    /// No principal matches the credentials presented.
+    Unauthenticated,
+    /// The principal is not scoped to the account.
+    Forbidden { principal: String, account: String },
+    RateLimited {
+        principal: String,
+        retry_after: Duration,
+    },
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Refusal::Unauthenticated => f.write_str("no valid token or client certificate"),
+            Refusal::Forbidden { principal, account } => {
+                write!(f, "{principal} may not sign as {account}")
+            }
+            Refusal::RateLimited {
+                principal,
+                retry_after,
+            } => write!(
+                f,
+                "{principal} is over its rate limit (retry in {}ms)",
+                retry_after.as_millis()
+            ),
+        }
+    }
+}
+
+/// What a request presented.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Credentials<'a> {
+    pub token: Option<&'a str>,
+    /// The leaf client certificate, DER.
+    pub client_cert: Option<&'a [u8]>,
+}
+
+/// A token bucket.
+#[derive(Debug)]
+struct Bucket {
+    per_second: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn take(&mut self, now: Instant) -> std::result::Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.per_second,
+            ))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Principal {
+    pub name: String,
+    token_sha256: Option<B256>,
+    client_cert_sha256: Option<B256>,
+    /// `None` for every account.
+    accounts: Option<Vec<String>>,
+    bucket: Option<Mutex<Bucket>>,
+}
+
+impl Principal {
+    /// The caller when the daemon runs without a config file: any account,
+    /// no limit.
+    pub fn anonymous() -> Self {
+        Principal {
+            name: "anonymous".to_string(),
+            token_sha256: None,
+            client_cert_sha256: None,
+            accounts: None,
+            bucket: None,
+        }
+    }
+
+    fn from_config(config: &PrincipalConfig, now: Instant) -> Self {
+        let bucket = config.requests_per_minute.map(|rate| {
+            let capacity = f64::from(config.burst.unwrap_or(rate).max(1));
+            Mutex::new(Bucket {
+                per_second: f64::from(rate) / 60.0,
+                capacity,
+                tokens: capacity,
+                last: now,
+            })
+        });
+        Principal {
+            name: config.name.clone(),
+            token_sha256: config.token_sha256,
+            client_cert_sha256: config.client_cert_sha256,
+            accounts: (!config.accounts.iter().any(|a| a == "*")).then(|| config.accounts.clone()),
+            bucket,
+        }
+    }
+
+    /// Every configured credential is present and matches.
+    fn matches(&self, credentials: &Credentials<'_>) -> bool {
+        let check = |expected: Option<B256>, presented: Option<&[u8]>| match expected {
+            None => true,
+            Some(digest) => presented
+                .is_some_and(|bytes| ct::eq(Sha256::digest(bytes).as_slice(), digest.as_slice())),
+        };
+        check(self.token_sha256, credentials.token.map(str::as_bytes))
+            & check(self.client_cert_sha256, credentials.client_cert)
+    }
+
+    pub fn may_use(&self, account: &str) -> bool {
+        self.accounts
+            .as_ref()
+            .is_none_or(|accounts| accounts.iter().any(|a| a == account))
+    }
+
+    /// Refused unless the principal may sign as `account`.
+    pub fn authorize(&self, account: &str) -> std::result::Result<(), Refusal> {
+        if self.may_use(account) {
+            Ok(())
+        } else {
+            Err(Refusal::Forbidden {
+                principal: self.name.clone(),
+                account: account.to_string(),
+            })
+        }
+    }
+
+    fn admit(&self, now: Instant) -> std::result::Result<(), Refusal> {
+        match &self.bucket {
+            Some(bucket) => bucket
+                .lock()
+                .expect("bucket lock poisoned")
+                .take(now)
+                .map_err(|retry_after| Refusal::RateLimited {
+                    principal: self.name.clone(),
+                    retry_after,
+                }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Matches requests to principals.
+#[derive(Debug)]
+pub struct Authenticator {
+    principals: Vec<Arc<Principal>>,
+}
+
+impl Authenticator {
+    pub fn new(config: &AuthConfig) -> Self {
+        let now = Instant::now();
+        Authenticator {
+            principals: config
+                .principals
+                .iter()
+                .map(|p| Arc::new(Principal::from_config(p, now)))
+                .collect(),
+        }
+    }
+
+    /// The principal `credentials` identify, charged one request.
+    pub fn authenticate(
+        &self,
+        credentials: &Credentials<'_>,
+        now: Instant,
+    ) -> std::result::Result<Arc<Principal>, Refusal> {
+        let principal = self
+            .principals
+            .iter()
+            .find(|p| p.matches(credentials))
+            .ok_or(Refusal::Unauthenticated)?;
+        principal.admit(now)?;
+        Ok(principal.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sha(bytes: &[u8]) -> String {
+        format!("0x{}", ct::encode_hex(&Sha256::digest(bytes)))
+    }
+
+    #[test]
+    fn authenticates_scopes_and_limits_principals() {
+        let config = AuthConfig::from_toml(&format!(
+            r#"
+            [[principal]]
+            name = "app"
+            token_sha256 = "{}"
+            accounts = ["alice"]
+            requests_per_minute = 60
+            burst = 2
+
+            [[principal]]
+            name = "ops"
+            token_sha256 = "{}"
+            client_cert_sha256 = "{}"
+            accounts = ["*"]
+            "#,
+            sha(b"app-token"),
+            sha(b"ops-token"),
+            sha(b"ops-cert"),
+        ))
+        .unwrap();
+        let auth = Authenticator::new(&config);
+        let now = Instant::now();
+        let token = |token| Credentials {
+            token: Some(token),
+            client_cert: None,
+        };
+
+        let app = auth.authenticate(&token("app-token"), now).unwrap();
+        assert_eq!(app.name, "app");
+        assert!(app.authorize("alice").is_ok());
+        assert!(matches!(
+            app.authorize("bob"),
+            Err(Refusal::Forbidden { .. })
+        ));
+        assert_eq!(
+            auth.authenticate(&token("wrong"), now).unwrap_err(),
+            Refusal::Unauthenticated
+        );
+
+        // A burst of 2, refilled at one a second.
+        auth.authenticate(&token("app-token"), now).unwrap();
+        assert!(matches!(
+            auth.authenticate(&token("app-token"), now),
+            Err(Refusal::RateLimited { .. })
+        ));
+        let later = now + Duration::from_secs(1);
+        auth.authenticate(&token("app-token"), later).unwrap();
+
+        // The token alone is not enough when a certificate is configured too.
+        assert!(auth.authenticate(&token("ops-token"), now).is_err());
+        let ops = auth
+            .authenticate(
+                &Credentials {
+                    token: Some("ops-token"),
+                    client_cert: Some(b"ops-cert"),
+                },
+                now,
+            )
+            .unwrap();
+        assert!(ops.authorize("bob").is_ok());
+
+        assert!(AuthConfig::from_toml("[[principal]]\nname = \"x\"\naccounts = []").is_err());
+    }
+}
@@ -0,0 +1,214 @@
+//! Thin, byte-oriented wrappers over the two ML-DSA-65 implementations we
+//! track: RustCrypto `ml-dsa` (what the Stylus verifier ships) and `fips204`.
+//!
+//! Everything here takes and returns encoded bytes so callers can mix
+//! backends freely, e.g. sign with one and verify with the other. `fips204`
+//! is compiled in with the `fips204` feature (on by default); `pq-keygen`,
+//! `pq-sign` and `pq-verify` pick one with `--backend`.
+
+#[cfg(feature = "fips204")]
+use fips204::ml_dsa_65;
+#[cfg(feature = "fips204")]
+use fips204::traits::{KeyGen as _, SerDes as _, Signer as _, Verifier as _};
+use ml_dsa::{MlDsa65, Signature, VerifyingKey};
+use rand::CryptoRng;
+use zeroize::Zeroizing;
+
+use crate::error::{Result, WalletError};
+use crate::keys::{self, PUBLIC_KEY_LEN, SEED_LEN, SIGNATURE_LEN};
+use crate::signer::PqSigner;
+
+/// An ML-DSA-65 implementation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    RustCrypto,
+    #[cfg(feature = "fips204")]
+    Fips204,
+}
+
+impl Backend {
+    /// The backends compiled into this build.
+    pub const ALL: &[Backend] = &[
+        Backend::RustCrypto,
+        #[cfg(feature = "fips204")]
+        Backend::Fips204,
+    ];
+
+    /// Encoded public key for `seed` (ML-DSA.KeyGen_internal).
+    pub fn public_key(self, seed: &[u8; SEED_LEN]) -> Vec<u8> {
+        match self {
+            Backend::RustCrypto => keys::keygen_from_seed(seed)
+                .verifying_key()
+                .encode()
+                .to_vec(),
+            #[cfg(feature = "fips204")]
+            Backend::Fips204 => {
+                let (pk, _) = ml_dsa_65::KG::keygen_from_seed(seed);
+                pk.into_bytes().to_vec()
+            }
+        }
+    }
+
+    /// ML-DSA.Sign with explicit per-signature randomness `rnd`. An all-zero
+    /// `rnd` is the deterministic variant that `pq-sign` uses.
+    #[tracing::instrument(level = "debug", name = "sign", skip_all, fields(backend = %self))]
+    pub fn sign(
+        self,
+        seed: &[u8; SEED_LEN],
+        msg: &[u8],
+        ctx: &[u8],
+        rnd: &[u8; 32],
+    ) -> Result<Vec<u8>> {
+        if ctx.len() > 255 {
+            return Err(WalletError::InvalidLength {
+                what: "context",
+                expected: 255,
+                actual: ctx.len(),
+            });
+        }
+        match self {
+            Backend::RustCrypto => PqSigner::from_seed(seed).sign_with(msg, ctx, rnd),
+            #[cfg(feature = "fips204")]
+            Backend::Fips204 => {
+                let (_, sk) = ml_dsa_65::KG::keygen_from_seed(seed);
+                let sig = sk
+                    .try_sign_with_seed(rnd, msg, ctx)
+                    .map_err(|e| WalletError::Backend(e.to_string()))?;
+                Ok(sig.to_vec())
+            }
+        }
+    }
+
+    /// [`sign`](Self::sign) with `rnd` drawn from `rng`.
+    pub fn sign_with_rng<R: CryptoRng + ?Sized>(
+        self,
+        seed: &[u8; SEED_LEN],
+        msg: &[u8],
+        ctx: &[u8],
+        rng: &mut R,
+    ) -> Result<Vec<u8>> {
+        let mut rnd = Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(rnd.as_mut());
+        self.sign(seed, msg, ctx, &rnd)
+    }
+
+    /// ML-DSA.Verify. Malformed keys or signatures verify as `false`.
+    #[tracing::instrument(level = "debug", name = "verify", skip_all, fields(backend = %self))]
+    pub fn verify(self, pk: &[u8], msg: &[u8], ctx: &[u8], sig: &[u8]) -> bool {
+        let Ok(pk_arr) = <[u8; PUBLIC_KEY_LEN]>::try_from(pk) else {
+            return false;
+        };
+        let Ok(sig_arr) = <[u8; SIGNATURE_LEN]>::try_from(sig) else {
+            return false;
+        };
+        match self {
+            Backend::RustCrypto => {
+                let vk = VerifyingKey::<MlDsa65>::decode(&pk_arr.into());
+                match Signature::<MlDsa65>::try_from(sig_arr.as_slice()) {
+                    Ok(sig) => vk.verify_with_context(msg, ctx, &sig),
+                    Err(_) => false,
+                }
+            }
+            #[cfg(feature = "fips204")]
+            Backend::Fips204 => match ml_dsa_65::PublicKey::try_from_bytes(pk_arr) {
+                Ok(vk) => vk.verify(msg, &sig_arr, ctx),
+                Err(_) => false,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::RustCrypto => write!(f, "rustcrypto"),
+            #[cfg(feature = "fips204")]
+            Backend::Fips204 => write!(f, "fips204"),
+        }
+    }
+}
+
+impl std::str::FromStr for Backend {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rustcrypto" | "ml-dsa" => Ok(Backend::RustCrypto),
+            #[cfg(feature = "fips204")]
+            "fips204" => Ok(Backend::Fips204),
+            #[cfg(not(feature = "fips204"))]
+            "fips204" => Err(WalletError::Backend(
+                "fips204 is not in this build (enable the fips204 feature)".into(),
+            )),
+            other => Err(WalletError::Backend(format!(
+                "unknown backend {other:?} (expected rustcrypto or fips204)"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ml_dsa::signature::Signer;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn deterministic_sign_matches_signer_trait() {
+        // pq-sign uses `Signer::sign`, which is deterministic with an empty context.
+        let seed = [3u8; SEED_LEN];
+        let msg = [0xAB_u8; 32];
+        let expected = keys::keygen_from_seed(&seed).signing_key().sign(&msg);
+        for backend in Backend::ALL {
+            let sig = backend.sign(&seed, &msg, &[], &[0u8; 32]).unwrap();
+            assert_eq!(sig, expected.encode().to_vec(), "{backend}");
+        }
+    }
+
+    #[test]
+    fn seeded_keygen_agrees_and_signatures_cross_verify() {
+        let seed = [9u8; SEED_LEN];
+        let pk = keys::keygen_from_seed(&seed)
+            .verifying_key()
+            .encode()
+            .to_vec();
+        let sigs: Vec<_> = Backend::ALL
+            .iter()
+            .map(|b| b.sign(&seed, b"cross", b"ctx", &[7; 32]).unwrap())
+            .collect();
+        for (signer, sig) in Backend::ALL.iter().zip(&sigs) {
+            assert_eq!(signer.public_key(&seed), pk, "{signer}");
+            assert_eq!(*sig, sigs[0], "{signer}");
+            for verifier in Backend::ALL {
+                assert!(
+                    verifier.verify(&pk, b"cross", b"ctx", sig),
+                    "{signer} -> {verifier}"
+                );
+                assert!(!verifier.verify(&pk, b"cross", b"other", sig));
+            }
+        }
+        for backend in Backend::ALL {
+            assert_eq!(backend.to_string().parse::<Backend>().unwrap(), *backend);
+            // A seeded rng gives the same hedged signature every time.
+            let mut rnd = [0u8; 32];
+            StdRng::seed_from_u64(1).fill_bytes(&mut rnd);
+            let mut rng = StdRng::seed_from_u64(1);
+            assert_eq!(
+                backend
+                    .sign_with_rng(&seed, b"cross", b"ctx", &mut rng)
+                    .unwrap(),
+                backend.sign(&seed, b"cross", b"ctx", &rnd).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn verify_rejects_malformed_inputs() {
+        for backend in Backend::ALL {
+            assert!(!backend.verify(&[0u8; 10], b"", &[], &[0u8; SIGNATURE_LEN]));
+            assert!(!backend.verify(&[0u8; PUBLIC_KEY_LEN], b"", &[], &[0u8; 10]));
+        }
+    }
+}
@@ -0,0 +1,313 @@
+//! Native and ERC-20 balances and allowances (`pq-account balance`), and the
+//! preflight that refuses to sign calls the account cannot pay for.
+//!
+//! A smart account can be funded before it is deployed, so balances are read
+//! at its address whether or not it has code yet. [`preflight`] adds up what a
+//! set of calls spends (native `value`, ERC-20 `transfer` amounts, and
+//! `transferFrom` amounts against both the holder's balance and the
+//! account's allowance) and reports every shortfall, instead of letting the
+//! bundler or the chain discover the revert. Targets without `decimals()`
+//! are taken for NFTs, whose `transferFrom` looks the same, and skipped.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use alloy_primitives::{Address, Bytes, U256};
+use alloy_sol_types::{SolCall, sol};
+use serde::Serialize;
+
+use crate::calldata::Call;
+use crate::decode::{self, transferCall, transferFromCall};
+use crate::error::{Result, WalletError};
+use crate::rpc::Transport;
+
+sol! {
+    function balanceOf(address owner) returns (uint256);
+    function allowance(address owner, address spender) returns (uint256);
+    function decimals() returns (uint8);
+    function symbol() returns (string);
+}
+
+/// One line of `pq-account balance`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Holding {
+    /// `None` for the chain's native currency.
+    pub token: Option<Address>,
+    pub symbol: String,
+    pub decimals: u8,
+    pub amount: U256,
+}
+
+impl fmt::Display for Holding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let amount = decode::format_units(self.amount, self.decimals);
+        match self.token {
+            Some(token) => write!(f, "{amount} {} ({token})", self.symbol),
+            None => write!(f, "{amount} {}", self.symbol),
+        }
+    }
+}
+
+fn call<T: Transport, C: SolCall>(rpc: &T, to: Address, call: C) -> Result<C::Return> {
+    let ret: Bytes = rpc.call(
+        "eth_call",
+        serde_json::json!([{ "to": to, "data": Bytes::from(call.abi_encode()) }, "latest"]),
+    )?;
+    if ret.is_empty() {
+        return Err(WalletError::Rpc(format!(
+            "{to} has no code; is it a token?"
+        )));
+    }
+    C::abi_decode_returns(&ret)
+        .map_err(|e| WalletError::Rpc(format!("{to}: undecodable {}: {e}", C::SIGNATURE)))
+}
+
+pub fn native_balance<T: Transport>(rpc: &T, owner: Address) -> Result<U256> {
+    rpc.call("eth_getBalance", serde_json::json!([owner, "latest"]))
+}
+
+pub fn balance_of<T: Transport>(rpc: &T, token: Address, owner: Address) -> Result<U256> {
+    call(rpc, token, balanceOfCall { owner })
+}
+
+pub fn allowance<T: Transport>(
+    rpc: &T,
+    token: Address,
+    owner: Address,
+    spender: Address,
+) -> Result<U256> {
+    call(rpc, token, allowanceCall { owner, spender })
+}
+
+/// Symbol and decimals: from the bundled registry if it knows the token,
+/// else from the token itself.
+pub fn describe<T: Transport>(rpc: &T, chain_id: u64, token: Address) -> Result<(String, u8)> {
+    if let Some(known) = decode::token(chain_id, token) {
+        return Ok((known.symbol.to_string(), known.decimals));
+    }
+    let decimals = call(rpc, token, decimalsCall {})?;
+    let symbol = call(rpc, token, symbolCall {}).unwrap_or_else(|_| "tokens".to_string());
+    Ok((symbol, decimals))
+}
+
+/// `owner`'s native balance, then its balance of each bundled token on the
+/// chain and of each of `tokens`.
+pub fn holdings<T: Transport>(
+    rpc: &T,
+    chain_id: u64,
+    owner: Address,
+    tokens: &[Address],
+) -> Result<Vec<Holding>> {
+    let mut out = vec![Holding {
+        token: None,
+        symbol: "ETH".to_string(),
+        decimals: 18,
+        amount: native_balance(rpc, owner)?,
+    }];
+    let bundled = decode::TOKENS
+        .iter()
+        .filter(|t| t.chain_id == chain_id)
+        .map(|t| t.address);
+    let mut seen = Vec::new();
+    for token in bundled.chain(tokens.iter().copied()) {
+        if seen.contains(&token) {
+            continue;
+        }
+        seen.push(token);
+        let (symbol, decimals) = describe(rpc, chain_id, token)?;
+        out.push(Holding {
+            token: Some(token),
+            symbol,
+            decimals,
+            amount: balance_of(rpc, token, owner)?,
+        });
+    }
+    Ok(out)
+}
+
+/// Something the calls spend more of than is there.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Shortfall {
+    /// `None` for native value.
+    pub token: Option<Address>,
+    /// Whose balance, or whose allowance to the account, falls short.
+    pub holder: Address,
+    /// Whether the allowance rather than the balance falls short.
+    pub allowance: bool,
+    pub symbol: String,
+    pub decimals: u8,
+    pub needed: U256,
+    pub available: U256,
+}
+
+impl fmt::Display for Shortfall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let whole = |v| format!("{} {}", decode::format_units(v, self.decimals), self.symbol);
+        let (needed, available) = (whole(self.needed), whole(self.available));
+        if self.allowance {
+            write!(
+                f,
+                "the calls pull {needed} from {} but it allows only {available}; approve the \
+                 account first",
+                self.holder
+            )
+        } else {
+            write!(
+                f,
+                "the calls spend {needed} but {} holds only {available}",
+                self.holder
+            )
+        }
+    }
+}
+
+/// What `sender`'s calls need but don't have, checked on `rpc`.
+pub fn preflight<T: Transport>(
+    rpc: &T,
+    chain_id: u64,
+    sender: Address,
+    calls: &[Call],
+) -> Result<Vec<Shortfall>> {
+    // (token, holder, is allowance) -> amount.
+    let mut needs: BTreeMap<(Option<Address>, Address, bool), U256> = BTreeMap::new();
+    let mut add = |key, amount: U256| {
+        let total = needs.entry(key).or_default();
+        *total = total.saturating_add(amount);
+    };
+    for c in calls {
+        if !c.value.is_zero() {
+            add((None, sender, false), c.value);
+        }
+        if let Ok(t) = transferCall::abi_decode(&c.data) {
+            add((Some(c.target), sender, false), t.amount);
+        } else if let Ok(t) = transferFromCall::abi_decode(&c.data) {
+            add((Some(c.target), t.from, false), t.amount);
+            if t.from != sender {
+                add((Some(c.target), t.from, true), t.amount);
+            }
+        }
+    }
+
+    let mut shortfalls = Vec::new();
+    for ((token, holder, is_allowance), needed) in needs {
+        let (symbol, decimals) = match token {
+            // An ERC-721 transferFrom has the same selector; NFTs have no
+            // decimals().
+            Some(token) => match describe(rpc, chain_id, token) {
+                Ok(described) => described,
+                Err(_) => continue,
+            },
+            None => ("ETH".to_string(), 18),
+        };
+        let available = match (token, is_allowance) {
+            (None, _) => native_balance(rpc, holder)?,
+            (Some(token), false) => balance_of(rpc, token, holder)?,
+            (Some(token), true) => allowance(rpc, token, holder, sender)?,
+        };
+        if available >= needed {
+            continue;
+        }
+        shortfalls.push(Shortfall {
+            token,
+            holder,
+            allowance: is_allowance,
+            symbol,
+            decimals,
+            needed,
+            available,
+        });
+    }
+    Ok(shortfalls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_sol_types::SolValue;
+    use serde_json::Value;
+
+    const ACCOUNT: Address = Address::repeat_byte(0xaa);
+    const OWNER: Address = Address::repeat_byte(0x0e);
+    const TOKEN: Address = Address::repeat_byte(0x70);
+
+    /// 1 ETH and 50 whole units of a 6-decimal "TKN" for everyone, with `OWNER`
+    /// allowing the account 10.
+    struct Node;
+
+    impl Transport for Node {
+        fn request(&self, method: &str, params: Value) -> Result<Value> {
+            if method == "eth_getBalance" {
+                return Ok(serde_json::json!(U256::from(10u64).pow(U256::from(18))));
+            }
+            assert_eq!(method, "eth_call");
+            let data: Bytes = serde_json::from_value(params[0]["data"].clone()).unwrap();
+            let ret = if balanceOfCall::abi_decode(&data).is_ok() {
+                U256::from(50_000_000u64).abi_encode()
+            } else if let Ok(c) = allowanceCall::abi_decode(&data) {
+                assert_eq!((c.owner, c.spender), (OWNER, ACCOUNT));
+                U256::from(10_000_000u64).abi_encode()
+            } else if decimalsCall::abi_decode(&data).is_ok() {
+                U256::from(6u8).abi_encode()
+            } else {
+                "TKN".to_string().abi_encode()
+            };
+            Ok(serde_json::json!(Bytes::from(ret)))
+        }
+    }
+
+    fn transfer(amount: u64) -> Call {
+        Call {
+            target: TOKEN,
+            value: U256::ZERO,
+            data: transferCall {
+                to: OWNER,
+                amount: U256::from(amount),
+            }
+            .abi_encode(),
+        }
+    }
+
+    #[test]
+    fn holdings_list_native_then_tokens() {
+        let held = holdings(&Node, 8453, ACCOUNT, &[TOKEN, TOKEN]).unwrap();
+        assert_eq!(held.len(), 2);
+        assert_eq!(held[0].to_string(), "1 ETH");
+        assert_eq!(held[1].to_string(), format!("50 TKN ({TOKEN})"));
+    }
+
+    #[test]
+    fn preflight_sums_spends_and_checks_allowances() {
+        // 30 + 30 TKN is more than the 50 held; 0.5 ETH is fine.
+        let mut calls = vec![transfer(30_000_000), transfer(30_000_000)];
+        calls[0].value = U256::from(5u64) * U256::from(10u64).pow(U256::from(17));
+        let short = preflight(&Node, 8453, ACCOUNT, &calls).unwrap();
+        assert_eq!(short.len(), 1);
+        assert_eq!(
+            short[0].to_string(),
+            format!("the calls spend 60 TKN but {ACCOUNT} holds only 50 TKN")
+        );
+
+        // Pulling 20 from OWNER is within its balance but over its allowance.
+        let pull = Call {
+            target: TOKEN,
+            value: U256::ZERO,
+            data: transferFromCall {
+                from: OWNER,
+                to: ACCOUNT,
+                amount: U256::from(20_000_000u64),
+            }
+            .abi_encode(),
+        };
+        let short = preflight(&Node, 8453, ACCOUNT, &[pull]).unwrap();
+        assert_eq!(short.len(), 1);
+        assert!(short[0].allowance);
+        assert!(short[0].to_string().contains("allows only 10 TKN"));
+        assert!(
+            preflight(&Node, 8453, ACCOUNT, &[transfer(1)])
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! Batch verification over a manifest of (public key, hash, signature)
+//! triples, for CI jobs that check large fixture sets in one go.
+//!
+//! A manifest is a JSON array of items, or an object holding one under
+//! `items` or `vectors` — so `pq-vectors --format foundry` output can be fed
+//! in directly. Key and signature fields are either `0x`-prefixed inline
+//! hex or a path, resolved relative to the manifest's directory:
+//!
+//! ```json
+//! {"items": [
+//!   {"name": "alice", "publicKey": "keys/pk.bin", "hash": "0x…", "signature": "sigs/1.bin"},
+//!   {"publicKey": "0x…", "message": "0x…", "signature": "0x…", "expected": false}
+//! ]}
+//! ```
+//!
+//! `expected` (or `expectedResult`, as `pq-vectors --format json` writes it)
+//! defaults to `true`; an item passes when the verification result
+//! matches it.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use alloy_primitives::keccak256;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+use crate::ct;
+use crate::error::{Result, WalletError};
+use crate::verify_cache::{CacheKey, CacheStats, VerifyCache};
+
+/// One manifest entry.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestItem {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Inline hex or path to `pk.bin`.
+    pub public_key: String,
+    /// 32-byte message hash, hex.
+    #[serde(alias = "message")]
+    pub hash: String,
+    /// Inline hex or path to `sig.bin`.
+    pub signature: String,
+    #[serde(default, alias = "expectedResult")]
+    pub expected: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ManifestFile {
+    List(Vec<ManifestItem>),
+    Items { items: Vec<ManifestItem> },
+    Vectors { vectors: Vec<ManifestItem> },
+}
+
+/// Parsed manifest plus the directory relative paths resolve against.
+#[derive(Clone, Debug)]
+pub struct Manifest {
+    pub items: Vec<ManifestItem>,
+    pub base: PathBuf,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes =
+            std::fs::read(path).map_err(|e| WalletError::Io(path.display().to_string(), e))?;
+        let base = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        Self::from_json(&bytes, base)
+    }
+
+    pub fn from_json(bytes: &[u8], base: PathBuf) -> Result<Self> {
+        let items = match serde_json::from_slice(bytes)
+            .map_err(|e| WalletError::Manifest(e.to_string()))?
+        {
+            ManifestFile::List(items)
+            | ManifestFile::Items { items }
+            | ManifestFile::Vectors { vectors: items } => items,
+        };
+        Ok(Manifest { items, base })
+    }
+
+    fn bytes(&self, what: &str, value: &str) -> std::result::Result<Vec<u8>, String> {
+        if value.starts_with("0x") || value.starts_with("0X") {
+            return ct::decode_hex(value).map_err(|e| format!("{what}: {e}"));
+        }
+        let path = self.base.join(value);
+        std::fs::read(&path).map_err(|e| format!("{what}: {}: {e}", path.display()))
+    }
+}
+
+/// Outcome for one item.
+#[derive(Clone, Debug, Serialize)]
+pub struct ItemResult {
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Whether the signature verified; `None` if the item couldn't be loaded.
+    pub valid: Option<bool>,
+    pub expected: bool,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    pub total: usize,
+    pub passed: usize,
+    pub failed: usize,
+    /// Items that could not be loaded (counted in `failed`).
+    pub errors: usize,
+    pub elapsed_ms: u128,
+    pub per_second: f64,
+    /// Lookups in the verification cache, when one was used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheStats>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BatchReport {
+    pub results: Vec<ItemResult>,
+    pub summary: Summary,
+}
+
+impl BatchReport {
+    pub fn all_passed(&self) -> bool {
+        self.summary.failed == 0
+    }
+}
+
+fn check(
+    manifest: &Manifest,
+    cache: Option<&VerifyCache>,
+    index: usize,
+    item: &ManifestItem,
+) -> ItemResult {
+    let expected = item.expected.unwrap_or(true);
+    let loaded = (|| {
+        let pk = manifest.bytes("public key", &item.public_key)?;
+        let hash: [u8; 32] =
+            ct::decode_hex_array("hash", &item.hash).map_err(|e| format!("hash: {e}"))?;
+        let sig = manifest.bytes("signature", &item.signature)?;
+        Ok::<_, String>((pk, hash, sig))
+    })();
+    let (valid, error) = match loaded {
+        Ok((pk, hash, sig)) => {
+            let verify = || Backend::RustCrypto.verify(&pk, &hash, &[], &sig);
+            let valid = match cache {
+                None => verify(),
+                Some(cache) => {
+                    let key = CacheKey::new(&pk, keccak256(hash), &sig);
+                    cache.get(&key).unwrap_or_else(|| {
+                        let valid = verify();
+                        if let Err(e) = cache.insert(key, valid) {
+                            tracing::warn!("{e}");
+                        }
+                        valid
+                    })
+                }
+            };
+            (Some(valid), None)
+        }
+        Err(e) => (None, Some(e)),
+    };
+    ItemResult {
+        index,
+        name: item.name.clone(),
+        valid,
+        expected,
+        passed: valid == Some(expected),
+        error,
+    }
+}
+
+/// Verify every item in parallel on the current rayon pool. Results keep
+/// manifest order.
+pub fn verify_all(manifest: &Manifest) -> BatchReport {
+    verify_all_cached(manifest, None)
+}
+
+/// [`verify_all`], answering repeated items from `cache` and adding new
+/// results to it.
+pub fn verify_all_cached(manifest: &Manifest, cache: Option<&VerifyCache>) -> BatchReport {
+    let start = Instant::now();
+    let before = cache.map(VerifyCache::stats);
+    let results: Vec<ItemResult> = manifest
+        .items
+        .par_iter()
+        .enumerate()
+        .map(|(i, item)| check(manifest, cache, i, item))
+        .collect();
+    let mut report = summarize(results, start.elapsed());
+    report.summary.cache = cache
+        .zip(before)
+        .map(|(cache, before)| cache.stats().since(&before));
+    report
+}
+
+fn summarize(results: Vec<ItemResult>, elapsed: Duration) -> BatchReport {
+    let total = results.len();
+    let passed = results.iter().filter(|r| r.passed).count();
+    let errors = results.iter().filter(|r| r.error.is_some()).count();
+    let secs = elapsed.as_secs_f64();
+    BatchReport {
+        results,
+        summary: Summary {
+            total,
+            passed,
+            failed: total - passed,
+            errors,
+            elapsed_ms: elapsed.as_millis(),
+            per_second: if secs > 0.0 { total as f64 / secs } else { 0.0 },
+            cache: None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vectors;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn foundry_vectors_are_a_valid_manifest() {
+        let generated = vectors::generate(3, &mut StdRng::seed_from_u64(1));
+        let json = vectors::to_foundry_json(&generated);
+        let manifest = Manifest::from_json(json.as_bytes(), PathBuf::from(".")).unwrap();
+        assert_eq!(manifest.items.len(), 6);
+
+        let report = verify_all(&manifest);
+        assert!(report.all_passed(), "{:?}", report.results);
+        // Half the generated vectors are negatives that must not verify.
+        assert_eq!(
+            report
+                .results
+                .iter()
+                .filter(|r| r.valid == Some(false))
+                .count(),
+            3
+        );
+    }
+
+    #[test]
+    fn unloadable_and_mismatched_items_fail() {
+        let json = br#"[
+            {"publicKey": "missing/pk.bin", "hash": "0x00", "signature": "0x00"},
+            {"publicKey": "0x00", "hash": "0x0000000000000000000000000000000000000000000000000000000000000000", "signature": "0x00"}
+        ]"#;
+        let manifest = Manifest::from_json(json, PathBuf::from(".")).unwrap();
+        let report = verify_all(&manifest);
+        assert_eq!(report.summary.failed, 2);
+        assert_eq!(report.summary.errors, 1);
+        assert_eq!(report.results[1].valid, Some(false));
+    }
+
+    #[test]
+    fn a_second_run_is_answered_from_the_cache() {
+        let generated = vectors::generate(2, &mut StdRng::seed_from_u64(2));
+        let json = vectors::to_foundry_json(&generated);
+        let manifest = Manifest::from_json(json.as_bytes(), PathBuf::from(".")).unwrap();
+        let cache = VerifyCache::in_memory(16);
+
+        let first = verify_all_cached(&manifest, Some(&cache));
+        let stats = first.summary.cache.unwrap();
+        assert_eq!((stats.hits, stats.misses), (0, 4));
+        let second = verify_all_cached(&manifest, Some(&cache));
+        let stats = second.summary.cache.unwrap();
+        assert_eq!((stats.hits, stats.misses, stats.hit_rate), (4, 0, 1.0));
+        assert!(second.all_passed(), "{:?}", second.results);
+        assert!(verify_all(&manifest).summary.cache.is_none());
+    }
+}
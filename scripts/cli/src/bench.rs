@@ -0,0 +1,261 @@
+//! The userOp signing pipeline split into stages that can be timed on their
+//! own, shared by the criterion benches and `pq-bench`.
+//!
+//! Every stage runs against a fixed [`Fixture`] so numbers from different
+//! hosts are comparable: a batch `execute` op, a deterministic seed and a
+//! keystore entry encrypted with the production Argon2id parameters.
+//! `keccak` and `sign` are the two stages the `fast-keccak` feature speeds
+//! up; see [`crate::hash_backend`].
+
+use std::fmt;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use alloy_primitives::{Address, U256, keccak256};
+use ml_dsa::signature::Signer;
+use ml_dsa::{MlDsa65, SigningKey};
+use serde::Serialize;
+
+use crate::calldata::{self, Call};
+use crate::envelope::{self, SCHEME_ML_DSA_65};
+use crate::error::{Result, WalletError};
+use crate::keys::SEED_LEN;
+use crate::keystore::{Account, KdfParams};
+use crate::userop::{self, ENTRY_POINT_V07, PackedUserOperation};
+
+/// Inputs every stage works on.
+pub struct Fixture {
+    pub seed: [u8; SEED_LEN],
+    pub signing_key: SigningKey<MlDsa65>,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+    /// ABI envelope around `signature`.
+    pub envelope: Vec<u8>,
+    pub op: PackedUserOperation,
+    pub op_json: String,
+    pub account: Account,
+    pub password: &'static [u8],
+    pub chain_id: U256,
+}
+
+impl Fixture {
+    /// Build the fixture, encrypting the keystore entry under `kdf`.
+    pub fn new(kdf: KdfParams) -> Result<Self> {
+        let seed = [0x42; SEED_LEN];
+        let password = b"bench";
+        let chain_id = U256::from(11155111);
+        let sk = SigningKey::<MlDsa65>::from_seed(&seed.into());
+        let op = build_user_op(Address::repeat_byte(0x11), U256::from(1));
+        let hash = userop::compute_user_op_hash(&op, ENTRY_POINT_V07, chain_id);
+        let signature = sk.sign(hash.as_slice()).encode().to_vec();
+        let op = PackedUserOperation {
+            signature: signature.clone(),
+            ..op
+        };
+        let public_key = sk.verifying_key().encode().to_vec();
+        Ok(Fixture {
+            seed,
+            envelope: envelope::encode_signature_envelope(
+                &public_key,
+                &signature,
+                SCHEME_ML_DSA_65,
+            )?,
+            signing_key: sk,
+            public_key,
+            signature,
+            op_json: serde_json::to_string(&op).expect("UserOperation serializes"),
+            op,
+            account: Account::encrypt("bench", &seed, password, kdf)?,
+            password,
+            chain_id,
+        })
+    }
+}
+
+/// A representative op: a two-call batch `execute` with typical gas fields.
+pub fn build_user_op(sender: Address, nonce: U256) -> PackedUserOperation {
+    let calls = [
+        Call {
+            target: Address::repeat_byte(0x22),
+            value: U256::from(10u64.pow(15)),
+            data: Vec::new(),
+        },
+        Call {
+            target: Address::repeat_byte(0x33),
+            value: U256::ZERO,
+            data: vec![0xab; 68],
+        },
+    ];
+    PackedUserOperation {
+        sender,
+        nonce,
+        init_code: Vec::new(),
+        call_data: calldata::execute(&calls),
+        account_gas_limits: userop::pack_u128_pair(400_000, 100_000),
+        pre_verification_gas: U256::from(120_000),
+        gas_fees: userop::pack_u128_pair(1_000_000_000, 30_000_000_000),
+        paymaster_and_data: Vec::new(),
+        signature: Vec::new(),
+    }
+}
+
+/// One timed step of the pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stage {
+    /// `compute_user_op_hash` on the fixture op.
+    UserOpHash,
+    /// ABI signature envelope, encode then decode.
+    Envelope,
+    /// UserOperation JSON, serialize then parse.
+    Json,
+    /// Argon2id + ChaCha20-Poly1305 keystore decryption.
+    KeystoreDecrypt,
+    /// Build, hash, sign and serialize a fresh op from the seed.
+    BuildAndSign,
+    /// keccak256 over the ABI signature envelope (about 5 KiB).
+    Keccak,
+    /// ML-DSA-65 signature over the userOpHash, key already expanded.
+    Sign,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 7] = [
+        Stage::UserOpHash,
+        Stage::Envelope,
+        Stage::Json,
+        Stage::KeystoreDecrypt,
+        Stage::BuildAndSign,
+        Stage::Keccak,
+        Stage::Sign,
+    ];
+
+    /// Run the stage once.
+    pub fn run(self, f: &Fixture) -> Result<()> {
+        match self {
+            Stage::UserOpHash => {
+                black_box(userop::compute_user_op_hash(
+                    black_box(&f.op),
+                    ENTRY_POINT_V07,
+                    f.chain_id,
+                ));
+            }
+            Stage::Envelope => {
+                let env = envelope::encode_signature_envelope(
+                    black_box(&f.public_key),
+                    black_box(&f.signature),
+                    SCHEME_ML_DSA_65,
+                )?;
+                black_box(envelope::decode_signature_envelope(&env)?);
+            }
+            Stage::Json => {
+                let text =
+                    serde_json::to_string(black_box(&f.op)).expect("UserOperation serializes");
+                let op: PackedUserOperation = serde_json::from_str(black_box(&f.op_json))
+                    .map_err(|e| WalletError::InvalidUserOp(e.to_string()))?;
+                black_box((text, op));
+            }
+            Stage::KeystoreDecrypt => {
+                black_box(f.account.decrypt_seed(black_box(f.password))?);
+            }
+            Stage::BuildAndSign => {
+                let mut op = build_user_op(f.op.sender, black_box(f.op.nonce));
+                let hash = userop::compute_user_op_hash(&op, ENTRY_POINT_V07, f.chain_id);
+                let sk = SigningKey::<MlDsa65>::from_seed(&black_box(f.seed).into());
+                op.signature = sk.sign(hash.as_slice()).encode().to_vec();
+                black_box(serde_json::to_string(&op).expect("UserOperation serializes"));
+            }
+            Stage::Keccak => {
+                black_box(keccak256(black_box(&f.envelope)));
+            }
+            Stage::Sign => {
+                let hash = userop::compute_user_op_hash(&f.op, ENTRY_POINT_V07, f.chain_id);
+                black_box(f.signing_key.sign(black_box(hash.as_slice())));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::UserOpHash => "user-op-hash",
+            Stage::Envelope => "envelope",
+            Stage::Json => "json",
+            Stage::KeystoreDecrypt => "keystore-decrypt",
+            Stage::BuildAndSign => "build-and-sign",
+            Stage::Keccak => "keccak",
+            Stage::Sign => "sign",
+        })
+    }
+}
+
+/// Per-stage wall-clock summary, in nanoseconds.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Timing {
+    pub stage: Stage,
+    pub iterations: usize,
+    pub mean_ns: u64,
+    pub p50_ns: u64,
+    pub p99_ns: u64,
+    pub ops_per_sec: f64,
+}
+
+/// Time `iterations` runs of `stage`, after one untimed warm-up run.
+pub fn measure(stage: Stage, f: &Fixture, iterations: usize) -> Result<Timing> {
+    stage.run(f)?;
+    let mut samples = Vec::with_capacity(iterations.max(1));
+    for _ in 0..iterations.max(1) {
+        let start = Instant::now();
+        stage.run(f)?;
+        samples.push(start.elapsed());
+    }
+    samples.sort();
+    let total: Duration = samples.iter().sum();
+    let mean = total / samples.len() as u32;
+    let percentile = |p: usize| samples[(samples.len() - 1) * p / 100].as_nanos() as u64;
+    Ok(Timing {
+        stage,
+        iterations: samples.len(),
+        mean_ns: mean.as_nanos() as u64,
+        p50_ns: percentile(50),
+        p99_ns: percentile(99),
+        ops_per_sec: samples.len() as f64 / total.as_secs_f64(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheapest Argon2 parameters, so the tests stay fast.
+    fn fixture() -> Fixture {
+        Fixture::new(KdfParams {
+            m_cost: 8,
+            t_cost: 1,
+            p_cost: 1,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn every_stage_runs_on_the_fixture() {
+        let f = fixture();
+        for stage in Stage::ALL {
+            stage.run(&f).unwrap();
+        }
+        let parsed: PackedUserOperation = serde_json::from_str(&f.op_json).unwrap();
+        assert_eq!(parsed, f.op);
+    }
+
+    #[test]
+    fn measure_reports_ordered_percentiles() {
+        let t = measure(Stage::UserOpHash, &fixture(), 20).unwrap();
+        assert_eq!(t.iterations, 20);
+        assert!(t.p50_ns <= t.p99_ns);
+        assert!(t.ops_per_sec > 0.0);
+        assert_eq!(serde_json::to_value(&t).unwrap()["stage"], "user-op-hash");
+    }
+}
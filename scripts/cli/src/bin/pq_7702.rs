@@ -0,0 +1,215 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use alloy_primitives::{Address, B256, Bytes, U64, U128, U256};
+use clap::{Parser, Subcommand};
+use pq_cli::delegation::{self, Authorization, Eip7702Transaction};
+use pq_cli::gas::cost;
+use pq_cli::output::{self, OutputArgs};
+use pq_cli::rpc::{HttpTransport, Transport};
+use zeroize::Zeroizing;
+
+#[derive(Parser)]
+#[command(about = "Delegate an existing EOA to the PQ account via EIP-7702")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Sign an authorization tuple with the EOA's ECDSA key, optionally
+    /// wrapping it in a type-4 transaction or a UserOperation
+    Authorize {
+        /// Contract the EOA delegates to (the PQ account implementation)
+        #[arg(long)]
+        delegate: Address,
+
+        /// Chain the authorization is valid on (0 = any chain)
+        #[arg(long)]
+        chain_id: u64,
+
+        /// File holding the EOA's hex secp256k1 private key
+        #[arg(long, env = "ECDSA_KEY_FILE")]
+        ecdsa_key: PathBuf,
+
+        /// EOA nonce to authorize at; fetched from --rpc if omitted
+        #[arg(long)]
+        nonce: Option<u64>,
+
+        /// Node JSON-RPC URL, for the nonce, fees and --send
+        #[arg(long, env = "LOCAL_RPC")]
+        rpc: Option<String>,
+
+        /// Build a self-sponsored type-4 transaction carrying the authorization
+        #[arg(long, conflicts_with = "userop")]
+        tx: bool,
+
+        /// Transaction target (default: the EOA itself)
+        #[arg(long, requires = "tx")]
+        to: Option<Address>,
+
+        /// Transaction calldata, e.g. the account's initializer
+        #[arg(long, requires = "tx")]
+        data: Option<Bytes>,
+
+        #[arg(long, default_value_t = 100_000, requires = "tx")]
+        gas_limit: u64,
+
+        /// Fees in wei; quoted from --rpc if omitted
+        #[arg(long, requires = "tx")]
+        max_fee_per_gas: Option<u128>,
+
+        #[arg(long, requires = "tx")]
+        max_priority_fee_per_gas: Option<u128>,
+
+        /// Broadcast the transaction with eth_sendRawTransaction
+        #[arg(long, requires_all = ["tx", "rpc"])]
+        send: bool,
+
+        /// UserOperation JSON to attach the authorization to as `eip7702Auth`
+        #[arg(long)]
+        userop: Option<PathBuf>,
+    },
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    let Command::Authorize {
+        delegate,
+        chain_id,
+        ecdsa_key,
+        nonce,
+        rpc,
+        tx,
+        to,
+        data,
+        gas_limit,
+        max_fee_per_gas,
+        max_priority_fee_per_gas,
+        send,
+        userop,
+    } = args.command;
+
+    if tx && chain_id == 0 {
+        output::fail(json, "--tx needs a concrete --chain-id");
+    }
+    let hex =
+        Zeroizing::new(std::fs::read_to_string(&ecdsa_key).expect("failed to read ECDSA key file"));
+    let key = output::or_fail(
+        json,
+        "ECDSA key",
+        delegation::signing_key_from_hex(hex.trim()),
+    );
+    let eoa = delegation::address_of(&key);
+    let node = rpc.map(|url| HttpTransport::new(url, Duration::from_secs(30)));
+
+    let account_nonce = match (nonce, &node) {
+        (Some(n), _) => n,
+        (None, Some(node)) => {
+            let n: U64 = output::or_fail(
+                json,
+                "eth_getTransactionCount",
+                node.call(
+                    "eth_getTransactionCount",
+                    serde_json::json!([eoa, "pending"]),
+                ),
+            );
+            n.to()
+        }
+        (None, None) => output::fail(json, "one of --nonce or --rpc is required"),
+    };
+    // When the EOA sends the transaction itself, its nonce is bumped before
+    // the authorization list is processed.
+    let auth_nonce = if tx { account_nonce + 1 } else { account_nonce };
+    let auth = output::or_fail(
+        json,
+        "sign authorization",
+        Authorization {
+            chain_id: U256::from(chain_id),
+            address: delegate,
+            nonce: U64::from(auth_nonce),
+        }
+        .sign(&key),
+    );
+    eprintln!("Authorizing {eoa} -> {delegate} at nonce {auth_nonce}");
+
+    if let Some(path) = userop {
+        let text = std::fs::read_to_string(&path).expect("failed to read UserOperation file");
+        let mut op: serde_json::Value = serde_json::from_str(&text)
+            .unwrap_or_else(|e| output::fail(json, format!("invalid UserOperation JSON: {e}")));
+        if op["sender"] != serde_json::json!(eoa) {
+            tracing::warn!("UserOperation sender is not {eoa}");
+        }
+        op["eip7702Auth"] = serde_json::json!(auth);
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&op).expect("UserOperation serializes")
+        );
+        return;
+    }
+
+    if !tx {
+        if json {
+            output::emit(&auth);
+        } else {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&auth).expect("authorization serializes")
+            );
+        }
+        return;
+    }
+
+    let (max_fee, tip) = match (max_fee_per_gas, max_priority_fee_per_gas, &node) {
+        (Some(max), tip, _) => (max, tip.unwrap_or(0)),
+        (None, tip, Some(node)) => {
+            let quote = output::or_fail(json, "fee quote", cost::suggest_fees(node));
+            (
+                quote.max_fee_per_gas,
+                tip.unwrap_or(quote.max_priority_fee_per_gas),
+            )
+        }
+        (None, _, None) => output::fail(json, "one of --max-fee-per-gas or --rpc is required"),
+    };
+    let transaction = Eip7702Transaction {
+        chain_id: U256::from(chain_id),
+        nonce: U64::from(account_nonce),
+        max_priority_fee_per_gas: U128::from(tip),
+        max_fee_per_gas: U128::from(max_fee),
+        gas_limit: U64::from(gas_limit),
+        to: to.unwrap_or(eoa),
+        value: U256::ZERO,
+        data: data.unwrap_or_default(),
+        authorization_list: vec![auth],
+    };
+    let raw = output::or_fail(json, "sign transaction", transaction.sign(&key));
+
+    let sent = send.then(|| {
+        let node = node.as_ref().unwrap();
+        let hash: B256 = output::or_fail(
+            json,
+            "eth_sendRawTransaction",
+            node.call("eth_sendRawTransaction", serde_json::json!([raw])),
+        );
+        hash
+    });
+    if json {
+        output::emit(&serde_json::json!({
+            "authorization": auth,
+            "rawTransaction": raw,
+            "transactionHash": sent,
+        }));
+    } else {
+        println!("{raw}");
+        if let Some(hash) = sent {
+            eprintln!("Sent {hash}");
+        }
+    }
+}
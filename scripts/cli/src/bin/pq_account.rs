@@ -0,0 +1,597 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use alloy_primitives::Address;
+use clap::{Parser, Subcommand};
+use pq_cli::WalletError;
+use pq_cli::audit::AuditLog;
+use pq_cli::backup::{KEM_PUBLIC_KEY_LEN, KEM_SEED_LEN};
+use pq_cli::balance;
+use pq_cli::cli::{self, KeystoreBackend};
+use pq_cli::deploy;
+use pq_cli::keys::SEED_LEN;
+use pq_cli::keystore::{Account, KdfParams, Keystore};
+use pq_cli::output::{self, AccountOutput, OutputArgs};
+use pq_cli::pkix::{self, KeyFormat};
+use pq_cli::profile::{EntryPointVersion, Profile, Validator};
+use pq_cli::rpc::HttpTransport;
+use pq_cli::signing_scheme::SigningScheme;
+use pq_cli::state_bundle::{Contents, StateBundle};
+use pq_cli::stats::{self, Endpoint, Period};
+
+#[derive(Parser)]
+#[command(about = "Manage named ML-DSA-65 accounts in ~/.pq-wallet/accounts")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create an account with a fresh seed, or import an existing sk.bin
+    New {
+        name: String,
+
+        /// Import this 32-byte seed file instead of generating one
+        #[arg(long)]
+        seed_file: Option<PathBuf>,
+
+        /// Smart account address on a chain, as CHAIN_ID=0xADDRESS (repeatable)
+        #[arg(long = "address", value_name = "CHAIN_ID=ADDRESS", value_parser = parse_chain_address)]
+        addresses: Vec<(u64, Address)>,
+
+        /// What the validator on a chain signs over, as CHAIN_ID=SCHEME
+        /// (hash, prehash-sha256, prehash-sha512 or raw; repeatable)
+        #[arg(long = "signing-scheme", value_name = "CHAIN_ID=SCHEME", value_parser = parse_chain_scheme)]
+        signing_schemes: Vec<(u64, SigningScheme)>,
+
+        /// What opens the seed: a password, or this host's TPM (needs the
+        /// tpm feature)
+        #[arg(long, value_enum, default_value_t = KeystoreBackend::Password)]
+        keystore_backend: KeystoreBackend,
+
+        /// With --keystore-backend tpm, the SHA-256 PCRs the seed is sealed
+        /// to [default: 0,2,4,7]
+        #[arg(long, value_name = "N,N,...")]
+        pcrs: Option<String>,
+
+        /// Register a watch-only account from this ML-DSA-65 public key
+        /// instead of a seed: it can build and simulate ops, which are then
+        /// signed elsewhere
+        #[arg(long, conflicts_with_all = ["seed_file", "keystore_backend", "pcrs"])]
+        public_key: Option<PathBuf>,
+
+        /// Encoding of --public-key: raw (1,952 bytes), der or pem (SPKI),
+        /// jwk or cose
+        #[arg(long, default_value_t = KeyFormat::Raw, requires = "public_key")]
+        key_format: KeyFormat,
+    },
+    /// List accounts
+    List,
+    /// Show an account's public details
+    Show { name: String },
+    /// Native and ERC-20 balances of an account's smart account, deployed
+    /// or not
+    Balance {
+        name: String,
+
+        /// Chain the smart account is on
+        #[arg(long)]
+        chain_id: u64,
+
+        /// Node JSON-RPC URL
+        #[arg(long, env = "LOCAL_RPC")]
+        rpc: String,
+
+        /// ERC-20s to list besides the bundled ones for the chain
+        /// (comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        tokens: Vec<Address>,
+    },
+    /// Ops the account signed, submitted and had included, the gas they
+    /// cost, each bundler's inclusion latency and the value moved per
+    /// period, from the audit log and the bundlers' receipts
+    Stats {
+        name: String,
+
+        /// Bundler JSON-RPC URL to look ops up on (repeatable; the first
+        /// that knows an op is taken as the one it was sent to)
+        #[arg(long = "bundler", value_name = "URL", required = true)]
+        bundlers: Vec<String>,
+
+        /// Node JSON-RPC URL, to time inclusion from the block (repeatable,
+        /// one per chain)
+        #[arg(long = "rpc", value_name = "URL")]
+        nodes: Vec<String>,
+
+        /// Bucket ops by signing day, week or month (UTC)
+        #[arg(long, value_enum, default_value_t = Period::Month)]
+        period: Period,
+
+        /// Also write the per-period totals here as CSV
+        #[arg(long)]
+        csv: Option<PathBuf>,
+
+        /// Audit log [default: $PQ_WALLET_AUDIT_LOG, else ~/.pq-wallet/audit.jsonl]
+        #[arg(long)]
+        audit_log: Option<PathBuf>,
+    },
+    /// Record what the account's validator on a chain expects, so ops for
+    /// that chain are signed in its construction and flags that contradict
+    /// it are refused
+    Profile {
+        name: String,
+
+        #[arg(long)]
+        chain_id: u64,
+
+        /// ml-dsa (PQValidatorModule), hybrid-ecdsa, hybrid-passkey or
+        /// precompile:ADDR[:abi]
+        #[arg(long, default_value = "ml-dsa")]
+        validator: Validator,
+
+        /// Versioned envelope the validator decodes (1, 2 or 3) instead of
+        /// its bare layout
+        #[arg(long)]
+        envelope: Option<u8>,
+
+        /// EntryPoint release the account is deployed against
+        #[arg(long, default_value = "v0.7")]
+        entry_point_version: EntryPointVersion,
+
+        /// What the validator signs over: hash, prehash-sha256,
+        /// prehash-sha512 or raw
+        #[arg(long, default_value = "hash")]
+        signing_scheme: SigningScheme,
+
+        /// Remove the chain's profile instead
+        #[arg(long, conflicts_with_all = ["validator", "envelope", "entry_point_version", "signing_scheme"])]
+        clear: bool,
+    },
+    /// Rename an account
+    Rename { from: String, to: String },
+    /// Delete an account and its encrypted seed
+    Delete {
+        name: String,
+
+        /// Required: the seed cannot be recovered afterwards
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Seal this host's accounts, address book, delegation certificates,
+    /// spend ledger, op queue and config into one archive for another host
+    ExportBundle {
+        /// The new host's kem.pk, from `pq-backup keygen`
+        #[arg(long)]
+        recipient_kem_pk: PathBuf,
+
+        /// Where to write the bundle JSON
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Check and unpack a bundle from `export-bundle` into the keystore
+    ImportBundle {
+        /// Bundle JSON from `export-bundle`
+        #[arg(long)]
+        file: PathBuf,
+
+        /// This host's kem.sk
+        #[arg(long)]
+        kem_sk: PathBuf,
+
+        /// Replace files that already exist
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+fn parse_chain_address(s: &str) -> Result<(u64, Address), String> {
+    let (chain, addr) = s
+        .split_once('=')
+        .ok_or("expected CHAIN_ID=ADDRESS".to_string())?;
+    let chain = chain
+        .parse()
+        .map_err(|e| format!("invalid chain id {chain:?}: {e}"))?;
+    let addr = addr
+        .parse()
+        .map_err(|e| format!("invalid address {addr:?}: {e}"))?;
+    Ok((chain, addr))
+}
+
+fn parse_chain_scheme(s: &str) -> Result<(u64, SigningScheme), String> {
+    let (chain, scheme) = s
+        .split_once('=')
+        .ok_or("expected CHAIN_ID=SCHEME".to_string())?;
+    let chain = chain
+        .parse()
+        .map_err(|e| format!("invalid chain id {chain:?}: {e}"))?;
+    Ok((
+        chain,
+        scheme.parse().map_err(|e: WalletError| e.to_string())?,
+    ))
+}
+
+fn read_fixed<const N: usize>(json: bool, what: &'static str, path: &Path) -> [u8; N] {
+    let bytes = std::fs::read(path)
+        .unwrap_or_else(|e| output::fail(json, format!("{}: {e}", path.display())));
+    let actual = bytes.len();
+    bytes.try_into().unwrap_or_else(|_| {
+        output::fail(
+            json,
+            WalletError::InvalidLength {
+                what,
+                expected: N,
+                actual,
+            },
+        )
+    })
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+    let store = output::or_fail(json, "keystore", Keystore::open_default());
+    let show = |account: &Account| {
+        output::or_fail(
+            json,
+            "corrupt account",
+            AccountOutput::new(account, &store.path_of(&account.name)),
+        )
+    };
+
+    match args.command {
+        Command::New {
+            name,
+            seed_file,
+            addresses,
+            signing_schemes,
+            keystore_backend,
+            pcrs,
+            public_key,
+            key_format,
+        } => {
+            if store.exists(&name) {
+                output::fail(json, format!("account {name:?} already exists"));
+            }
+            let mut account = match public_key {
+                Some(path) => {
+                    let bytes = std::fs::read(&path).unwrap_or_else(|e| {
+                        output::fail(json, format!("failed to read {}: {e}", path.display()))
+                    });
+                    let pk = output::or_fail(
+                        json,
+                        "--public-key",
+                        pkix::decode_public_key(&bytes, key_format),
+                    );
+                    output::or_fail(json, "invalid account", Account::watch_only(&name, &pk))
+                }
+                None => {
+                    let seed: [u8; SEED_LEN] = match seed_file {
+                        Some(path) => {
+                            let bytes = std::fs::read(&path).expect("failed to read seed file");
+                            let actual = bytes.len();
+                            bytes.try_into().unwrap_or_else(|_| {
+                                output::fail(
+                                    json,
+                                    WalletError::InvalidLength {
+                                        what: "seed",
+                                        expected: SEED_LEN,
+                                        actual,
+                                    },
+                                )
+                            })
+                        }
+                        None => rand::random(),
+                    };
+                    match keystore_backend {
+                        KeystoreBackend::Password => {
+                            if pcrs.is_some() {
+                                output::fail(json, "--pcrs needs --keystore-backend tpm");
+                            }
+                            let pw = output::or_fail(
+                                json,
+                                "password",
+                                cli::read_password(&format!("New password for {name}: "), true),
+                            );
+                            output::or_fail(
+                                json,
+                                "failed to encrypt seed",
+                                Account::encrypt(&name, &seed, pw.as_bytes(), KdfParams::default()),
+                            )
+                        }
+                        KeystoreBackend::Tpm => seal(json, &name, &seed, pcrs.as_deref()),
+                    }
+                }
+            };
+            account.addresses.extend(addresses);
+            account.signing_schemes.extend(signing_schemes);
+            output::or_fail(json, "failed to save account", store.insert(&account));
+            report(json, &show(&account));
+        }
+        Command::List => {
+            let accounts = output::or_fail(json, "failed to list accounts", store.list());
+            let rows: Vec<_> = accounts.iter().map(show).collect();
+            if json {
+                output::emit(&rows);
+            } else if rows.is_empty() {
+                println!("No accounts in {}", store.accounts_dir().display());
+            } else {
+                for row in rows {
+                    println!(
+                        "{:<20} {}  {} chain(s){}",
+                        row.name,
+                        row.address,
+                        row.addresses.len(),
+                        if row.watch_only { "  watch-only" } else { "" }
+                    );
+                }
+            }
+        }
+        Command::Show { name } => {
+            let account = output::or_fail(json, "failed to load account", store.load(&name));
+            report(json, &show(&account));
+        }
+        Command::Balance {
+            name,
+            chain_id,
+            rpc,
+            tokens,
+        } => {
+            let account = output::or_fail(json, "failed to load account", store.load(&name));
+            let Some(&address) = account.addresses.get(&chain_id) else {
+                output::fail(
+                    json,
+                    format!("account {name} has no address on chain {chain_id}"),
+                );
+            };
+            let node = HttpTransport::new(rpc, Duration::from_secs(30));
+            let deployed = output::or_fail(json, "eth_getCode", deploy::has_code(&node, address));
+            let holdings = output::or_fail(
+                json,
+                "balance",
+                balance::holdings(&node, chain_id, address, &tokens),
+            );
+            if json {
+                output::emit(&serde_json::json!({
+                    "name": account.name,
+                    "chainId": chain_id,
+                    "address": address,
+                    "deployed": deployed,
+                    "holdings": holdings,
+                }));
+            } else {
+                let note = if deployed { "" } else { "  (not deployed yet)" };
+                println!("{}  {address}{note}", account.name);
+                for holding in &holdings {
+                    println!("  {holding}");
+                }
+            }
+        }
+        Command::Stats {
+            name,
+            bundlers,
+            nodes,
+            period,
+            csv,
+            audit_log,
+        } => {
+            let account = output::or_fail(json, "failed to load account", store.load(&name));
+            let key_id = output::or_fail(json, "key id", account.key_id());
+            let log = match audit_log {
+                Some(path) => AuditLog::open(path),
+                None => output::or_fail(json, "audit log", AuditLog::open_default()),
+            };
+            let entries = output::or_fail(json, "failed to read audit log", log.entries());
+            let connect = |url: String| {
+                let label = url.split('?').next().unwrap_or_default().to_string();
+                let rpc = HttpTransport::new(url.clone(), Duration::from_secs(30));
+                output::or_fail(
+                    json,
+                    &format!("eth_chainId on {label}"),
+                    Endpoint::connect(label.clone(), rpc),
+                )
+            };
+            let bundlers: Vec<_> = bundlers.into_iter().map(connect).collect();
+            let nodes: Vec<_> = nodes.into_iter().map(connect).collect();
+            let ops = stats::signed_ops(&entries, key_id)
+                .iter()
+                .map(|op| output::or_fail(json, "bundler", stats::observe(op, &bundlers, &nodes)))
+                .collect();
+            let stats = stats::summarize(&account.name, ops, period);
+            if let Some(path) = &csv {
+                std::fs::write(path, stats.to_csv()).expect("failed to write CSV");
+            }
+            if json {
+                output::emit(&stats);
+            } else {
+                let t = &stats.totals;
+                println!("{}  {key_id}", stats.account);
+                println!(
+                    "  {} signed, {} submitted, {} included, {} failed",
+                    t.signed, t.submitted, t.included, t.failed
+                );
+                println!(
+                    "  gas used {}, gas cost {} wei, value moved {} wei",
+                    t.gas_used, t.gas_cost, t.value
+                );
+                for b in &stats.bundlers {
+                    let latency = b
+                        .average_latency
+                        .map_or("-".to_string(), |s| format!("{s:.1}s"));
+                    println!(
+                        "  {}: {} submitted, {} included, {} failed, average latency {latency}",
+                        b.bundler, b.totals.submitted, b.totals.included, b.totals.failed
+                    );
+                }
+                println!(
+                    "  {:<10} {:>6} {:>9} {:>8} {:>6} {:>22} {:>22}",
+                    period.to_string(),
+                    "signed",
+                    "submitted",
+                    "included",
+                    "failed",
+                    "gas cost (wei)",
+                    "value (wei)"
+                );
+                for p in &stats.periods {
+                    let t = &p.totals;
+                    println!(
+                        "  {:<10} {:>6} {:>9} {:>8} {:>6} {:>22} {:>22}",
+                        p.period, t.signed, t.submitted, t.included, t.failed, t.gas_cost, t.value
+                    );
+                }
+                if let Some(path) = &csv {
+                    println!("CSV written to {}", path.display());
+                }
+            }
+        }
+        Command::Profile {
+            name,
+            chain_id,
+            validator,
+            envelope,
+            entry_point_version,
+            signing_scheme,
+            clear,
+        } => {
+            let mut account = output::or_fail(json, "failed to load account", store.load(&name));
+            if clear {
+                account.profiles.remove(&chain_id);
+            } else {
+                let profile = Profile {
+                    validator,
+                    envelope,
+                    entry_point: entry_point_version,
+                    signing_scheme,
+                };
+                output::or_fail(json, "invalid profile", profile.validate());
+                // The profile's scheme takes over from the chain's own.
+                account.signing_schemes.remove(&chain_id);
+                account.profiles.insert(chain_id, profile);
+            }
+            output::or_fail(json, "failed to save account", store.save(&account));
+            report(json, &show(&account));
+        }
+        Command::Rename { from, to } => {
+            let account = output::or_fail(json, "failed to rename", store.rename(&from, &to));
+            report(json, &show(&account));
+        }
+        Command::Delete { name, yes } => {
+            if !yes {
+                output::fail(json, "refusing to delete without --yes");
+            }
+            let account = output::or_fail(json, "failed to load account", store.load(&name));
+            let shown = show(&account);
+            output::or_fail(json, "failed to delete", store.delete(&name));
+            report(json, &shown);
+        }
+        Command::ExportBundle {
+            recipient_kem_pk,
+            output: out_path,
+        } => {
+            let recipient: [u8; KEM_PUBLIC_KEY_LEN] =
+                read_fixed(json, "KEM public key", &recipient_kem_pk);
+            let root = output::or_fail(json, "keystore", Keystore::default_root());
+            let config = pq_cli::config::Config::default_path();
+            let contents = output::or_fail(
+                json,
+                "failed to collect",
+                Contents::collect(&root, config.as_deref()),
+            );
+            let bundle = output::or_fail(
+                json,
+                "failed to seal",
+                StateBundle::seal(&contents, &recipient),
+            );
+            std::fs::write(&out_path, bundle.to_json())
+                .unwrap_or_else(|e| output::fail(json, format!("{}: {e}", out_path.display())));
+            if json {
+                output::emit(&serde_json::json!({
+                    "files": contents.files.iter().map(|f| &f.path).collect::<Vec<_>>(),
+                    "recipient": bundle.recipient,
+                    "path": out_path.display().to_string(),
+                }));
+            } else {
+                for file in &contents.files {
+                    println!("  {}", file.path);
+                }
+                println!(
+                    "Sealed {} files to {} in {}",
+                    contents.files.len(),
+                    bundle.recipient,
+                    out_path.display()
+                );
+            }
+        }
+        Command::ImportBundle {
+            file,
+            kem_sk,
+            force,
+        } => {
+            let kem_seed: [u8; KEM_SEED_LEN] = read_fixed(json, "KEM seed", &kem_sk);
+            let bytes = std::fs::read(&file)
+                .unwrap_or_else(|e| output::fail(json, format!("{}: {e}", file.display())));
+            let bundle = output::or_fail(json, "failed to read", StateBundle::from_json(&bytes));
+            let contents = output::or_fail(json, "failed to open", bundle.open(&kem_seed));
+            let root = output::or_fail(json, "keystore", Keystore::default_root());
+            let written = output::or_fail(json, "failed to import", contents.restore(&root, force));
+            if json {
+                output::emit(&serde_json::json!({
+                    "files": written.iter().map(|p| p.display().to_string()).collect::<Vec<_>>(),
+                    "createdAt": contents.created_at,
+                }));
+            } else {
+                for path in &written {
+                    println!("  {}", path.display());
+                }
+                println!("Imported {} files into {}", written.len(), root.display());
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tpm")]
+fn seal(json: bool, name: &str, seed: &[u8; SEED_LEN], pcrs: Option<&str>) -> Account {
+    use pq_cli::tpm::{DEFAULT_PCRS, Pcrs, Tpm};
+
+    let pcrs: Pcrs = output::or_fail(json, "--pcrs", pcrs.unwrap_or(DEFAULT_PCRS).parse());
+    let tpm = output::or_fail(json, "tpm", Tpm::from_environment());
+    output::or_fail(
+        json,
+        "failed to seal seed",
+        Account::encrypt_wrapped(name, seed, &tpm, &pcrs.to_string()),
+    )
+}
+
+#[cfg(not(feature = "tpm"))]
+fn seal(json: bool, _: &str, _: &[u8; SEED_LEN], _: Option<&str>) -> Account {
+    output::fail(json, "pq-account was built without the tpm feature")
+}
+
+fn report(json: bool, account: &AccountOutput) {
+    if json {
+        output::emit(account);
+        return;
+    }
+    println!("Name:        {}", account.name);
+    println!("Scheme:      {}", account.scheme);
+    println!("Key ID:      {}", account.address);
+    println!("Fingerprint: {}", account.fingerprint);
+    println!("File:        {}", account.path);
+    if account.watch_only {
+        println!("Watch-only:  yes (no seed; sign elsewhere)");
+    }
+    for (chain, addr) in &account.addresses {
+        println!("Chain {chain}: {addr}");
+    }
+    for (chain, scheme) in &account.signing_schemes {
+        println!("Chain {chain}: signs {scheme}");
+    }
+    for (chain, profile) in &account.profiles {
+        println!("Chain {chain}: {}", profile.summary());
+    }
+}
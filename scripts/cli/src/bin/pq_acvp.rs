@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use pq_cli::acvp::{self, Mode, ParameterSet, RunOptions};
+use pq_cli::backend::Backend;
+use pq_cli::logging::LogArgs;
+use pq_cli::output;
+
+#[derive(Parser)]
+#[command(about = "Run NIST ACVP ML-DSA vectors against a backend")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    log: LogArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a keyGen, sigGen or sigVer vector file (mode is read from the file)
+    Run {
+        /// ACVP JSON vector file
+        #[arg(long)]
+        file: PathBuf,
+
+        /// Backend to test: rustcrypto or fips204
+        #[arg(long, default_value = "rustcrypto")]
+        backend: Backend,
+
+        /// Restrict to a parameter set (repeatable), e.g. ML-DSA-65
+        #[arg(long = "parameter-set")]
+        parameter_sets: Vec<ParameterSet>,
+
+        /// Write the JSON report to a file instead of stdout
+        #[arg(long)]
+        report: Option<PathBuf>,
+    },
+    /// Download a vector set from the NIST ACVP-Server repository
+    Fetch {
+        /// keyGen, sigGen or sigVer
+        #[arg(long)]
+        mode: Mode,
+
+        /// Override the download URL
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Destination file
+        #[arg(long)]
+        output: PathBuf,
+    },
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.log.init();
+
+    match args.command {
+        Command::Run {
+            file,
+            backend,
+            parameter_sets,
+            report,
+        } => {
+            let opts = RunOptions { parameter_sets };
+            let result = acvp::run_file(&file, backend, &opts).expect("failed to run vectors");
+            let json = serde_json::to_string_pretty(&result).expect("report serializes");
+            match report {
+                Some(path) => std::fs::write(&path, json).expect("failed to write report"),
+                None => println!("{json}"),
+            }
+            eprintln!(
+                "ACVP {:?} [{}]: {}/{} passed, {} failed, {} groups skipped",
+                result.mode,
+                result.backend,
+                result.passed,
+                result.total,
+                result.failures.len(),
+                result.skipped.len()
+            );
+            if !result.is_clean() {
+                std::process::exit(output::EXIT_FAILURE);
+            }
+        }
+        Command::Fetch { mode, url, output } => {
+            acvp::fetch(mode, url.as_deref(), &output).expect("failed to fetch vectors");
+            eprintln!("{mode:?} vectors written to {}", output.display());
+        }
+    }
+}
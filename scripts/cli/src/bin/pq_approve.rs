@@ -0,0 +1,263 @@
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::{Address, B256, U256};
+use clap::{Parser, Subcommand};
+use pq_cli::backend::Backend;
+use pq_cli::cli::{self, SigningKeyArgs};
+use pq_cli::grpc::{self, Client, pb};
+use pq_cli::output::{self, OutputArgs};
+use pq_cli::proposal::{Proposal, Status};
+use pq_cli::review::Review;
+use pq_cli::siwe;
+use pq_cli::userop::{self, PackedUserOperation};
+
+#[derive(Parser)]
+#[command(about = "Review and decide pq-signerd proposals as a dual-control approver")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    /// pq-signerd's gRPC endpoint
+    #[arg(
+        long,
+        env = "PQ_SIGNERD",
+        default_value = "http://127.0.0.1:50051",
+        global = true
+    )]
+    daemon: String,
+
+    /// Bearer token of the principal to call as
+    #[arg(long, env = "PQ_SIGNERD_TOKEN", hide_env_values = true, global = true)]
+    token: Option<String>,
+
+    /// CA certificate (PEM) the daemon's TLS certificate is signed by; an
+    /// https:// --daemon needs it
+    #[arg(long, global = true)]
+    ca: Option<PathBuf>,
+
+    /// Client certificate (PEM), for a daemon that requires one
+    #[arg(long, requires_all = ["ca", "client_key"], global = true)]
+    client_cert: Option<PathBuf>,
+
+    #[arg(long, requires = "client_cert", global = true)]
+    client_key: Option<PathBuf>,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// The open proposals for the accounts the principal may use
+    List {
+        /// Decided and expired proposals too
+        #[arg(long)]
+        all: bool,
+    },
+    /// One proposal, with its op decoded
+    Show { id: B256 },
+    /// Review a proposal and approve it with the approver key; the daemon
+    /// then signs the op
+    Approve {
+        id: B256,
+
+        /// The approver key, one the policy's [dual_control] lists
+        #[command(flatten)]
+        key: SigningKeyArgs,
+
+        /// Approve without the review prompt
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Reject a proposal with the approver key
+    Reject {
+        id: B256,
+
+        #[command(flatten)]
+        key: SigningKeyArgs,
+    },
+}
+
+fn read(json: bool, path: &Path) -> Vec<u8> {
+    std::fs::read(path).unwrap_or_else(|e| output::fail(json, format!("{}: {e}", path.display())))
+}
+
+/// `proposal` as the daemon sent it, checked against its own op: the
+/// userOpHash and ID are recomputed here, so a daemon cannot have an
+/// approver sign off an op other than the one shown.
+fn decode(json: bool, proposal: pb::Proposal) -> Proposal {
+    let user_op: PackedUserOperation = serde_json::from_str(&proposal.user_op_json)
+        .unwrap_or_else(|e| output::fail(json, format!("proposal op: {e}")));
+    let entry_point = Address::try_from(&proposal.entry_point[..])
+        .unwrap_or_else(|_| output::fail(json, "proposal entry point is not 20 bytes"));
+    let user_op_hash =
+        userop::compute_user_op_hash(&user_op, entry_point, U256::from(proposal.chain_id));
+    let id = Proposal::id_for(&proposal.account, user_op_hash);
+    if proposal.user_op_hash != user_op_hash.as_slice() || proposal.id != id.as_slice() {
+        output::fail(json, "the daemon's userOpHash or ID does not match the op");
+    }
+    let status: Status = serde_json::from_value(serde_json::Value::String(proposal.status))
+        .unwrap_or_else(|e| output::fail(json, format!("proposal status: {e}")));
+    Proposal {
+        id,
+        account: proposal.account,
+        principal: proposal.principal,
+        chain_id: proposal.chain_id,
+        entry_point,
+        user_op,
+        user_op_hash,
+        reason: proposal.reason,
+        created_at: proposal.created_at,
+        expires_at: proposal.expires_at,
+        status,
+        decided_by: B256::try_from(&proposal.decided_by[..]).ok(),
+    }
+}
+
+fn print_line(proposal: &Proposal) {
+    println!(
+        "{}  {}  chain {}  {}  expires {}  {}",
+        proposal.id,
+        proposal.account,
+        proposal.chain_id,
+        proposal.status,
+        siwe::timestamp(proposal.expires_at),
+        proposal.reason
+    );
+}
+
+async fn fetch(json: bool, client: &mut Client, id: B256) -> Proposal {
+    let request = pb::GetProposalRequest { id: id.to_vec() };
+    let found = client
+        .get_proposal(request)
+        .await
+        .unwrap_or_else(|e| output::fail(json, format!("get proposal: {}", e.message())));
+    decode(json, found.into_inner())
+}
+
+/// Sign `proposal`'s decision with `key` and send it.
+async fn decide(
+    json: bool,
+    client: &mut Client,
+    proposal: &Proposal,
+    key: &SigningKeyArgs,
+    approve: bool,
+) -> Proposal {
+    let seed = output::or_fail(json, "failed to load approver key", key.seed());
+    let backend = Backend::RustCrypto;
+    let signature = output::or_fail(
+        json,
+        "sign",
+        backend.sign(&seed, proposal.digest(approve).as_slice(), &[], &[0; 32]),
+    );
+    let request = pb::DecideProposalRequest {
+        id: proposal.id.to_vec(),
+        approve,
+        public_key: backend.public_key(&seed),
+        signature,
+    };
+    let decided = client
+        .decide_proposal(request)
+        .await
+        .unwrap_or_else(|e| output::fail(json, format!("decide proposal: {}", e.message())));
+    decode(json, decided.into_inner())
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    let tls = args.ca.as_ref().map(|ca| {
+        let identity = args
+            .client_cert
+            .as_ref()
+            .zip(args.client_key.as_ref())
+            .map(|(cert, key)| (read(json, cert), read(json, key)));
+        let identity = identity.as_ref().map(|(c, k)| (&c[..], &k[..]));
+        grpc::client_tls_config(&read(json, ca), identity)
+    });
+    let mut client = output::or_fail(
+        json,
+        "connect",
+        grpc::connect(&args.daemon, tls, args.token.as_deref()).await,
+    );
+
+    match args.command {
+        Command::List { all } => {
+            let request = pb::ListProposalsRequest { pending_only: !all };
+            let listed = client
+                .list_proposals(request)
+                .await
+                .unwrap_or_else(|e| output::fail(json, format!("list proposals: {}", e.message())));
+            let proposals: Vec<_> = listed
+                .into_inner()
+                .proposals
+                .into_iter()
+                .map(|p| decode(json, p))
+                .collect();
+            if json {
+                output::emit(&proposals);
+            } else if proposals.is_empty() {
+                println!("No proposals.");
+            } else {
+                for proposal in &proposals {
+                    print_line(proposal);
+                }
+            }
+        }
+        Command::Show { id } => {
+            let proposal = fetch(json, &mut client, id).await;
+            if json {
+                output::emit(&proposal);
+            } else {
+                print_line(&proposal);
+                println!("Requested by {}", proposal.principal);
+                Review::new(&proposal.user_op, proposal.chain_id, proposal.user_op_hash).print();
+            }
+        }
+        Command::Approve { id, key, yes } => {
+            let proposal = fetch(json, &mut client, id).await;
+            if proposal.status != Status::Pending {
+                output::fail(json, format!("proposal {id} is {}", proposal.status));
+            }
+            if !yes {
+                let review =
+                    Review::new(&proposal.user_op, proposal.chain_id, proposal.user_op_hash);
+                eprintln!(
+                    "{} asks {} to sign: {}",
+                    proposal.principal, proposal.account, proposal.reason
+                );
+                review.print();
+                let confirmed = output::or_fail(
+                    json,
+                    "no terminal to confirm on (pass --yes)",
+                    cli::confirm("Approve?"),
+                );
+                if !confirmed {
+                    output::fail(json, "not approved");
+                }
+            }
+            let decided = decide(json, &mut client, &proposal, &key, true).await;
+            if json {
+                output::emit(&decided);
+            } else {
+                println!(
+                    "Approved {id} as {}; the daemon signed userOp {}.",
+                    decided.decided_by.unwrap_or_default(),
+                    decided.user_op_hash
+                );
+            }
+        }
+        Command::Reject { id, key } => {
+            let proposal = fetch(json, &mut client, id).await;
+            let decided = decide(json, &mut client, &proposal, &key, false).await;
+            if json {
+                output::emit(&decided);
+            } else {
+                println!("Rejected {id}.");
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::{B256, Bytes};
+use clap::{Parser, Subcommand};
+use pq_cli::attestation::{self, Attestation, HostClaims};
+use pq_cli::cli::PublicKeyArgs;
+use pq_cli::keys;
+use pq_cli::output::{self, OutputArgs};
+use pq_cli::pkix::{self, KeyFormat};
+
+#[derive(Parser)]
+#[command(about = "Issue and check signer host attestations for ML-DSA-65 keys")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Sign a record stating that a key is held by this host
+    Issue {
+        /// The host's own sk.bin (a separate ML-DSA-65 key, see pq-keygen)
+        #[arg(long)]
+        host_key: PathBuf,
+
+        /// The signing key being attested
+        #[command(flatten)]
+        key: PublicKeyArgs,
+
+        /// TEE platform, e.g. aws-nitro, sgx, tdx
+        #[arg(long)]
+        platform: Option<String>,
+
+        /// Enclave or image measurement (32 bytes hex)
+        #[arg(long)]
+        measurement: Option<B256>,
+
+        /// Raw TEE quote to bind into the record
+        #[arg(long)]
+        quote: Option<PathBuf>,
+
+        /// How long the record stays valid
+        #[arg(long, default_value_t = 90)]
+        valid_for_days: u64,
+
+        /// Where to write the record JSON
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Check a record against a key and the trusted host fingerprints
+    Verify {
+        /// Record JSON from `issue`
+        #[arg(long)]
+        attestation: PathBuf,
+
+        /// The key the record should attest
+        #[command(flatten)]
+        key: PublicKeyArgs,
+
+        /// Fingerprint of an accepted host key (repeatable)
+        #[arg(long = "trusted-host", value_name = "FINGERPRINT", required = true)]
+        trusted_hosts: Vec<B256>,
+    },
+}
+
+fn read(json: bool, path: &Path) -> Vec<u8> {
+    std::fs::read(path).unwrap_or_else(|e| output::fail(json, format!("{}: {e}", path.display())))
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    match args.command {
+        Command::Issue {
+            host_key,
+            key,
+            platform,
+            measurement,
+            quote,
+            valid_for_days,
+            output: out_path,
+        } => {
+            if !key.is_present() {
+                output::fail(json, "one of --key or --account is required");
+            }
+            let pk = output::or_fail(json, "failed to load public key", key.public_key());
+            let host_seed = output::or_fail(
+                json,
+                "failed to load host key",
+                pkix::decode_private_key(&read(json, &host_key), KeyFormat::Raw),
+            );
+            let claims = HostClaims {
+                platform,
+                measurement,
+                quote: quote.map(|path| Bytes::from(read(json, &path))),
+            };
+            let issued_at = attestation::now();
+            let expires_at = issued_at + valid_for_days * 86_400;
+            let record = Attestation::issue(&host_seed, &pk, issued_at, expires_at, claims);
+            std::fs::write(&out_path, record.to_json()).expect("failed to write attestation");
+            if json {
+                output::emit(&serde_json::json!({
+                    "address": keys::address(&pk).to_string(),
+                    "host": record.host_fingerprint(),
+                    "expiresAt": record.expires_at,
+                    "path": out_path.display().to_string(),
+                }));
+            } else {
+                println!("Key ID:      {}", keys::address(&pk));
+                println!("Host:        {}", record.host_fingerprint());
+                println!("Expires at:  {}", record.expires_at);
+                println!("Record:      {}", out_path.display());
+            }
+        }
+        Command::Verify {
+            attestation: path,
+            key,
+            trusted_hosts,
+        } => {
+            if !key.is_present() {
+                output::fail(json, "one of --key or --account is required");
+            }
+            let pk = output::or_fail(json, "failed to load public key", key.public_key());
+            let record = output::or_fail(
+                json,
+                "attestation",
+                Attestation::from_json(&read(json, &path)),
+            );
+            output::or_fail(
+                json,
+                "rejected",
+                record.verify(&pk, &trusted_hosts, attestation::now()),
+            );
+            if json {
+                output::emit(&serde_json::json!({
+                    "valid": true,
+                    "host": record.host_fingerprint(),
+                    "platform": record.platform,
+                    "measurement": record.measurement,
+                    "expiresAt": record.expires_at,
+                }));
+            } else {
+                println!("Valid");
+                println!("Host:        {}", record.host_fingerprint());
+                if let Some(platform) = &record.platform {
+                    println!("Platform:    {platform}");
+                }
+                if let Some(measurement) = record.measurement {
+                    println!("Measurement: {measurement}");
+                }
+                println!("Expires at:  {}", record.expires_at);
+            }
+        }
+    }
+}
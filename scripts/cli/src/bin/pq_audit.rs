@@ -0,0 +1,104 @@
+use std::path::PathBuf;
+
+use alloy_primitives::B256;
+use clap::{Parser, Subcommand};
+use pq_cli::WalletError;
+use pq_cli::audit::AuditLog;
+use pq_cli::output::{self, OutputArgs, VerifyOutput};
+
+#[derive(Parser)]
+#[command(about = "Inspect and verify the hash-chained signing audit log")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Log to read [default: $PQ_WALLET_AUDIT_LOG, else ~/.pq-wallet/audit.jsonl]
+    #[arg(long, global = true)]
+    file: Option<PathBuf>,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Walk the hash chain and report the head
+    Verify {
+        /// Head hash recorded earlier; detects entries cut off the end
+        #[arg(long)]
+        expect_head: Option<B256>,
+    },
+    /// Print entries, oldest first
+    List {
+        /// Only the last N entries
+        #[arg(long)]
+        tail: Option<usize>,
+    },
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+    let log = match args.file {
+        Some(path) => AuditLog::open(path),
+        None => output::or_fail(json, "audit log", AuditLog::open_default()),
+    };
+
+    match args.command {
+        Command::Verify { expect_head } => {
+            let report = match log.verify() {
+                Ok(report) => report,
+                // A broken chain is a failed check, not an I/O error.
+                Err(e @ WalletError::Audit(_)) => {
+                    if json {
+                        output::emit(&VerifyOutput::invalid(e.to_string()));
+                    } else {
+                        eprintln!("error: tampering detected: {e}");
+                    }
+                    std::process::exit(output::EXIT_FAILURE);
+                }
+                Err(e) => output::fail(json, e),
+            };
+            let head_ok = expect_head.is_none_or(|head| report.head == head);
+            if json {
+                output::emit(&serde_json::json!({
+                    "valid": head_ok,
+                    "entries": report.entries,
+                    "head": report.head,
+                }));
+            } else {
+                println!("Entries:     {}", report.entries);
+                println!("Head:        {}", report.head);
+            }
+            if !head_ok {
+                if !json {
+                    eprintln!(
+                        "error: head does not match --expect-head (entries were removed or added)"
+                    );
+                }
+                std::process::exit(output::EXIT_FAILURE);
+            }
+        }
+        Command::List { tail } => {
+            let entries = output::or_fail(json, "tampering detected", log.entries());
+            let skip = tail.map_or(0, |n| entries.len().saturating_sub(n));
+            for entry in &entries[skip..] {
+                if json {
+                    output::emit(entry);
+                    continue;
+                }
+                let r = &entry.record;
+                let chain = r.chain_id.map_or(String::new(), |c| format!(" chain {c}"));
+                let account = r.account.as_deref().unwrap_or("-");
+                println!(
+                    "#{} {} {} {account} {}{chain}",
+                    entry.seq, entry.timestamp, r.tool, r.key_id
+                );
+                for line in &r.summary {
+                    println!("    {line}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand};
+use pq_cli::WalletError;
+use pq_cli::backup::{self, Backup, KEM_PUBLIC_KEY_LEN, KEM_SEED_LEN};
+use pq_cli::cli::{self, SigningKeyArgs};
+use pq_cli::keys;
+use pq_cli::keystore::{Account, KdfParams, Keystore};
+use pq_cli::output::{self, OutputArgs};
+
+#[derive(Parser)]
+#[command(about = "Move ML-DSA-65 seeds between devices, sealed with ML-KEM-768")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create the receiving device's ML-KEM-768 keypair
+    Keygen {
+        /// Directory for kem.pk (1184 bytes) and kem.sk (64-byte seed)
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Seal a seed to a recipient's KEM key
+    Export {
+        #[command(flatten)]
+        signer: SigningKeyArgs,
+
+        /// Recipient's kem.pk
+        #[arg(long)]
+        recipient_kem_pk: PathBuf,
+
+        /// Where to write the backup JSON
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Open a backup with this device's KEM secret
+    Import {
+        /// Backup JSON from `export`
+        #[arg(long)]
+        file: PathBuf,
+
+        /// This device's kem.sk
+        #[arg(long)]
+        kem_sk: PathBuf,
+
+        /// Store the seed as this keystore account (prompts for a new
+        /// password); defaults to the name in the backup
+        #[arg(long, conflicts_with = "output")]
+        account: Option<String>,
+
+        /// Write pk.bin and sk.bin here instead of the keystore
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+fn read_fixed<const N: usize>(json: bool, what: &'static str, path: &Path) -> [u8; N] {
+    let bytes = std::fs::read(path)
+        .unwrap_or_else(|e| output::fail(json, format!("{}: {e}", path.display())));
+    let actual = bytes.len();
+    bytes.try_into().unwrap_or_else(|_| {
+        output::fail(
+            json,
+            WalletError::InvalidLength {
+                what,
+                expected: N,
+                actual,
+            },
+        )
+    })
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    match args.command {
+        Command::Keygen { output: dir } => {
+            let seed: [u8; KEM_SEED_LEN] = rand::random();
+            let pk = backup::kem_public_key(&seed);
+            std::fs::create_dir_all(&dir).expect("failed to create output directory");
+            std::fs::write(dir.join("kem.pk"), pk).expect("failed to write KEM public key");
+            std::fs::write(dir.join("kem.sk"), seed).expect("failed to write KEM seed");
+            let fingerprint = backup::kem_fingerprint(&pk);
+            if json {
+                output::emit(&serde_json::json!({
+                    "fingerprint": fingerprint,
+                    "path": dir.display().to_string(),
+                }));
+            } else {
+                println!("Public key:  {} (1184 bytes)", dir.join("kem.pk").display());
+                println!("Seed:        {} (64 bytes)", dir.join("kem.sk").display());
+                println!("Fingerprint: {fingerprint}");
+            }
+        }
+        Command::Export {
+            signer,
+            recipient_kem_pk,
+            output: out_path,
+        } => {
+            if !signer.is_present() {
+                output::fail(json, "one of --key or --account is required");
+            }
+            let recipient: [u8; KEM_PUBLIC_KEY_LEN] =
+                read_fixed(json, "KEM public key", &recipient_kem_pk);
+            let seed = output::or_fail(json, "failed to load signing key", signer.seed());
+            let name = signer.account.clone().unwrap_or_else(|| "imported".into());
+            let (addresses, signing_schemes) = match &signer.account {
+                Some(account) => {
+                    let store = output::or_fail(json, "keystore", Keystore::open_default());
+                    let entry =
+                        output::or_fail(json, "failed to load account", store.load(account));
+                    (entry.addresses, entry.signing_schemes)
+                }
+                None => Default::default(),
+            };
+            let sealed = output::or_fail(
+                json,
+                "failed to seal backup",
+                Backup::seal(&name, &seed, &recipient, addresses, signing_schemes),
+            );
+            std::fs::write(&out_path, sealed.to_json()).expect("failed to write backup");
+            let pk = output::or_fail(json, "backup", sealed.public_key());
+            if json {
+                output::emit(&serde_json::json!({
+                    "address": keys::address(&pk).to_string(),
+                    "recipient": sealed.recipient,
+                    "path": out_path.display().to_string(),
+                }));
+            } else {
+                println!("Key ID:      {}", keys::address(&pk));
+                println!("Recipient:   {}", sealed.recipient);
+                println!("Backup:      {}", out_path.display());
+            }
+        }
+        Command::Import {
+            file,
+            kem_sk,
+            account,
+            output: out_dir,
+        } => {
+            let bytes = std::fs::read(&file)
+                .unwrap_or_else(|e| output::fail(json, format!("{}: {e}", file.display())));
+            let sealed = output::or_fail(json, "backup", Backup::from_json(&bytes));
+            let kem_seed: [u8; KEM_SEED_LEN] = read_fixed(json, "KEM seed", &kem_sk);
+            let seed = output::or_fail(json, "failed to open backup", sealed.open(&kem_seed));
+            let pk = output::or_fail(json, "backup", sealed.public_key());
+
+            let path = match out_dir {
+                Some(dir) => {
+                    std::fs::create_dir_all(&dir).expect("failed to create output directory");
+                    std::fs::write(dir.join("pk.bin"), pk).expect("failed to write public key");
+                    std::fs::write(dir.join("sk.bin"), &seed[..]).expect("failed to write seed");
+                    dir
+                }
+                None => {
+                    let name = account.unwrap_or_else(|| sealed.name.clone());
+                    let store = output::or_fail(json, "keystore", Keystore::open_default());
+                    if store.exists(&name) {
+                        output::fail(json, format!("account {name:?} already exists"));
+                    }
+                    let pw = output::or_fail(
+                        json,
+                        "password",
+                        cli::read_password(&format!("New password for {name}: "), true),
+                    );
+                    let mut entry = output::or_fail(
+                        json,
+                        "failed to encrypt seed",
+                        Account::encrypt(&name, &seed, pw.as_bytes(), KdfParams::default()),
+                    );
+                    entry.addresses = sealed.addresses.clone();
+                    entry.signing_schemes = sealed.signing_schemes.clone();
+                    output::or_fail(json, "failed to save account", store.insert(&entry))
+                }
+            };
+            if json {
+                output::emit(&serde_json::json!({
+                    "address": keys::address(&pk).to_string(),
+                    "path": path.display().to_string(),
+                }));
+            } else {
+                println!("Key ID:      {}", keys::address(&pk));
+                println!("Imported to: {}", path.display());
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use clap::Parser;
+use pq_cli::bench::{self, Fixture, Stage};
+use pq_cli::hash_backend;
+use pq_cli::keystore::KdfParams;
+use pq_cli::output::{self, OutputArgs};
+
+#[derive(Parser)]
+#[command(about = "Time each stage of the userOp signing pipeline on this host")]
+struct Args {
+    /// Timed runs per stage
+    #[arg(long, default_value_t = 100)]
+    iterations: usize,
+
+    /// Only run these stages (repeatable; default: all)
+    #[arg(long, value_enum)]
+    stage: Vec<Stage>,
+
+    /// Argon2id memory cost in KiB for the keystore fixture
+    #[arg(long, default_value_t = KdfParams::default().m_cost)]
+    kdf_m_cost: u32,
+
+    /// Argon2id passes for the keystore fixture
+    #[arg(long, default_value_t = KdfParams::default().t_cost)]
+    kdf_t_cost: u32,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    let kdf = KdfParams {
+        m_cost: args.kdf_m_cost,
+        t_cost: args.kdf_t_cost,
+        ..KdfParams::default()
+    };
+    let fixture = output::or_fail(json, "fixture", Fixture::new(kdf));
+    let stages = if args.stage.is_empty() {
+        Stage::ALL.to_vec()
+    } else {
+        args.stage
+    };
+
+    let backends = hash_backend::detect();
+    let mut timings = Vec::with_capacity(stages.len());
+    for stage in stages {
+        eprintln!("Running {stage} x{}", args.iterations);
+        timings.push(output::or_fail(
+            json,
+            "benchmark",
+            bench::measure(stage, &fixture, args.iterations),
+        ));
+    }
+
+    if json {
+        output::emit(&serde_json::json!({ "hashBackends": backends, "timings": timings }));
+    } else {
+        println!("Hashing: {backends}");
+        println!(
+            "{:<18} {:>12} {:>12} {:>12} {:>12}",
+            "stage", "mean", "p50", "p99", "ops/s"
+        );
+        for t in &timings {
+            println!(
+                "{:<18} {:>12} {:>12} {:>12} {:>12.1}",
+                t.stage.to_string(),
+                fmt_ns(t.mean_ns),
+                fmt_ns(t.p50_ns),
+                fmt_ns(t.p99_ns),
+                t.ops_per_sec
+            );
+        }
+    }
+}
+
+fn fmt_ns(ns: u64) -> String {
+    match ns {
+        0..1_000 => format!("{ns} ns"),
+        1_000..1_000_000 => format!("{:.1} µs", ns as f64 / 1e3),
+        _ => format!("{:.2} ms", ns as f64 / 1e6),
+    }
+}
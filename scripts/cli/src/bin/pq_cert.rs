@@ -0,0 +1,168 @@
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::keccak256;
+use clap::{Parser, Subcommand, ValueEnum};
+use ml_dsa::{MlDsa65, SigningKey};
+use pq_cli::attestation;
+use pq_cli::audit::{AuditLog, Record};
+use pq_cli::backend::Backend;
+use pq_cli::cli::SigningKeyArgs;
+use pq_cli::ct;
+use pq_cli::keys;
+use pq_cli::output::{self, OutputArgs};
+use pq_cli::pkix;
+use pq_cli::x509::{self, Identity};
+use sha2::{Digest, Sha256};
+
+#[derive(Parser)]
+#[command(about = "Issue X.509 certificates and CSRs for an ML-DSA-65 key, for mTLS")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write a self-signed end-entity certificate for the key
+    SelfSigned {
+        #[command(flatten)]
+        cert: CertArgs,
+
+        /// Days the certificate is valid for
+        #[arg(long, default_value_t = 365)]
+        days: u64,
+    },
+    /// Write a PKCS#10 certificate signing request for a CA to sign
+    Csr {
+        #[command(flatten)]
+        cert: CertArgs,
+    },
+}
+
+#[derive(clap::Args)]
+struct CertArgs {
+    #[command(flatten)]
+    signer: SigningKeyArgs,
+
+    /// Subject common name (defaults to the key's address)
+    #[arg(long)]
+    cn: Option<String>,
+
+    /// DNS name for subjectAltName; repeat for several
+    #[arg(long = "dns", value_name = "NAME")]
+    dns_names: Vec<String>,
+
+    /// Output encoding: pem or der
+    #[arg(long = "format", value_name = "FORMAT", value_enum, default_value_t = Encoding::Pem)]
+    encoding: Encoding,
+
+    /// Output path
+    #[arg(long)]
+    output: PathBuf,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Encoding {
+    Pem,
+    Der,
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    let (cert, days) = match &args.command {
+        Command::SelfSigned { cert, days } => (cert, Some(*days)),
+        Command::Csr { cert } => (cert, None),
+    };
+    if !cert.signer.is_present() {
+        output::fail(json, "one of --key or --account is required");
+    }
+    let seed = output::or_fail(json, "failed to load signing key", cert.signer.seed());
+    let sk = SigningKey::<MlDsa65>::from_seed(&(*seed).into());
+    let pk = sk.verifying_key().encode();
+    let key_id = keys::address(&pk);
+    let id = Identity {
+        common_name: cert.cn.clone().unwrap_or_else(|| key_id.to_string()),
+        dns_names: cert.dns_names.clone(),
+    };
+
+    let (der, label, summary) = match days {
+        Some(days) => {
+            let not_before = attestation::now();
+            let not_after = not_before.saturating_add(days.saturating_mul(86_400));
+            let mut serial: [u8; 16] = rand::random();
+            // Positive, and minimal because the first byte is non-zero.
+            serial[0] = serial[0] & 0x7f | 0x01;
+            let der = output::or_fail(
+                json,
+                "certificate",
+                x509::self_signed(&sk, &id, &serial, not_before, not_after),
+            );
+            let summary = format!(
+                "self-signed certificate for CN={} serial 0x{} valid {days} days",
+                id.common_name,
+                ct::encode_hex(&serial)
+            );
+            (der, x509::CERTIFICATE_LABEL, summary)
+        }
+        None => (
+            x509::csr(&sk, &id),
+            x509::CSR_LABEL,
+            format!("certificate signing request for CN={}", id.common_name),
+        ),
+    };
+
+    // The signature is the last 3,309 bytes of the structure.
+    let signature = &der[der.len() - keys::SIGNATURE_LEN..];
+    let record = Record {
+        tool: "pq-cert".to_string(),
+        account: cert.signer.account.clone(),
+        key_id,
+        digest: None,
+        chain_id: None,
+        signature: keccak256(signature),
+        summary: vec![summary],
+        backend: Backend::RustCrypto.to_string(),
+    };
+    output::or_fail(
+        json,
+        "certificate withheld",
+        AuditLog::open_default().and_then(|log| log.append(record)),
+    );
+    write(&cert.output, &der, label, cert.encoding, json);
+
+    let sha256 = format!("0x{}", ct::encode_hex(&Sha256::digest(&der)));
+    if json {
+        output::emit(&serde_json::json!({
+            "path": cert.output.display().to_string(),
+            "subject": id.common_name,
+            "keyId": key_id,
+            "sha256": sha256,
+        }));
+        return;
+    }
+    let what = if days.is_some() {
+        "Certificate"
+    } else {
+        "Certificate request"
+    };
+    println!("{what} written to {}", cert.output.display());
+    println!("Subject:  CN={}", id.common_name);
+    println!("Key ID:   {key_id}");
+    println!("SHA-256:  {sha256}");
+}
+
+fn write(path: &Path, der: &[u8], label: &str, encoding: Encoding, json: bool) {
+    let bytes = match encoding {
+        Encoding::Pem => pkix::to_pem(label, der).into_bytes(),
+        Encoding::Der => der.to_vec(),
+    };
+    if let Err(e) = std::fs::write(path, bytes) {
+        output::fail(json, format!("{}: {e}", path.display()));
+    }
+}
@@ -0,0 +1,55 @@
+use clap::Parser;
+use pq_cli::conformance;
+use pq_cli::output::{self, OutputArgs};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+#[derive(Parser)]
+#[command(about = "Cross-check the ml-dsa and fips204 backends on randomized inputs")]
+struct Args {
+    /// Number of randomized cases
+    #[arg(long, default_value_t = 100)]
+    cases: usize,
+
+    /// Maximum message length in bytes
+    #[arg(long, default_value_t = 1024)]
+    max_message_len: usize,
+
+    /// RNG seed for reproducible runs (defaults to OS randomness)
+    #[arg(long)]
+    rng_seed: Option<u64>,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+
+    let mut rng = match args.rng_seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => rand::make_rng(),
+    };
+    let report = conformance::run(args.cases, args.max_message_len, &mut rng);
+
+    if args.format.json {
+        output::emit(&report);
+    } else {
+        for d in &report.divergences {
+            println!(
+                "DIVERGENCE case={} seed={} msg_len={} ctx_len={}: {:?}",
+                d.case, d.seed, d.message_len, d.context_len, d.kind
+            );
+        }
+        println!(
+            "{} cases, {} divergences",
+            report.cases,
+            report.divergences.len()
+        );
+    }
+
+    if !report.is_clean() {
+        std::process::exit(output::EXIT_FAILURE);
+    }
+}
@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use pq_cli::contacts::{AddressBook, Contact, Target};
+use pq_cli::ens;
+use pq_cli::output::{self, OutputArgs};
+use pq_cli::rpc::HttpTransport;
+
+#[derive(Parser)]
+#[command(about = "Name addresses in ~/.pq-wallet/contacts.json, from hex or ENS")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Name an address, or the address an ENS name resolves to now
+    Add {
+        name: String,
+
+        /// Address (checksummed if mixed-case) or ENS name
+        target: Target,
+
+        /// Free-form note shown by `list`
+        #[arg(long)]
+        note: Option<String>,
+
+        /// Ethereum mainnet or Sepolia JSON-RPC URL, for ENS names
+        #[arg(long, env = "ENS_RPC")]
+        ens_rpc: Option<String>,
+    },
+    /// List contacts
+    List,
+    /// Forget a contact
+    Remove { name: String },
+    /// Print the address a contact, ENS name or address stands for, with
+    /// the verified ENS primary name when --ens-rpc is given
+    Resolve {
+        target: Target,
+
+        /// Ethereum mainnet or Sepolia JSON-RPC URL, for ENS names
+        #[arg(long, env = "ENS_RPC")]
+        ens_rpc: Option<String>,
+    },
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+    let mut book = output::or_fail(json, "address book", AddressBook::open_default());
+    let node =
+        |url: Option<String>| url.map(|url| HttpTransport::new(url, Duration::from_secs(30)));
+
+    match args.command {
+        Command::Add {
+            name,
+            target,
+            note,
+            ens_rpc,
+        } => {
+            if matches!(target, Target::Contact(_)) {
+                output::fail(json, format!("{target} is not an address or an ENS name"));
+            }
+            let address = output::or_fail(
+                json,
+                "resolve",
+                target.resolve(&book, node(ens_rpc).as_ref()),
+            );
+            let contact = Contact {
+                address,
+                ens: match &target {
+                    Target::Ens(name) => Some(name.clone()),
+                    _ => None,
+                },
+                note,
+            };
+            output::or_fail(json, "not added", book.add(&name, contact));
+            output::or_fail(json, "failed to save address book", book.save());
+            if json {
+                output::emit(
+                    &serde_json::json!({ "name": name.to_ascii_lowercase(), "address": address }),
+                );
+            } else {
+                println!("{} {address}", name.to_ascii_lowercase());
+            }
+        }
+        Command::List => {
+            if json {
+                let rows: Vec<_> = book
+                    .iter()
+                    .map(|(name, c)| {
+                        serde_json::json!({
+                            "name": name,
+                            "address": c.address,
+                            "ens": c.ens,
+                            "note": c.note,
+                        })
+                    })
+                    .collect();
+                output::emit(&rows);
+            } else if book.iter().next().is_none() {
+                println!("No contacts in {}", book.path().display());
+            } else {
+                for (name, c) in book.iter() {
+                    let ens = c
+                        .ens
+                        .as_deref()
+                        .map(|e| format!("  {e}"))
+                        .unwrap_or_default();
+                    let note = c
+                        .note
+                        .as_deref()
+                        .map(|n| format!("  # {n}"))
+                        .unwrap_or_default();
+                    println!("{name:<20} {}{ens}{note}", c.address);
+                }
+            }
+        }
+        Command::Remove { name } => {
+            let removed = output::or_fail(json, "not removed", book.remove(&name));
+            output::or_fail(json, "failed to save address book", book.save());
+            if json {
+                output::emit(&removed);
+            } else {
+                println!(
+                    "Removed {} ({})",
+                    name.to_ascii_lowercase(),
+                    removed.address
+                );
+            }
+        }
+        Command::Resolve { target, ens_rpc } => {
+            let rpc = node(ens_rpc);
+            let address = output::or_fail(json, "resolve", target.resolve(&book, rpc.as_ref()));
+            let primary = match &rpc {
+                Some(rpc) => output::or_fail(json, "reverse lookup", ens::reverse(rpc, address)),
+                None => None,
+            };
+            if json {
+                output::emit(&serde_json::json!({
+                    "address": address,
+                    "contact": book.name_of(address),
+                    "ens": primary,
+                }));
+            } else {
+                let names: Vec<&str> = book
+                    .name_of(address)
+                    .into_iter()
+                    .chain(primary.as_deref())
+                    .collect();
+                if names.is_empty() {
+                    println!("{address}");
+                } else {
+                    println!("{address} ({})", names.join(", "));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use alloy_primitives::{Address, B256, U256};
+use clap::{Parser, Subcommand};
+use pq_cli::attestation;
+use pq_cli::calldata::{self, Call};
+use pq_cli::check::RECOMMENDED_VERIFICATION_GAS;
+use pq_cli::cli::SigningKeyArgs;
+use pq_cli::gas;
+use pq_cli::keystore::Keystore;
+use pq_cli::output::{self, OutputArgs};
+use pq_cli::pkix::{self, KeyFormat};
+use pq_cli::plan::DEFAULT_CALL_GAS_LIMIT;
+use pq_cli::policy::Policy;
+use pq_cli::robot::{Certificate, DelegationStore, Status};
+use pq_cli::rpc::HttpTransport;
+use pq_cli::simulate;
+use pq_cli::userop::{self, ENTRY_POINT_V07, PackedUserOperation};
+use pq_cli::validity::{self, ValidityWindow};
+
+#[derive(Parser)]
+#[command(about = "Delegate signing for a smart account to a robot key, for a limited time")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Issued certificates [default: ~/.pq-wallet/delegations.json]
+    #[arg(long, global = true)]
+    store: Option<PathBuf>,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Sign a certificate with the owner key granting a robot key the right
+    /// to sign the account's UserOperations
+    Issue {
+        /// The owner key, the one the validator module holds
+        #[command(flatten)]
+        key: SigningKeyArgs,
+
+        /// The robot's public key (pk.bin)
+        #[arg(long, required_unless_present = "robot_account")]
+        robot_key: Option<PathBuf>,
+
+        /// Keystore account holding the robot key
+        #[arg(long, conflicts_with = "robot_key")]
+        robot_account: Option<String>,
+
+        /// Smart account the robot may sign for
+        #[arg(long)]
+        wallet: Address,
+
+        /// Chain the grant is good on; 0 for any
+        #[arg(long, default_value_t = 0)]
+        chain_id: u64,
+
+        /// How long the grant lasts from now (e.g. 15m, 1h, 7d)
+        #[arg(long, value_parser = validity::parse_duration)]
+        valid_for: Duration,
+
+        /// Signing policy (TOML or JSON) the robot's pq-signerd must run
+        /// under; its hash is pinned in the grant
+        #[arg(long)]
+        policy: Option<PathBuf>,
+
+        /// Where to write the certificate JSON
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Mark a certificate revoked and print the call that revokes it on
+    /// chain
+    Revoke {
+        /// Certificate hash, as `issue` and `list` print it
+        hash: B256,
+
+        /// Validator module address; prints the account's ERC-7579
+        /// execute calldata instead of the bare module call
+        #[arg(long)]
+        module: Option<Address>,
+
+        /// Write the unsigned revocation UserOperation (JSON) here, with its
+        /// nonce and fees from --rpc, for `pq-userop sign`
+        #[arg(long, requires_all = ["module", "rpc"])]
+        op: Option<PathBuf>,
+
+        /// Node JSON-RPC URL, for --op's nonce and gas fees
+        #[arg(long, env = "LOCAL_RPC")]
+        rpc: Option<String>,
+
+        /// EntryPoint address, for --op
+        #[arg(long, default_value_t = ENTRY_POINT_V07)]
+        entry_point: Address,
+    },
+    /// The certificates issued from this machine, with their status
+    List,
+}
+
+fn read(json: bool, path: &Path) -> Vec<u8> {
+    std::fs::read(path).unwrap_or_else(|e| output::fail(json, format!("{}: {e}", path.display())))
+}
+
+/// An unsigned op from the certificate's account making `call_data`, with
+/// the account's next nonce, suggested fees and default gas limits.
+fn revocation_op(
+    json: bool,
+    cert: &Certificate,
+    call_data: Vec<u8>,
+    rpc: Option<String>,
+    entry_point: Address,
+) -> PackedUserOperation {
+    let node = HttpTransport::new(rpc.expect("clap requires --rpc"), Duration::from_secs(30));
+    let nonce = output::or_fail(
+        json,
+        "getNonce",
+        simulate::get_nonce(&node, entry_point, cert.account),
+    );
+    let quote = output::or_fail(json, "fee quote", gas::cost::suggest_fees(&node));
+    PackedUserOperation {
+        sender: cert.account,
+        nonce,
+        init_code: vec![],
+        call_data,
+        account_gas_limits: userop::pack_u128_pair(
+            RECOMMENDED_VERIFICATION_GAS,
+            DEFAULT_CALL_GAS_LIMIT,
+        ),
+        pre_verification_gas: U256::ZERO,
+        gas_fees: userop::pack_u128_pair(quote.max_priority_fee_per_gas, quote.max_fee_per_gas),
+        paymaster_and_data: vec![],
+        signature: vec![],
+    }
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    let store_path = args.store.unwrap_or_else(|| {
+        output::or_fail(json, "delegation store", DelegationStore::default_path())
+    });
+    let mut store = output::or_fail(json, "delegation store", DelegationStore::load(&store_path));
+    let now = attestation::now();
+
+    match args.command {
+        Command::Issue {
+            key,
+            robot_key,
+            robot_account,
+            wallet,
+            chain_id,
+            valid_for,
+            policy,
+            output: out_path,
+        } => {
+            let robot = match (robot_key, robot_account) {
+                (Some(path), _) => output::or_fail(
+                    json,
+                    "robot key",
+                    pkix::decode_public_key(&read(json, &path), KeyFormat::Raw),
+                ),
+                (None, Some(name)) => output::or_fail(
+                    json,
+                    "robot key",
+                    Keystore::open_default().and_then(|ks| ks.load(&name)?.public_key()),
+                ),
+                (None, None) => unreachable!("clap requires one"),
+            };
+            let policy_hash = match &policy {
+                Some(path) => {
+                    output::or_fail(json, "policy", Policy::load(path).and_then(|p| p.hash()))
+                }
+                None => B256::ZERO,
+            };
+            let window = output::or_fail(
+                json,
+                "validity window",
+                ValidityWindow::starting_at(now, valid_for),
+            );
+            let seed = output::or_fail(json, "failed to load owner key", key.seed());
+            let cert = output::or_fail(
+                json,
+                "failed to issue",
+                Certificate::issue(&seed, &robot, wallet, chain_id, window, policy_hash),
+            );
+            std::fs::write(&out_path, cert.to_json())
+                .unwrap_or_else(|e| output::fail(json, format!("{}: {e}", out_path.display())));
+            let hash = store.insert(cert.clone());
+            output::or_fail(json, "delegation store", store.save(&store_path));
+            if json {
+                output::emit(&serde_json::json!({
+                    "hash": hash,
+                    "wallet": cert.account,
+                    "chainId": cert.chain_id,
+                    "robotKey": cert.robot_fingerprint(),
+                    "validAfter": cert.valid_after,
+                    "validUntil": cert.valid_until,
+                    "policyHash": cert.policy_hash,
+                    "path": out_path.display().to_string(),
+                }));
+            } else {
+                println!("Delegation:  {hash}");
+                println!("Robot key:   {}", cert.robot_fingerprint());
+                println!("Wallet:      {}", cert.account);
+                match cert.chain_id {
+                    0 => println!("Chain:       any"),
+                    id => println!("Chain:       {id}"),
+                }
+                println!("Valid until: {}", cert.valid_until);
+                if !cert.policy_hash.is_zero() {
+                    println!("Policy:      {}", cert.policy_hash);
+                }
+                println!("Certificate: {}", out_path.display());
+            }
+        }
+        Command::Revoke {
+            hash,
+            module,
+            op: op_path,
+            rpc,
+            entry_point,
+        } => {
+            let issued = output::or_fail(json, "failed to revoke", store.revoke(hash, now)).clone();
+            output::or_fail(json, "delegation store", store.save(&store_path));
+            let call = issued.certificate.revoke_call();
+            let calldata = match module {
+                Some(module) => calldata::execute(&[Call {
+                    target: module,
+                    value: U256::ZERO,
+                    data: call,
+                }]),
+                None => call,
+            };
+            if let Some(path) = &op_path {
+                let op = revocation_op(
+                    json,
+                    &issued.certificate,
+                    calldata.clone(),
+                    rpc,
+                    entry_point,
+                );
+                let text = serde_json::to_string_pretty(&op).expect("UserOperation serializes");
+                std::fs::write(path, text)
+                    .unwrap_or_else(|e| output::fail(json, format!("{}: {e}", path.display())));
+            }
+            if json {
+                output::emit(&serde_json::json!({
+                    "hash": hash,
+                    "wallet": issued.certificate.account,
+                    "revokedAt": issued.revoked_at,
+                    "calldata": format!("0x{}", hex::encode(&calldata)),
+                    "op": op_path.as_ref().map(|p| p.display().to_string()),
+                }));
+            } else if let Some(path) = &op_path {
+                println!("Revoked {hash} locally.");
+                println!(
+                    "Revocation UserOperation from {}: {}",
+                    issued.certificate.account,
+                    path.display()
+                );
+                println!("Sign it with the owner key (pq-userop sign), then submit it.");
+            } else {
+                println!("Revoked {hash} locally.");
+                match module {
+                    Some(_) => println!(
+                        "Use this as the callData of a UserOperation from {} to revoke it:",
+                        issued.certificate.account
+                    ),
+                    None => println!(
+                        "Call the validator module from {} with this to revoke it on chain:",
+                        issued.certificate.account
+                    ),
+                }
+                println!("0x{}", hex::encode(&calldata));
+            }
+        }
+        Command::List => {
+            let status = |issued: &pq_cli::robot::Issued| match issued.revoked_at {
+                Some(_) => "revoked",
+                None => match issued.certificate.status(now) {
+                    Status::NotYetValid => "not-yet-valid",
+                    Status::Active => "active",
+                    Status::Expired => "expired",
+                },
+            };
+            if json {
+                let entries: Vec<_> = store
+                    .issued
+                    .iter()
+                    .map(|(hash, issued)| {
+                        serde_json::json!({
+                            "hash": hash,
+                            "status": status(issued),
+                            "wallet": issued.certificate.account,
+                            "chainId": issued.certificate.chain_id,
+                            "robotKey": issued.certificate.robot_fingerprint(),
+                            "validUntil": issued.certificate.valid_until,
+                            "revokedAt": issued.revoked_at,
+                        })
+                    })
+                    .collect();
+                output::emit(&serde_json::json!({ "delegations": entries }));
+            } else if store.issued.is_empty() {
+                println!("No delegations in {}", store_path.display());
+            } else {
+                for (hash, issued) in &store.issued {
+                    println!(
+                        "{hash}  {:<13}  {}  until {}",
+                        status(issued),
+                        issued.certificate.account,
+                        issued.certificate.valid_until
+                    );
+                }
+            }
+        }
+    }
+}
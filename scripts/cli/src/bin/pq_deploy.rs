@@ -0,0 +1,249 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use alloy_primitives::{B256, Bytes, U64, U128, U256};
+use clap::{Parser, Subcommand};
+use pq_cli::cli::RegistryArgs;
+use pq_cli::delegation;
+use pq_cli::deploy::{self, Artifact, CREATE2_DEPLOYER, Eip1559Transaction, Plan};
+use pq_cli::gas::cost;
+use pq_cli::output::{self, OutputArgs};
+use pq_cli::rpc::{HttpTransport, Transport};
+use zeroize::Zeroizing;
+
+#[derive(Parser)]
+#[command(about = "Deploy contracts to deterministic CREATE2 addresses")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(clap::Args)]
+struct ContractArgs {
+    /// Foundry artifact JSON (out/<File>.sol/<Name>.json)
+    #[arg(long)]
+    artifact: PathBuf,
+
+    /// ABI-encoded constructor arguments (hex)
+    #[arg(long = "args", value_name = "HEX")]
+    constructor_args: Option<Bytes>,
+
+    /// CREATE2 salt
+    #[arg(long, default_value_t = B256::ZERO)]
+    salt: B256,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the address the contract deploys to (offline)
+    Address {
+        #[command(flatten)]
+        contract: ContractArgs,
+    },
+    /// Build and sign the deployer call, and with --send broadcast it
+    Deploy {
+        #[command(flatten)]
+        contract: ContractArgs,
+
+        /// Chain to deploy on
+        #[arg(long)]
+        chain_id: u64,
+
+        /// Node JSON-RPC URL, for the nonce, fees, gas and --send
+        #[arg(long, env = "LOCAL_RPC")]
+        rpc: String,
+
+        /// File holding the funded account's hex secp256k1 private key
+        #[arg(long, env = "ECDSA_KEY_FILE")]
+        ecdsa_key: PathBuf,
+
+        /// Gas limit; estimated with eth_estimateGas if omitted
+        #[arg(long)]
+        gas_limit: Option<u64>,
+
+        /// Fees in wei; quoted from --rpc if omitted
+        #[arg(long)]
+        max_fee_per_gas: Option<u128>,
+
+        #[arg(long)]
+        max_priority_fee_per_gas: Option<u128>,
+
+        /// Broadcast the transaction with eth_sendRawTransaction
+        #[arg(long)]
+        send: bool,
+
+        #[command(flatten)]
+        registry: RegistryArgs,
+    },
+}
+
+impl ContractArgs {
+    fn plan(&self, json: bool) -> Plan {
+        let artifact = output::or_fail(json, "--artifact", Artifact::load(&self.artifact));
+        let args = self.constructor_args.clone().unwrap_or_default();
+        Plan::new(&artifact, &args, self.salt)
+    }
+}
+
+fn print_plan(plan: &Plan) {
+    println!("Contract:    {}", plan.contract);
+    println!("Address:     {}", plan.address);
+    println!("Salt:        {}", plan.salt);
+    println!("Init hash:   {}", plan.init_code_hash);
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    match args.command {
+        Command::Address { contract } => {
+            let plan = contract.plan(json);
+            if json {
+                output::emit(&plan);
+            } else {
+                print_plan(&plan);
+            }
+        }
+        Command::Deploy {
+            contract,
+            chain_id,
+            rpc,
+            ecdsa_key,
+            gas_limit,
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+            send,
+            registry,
+        } => {
+            let plan = contract.plan(json);
+            let node = HttpTransport::new(rpc, Duration::from_secs(30));
+            let node_chain: U64 = output::or_fail(
+                json,
+                "eth_chainId",
+                node.call("eth_chainId", serde_json::json!([])),
+            );
+            if node_chain != U64::from(chain_id) {
+                output::fail(
+                    json,
+                    format!("node is on chain {node_chain}, not {chain_id}"),
+                );
+            }
+            // The contract itself may not be there yet; everything else the
+            // registry pins for the chain must be.
+            output::or_fail(
+                json,
+                "refusing to deploy",
+                registry.check(&node, chain_id, Some(plan.address)),
+            );
+            // Deploying is idempotent: the address is already taken by the
+            // same code.
+            if output::or_fail(json, "eth_getCode", deploy::has_code(&node, plan.address)) {
+                if json {
+                    output::emit(&serde_json::json!({ "plan": plan, "alreadyDeployed": true }));
+                } else {
+                    print_plan(&plan);
+                    println!("Already deployed");
+                }
+                return;
+            }
+            if !output::or_fail(
+                json,
+                "eth_getCode",
+                deploy::has_code(&node, CREATE2_DEPLOYER),
+            ) {
+                output::fail(
+                    json,
+                    format!(
+                        "no CREATE2 deployer at {CREATE2_DEPLOYER} on chain {chain_id}; \
+                         broadcast its presigned deployment transaction first"
+                    ),
+                );
+            }
+
+            let hex = Zeroizing::new(
+                std::fs::read_to_string(&ecdsa_key).expect("failed to read ECDSA key file"),
+            );
+            let key = output::or_fail(
+                json,
+                "ECDSA key",
+                delegation::signing_key_from_hex(hex.trim()),
+            );
+            let from = delegation::address_of(&key);
+            let nonce: U64 = output::or_fail(
+                json,
+                "eth_getTransactionCount",
+                node.call(
+                    "eth_getTransactionCount",
+                    serde_json::json!([from, "pending"]),
+                ),
+            );
+            let data = Bytes::from(plan.data.clone());
+            let gas_limit = match gas_limit {
+                Some(limit) => limit,
+                None => {
+                    let estimate: U64 = output::or_fail(
+                        json,
+                        "eth_estimateGas",
+                        node.call(
+                            "eth_estimateGas",
+                            serde_json::json!([{ "from": from, "to": CREATE2_DEPLOYER, "data": data }]),
+                        ),
+                    );
+                    // Headroom for state that changes before inclusion.
+                    estimate.to::<u64>() * 6 / 5
+                }
+            };
+            let (max_fee, tip) = match (max_fee_per_gas, max_priority_fee_per_gas) {
+                (Some(max), tip) => (max, tip.unwrap_or(0)),
+                (None, tip) => {
+                    let quote = output::or_fail(json, "fee quote", cost::suggest_fees(&node));
+                    (
+                        quote.max_fee_per_gas,
+                        tip.unwrap_or(quote.max_priority_fee_per_gas),
+                    )
+                }
+            };
+            let transaction = Eip1559Transaction {
+                chain_id: U256::from(chain_id),
+                nonce,
+                max_priority_fee_per_gas: U128::from(tip),
+                max_fee_per_gas: U128::from(max_fee),
+                gas_limit: U64::from(gas_limit),
+                to: CREATE2_DEPLOYER,
+                value: U256::ZERO,
+                data,
+            };
+            let raw = output::or_fail(json, "sign transaction", transaction.sign(&key));
+
+            let sent = send.then(|| {
+                let hash: B256 = output::or_fail(
+                    json,
+                    "eth_sendRawTransaction",
+                    node.call("eth_sendRawTransaction", serde_json::json!([raw])),
+                );
+                hash
+            });
+            if json {
+                output::emit(&serde_json::json!({
+                    "plan": plan,
+                    "alreadyDeployed": false,
+                    "from": from,
+                    "rawTransaction": raw,
+                    "transactionHash": sent,
+                }));
+            } else {
+                print_plan(&plan);
+                println!("From:        {from}");
+                match sent {
+                    Some(hash) => println!("Sent:        {hash}"),
+                    None => println!("{raw}"),
+                }
+            }
+        }
+    }
+}
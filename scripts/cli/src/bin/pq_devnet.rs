@@ -0,0 +1,98 @@
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use alloy_primitives::Address;
+use clap::Parser;
+use pq_cli::delegation;
+use pq_cli::deploy;
+use pq_cli::devnet::{ANVIL_DEV_KEY, DevBundler};
+use pq_cli::output::{self, OutputArgs};
+use pq_cli::rpc::HttpTransport;
+use pq_cli::userop::ENTRY_POINT_V07;
+use zeroize::Zeroizing;
+
+#[derive(Parser)]
+#[command(about = "Serve a minimal ERC-4337 bundler in front of a local Anvil node")]
+struct Args {
+    /// Anvil JSON-RPC URL
+    #[arg(long, env = "LOCAL_RPC", default_value = "http://127.0.0.1:8545")]
+    rpc: String,
+
+    /// EntryPoint to bundle for
+    #[arg(long, default_value_t = ENTRY_POINT_V07)]
+    entry_point: Address,
+
+    /// Address to serve bundler JSON-RPC on
+    #[arg(long, default_value = "127.0.0.1:4337")]
+    listen: SocketAddr,
+
+    /// File holding the hex secp256k1 key that pays for bundles; Anvil's
+    /// first account if omitted
+    #[arg(long, env = "ECDSA_KEY_FILE")]
+    dev_key: Option<PathBuf>,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    let hex = Zeroizing::new(match &args.dev_key {
+        Some(path) => output::or_fail(
+            json,
+            "--dev-key",
+            std::fs::read_to_string(path)
+                .map_err(|e| pq_cli::error::WalletError::Io(path.display().to_string(), e)),
+        ),
+        None => ANVIL_DEV_KEY.to_string(),
+    });
+    let key = output::or_fail(
+        json,
+        "dev key",
+        delegation::signing_key_from_hex(hex.trim()),
+    );
+
+    let node = HttpTransport::new(args.rpc, Duration::from_secs(30));
+    if !output::or_fail(
+        json,
+        "eth_getCode",
+        deploy::has_code(&node, args.entry_point),
+    ) {
+        output::fail(
+            json,
+            format!(
+                "no EntryPoint at {}; deploy it to the node first (e.g. `anvil --fork-url` a chain \
+                 that has it, or run the contracts' deploy script)",
+                args.entry_point
+            ),
+        );
+    }
+    let mut bundler = output::or_fail(
+        json,
+        "eth_chainId",
+        DevBundler::new(node, args.entry_point, key),
+    );
+    let listener = output::or_fail(
+        json,
+        "--listen",
+        TcpListener::bind(args.listen)
+            .map_err(|e| pq_cli::error::WalletError::Io(args.listen.to_string(), e)),
+    );
+
+    if json {
+        output::emit(&serde_json::json!({
+            "listen": args.listen,
+            "entryPoint": args.entry_point,
+            "bundler": bundler.bundler_address(),
+        }));
+    } else {
+        println!("Bundler RPC: http://{}", args.listen);
+        println!("EntryPoint:  {}", args.entry_point);
+        println!("Bundler:     {}", bundler.bundler_address());
+    }
+    output::or_fail(json, "serve", bundler.serve(listener));
+}
@@ -0,0 +1,341 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand};
+use pq_cli::WalletError;
+use pq_cli::attestation;
+use pq_cli::backup::{KEM_PUBLIC_KEY_LEN, KEM_SEED_LEN};
+use pq_cli::cli::{self, SigningKeyArgs};
+use pq_cli::escrow::{Escrow, Guardian, ReleasedShare, SignedTranscript, Transcript};
+use pq_cli::keystore::{Account, KdfParams, Keystore};
+use pq_cli::output::{self, OutputArgs};
+use pq_cli::siwe;
+
+#[derive(Parser)]
+#[command(
+    about = "Escrow an ML-DSA-65 seed to guardians' ML-KEM-768 keys, any k of whom can recover it"
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Split a seed's content key into one share per guardian and seal each
+    /// share to the guardian's KEM key
+    Deposit {
+        #[command(flatten)]
+        signer: SigningKeyArgs,
+
+        /// A guardian, as LABEL=kem.pk (from `pq-backup keygen`; repeatable)
+        #[arg(long = "guardian", value_name = "LABEL=KEM_PK", required = true)]
+        guardians: Vec<String>,
+
+        /// Guardians needed to recover
+        #[arg(long)]
+        threshold: u8,
+
+        /// Where to write the escrow JSON
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// As a guardian, open your share and re-seal it to the recovering
+    /// device's KEM key
+    Release {
+        /// Escrow JSON from `deposit`
+        #[arg(long)]
+        escrow: PathBuf,
+
+        /// The guardian's kem.sk
+        #[arg(long)]
+        kem_sk: PathBuf,
+
+        /// The recovering device's kem.pk
+        #[arg(long)]
+        to: PathBuf,
+
+        /// Where to write the released share JSON
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Recombine the shares, restore the seed and write the signed recovery
+    /// transcript
+    Recover {
+        #[arg(long)]
+        escrow: PathBuf,
+
+        /// A released share from `release` (repeatable)
+        #[arg(long = "share", requires = "kem_sk")]
+        shares: Vec<PathBuf>,
+
+        /// This device's kem.sk, which the shares are released to
+        #[arg(long)]
+        kem_sk: Option<PathBuf>,
+
+        /// A guardian's own kem.sk, for a guardian recovering in person
+        /// (repeatable)
+        #[arg(long = "guardian-kem-sk")]
+        guardian_kem_sks: Vec<PathBuf>,
+
+        /// Where to write the recovery transcript JSON
+        #[arg(long)]
+        transcript: PathBuf,
+
+        /// Store the seed as this keystore account (prompts for a new
+        /// password); defaults to the name in the escrow
+        #[arg(long, conflicts_with = "output")]
+        account: Option<String>,
+
+        /// Write pk.bin and sk.bin here instead of the keystore
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Check a recovery transcript's signature, and that it is of an escrow
+    Verify {
+        #[arg(long)]
+        transcript: PathBuf,
+
+        #[arg(long)]
+        escrow: Option<PathBuf>,
+    },
+}
+
+fn read(json: bool, path: &Path) -> Vec<u8> {
+    std::fs::read(path).unwrap_or_else(|e| output::fail(json, format!("{}: {e}", path.display())))
+}
+
+fn read_fixed<const N: usize>(json: bool, what: &'static str, path: &Path) -> [u8; N] {
+    let bytes = read(json, path);
+    let actual = bytes.len();
+    bytes.try_into().unwrap_or_else(|_| {
+        output::fail(
+            json,
+            WalletError::InvalidLength {
+                what,
+                expected: N,
+                actual,
+            },
+        )
+    })
+}
+
+fn load_escrow(json: bool, path: &Path) -> Escrow {
+    output::or_fail(json, "escrow", Escrow::from_json(&read(json, path)))
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    match args.command {
+        Command::Deposit {
+            signer,
+            guardians,
+            threshold,
+            output: out_path,
+        } => {
+            if !signer.is_present() {
+                output::fail(json, "one of --key or --account is required");
+            }
+            let guardians: Vec<Guardian> = guardians
+                .iter()
+                .map(|spec| {
+                    let Some((label, path)) = spec.split_once('=') else {
+                        output::fail(json, format!("--guardian {spec}: expected LABEL=KEM_PK"))
+                    };
+                    Guardian {
+                        label: label.to_string(),
+                        kem_public_key: read_fixed::<KEM_PUBLIC_KEY_LEN>(
+                            json,
+                            "KEM public key",
+                            Path::new(path),
+                        ),
+                    }
+                })
+                .collect();
+            if threshold == 1 && guardians.len() > 1 {
+                eprintln!("warning: with --threshold 1, every guardian alone can recover the seed");
+            }
+            let seed = output::or_fail(json, "failed to load signing key", signer.seed());
+            let name = signer.account.clone().unwrap_or_else(|| "recovered".into());
+            let escrow = output::or_fail(
+                json,
+                "failed to deposit",
+                Escrow::deposit(&name, &seed, threshold, &guardians),
+            );
+            std::fs::write(&out_path, escrow.to_json()).expect("failed to write escrow");
+            let id = output::or_fail(json, "escrow", escrow.id());
+            if json {
+                output::emit(&serde_json::json!({
+                    "escrow": id,
+                    "keyId": escrow.key_id(),
+                    "threshold": threshold,
+                    "guardians": escrow.guardians.iter().map(|g| serde_json::json!({
+                        "index": g.index,
+                        "label": g.label,
+                        "guardian": g.guardian,
+                    })).collect::<Vec<_>>(),
+                    "path": out_path.display().to_string(),
+                }));
+            } else {
+                println!("Key ID:      {}", escrow.key_id());
+                println!("Escrow:      {id}");
+                for g in &escrow.guardians {
+                    println!("Guardian {}:  {} {}", g.index, g.label, g.guardian);
+                }
+                println!(
+                    "Recovery needs {threshold} of {} guardians; escrow written to {}",
+                    escrow.guardians.len(),
+                    out_path.display()
+                );
+            }
+        }
+        Command::Release {
+            escrow,
+            kem_sk,
+            to,
+            output: out_path,
+        } => {
+            let escrow = load_escrow(json, &escrow);
+            let kem_seed: [u8; KEM_SEED_LEN] = read_fixed(json, "KEM seed", &kem_sk);
+            let recipient: [u8; KEM_PUBLIC_KEY_LEN] = read_fixed(json, "KEM public key", &to);
+            let released = output::or_fail(
+                json,
+                "failed to release share",
+                escrow.release(&kem_seed, &recipient),
+            );
+            std::fs::write(&out_path, released.to_json()).expect("failed to write share");
+            if json {
+                output::emit(&serde_json::json!({
+                    "escrow": released.escrow,
+                    "index": released.index,
+                    "recipient": released.recipient,
+                    "path": out_path.display().to_string(),
+                }));
+            } else {
+                println!("Key ID:      {}", escrow.key_id());
+                println!(
+                    "Share:       {} of escrow {}",
+                    released.index, released.escrow
+                );
+                println!("Released to: {}", released.recipient);
+                println!("Written to:  {}", out_path.display());
+            }
+        }
+        Command::Recover {
+            escrow: escrow_path,
+            shares,
+            kem_sk,
+            guardian_kem_sks,
+            transcript: transcript_path,
+            account,
+            output: out_dir,
+        } => {
+            let escrow = load_escrow(json, &escrow_path);
+            let mut opened = Vec::new();
+            if let Some(kem_sk) = &kem_sk {
+                let kem_seed: [u8; KEM_SEED_LEN] = read_fixed(json, "KEM seed", kem_sk);
+                for path in &shares {
+                    let released =
+                        output::or_fail(json, "share", ReleasedShare::from_json(&read(json, path)));
+                    opened.push(output::or_fail(
+                        json,
+                        "failed to open share",
+                        escrow.open_release(&released, &kem_seed),
+                    ));
+                }
+            }
+            for path in &guardian_kem_sks {
+                let kem_seed: [u8; KEM_SEED_LEN] = read_fixed(json, "KEM seed", path);
+                opened.push(output::or_fail(
+                    json,
+                    "failed to open share",
+                    escrow.open_share(&kem_seed),
+                ));
+            }
+            let seed = output::or_fail(json, "failed to recover", escrow.recover(&opened));
+            let transcript = output::or_fail(
+                json,
+                "transcript",
+                Transcript::new(&escrow, &opened, attestation::now()).and_then(|t| t.sign(&seed)),
+            );
+            let path = match out_dir {
+                Some(dir) => {
+                    std::fs::create_dir_all(&dir).expect("failed to create output directory");
+                    std::fs::write(dir.join("pk.bin"), &escrow.public_key)
+                        .expect("failed to write public key");
+                    std::fs::write(dir.join("sk.bin"), &seed[..]).expect("failed to write seed");
+                    dir
+                }
+                None => {
+                    let name = account.unwrap_or_else(|| escrow.name.clone());
+                    let store = output::or_fail(json, "keystore", Keystore::open_default());
+                    if store.exists(&name) {
+                        output::fail(json, format!("account {name:?} already exists"));
+                    }
+                    let pw = output::or_fail(
+                        json,
+                        "password",
+                        cli::read_password(&format!("New password for {name}: "), true),
+                    );
+                    let entry = output::or_fail(
+                        json,
+                        "failed to encrypt seed",
+                        Account::encrypt(&name, &seed, pw.as_bytes(), KdfParams::default()),
+                    );
+                    output::or_fail(json, "failed to save account", store.insert(&entry))
+                }
+            };
+            std::fs::write(&transcript_path, transcript.to_json())
+                .expect("failed to write transcript");
+            if json {
+                output::emit(&serde_json::json!({
+                    "keyId": escrow.key_id(),
+                    "transcript": transcript,
+                    "path": path.display().to_string(),
+                }));
+            } else {
+                println!("Key ID:      {}", escrow.key_id());
+                for share in &transcript.transcript.shares {
+                    println!(
+                        "Share {}:     {} {}",
+                        share.index, share.label, share.guardian
+                    );
+                }
+                println!("Restored to: {}", path.display());
+                println!("Transcript:  {}", transcript_path.display());
+            }
+        }
+        Command::Verify { transcript, escrow } => {
+            let signed = output::or_fail(
+                json,
+                "transcript",
+                SignedTranscript::from_json(&read(json, &transcript)),
+            );
+            let escrow = escrow.map(|path| load_escrow(json, &path));
+            output::or_fail(json, "invalid transcript", signed.verify(escrow.as_ref()));
+            let t = &signed.transcript;
+            if json {
+                output::emit(&serde_json::json!({ "valid": true, "transcript": t }));
+            } else {
+                println!("Key ID:      {}", t.key_id);
+                println!("Escrow:      {}", t.escrow);
+                println!(
+                    "Recovered at {} from {} of {} required shares: {}",
+                    siwe::timestamp(t.recovered_at),
+                    t.shares.len(),
+                    t.threshold,
+                    t.shares
+                        .iter()
+                        .map(|s| s.label.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+    }
+}
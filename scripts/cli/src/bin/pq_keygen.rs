@@ -1,33 +1,504 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use ml_dsa::{KeyGen, MlDsa65};
+use pq_cli::attestation;
+use pq_cli::backend::Backend;
+use pq_cli::ceremony::{self, Commitment, EntropyFormat, Reveal, Transcript};
+use pq_cli::cli::{self, SeedSourceArgs};
+use pq_cli::error::WalletError;
+use pq_cli::keys;
+use pq_cli::keystore::{Account, KdfParams, Keystore};
+use pq_cli::message::HashAlg;
+use pq_cli::output::{self, KeygenOutput, OutputArgs};
+use pq_cli::pkix::{self, KeyFormat};
+use pq_cli::provision;
+use zeroize::Zeroizing;
 
 #[derive(Parser)]
-#[command(about = "Generate an ML-DSA-65 keypair")]
+#[command(
+    about = "Generate an ML-DSA-65 keypair",
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    /// Output directory for pk.bin and sk.bin
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Output directory for pk.bin and sk.bin (with --count, the parent of
+    /// one directory per key)
+    #[arg(
+        long,
+        visible_alias = "out",
+        required_unless_present = "account",
+        conflicts_with = "account"
+    )]
+    output: Option<PathBuf>,
+
+    /// Store the key as a new keystore account instead of loose files
     #[arg(long)]
-    output: PathBuf,
+    account: Option<String>,
+
+    /// Key file encoding: raw (pk.bin, sk.bin), der/pem (SubjectPublicKeyInfo
+    /// and PKCS#8), jwk or cose; files are named pk.<format>, sk.<format>
+    #[arg(
+        long = "format",
+        default_value_t = KeyFormat::Raw,
+        conflicts_with_all = ["account", "count"]
+    )]
+    key_format: KeyFormat,
+
+    /// Generate this many keypairs, plus an index.csv of name, address and
+    /// pubkey hash
+    #[arg(long, conflicts_with = "account")]
+    count: Option<u64>,
+
+    /// Spread --count generation across all cores
+    #[arg(long, requires = "count")]
+    parallel: bool,
+
+    /// 32-byte master seed file; every --count key is derived from it, so
+    /// the set is reproducible. --seed-hex or --from-passphrase with
+    /// --count supply the master seed the same way
+    #[arg(long, requires = "count", conflicts_with_all = ["seed_hex", "seed_file", "from_passphrase"])]
+    master_seed: Option<PathBuf>,
+
+    #[command(flatten)]
+    seed: SeedSourceArgs,
+
+    /// Argon2id salt for --from-passphrase
+    #[arg(long, requires = "from_passphrase", default_value = std::str::from_utf8(keys::PASSPHRASE_SALT).unwrap())]
+    passphrase_salt: String,
+
+    /// ML-DSA-65 implementation to derive the public key with: rustcrypto,
+    /// or fips204
+    #[arg(long, default_value = "rustcrypto", conflicts_with = "count")]
+    backend: Backend,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a key from several participants' entropy, committed to
+    /// before anyone reveals it
+    #[command(subcommand)]
+    Ceremony(CeremonyStep),
+}
+
+#[derive(Subcommand)]
+enum CeremonyStep {
+    /// Record your contribution and print the commitment to publish
+    Commit {
+        /// Name of the ceremony, the same for every participant
+        #[arg(long)]
+        ceremony: String,
+
+        /// Your name in the transcript
+        #[arg(long)]
+        participant: String,
+
+        /// How the entropy is written down
+        #[arg(long, value_enum, default_value_t = EntropyFormat::Hex)]
+        entropy_format: EntropyFormat,
+
+        /// Read the entropy from this file instead of a hidden prompt
+        #[arg(long)]
+        entropy_file: Option<PathBuf>,
+
+        /// Where to keep your reveal, private until every commitment is out
+        #[arg(long)]
+        reveal: PathBuf,
+
+        /// Also write the commitment JSON here
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Check every reveal against its commitment and generate the key
+    Finalize {
+        /// A participant's commitment JSON (repeatable)
+        #[arg(long = "commitment", required = true)]
+        commitments: Vec<PathBuf>,
+
+        /// A participant's reveal (repeatable)
+        #[arg(long = "reveal", required = true)]
+        reveals: Vec<PathBuf>,
+
+        /// Output directory for pk.bin and sk.bin
+        #[arg(long, required_unless_present = "account", conflicts_with = "account")]
+        output: Option<PathBuf>,
+
+        /// Store the key as a new keystore account instead
+        #[arg(long)]
+        account: Option<String>,
+
+        /// Where to write the signed transcript
+        #[arg(long)]
+        transcript: PathBuf,
+    },
+    /// Check a transcript's signature and that it lists given commitments
+    Verify {
+        /// Transcript JSON from `finalize`
+        #[arg(long)]
+        transcript: PathBuf,
+
+        /// A commitment the transcript must list (repeatable)
+        #[arg(long = "commitment")]
+        commitments: Vec<PathBuf>,
+
+        /// The public key (pk.bin) the ceremony should have produced
+        #[arg(long)]
+        key: Option<PathBuf>,
+    },
+}
+
+fn read(json: bool, path: &Path) -> Vec<u8> {
+    std::fs::read(path).unwrap_or_else(|e| output::fail(json, format!("{}: {e}", path.display())))
 }
 
 fn main() {
-    let args = Args::parse();
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    if let Some(Command::Ceremony(step)) = args.command {
+        run_ceremony(step, json);
+        return;
+    }
+    if let Some(count) = args.count {
+        provision(&args, count, json);
+        return;
+    }
+
+    let seed = match output::or_fail(
+        json,
+        "seed",
+        args.seed_source().seed(args.passphrase_salt.as_bytes()),
+    ) {
+        Some(seed) => seed,
+        None => Zeroizing::new(MlDsa65::key_gen(&mut rand::rng()).to_seed().into()),
+    };
+    let pk_encoded = args.backend.public_key(&seed);
+    let saved = save(
+        &seed,
+        args.backend,
+        args.account.as_deref(),
+        args.output.as_deref(),
+        args.key_format,
+        json,
+    );
+    if json {
+        output::emit(&KeygenOutput::new(&pk_encoded, saved.path()));
+    } else {
+        saved.print(&pk_encoded, args.key_format);
+    }
+}
+
+/// Where [`save`] put a key.
+enum Saved {
+    Account {
+        name: String,
+        path: PathBuf,
+    },
+    Files {
+        dir: PathBuf,
+        pk: PathBuf,
+        sk: PathBuf,
+    },
+}
+
+impl Saved {
+    /// The account file, or the directory of the key files.
+    fn path(&self) -> &Path {
+        match self {
+            Saved::Account { path, .. } => path,
+            Saved::Files { dir, .. } => dir,
+        }
+    }
+
+    fn print(&self, pk_encoded: &[u8], key_format: KeyFormat) {
+        match (self, key_format) {
+            (Saved::Account { name, path }, _) => {
+                println!("Account:     {name} ({})", path.display());
+                println!("Key ID:      {}", keys::address(pk_encoded));
+            }
+            (Saved::Files { pk, sk, .. }, KeyFormat::Raw) => {
+                println!("Public key:  {} (1952 bytes)", pk.display());
+                println!("Seed:        {} (32 bytes)", sk.display());
+            }
+            (Saved::Files { pk, sk, .. }, format) => {
+                println!("Public key:  {} ({format})", pk.display());
+                println!("Private key: {} ({format}, seed)", sk.display());
+            }
+        }
+        println!(
+            "Fingerprint: {}",
+            keys::fingerprint(pk_encoded, HashAlg::Keccak256)
+        );
+    }
+}
+
+/// Store `seed` as keystore account `account`, or as key files in `output`.
+fn save(
+    seed: &[u8; keys::SEED_LEN],
+    backend: Backend,
+    account: Option<&str>,
+    output: Option<&Path>,
+    key_format: KeyFormat,
+    json: bool,
+) -> Saved {
+    let pk_encoded: [u8; keys::PUBLIC_KEY_LEN] = backend
+        .public_key(seed)
+        .try_into()
+        .expect("ML-DSA-65 public keys are 1952 bytes");
+    if let Some(name) = account {
+        let store = output::or_fail(json, "keystore", Keystore::open_default());
+        let pw = output::or_fail(
+            json,
+            "password",
+            cli::read_password(&format!("New password for {name}: "), true),
+        );
+        let account = output::or_fail(
+            json,
+            "failed to encrypt seed",
+            Account::encrypt(name, seed, pw.as_bytes(), KdfParams::default()),
+        );
+        let path = output::or_fail(json, "failed to save account", store.insert(&account));
+        return Saved::Account {
+            name: name.to_string(),
+            path,
+        };
+    }
 
-    std::fs::create_dir_all(&args.output).expect("failed to create output directory");
+    let dir = output.expect("clap requires --output without --account");
+    std::fs::create_dir_all(dir).expect("failed to create output directory");
 
-    let mut rng = rand::rng();
-    let kp: ml_dsa::KeyPair<MlDsa65> = MlDsa65::key_gen(&mut rng);
+    let ext = key_format.extension();
+    let pk_path = dir.join(format!("pk.{ext}"));
+    let sk_path = dir.join(format!("sk.{ext}"));
 
-    let pk_path = args.output.join("pk.bin");
-    let sk_path = args.output.join("sk.bin");
+    std::fs::write(&pk_path, pkix::encode_public_key(&pk_encoded, key_format))
+        .expect("failed to write public key");
+    std::fs::write(&sk_path, pkix::encode_private_key(seed, key_format))
+        .expect("failed to write seed");
+    Saved::Files {
+        dir: dir.to_path_buf(),
+        pk: pk_path,
+        sk: sk_path,
+    }
+}
 
-    let pk_encoded = kp.verifying_key().encode();
-    let seed = kp.to_seed();
+fn run_ceremony(step: CeremonyStep, json: bool) {
+    match step {
+        CeremonyStep::Commit {
+            ceremony,
+            participant,
+            entropy_format,
+            entropy_file,
+            reveal: reveal_path,
+            output: out_path,
+        } => {
+            let text = match entropy_file {
+                Some(path) => Zeroizing::new(
+                    String::from_utf8(read(json, &path))
+                        .unwrap_or_else(|_| output::fail(json, "entropy file is not text")),
+                ),
+                None => {
+                    let what = match entropy_format {
+                        EntropyFormat::Hex => "hex",
+                        EntropyFormat::Dice => "dice rolls",
+                    };
+                    Zeroizing::new(
+                        rpassword::prompt_password(format!("Entropy for {participant} ({what}): "))
+                            .unwrap_or_else(|e| output::fail(json, format!("entropy prompt: {e}"))),
+                    )
+                }
+            };
+            let entropy = output::or_fail(
+                json,
+                "entropy",
+                ceremony::parse_entropy(&text, entropy_format),
+            );
+            let reveal = Reveal::new(&ceremony, &participant, &entropy);
+            let commitment = output::or_fail(json, "commitment", reveal.commitment());
+            output::or_fail(json, "failed to write reveal", reveal.write(&reveal_path));
+            if let Some(path) = &out_path {
+                std::fs::write(path, commitment.to_json()).expect("failed to write commitment");
+            }
+            if json {
+                output::emit(&commitment);
+            } else {
+                println!("Commitment:  {}", commitment.commitment);
+                println!(
+                    "Reveal:      {} (keep private until every commitment is published)",
+                    reveal_path.display()
+                );
+            }
+        }
+        CeremonyStep::Finalize {
+            commitments,
+            reveals,
+            output: out_dir,
+            account,
+            transcript: transcript_path,
+        } => {
+            let commitments: Vec<Commitment> = commitments
+                .iter()
+                .map(|path| {
+                    output::or_fail(json, "commitment", Commitment::from_json(&read(json, path)))
+                })
+                .collect();
+            let reveals: Vec<Reveal> = reveals
+                .iter()
+                .map(|path| {
+                    let bytes = Zeroizing::new(read(json, path));
+                    output::or_fail(json, "reveal", Reveal::from_json(&bytes))
+                })
+                .collect();
+            let outcome = output::or_fail(
+                json,
+                "ceremony failed",
+                ceremony::finalize(&commitments, &reveals, attestation::now()),
+            );
+            let transcript = &outcome.transcript;
+            std::fs::write(&transcript_path, transcript.to_json())
+                .expect("failed to write transcript");
+            let saved = save(
+                &outcome.seed,
+                Backend::RustCrypto,
+                account.as_deref(),
+                out_dir.as_deref(),
+                KeyFormat::Raw,
+                json,
+            );
+            if json {
+                let mut out = serde_json::to_value(KeygenOutput::new(
+                    &transcript.body.public_key,
+                    saved.path(),
+                ))
+                .expect("output types always serialize");
+                out["ceremony"] = transcript.body.ceremony.clone().into();
+                out["participants"] = transcript.body.participants.len().into();
+                out["transcript"] = transcript_path.display().to_string().into();
+                output::emit(&out);
+            } else {
+                println!(
+                    "Ceremony:    {} ({} participants)",
+                    transcript.body.ceremony,
+                    transcript.body.participants.len()
+                );
+                saved.print(&transcript.body.public_key, KeyFormat::Raw);
+                println!("Transcript:  {}", transcript_path.display());
+            }
+        }
+        CeremonyStep::Verify {
+            transcript: path,
+            commitments,
+            key,
+        } => {
+            let transcript = output::or_fail(
+                json,
+                "transcript",
+                Transcript::from_json(&read(json, &path)),
+            );
+            let mut problems = Vec::new();
+            match transcript.verify() {
+                Ok(()) => {}
+                Err(e @ WalletError::Ceremony(_)) => problems.push(e.to_string()),
+                Err(e) => output::fail(json, e),
+            }
+            for path in &commitments {
+                let commitment =
+                    output::or_fail(json, "commitment", Commitment::from_json(&read(json, path)));
+                if !transcript.includes(&commitment) {
+                    problems.push(format!(
+                        "{}'s commitment {} is not in the transcript",
+                        commitment.participant, commitment.commitment
+                    ));
+                }
+            }
+            if let Some(path) = &key
+                && read(json, path) != transcript.body.public_key[..]
+            {
+                problems.push(format!("{} is not the ceremony key", path.display()));
+            }
+            let valid = problems.is_empty();
+            if json {
+                output::emit(&serde_json::json!({
+                    "valid": valid,
+                    "problems": problems,
+                    "ceremony": transcript.body.ceremony,
+                    "keyId": transcript.body.key_id,
+                    "fingerprint": transcript.body.fingerprint,
+                    "participants": transcript.body.participants,
+                }));
+            } else {
+                println!("Ceremony:    {}", transcript.body.ceremony);
+                println!("Key ID:      {}", transcript.body.key_id);
+                println!("Fingerprint: {}", transcript.body.fingerprint);
+                for c in &transcript.body.participants {
+                    println!("  {}  {}", c.commitment, c.participant);
+                }
+                for problem in &problems {
+                    eprintln!("error: {problem}");
+                }
+                if valid {
+                    println!("Valid");
+                }
+            }
+            if !valid {
+                std::process::exit(output::EXIT_FAILURE);
+            }
+        }
+    }
+}
 
-    std::fs::write(&pk_path, &pk_encoded[..]).expect("failed to write public key");
-    std::fs::write(&sk_path, &seed[..]).expect("failed to write seed");
+impl Args {
+    /// `--master-seed FILE` is `--seed-file FILE` under another name.
+    fn seed_source(&self) -> SeedSourceArgs {
+        SeedSourceArgs {
+            seed_hex: self.seed.seed_hex.clone(),
+            seed_file: self
+                .master_seed
+                .clone()
+                .or_else(|| self.seed.seed_file.clone()),
+            from_passphrase: self.seed.from_passphrase,
+        }
+    }
+}
 
-    println!("Public key:  {} (1952 bytes)", pk_path.display());
-    println!("Seed:        {} (32 bytes)", sk_path.display());
-}
\ No newline at end of file
+fn provision(args: &Args, count: u64, json: bool) {
+    let dir = args.output.as_ref().unwrap();
+    std::fs::create_dir_all(dir).expect("failed to create output directory");
+    let master = output::or_fail(
+        json,
+        "master seed",
+        args.seed_source().seed(args.passphrase_salt.as_bytes()),
+    );
+    if master.is_none() {
+        tracing::warn!("no master seed; keys are random and cannot be regenerated");
+    }
+
+    let start = Instant::now();
+    let keys = output::or_fail(
+        json,
+        "provisioning failed",
+        provision::generate(dir, count, master.as_deref(), args.parallel),
+    );
+    let index = dir.join(provision::INDEX_FILE);
+    if json {
+        output::emit(&serde_json::json!({
+            "count": keys.len(),
+            "index": index.display().to_string(),
+            "elapsedMs": start.elapsed().as_millis(),
+        }));
+    } else {
+        println!(
+            "Generated {} keypairs in {:.1?}; index: {}",
+            keys.len(),
+            start.elapsed(),
+            index.display()
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use alloy_primitives::{Address, Bytes};
+use clap::{Parser, Subcommand};
+use pq_cli::calldata::{self, InitLayout, ModuleType};
+use pq_cli::cli::{PublicKeyArgs, RegistryArgs};
+use pq_cli::output::{self, OutputArgs};
+use pq_cli::rpc::HttpTransport;
+use pq_cli::userop::PackedUserOperation;
+
+#[derive(Parser)]
+#[command(about = "Encode ERC-7579 module install/uninstall calls")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(clap::Args)]
+struct ModuleArgs {
+    /// Module type: validator, executor, fallback or hook (or its ID)
+    #[arg(long = "type")]
+    module_type: ModuleType,
+
+    /// Module contract address
+    #[arg(long)]
+    module: Address,
+
+    /// UserOperation JSON whose callData to replace; prints the updated op
+    #[arg(long)]
+    op: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// installModule(type, module, initData)
+    Install {
+        #[command(flatten)]
+        module: ModuleArgs,
+
+        /// Raw initData for the module's onInstall
+        #[arg(long, conflicts_with_all = ["key", "account"])]
+        init_data: Option<Bytes>,
+
+        /// ML-DSA public key to register (the PQ validator's onInstall data)
+        #[command(flatten)]
+        public_key: PublicKeyArgs,
+
+        /// Lay out initData for a Kernel v3 account (hook sentinel prefix)
+        #[arg(long)]
+        kernel: bool,
+
+        /// With --registry: refuse a validator module other than the
+        /// registry's, or a chain whose pinned contracts hold other code
+        #[command(flatten)]
+        registry: RegistryArgs,
+
+        /// Chain the account is on, for --registry
+        #[arg(long)]
+        chain_id: Option<u64>,
+
+        /// Node JSON-RPC URL, for --registry
+        #[arg(long, env = "LOCAL_RPC")]
+        rpc: Option<String>,
+    },
+    /// uninstallModule(type, module, deInitData)
+    Uninstall {
+        #[command(flatten)]
+        module: ModuleArgs,
+
+        #[arg(long)]
+        deinit_data: Option<Bytes>,
+    },
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    let (module, data) = match args.command {
+        Command::Install {
+            module,
+            init_data,
+            public_key,
+            kernel,
+            registry,
+            chain_id,
+            rpc,
+        } => {
+            if registry.registry.is_some() {
+                let (Some(chain_id), Some(rpc)) = (chain_id, rpc) else {
+                    output::fail(json, "--registry needs --chain-id and --rpc");
+                };
+                let node = HttpTransport::new(rpc, Duration::from_secs(30));
+                let deployment = output::or_fail(
+                    json,
+                    "refusing to install",
+                    registry.check(&node, chain_id, None),
+                )
+                .expect("--registry is set");
+                if module.module_type == ModuleType::Validator
+                    && module.module != deployment.validator.address
+                {
+                    output::fail(
+                        json,
+                        format!(
+                            "refusing to install: {} is not the registry's validator on chain \
+                             {chain_id} ({})",
+                            module.module, deployment.validator.address
+                        ),
+                    );
+                }
+            }
+            let init = match init_data {
+                Some(data) => data.to_vec(),
+                None if public_key.is_present() => {
+                    output::or_fail(json, "failed to load public key", public_key.public_key())
+                        .to_vec()
+                }
+                None => Vec::new(),
+            };
+            let layout = if kernel {
+                InitLayout::Kernel
+            } else {
+                InitLayout::Raw
+            };
+            let init = layout.wrap(module.module_type, &init);
+            let data = calldata::install_module(module.module_type, module.module, &init);
+            (module, data)
+        }
+        Command::Uninstall {
+            module,
+            deinit_data,
+        } => {
+            let deinit = deinit_data.unwrap_or_default();
+            let data = calldata::uninstall_module(module.module_type, module.module, &deinit);
+            (module, data)
+        }
+    };
+    let data = Bytes::from(data);
+
+    if let Some(path) = &module.op {
+        let text = std::fs::read_to_string(path).expect("failed to read UserOperation file");
+        let mut op: PackedUserOperation = serde_json::from_str(&text)
+            .unwrap_or_else(|e| output::fail(json, format!("invalid UserOperation JSON: {e}")));
+        op.call_data = data.to_vec();
+        // The old signature covered the old callData.
+        op.signature.clear();
+        eprintln!("callData set; re-sign with pq-userop sign before submitting");
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&op).expect("UserOperation serializes")
+        );
+    } else if json {
+        output::emit(&serde_json::json!({
+            "callData": data,
+            "moduleType": module.module_type.to_string(),
+            "moduleTypeId": module.module_type.id(),
+            "module": module.module,
+        }));
+    } else {
+        println!("{data}");
+    }
+}
@@ -0,0 +1,270 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use alloy_primitives::B256;
+use clap::{Parser, Subcommand};
+use pq_cli::cli::SigningKeyArgs;
+use pq_cli::error::WalletError;
+use pq_cli::output::{self, OutputArgs};
+use pq_cli::registry::{CodeStatus, Registry, SignedRegistry};
+use pq_cli::release::{Artifact, FileStatus, Manifest, SignedManifest};
+use pq_cli::rpc::HttpTransport;
+
+#[derive(Parser)]
+#[command(
+    about = "Sign release manifests and deployment registries, and check downloads and chains against them"
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Hash the built binaries and sign the manifest with the release key
+    Attest {
+        /// The release key
+        #[command(flatten)]
+        key: SigningKeyArgs,
+
+        /// Release version
+        #[arg(long, default_value = env!("CARGO_PKG_VERSION"))]
+        release: String,
+
+        /// Git commit the binaries were built from
+        #[arg(long)]
+        commit: Option<String>,
+
+        /// Target triple the binaries were built for
+        #[arg(long)]
+        target: Option<String>,
+
+        /// Where to write the signed manifest JSON
+        #[arg(long)]
+        output: PathBuf,
+
+        /// The binaries, e.g. target/release/pq-*
+        #[arg(required = true)]
+        artifacts: Vec<PathBuf>,
+    },
+    /// Sign the deployment registry (cli/registry.json) with the release key
+    SignRegistry {
+        /// The release key
+        #[command(flatten)]
+        key: SigningKeyArgs,
+
+        /// Unsigned registry JSON
+        #[arg(long, default_value = "scripts/cli/registry.json")]
+        registry: PathBuf,
+
+        /// Where to write the signed registry JSON
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Check a signed registry, then the code of the contracts it pins on
+    /// a chain
+    CheckRegistry {
+        /// Signed registry from `sign-registry`
+        #[arg(long)]
+        registry: PathBuf,
+
+        /// Fingerprint of an accepted release key (repeatable)
+        #[arg(long = "trusted-key", value_name = "FINGERPRINT", required = true)]
+        trusted_keys: Vec<B256>,
+
+        #[arg(long)]
+        chain_id: u64,
+
+        /// Node JSON-RPC URL
+        #[arg(long, env = "LOCAL_RPC")]
+        rpc: String,
+    },
+    /// Check a manifest's signature, then the downloaded files against it
+    Verify {
+        /// Signed manifest from `attest`
+        #[arg(long)]
+        manifest: PathBuf,
+
+        /// Fingerprint of an accepted release key (repeatable)
+        #[arg(long = "trusted-key", value_name = "FINGERPRINT", required = true)]
+        trusted_keys: Vec<B256>,
+
+        /// Directory holding every listed binary [default: the manifest's]
+        #[arg(long, conflicts_with = "files")]
+        dir: Option<PathBuf>,
+
+        /// Check only these files, each against the entry of its name
+        files: Vec<PathBuf>,
+    },
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    match args.command {
+        Command::Attest {
+            key,
+            release,
+            commit,
+            target,
+            output: out_path,
+            artifacts,
+        } => {
+            let artifacts = artifacts
+                .iter()
+                .map(|path| output::or_fail(json, "failed to hash", Artifact::hash(path)))
+                .collect();
+            let manifest = output::or_fail(
+                json,
+                "manifest",
+                Manifest::new(release, commit, target, artifacts),
+            );
+            let seed = output::or_fail(json, "failed to load release key", key.seed());
+            let signed = output::or_fail(json, "failed to sign", manifest.sign(&seed));
+            std::fs::write(&out_path, signed.to_json()).expect("failed to write manifest");
+            if json {
+                output::emit(&serde_json::json!({
+                    "releaseKey": signed.release_key_fingerprint(),
+                    "release": signed.manifest.release,
+                    "artifacts": signed.manifest.artifacts,
+                    "path": out_path.display().to_string(),
+                }));
+            } else {
+                for artifact in &signed.manifest.artifacts {
+                    println!("{}  {}", hex::encode(artifact.sha256), artifact.name);
+                }
+                println!("Release key: {}", signed.release_key_fingerprint());
+                println!("Manifest:    {}", out_path.display());
+            }
+        }
+        Command::SignRegistry {
+            key,
+            registry,
+            output: out_path,
+        } => {
+            let bytes = std::fs::read(&registry)
+                .unwrap_or_else(|e| output::fail(json, format!("{}: {e}", registry.display())));
+            let registry = output::or_fail(json, "registry", Registry::from_json(&bytes));
+            let seed = output::or_fail(json, "failed to load release key", key.seed());
+            let signed = output::or_fail(json, "failed to sign", registry.sign(&seed));
+            std::fs::write(&out_path, signed.to_json()).expect("failed to write registry");
+            if json {
+                output::emit(&serde_json::json!({
+                    "releaseKey": signed.release_key_fingerprint(),
+                    "release": signed.registry.release,
+                    "chains": signed.registry.chains.keys().collect::<Vec<_>>(),
+                    "path": out_path.display().to_string(),
+                }));
+            } else {
+                for (chain_id, deployment) in &signed.registry.chains {
+                    for (role, pinned) in deployment.contracts() {
+                        println!(
+                            "chain {chain_id} {role} {}  {}",
+                            pinned.address, pinned.code_hash
+                        );
+                    }
+                }
+                println!("Release key: {}", signed.release_key_fingerprint());
+                println!("Registry:    {}", out_path.display());
+            }
+        }
+        Command::CheckRegistry {
+            registry,
+            trusted_keys,
+            chain_id,
+            rpc,
+        } => {
+            let bytes = std::fs::read(&registry)
+                .unwrap_or_else(|e| output::fail(json, format!("{}: {e}", registry.display())));
+            let signed = output::or_fail(json, "registry", SignedRegistry::from_json(&bytes));
+            output::or_fail(json, "untrusted registry", signed.verify(&trusted_keys));
+            let node = HttpTransport::new(rpc, Duration::from_secs(30));
+            let checks = output::or_fail(json, "check", signed.check_chain(&node, chain_id));
+            let valid = checks.iter().all(|c| c.status == CodeStatus::Ok);
+            if json {
+                output::emit(&serde_json::json!({
+                    "valid": valid,
+                    "release": signed.registry.release,
+                    "chainId": chain_id,
+                    "contracts": checks,
+                }));
+            } else {
+                println!("Release:     {}", signed.registry.release);
+                for check in &checks {
+                    println!("{check}");
+                }
+            }
+            if !valid {
+                std::process::exit(output::EXIT_FAILURE);
+            }
+        }
+        Command::Verify {
+            manifest: path,
+            trusted_keys,
+            dir,
+            files,
+        } => {
+            let bytes = std::fs::read(&path)
+                .unwrap_or_else(|e| output::fail(json, format!("{}: {e}", path.display())));
+            let signed = output::or_fail(json, "manifest", SignedManifest::from_json(&bytes));
+            match signed.verify(&trusted_keys) {
+                Ok(()) => {}
+                // An untrusted or forged manifest is a failed check.
+                Err(e @ WalletError::Release(_)) => {
+                    if json {
+                        output::emit(
+                            &serde_json::json!({ "valid": false, "reason": e.to_string() }),
+                        );
+                    } else {
+                        eprintln!("error: {e}");
+                    }
+                    std::process::exit(output::EXIT_FAILURE);
+                }
+                Err(e) => output::fail(json, e),
+            }
+            let checks = if files.is_empty() {
+                let dir = dir.unwrap_or_else(|| {
+                    path.parent()
+                        .filter(|p| !p.as_os_str().is_empty())
+                        .unwrap_or(Path::new("."))
+                        .to_path_buf()
+                });
+                output::or_fail(json, "failed to check", signed.check_dir(&dir))
+            } else {
+                files
+                    .iter()
+                    .map(|file| output::or_fail(json, "failed to check", signed.check_file(file)))
+                    .collect()
+            };
+            let valid = checks.iter().all(|c| c.status == FileStatus::Ok);
+            if json {
+                output::emit(&serde_json::json!({
+                    "valid": valid,
+                    "releaseKey": signed.release_key_fingerprint(),
+                    "release": signed.manifest.release,
+                    "files": checks,
+                }));
+            } else {
+                println!("Release:     {}", signed.manifest.release);
+                println!("Release key: {}", signed.release_key_fingerprint());
+                for check in &checks {
+                    let status = match check.status {
+                        FileStatus::Ok => "OK",
+                        FileStatus::Mismatch => "MISMATCH",
+                        FileStatus::Missing => "MISSING",
+                        FileStatus::Unlisted => "NOT IN MANIFEST",
+                    };
+                    println!("{}: {status}", check.name);
+                }
+            }
+            if !valid {
+                std::process::exit(output::EXIT_FAILURE);
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use alloy_primitives::{Address, B256};
+use clap::Parser;
+use pq_cli::output::{self, EXIT_FAILURE, OutputArgs};
+use pq_cli::replay::{self, Verdict};
+use pq_cli::rpc::HttpTransport;
+
+#[derive(Parser)]
+#[command(about = "Re-verify a historical bundle's signatures off chain and report divergences")]
+struct Args {
+    /// The handleOps transaction
+    #[arg(long)]
+    tx: B256,
+
+    /// Archive node JSON-RPC URL; it must serve storage at the bundle's
+    /// parent block
+    #[arg(long, env = "LOCAL_RPC")]
+    rpc: String,
+
+    /// The PQValidatorModule the senders use
+    #[arg(long)]
+    module: Address,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    let node = HttpTransport::new(&args.rpc, Duration::from_secs(30));
+    let replay = output::or_fail(json, "replay", replay::replay(&node, args.tx, args.module));
+    let diverged = replay.divergences().count();
+    if json {
+        output::emit(&replay);
+    } else {
+        println!("Bundle:      {}", replay.transaction);
+        println!(
+            "Block:       {} on chain {}{}",
+            replay.block_number,
+            replay.chain_id,
+            if replay.included { "" } else { " (reverted)" }
+        );
+        println!("EntryPoint:  {}", replay.entry_point);
+        for op in &replay.ops {
+            let verdict = match op.verdict {
+                Verdict::Valid => "valid",
+                Verdict::ValidDelegated => "valid (delegated)",
+                Verdict::Invalid => "INVALID",
+                Verdict::NoKey => "no key in the module, skipped",
+            };
+            println!(
+                "#{:<3} {} nonce {}  {}  {verdict}",
+                op.index, op.sender, op.nonce, op.user_op_hash
+            );
+            if let Some(divergence) = &op.divergence {
+                println!("     DIVERGENCE: {divergence}");
+            }
+        }
+        match diverged {
+            0 => println!("No divergence in {} ops.", replay.ops.len()),
+            n => println!("{n} of {} ops diverge.", replay.ops.len()),
+        }
+    }
+    if diverged > 0 {
+        std::process::exit(EXIT_FAILURE);
+    }
+}
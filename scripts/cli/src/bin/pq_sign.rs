@@ -1,41 +1,386 @@
 use std::path::PathBuf;
 
-use clap::Parser;
-use ml_dsa::{MlDsa65, SigningKey, signature::Signer};
+use alloy_primitives::{Address, B256, keccak256};
+use clap::{Parser, Subcommand};
+use pq_cli::attestation::{self, Attestation};
+use pq_cli::audit::{AuditLog, Record};
+use pq_cli::backend::Backend;
+use pq_cli::cli::{MessageArgs, PersonalMessageArgs, SignatureFormat, SigningKeyArgs};
+use pq_cli::cose;
+use pq_cli::ct;
+use pq_cli::envelope::{self, Envelope, SCHEME_ML_DSA_65, SignatureEnvelope};
+use pq_cli::keys;
+use pq_cli::message::{HashAlg, MessageInput};
+use pq_cli::output::{self, OutputArgs, SignOutput};
+use pq_cli::siwe;
+use pq_cli::strict::EnvelopeLayout;
 
 #[derive(Parser)]
-#[command(about = "Sign a 32-byte hash with ML-DSA-65")]
+#[command(
+    about = "Sign a 32-byte hash, a file, or stdin with ML-DSA-65",
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    /// Path to seed file (sk.bin, 32 bytes)
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    signer: SigningKeyArgs,
+
+    #[command(flatten)]
+    message: MessageArgs,
+
+    /// Output path for signature
+    #[arg(long, required_unless_present = "timing_probe")]
+    output: Option<PathBuf>,
+
+    /// Signature encoding: raw, or cose for a COSE_Sign1 that carries the
+    /// message and the key's fingerprint as kid
+    #[arg(long = "format", value_name = "FORMAT", value_enum, default_value_t = SignatureFormat::Raw)]
+    sig_format: SignatureFormat,
+
+    /// Developer mode: measure sign latency variance over N random seeds
+    /// instead of signing
+    #[arg(long, value_name = "SAMPLES", conflicts_with_all = ["key", "account", "output"])]
+    timing_probe: Option<usize>,
+
+    /// Host attestation for the signing key (from `pq-attest issue`), attached
+    /// to the --json response
+    #[arg(long, requires = "json")]
+    attestation: Option<PathBuf>,
+
+    /// ML-DSA-65 implementation to sign with: rustcrypto, or fips204
+    #[arg(long, global = true, default_value = "rustcrypto")]
+    backend: Backend,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Sign an EIP-191 personal message, for contracts that check it
+    /// through ERC-1271
+    Personal(PersonalArgs),
+    /// Sign in to a dapp with Sign-In with Ethereum (EIP-4361) as a smart
+    /// account, through its ERC-1271 path
+    Siwe(SiweArgs),
+}
+
+#[derive(clap::Args)]
+struct PersonalArgs {
+    #[command(flatten)]
+    signer: SigningKeyArgs,
+
+    #[command(flatten)]
+    message: PersonalMessageArgs,
+
+    /// Output path for the envelope
     #[arg(long)]
-    key: PathBuf,
+    output: PathBuf,
 
-    /// Hex-encoded 32-byte hash to sign (with or without 0x prefix)
+    /// Envelope layout: abi, packed or versioned
+    #[arg(long, value_name = "LAYOUT", default_value = "abi")]
+    envelope: EnvelopeLayout,
+}
+
+#[derive(clap::Args)]
+struct SiweArgs {
+    #[command(flatten)]
+    signer: SigningKeyArgs,
+
+    /// Domain asking for the sign-in, e.g. app.example.com
     #[arg(long)]
-    hash: String,
+    domain: String,
 
-    /// Output path for signature
+    /// Smart account signing in
+    #[arg(long)]
+    smart_account: Address,
+
+    /// URI of the resource the sign-in is for
+    #[arg(long)]
+    uri: String,
+
+    #[arg(long)]
+    chain_id: u64,
+
+    /// PQValidatorModule installed on the account
+    #[arg(long)]
+    module: Address,
+
+    /// Contract that calls isValidSignature on the account [default: zero,
+    /// as for an eth_call without a from]
+    #[arg(long, default_value_t = Address::ZERO, hide_default_value = true)]
+    caller: Address,
+
+    /// Human-readable statement shown to the user
+    #[arg(long)]
+    statement: Option<String>,
+
+    /// Nonce the dapp issued [default: random]
+    #[arg(long)]
+    nonce: Option<String>,
+
+    /// Seconds until the sign-in expires
+    #[arg(long)]
+    expires_in: Option<u64>,
+
+    /// Resource the sign-in covers (repeatable)
+    #[arg(long = "resource")]
+    resources: Vec<String>,
+
+    /// Output path for the signature
     #[arg(long)]
     output: PathBuf,
 }
 
 fn main() {
-    let args = Args::parse();
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    match args.command {
+        Some(Command::Personal(personal)) => {
+            return sign_personal(personal, args.backend, args.format.json);
+        }
+        Some(Command::Siwe(siwe)) => return sign_siwe(siwe, args.backend, args.format.json),
+        None => {}
+    }
+
+    if let Some(samples) = args.timing_probe {
+        let report = ct::probe_sign_timing(samples);
+        println!("Samples:  {}", report.samples);
+        println!("Mean:     {:?}", report.mean);
+        println!("Stddev:   {:?}", report.stddev);
+        println!("Min/Max:  {:?} / {:?}", report.min, report.max);
+        println!("CV:       {:.3}", report.coefficient_of_variation());
+        return;
+    }
+
+    let json = args.format.json;
+    if !args.signer.is_present() {
+        output::fail(json, "one of --key or --account is required");
+    }
+    let sig_path = args.output.unwrap();
+
+    let seed = output::or_fail(json, "failed to load signing key", args.signer.seed());
+    let pk = args.backend.public_key(&seed);
 
-    let seed_bytes = std::fs::read(&args.key).expect("failed to read seed file");
-    let seed_arr: [u8; 32] = seed_bytes
-        .try_into()
-        .expect("seed must be exactly 32 bytes");
-    let sk = SigningKey::<MlDsa65>::from_seed(&seed_arr.into());
+    let attestation = args.attestation.map(|path| {
+        let bytes = std::fs::read(&path)
+            .unwrap_or_else(|e| output::fail(json, format!("{}: {e}", path.display())));
+        let record = output::or_fail(json, "attestation", Attestation::from_json(&bytes));
+        // Trust is the relying party's call; the signer only refuses to hand
+        // out a record that names another key or has lapsed.
+        if record.key != keys::fingerprint(&pk, HashAlg::Keccak256) {
+            output::fail(json, "attestation is for a different key");
+        }
+        if attestation::now() >= record.expires_at {
+            output::fail(json, "attestation has expired");
+        }
+        record
+    });
 
-    let hash_hex = args.hash.strip_prefix("0x").unwrap_or(&args.hash);
-    let hash_bytes = hex::decode(hash_hex).expect("invalid hex in --hash");
-    assert!(hash_bytes.len() == 32, "hash must be exactly 32 bytes");
+    let input = match output::or_fail(json, "invalid message input", args.message.resolve()) {
+        // Hash once up front so stdin is only consumed a single time.
+        input @ MessageInput::Digest(_, alg) => {
+            let digest = output::or_fail(json, "failed to hash input", input.digest()).unwrap();
+            eprintln!("Digest ({alg}): 0x{}", ct::encode_hex(&digest));
+            MessageInput::Hash(digest)
+        }
+        input => input,
+    };
 
-    let sig = sk.sign(&hash_bytes);
+    #[cfg(feature = "metrics")]
+    let started = std::time::Instant::now();
+    let (sig_encoded, cose) = match args.sig_format {
+        SignatureFormat::Raw => (
+            output::or_fail(json, "failed to sign", input.sign_on(args.backend, &seed)),
+            None,
+        ),
+        SignatureFormat::Cose => {
+            let payload = output::or_fail(json, "failed to read message", input.payload());
+            let kid = keys::fingerprint(&pk, HashAlg::Keccak256);
+            let cose = output::or_fail(
+                json,
+                "failed to sign",
+                cose::sign1_on(args.backend, &seed, kid.as_slice(), &payload),
+            );
+            (cose.signature.clone(), Some(cose.encode()))
+        }
+    };
+    #[cfg(feature = "metrics")]
+    pq_cli::metrics::signature("pq-sign", started.elapsed());
 
-    let sig_encoded = sig.encode();
-    std::fs::write(&args.output, &sig_encoded[..]).expect("failed to write signature");
+    let digest = output::or_fail(json, "failed to hash input", input.digest()).map(B256::from);
+    let summary = match (&input, digest) {
+        (MessageInput::Raw(src), _) => format!("sign raw message from {}", src.label()),
+        (_, digest) => format!("sign 32-byte message {}", digest.unwrap_or_default()),
+    };
+    let record = Record {
+        tool: "pq-sign".to_string(),
+        account: args.signer.account.clone(),
+        key_id: keys::address(&pk),
+        digest,
+        chain_id: None,
+        signature: keccak256(&sig_encoded[..]),
+        summary: vec![summary],
+        backend: args.backend.to_string(),
+    };
+    output::or_fail(
+        json,
+        "signature withheld",
+        AuditLog::open_default().and_then(|log| log.append(record)),
+    );
+    let written = cose.as_deref().unwrap_or(&sig_encoded);
+    std::fs::write(&sig_path, written).expect("failed to write signature");
 
-    println!("Signature written to {} (3309 bytes)", args.output.display());
-}
\ No newline at end of file
+    if json {
+        let mut out = SignOutput::ml_dsa_65(&sig_encoded);
+        out.attestation = attestation;
+        out.cose = cose.map(|c| format!("0x{}", ct::encode_hex(&c)));
+        output::emit(&out);
+        return;
+    }
+
+    match args.sig_format {
+        SignatureFormat::Raw => {
+            println!("Signature written to {} (3309 bytes)", sig_path.display())
+        }
+        SignatureFormat::Cose => println!(
+            "COSE_Sign1 written to {} ({} bytes)",
+            sig_path.display(),
+            written.len()
+        ),
+    }
+}
+
+fn sign_personal(args: PersonalArgs, backend: Backend, json: bool) {
+    let personal = output::or_fail(json, "invalid message", args.message.resolve());
+    let seed = output::or_fail(json, "failed to load signing key", args.signer.seed());
+    let pk = backend.public_key(&seed);
+
+    let message_hash = personal.hash();
+    let signed_hash = personal.signed_hash();
+    let sig = output::or_fail(
+        json,
+        "failed to sign",
+        MessageInput::Hash(signed_hash.0).sign_on(backend, &seed),
+    );
+    let pq = SignatureEnvelope {
+        scheme_id: SCHEME_ML_DSA_65,
+        public_key: pk.clone(),
+        signature: sig.clone(),
+    };
+    let encoded = output::or_fail(
+        json,
+        "failed to encode envelope",
+        match args.envelope {
+            EnvelopeLayout::Abi => {
+                envelope::encode_signature_envelope(&pq.public_key, &pq.signature, pq.scheme_id)
+            }
+            EnvelopeLayout::Packed => {
+                envelope::encode_signature_envelope_packed(&pq.public_key, &pq.signature)
+            }
+            EnvelopeLayout::Versioned => Envelope::V1(pq).encode(),
+        },
+    );
+
+    let summary = match personal.binding {
+        Some(binding) => format!(
+            "sign personal message {message_hash} for {} via module {}",
+            binding.account, binding.module
+        ),
+        None => format!("sign personal message {message_hash}"),
+    };
+    let record = Record {
+        tool: "pq-sign".to_string(),
+        account: args.signer.account.clone(),
+        key_id: keys::address(&pk),
+        digest: Some(signed_hash),
+        chain_id: personal.binding.map(|b| b.chain_id),
+        signature: keccak256(&sig[..]),
+        summary: vec![summary],
+        backend: backend.to_string(),
+    };
+    output::or_fail(
+        json,
+        "signature withheld",
+        AuditLog::open_default().and_then(|log| log.append(record)),
+    );
+    std::fs::write(&args.output, &encoded).expect("failed to write envelope");
+
+    if json {
+        let mut out = SignOutput::ml_dsa_65(&sig);
+        out.message_hash = Some(message_hash);
+        out.envelope = Some(format!("0x{}", ct::encode_hex(&encoded)));
+        output::emit(&out);
+        return;
+    }
+    println!("Message hash: {message_hash}");
+    if personal.binding.is_some() {
+        println!("Signed hash:  {signed_hash}");
+    }
+    println!(
+        "{} envelope written to {} ({} bytes)",
+        args.envelope,
+        args.output.display(),
+        encoded.len()
+    );
+}
+
+fn sign_siwe(args: SiweArgs, backend: Backend, json: bool) {
+    let mut message =
+        siwe::Message::new(&args.domain, args.smart_account, &args.uri, args.chain_id);
+    message.statement = args.statement;
+    message.resources = args.resources;
+    if let Some(nonce) = args.nonce {
+        message.nonce = nonce;
+    }
+    if let Some(secs) = args.expires_in {
+        message.expiration_time = Some(siwe::timestamp(attestation::now() + secs));
+    }
+    // Render and re-parse so a bad field fails here, not at the dapp.
+    let text = message.to_string();
+    output::or_fail(json, "invalid sign-in", text.parse::<siwe::Message>());
+
+    let seed = output::or_fail(json, "failed to load signing key", args.signer.seed());
+    let pk = backend.public_key(&seed);
+    let signed_hash = message.signed_hash(args.module, args.caller);
+    let sig = output::or_fail(
+        json,
+        "failed to sign",
+        MessageInput::Hash(signed_hash.0).sign_on(backend, &seed),
+    );
+
+    let record = Record {
+        tool: "pq-sign".to_string(),
+        account: args.signer.account.clone(),
+        key_id: keys::address(&pk),
+        digest: Some(signed_hash),
+        chain_id: Some(args.chain_id),
+        signature: keccak256(&sig),
+        summary: vec![format!(
+            "sign in to {} as {} (nonce {})",
+            message.domain, message.address, message.nonce
+        )],
+        backend: backend.to_string(),
+    };
+    output::or_fail(
+        json,
+        "signature withheld",
+        AuditLog::open_default().and_then(|log| log.append(record)),
+    );
+    std::fs::write(&args.output, &sig).expect("failed to write signature");
+
+    if json {
+        let mut out = SignOutput::ml_dsa_65(&sig);
+        out.message = Some(text);
+        output::emit(&out);
+        return;
+    }
+    println!("{text}");
+    eprintln!(
+        "Signature written to {} ({} bytes)",
+        args.output.display(),
+        sig.len()
+    );
+}
@@ -0,0 +1,288 @@
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use alloy_primitives::Address;
+use clap::Parser;
+use pq_cli::attestation;
+use pq_cli::audit::AuditLog;
+use pq_cli::auth::AuthConfig;
+use pq_cli::backend::Backend;
+use pq_cli::cli;
+use pq_cli::daemon::Daemon;
+use pq_cli::error::Result;
+use pq_cli::grpc;
+use pq_cli::keystore::Keystore;
+use pq_cli::notify::Notifier;
+use pq_cli::output::{self, OutputArgs};
+use pq_cli::policy::{Policy, SpendLedger};
+use pq_cli::proposal::ProposalStore;
+use pq_cli::revocation::RevocationList;
+use pq_cli::robot::Certificate;
+use pq_cli::rpc::HttpTransport;
+use pq_cli::validity;
+
+#[derive(Parser)]
+#[command(about = "Serve keystore accounts to remote signers over gRPC")]
+struct Args {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:50051")]
+    listen: SocketAddr,
+
+    /// Keystore account to unlock and serve; repeatable
+    #[arg(long = "account", required = true)]
+    accounts: Vec<String>,
+
+    /// Daemon config (TOML) naming the principals that may sign: their
+    /// token or client-certificate hashes, accounts and rate limits. Without
+    /// it, anyone who can connect may use every account
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Signing policy (TOML or JSON) every UserOperation must pass
+    #[arg(long)]
+    policy: Option<PathBuf>,
+
+    /// Daily spend ledger for --policy [default: ~/.pq-wallet/policy-spend.json]
+    #[arg(long, requires = "policy")]
+    policy_ledger: Option<PathBuf>,
+
+    /// Proposal store, for a policy with [dual_control]
+    /// [default: ~/.pq-wallet/proposals.json]
+    #[arg(long, requires = "policy")]
+    proposals: Option<PathBuf>,
+
+    /// Delegation certificate from `pq-delegate issue` for one of the
+    /// accounts, a robot key; its ops are signed under it. Repeatable
+    #[arg(long = "delegation", value_name = "CERT")]
+    delegations: Vec<PathBuf>,
+
+    /// Node JSON-RPC URL to read --module's revocations from; delegated ops
+    /// are refused once their certificate is revoked on chain
+    #[arg(long, requires_all = ["delegations", "module"])]
+    revocation_rpc: Option<String>,
+
+    /// The PQValidatorModule the delegated accounts use
+    #[arg(long, requires = "revocation_rpc")]
+    module: Option<Address>,
+
+    /// Refuse delegated ops while the revocation list has not been
+    /// refreshed for this long
+    #[arg(long, value_parser = validity::parse_duration, default_value = "10m")]
+    revocation_max_age: Duration,
+
+    /// Revocation list cache [default: ~/.pq-wallet/revocations.json]
+    #[arg(long, requires = "revocation_rpc")]
+    revocation_cache: Option<PathBuf>,
+
+    /// Notification sinks (TOML or JSON): webhooks and commands that get
+    /// each signature and policy denial
+    #[arg(long)]
+    notify: Option<PathBuf>,
+
+    /// Audit log [default: $PQ_WALLET_AUDIT_LOG, else ~/.pq-wallet/audit.jsonl]
+    #[arg(long)]
+    audit_log: Option<PathBuf>,
+
+    /// Server certificate chain (PEM); enables TLS
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// Private key for --tls-cert (PEM)
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// CA (PEM) that client certificates must chain to (mTLS)
+    #[arg(long, requires = "tls_cert")]
+    client_ca: Option<PathBuf>,
+
+    /// Signatures computed at once, per account
+    #[arg(long, default_value_t = 4)]
+    max_concurrent: usize,
+
+    /// Allow listening on a non-loopback address without TLS
+    #[arg(long)]
+    insecure: bool,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+fn read(json: bool, path: &Path) -> Vec<u8> {
+    std::fs::read(path).unwrap_or_else(|e| output::fail(json, format!("{}: {e}", path.display())))
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+    if args.tls_cert.is_none() && !args.listen.ip().is_loopback() && !args.insecure {
+        output::fail(
+            json,
+            format!(
+                "refusing to serve keys on {} without TLS (pass --tls-cert, or --insecure)",
+                args.listen
+            ),
+        );
+    }
+    let tls = args.tls_cert.as_ref().map(|cert| {
+        let key = args.tls_key.as_ref().expect("clap requires --tls-key");
+        let ca = args.client_ca.as_ref().map(|ca| read(json, ca));
+        grpc::tls_config(&read(json, cert), &read(json, key), ca.as_deref())
+    });
+
+    let audit = match args.audit_log {
+        Some(path) => AuditLog::open(path),
+        None => output::or_fail(json, "audit log", AuditLog::open_default()),
+    };
+    let mut daemon = Daemon::new(audit, Backend::RustCrypto);
+    if let Some(path) = &args.config {
+        let config = output::or_fail(json, "daemon config", AuthConfig::load(path));
+        let by_cert = config
+            .principals
+            .iter()
+            .find(|p| p.client_cert_sha256.is_some());
+        if let (Some(p), None) = (by_cert, &args.client_ca) {
+            output::fail(
+                json,
+                format!(
+                    "principal {} is identified by client certificate, which needs --client-ca",
+                    p.name
+                ),
+            );
+        }
+        daemon = daemon.with_auth(&config);
+    }
+    if let Some(path) = &args.policy {
+        let policy = output::or_fail(json, "failed to load policy", Policy::load(path));
+        let ledger = args
+            .policy_ledger
+            .clone()
+            .unwrap_or_else(|| output::or_fail(json, "policy ledger", SpendLedger::default_path()));
+        let dual_control = policy.dual_control.is_some();
+        daemon = output::or_fail(json, "policy ledger", daemon.with_policy(policy, ledger));
+        if dual_control {
+            let store = args.proposals.clone().unwrap_or_else(|| {
+                output::or_fail(json, "proposals", ProposalStore::default_path())
+            });
+            daemon = output::or_fail(json, "proposals", daemon.with_proposals(store));
+        }
+    }
+    if let Some(path) = &args.notify {
+        let notifier = output::or_fail(json, "notifications", Notifier::load(path));
+        daemon = daemon.with_notifier(notifier);
+    }
+    let keystore = output::or_fail(json, "keystore", Keystore::open_default());
+    for name in &args.accounts {
+        let account = output::or_fail(json, "keystore", keystore.load(name));
+        let seed = output::or_fail(json, name, cli::unlock_account(&account));
+        output::or_fail(
+            json,
+            name,
+            daemon.add_account(&account, seed, args.max_concurrent),
+        );
+    }
+    let mut delegated = BTreeSet::new();
+    for path in &args.delegations {
+        let cert = output::or_fail(
+            json,
+            "delegation",
+            Certificate::from_json(&read(json, path)),
+        );
+        delegated.insert(cert.account);
+        output::or_fail(json, "delegation", daemon.add_delegation(cert));
+    }
+    let mut refresher = None;
+    if let Some(url) = &args.revocation_rpc {
+        let module = args.module.expect("clap requires --module");
+        let cache = args.revocation_cache.clone().unwrap_or_else(|| {
+            output::or_fail(json, "revocation list", RevocationList::default_path())
+        });
+        let mut list = output::or_fail(
+            json,
+            "revocation list",
+            RevocationList::load_or_new(&cache, module, delegated),
+        );
+        let node = Arc::new(HttpTransport::new(url.clone(), Duration::from_secs(30)));
+        // With the node down at startup the cached list stands in, until it
+        // goes stale.
+        if let Err(e) = refresh(&mut list, &node, &cache) {
+            tracing::warn!(error = %e, "revocation list refresh failed");
+        }
+        daemon = daemon.with_revocations(list, args.revocation_max_age);
+        refresher = Some((node, cache));
+    }
+
+    let listener = tokio::net::TcpListener::bind(args.listen)
+        .await
+        .unwrap_or_else(|e| output::fail(json, format!("listen on {}: {e}", args.listen)));
+    let addr = listener.local_addr().unwrap_or(args.listen);
+    if json {
+        output::emit(&serde_json::json!({
+            "listening": addr.to_string(),
+            "tls": args.tls_cert.is_some(),
+            "mtls": args.client_ca.is_some(),
+            "authenticated": args.config.is_some(),
+            "accounts": args.accounts,
+        }));
+    } else {
+        eprintln!(
+            "pq-signerd: serving {} on {addr}{}",
+            args.accounts.join(", "),
+            match (&args.tls_cert, &args.client_ca) {
+                (Some(_), Some(_)) => " (mTLS)",
+                (Some(_), None) => " (TLS)",
+                (None, _) => "",
+            }
+        );
+    }
+    let shutdown = async {
+        tokio::signal::ctrl_c().await.ok();
+        tracing::info!("shutting down");
+    };
+    let daemon = Arc::new(daemon);
+    if let Some((node, cache)) = refresher {
+        let every = (args.revocation_max_age / 4).max(Duration::from_secs(1));
+        tokio::spawn(keep_fresh(daemon.clone(), node, cache, every));
+    }
+    output::or_fail(
+        json,
+        "pq-signerd",
+        grpc::serve(daemon, listener, tls, shutdown).await,
+    );
+}
+
+fn refresh(list: &mut RevocationList, node: &HttpTransport, cache: &Path) -> Result<()> {
+    list.refresh(node, attestation::now())?;
+    list.save(cache)
+}
+
+/// Refresh the daemon's revocation list every `every`. Failures are logged
+/// and retried; meanwhile the daemon refuses delegated ops once the list is
+/// stale.
+async fn keep_fresh(
+    daemon: Arc<Daemon>,
+    node: Arc<HttpTransport>,
+    cache: PathBuf,
+    every: Duration,
+) {
+    loop {
+        tokio::time::sleep(every).await;
+        let Some(mut list) = daemon.revocations() else {
+            return;
+        };
+        let node = node.clone();
+        let cache = cache.clone();
+        let refreshed =
+            tokio::task::spawn_blocking(move || refresh(&mut list, &node, &cache).map(|()| list))
+                .await;
+        match refreshed {
+            Ok(Ok(list)) => daemon.update_revocations(list),
+            Ok(Err(e)) => tracing::warn!(error = %e, "revocation list refresh failed"),
+            Err(e) => tracing::warn!(error = %e, "revocation list refresh panicked"),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::path::PathBuf;
+
+use alloy_primitives::{B256, keccak256};
+use clap::{Parser, Subcommand};
+use pq_cli::audit::{AuditLog, Record};
+use pq_cli::keys;
+use pq_cli::output::{self, OutputArgs, SignOutput};
+use pq_cli::threshold::{self, DEFAULT_ATTEMPTS, GroupKey, Partial, Reveal, Session};
+
+#[derive(Parser)]
+#[command(about = "Coordinate experimental threshold ML-DSA-65 signing sessions")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Trusted-dealer setup: write group.json and share-N.json for each
+    /// party. Run offline and hand each share to its party only
+    Deal {
+        #[arg(long, default_value_t = 2)]
+        threshold: u8,
+
+        #[arg(long, default_value_t = 3)]
+        parties: u8,
+
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Open a session for the signers to commit to
+    Start {
+        #[arg(long)]
+        group: PathBuf,
+
+        /// 32-byte hash to sign (e.g. a userOpHash)
+        #[arg(long)]
+        hash: B256,
+
+        /// Participating parties, e.g. 1,3
+        #[arg(long, value_delimiter = ',', required = true)]
+        signers: Vec<u8>,
+
+        /// Parallel attempts, so rejection sampling rarely forces a restart
+        #[arg(long, default_value_t = DEFAULT_ATTEMPTS)]
+        attempts: u16,
+
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Combine the signers' partial signatures into a standard signature
+    Combine {
+        #[arg(long)]
+        group: PathBuf,
+
+        #[arg(long)]
+        session: PathBuf,
+
+        /// Each signer's round 2 reveal (repeat per signer)
+        #[arg(long = "reveal", required = true)]
+        reveals: Vec<PathBuf>,
+
+        /// Each signer's round 3 partial (repeat per signer)
+        #[arg(long = "partial", required = true)]
+        partials: Vec<PathBuf>,
+
+        /// Where to write the 3309-byte signature
+        #[arg(long)]
+        output: PathBuf,
+    },
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    match args.command {
+        Command::Deal {
+            threshold,
+            parties,
+            output: dir,
+        } => {
+            let (group, shares) = output::or_fail(
+                json,
+                "failed to deal",
+                threshold::deal(threshold, parties, &mut rand::rng()),
+            );
+            std::fs::create_dir_all(&dir).expect("failed to create output directory");
+            output::or_fail(
+                json,
+                "failed to write group key",
+                threshold::save(&dir.join("group.json"), &group, false),
+            );
+            for share in &shares {
+                let path = dir.join(format!("share-{}.json", share.party));
+                output::or_fail(
+                    json,
+                    "failed to write share",
+                    threshold::save(&path, share, true),
+                );
+            }
+            let pk = output::or_fail(json, "group key", group.public_key());
+            if json {
+                output::emit(&serde_json::json!({
+                    "address": keys::address(&pk).to_string(),
+                    "fingerprint": group.fingerprint(),
+                    "threshold": threshold,
+                    "parties": parties,
+                    "path": dir.display().to_string(),
+                }));
+            } else {
+                println!("Group:       {threshold}-of-{parties}");
+                println!("Key ID:      {}", keys::address(&pk));
+                println!("Fingerprint: {}", group.fingerprint());
+                println!("Shares:      {}/share-N.json", dir.display());
+            }
+        }
+        Command::Start {
+            group,
+            hash,
+            signers,
+            attempts,
+            output: out_path,
+        } => {
+            let group: GroupKey = output::or_fail(json, "group key", threshold::load(&group));
+            let session = output::or_fail(
+                json,
+                "invalid session",
+                Session::new(&group, hash.as_slice(), signers, attempts),
+            );
+            output::or_fail(
+                json,
+                "failed to write session",
+                threshold::save(&out_path, &session, false),
+            );
+            if json {
+                output::emit(&session);
+            } else {
+                println!("Session {} written to {}", session.id, out_path.display());
+            }
+        }
+        Command::Combine {
+            group,
+            session,
+            reveals,
+            partials,
+            output: out_path,
+        } => {
+            let group: GroupKey = output::or_fail(json, "group key", threshold::load(&group));
+            let session: Session = output::or_fail(json, "session", threshold::load(&session));
+            let reveals: Vec<Reveal> = reveals
+                .iter()
+                .map(|p| output::or_fail(json, "reveal", threshold::load(p)))
+                .collect();
+            let partials: Vec<Partial> = partials
+                .iter()
+                .map(|p| output::or_fail(json, "partial", threshold::load(p)))
+                .collect();
+            #[cfg(feature = "metrics")]
+            let started = std::time::Instant::now();
+            let sig = output::or_fail(
+                json,
+                "failed to combine",
+                threshold::combine(&group, &session, &reveals, &partials),
+            );
+            #[cfg(feature = "metrics")]
+            pq_cli::metrics::signature("pq-threshold-coordinator combine", started.elapsed());
+            let record = Record {
+                tool: "pq-threshold-coordinator combine".to_string(),
+                account: None,
+                key_id: keys::address(&group.public_key),
+                digest: B256::try_from(session.message.as_ref()).ok(),
+                chain_id: None,
+                signature: keccak256(sig),
+                summary: vec![format!(
+                    "{}-of-{} threshold signature by parties {:?}",
+                    group.threshold, group.parties, session.signers
+                )],
+                backend: "threshold".to_string(),
+            };
+            output::or_fail(
+                json,
+                "signature withheld",
+                AuditLog::open_default().and_then(|log| log.append(record)),
+            );
+            std::fs::write(&out_path, sig).expect("failed to write signature");
+            if json {
+                output::emit(&SignOutput::ml_dsa_65(&sig));
+            } else {
+                println!("Signature written to {} (3309 bytes)", out_path.display());
+            }
+        }
+    }
+}
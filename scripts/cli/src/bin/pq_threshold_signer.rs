@@ -0,0 +1,155 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use pq_cli::output::{self, OutputArgs};
+use pq_cli::threshold::{self, Commitment, KeyShare, Reveal, Session, SignerState};
+
+#[derive(Parser)]
+#[command(about = "Take part in experimental threshold ML-DSA-65 signing with one key share")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Round 1: sample nonces for a session and commit to them
+    Commit {
+        #[arg(long)]
+        share: PathBuf,
+
+        #[arg(long)]
+        session: PathBuf,
+
+        /// Secret nonce state kept until `respond`
+        #[arg(long)]
+        state: PathBuf,
+
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Round 2: reveal once every signer's commitment is in
+    Reveal {
+        #[arg(long)]
+        state: PathBuf,
+
+        /// Every signer's commitment, this one's included (repeat per signer)
+        #[arg(long = "commitment", required = true)]
+        commitments: Vec<PathBuf>,
+
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Round 3: check the reveals and answer with partial signatures; the
+    /// state file is deleted so its nonces are never reused
+    Respond {
+        #[arg(long)]
+        share: PathBuf,
+
+        #[arg(long)]
+        state: PathBuf,
+
+        /// Every signer's reveal (repeat per signer)
+        #[arg(long = "reveal", required = true)]
+        reveals: Vec<PathBuf>,
+
+        #[arg(long)]
+        output: PathBuf,
+    },
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+    let done = |what: &str, path: &PathBuf| {
+        if json {
+            output::emit(&serde_json::json!({ "path": path.display().to_string() }));
+        } else {
+            println!("{what} written to {}", path.display());
+        }
+    };
+
+    match args.command {
+        Command::Commit {
+            share,
+            session,
+            state,
+            output: out_path,
+        } => {
+            let share: KeyShare = output::or_fail(json, "key share", threshold::load(&share));
+            let session: Session = output::or_fail(json, "session", threshold::load(&session));
+            let (signer_state, commitment) = output::or_fail(
+                json,
+                "failed to commit",
+                share.commit(&session, &mut rand::rng()),
+            );
+            output::or_fail(
+                json,
+                "failed to write state",
+                threshold::save(&state, &signer_state, true),
+            );
+            output::or_fail(
+                json,
+                "failed to write commitment",
+                threshold::save(&out_path, &commitment, false),
+            );
+            done("Commitment", &out_path);
+        }
+        Command::Reveal {
+            state,
+            commitments,
+            output: out_path,
+        } => {
+            let mut signer_state: SignerState =
+                output::or_fail(json, "signer state", threshold::load(&state));
+            let commitments: Vec<Commitment> = commitments
+                .iter()
+                .map(|p| output::or_fail(json, "commitment", threshold::load(p)))
+                .collect();
+            let reveal =
+                output::or_fail(json, "failed to reveal", signer_state.reveal(&commitments));
+            output::or_fail(
+                json,
+                "failed to write state",
+                threshold::save(&state, &signer_state, true),
+            );
+            output::or_fail(
+                json,
+                "failed to write reveal",
+                threshold::save(&out_path, &reveal, false),
+            );
+            done("Reveal", &out_path);
+        }
+        Command::Respond {
+            share,
+            state,
+            reveals,
+            output: out_path,
+        } => {
+            let share: KeyShare = output::or_fail(json, "key share", threshold::load(&share));
+            let signer_state: SignerState =
+                output::or_fail(json, "signer state", threshold::load(&state));
+            let reveals: Vec<Reveal> = reveals
+                .iter()
+                .map(|p| output::or_fail(json, "reveal", threshold::load(p)))
+                .collect();
+            // Burn the nonces before anything derived from them leaves.
+            std::fs::remove_file(&state).expect("failed to delete signer state");
+            let partial = output::or_fail(
+                json,
+                "failed to respond",
+                signer_state.respond(&share, &reveals),
+            );
+            output::or_fail(
+                json,
+                "failed to write partial",
+                threshold::save(&out_path, &partial, false),
+            );
+            done("Partial signature", &out_path);
+        }
+    }
+}
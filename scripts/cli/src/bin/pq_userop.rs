@@ -0,0 +1,2480 @@
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use alloy_primitives::{Address, B256, Bytes, U256, keccak256};
+use clap::{Parser, Subcommand};
+use ml_dsa::signature::Signer;
+use ml_dsa::{MlDsa65, SigningKey};
+use pq_cli::WalletError;
+use pq_cli::aa_errors;
+use pq_cli::assets::{self, AssetChanges};
+use pq_cli::attestation::{self, SignResponse};
+use pq_cli::audit::{AuditLog, Record};
+use pq_cli::backend::Backend;
+use pq_cli::balance;
+use pq_cli::bundler::{self, State, UserOpStatus};
+use pq_cli::calldata::Call;
+use pq_cli::check::{self, Snapshot};
+use pq_cli::cli::{self, FeeArgs, PublicKeyArgs, SigningKeyArgs};
+use pq_cli::contacts::{AddressBook, Names, Target};
+use pq_cli::ct;
+use pq_cli::decode;
+use pq_cli::delegation;
+use pq_cli::envelope::ENVELOPE_V2;
+use pq_cli::erc7562;
+use pq_cli::failover::Failover;
+use pq_cli::finality::{Confirmation, Finality, Tracker};
+use pq_cli::gas::l1_fee::{ArbitrumL1Fee, OpStackL1Fee};
+use pq_cli::gas::pre_verification::{self, Calibration, L1DataFee, Rollup, StaticL1Fee};
+use pq_cli::gas::{breakdown, cost};
+use pq_cli::keys::{self, SIGNATURE_LEN};
+use pq_cli::keystore::Keystore;
+use pq_cli::notify::{Event, Notification, Notifier};
+use pq_cli::output::{self, OutputArgs};
+use pq_cli::paymaster::{self, TokenFee};
+use pq_cli::plan::{OpOutcome, Plan, PlannedCall};
+use pq_cli::policy::{Policy, SpendLedger};
+use pq_cli::precompile;
+use pq_cli::profile::{self, Validator};
+#[cfg(feature = "queue")]
+use pq_cli::queue::{self, Entry, Queue};
+use pq_cli::review::Review;
+use pq_cli::rpc::HttpTransport;
+use pq_cli::rpc::Transport;
+use pq_cli::self_bundle;
+use pq_cli::sig_transport::{self, SigTransport};
+use pq_cli::signing_scheme::SigningScheme;
+use pq_cli::simulate::{self, Simulation};
+use pq_cli::userop::{self, ENTRY_POINT_V07, PackedUserOperation};
+use pq_cli::validity::{self, ValidityWindow};
+use pq_cli::webauthn::{self, Assertion};
+use rayon::prelude::*;
+use zeroize::Zeroizing;
+
+#[derive(Parser)]
+#[command(about = "Hash, sign, estimate and track ERC-4337 v0.7 UserOperations")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(clap::Args)]
+struct OpArgs {
+    /// UserOperation JSON file (eth_sendUserOperation params[0]); `-` for stdin
+    #[arg(long)]
+    file: PathBuf,
+
+    /// EntryPoint address
+    #[arg(long, default_value_t = ENTRY_POINT_V07)]
+    entry_point: Address,
+
+    /// Chain ID the EntryPoint is deployed on
+    #[arg(long)]
+    chain_id: u64,
+}
+
+/// How `batch` and `send` deliver the ops they sign.
+#[derive(clap::Args)]
+struct Delivery {
+    /// Bundler JSON-RPC URL: estimates gas and receives the ops; several,
+    /// comma-separated, are checked and then failed over in order
+    #[arg(long, env = "BUNDLER_RPC")]
+    bundler: Option<String>,
+
+    /// Node JSON-RPC URL, for nonces and gas fees
+    #[arg(long, env = "LOCAL_RPC")]
+    rpc: String,
+
+    #[command(flatten)]
+    fees: FeeArgs,
+
+    /// Write one handleOps(ops, beneficiary) call (JSON) here instead of
+    /// sending the ops to the bundler
+    #[arg(long, requires = "beneficiary")]
+    handle_ops: Option<PathBuf>,
+
+    /// Address the EntryPoint pays for --handle-ops
+    #[arg(long)]
+    beneficiary: Option<Address>,
+
+    /// Write the signed UserOperations (JSON array) here
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    /// Stop tracking after this many seconds (0 = check once)
+    #[arg(long, default_value_t = 120)]
+    timeout: u64,
+
+    /// Seconds between polls
+    #[arg(long, default_value_t = 1.0)]
+    interval: f64,
+
+    /// Ethereum mainnet or Sepolia JSON-RPC URL: resolves an ENS --to
+    /// (--rpc when omitted) and adds verified ENS names to the previews
+    #[arg(long, env = "ENS_RPC")]
+    ens_rpc: Option<String>,
+
+    /// Paymaster service JSON-RPC URL, for --fee-token
+    #[arg(long, env = "PAYMASTER_RPC")]
+    paymaster_service: Option<String>,
+
+    /// Pay gas in this ERC-20 through the service's token paymaster,
+    /// approving the paymaster for the fee in the op when needed
+    #[arg(long, requires = "paymaster_service")]
+    fee_token: Option<Address>,
+
+    /// Sign even when the calls spend more native value or ERC-20 balance
+    /// (or allowance) than the account has, or than the token fee needs
+    #[arg(long)]
+    skip_balance_check: bool,
+
+    /// Sign without asking after the calldata previews (for automation)
+    #[arg(long, short = 'y')]
+    yes: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the userOpHash
+    Hash {
+        #[command(flatten)]
+        op: OpArgs,
+
+        /// Also ask the EntryPoint on --rpc for getUserOpHash and fail
+        /// unless it matches
+        #[arg(long, requires = "rpc")]
+        verify_onchain: bool,
+
+        /// Node JSON-RPC URL used for --verify-onchain
+        #[arg(long, env = "LOCAL_RPC")]
+        rpc: Option<String>,
+    },
+    /// Validate the op offline: field ranges, nonce, signature length, gas
+    /// limits and, with --state, deployment and prefund
+    Check {
+        #[command(flatten)]
+        op: OpArgs,
+
+        /// On-chain verifier the account uses (see `sign`)
+        #[arg(long)]
+        verifier: Option<String>,
+
+        /// JSON snapshot of known account state: address -> {code, balance,
+        /// deposit, nonce}
+        #[arg(long)]
+        state: Option<PathBuf>,
+    },
+    /// Sign the userOpHash and fill in `signature`
+    Sign {
+        #[command(flatten)]
+        op: OpArgs,
+
+        #[command(flatten)]
+        signer: SigningKeyArgs,
+
+        /// Write the signed UserOperation here instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+
+        /// On-chain verifier the account uses, which decides the signature
+        /// layout: contract:ADDR (bare signature, the default),
+        /// precompile:ADDR (pk || sig) or precompile:ADDR:abi
+        #[arg(long)]
+        verifier: Option<String>,
+
+        /// How the signature reaches the validator: inline, chunked[:SIZE]
+        /// (pre-published storeChunk calls) or blob
+        #[arg(long, default_value_t = SigTransport::Inline)]
+        sig_transport: SigTransport,
+
+        /// SignatureStore contract the chunked transport writes to
+        #[arg(long)]
+        chunk_store: Option<Address>,
+
+        /// Where to write the storeChunk calls (JSON) or blob (hex) that must
+        /// be published before the op
+        #[arg(long)]
+        transport_out: Option<PathBuf>,
+
+        /// WebAuthn assertion (PublicKeyCredential JSON) over the
+        /// userOpHash, for accounts that also require a passkey signature
+        #[arg(long)]
+        passkey_assertion: Option<PathBuf>,
+
+        /// The passkey's P-256 public key (SEC1 hex), to verify the
+        /// assertion before packing it
+        #[arg(long, requires = "passkey_assertion")]
+        passkey_pubkey: Option<String>,
+
+        /// Require the user-verified flag on the assertion
+        #[arg(long, requires = "passkey_assertion")]
+        require_uv: bool,
+
+        /// Co-sign the userOpHash with the ECDSA key at this BIP-32 path on
+        /// a Ledger (m/44'/60'/0'/0/0 when no path is given), for hybrid
+        /// validators (needs the ledger feature)
+        #[arg(
+            long,
+            value_name = "PATH",
+            num_args = 0..=1,
+            default_missing_value = "m/44'/60'/0'/0/0",
+            conflicts_with = "passkey_assertion"
+        )]
+        ledger: Option<String>,
+
+        /// Signing policy (TOML or JSON) the op must pass before it is signed
+        #[arg(long)]
+        policy: Option<PathBuf>,
+
+        /// Daily spend ledger for --policy [default: ~/.pq-wallet/policy-spend.json]
+        #[arg(long, requires = "policy")]
+        policy_ledger: Option<PathBuf>,
+
+        /// The op has been approved by a human, for policies with
+        /// `approval_above`
+        #[arg(long, requires = "policy")]
+        approved: bool,
+
+        /// What the account's validator signs over: hash (the userOpHash,
+        /// the default), prehash-sha256 / prehash-sha512 (HashML-DSA over
+        /// the serialized op) or raw (the serialized op itself); defaults to
+        /// the --account's setting for --chain-id
+        #[arg(long)]
+        signing_scheme: Option<SigningScheme>,
+
+        /// Make the signature expire: sign the userOpHash bound to the chain
+        /// ID and a validity window starting now (e.g. 90s, 15m, 1h, 7d),
+        /// and prefix the signature with the window
+        #[arg(long, value_parser = validity::parse_duration)]
+        valid_for: Option<Duration>,
+
+        /// Time-lock the signature: its window opens this long from now
+        /// (e.g. 24h), and lasts --valid-for, or for good without it
+        #[arg(long, value_parser = validity::parse_duration)]
+        valid_after: Option<Duration>,
+
+        /// Node JSON-RPC URL to simulate the op's calls on, so the preview
+        /// shows the ETH and tokens they move
+        #[arg(long, env = "LOCAL_RPC")]
+        rpc: Option<String>,
+
+        /// Sign without asking after the calldata preview (for automation)
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Fill in `signature` from a remote signer's `pq-sign --json` response,
+    /// after checking the signature and the host's attestation
+    Attach {
+        #[command(flatten)]
+        op: OpArgs,
+
+        /// `pq-sign --json` output for the userOpHash
+        #[arg(long)]
+        response: PathBuf,
+
+        /// The signing key the response should come from
+        #[command(flatten)]
+        signer: PublicKeyArgs,
+
+        /// Fingerprint of a signer host key to accept attestations from
+        /// (repeatable)
+        #[arg(long = "trusted-host", value_name = "FINGERPRINT")]
+        trusted_hosts: Vec<B256>,
+
+        /// Accept a response that carries no attestation
+        #[arg(long)]
+        allow_unattested: bool,
+
+        /// On-chain verifier the account uses (see `sign`)
+        #[arg(long)]
+        verifier: Option<String>,
+
+        /// Write the signed UserOperation here instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Estimate preVerificationGas, asking the bundler first and falling
+    /// back to a local calculation
+    Estimate {
+        #[command(flatten)]
+        op: OpArgs,
+
+        /// Bundler JSON-RPC URL (or several, comma-separated); without it
+        /// the estimate is local only
+        #[arg(long, env = "BUNDLER_RPC")]
+        bundler: Option<String>,
+
+        /// Node JSON-RPC URL: quotes gas fees into the op and queries the
+        /// rollup's L1 data fee
+        #[arg(long, env = "LOCAL_RPC")]
+        rpc: Option<String>,
+
+        #[command(flatten)]
+        fees: FeeArgs,
+
+        /// Ops expected to share the bundle transaction
+        #[arg(long, default_value_t = 1)]
+        bundle_size: u64,
+
+        /// L1 base fee in wei, to include the L1 data fee on rollups
+        #[arg(long, requires = "l2_gas_price")]
+        l1_base_fee: Option<u128>,
+
+        /// L2 gas price in wei, to convert the L1 data fee into L2 gas
+        #[arg(long, requires = "l1_base_fee")]
+        l2_gas_price: Option<u128>,
+
+        /// ETH price in USD, to print costs in dollars
+        #[arg(long, env = "ETH_USD")]
+        eth_usd: Option<f64>,
+
+        /// Write the UserOperation with preVerificationGas (and, with
+        /// --rpc, gas fees) filled in
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Break the op's calldata down by field, byte counts and gas, and
+    /// suggest ways to make it smaller
+    Analyze {
+        #[command(flatten)]
+        op: OpArgs,
+
+        /// Ops expected to share the bundle transaction
+        #[arg(long, default_value_t = 1)]
+        bundle_size: u64,
+    },
+    /// Trace the op's validation on a node and report the ERC-7562 rules
+    /// it breaks (exit code 1 if any)
+    Rules {
+        #[command(flatten)]
+        op: OpArgs,
+
+        /// Node JSON-RPC URL; needs debug_traceCall with JS tracers
+        #[arg(long, env = "LOCAL_RPC")]
+        rpc: String,
+
+        /// Hex file with EntryPointSimulations deployed bytecode; traces
+        /// simulateValidation instead of handleOps
+        #[arg(long)]
+        simulations_code: Option<PathBuf>,
+    },
+    /// Send a signed UserOperation to a bundler, optionally simulating it
+    /// against a node first
+    Submit {
+        /// UserOperation JSON file (eth_sendUserOperation params[0]); `-` for stdin
+        #[arg(long)]
+        #[cfg_attr(feature = "queue", arg(required_unless_present = "all"))]
+        #[cfg_attr(not(feature = "queue"), arg(required = true))]
+        file: Option<PathBuf>,
+
+        /// EntryPoint address
+        #[arg(long, default_value_t = ENTRY_POINT_V07)]
+        entry_point: Address,
+
+        /// Chain ID the EntryPoint is deployed on; with --all, only this
+        /// chain's queued ops are sent [default: the bundler's]
+        #[arg(long)]
+        #[cfg_attr(feature = "queue", arg(required_unless_present = "all"))]
+        #[cfg_attr(not(feature = "queue"), arg(required = true))]
+        chain_id: Option<u64>,
+
+        /// Bundler JSON-RPC URL, or several comma-separated to fail over
+        /// along
+        #[arg(long, env = "BUNDLER_RPC", required_unless_present = "self_bundle")]
+        bundler: Option<String>,
+
+        /// Be the bundler: simulate the op, then send it in a handleOps
+        /// transaction signed with --ecdsa-key to --rpc, and wait for it
+        #[arg(long, requires_all = ["rpc", "ecdsa_key"], conflicts_with = "aggregator")]
+        #[cfg_attr(feature = "queue", arg(conflicts_with = "all"))]
+        self_bundle: bool,
+
+        /// With --self-bundle, file holding the hex secp256k1 key of the
+        /// funded EOA (or EIP-7702-delegated EOA) that sends the transaction
+        #[arg(long, env = "ECDSA_KEY_FILE", requires = "self_bundle")]
+        ecdsa_key: Option<PathBuf>,
+
+        /// With --self-bundle, who the EntryPoint pays the op's gas to
+        /// [default: the --ecdsa-key EOA]
+        #[arg(long, requires = "self_bundle")]
+        beneficiary: Option<Address>,
+
+        /// With --self-bundle, give up waiting for the transaction after
+        /// this many seconds
+        #[arg(long, default_value_t = 120)]
+        timeout: u64,
+
+        /// eth_call the op through the EntryPoint first and stop on a revert
+        #[arg(long, requires = "rpc")]
+        simulate: bool,
+
+        /// Node JSON-RPC URL used for --simulate; with --all, checks queued
+        /// nonces against the chain and quotes fees for re-signing
+        #[arg(long, env = "LOCAL_RPC")]
+        rpc: Option<String>,
+
+        /// Hex file with EntryPointSimulations deployed bytecode; switches
+        /// --simulate from handleOps to simulateValidation via state override
+        #[arg(long, requires = "simulate")]
+        simulations_code: Option<PathBuf>,
+
+        /// IAggregator that validates the op's signature; passed to the
+        /// bundler so it can batch the op with others under it
+        #[arg(long)]
+        aggregator: Option<Address>,
+
+        /// Work through the queue instead: send pending ops, check
+        /// submitted ones, and re-sign ops whose fees the bundler refuses
+        #[cfg(feature = "queue")]
+        #[arg(long, conflicts_with_all = ["file", "simulate"])]
+        all: bool,
+
+        /// With --all, fee quote used when re-signing
+        #[cfg(feature = "queue")]
+        #[command(flatten)]
+        fees: FeeArgs,
+
+        /// With --all, keep included ops submitted until final on --rpc
+        /// (confirmations, safe or finalized), and send reorged ones again
+        #[cfg(feature = "queue")]
+        #[arg(long)]
+        finality: Option<Finality>,
+    },
+    /// Add a signed UserOperation to the queue for `submit --all`
+    #[cfg(feature = "queue")]
+    Enqueue {
+        #[command(flatten)]
+        op: OpArgs,
+
+        /// Keystore account that signed the op, so `submit --all` can
+        /// re-sign it with higher fees
+        #[arg(long)]
+        account: Option<String>,
+
+        /// Hold the op until this time (unix seconds) [default: the start
+        /// of the signature's --valid-after window, when the field is a bare
+        /// windowed signature]
+        #[arg(long)]
+        not_before: Option<u64>,
+    },
+    /// Replace a queued op that is stuck on its fees: raise them, re-sign
+    /// with the same nonce and send the replacement to the bundler
+    #[cfg(feature = "queue")]
+    Bump {
+        /// userOpHash of the queued op
+        #[arg(long)]
+        hash: B256,
+
+        /// Factor to scale both fees by; each rises by at least the
+        /// bundlers' 10% replacement minimum
+        #[arg(long, default_value_t = 1.2)]
+        multiplier: f64,
+
+        /// Bundler JSON-RPC URL, or several comma-separated to fail over
+        /// along
+        #[arg(long, env = "BUNDLER_RPC")]
+        bundler: String,
+
+        /// Re-sign without asking (for automation)
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// List queued UserOperations
+    #[cfg(feature = "queue")]
+    Queue {
+        /// Only entries with this status (repeatable): pending, submitted,
+        /// included or failed
+        #[arg(long)]
+        status: Vec<queue::Status>,
+    },
+    /// Build and sign one op per account from a plan, send them all and
+    /// track them until included
+    Batch {
+        /// Plan (YAML, TOML or JSON) of calls: account, target, value, data
+        #[arg(long)]
+        plan: PathBuf,
+
+        #[command(flatten)]
+        delivery: Delivery,
+    },
+    /// Sign and send one call from a keystore account; --to takes an
+    /// address, a contact or an ENS name
+    Send {
+        /// Keystore account to send from
+        #[arg(long)]
+        account: String,
+
+        /// Address, `pq-contacts` name or ENS name to call
+        #[arg(long)]
+        to: Target,
+
+        /// Native value in wei
+        #[arg(long, default_value_t = U256::ZERO)]
+        value: U256,
+
+        /// Calldata (hex)
+        #[arg(long, default_value_t = Bytes::new())]
+        data: Bytes,
+
+        /// Chain ID to send on
+        #[arg(long)]
+        chain_id: u64,
+
+        /// EntryPoint address
+        #[arg(long, default_value_t = ENTRY_POINT_V07)]
+        entry_point: Address,
+
+        #[command(flatten)]
+        delivery: Delivery,
+    },
+    /// Poll a bundler until the op is included, then report the
+    /// UserOperationEvent outcome
+    Status {
+        /// userOpHash returned by eth_sendUserOperation
+        #[arg(long)]
+        hash: B256,
+
+        /// Bundler JSON-RPC URL, or several comma-separated to fail over
+        /// along
+        #[arg(long, env = "BUNDLER_RPC")]
+        bundler: String,
+
+        /// Give up after this many seconds (0 = check once)
+        #[arg(long, default_value_t = 60)]
+        timeout: u64,
+
+        /// Seconds between polls
+        #[arg(long, default_value_t = 1.0)]
+        interval: f64,
+
+        /// Once included, also wait until final on --rpc: a number of
+        /// confirmations, or the node's safe or finalized block
+        #[arg(long)]
+        finality: Option<Finality>,
+
+        /// Node JSON-RPC URL used for --finality
+        #[arg(long, env = "LOCAL_RPC")]
+        rpc: Option<String>,
+
+        /// Notification sinks (TOML or JSON) to tell once the op is
+        /// included
+        #[arg(long)]
+        notify: Option<PathBuf>,
+    },
+    /// Check each bundler's chain ID, supported EntryPoints and latency;
+    /// exits 1 if any is unhealthy
+    Bundlers {
+        /// Bundler JSON-RPC URLs, comma-separated
+        #[arg(long, env = "BUNDLER_RPC")]
+        bundler: String,
+
+        /// Chain ID the bundlers must serve [default: the first one's]
+        #[arg(long)]
+        chain_id: Option<u64>,
+
+        /// EntryPoint the bundlers must support
+        #[arg(long, default_value_t = ENTRY_POINT_V07)]
+        entry_point: Address,
+    },
+}
+
+/// `submit --self-bundle`'s node and sender.
+struct SelfBundle {
+    node: HttpTransport,
+    key_file: PathBuf,
+    beneficiary: Option<Address>,
+    timeout: Duration,
+}
+
+impl SelfBundle {
+    /// Send `user_op` in a handleOps transaction of our own, wait for it
+    /// and report the op's outcome; exits 1 unless it succeeded.
+    fn submit(self, json: bool, op: &OpArgs, user_op: &PackedUserOperation, hash: B256) {
+        let hex = Zeroizing::new(output::or_fail(
+            json,
+            "--ecdsa-key",
+            std::fs::read_to_string(&self.key_file)
+                .map_err(|e| WalletError::Io(self.key_file.display().to_string(), e)),
+        ));
+        let key = output::or_fail(
+            json,
+            "--ecdsa-key",
+            delegation::signing_key_from_hex(hex.trim()),
+        );
+        let node_chain: alloy_primitives::U64 = output::or_fail(
+            json,
+            "eth_chainId",
+            self.node.call("eth_chainId", serde_json::json!([])),
+        );
+        if node_chain.to::<u64>() != op.chain_id {
+            output::fail(
+                json,
+                format!(
+                    "--rpc is on chain {node_chain}, not --chain-id {}",
+                    op.chain_id
+                ),
+            );
+        }
+        let beneficiary = self
+            .beneficiary
+            .unwrap_or_else(|| delegation::address_of(&key));
+        let sent = self_bundle::send(
+            &self.node,
+            op.chain_id,
+            std::slice::from_ref(user_op),
+            op.entry_point,
+            beneficiary,
+            &key,
+        );
+        let bundle = sent.unwrap_or_else(|e| {
+            let diag = aa_errors::diagnose_error(&e, Some(user_op));
+            if json {
+                output::emit(&serde_json::json!({ "userOpHash": hash, "diagnostic": diag }));
+            } else {
+                println!("handleOps transaction not sent: {diag}");
+            }
+            std::process::exit(output::EXIT_FAILURE)
+        });
+        if !json {
+            eprintln!(
+                "Sent handleOps in {} from {}",
+                bundle.transaction_hash, bundle.from
+            );
+        }
+        let status = output::or_fail(
+            json,
+            "status",
+            self_bundle::wait(
+                &self.node,
+                &bundle,
+                op.entry_point,
+                hash,
+                self.timeout,
+                Duration::from_secs(1),
+            ),
+        );
+        if json {
+            let mut out = serde_json::to_value(&status).expect("status serializes");
+            out["bundle"] = serde_json::to_value(&bundle).expect("bundle serializes");
+            output::emit(&out);
+        } else {
+            print_status(&status);
+        }
+        if status.success != Some(true) {
+            std::process::exit(output::EXIT_FAILURE);
+        }
+    }
+}
+
+/// `--bundler`'s endpoints. With more than one, each is checked against
+/// `chain_id` and `entry_point` first, so requests skip the unhealthy ones.
+fn bundlers(urls: &str, chain_id: Option<u64>, entry_point: Address) -> Failover {
+    let bundlers = Failover::new(urls, Duration::from_secs(30));
+    if bundlers.urls().count() > 1 {
+        for health in bundlers.check(chain_id, entry_point) {
+            if let Some(problem) = health.problem {
+                tracing::warn!(url = %health.url, "bundler unhealthy: {problem}");
+            }
+        }
+    }
+    bundlers
+}
+
+/// The hybrid `userOp.signature`: `pq_signature` with the Ledger's ECDSA
+/// signature over `hash`, in a V2 envelope with `v2_key` when given.
+#[cfg(feature = "ledger")]
+fn ledger_co_sign(
+    json: bool,
+    path: &str,
+    hash: B256,
+    pq_signature: &[u8],
+    v2_key: Option<&[u8]>,
+) -> Vec<u8> {
+    use pq_cli::envelope::{Envelope, EnvelopeV2, SCHEME_ML_DSA_65, SignatureEnvelope};
+    use pq_cli::ledger::{self, Ledger};
+
+    let path = output::or_fail(json, "--ledger", path.parse());
+    let mut device = output::or_fail(json, "ledger", Ledger::open());
+    eprintln!("Confirm the userOpHash on the Ledger");
+    let (address, signature) =
+        output::or_fail(json, "ledger", device.sign_user_op_hash(&path, hash));
+    eprintln!("ECDSA co-signer: {address}");
+    let Some(public_key) = v2_key else {
+        return ledger::hybrid_signature(&signature, pq_signature);
+    };
+    let envelope = Envelope::V2(EnvelopeV2 {
+        pq: SignatureEnvelope {
+            scheme_id: SCHEME_ML_DSA_65,
+            public_key: public_key.to_vec(),
+            signature: pq_signature.to_vec(),
+        },
+        ecdsa_signature: signature.as_bytes().to_vec(),
+    });
+    output::or_fail(json, "signature envelope", envelope.encode())
+}
+
+#[cfg(not(feature = "ledger"))]
+fn ledger_co_sign(json: bool, _: &str, _: B256, _: &[u8], _: Option<&[u8]>) -> Vec<u8> {
+    output::fail(json, "pq-userop was built without the ledger feature")
+}
+
+/// Show `review` and ask whether to sign: on the full-screen review with the
+/// `tui` feature and a terminal, else with a y/N prompt.
+/// What `op` moves, or `None` (with a warning) if it could not be simulated.
+fn preview_assets(
+    node: &HttpTransport,
+    op: &PackedUserOperation,
+    entry_point: Address,
+    chain_id: u64,
+) -> Option<AssetChanges> {
+    assets::simulate(node, op, entry_point, chain_id)
+        .inspect_err(|e| tracing::warn!("no asset-change preview: {e}"))
+        .ok()
+}
+
+fn confirm_signing(json: bool, review: &Review) -> bool {
+    #[cfg(feature = "tui")]
+    if std::io::IsTerminal::is_terminal(&std::io::stderr()) {
+        return output::or_fail(json, "review", pq_cli::review::run(review));
+    }
+    review.print();
+    output::or_fail(
+        json,
+        "no terminal to confirm on (pass --yes)",
+        cli::confirm("Sign?"),
+    )
+}
+
+impl OpArgs {
+    fn load(&self, json: bool) -> (PackedUserOperation, B256) {
+        let _span = tracing::debug_span!("userop_build", chain_id = self.chain_id).entered();
+        let text = if self.file.as_os_str() == "-" {
+            let mut buf = String::new();
+            std::io::stdin()
+                .read_to_string(&mut buf)
+                .expect("failed to read stdin");
+            buf
+        } else {
+            std::fs::read_to_string(&self.file).expect("failed to read UserOperation file")
+        };
+        let op: PackedUserOperation = serde_json::from_str(&text)
+            .unwrap_or_else(|e| output::fail(json, format!("invalid UserOperation JSON: {e}")));
+        let hash = userop::compute_user_op_hash(&op, self.entry_point, U256::from(self.chain_id));
+        tracing::debug!(sender = %op.sender, nonce = %op.nonce, %hash, "loaded");
+        (op, hash)
+    }
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    match args.command {
+        Command::Hash {
+            op,
+            verify_onchain,
+            rpc,
+        } => {
+            let (user_op, hash) = op.load(json);
+            if verify_onchain {
+                let rpc = HttpTransport::new(rpc.unwrap(), Duration::from_secs(30));
+                output::or_fail(
+                    json,
+                    "--verify-onchain",
+                    simulate::cross_check_user_op_hash(&rpc, &user_op, op.entry_point, op.chain_id),
+                );
+                tracing::info!(%hash, entry_point = %op.entry_point, "matches getUserOpHash");
+            }
+            if json {
+                output::emit(&serde_json::json!({ "userOpHash": hash }));
+            } else {
+                println!("{hash}");
+            }
+        }
+        Command::Check {
+            op,
+            verifier,
+            state,
+        } => {
+            let (user_op, _) = op.load(json);
+            let verifier = verifier
+                .map(|spec| output::or_fail(json, "--verifier", precompile::parse_verifier(&spec)));
+            let snapshot = match state {
+                Some(path) => output::or_fail(json, "--state", Snapshot::load(&path)),
+                None => Snapshot::default(),
+            };
+            let report = check::check(&user_op, op.chain_id, verifier.as_deref(), &snapshot);
+            if json {
+                output::emit(&report);
+            } else {
+                for finding in &report.findings {
+                    println!("{finding}");
+                }
+                if report.ok {
+                    println!("OK ({} warnings)", report.findings.len());
+                }
+            }
+            if !report.ok {
+                std::process::exit(output::EXIT_FAILURE);
+            }
+        }
+        Command::Sign {
+            op,
+            signer,
+            output: out_path,
+            verifier,
+            sig_transport,
+            chunk_store,
+            transport_out,
+            passkey_assertion,
+            passkey_pubkey,
+            require_uv,
+            ledger,
+            policy,
+            policy_ledger,
+            approved,
+            signing_scheme,
+            valid_for,
+            valid_after,
+            rpc,
+            yes,
+        } => {
+            if !signer.is_present() {
+                output::fail(json, "one of --key or --account is required");
+            }
+            if sig_transport != SigTransport::Inline && transport_out.is_none() {
+                output::fail(json, "--sig-transport chunked|blob needs --transport-out");
+            }
+            if matches!(sig_transport, SigTransport::Chunked { .. }) && chunk_store.is_none() {
+                output::fail(json, "--sig-transport chunked needs --chunk-store");
+            }
+            let (mut user_op, hash) = op.load(json);
+            let account = signer
+                .account
+                .as_ref()
+                .and_then(|name| Keystore::open_default().ok()?.load(name).ok());
+            let profile = account
+                .as_ref()
+                .and_then(|account| account.profiles.get(&op.chain_id).copied());
+            let verifier = verifier.map(|spec| {
+                let validator = output::or_fail(json, "--verifier", Validator::of_verifier(&spec));
+                (spec, validator)
+            });
+            if let Some(profile) = &profile {
+                let request = profile::Request {
+                    entry_point: op.entry_point,
+                    signing_scheme,
+                    verifier: verifier.as_ref().map(|(_, validator)| *validator),
+                    co_signature: if ledger.is_some() {
+                        Some(Validator::HybridEcdsa)
+                    } else {
+                        passkey_assertion.as_ref().map(|_| Validator::HybridPasskey)
+                    },
+                    windowed: valid_for.is_some() || valid_after.is_some(),
+                };
+                output::or_fail(
+                    json,
+                    "refusing to sign",
+                    profile.check(op.chain_id, &request),
+                );
+            }
+            let spend = policy.map(|path| {
+                let policy = output::or_fail(json, "failed to load policy", Policy::load(&path));
+                let ledger_path = policy_ledger.unwrap_or_else(|| {
+                    output::or_fail(json, "policy ledger", SpendLedger::default_path())
+                });
+                let ledger =
+                    output::or_fail(json, "policy ledger", SpendLedger::load(&ledger_path));
+                let now = attestation::now();
+                let spent = ledger.spent_on(op.chain_id, now);
+                match policy.check(&user_op, op.chain_id, spent, approved) {
+                    Ok(value) => (ledger, ledger_path, now, value),
+                    Err(denial) => {
+                        #[cfg(feature = "metrics")]
+                        pq_cli::metrics::policy_denial(denial.rule);
+                        if json {
+                            output::emit(&denial);
+                        } else {
+                            eprintln!("{denial}");
+                        }
+                        std::process::exit(output::EXIT_FAILURE);
+                    }
+                }
+            });
+            let mut review = Review::new(&user_op, op.chain_id, hash);
+            match AddressBook::open_default() {
+                Ok(book) => review.label(Names::from_book(&book)),
+                Err(e) => tracing::warn!("no contact names in the preview: {e}"),
+            }
+            if let Some(url) = rpc {
+                let node = HttpTransport::new(url, Duration::from_secs(30));
+                review.assets = preview_assets(&node, &user_op, op.entry_point, op.chain_id);
+            }
+            if yes {
+                review.print();
+            } else if !confirm_signing(json, &review) {
+                output::fail(json, "not signed");
+            }
+            let seed = output::or_fail(json, "failed to load signing key", signer.seed());
+            #[cfg(feature = "metrics")]
+            let started = std::time::Instant::now();
+            let sk = SigningKey::<MlDsa65>::from_seed(&(*seed).into());
+            let scheme = signing_scheme.unwrap_or_else(|| {
+                account
+                    .as_ref()
+                    .map(|account| account.signing_scheme(op.chain_id))
+                    .unwrap_or_default()
+            });
+            let window = (valid_for.is_some() || valid_after.is_some()).then(|| {
+                if scheme != SigningScheme::HashAsMessage {
+                    output::fail(
+                        json,
+                        format!(
+                            "--valid-for and --valid-after need the hash signing scheme, not {scheme}"
+                        ),
+                    );
+                }
+                let now = attestation::now();
+                let window = match (valid_after, valid_for) {
+                    (Some(delay), duration) => ValidityWindow::time_locked(now, delay, duration),
+                    (None, Some(duration)) => ValidityWindow::starting_at(now, duration),
+                    (None, None) => unreachable!("checked above"),
+                };
+                output::or_fail(json, "validity window", window)
+            });
+            let sig = {
+                let _span = tracing::debug_span!("sign", %hash, %scheme).entered();
+                match window {
+                    Some(window) => {
+                        let digest = window.signed_hash(hash, U256::from(op.chain_id));
+                        sk.sign(digest.as_slice())
+                    }
+                    None => scheme.sign(&sk, &user_op, op.entry_point, U256::from(op.chain_id)),
+                }
+            };
+            #[cfg(feature = "metrics")]
+            pq_cli::metrics::signature("pq-userop sign", started.elapsed());
+            user_op.signature = sig.encode().to_vec();
+            let record = Record {
+                tool: "pq-userop sign".to_string(),
+                account: signer.account.clone(),
+                key_id: keys::address(&sk.verifying_key().encode()),
+                digest: Some(hash),
+                chain_id: Some(op.chain_id),
+                signature: keccak256(&user_op.signature),
+                summary: review.calls.clone(),
+                backend: Backend::RustCrypto.to_string(),
+            };
+            output::or_fail(
+                json,
+                "signature withheld",
+                AuditLog::open_default().and_then(|log| log.append(record)),
+            );
+            let pk = sk.verifying_key().encode();
+            if let Some(profile) = &profile {
+                user_op.signature = output::or_fail(
+                    json,
+                    "signature envelope",
+                    profile.signature_field(&pk, &user_op.signature),
+                );
+            } else if let Some((spec, _)) = verifier {
+                let verifier = output::or_fail(
+                    json,
+                    "--verifier",
+                    precompile::parse_verifier(spec.as_str()),
+                );
+                user_op.signature = output::or_fail(
+                    json,
+                    "signature envelope",
+                    verifier.signature_field(&pk, &user_op.signature),
+                );
+            }
+            if let Some(window) = window {
+                user_op.signature = window.wrap(&user_op.signature);
+                if window.valid_until == validity::MAX_TIMESTAMP {
+                    eprintln!(
+                        "Signature valid from {} on (unix seconds)",
+                        window.valid_after
+                    );
+                } else {
+                    eprintln!(
+                        "Signature valid from {} until {} (unix seconds)",
+                        window.valid_after, window.valid_until
+                    );
+                }
+            }
+            if let Some(path) = passkey_assertion {
+                let bytes = std::fs::read(&path).unwrap_or_else(|e| {
+                    output::fail(json, format!("failed to read {}: {e}", path.display()))
+                });
+                let assertion =
+                    output::or_fail(json, "passkey assertion", Assertion::from_json(&bytes));
+                let auth =
+                    output::or_fail(json, "passkey assertion", assertion.check(hash, require_uv));
+                if let Some(hex) = passkey_pubkey {
+                    let key = output::or_fail(
+                        json,
+                        "--passkey-pubkey",
+                        webauthn::verifying_key_from_hex(&hex),
+                    );
+                    if !output::or_fail(json, "passkey assertion", assertion.verify(&key)) {
+                        output::fail(json, "passkey signature does not verify");
+                    }
+                }
+                user_op.signature = webauthn::co_signature(&auth, &user_op.signature);
+            }
+            if let Some(path) = ledger {
+                let v2_key = profile
+                    .is_some_and(|p| p.envelope == Some(ENVELOPE_V2))
+                    .then_some(&pk[..]);
+                user_op.signature = ledger_co_sign(json, &path, hash, &user_op.signature, v2_key);
+            }
+            let packed = output::or_fail(
+                json,
+                "signature transport",
+                sig_transport::pack(sig_transport, &user_op.signature),
+            );
+            user_op.signature = packed.reference.encode();
+            if let Some(path) = transport_out {
+                let published = match &packed.blob {
+                    Some(blob) => Bytes::copy_from_slice(blob).to_string(),
+                    None => {
+                        let calls: Vec<_> = sig_transport::chunk_calls(
+                            chunk_store.unwrap(),
+                            &packed,
+                        )
+                        .into_iter()
+                        .map(|c| serde_json::json!({ "to": c.target, "data": Bytes::from(c.data) }))
+                        .collect();
+                        serde_json::to_string_pretty(&calls).expect("calls serialize")
+                    }
+                };
+                std::fs::write(&path, published).expect("failed to write transport payload");
+                eprintln!(
+                    "Publish {} before submitting ({sig_transport})",
+                    path.display()
+                );
+            }
+            eprintln!("userOpHash: {hash}");
+            if let Some((mut ledger, path, now, value)) = spend {
+                ledger.record(op.chain_id, now, value);
+                output::or_fail(json, "policy ledger", ledger.save(&path));
+            }
+
+            let rendered =
+                serde_json::to_string_pretty(&user_op).expect("UserOperation serializes");
+            match out_path {
+                Some(path) => {
+                    std::fs::write(&path, rendered).expect("failed to write UserOperation");
+                    eprintln!("Signed UserOperation written to {}", path.display());
+                }
+                None => println!("{rendered}"),
+            }
+        }
+        Command::Attach {
+            op,
+            response,
+            signer,
+            trusted_hosts,
+            allow_unattested,
+            verifier,
+            output: out_path,
+        } => {
+            if !signer.is_present() {
+                output::fail(json, "one of --key or --account is required");
+            }
+            let (mut user_op, hash) = op.load(json);
+            let pk = output::or_fail(json, "failed to load public key", signer.public_key());
+            let bytes = std::fs::read(&response).unwrap_or_else(|e| {
+                output::fail(json, format!("failed to read {}: {e}", response.display()))
+            });
+            let response = output::or_fail(json, "--response", SignResponse::from_json(&bytes));
+            if !Backend::RustCrypto.verify(&pk, hash.as_slice(), &[], &response.signature) {
+                #[cfg(feature = "metrics")]
+                pq_cli::metrics::verification_failure("pq-userop attach");
+                output::fail(json, "signature does not verify over the userOpHash");
+            }
+            match &response.attestation {
+                Some(record) => output::or_fail(
+                    json,
+                    "rejected response",
+                    record.verify(&pk, &trusted_hosts, attestation::now()),
+                ),
+                None if allow_unattested => tracing::warn!("response is not attested"),
+                None => output::fail(json, "response carries no attestation"),
+            }
+            user_op.signature = response.signature.to_vec();
+            if let Some(spec) = verifier {
+                let verifier = output::or_fail(
+                    json,
+                    "--verifier",
+                    precompile::parse_verifier(spec.as_str()),
+                );
+                user_op.signature = output::or_fail(
+                    json,
+                    "signature envelope",
+                    verifier.signature_field(&pk, &user_op.signature),
+                );
+            }
+            eprintln!("userOpHash: {hash}");
+
+            let rendered =
+                serde_json::to_string_pretty(&user_op).expect("UserOperation serializes");
+            match out_path {
+                Some(path) => {
+                    std::fs::write(&path, rendered).expect("failed to write UserOperation");
+                    eprintln!("Signed UserOperation written to {}", path.display());
+                }
+                None => println!("{rendered}"),
+            }
+        }
+        Command::Estimate {
+            op,
+            bundler: bundler_url,
+            rpc,
+            fees,
+            bundle_size,
+            l1_base_fee,
+            l2_gas_price,
+            eth_usd,
+            output: out_path,
+        } => {
+            let (mut user_op, _) = op.load(json);
+            let calibration = Calibration {
+                bundle_size,
+                ..Calibration::for_chain(op.chain_id)
+            };
+            let node = rpc.map(|url| HttpTransport::new(url, Duration::from_secs(30)));
+            let quote = node
+                .as_ref()
+                .map(|node| output::or_fail(json, "fee quote", fees.quote(node, op.chain_id)));
+            if let Some(q) = &quote {
+                user_op.gas_fees =
+                    userop::pack_u128_pair(q.max_priority_fee_per_gas, q.max_fee_per_gas);
+            }
+
+            let l1: Option<Box<dyn L1DataFee + '_>> =
+                match (l1_base_fee.zip(l2_gas_price), &node, calibration.rollup) {
+                    (Some((l1_base_fee, l2_gas_price)), _, _) => Some(Box::new(StaticL1Fee {
+                        l1_base_fee,
+                        l2_gas_price,
+                    })),
+                    (None, Some(node), Some(Rollup::Arbitrum)) => Some(Box::new(ArbitrumL1Fee {
+                        rpc: node,
+                        entry_point: op.entry_point,
+                    })),
+                    (None, Some(node), Some(Rollup::OpStack)) => Some(Box::new(OpStackL1Fee {
+                        rpc: node,
+                        gas_price: quote.map_or(0, |q| q.gas_price()),
+                    })),
+                    _ => None,
+                };
+            if let (Some(rollup), None) = (calibration.rollup, &l1) {
+                eprintln!(
+                    "warning: {} ({rollup:?}) charges an L1 data fee; pass --rpc, or \
+                     --l1-base-fee and --l2-gas-price, to include it",
+                    calibration.chain
+                );
+            }
+            let local = output::or_fail(
+                json,
+                "estimate",
+                pre_verification::estimate(&user_op, &calibration, l1.as_deref()),
+            );
+
+            let remote = bundler_url.and_then(|url| {
+                let rpc = bundlers(&url, Some(op.chain_id), op.entry_point);
+                let mut probe = user_op.clone();
+                if probe.signature.is_empty() {
+                    probe.signature = vec![0xff; SIGNATURE_LEN];
+                }
+                bundler::estimate_gas(&rpc, &probe, op.entry_point)
+                    .inspect_err(|e| tracing::warn!("bundler estimate unavailable: {e}"))
+                    .ok()
+            });
+            let (pvg, source) = match &remote {
+                Some(est) => (est.pre_verification_gas, "bundler"),
+                None => (U256::from(local.pre_verification_gas), "local"),
+            };
+            user_op.pre_verification_gas = pvg;
+            // Without a quote the base fee is unknown; price at maxFeePerGas.
+            let cost = cost::cost(&user_op, quote.map_or(u128::MAX, |q| q.base_fee));
+            let usd = |wei: u128| eth_usd.map(|price| wei as f64 / 1e18 * price);
+
+            if let Some(path) = out_path {
+                let rendered =
+                    serde_json::to_string_pretty(&user_op).expect("UserOperation serializes");
+                std::fs::write(&path, rendered).expect("failed to write UserOperation");
+                eprintln!("UserOperation written to {}", path.display());
+            }
+            if json {
+                output::emit(&serde_json::json!({
+                    "preVerificationGas": pvg,
+                    "source": source,
+                    "local": local,
+                    "bundler": remote,
+                    "fees": quote,
+                    "cost": cost,
+                    "costUsd": usd(cost.current_wei),
+                    "maxCostUsd": usd(cost.max_wei),
+                }));
+            } else {
+                println!("{pvg}");
+                eprintln!(
+                    "source: {source}; local {} = calldata {} + overhead {}{} + L1 data {} ({} bytes, {}% margin)",
+                    local.pre_verification_gas,
+                    local.calldata_gas,
+                    local.overhead_gas,
+                    local
+                        .floor_gas
+                        .map(|f| format!(" (EIP-7623 floor {f})"))
+                        .unwrap_or_default(),
+                    local.l1_data_gas,
+                    local.encoded_bytes,
+                    calibration.margin_percent,
+                );
+                if let Some(q) = &quote {
+                    eprintln!(
+                        "fees: base {} wei, maxPriorityFeePerGas {}, maxFeePerGas {}",
+                        q.base_fee, q.max_priority_fee_per_gas, q.max_fee_per_gas
+                    );
+                }
+                if user_op.max_fee_per_gas() > 0 {
+                    let fmt = |wei: u128| match usd(wei) {
+                        Some(d) => format!("{} ETH (${d:.2})", cost::format_ether(wei)),
+                        None => format!("{} ETH", cost::format_ether(wei)),
+                    };
+                    eprintln!(
+                        "cost: {} now, {} max, for {} gas",
+                        fmt(cost.current_wei),
+                        fmt(cost.max_wei),
+                        cost.gas
+                    );
+                }
+            }
+        }
+        Command::Analyze { op, bundle_size } => {
+            let (user_op, _) = op.load(json);
+            let calibration = Calibration {
+                bundle_size,
+                ..Calibration::for_chain(op.chain_id)
+            };
+            let report =
+                output::or_fail(json, "analyze", breakdown::analyze(&user_op, &calibration));
+            if json {
+                output::emit(&report);
+            } else {
+                println!(
+                    "{:<20} {:>6} {:>8} {:>6} {:>8}",
+                    "field", "bytes", "encoded", "zero", "gas"
+                );
+                for f in &report.fields {
+                    println!(
+                        "{:<20} {:>6} {:>8} {:>6} {:>8}",
+                        f.field, f.bytes, f.encoded_bytes, f.zero_bytes, f.calldata_gas
+                    );
+                }
+                println!(
+                    "{:<20} {:>6} {:>8} {:>6} {:>8}",
+                    "total", "", report.encoded_bytes, report.zero_bytes, report.calldata_gas
+                );
+                println!(
+                    "preVerificationGas {} on {}{}",
+                    report.pre_verification_gas,
+                    calibration.chain,
+                    if report.floor {
+                        " (EIP-7623 floor)"
+                    } else {
+                        ""
+                    }
+                );
+                if report.placeholder_signature {
+                    println!(
+                        "The op is unsigned; its signature is costed at {SIGNATURE_LEN} bytes."
+                    );
+                }
+                for suggestion in &report.suggestions {
+                    println!("- {suggestion}");
+                }
+            }
+        }
+        Command::Rules {
+            op,
+            rpc,
+            simulations_code,
+        } => {
+            let (user_op, hash) = op.load(json);
+            let node = HttpTransport::new(rpc, Duration::from_secs(60));
+            let code = simulations_code.map(|path| {
+                let hex =
+                    std::fs::read_to_string(&path).expect("failed to read simulations bytecode");
+                output::or_fail(json, "simulations bytecode", ct::decode_hex(hex.trim()))
+            });
+            let violations = output::or_fail(
+                json,
+                "trace",
+                erc7562::check(&node, &user_op, op.entry_point, code.as_deref()),
+            );
+            if json {
+                output::emit(&serde_json::json!({
+                    "userOpHash": hash,
+                    "violations": violations,
+                }));
+            } else if violations.is_empty() {
+                println!("No ERC-7562 violations");
+            } else {
+                for v in &violations {
+                    println!("{v}");
+                }
+            }
+            if !violations.is_empty() {
+                std::process::exit(output::EXIT_FAILURE);
+            }
+        }
+        Command::Submit {
+            file,
+            entry_point,
+            chain_id,
+            bundler: bundler_url,
+            self_bundle,
+            ecdsa_key,
+            beneficiary,
+            timeout,
+            simulate,
+            rpc,
+            simulations_code,
+            aggregator,
+            #[cfg(feature = "queue")]
+            all,
+            #[cfg(feature = "queue")]
+            fees,
+            #[cfg(feature = "queue")]
+            finality,
+        } => {
+            #[cfg(feature = "queue")]
+            if all {
+                submit_all(
+                    json,
+                    bundler_url.expect("clap requires --bundler"),
+                    rpc,
+                    chain_id,
+                    entry_point,
+                    &fees,
+                    finality,
+                );
+                return;
+            }
+            let op = OpArgs {
+                file: file.expect("required without --all"),
+                entry_point,
+                chain_id: chain_id.expect("required without --all"),
+            };
+            let (user_op, hash) = op.load(json);
+            let _span = tracing::info_span!("submit", %hash).entered();
+            // A handleOps that reverts costs the sender its gas.
+            if simulate || self_bundle {
+                let node = HttpTransport::new(rpc.clone().unwrap(), Duration::from_secs(30));
+                let sim = match simulations_code {
+                    Some(path) => {
+                        let hex = std::fs::read_to_string(&path)
+                            .expect("failed to read simulations bytecode");
+                        let code = output::or_fail(
+                            json,
+                            "simulations bytecode",
+                            ct::decode_hex(hex.trim()),
+                        );
+                        simulate::simulate_validation(&node, &user_op, op.entry_point, &code)
+                    }
+                    None => simulate::simulate_handle_ops(
+                        &node,
+                        &user_op,
+                        op.entry_point,
+                        user_op.sender,
+                    ),
+                };
+                let sim = output::or_fail(json, "simulation", sim);
+                if let Some(diag) = aa_errors::diagnose_simulation(&sim, &user_op) {
+                    if json {
+                        output::emit(&serde_json::json!({
+                            "userOpHash": hash,
+                            "simulation": sim,
+                            "diagnostic": diag,
+                        }));
+                    } else {
+                        println!("Simulation rejected the UserOperation: {diag}");
+                    }
+                    std::process::exit(output::EXIT_FAILURE);
+                }
+                if !json {
+                    eprintln!("Simulation passed");
+                    if let Simulation::Ok {
+                        validation: Some(v),
+                    } = &sim
+                    {
+                        eprintln!("  preOpGas {}  prefund {}", v.pre_op_gas, v.prefund);
+                    }
+                }
+            }
+
+            if self_bundle {
+                let bundler = SelfBundle {
+                    node: HttpTransport::new(rpc.unwrap(), Duration::from_secs(30)),
+                    key_file: ecdsa_key.expect("clap requires --ecdsa-key"),
+                    beneficiary,
+                    timeout: Duration::from_secs(timeout),
+                };
+                bundler.submit(json, &op, &user_op, hash);
+                return;
+            }
+            let bundler_url = bundler_url.expect("clap requires --bundler");
+            let bundler_rpc = bundlers(&bundler_url, chain_id, op.entry_point);
+            let sent =
+                bundler::send_user_operation(&bundler_rpc, &user_op, op.entry_point, aggregator);
+            let returned = sent.unwrap_or_else(|e| {
+                let diag = aa_errors::diagnose_error(&e, Some(&user_op));
+                if json {
+                    output::emit(&serde_json::json!({ "userOpHash": hash, "diagnostic": diag }));
+                } else {
+                    println!("Bundler rejected the UserOperation: {diag}");
+                }
+                std::process::exit(output::EXIT_FAILURE)
+            });
+            tracing::info!(%returned, "accepted by bundler");
+            if returned != hash {
+                tracing::warn!("bundler returned {returned}, expected {hash}");
+            }
+            if json {
+                output::emit(&serde_json::json!({ "userOpHash": returned }));
+            } else {
+                println!("{returned}");
+            }
+        }
+        Command::Batch {
+            plan: plan_path,
+            delivery,
+        } => {
+            let plan = output::or_fail(json, "failed to load plan", Plan::load(&plan_path));
+            let book = output::or_fail(json, "address book", AddressBook::open_default());
+            deliver(
+                json,
+                "pq-userop batch",
+                &plan,
+                delivery,
+                Names::from_book(&book),
+            );
+        }
+        Command::Send {
+            account,
+            to,
+            value,
+            data,
+            chain_id,
+            entry_point,
+            delivery,
+        } => {
+            let book = output::or_fail(json, "address book", AddressBook::open_default());
+            let ens_rpc = HttpTransport::new(
+                delivery
+                    .ens_rpc
+                    .clone()
+                    .unwrap_or_else(|| delivery.rpc.clone()),
+                Duration::from_secs(30),
+            );
+            let target = output::or_fail(json, "--to", to.resolve(&book, Some(&ens_rpc)));
+            let mut names = Names::from_book(&book);
+            if let Target::Ens(name) = &to {
+                names.insert(target, name.clone());
+            }
+            if !json {
+                eprintln!("Sending to {}", names.label(target));
+            }
+            let plan = Plan {
+                chain_id,
+                entry_point,
+                verification_gas_limit: None,
+                call_gas_limit: None,
+                calls: vec![PlannedCall {
+                    account,
+                    target,
+                    value,
+                    data,
+                }],
+            };
+            deliver(json, "pq-userop send", &plan, delivery, names);
+        }
+        #[cfg(feature = "queue")]
+        Command::Enqueue {
+            op,
+            account,
+            not_before,
+        } => {
+            let (user_op, hash) = op.load(json);
+            if user_op.signature.is_empty() {
+                output::fail(json, "the UserOperation is not signed");
+            }
+            let window = match not_before {
+                Some(after) => Some(output::or_fail(
+                    json,
+                    "--not-before",
+                    ValidityWindow::new(after, validity::MAX_TIMESTAMP),
+                )),
+                None => ValidityWindow::of_field(&user_op.signature),
+            };
+            let queue = output::or_fail(json, "failed to open queue", Queue::open_default());
+            let entry = output::or_fail(
+                json,
+                "not queued",
+                queue.enqueue(
+                    op.chain_id,
+                    op.entry_point,
+                    account.as_deref(),
+                    &user_op,
+                    hash,
+                    window,
+                ),
+            );
+            if json {
+                output::emit(&serde_json::json!({
+                    "id": entry.id,
+                    "userOpHash": hash,
+                    "validAfter": window.map(|w| w.valid_after),
+                }));
+            } else if let Some(window) = window.filter(|w| w.valid_after > attestation::now()) {
+                println!(
+                    "{:<13}{hash}  queued, held until {}",
+                    entry.id, window.valid_after
+                );
+            } else {
+                println!("{:<13}{hash}  queued", entry.id);
+            }
+        }
+        #[cfg(feature = "queue")]
+        Command::Bump {
+            hash,
+            multiplier,
+            bundler: bundler_url,
+            yes,
+        } => {
+            if multiplier.is_nan() || multiplier < 1.0 {
+                output::fail(json, "--multiplier must be at least 1");
+            }
+            let queue = output::or_fail(json, "failed to open queue", Queue::open_default());
+            let entry = output::or_fail(json, "failed to read queue", queue.find_by_hash(hash))
+                .unwrap_or_else(|| output::fail(json, format!("{hash} is not in the queue")));
+            let id = entry.id;
+            if !matches!(
+                entry.status,
+                queue::Status::Pending | queue::Status::Submitted
+            ) {
+                output::fail(json, format!("entry {id} is already {}", entry.status));
+            }
+            let Some(name) = entry.account.clone() else {
+                output::fail(
+                    json,
+                    format!("entry {id} was queued without --account, so it cannot be re-signed"),
+                );
+            };
+            let old = &entry.user_op;
+            let (tip, max) = cost::replacement_fees(
+                old.max_priority_fee_per_gas(),
+                old.max_fee_per_gas(),
+                multiplier,
+            );
+            eprintln!(
+                "maxPriorityFeePerGas {} -> {tip} wei",
+                old.max_priority_fee_per_gas()
+            );
+            eprintln!(
+                "maxFeePerGas         {} -> {max} wei",
+                old.max_fee_per_gas()
+            );
+            if !yes
+                && !output::or_fail(
+                    json,
+                    "no terminal to confirm on (pass --yes)",
+                    cli::confirm("Re-sign with these fees?"),
+                )
+            {
+                output::fail(json, "not signed");
+            }
+            let bumped = PackedUserOperation {
+                gas_fees: userop::pack_u128_pair(tip, max),
+                ..old.clone()
+            };
+            let (bumped, bumped_hash) = output::or_fail(
+                json,
+                "re-signing failed",
+                resign(&entry, &name, bumped, "pq-userop bump"),
+            );
+
+            let bundler_rpc = bundlers(&bundler_url, Some(entry.chain_id), entry.entry_point);
+            match bundler::send_user_operation(&bundler_rpc, &bumped, entry.entry_point, None) {
+                Ok(returned) => {
+                    tracing::info!(%returned, replaces = %hash, "accepted by bundler");
+                    output::or_fail(
+                        json,
+                        "failed to update queue",
+                        queue
+                            .replace_op(id, &bumped, bumped_hash)
+                            .and_then(|()| queue.set_status(id, queue::Status::Submitted, None)),
+                    );
+                }
+                // Keep the replacement so `submit --all` can send it later.
+                Err(e @ pq_cli::WalletError::Rpc(_)) => {
+                    output::or_fail(
+                        json,
+                        "failed to update queue",
+                        queue
+                            .replace_op(id, &bumped, bumped_hash)
+                            .and_then(|()| queue.set_status(id, queue::Status::Pending, None))
+                            .and_then(|()| queue.record_attempt(id, &e.to_string())),
+                    );
+                    output::fail(
+                        json,
+                        format!("replacement queued but not sent (run submit --all): {e}"),
+                    );
+                }
+                Err(e) => {
+                    let diag = aa_errors::diagnose_error(&e, Some(&bumped));
+                    if json {
+                        output::emit(&serde_json::json!({
+                            "userOpHash": bumped_hash,
+                            "diagnostic": diag,
+                        }));
+                    } else {
+                        println!("Bundler rejected the replacement: {diag}");
+                    }
+                    std::process::exit(output::EXIT_FAILURE);
+                }
+            }
+            if json {
+                output::emit(&serde_json::json!({
+                    "id": id,
+                    "replaces": hash,
+                    "userOpHash": bumped_hash,
+                }));
+            } else {
+                println!("{bumped_hash}");
+            }
+        }
+        #[cfg(feature = "queue")]
+        Command::Queue { status } => {
+            let queue = output::or_fail(json, "failed to open queue", Queue::open_default());
+            let entries = output::or_fail(json, "failed to read queue", queue.list(&status));
+            if json {
+                output::emit(&entries);
+            } else {
+                for entry in &entries {
+                    print_entry(entry);
+                }
+            }
+        }
+        Command::Status {
+            hash,
+            bundler: url,
+            timeout,
+            interval,
+            finality,
+            rpc: node_url,
+            notify,
+        } => {
+            let notifier =
+                notify.map(|path| output::or_fail(json, "notifications", Notifier::load(&path)));
+            let node = finality.map(|_| {
+                let url = node_url.unwrap_or_else(|| output::fail(json, "--finality needs --rpc"));
+                HttpTransport::new(url, Duration::from_secs(30))
+            });
+            let started = std::time::Instant::now();
+            let rpc = Failover::new(&url, Duration::from_secs(30));
+            let status = output::or_fail(
+                json,
+                "status",
+                bundler::wait_for_status(
+                    &rpc,
+                    hash,
+                    Duration::from_secs(timeout),
+                    Duration::from_secs_f64(interval),
+                    |s| {
+                        if !json {
+                            eprintln!("{:?}...", s.state);
+                        }
+                    },
+                ),
+            );
+            let confirmation = match (finality, &node, status.transaction_hash) {
+                (Some(finality), Some(node), Some(tx)) if status.state == State::Included => {
+                    let block = status.block_number.unwrap_or_default().saturating_to();
+                    let mut tracker = Tracker::new(tx, block, finality);
+                    Some(output::or_fail(
+                        json,
+                        "confirmation",
+                        tracker.wait(
+                            node,
+                            Duration::from_secs(timeout).saturating_sub(started.elapsed()),
+                            Duration::from_secs_f64(interval),
+                            |c| {
+                                if !json {
+                                    eprintln!("{} confirmations...", c.confirmations);
+                                }
+                            },
+                        ),
+                    ))
+                }
+                _ => None,
+            };
+            if let Some(notifier) = &notifier
+                && status.state == State::Included
+            {
+                let mut data = serde_json::to_value(&status).expect("status serializes");
+                data["userOpHash"] = serde_json::json!(hash);
+                notifier.notify(&Notification::new(
+                    Event::OpIncluded,
+                    "pq-userop",
+                    match status.success {
+                        Some(true) => format!("userOp {hash} included"),
+                        _ => format!("userOp {hash} included, call reverted"),
+                    },
+                    data,
+                ));
+            }
+            #[cfg(feature = "queue")]
+            let requeued = confirmation.is_some_and(|c| {
+                output::or_fail(
+                    json,
+                    "failed to update queue",
+                    record_confirmation(hash, &c, status.success == Some(true)),
+                )
+            });
+            if json {
+                let mut out = serde_json::to_value(&status).expect("status serializes");
+                if let Some(c) = &confirmation {
+                    out["confirmation"] = serde_json::to_value(c).expect("confirmation serializes");
+                }
+                output::emit(&out);
+            } else {
+                print_status(&status);
+                if let Some(c) = &confirmation {
+                    print_confirmation(c);
+                }
+                #[cfg(feature = "queue")]
+                if requeued {
+                    println!("Re-queued as pending; run `submit --all` to send it again");
+                }
+            }
+            // Included-and-succeeded is the only outcome a deploy script can proceed on.
+            if status.success != Some(true) || confirmation.is_some_and(|c| !c.is_final) {
+                std::process::exit(output::EXIT_FAILURE);
+            }
+        }
+        Command::Bundlers {
+            bundler: urls,
+            chain_id,
+            entry_point,
+        } => {
+            let report = Failover::new(&urls, Duration::from_secs(30)).check(chain_id, entry_point);
+            if json {
+                output::emit(&report);
+            } else {
+                for health in &report {
+                    let latency = health
+                        .latency_ms
+                        .map_or("-".to_string(), |ms| format!("{ms} ms"));
+                    let chain = health.chain_id.map_or("-".to_string(), |id| id.to_string());
+                    let state = health.problem.as_deref().unwrap_or("healthy");
+                    println!("{}  chain {chain}  {latency}  {state}", health.url);
+                }
+            }
+            if report.is_empty() || report.iter().any(|h| h.problem.is_some()) {
+                std::process::exit(output::EXIT_FAILURE);
+            }
+        }
+    }
+}
+
+/// Build, review, sign and deliver every op in `plan`, as `tool` in the
+/// audit log, labelling the reviews with `names`.
+fn deliver(json: bool, tool: &str, plan: &Plan, delivery: Delivery, mut names: Names) {
+    let Delivery {
+        bundler: bundler_url,
+        rpc,
+        fees,
+        handle_ops,
+        beneficiary,
+        output: out_path,
+        timeout,
+        interval,
+        ens_rpc,
+        paymaster_service,
+        fee_token,
+        skip_balance_check,
+        yes,
+    } = delivery;
+    if bundler_url.is_none() && handle_ops.is_none() {
+        output::fail(json, "one of --bundler or --handle-ops is required");
+    }
+    let (chain_id, entry_point) = (plan.chain_id, plan.entry_point);
+    let groups = plan.accounts();
+    let keystore = output::or_fail(json, "keystore", Keystore::open_default());
+    let accounts: Vec<_> = groups
+        .iter()
+        .map(|g| output::or_fail(json, "failed to load account", keystore.load(&g.account)))
+        .collect();
+    for account in &accounts {
+        output::or_fail(json, "cannot sign the batch", account.require_seed());
+        if let Some(profile) = account.profiles.get(&chain_id) {
+            output::or_fail(
+                json,
+                &format!("cannot sign the batch as {}", account.name),
+                profile.check(chain_id, &profile::Request::new(entry_point)),
+            );
+        }
+    }
+    let senders: Vec<Address> = accounts
+        .iter()
+        .map(|a| {
+            a.addresses.get(&chain_id).copied().unwrap_or_else(|| {
+                output::fail(
+                    json,
+                    format!("account {} has no address on chain {chain_id}", a.name),
+                )
+            })
+        })
+        .collect();
+
+    let node = HttpTransport::new(rpc, Duration::from_secs(30));
+    if !skip_balance_check {
+        let mut shortfalls = Vec::new();
+        for (group, &sender) in groups.iter().zip(&senders) {
+            shortfalls.extend(output::or_fail(
+                json,
+                "balance check",
+                balance::preflight(&node, chain_id, sender, &group.calls),
+            ));
+        }
+        if !shortfalls.is_empty() {
+            if json {
+                output::emit(&shortfalls);
+            } else {
+                for shortfall in &shortfalls {
+                    eprintln!("{shortfall}");
+                }
+                eprintln!(
+                    "not signed: the ops would revert (pass --skip-balance-check to sign anyway)"
+                );
+            }
+            std::process::exit(output::EXIT_FAILURE);
+        }
+    }
+    let quote = output::or_fail(json, "fee quote", fees.quote(&node, chain_id));
+    let gas_fees = userop::pack_u128_pair(quote.max_priority_fee_per_gas, quote.max_fee_per_gas);
+    let token_quote = fee_token.map(|token| {
+        let service = HttpTransport::new(
+            paymaster_service.expect("--fee-token requires --paymaster-service"),
+            Duration::from_secs(30),
+        );
+        let quote = output::or_fail(
+            json,
+            "paymaster quote",
+            paymaster::quote(&service, token, entry_point, chain_id),
+        );
+        let (symbol, decimals) =
+            output::or_fail(json, "fee token", balance::describe(&node, chain_id, token));
+        (quote, symbol, decimals)
+    });
+    let bundler_rpc = bundler_url.map(|url| bundlers(&url, Some(chain_id), entry_point));
+    let calibration = Calibration {
+        bundle_size: groups.len() as u64,
+        ..Calibration::for_chain(chain_id)
+    };
+    if let (None, Some(rollup)) = (&bundler_rpc, calibration.rollup) {
+        eprintln!(
+            "warning: {} ({rollup:?}) charges an L1 data fee the local \
+             preVerificationGas leaves out; pass --bundler to estimate it",
+            calibration.chain
+        );
+    }
+    let built: pq_cli::Result<Vec<(PackedUserOperation, Option<TokenFee>)>> = groups
+        .par_iter()
+        .zip(senders.par_iter())
+        .zip(accounts.par_iter())
+        .map(|((group, &sender), account)| {
+            let nonce = simulate::get_nonce(&node, entry_point, sender)?;
+            let build = |calls: &[Call]| {
+                let mut op = plan.unsigned_op(sender, nonce, calls, gas_fees);
+                if let Some((quote, ..)) = &token_quote {
+                    op.paymaster_and_data =
+                        quote.paymaster_and_data(paymaster::DEFAULT_VERIFICATION_GAS);
+                }
+                match &bundler_rpc {
+                    Some(rpc) => {
+                        let probe = PackedUserOperation {
+                            signature: account.profiles.get(&chain_id).map_or_else(
+                                || vec![0xff; SIGNATURE_LEN],
+                                |p| p.placeholder_signature(),
+                            ),
+                            ..op.clone()
+                        };
+                        let estimate = bundler::estimate_gas(rpc, &probe, entry_point)?;
+                        plan.apply_estimate(&mut op, &estimate)?;
+                    }
+                    None => {
+                        let local = pre_verification::estimate(&op, &calibration, None)?;
+                        op.pre_verification_gas = U256::from(local.pre_verification_gas);
+                    }
+                }
+                Ok(op)
+            };
+            let Some((quote, symbol, decimals)) = &token_quote else {
+                return Ok((build(&group.calls)?, None));
+            };
+            let allowance = balance::allowance(&node, quote.token, sender, quote.paymaster)?;
+            let (op, max, approves) = paymaster::sponsor(quote, allowance, &group.calls, build)?;
+            let fee = TokenFee {
+                token: quote.token,
+                symbol: symbol.clone(),
+                decimals: *decimals,
+                max,
+                approves,
+            };
+            Ok((op, Some(fee)))
+        })
+        .collect();
+    let (mut ops, token_fees): (Vec<_>, Vec<_>) =
+        output::or_fail(json, "failed to build UserOperations", built)
+            .into_iter()
+            .unzip();
+    if !skip_balance_check {
+        for (op, fee) in ops.iter().zip(&token_fees) {
+            let Some(fee) = fee else { continue };
+            let held = output::or_fail(
+                json,
+                "balance check",
+                balance::balance_of(&node, fee.token, op.sender),
+            );
+            if held < fee.max {
+                output::fail(
+                    json,
+                    format!(
+                        "not signed: {} holds {} {} but gas can cost up to {} \
+                         (pass --skip-balance-check to sign anyway)",
+                        op.sender,
+                        decode::format_units(held, fee.decimals),
+                        fee.symbol,
+                        fee.amount()
+                    ),
+                );
+            }
+        }
+    }
+    let hashes: Vec<B256> = ops
+        .iter()
+        .map(|op| userop::compute_user_op_hash(op, entry_point, U256::from(chain_id)))
+        .collect();
+    let mut reviews: Vec<Review> = ops
+        .iter()
+        .zip(&hashes)
+        .map(|(op, &hash)| Review::new(op, chain_id, hash))
+        .collect();
+    if let Some(url) = ens_rpc {
+        let ens_rpc = HttpTransport::new(url, Duration::from_secs(30));
+        let mut lines: Vec<String> = reviews.iter().flat_map(|r| r.calls.clone()).collect();
+        lines.extend(reviews.iter().map(|r| r.sender.to_string()));
+        if let Err(e) = names.add_reverse(&ens_rpc, &lines) {
+            tracing::warn!("no reverse ENS names in the previews: {e}");
+        }
+    }
+    for ((review, op), fee) in reviews.iter_mut().zip(&ops).zip(token_fees) {
+        review.token_fee = fee;
+        review.label(names.clone());
+        review.assets = preview_assets(&node, op, entry_point, chain_id);
+    }
+    for review in &reviews {
+        review.print();
+    }
+    if !yes
+        && !output::or_fail(
+            json,
+            "no terminal to confirm on (pass --yes)",
+            cli::confirm(&format!("Sign {} UserOperations?", ops.len())),
+        )
+    {
+        output::fail(json, "not signed");
+    }
+
+    // Prompts one at a time; the Argon2 unlocks and signing run in parallel.
+    let passwords: Vec<_> = groups
+        .iter()
+        .map(|g| {
+            output::or_fail(
+                json,
+                "password",
+                cli::read_password(&format!("Password for {}: ", g.account), false),
+            )
+        })
+        .collect();
+    let signed: pq_cli::Result<Vec<(Vec<u8>, Address)>> = (0..ops.len())
+        .into_par_iter()
+        .map(|i| {
+            let seed = accounts[i].decrypt_seed(passwords[i].as_bytes())?;
+            let sk = SigningKey::<MlDsa65>::from_seed(&(*seed).into());
+            let scheme = accounts[i].signing_scheme(chain_id);
+            let sig = scheme.sign(&sk, &ops[i], entry_point, U256::from(chain_id));
+            let pk = sk.verifying_key().encode();
+            let field = match accounts[i].profiles.get(&chain_id) {
+                Some(profile) => profile.signature_field(&pk, &sig.encode())?,
+                None => sig.encode().to_vec(),
+            };
+            Ok((field, keys::address(&pk)))
+        })
+        .collect();
+    let signed = output::or_fail(json, "signing failed", signed);
+    let log = output::or_fail(json, "signature withheld", AuditLog::open_default());
+    for (i, (signature, key_id)) in signed.into_iter().enumerate() {
+        let record = Record {
+            tool: tool.to_string(),
+            account: Some(groups[i].account.clone()),
+            key_id,
+            digest: Some(hashes[i]),
+            chain_id: Some(chain_id),
+            signature: keccak256(&signature),
+            summary: reviews[i].calls.clone(),
+            backend: Backend::RustCrypto.to_string(),
+        };
+        output::or_fail(json, "signature withheld", log.append(record));
+        ops[i].signature = signature;
+    }
+    if let Some(path) = out_path {
+        let rendered = serde_json::to_string_pretty(&ops).expect("UserOperations serialize");
+        std::fs::write(&path, rendered).expect("failed to write UserOperations");
+        eprintln!("Signed UserOperations written to {}", path.display());
+    }
+
+    let mut outcomes: Vec<OpOutcome> = groups
+        .iter()
+        .zip(&ops)
+        .zip(&hashes)
+        .map(|((group, op), &user_op_hash)| OpOutcome {
+            account: group.account.clone(),
+            sender: op.sender,
+            user_op_hash,
+            error: None,
+            status: None,
+        })
+        .collect();
+    match (handle_ops, bundler_rpc) {
+        (Some(path), _) => {
+            let data = simulate::encode_handle_ops(&ops, beneficiary.unwrap());
+            let call = serde_json::json!({ "to": entry_point, "data": Bytes::from(data) });
+            std::fs::write(
+                &path,
+                serde_json::to_string_pretty(&call).expect("call serializes"),
+            )
+            .expect("failed to write handleOps call");
+            eprintln!(
+                "Send {} to execute all {} ops in one transaction",
+                path.display(),
+                ops.len()
+            );
+        }
+        (None, Some(bundler_rpc)) => {
+            let mut accepted = Vec::new();
+            for (i, op) in ops.iter().enumerate() {
+                match bundler::send_user_operation(&bundler_rpc, op, entry_point, None) {
+                    Ok(returned) => {
+                        if returned != hashes[i] {
+                            tracing::warn!("bundler returned {returned}, expected {}", hashes[i]);
+                        }
+                        accepted.push(i);
+                    }
+                    Err(e) => outcomes[i].error = Some(aa_errors::diagnose_error(&e, Some(op))),
+                }
+            }
+            let tracked: Vec<B256> = accepted.iter().map(|&i| hashes[i]).collect();
+            let statuses = output::or_fail(
+                json,
+                "status",
+                bundler::wait_for_all(
+                    &bundler_rpc,
+                    &tracked,
+                    Duration::from_secs(timeout),
+                    Duration::from_secs_f64(interval),
+                    |outstanding| {
+                        if !json {
+                            eprintln!("{outstanding} pending...");
+                        }
+                    },
+                ),
+            );
+            for (i, status) in accepted.into_iter().zip(statuses) {
+                outcomes[i].status = Some(status);
+            }
+        }
+        (None, None) => unreachable!("checked above"),
+    }
+    if json {
+        output::emit(&outcomes);
+    } else {
+        for outcome in &outcomes {
+            print_outcome(outcome);
+        }
+    }
+    let sent = outcomes
+        .iter()
+        .any(|o| o.error.is_some() || o.status.is_some());
+    if sent && !outcomes.iter().all(OpOutcome::succeeded) {
+        std::process::exit(output::EXIT_FAILURE);
+    }
+}
+
+/// `submit --all`: move each queued op for the chain one step along.
+#[cfg(feature = "queue")]
+fn submit_all(
+    json: bool,
+    bundler_url: String,
+    rpc: Option<String>,
+    chain_id: Option<u64>,
+    entry_point: Address,
+    fees: &FeeArgs,
+    finality: Option<Finality>,
+) {
+    let bundler_rpc = bundlers(&bundler_url, chain_id, entry_point);
+    let node = rpc.map(|url| HttpTransport::new(url, Duration::from_secs(30)));
+    if finality.is_some() && node.is_none() {
+        output::fail(json, "--finality needs --rpc");
+    }
+    let chain_id = chain_id.unwrap_or_else(|| {
+        let id: alloy_primitives::U64 = output::or_fail(
+            json,
+            "bundler eth_chainId",
+            bundler_rpc.call("eth_chainId", serde_json::json!([])),
+        );
+        id.to::<u64>()
+    });
+    let queue = output::or_fail(json, "failed to open queue", Queue::open_default());
+    let live = output::or_fail(
+        json,
+        "failed to read queue",
+        queue.list(&[queue::Status::Pending, queue::Status::Submitted]),
+    );
+    let mut results = Vec::new();
+    for entry in live.into_iter().filter(|e| e.chain_id == chain_id) {
+        let id = entry.id;
+        let _span = tracing::info_span!("queued", id, hash = %entry.user_op_hash).entered();
+        match advance(&queue, entry, &bundler_rpc, node.as_ref(), fees, finality) {
+            Ok(()) => {}
+            // The bundler or node did not answer: try again next run.
+            Err(e @ pq_cli::WalletError::Rpc(_)) => output::or_fail(
+                json,
+                "failed to update queue",
+                queue.record_attempt(id, &e.to_string()),
+            ),
+            Err(e) => output::fail(json, format!("entry {id}: {e}")),
+        }
+        results.push(output::or_fail(json, "failed to read queue", queue.get(id)));
+    }
+    if json {
+        output::emit(&results);
+    } else if results.is_empty() {
+        eprintln!("Nothing queued for chain {chain_id}");
+    } else {
+        for entry in &results {
+            print_entry(entry);
+        }
+    }
+    let now = attestation::now();
+    let stuck = results.iter().any(|e| {
+        e.status == queue::Status::Failed
+            || (e.status == queue::Status::Pending && !e.is_scheduled(now))
+    });
+    if stuck {
+        std::process::exit(output::EXIT_FAILURE);
+    }
+}
+
+/// Check a submitted op's progress, or send a pending one: ops whose nonce
+/// is already used fail without being sent, and a fee rejection re-signs the
+/// op when it has an account and there is a node to quote fees from. With
+/// `finality`, a successful op stays submitted until final, and goes back to
+/// pending if a reorg drops it.
+#[cfg(feature = "queue")]
+fn advance(
+    queue: &Queue,
+    entry: Entry,
+    bundler_rpc: &Failover,
+    node: Option<&HttpTransport>,
+    fees: &FeeArgs,
+    finality: Option<Finality>,
+) -> pq_cli::Result<()> {
+    let id = entry.id;
+    if entry.status == queue::Status::Submitted {
+        let status = bundler::status(bundler_rpc, entry.user_op_hash)?;
+        match (status.state, status.success) {
+            (State::Included, Some(true)) => {
+                let (Some(finality), Some(node), Some(tx)) =
+                    (finality, node, status.transaction_hash)
+                else {
+                    return queue.set_status(id, queue::Status::Included, None);
+                };
+                let block = status.block_number.unwrap_or_default().saturating_to();
+                let confirmation = Tracker::new(tx, block, finality).poll(node)?;
+                if confirmation.is_final {
+                    return queue.set_status(id, queue::Status::Included, None);
+                }
+                if !confirmation.dropped {
+                    return Ok(());
+                }
+                tracing::warn!(block, "reorged out; sending again");
+                let reason = format!("reorged out of block {block}");
+                queue.set_status(id, queue::Status::Pending, Some(&reason))?;
+            }
+            (State::Included, _) => {
+                let reason = match &status.revert_reason {
+                    Some(data) => format!("call reverted ({data})"),
+                    None => "call reverted".to_string(),
+                };
+                return queue.set_status(id, queue::Status::Failed, Some(&reason));
+            }
+            (State::Pending, _) => return Ok(()),
+            (State::Unknown, _) => {
+                tracing::warn!("dropped by the bundler; sending again");
+                queue.set_status(id, queue::Status::Pending, Some("dropped by the bundler"))?;
+            }
+        }
+    }
+
+    let now = attestation::now();
+    if entry.is_scheduled(now) {
+        tracing::debug!("time-locked until {}", entry.window.unwrap().valid_after);
+        return Ok(());
+    }
+    if let Some(window) = entry.window
+        && now >= window.valid_until
+    {
+        let reason = format!("validity window closed at {}", window.valid_until);
+        return queue.set_status(id, queue::Status::Failed, Some(&reason));
+    }
+    let op = &entry.user_op;
+    // Only key 0's sequence is checked; other keys are rare in queued ops.
+    if let Some(node) = node
+        && op.nonce >> 64 == U256::ZERO
+    {
+        let next = simulate::get_nonce(node, entry.entry_point, op.sender)?;
+        if next > op.nonce {
+            let reason = format!("nonce {} already used (account is at {next})", op.nonce);
+            return queue.set_status(id, queue::Status::Failed, Some(&reason));
+        }
+    }
+    let mut sent = bundler::send_user_operation(bundler_rpc, op, entry.entry_point, None);
+    let mut op = op.clone();
+    if let (Err(e), Some(account), Some(node)) = (&sent, &entry.account, node)
+        && queue::is_fee_rejection(e)
+    {
+        tracing::info!(error = %e, "re-pricing and re-signing");
+        let (resigned, hash) = reprice(&entry, account, bundler_rpc, node, fees)?;
+        queue.replace_op(id, &resigned, hash)?;
+        sent = bundler::send_user_operation(bundler_rpc, &resigned, entry.entry_point, None);
+        op = resigned;
+    }
+    match sent {
+        Ok(returned) => {
+            tracing::info!(%returned, "accepted by bundler");
+            queue.set_status(id, queue::Status::Submitted, None)
+        }
+        Err(e @ pq_cli::WalletError::Rpc(_)) => Err(e),
+        Err(e) => {
+            let diag = aa_errors::diagnose_error(&e, Some(&op));
+            queue.set_status(id, queue::Status::Failed, Some(&diag.reason))
+        }
+    }
+}
+
+/// Re-price a queued op at the current quote (never below its old fees),
+/// re-estimate its gas and re-sign it with keystore account `name`.
+#[cfg(feature = "queue")]
+fn reprice(
+    entry: &Entry,
+    name: &str,
+    bundler_rpc: &Failover,
+    node: &HttpTransport,
+    fees: &FeeArgs,
+) -> pq_cli::Result<(PackedUserOperation, B256)> {
+    let quote = fees.quote(node, entry.chain_id)?;
+    let mut op = entry.user_op.clone();
+    op.gas_fees = userop::pack_u128_pair(
+        quote
+            .max_priority_fee_per_gas
+            .max(op.max_priority_fee_per_gas()),
+        quote.max_fee_per_gas.max(op.max_fee_per_gas()),
+    );
+    let probe = PackedUserOperation {
+        signature: vec![0xff; SIGNATURE_LEN],
+        ..op.clone()
+    };
+    let estimate = bundler::estimate_gas(bundler_rpc, &probe, entry.entry_point)?;
+    op.pre_verification_gas = estimate.pre_verification_gas;
+    op.account_gas_limits = userop::pack_u128_pair(
+        estimate.verification_gas_limit.saturating_to(),
+        estimate.call_gas_limit.saturating_to(),
+    );
+    resign(entry, name, op, "pq-userop submit")
+}
+
+/// Sign `op`, a changed copy of a queued op, with keystore account `name`
+/// under its signing scheme for the entry's chain, and log it as `tool`. A
+/// bare windowed signature is re-signed under the same window, so a
+/// time-lock survives re-pricing.
+#[cfg(feature = "queue")]
+fn resign(
+    entry: &Entry,
+    name: &str,
+    mut op: PackedUserOperation,
+    tool: &str,
+) -> pq_cli::Result<(PackedUserOperation, B256)> {
+    let account = Keystore::open_default()?.load(name)?;
+    let chain = U256::from(entry.chain_id);
+    let hash = userop::compute_user_op_hash(&op, entry.entry_point, chain);
+    let seed = cli::unlock_account(&account)?;
+    let sk = SigningKey::<MlDsa65>::from_seed(&(*seed).into());
+    let scheme = account.signing_scheme(entry.chain_id);
+    op.signature = match ValidityWindow::of_field(&entry.user_op.signature) {
+        Some(window) => window.wrap(&sk.sign(window.signed_hash(hash, chain).as_slice()).encode()),
+        None => {
+            let sig = scheme.sign(&sk, &op, entry.entry_point, chain).encode();
+            match account.profiles.get(&entry.chain_id) {
+                Some(profile) => {
+                    let request = profile::Request::new(entry.entry_point);
+                    profile.check(entry.chain_id, &request)?;
+                    profile.signature_field(&sk.verifying_key().encode(), &sig)?
+                }
+                None => sig.to_vec(),
+            }
+        }
+    };
+    AuditLog::open_default()?.append(Record {
+        tool: tool.to_string(),
+        account: Some(name.to_string()),
+        key_id: keys::address(&sk.verifying_key().encode()),
+        digest: Some(hash),
+        chain_id: Some(entry.chain_id),
+        signature: keccak256(&op.signature),
+        summary: Review::new(&op, entry.chain_id, hash).calls,
+        backend: Backend::RustCrypto.to_string(),
+    })?;
+    Ok((op, hash))
+}
+
+/// Bring a queued op's entry in line with its confirmation: included once
+/// final and successful, pending again if dropped. Returns whether it was
+/// re-queued. Does nothing without a queue or an entry for `hash`.
+#[cfg(feature = "queue")]
+fn record_confirmation(
+    hash: B256,
+    confirmation: &Confirmation,
+    success: bool,
+) -> pq_cli::Result<bool> {
+    if !Queue::default_path()?.exists() {
+        return Ok(false);
+    }
+    let queue = Queue::open_default()?;
+    let Some(entry) = queue.find_by_hash(hash)? else {
+        return Ok(false);
+    };
+    if confirmation.dropped {
+        let reason = format!("reorged out of block {}", confirmation.block_number);
+        queue.set_status(entry.id, queue::Status::Pending, Some(&reason))?;
+        return Ok(true);
+    }
+    if confirmation.is_final && success {
+        queue.set_status(entry.id, queue::Status::Included, None)?;
+    }
+    Ok(false)
+}
+
+#[cfg(feature = "queue")]
+fn print_entry(entry: &Entry) {
+    let note = match (&entry.last_error, entry.window) {
+        (Some(error), _) => format!("  ({error})"),
+        (None, Some(window)) if entry.is_scheduled(attestation::now()) => {
+            format!("  (held until {})", window.valid_after)
+        }
+        (None, _) => String::new(),
+    };
+    println!(
+        "{:<13}{}  {}{note}",
+        entry.id, entry.user_op_hash, entry.status
+    );
+}
+
+fn print_outcome(outcome: &OpOutcome) {
+    let state = match (&outcome.error, &outcome.status) {
+        (Some(diag), _) => format!("rejected: {}", diag.reason),
+        (None, None) => "signed".to_string(),
+        (None, Some(status)) => match (status.state, status.success) {
+            (State::Included, Some(true)) => match status.transaction_hash {
+                Some(tx) => format!("included in {tx}"),
+                None => "included".to_string(),
+            },
+            (State::Included, _) => "included, call reverted".to_string(),
+            (State::Pending, _) => "pending".to_string(),
+            (State::Unknown, _) => "unknown to bundler".to_string(),
+        },
+    };
+    println!("{:<13}{}  {state}", outcome.account, outcome.user_op_hash);
+}
+
+fn print_confirmation(confirmation: &Confirmation) {
+    if confirmation.dropped {
+        println!(
+            "Finality:    dropped from block {} by a reorg",
+            confirmation.block_number
+        );
+        return;
+    }
+    let state = match (confirmation.is_final, confirmation.timed_out) {
+        (true, _) => "final",
+        (false, true) => "not final, timed out",
+        (false, false) => "not final",
+    };
+    println!(
+        "Finality:    {state} ({} confirmations)",
+        confirmation.confirmations
+    );
+    if confirmation.reorgs > 0 {
+        println!(
+            "Reorgs:      moved {} times, now in block {}",
+            confirmation.reorgs, confirmation.block_number
+        );
+    }
+}
+
+fn print_status(status: &UserOpStatus) {
+    println!("UserOp:      {}", status.user_op_hash);
+    match status.state {
+        State::Unknown => println!("State:       unknown to bundler"),
+        State::Pending => println!("State:       pending"),
+        State::Included => println!("State:       included"),
+    }
+    if let Some(tx) = status.transaction_hash {
+        println!("Transaction: {tx}");
+    }
+    if let Some(block) = status.block_number {
+        println!("Block:       {block}");
+    }
+    if let Some(success) = status.success {
+        println!("Success:     {success}");
+    }
+    if let (Some(used), Some(cost)) = (status.actual_gas_used, status.actual_gas_cost) {
+        println!("Gas used:    {used} (cost {cost} wei)");
+    }
+    if let Some(reason) = &status.revert_reason {
+        println!("Revert:      {reason}");
+    }
+    if status.timed_out {
+        println!("Timed out waiting for inclusion");
+    }
+}
@@ -0,0 +1,100 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use pq_cli::logging::LogArgs;
+use pq_cli::vectors;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+#[derive(Parser)]
+#[command(about = "Generate ML-DSA-65 test vectors for the on-chain verifier")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    log: LogArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Emit valid vectors plus one corrupted negative case per valid vector
+    Generate {
+        /// Number of valid vectors (output contains twice as many entries)
+        #[arg(long, default_value_t = 1)]
+        count: usize,
+
+        /// Follow each valid vector with one negative per corruption
+        /// category (c_tilde/z/hint bit-flips, wrong context, truncation,
+        /// wrong key, reused signature), labeled with the expected rejection
+        #[arg(long)]
+        categorized: bool,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = Format::Json)]
+        format: Format,
+
+        /// RNG seed for reproducible output (defaults to OS randomness)
+        #[arg(long)]
+        rng_seed: Option<u64>,
+
+        /// Write to a file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// Labeled camelCase JSON array
+    Json,
+    /// `{"vectors": [...]}` with alphabetical keys for `vm.parseJson`
+    Foundry,
+    /// `PK_HEX=... MSG_HASH=... SIG_HEX=...` lines for `eval`
+    Env,
+    /// A `fixtures.rs` module of `const` vectors and signed UserOperations
+    /// for other Rust crates' tests
+    Rust,
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.log.init();
+
+    match args.command {
+        Command::Generate {
+            count,
+            categorized,
+            format,
+            rng_seed,
+            output,
+        } => {
+            assert!(count > 0, "--count must be at least 1");
+            let mut rng = match rng_seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => rand::make_rng(),
+            };
+            let generated = if categorized {
+                vectors::generate_categorized(count, &mut rng)
+            } else {
+                vectors::generate(count, &mut rng)
+            };
+
+            let rendered = match format {
+                Format::Json => vectors::to_json(&generated),
+                Format::Foundry if categorized => vectors::to_foundry_labeled_json(&generated),
+                Format::Foundry => vectors::to_foundry_json(&generated),
+                Format::Env => vectors::to_env(&generated),
+                Format::Rust => vectors::to_rust(&generated),
+            };
+
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, rendered).expect("failed to write vectors");
+                    eprintln!("{} vectors written to {}", generated.len(), path.display());
+                }
+                None => println!("{rendered}"),
+            }
+        }
+    }
+}
@@ -1,46 +1,510 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use clap::Parser;
-use ml_dsa::{MlDsa65, signature::Verifier};
+use alloy_primitives::{B256, keccak256};
+use clap::{Parser, Subcommand};
+use ml_dsa::{MlDsa65, Signature, VerifyingKey};
+use pq_cli::backend::Backend;
+use pq_cli::batch::{self, Manifest};
+use pq_cli::cli::{MessageArgs, PersonalMessageArgs, PublicKeyArgs, SignatureFormat};
+use pq_cli::cose;
+use pq_cli::ct;
+use pq_cli::envelope::{self, Envelope, SCHEME_ML_DSA_65};
+use pq_cli::keys::{self, PUBLIC_KEY_LEN};
+use pq_cli::message::HashAlg;
+use pq_cli::output::{self, OutputArgs, VerifyOutput};
+use pq_cli::precompile;
+use pq_cli::rpc::HttpTransport;
+use pq_cli::schemes::{Detected, MlDsa65Scheme, Scheme, SchemeRegistry};
+use pq_cli::strict::{self, EnvelopeLayout};
+use pq_cli::verify_cache::{self, CacheKey, VerifyCache};
 
 #[derive(Parser)]
-#[command(about = "Verify an ML-DSA-65 signature against a 32-byte hash")]
+#[command(
+    about = "Verify an ML-DSA-65 signature over a 32-byte hash, a file, or stdin",
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    /// Path to public key (pk.bin)
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    key: PublicKeyArgs,
+
+    #[command(flatten)]
+    message: MessageArgs,
+
+    /// Path to signature file (sig.bin)
+    #[arg(long, required_unless_present = "manifest")]
+    sig: Option<PathBuf>,
+
+    /// The --sig file holds a signature envelope (abi, packed or
+    /// versioned) rather than a bare signature; its public key must match
+    /// the key given
+    #[arg(long, value_name = "LAYOUT")]
+    envelope: Option<EnvelopeLayout>,
+
+    /// Encoding of --sig: raw, or cose for a COSE_Sign1, whose kid must be
+    /// the key's fingerprint and whose payload must match any message given
+    #[arg(
+        long = "format",
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t = SignatureFormat::Raw,
+        conflicts_with_all = ["envelope", "onchain", "manifest"]
+    )]
+    sig_format: SignatureFormat,
+
+    /// Also reject signatures and envelopes that are not canonically
+    /// encoded, exactly as the on-chain verifier does
     #[arg(long)]
-    key: PathBuf,
+    strict: bool,
+
+    /// Verify every (publicKey, hash, signature) item in a JSON manifest
+    /// instead of a single signature
+    #[arg(long, conflicts_with_all = ["key", "account", "hash", "message_file", "stdin", "sig"])]
+    manifest: Option<PathBuf>,
+
+    /// Worker threads for --manifest (defaults to one per CPU)
+    #[arg(long, requires = "manifest")]
+    jobs: Option<usize>,
 
-    /// Hex-encoded 32-byte hash (with or without 0x prefix)
+    /// Also verify through an on-chain verifier via eth_call:
+    /// contract:ADDR, precompile:ADDR or precompile:ADDR:abi
+    #[arg(long, requires_all = ["rpc", "hash"])]
+    onchain: Option<String>,
+
+    /// Fail unless the public key hashes to this on-chain owner commitment
+    #[arg(long, conflicts_with = "manifest")]
+    expect_fingerprint: Option<B256>,
+
+    /// Hash used for --expect-fingerprint: keccak256 or sha3-256
+    #[arg(long, default_value = "keccak256", requires = "expect_fingerprint")]
+    fingerprint_hash: HashAlg,
+
+    /// Node JSON-RPC URL for --onchain
+    #[arg(long, env = "LOCAL_RPC")]
+    rpc: Option<String>,
+
+    /// Verify without reading or adding to the verification cache
+    /// ($PQ_WALLET_VERIFY_CACHE, else verify-cache.bin in the keystore root)
     #[arg(long)]
-    hash: String,
+    no_cache: bool,
 
-    /// Path to signature file (sig.bin)
+    /// Results the verification cache keeps
+    #[arg(long, default_value_t = verify_cache::DEFAULT_CAPACITY, conflicts_with = "no_cache")]
+    cache_size: usize,
+
+    /// ML-DSA-65 implementation to verify with: rustcrypto, or fips204
+    #[arg(long, global = true, default_value = "rustcrypto")]
+    backend: Backend,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Verify a `pq-sign personal` signature over an EIP-191 personal
+    /// message
+    Personal(PersonalArgs),
+}
+
+#[derive(clap::Args)]
+struct PersonalArgs {
+    #[command(flatten)]
+    key: PublicKeyArgs,
+
+    #[command(flatten)]
+    message: PersonalMessageArgs,
+
+    /// Path to the envelope or signature
     #[arg(long)]
     sig: PathBuf,
+
+    /// Layout of --sig: abi, packed or versioned; without it --sig is a
+    /// bare signature
+    #[arg(long, value_name = "LAYOUT")]
+    envelope: Option<EnvelopeLayout>,
 }
 
 fn main() {
-    let args = Args::parse();
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+
+    let json = args.format.json;
+    if let Some(Command::Personal(personal)) = &args.command {
+        verify_personal(personal, args.backend, json);
+    }
+    let cache = (!args.no_cache)
+        .then(|| open_cache(args.cache_size))
+        .flatten();
+    if let Some(path) = &args.manifest {
+        verify_manifest(path, args.jobs, cache.as_ref(), json);
+    }
+
+    let registry = SchemeRegistry::default();
+    let pk_bytes = output::or_fail(
+        json,
+        "failed to load public key",
+        args.key.public_key_bytes(),
+    );
+    let sig_data = std::fs::read(args.sig.as_ref().unwrap()).expect("failed to read signature");
+    if args.sig_format == SignatureFormat::Cose {
+        let mut result = match <[u8; PUBLIC_KEY_LEN]>::try_from(pk_bytes.as_slice()) {
+            Ok(pk_arr) => verify_cose(
+                &sig_data,
+                &args.message,
+                args.strict,
+                args.backend,
+                &pk_arr,
+                json,
+            ),
+            Err(_) => {
+                VerifyOutput::invalid("COSE_Sign1 signatures are ML-DSA-65 and the key is not")
+            }
+        };
+        result.scheme = Some(MlDsa65Scheme.name());
+        finish(result, &args, &pk_bytes, json);
+    }
+
+    let input = output::or_fail(json, "invalid message input", args.message.resolve());
+    let detected = match args.envelope {
+        Some(layout) => open_envelope(&registry, &sig_data, layout, &pk_bytes),
+        None => registry
+            .detect(&pk_bytes, &sig_data)
+            .map_err(|e| e.to_string()),
+    }
+    .and_then(|found| {
+        if args.strict {
+            check_strict(&found, &sig_data, &pk_bytes)?;
+        }
+        Ok(found)
+    });
+    let mut result = match &detected {
+        Err(reason) => VerifyOutput::invalid(reason.clone()),
+        Ok(found) => {
+            let ml_dsa_65 = found.scheme.id() == Some(SCHEME_ML_DSA_65);
+            let verified = if ml_dsa_65 && args.backend == Backend::RustCrypto {
+                // Through MessageInput, which streams --raw input.
+                let pk_arr: [u8; PUBLIC_KEY_LEN] = pk_bytes
+                    .as_slice()
+                    .try_into()
+                    .expect("detection checked the length");
+                let pk = VerifyingKey::<MlDsa65>::decode(&pk_arr.into());
+                match Signature::<MlDsa65>::try_from(found.signature.as_slice()) {
+                    Ok(sig) => match &cache {
+                        None => input.verify(&pk, &sig).map(|valid| (valid, false)),
+                        Some(cache) => input.mu_and_hash(&pk).and_then(|(mu, hash)| {
+                            let key = CacheKey::new(&pk_bytes, hash, &found.signature);
+                            cache.verify_with(key, || Ok(pk.verify_mu(&mu, &sig)))
+                        }),
+                    },
+                    Err(_) => Ok((false, false)),
+                }
+            } else {
+                // Other schemes, and fips204, which has no external-μ
+                // interface, take the whole message.
+                input.payload().and_then(|message| {
+                    let verify = || {
+                        if ml_dsa_65 {
+                            Ok(args
+                                .backend
+                                .verify(&pk_bytes, &message, &[], &found.signature))
+                        } else {
+                            found.scheme.verify(&pk_bytes, &message, &found.signature)
+                        }
+                    };
+                    match &cache {
+                        None => verify().map(|valid| (valid, false)),
+                        Some(cache) => {
+                            let key =
+                                CacheKey::new(&pk_bytes, keccak256(&message), &found.signature);
+                            cache.verify_with(key, verify)
+                        }
+                    }
+                })
+            };
+            let (mut result, cached) = match verified {
+                Ok((true, cached)) => (VerifyOutput::valid(), cached),
+                Ok((false, cached)) => (
+                    VerifyOutput::invalid("signature does not verify under this key"),
+                    cached,
+                ),
+                Err(e) => output::fail(json, format!("failed to read message: {e}")),
+            };
+            result.scheme = Some(found.scheme.name());
+            result.cached = cached;
+            if !json {
+                let layout = found
+                    .envelope
+                    .map(|layout| format!(", from a {layout} envelope"))
+                    .unwrap_or_default();
+                let cached = if cached { " (cached result)" } else { "" };
+                eprintln!("Scheme: {}{layout}{cached}", found.scheme.name());
+            }
+            result
+        }
+    };
+
+    if let Some(spec) = &args.onchain {
+        let Ok(found) = &detected else {
+            output::fail(json, "--onchain: no signature to send");
+        };
+        let Ok(pk_arr) = <[u8; PUBLIC_KEY_LEN]>::try_from(pk_bytes.as_slice()) else {
+            output::fail(
+                json,
+                format!(
+                    "--onchain verifies ML-DSA-65 only, not {}",
+                    found.scheme.name()
+                ),
+            );
+        };
+        let verifier = output::or_fail(json, "--onchain", precompile::parse_verifier(spec));
+        let node = HttpTransport::new(args.rpc.clone().unwrap(), Duration::from_secs(30));
+        let hash = output::or_fail(
+            json,
+            "invalid hash",
+            ct::decode_hex_array::<32>("hash", args.message.hash.as_deref().unwrap()),
+        );
+        let onchain = output::or_fail(
+            json,
+            "on-chain verification",
+            precompile::verify_on_chain(
+                &node,
+                verifier.as_ref(),
+                hash.into(),
+                &pk_arr,
+                &found.signature,
+            ),
+        );
+        if onchain != result.valid {
+            result = VerifyOutput::invalid(format!(
+                "{verifier} says {}, local verification says {}",
+                if onchain { "valid" } else { "invalid" },
+                if result.valid { "valid" } else { "invalid" },
+            ));
+        }
+    }
 
-    let pk_bytes = std::fs::read(&args.key).expect("failed to read public key");
-    let pk_arr: [u8; 1952] = pk_bytes
-        .try_into()
-        .expect("public key must be exactly 1952 bytes");
-    let pk = ml_dsa::VerifyingKey::<MlDsa65>::decode(&pk_arr.into());
+    #[cfg(feature = "metrics")]
+    if let Some(cache) = &cache {
+        pq_cli::metrics::verify_cache_lookups("pq-verify", &cache.stats());
+    }
+    finish(result, &args, &pk_bytes, json);
+}
+
+/// The verification cache, or none if it cannot be opened: verifying
+/// without it is only slower.
+fn open_cache(capacity: usize) -> Option<VerifyCache> {
+    VerifyCache::open_default(capacity)
+        .inspect_err(|e| tracing::warn!("verifying without the cache: {e}"))
+        .ok()
+}
+
+/// Apply --expect-fingerprint, report and exit.
+fn finish(mut result: VerifyOutput, args: &Args, pk_arr: &[u8], json: bool) -> ! {
+    if let Some(expected) = args.expect_fingerprint {
+        let actual = keys::fingerprint(pk_arr, args.fingerprint_hash);
+        if actual != expected {
+            result = VerifyOutput::invalid(format!(
+                "public key {} fingerprint {actual} does not match expected {expected}",
+                args.fingerprint_hash
+            ));
+        }
+    }
+
+    report(result, json)
+}
 
-    let hash_hex = args.hash.strip_prefix("0x").unwrap_or(&args.hash);
-    let hash_bytes = hex::decode(hash_hex).expect("invalid hex in --hash");
-    assert!(hash_bytes.len() == 32, "hash must be exactly 32 bytes");
+fn report(result: VerifyOutput, json: bool) -> ! {
+    #[cfg(feature = "metrics")]
+    if !result.valid {
+        pq_cli::metrics::verification_failure("pq-verify");
+    }
+    if json {
+        output::emit(&result);
+    } else if let Some(reason) = &result.reason {
+        println!("Invalid");
+        eprintln!("{reason}");
+    } else {
+        println!("Valid");
+    }
+    std::process::exit(result.exit_code());
+}
 
-    let sig_bytes = std::fs::read(&args.sig).expect("failed to read signature");
-    let sig = ml_dsa::Signature::<MlDsa65>::try_from(sig_bytes.as_slice())
-        .expect("invalid signature (must be 3309 bytes)");
+/// The mirror of `pq-sign personal`: the same EIP-191 hash, bound the same
+/// way, under the key given.
+fn verify_personal(args: &PersonalArgs, backend: Backend, json: bool) -> ! {
+    let personal = output::or_fail(json, "invalid message", args.message.resolve());
+    let pk_arr = output::or_fail(json, "failed to load public key", args.key.public_key());
+    let data = std::fs::read(&args.sig)
+        .unwrap_or_else(|e| output::fail(json, format!("{}: {e}", args.sig.display())));
+    let registry = SchemeRegistry::default();
+    let signature = match args.envelope {
+        Some(layout) => open_envelope(&registry, &data, layout, &pk_arr).and_then(|found| {
+            match found.scheme.id() {
+                Some(SCHEME_ML_DSA_65) => Ok(found.signature),
+                _ => Err(format!(
+                    "personal messages are signed with ML-DSA-65, not {}",
+                    found.scheme.name()
+                )),
+            }
+        }),
+        None => Ok(data),
+    };
+    let mut result = match signature {
+        Err(reason) => VerifyOutput::invalid(reason),
+        Ok(sig) if backend.verify(&pk_arr, personal.signed_hash().as_slice(), &[], &sig) => {
+            VerifyOutput::valid()
+        }
+        Ok(_) => VerifyOutput::invalid("signature does not verify under this key"),
+    };
+    result.scheme = Some(MlDsa65Scheme.name());
+    if !json {
+        eprintln!("Message hash: {}", personal.hash());
+    }
+    report(result, json)
+}
 
-    match pk.verify(&hash_bytes, &sig) {
-        Ok(()) => println!("Valid"),
-        Err(_) => {
-            println!("Invalid");
-            std::process::exit(1);
+/// Check a COSE_Sign1: its kid, if any, names this key, and an attached
+/// payload is the message given, if one is. A detached payload needs one.
+fn verify_cose(
+    data: &[u8],
+    message: &MessageArgs,
+    strict: bool,
+    backend: Backend,
+    pk: &[u8],
+    json: bool,
+) -> VerifyOutput {
+    let cose = match cose::decode_sign1(data) {
+        Ok(cose) => cose,
+        Err(e) => return VerifyOutput::invalid(e.to_string()),
+    };
+    if strict && let Err(v) = strict::check_signature(&cose.signature) {
+        return VerifyOutput::invalid(format!("non-canonical encoding: {v}"));
+    }
+    let kid = keys::fingerprint(pk, HashAlg::Keccak256);
+    if cose.kid.as_deref().is_some_and(|k| k != kid.as_slice()) {
+        return VerifyOutput::invalid("COSE_Sign1 kid is not this key's fingerprint");
+    }
+    let given = message.is_present().then(|| {
+        output::or_fail(
+            json,
+            "invalid message input",
+            message.resolve().and_then(|input| input.payload()),
+        )
+    });
+    let payload = match (cose.payload.as_ref(), given) {
+        (Some(attached), Some(given)) if *attached != given => {
+            return VerifyOutput::invalid("COSE_Sign1 carries a different message");
         }
+        (Some(attached), _) => attached.clone(),
+        (None, Some(given)) => given,
+        (None, None) => output::fail(
+            json,
+            "the COSE_Sign1 payload is detached; give the message with --hash, --message-file \
+             or --stdin",
+        ),
+    };
+    if cose.verify_on(backend, pk, &payload) {
+        VerifyOutput::valid()
+    } else {
+        VerifyOutput::invalid("signature does not verify under this key")
     }
-}
\ No newline at end of file
+}
+
+/// Pull the signature out of an envelope without the --strict checks.
+fn open_envelope<'r>(
+    registry: &'r SchemeRegistry,
+    data: &[u8],
+    layout: EnvelopeLayout,
+    pk: &[u8],
+) -> Result<Detected<'r>, String> {
+    let decoded = match layout {
+        EnvelopeLayout::Abi => envelope::decode_signature_envelope(data),
+        EnvelopeLayout::Packed => envelope::decode_signature_envelope_packed(data),
+        EnvelopeLayout::Versioned => Envelope::decode(data).map(|env| env.pq().clone()),
+    }
+    .map_err(|e| format!("malformed {layout} envelope: {e}"))?;
+    if decoded.public_key != pk {
+        return Err("envelope carries a different public key".into());
+    }
+    let scheme = registry
+        .by_id(decoded.scheme_id)
+        .ok_or_else(|| format!("envelope scheme {} is unknown", decoded.scheme_id))?;
+    Ok(Detected {
+        scheme,
+        signature: decoded.signature,
+        envelope: Some(layout),
+    })
+}
+
+/// The --strict encoding checks, which only ML-DSA-65 has.
+fn check_strict(found: &Detected, data: &[u8], pk: &[u8]) -> Result<(), String> {
+    match found.envelope {
+        Some(layout) => strict::check_envelope(data, layout, Some(pk)).map(|_| ()),
+        None if found.scheme.id() == Some(SCHEME_ML_DSA_65) => {
+            strict::check_signature(&found.signature)
+        }
+        None => {
+            return Err(format!(
+                "--strict knows only ML-DSA-65's encoding, not {}'s",
+                found.scheme.name()
+            ));
+        }
+    }
+    .map_err(|v| format!("non-canonical encoding: {v}"))
+}
+
+fn verify_manifest(path: &Path, jobs: Option<usize>, cache: Option<&VerifyCache>, json: bool) -> ! {
+    let manifest = output::or_fail(json, "failed to load manifest", Manifest::load(path));
+    let mut pool = rayon::ThreadPoolBuilder::new();
+    if let Some(n) = jobs {
+        pool = pool.num_threads(n);
+    }
+    let report = pool
+        .build()
+        .expect("failed to start worker threads")
+        .install(|| batch::verify_all_cached(&manifest, cache));
+    #[cfg(feature = "metrics")]
+    if let Some(stats) = &report.summary.cache {
+        pq_cli::metrics::verify_cache_lookups("pq-verify", stats);
+    }
+
+    if json {
+        output::emit(&report);
+    } else {
+        for r in &report.results {
+            let label = r.name.as_deref().unwrap_or("");
+            match (&r.error, r.passed) {
+                (Some(e), _) => println!("ERROR #{} {label} {e}", r.index),
+                (None, true) => println!("ok    #{} {label}", r.index),
+                (None, false) => println!(
+                    "FAIL  #{} {label} (valid={}, expected={})",
+                    r.index,
+                    r.valid.unwrap_or(false),
+                    r.expected
+                ),
+            }
+        }
+        let s = &report.summary;
+        println!(
+            "{} items: {} passed, {} failed ({} errors) in {} ms ({:.0}/s)",
+            s.total, s.passed, s.failed, s.errors, s.elapsed_ms, s.per_second
+        );
+        if let Some(c) = &s.cache {
+            println!(
+                "cache: {} hits, {} misses ({:.0}% hit rate)",
+                c.hits,
+                c.misses,
+                c.hit_rate * 100.0
+            );
+        }
+    }
+    std::process::exit(if report.all_passed() {
+        output::EXIT_OK
+    } else {
+        output::EXIT_FAILURE
+    })
+}
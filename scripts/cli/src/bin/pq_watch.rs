@@ -0,0 +1,142 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use alloy_primitives::{Address, U64};
+use clap::Parser;
+use pq_cli::notify::{Event, Notification, Notifier, Sink};
+use pq_cli::output::{self, EXIT_FAILURE, OutputArgs};
+use pq_cli::rpc::{HttpTransport, Transport};
+use pq_cli::userop::ENTRY_POINT_V07;
+use pq_cli::watch::{Severity, Watcher};
+
+#[derive(Parser)]
+#[command(
+    about = "Watch a smart account's UserOperations and module changes, and alert on the \
+                   risky ones"
+)]
+struct Args {
+    /// Smart account to watch
+    #[arg(long)]
+    account: Address,
+
+    /// Node JSON-RPC URL
+    #[arg(long, env = "LOCAL_RPC")]
+    rpc: String,
+
+    /// EntryPoint address
+    #[arg(long, default_value_t = ENTRY_POINT_V07)]
+    entry_point: Address,
+
+    /// First block to read [default: the node's head]
+    #[arg(long)]
+    from_block: Option<u64>,
+
+    /// A module whose install is expected (repeatable); any other install
+    /// is an alert
+    #[arg(long = "expect-module")]
+    expect_modules: Vec<Address>,
+
+    /// Seconds between polls
+    #[arg(long, default_value_t = 12.0)]
+    interval: f64,
+
+    /// Read up to the head once and exit, 1 if there was an alert
+    #[arg(long)]
+    once: bool,
+
+    /// Print only alerts
+    #[arg(long)]
+    alerts_only: bool,
+
+    /// POST each alert's JSON to this URL
+    #[arg(long)]
+    webhook: Option<String>,
+
+    /// Notification sinks (TOML or JSON) to send alerts to, as in
+    /// pq-signerd
+    #[arg(long)]
+    notify: Option<PathBuf>,
+
+    #[command(flatten)]
+    format: OutputArgs,
+}
+
+fn main() {
+    let args: Args = pq_cli::config::parse();
+    args.format.log.init();
+    let json = args.format.json;
+
+    let node = HttpTransport::new(&args.rpc, Duration::from_secs(30));
+    let from_block = match args.from_block {
+        Some(block) => block,
+        None => output::or_fail(
+            json,
+            "eth_blockNumber",
+            node.call::<U64>("eth_blockNumber", serde_json::json!([])),
+        )
+        .to::<u64>(),
+    };
+    let expected: BTreeSet<Address> = args.expect_modules.into_iter().collect();
+    let mut notifier = match &args.notify {
+        Some(path) => output::or_fail(json, "notifications", Notifier::load(path)),
+        None => Notifier::default(),
+    };
+    if let Some(url) = &args.webhook {
+        // The bare event, as before there were notification sinks.
+        notifier.sinks.push(Sink {
+            template: Some(serde_json::json!("{{data}}")),
+            ..Sink::webhook(url, [Event::WatchAlert])
+        });
+    }
+    let mut watcher = Watcher::new(args.account, args.entry_point, expected, from_block);
+    if !json {
+        eprintln!("Watching {} from block {from_block}", args.account);
+    }
+
+    let mut alerted = false;
+    loop {
+        match watcher.poll(&node) {
+            Ok(events) => {
+                for event in &events {
+                    let alert = event.severity == Severity::Alert;
+                    alerted |= alert;
+                    if alert {
+                        notifier.notify(&Notification::new(
+                            Event::WatchAlert,
+                            "pq-watch",
+                            event.summary(),
+                            serde_json::to_value(event).expect("event serializes"),
+                        ));
+                    }
+                    if args.alerts_only && !alert {
+                        continue;
+                    }
+                    if json {
+                        output::emit(event);
+                    } else if alert {
+                        println!("ALERT {}", event.summary());
+                    } else {
+                        println!("      {}", event.summary());
+                    }
+                }
+            }
+            Err(e) if args.once => output::fail(json, format!("poll failed: {e}")),
+            Err(e) => {
+                tracing::warn!(error = %e, "poll failed");
+                std::thread::sleep(Duration::from_secs_f64(args.interval));
+                continue;
+            }
+        }
+        // A watcher far behind catches up without waiting between polls.
+        if watcher.caught_up() {
+            if args.once {
+                break;
+            }
+            std::thread::sleep(Duration::from_secs_f64(args.interval));
+        }
+    }
+    if alerted {
+        std::process::exit(EXIT_FAILURE);
+    }
+}
@@ -0,0 +1,456 @@
+//! ERC-4337 bundler RPC: UserOperation lookup and receipt tracking.
+//!
+//! [`wait_for_status`] polls `eth_getUserOperationReceipt` (and
+//! `eth_getUserOperationByHash` while no receipt exists) until the op is
+//! included or the timeout expires. Success and gas figures come from the
+//! EntryPoint's `UserOperationEvent` in the transaction logs rather than the
+//! bundler's own summary fields, so a misreporting bundler can't make a
+//! reverted op look successful.
+
+use std::time::{Duration, Instant};
+
+use alloy_primitives::{Address, B256, Bytes, U256};
+use alloy_sol_types::{SolEvent, sol};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, WalletError};
+use crate::rpc::Transport;
+use crate::userop::PackedUserOperation;
+
+sol! {
+    /// EntryPoint v0.7 `IEntryPoint.UserOperationEvent`.
+    event UserOperationEvent(
+        bytes32 indexed userOpHash,
+        address indexed sender,
+        address indexed paymaster,
+        uint256 nonce,
+        bool success,
+        uint256 actualGasCost,
+        uint256 actualGasUsed
+    );
+
+    /// Emitted before `UserOperationEvent` when the op's execution reverts.
+    event UserOperationRevertReason(
+        bytes32 indexed userOpHash,
+        address indexed sender,
+        uint256 nonce,
+        bytes revertReason
+    );
+}
+
+/// An RPC log entry.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Log {
+    pub address: Address,
+    pub topics: Vec<B256>,
+    pub data: Bytes,
+}
+
+/// The transaction receipt nested in a UserOperation receipt.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionReceipt {
+    pub transaction_hash: B256,
+    pub block_number: U256,
+    #[serde(default)]
+    pub logs: Vec<Log>,
+}
+
+/// `eth_getUserOperationReceipt` result.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationReceipt {
+    pub user_op_hash: B256,
+    pub entry_point: Address,
+    pub receipt: TransactionReceipt,
+    #[serde(default)]
+    pub logs: Vec<Log>,
+}
+
+/// `eth_getUserOperationByHash` result (only the fields we track).
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationLookup {
+    pub entry_point: Address,
+    #[serde(default)]
+    pub transaction_hash: Option<B256>,
+    #[serde(default)]
+    pub block_number: Option<U256>,
+}
+
+/// Where an op is in its lifecycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+    /// The bundler has never heard of this hash.
+    Unknown,
+    /// Known to the bundler, not yet in a block.
+    Pending,
+    /// Mined; see `success`.
+    Included,
+}
+
+/// What `pq-userop status` reports.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOpStatus {
+    pub user_op_hash: B256,
+    pub state: State,
+    pub transaction_hash: Option<B256>,
+    pub block_number: Option<U256>,
+    /// `UserOperationEvent.success`.
+    pub success: Option<bool>,
+    pub actual_gas_used: Option<U256>,
+    pub actual_gas_cost: Option<U256>,
+    /// Raw revert data from `UserOperationRevertReason`, if emitted.
+    pub revert_reason: Option<Bytes>,
+    /// True if polling stopped at the timeout rather than on inclusion.
+    pub timed_out: bool,
+}
+
+impl UserOpStatus {
+    fn new(user_op_hash: B256, state: State) -> Self {
+        UserOpStatus {
+            user_op_hash,
+            state,
+            transaction_hash: None,
+            block_number: None,
+            success: None,
+            actual_gas_used: None,
+            actual_gas_cost: None,
+            revert_reason: None,
+            timed_out: false,
+        }
+    }
+
+    /// Build the status for an included op from its receipt, decoding the
+    /// EntryPoint events for `user_op_hash`.
+    pub fn from_receipt(user_op_hash: B256, receipt: &UserOperationReceipt) -> Result<Self> {
+        let mut status = UserOpStatus::new(user_op_hash, State::Included);
+        status.transaction_hash = Some(receipt.receipt.transaction_hash);
+        status.block_number = Some(receipt.receipt.block_number);
+
+        let from_entry_point = |log: &&Log| {
+            log.address == receipt.entry_point && log.topics.get(1) == Some(&user_op_hash)
+        };
+        let logs = receipt.receipt.logs.iter().chain(&receipt.logs);
+        for log in logs.filter(from_entry_point) {
+            match log.topics.first() {
+                Some(t) if *t == UserOperationEvent::SIGNATURE_HASH => {
+                    let event =
+                        UserOperationEvent::decode_raw_log(log.topics.iter().copied(), &log.data)
+                            .map_err(|e| WalletError::Rpc(format!("UserOperationEvent: {e}")))?;
+                    status.success = Some(event.success);
+                    status.actual_gas_used = Some(event.actualGasUsed);
+                    status.actual_gas_cost = Some(event.actualGasCost);
+                }
+                Some(t) if *t == UserOperationRevertReason::SIGNATURE_HASH => {
+                    let event = UserOperationRevertReason::decode_raw_log(
+                        log.topics.iter().copied(),
+                        &log.data,
+                    )
+                    .map_err(|e| WalletError::Rpc(format!("UserOperationRevertReason: {e}")))?;
+                    status.revert_reason = Some(event.revertReason);
+                }
+                _ => {}
+            }
+        }
+        if status.success.is_none() {
+            return Err(WalletError::Rpc(format!(
+                "receipt for {user_op_hash} has no UserOperationEvent from {}",
+                receipt.entry_point
+            )));
+        }
+        Ok(status)
+    }
+}
+
+/// `eth_estimateUserOperationGas` result.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GasEstimate {
+    pub pre_verification_gas: U256,
+    pub verification_gas_limit: U256,
+    pub call_gas_limit: U256,
+    #[serde(default)]
+    pub paymaster_verification_gas_limit: Option<U256>,
+    #[serde(default)]
+    pub paymaster_post_op_gas_limit: Option<U256>,
+}
+
+/// Ask the bundler to estimate `op`'s gas limits.
+pub fn estimate_gas<T: Transport>(
+    rpc: &T,
+    op: &PackedUserOperation,
+    entry_point: Address,
+) -> Result<GasEstimate> {
+    rpc.call(
+        "eth_estimateUserOperationGas",
+        serde_json::json!([op, entry_point]),
+    )
+}
+
+/// `eth_sendUserOperation`. With `aggregator`, the op object also carries
+/// an `aggregator` field naming the `IAggregator` that validates its
+/// signature (see [`crate::aggregator`]), so an aggregating bundler can
+/// group it without first simulating to discover one.
+pub fn send_user_operation<T: Transport>(
+    rpc: &T,
+    op: &PackedUserOperation,
+    entry_point: Address,
+    aggregator: Option<Address>,
+) -> Result<B256> {
+    let mut op_json = serde_json::to_value(op).expect("UserOperations serialize");
+    if let Some(aggregator) = aggregator {
+        op_json["aggregator"] = serde_json::json!(aggregator);
+    }
+    rpc.call(
+        "eth_sendUserOperation",
+        serde_json::json!([op_json, entry_point]),
+    )
+}
+
+/// One lookup, no waiting.
+#[tracing::instrument(level = "debug", name = "status", skip(rpc))]
+pub fn status<T: Transport>(rpc: &T, user_op_hash: B256) -> Result<UserOpStatus> {
+    let params = serde_json::json!([user_op_hash]);
+    let receipt: Option<UserOperationReceipt> =
+        rpc.call("eth_getUserOperationReceipt", params.clone())?;
+    if let Some(receipt) = receipt {
+        return UserOpStatus::from_receipt(user_op_hash, &receipt);
+    }
+    let lookup: Option<UserOperationLookup> = rpc.call("eth_getUserOperationByHash", params)?;
+    Ok(match lookup {
+        None => UserOpStatus::new(user_op_hash, State::Unknown),
+        Some(op) => {
+            // Mined but the bundler hasn't indexed the receipt yet: keep polling.
+            let mut status = UserOpStatus::new(user_op_hash, State::Pending);
+            status.transaction_hash = op.transaction_hash;
+            status.block_number = op.block_number;
+            status
+        }
+    })
+}
+
+/// Poll [`status`] every `interval` until the op is included or `timeout`
+/// elapses. `on_poll` sees every intermediate status (for progress output).
+pub fn wait_for_status<T: Transport>(
+    rpc: &T,
+    user_op_hash: B256,
+    timeout: Duration,
+    interval: Duration,
+    mut on_poll: impl FnMut(&UserOpStatus),
+) -> Result<UserOpStatus> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let mut current = status(rpc, user_op_hash)?;
+        tracing::debug!(state = ?current.state, "polled");
+        if current.state == State::Included {
+            return Ok(current);
+        }
+        on_poll(&current);
+        if Instant::now() + interval > deadline {
+            current.timed_out = true;
+            return Ok(current);
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// [`wait_for_status`] for several ops at once: poll every op not yet
+/// included, every `interval`, until all are or `timeout` elapses.
+/// `on_poll` sees how many are still outstanding after each round.
+pub fn wait_for_all<T: Transport>(
+    rpc: &T,
+    user_op_hashes: &[B256],
+    timeout: Duration,
+    interval: Duration,
+    mut on_poll: impl FnMut(usize),
+) -> Result<Vec<UserOpStatus>> {
+    let deadline = Instant::now() + timeout;
+    let mut statuses: Vec<UserOpStatus> = user_op_hashes
+        .iter()
+        .map(|&hash| UserOpStatus::new(hash, State::Unknown))
+        .collect();
+    loop {
+        for current in statuses.iter_mut().filter(|s| s.state != State::Included) {
+            *current = status(rpc, current.user_op_hash)?;
+        }
+        let outstanding = statuses
+            .iter()
+            .filter(|s| s.state != State::Included)
+            .count();
+        tracing::debug!(outstanding, "polled");
+        if outstanding == 0 {
+            return Ok(statuses);
+        }
+        on_poll(outstanding);
+        if Instant::now() + interval > deadline {
+            for current in statuses.iter_mut().filter(|s| s.state != State::Included) {
+                current.timed_out = true;
+            }
+            return Ok(statuses);
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::userop::ENTRY_POINT_V07 as EP;
+    use alloy_primitives::{LogData, address};
+    use serde_json::{Value, json};
+    use std::cell::Cell;
+
+    /// Returns `null` receipts for the first `pending_polls` polls, then `receipt`.
+    struct Canned {
+        receipt: Value,
+        pending_polls: Cell<usize>,
+    }
+
+    impl Transport for Canned {
+        fn request(&self, method: &str, _params: Value) -> Result<Value> {
+            match method {
+                "eth_getUserOperationReceipt" if self.pending_polls.get() == 0 => {
+                    Ok(self.receipt.clone())
+                }
+                "eth_getUserOperationReceipt" => {
+                    self.pending_polls.set(self.pending_polls.get() - 1);
+                    Ok(Value::Null)
+                }
+                "eth_getUserOperationByHash" => Ok(json!({"entryPoint": EP})),
+                other => panic!("unexpected {other}"),
+            }
+        }
+    }
+
+    fn receipt_json(hash: B256, success: bool) -> Value {
+        let sender = address!("0x1111111111111111111111111111111111111111");
+        let event = UserOperationEvent {
+            userOpHash: hash,
+            sender,
+            paymaster: Address::ZERO,
+            nonce: U256::from(1),
+            success,
+            actualGasCost: U256::from(42_000_000u64),
+            actualGasUsed: U256::from(420_000u64),
+        };
+        let LogData { data, .. } = event.encode_log_data();
+        let topics: Vec<B256> = event.encode_topics().into_iter().map(|t| t.0).collect();
+        json!({
+            "userOpHash": hash,
+            "entryPoint": EP,
+            "sender": sender,
+            "success": !success, // deliberately wrong: the event is authoritative
+            "receipt": {
+                "transactionHash": B256::repeat_byte(0xEE),
+                "blockNumber": "0x10",
+                "logs": [
+                    {"address": sender, "topics": [B256::repeat_byte(1)], "data": "0x"},
+                    {"address": EP, "topics": topics, "data": data},
+                ],
+            },
+        })
+    }
+
+    #[test]
+    fn decodes_user_operation_event() {
+        let hash = B256::repeat_byte(0xAA);
+        let receipt: UserOperationReceipt =
+            serde_json::from_value(receipt_json(hash, false)).unwrap();
+        let status = UserOpStatus::from_receipt(hash, &receipt).unwrap();
+        assert_eq!(status.state, State::Included);
+        assert_eq!(status.success, Some(false));
+        assert_eq!(status.actual_gas_used, Some(U256::from(420_000u64)));
+        assert_eq!(status.block_number, Some(U256::from(16)));
+    }
+
+    #[test]
+    fn polls_until_included() {
+        let hash = B256::repeat_byte(0xAB);
+        let rpc = Canned {
+            receipt: receipt_json(hash, true),
+            pending_polls: Cell::new(2),
+        };
+        let mut seen = Vec::new();
+        let status = wait_for_status(
+            &rpc,
+            hash,
+            Duration::from_secs(5),
+            Duration::from_millis(1),
+            |s| seen.push(s.state),
+        )
+        .unwrap();
+        assert_eq!(seen, [State::Pending, State::Pending]);
+        assert_eq!(status.success, Some(true));
+        assert!(!status.timed_out);
+    }
+
+    #[test]
+    fn times_out_while_pending() {
+        let hash = B256::repeat_byte(0xAC);
+        let rpc = Canned {
+            receipt: Value::Null,
+            pending_polls: Cell::new(usize::MAX),
+        };
+        let status =
+            wait_for_status(&rpc, hash, Duration::ZERO, Duration::from_millis(1), |_| {}).unwrap();
+        assert!(status.timed_out);
+        assert_eq!(status.state, State::Pending);
+    }
+
+    #[test]
+    fn waits_for_every_op() {
+        let hash = B256::repeat_byte(0xAD);
+        // The first lookup is still pending, the second already included.
+        let rpc = Canned {
+            receipt: receipt_json(hash, true),
+            pending_polls: Cell::new(1),
+        };
+        let mut rounds = Vec::new();
+        let statuses = wait_for_all(
+            &rpc,
+            &[hash, hash],
+            Duration::from_secs(5),
+            Duration::from_millis(1),
+            |outstanding| rounds.push(outstanding),
+        )
+        .unwrap();
+        assert_eq!(rounds, [1]);
+        assert!(
+            statuses
+                .iter()
+                .all(|s| s.success == Some(true) && !s.timed_out)
+        );
+    }
+
+    #[test]
+    fn send_names_the_aggregator() {
+        /// Records the op object it was sent.
+        struct Capture(std::cell::RefCell<Value>);
+        impl Transport for Capture {
+            fn request(&self, _method: &str, params: Value) -> Result<Value> {
+                *self.0.borrow_mut() = params[0].clone();
+                Ok(json!(B256::ZERO))
+            }
+        }
+        let op = crate::userop::PackedUserOperation {
+            sender: Address::ZERO,
+            nonce: U256::ZERO,
+            init_code: vec![],
+            call_data: vec![],
+            account_gas_limits: B256::ZERO,
+            pre_verification_gas: U256::ZERO,
+            gas_fees: B256::ZERO,
+            paymaster_and_data: vec![],
+            signature: vec![],
+        };
+        let rpc = Capture(Default::default());
+        let aggregator = address!("0x2222222222222222222222222222222222222222");
+        send_user_operation(&rpc, &op, EP, None).unwrap();
+        assert!(rpc.0.borrow().get("aggregator").is_none());
+        send_user_operation(&rpc, &op, EP, Some(aggregator)).unwrap();
+        assert_eq!(rpc.0.borrow()["aggregator"], json!(aggregator));
+    }
+}
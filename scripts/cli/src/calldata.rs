@@ -0,0 +1,248 @@
+//! UserOperation `callData` for ERC-7579 modular accounts.
+//!
+//! The EntryPoint calls the account with `callData` verbatim, so a module
+//! install is just `installModule(...)` as the op's callData (the account
+//! only accepts it from the EntryPoint or itself), and a transfer or
+//! contract call is `execute(mode, executionCalldata)`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use alloy_primitives::{Address, B256, Bytes, U256};
+use alloy_sol_types::{SolCall, SolValue, sol};
+
+use crate::error::WalletError;
+
+sol! {
+    /// ERC-7579 `Execution`, for batch mode.
+    struct Execution {
+        address target;
+        uint256 value;
+        bytes callData;
+    }
+
+    function execute(bytes32 mode, bytes executionCalldata);
+    function installModule(uint256 moduleTypeId, address module, bytes initData);
+    function uninstallModule(uint256 moduleTypeId, address module, bytes deInitData);
+}
+
+/// ERC-7579 module type IDs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModuleType {
+    Validator,
+    Executor,
+    Fallback,
+    Hook,
+}
+
+impl ModuleType {
+    pub fn id(self) -> u64 {
+        match self {
+            ModuleType::Validator => 1,
+            ModuleType::Executor => 2,
+            ModuleType::Fallback => 3,
+            ModuleType::Hook => 4,
+        }
+    }
+}
+
+impl fmt::Display for ModuleType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ModuleType::Validator => "validator",
+            ModuleType::Executor => "executor",
+            ModuleType::Fallback => "fallback",
+            ModuleType::Hook => "hook",
+        })
+    }
+}
+
+impl FromStr for ModuleType {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self, WalletError> {
+        match s {
+            "validator" | "1" => Ok(ModuleType::Validator),
+            "executor" | "2" => Ok(ModuleType::Executor),
+            "fallback" | "3" => Ok(ModuleType::Fallback),
+            "hook" | "4" => Ok(ModuleType::Hook),
+            _ => Err(WalletError::Usage(
+                "module type must be validator, executor, fallback or hook",
+            )),
+        }
+    }
+}
+
+/// How the account expects `initData` to be laid out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InitLayout {
+    /// Passed straight to the module's `onInstall` (reference
+    /// implementation, Nexus, Safe7579).
+    #[default]
+    Raw,
+    /// Kernel v3 validators: `hook(20) || abi.encode(validatorData,
+    /// hookData, selectorData)`, with hook `address(1)` meaning none.
+    Kernel,
+}
+
+/// Kernel's "installed without a hook" sentinel.
+const KERNEL_NO_HOOK: Address = Address::with_last_byte(1);
+
+impl InitLayout {
+    /// Wrap the module's own `onInstall` data for this account.
+    pub fn wrap(self, module_type: ModuleType, data: &[u8]) -> Vec<u8> {
+        match (self, module_type) {
+            (InitLayout::Kernel, ModuleType::Validator) => {
+                let mut out = KERNEL_NO_HOOK.to_vec();
+                out.extend(
+                    (Bytes::copy_from_slice(data), Bytes::new(), Bytes::new()).abi_encode_params(),
+                );
+                out
+            }
+            _ => data.to_vec(),
+        }
+    }
+}
+
+/// `installModule(type, module, initData)`.
+pub fn install_module(module_type: ModuleType, module: Address, init_data: &[u8]) -> Vec<u8> {
+    installModuleCall {
+        moduleTypeId: U256::from(module_type.id()),
+        module,
+        initData: Bytes::copy_from_slice(init_data),
+    }
+    .abi_encode()
+}
+
+/// `uninstallModule(type, module, deInitData)`.
+pub fn uninstall_module(module_type: ModuleType, module: Address, deinit_data: &[u8]) -> Vec<u8> {
+    uninstallModuleCall {
+        moduleTypeId: U256::from(module_type.id()),
+        module,
+        deInitData: Bytes::copy_from_slice(deinit_data),
+    }
+    .abi_encode()
+}
+
+/// One call made by the account.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Call {
+    pub target: Address,
+    pub value: U256,
+    pub data: Vec<u8>,
+}
+
+/// `execute(mode, executionCalldata)` in single mode for one call, batch
+/// mode otherwise. Both use the default exec type (revert on failure).
+pub fn execute(calls: &[Call]) -> Vec<u8> {
+    let (mode, execution_calldata) = match calls {
+        [call] => {
+            let mut packed = call.target.to_vec();
+            packed.extend_from_slice(&call.value.to_be_bytes::<32>());
+            packed.extend_from_slice(&call.data);
+            (B256::ZERO, packed)
+        }
+        calls => {
+            let executions: Vec<Execution> = calls
+                .iter()
+                .map(|c| Execution {
+                    target: c.target,
+                    value: c.value,
+                    callData: Bytes::copy_from_slice(&c.data),
+                })
+                .collect();
+            let mut mode = B256::ZERO;
+            mode[0] = 0x01;
+            (mode, executions.abi_encode())
+        }
+    };
+    executeCall {
+        mode,
+        executionCalldata: execution_calldata.into(),
+    }
+    .abi_encode()
+}
+
+/// Inverse of [`execute`]: the calls an `execute` callData makes, or `None`
+/// for any other function or an exec mode other than single/batch call.
+pub fn decode_execute(data: &[u8]) -> Option<Vec<Call>> {
+    let call = executeCall::abi_decode(data).ok()?;
+    let inner = call.executionCalldata.as_ref();
+    match call.mode[0] {
+        0x00 if inner.len() >= 52 => Some(vec![Call {
+            target: Address::from_slice(&inner[..20]),
+            value: U256::from_be_slice(&inner[20..52]),
+            data: inner[52..].to_vec(),
+        }]),
+        0x01 => <Vec<Execution>>::abi_decode(inner).ok().map(|executions| {
+            executions
+                .into_iter()
+                .map(|e| Call {
+                    target: e.target,
+                    value: e.value,
+                    data: e.callData.to_vec(),
+                })
+                .collect()
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::PUBLIC_KEY_LEN;
+
+    #[test]
+    fn install_validator_round_trips() {
+        let module = Address::repeat_byte(0x42);
+        let pk = vec![7u8; PUBLIC_KEY_LEN];
+        let data = install_module(ModuleType::Validator, module, &pk);
+        assert_eq!(data[..4], installModuleCall::SELECTOR);
+        let call = installModuleCall::abi_decode(&data).unwrap();
+        assert_eq!(call.moduleTypeId, U256::from(1));
+        assert_eq!(call.module, module);
+        assert_eq!(call.initData.as_ref(), pk.as_slice());
+        assert_eq!("hook".parse::<ModuleType>().unwrap(), ModuleType::Hook);
+        assert!("session".parse::<ModuleType>().is_err());
+    }
+
+    #[test]
+    fn kernel_layout_prefixes_hook_sentinel() {
+        let wrapped = InitLayout::Kernel.wrap(ModuleType::Validator, &[1, 2, 3]);
+        assert_eq!(wrapped[..20], KERNEL_NO_HOOK[..]);
+        let (validator_data, hook_data, selector_data) =
+            <(Bytes, Bytes, Bytes)>::abi_decode_params(&wrapped[20..]).unwrap();
+        assert_eq!(validator_data.as_ref(), [1, 2, 3]);
+        assert!(hook_data.is_empty() && selector_data.is_empty());
+        // Non-validator modules are passed through unchanged.
+        assert_eq!(InitLayout::Kernel.wrap(ModuleType::Executor, &[9]), [9]);
+    }
+
+    #[test]
+    fn execute_picks_single_or_batch_mode() {
+        let call = Call {
+            target: Address::repeat_byte(0x11),
+            value: U256::from(5),
+            data: vec![0xab],
+        };
+        let single = executeCall::abi_decode(&execute(std::slice::from_ref(&call))).unwrap();
+        assert_eq!(single.mode, B256::ZERO);
+        assert_eq!(single.executionCalldata.len(), 20 + 32 + 1);
+        assert_eq!(
+            decode_execute(&execute(std::slice::from_ref(&call))).unwrap(),
+            std::slice::from_ref(&call)
+        );
+        let encoded = execute(&[call.clone(), call.clone()]);
+        assert_eq!(decode_execute(&encoded).unwrap().len(), 2);
+        assert!(decode_execute(&install_module(ModuleType::Hook, call.target, &[])).is_none());
+        let batch = executeCall::abi_decode(&encoded).unwrap();
+        assert_eq!(batch.mode[0], 0x01);
+        assert_eq!(
+            <Vec<Execution>>::abi_decode(&batch.executionCalldata)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! JSON Canonicalization Scheme (RFC 8785), for metadata that gets hashed
+//! or signed.
+//!
+//! The same document serializes in many ways: key order, whitespace,
+//! escapes and number spellings all vary between implementations. JCS
+//! fixes one of them. Object members are sorted by the UTF-16 code units
+//! of their names, and there is no whitespace. Strings escape only `"`,
+//! `\` and control characters. Numbers are written the way ECMAScript
+//! prints an IEEE 754 double, so `4.50` becomes `4.5` and `1E30` becomes
+//! `1e+30`. Hash [`to_vec`]'s output, never `serde_json::to_vec`'s, when
+//! another implementation has to arrive at the same bytes.
+//!
+//! serde_json is built with `float_roundtrip`, so a parsed document keeps its
+//! exact doubles. Integers beyond ±2^53 have no exact double and are refused
+//! rather than rounded. Carry such amounts (wei, `uint256`) as strings, which
+//! alloy's `U256` already does.
+
+use std::cmp::Ordering;
+
+use serde::Serialize;
+use serde_json::{Number, Value};
+
+use crate::error::{Result, WalletError};
+
+/// Largest integer a double holds exactly.
+pub const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+fn canonical_err(reason: impl Into<String>) -> WalletError {
+    WalletError::Canonical(reason.into())
+}
+
+/// The canonical encoding of `value`.
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    to_string(value).map(String::into_bytes)
+}
+
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String> {
+    let value = serde_json::to_value(value).map_err(|e| canonical_err(e.to_string()))?;
+    let mut out = String::new();
+    write_value(&mut out, &value)?;
+    Ok(out)
+}
+
+fn write_value(out: &mut String, value: &Value) -> Result<()> {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => out.push_str(&number(n)?),
+        Value::String(s) => write_string(out, s),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(out, item)?;
+            }
+            out.push(']');
+        }
+        Value::Object(members) => {
+            let mut members: Vec<_> = members.iter().collect();
+            members.sort_by(|(a, _), (b, _)| utf16_cmp(a, b));
+            out.push('{');
+            for (i, (name, member)) in members.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(out, name);
+                out.push(':');
+                write_value(out, member)?;
+            }
+            out.push('}');
+        }
+    }
+    Ok(())
+}
+
+fn utf16_cmp(a: &str, b: &str) -> Ordering {
+    a.encode_utf16().cmp(b.encode_utf16())
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{08}' => out.push_str("\\b"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\u{0c}' => out.push_str("\\f"),
+            '\r' => out.push_str("\\r"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn number(n: &Number) -> Result<String> {
+    if let Some(i) = n.as_i64() {
+        if i.unsigned_abs() > MAX_SAFE_INTEGER {
+            return Err(canonical_err(format!(
+                "{i} is beyond ±2^53; encode it as a string"
+            )));
+        }
+        return Ok(i.to_string());
+    }
+    if let Some(u) = n.as_u64() {
+        return Err(canonical_err(format!(
+            "{u} is beyond ±2^53; encode it as a string"
+        )));
+    }
+    let f = n
+        .as_f64()
+        .ok_or_else(|| canonical_err(format!("{n} is not a double")))?;
+    format_double(f)
+}
+
+/// ECMAScript's `Number.prototype.toString` for a finite double.
+pub fn format_double(f: f64) -> Result<String> {
+    if !f.is_finite() {
+        return Err(canonical_err(format!("{f} has no JSON form")));
+    }
+    if f == 0.0 {
+        return Ok("0".to_string());
+    }
+    // Rust's `{:e}` gives the shortest digits that round-trip, as ECMAScript
+    // requires; only the layout differs.
+    let sci = format!("{:e}", f.abs());
+    let (mantissa, exponent) = sci.split_once('e').expect("{:e} has an exponent");
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let k = digits.len() as i32;
+    let n = exponent
+        .parse::<i32>()
+        .expect("{:e} exponent is an integer")
+        + 1;
+    let sign = if f < 0.0 { "-" } else { "" };
+    let body = if k <= n && n <= 21 {
+        format!("{digits}{}", "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        let (int, frac) = digits.split_at(n as usize);
+        format!("{int}.{frac}")
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{digits}", "0".repeat((-n) as usize))
+    } else {
+        let (first, rest) = digits.split_at(1);
+        let rest = if rest.is_empty() {
+            String::new()
+        } else {
+            format!(".{rest}")
+        };
+        let e = n - 1;
+        format!("{first}{rest}e{}{}", if e < 0 { "-" } else { "+" }, e.abs())
+    };
+    Ok(format!("{sign}{body}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_rfc_8785_examples() {
+        // RFC 8785 §3.2.2.
+        let input = r#"{
+            "numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001],
+            "string": "\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/",
+            "literals": [null, true, false]
+        }"#;
+        let value: Value = serde_json::from_str(input).unwrap();
+        assert_eq!(
+            to_string(&value).unwrap(),
+            r#"{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/"}"#
+        );
+
+        // §3.2.3: sorted by UTF-16 code units, so U+1F600 (a surrogate pair)
+        // comes before U+FB33.
+        let value = serde_json::json!({
+            "\u{20ac}": 1, "\r": 2, "\u{fb33}": 3, "1": 4, "\u{1f600}": 5, "\u{80}": 6, "\u{f6}": 7,
+        });
+        assert_eq!(
+            to_string(&value).unwrap(),
+            "{\"\\r\":2,\"1\":4,\"\u{80}\":6,\"\u{f6}\":7,\"\u{20ac}\":1,\"\u{1f600}\":5,\"\u{fb33}\":3}"
+        );
+    }
+
+    #[test]
+    fn numbers_print_like_ecmascript_and_big_integers_are_refused() {
+        for (f, expected) in [
+            (1e21, "1e+21"),
+            (1e20, "100000000000000000000"),
+            (0.000001, "0.000001"),
+            (1e-7, "1e-7"),
+            (-0.0, "0"),
+            (5e-324, "5e-324"),
+            (1.7976931348623157e308, "1.7976931348623157e+308"),
+            (295147905179352830000.0, "295147905179352830000"),
+            (-1.5, "-1.5"),
+        ] {
+            assert_eq!(format_double(f).unwrap(), expected, "{f:e}");
+        }
+        assert!(format_double(f64::NAN).is_err());
+        assert_eq!(
+            to_string(&[MAX_SAFE_INTEGER]).unwrap(),
+            "[9007199254740991]"
+        );
+        assert!(to_string(&[MAX_SAFE_INTEGER + 1]).is_err());
+        assert!(to_string(&[i64::MIN]).is_err());
+    }
+}
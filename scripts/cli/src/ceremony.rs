@@ -0,0 +1,412 @@
+//! Key ceremonies: a master seed derived from entropy that several
+//! participants contribute, with a commit-then-reveal round so that no one
+//! can pick their contribution after seeing the others'.
+//!
+//! Each participant runs `pq-keygen ceremony commit` on their own machine
+//! with hex from their own source or at least 100 dice rolls. That writes a
+//! [`Reveal`] (their nonce and entropy, kept private) and prints a
+//! [`Commitment`], a hash of it, which they publish. Only once every
+//! commitment is published do participants hand their reveal files to the
+//! machine running `pq-keygen ceremony finalize`. [`finalize`] checks each
+//! reveal against its commitment and derives the seed from all of them.
+//! The seed is unpredictable as long as one participant was honest and
+//! kept their reveal private until then.
+//!
+//! The [`Transcript`] records the commitments and the resulting public key,
+//! never the entropy. The new key signs it over `TRANSCRIPT_DOMAIN` and its
+//! RFC 8785 encoding, which proves that the key came out of this ceremony.
+//! Participants check that their commitment is in it with `ceremony verify`.
+
+use std::fmt;
+use std::path::Path;
+
+use alloy_primitives::{Address, B256, Bytes, keccak256};
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::backend::Backend;
+use crate::canonical_json;
+use crate::ct;
+use crate::error::{Result, WalletError};
+use crate::keys::{self, SEED_LEN};
+use crate::keystore;
+use crate::message::HashAlg;
+use crate::signer::PqSigner;
+
+pub const TRANSCRIPT_VERSION: u32 = 1;
+/// Domain separators for the commitment, the seed and the transcript.
+pub const COMMIT_DOMAIN: &[u8] = b"pq-wallet/ceremony/commit/v1";
+pub const SEED_DOMAIN: &[u8] = b"pq-wallet/ceremony/seed/v1";
+pub const TRANSCRIPT_DOMAIN: &[u8] = b"pq-wallet/ceremony/transcript/v1";
+/// Shortest hex contribution accepted, in bytes (256 bits).
+pub const MIN_HEX_BYTES: usize = 32;
+/// Fewest dice rolls accepted; 100 fair rolls carry 258 bits.
+pub const MIN_DICE_ROLLS: usize = 100;
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::Ceremony(reason.into())
+}
+
+/// How a participant writes down their entropy.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EntropyFormat {
+    /// Hex digits, at least 64 of them
+    #[default]
+    Hex,
+    /// Six-sided dice rolls as digits 1 to 6, at least 100 of them
+    Dice,
+}
+
+/// The bytes a contribution commits to: decoded hex, or the roll digits
+/// themselves. Whitespace is ignored in both.
+pub fn parse_entropy(input: &str, format: EntropyFormat) -> Result<Zeroizing<Vec<u8>>> {
+    let compact: Zeroizing<String> =
+        Zeroizing::new(input.chars().filter(|c| !c.is_whitespace()).collect());
+    match format {
+        EntropyFormat::Hex => {
+            let bytes = Zeroizing::new(ct::decode_hex(&compact)?);
+            if bytes.len() < MIN_HEX_BYTES {
+                return Err(err(format!(
+                    "{} bytes of hex entropy; at least {MIN_HEX_BYTES} are needed",
+                    bytes.len()
+                )));
+            }
+            Ok(bytes)
+        }
+        EntropyFormat::Dice => {
+            if !compact.bytes().all(|b| (b'1'..=b'6').contains(&b)) {
+                return Err(err("dice rolls must be digits 1 to 6"));
+            }
+            if compact.len() < MIN_DICE_ROLLS {
+                return Err(err(format!(
+                    "{} dice rolls; at least {MIN_DICE_ROLLS} are needed",
+                    compact.len()
+                )));
+            }
+            Ok(Zeroizing::new(compact.as_bytes().to_vec()))
+        }
+    }
+}
+
+/// Append `bytes` with a 4-byte big-endian length.
+fn put(preimage: &mut Vec<u8>, bytes: &[u8]) {
+    preimage.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    preimage.extend_from_slice(bytes);
+}
+
+/// What a participant publishes before anyone reveals.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Commitment {
+    pub ceremony: String,
+    pub participant: String,
+    pub commitment: B256,
+}
+
+impl Commitment {
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).map_err(|e| err(format!("invalid commitment: {e}")))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("commitment serializes")
+    }
+}
+
+/// A participant's contribution, private until every commitment is out.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reveal {
+    pub ceremony: String,
+    pub participant: String,
+    /// Random salt, so equal contributions commit differently.
+    nonce: B256,
+    /// Hex of the contribution bytes.
+    entropy: String,
+}
+
+impl Reveal {
+    pub fn new(ceremony: &str, participant: &str, entropy: &[u8]) -> Self {
+        Reveal {
+            ceremony: ceremony.to_string(),
+            participant: participant.to_string(),
+            nonce: B256::from(rand::random::<[u8; 32]>()),
+            entropy: ct::encode_hex(entropy),
+        }
+    }
+
+    fn entropy(&self) -> Result<Zeroizing<Vec<u8>>> {
+        ct::decode_hex(&self.entropy).map(Zeroizing::new)
+    }
+
+    pub fn commitment(&self) -> Result<Commitment> {
+        let mut preimage = Zeroizing::new(COMMIT_DOMAIN.to_vec());
+        put(&mut preimage, self.ceremony.as_bytes());
+        put(&mut preimage, self.participant.as_bytes());
+        preimage.extend_from_slice(self.nonce.as_slice());
+        put(&mut preimage, &self.entropy()?);
+        Ok(Commitment {
+            ceremony: self.ceremony.clone(),
+            participant: self.participant.clone(),
+            commitment: keccak256(&*preimage),
+        })
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).map_err(|e| err(format!("invalid reveal: {e}")))
+    }
+
+    /// Write the reveal readable only by its owner.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = Zeroizing::new(serde_json::to_string_pretty(self).expect("reveal serializes"));
+        keystore::write_private(path, json.as_bytes())
+    }
+}
+
+impl Drop for Reveal {
+    fn drop(&mut self) {
+        self.nonce.0.zeroize();
+        self.entropy.zeroize();
+    }
+}
+
+impl fmt::Debug for Reveal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reveal")
+            .field("ceremony", &self.ceremony)
+            .field("participant", &self.participant)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscriptBody {
+    pub version: u32,
+    pub ceremony: String,
+    /// Every commitment, sorted by participant.
+    pub participants: Vec<Commitment>,
+    /// The ceremony key's ML-DSA-65 public key.
+    pub public_key: Bytes,
+    pub key_id: Address,
+    pub fingerprint: B256,
+    pub created_at: u64,
+}
+
+impl TranscriptBody {
+    /// The 32-byte digest the ceremony key signs.
+    pub fn statement(&self) -> Result<B256> {
+        let mut preimage = TRANSCRIPT_DOMAIN.to_vec();
+        preimage.extend_from_slice(&canonical_json::to_vec(self)?);
+        Ok(keccak256(preimage))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transcript {
+    #[serde(flatten)]
+    pub body: TranscriptBody,
+    /// The ceremony key's signature over [`TranscriptBody::statement`].
+    pub signature: Bytes,
+}
+
+impl Transcript {
+    /// Check the key fields agree and the key signed the transcript.
+    pub fn verify(&self) -> Result<()> {
+        let body = &self.body;
+        if body.version != TRANSCRIPT_VERSION {
+            return Err(err(format!("unsupported version {}", body.version)));
+        }
+        if body.key_id != keys::address(&body.public_key)
+            || body.fingerprint != keys::fingerprint(&body.public_key, HashAlg::Keccak256)
+        {
+            return Err(err("key ID or fingerprint does not match the public key"));
+        }
+        if body
+            .participants
+            .iter()
+            .any(|c| c.ceremony != body.ceremony)
+        {
+            return Err(err("a commitment is from another ceremony"));
+        }
+        if !Backend::RustCrypto.verify(
+            &body.public_key,
+            body.statement()?.as_slice(),
+            &[],
+            &self.signature,
+        ) {
+            return Err(err("signature does not verify"));
+        }
+        Ok(())
+    }
+
+    pub fn includes(&self, commitment: &Commitment) -> bool {
+        self.body.participants.contains(commitment)
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).map_err(|e| err(format!("invalid transcript: {e}")))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("transcript serializes")
+    }
+}
+
+/// The ceremony's seed and signed transcript.
+pub struct Outcome {
+    pub seed: Zeroizing<[u8; SEED_LEN]>,
+    pub transcript: Transcript,
+}
+
+/// Check every reveal against its commitment, then derive the seed from
+/// all of them, in participant order.
+pub fn finalize(
+    commitments: &[Commitment],
+    reveals: &[Reveal],
+    created_at: u64,
+) -> Result<Outcome> {
+    let mut commitments = commitments.to_vec();
+    commitments.sort_by(|a, b| a.participant.cmp(&b.participant));
+    if commitments.len() < 2 {
+        return Err(err("a ceremony needs at least two participants"));
+    }
+    if let Some(pair) = commitments
+        .windows(2)
+        .find(|w| w[0].participant == w[1].participant)
+    {
+        return Err(err(format!("{} committed twice", pair[0].participant)));
+    }
+    let ceremony = commitments[0].ceremony.clone();
+    if let Some(other) = commitments.iter().find(|c| c.ceremony != ceremony) {
+        return Err(err(format!(
+            "{}'s commitment is for ceremony {:?}, not {ceremony:?}",
+            other.participant, other.ceremony
+        )));
+    }
+    if let Some(extra) = reveals
+        .iter()
+        .find(|r| !commitments.iter().any(|c| c.participant == r.participant))
+    {
+        return Err(err(format!(
+            "reveal from {}, who made no commitment",
+            extra.participant
+        )));
+    }
+
+    let mut preimage = Zeroizing::new(SEED_DOMAIN.to_vec());
+    put(&mut preimage, ceremony.as_bytes());
+    for commitment in &commitments {
+        let mut matching = reveals
+            .iter()
+            .filter(|r| r.participant == commitment.participant);
+        let reveal = matching
+            .next()
+            .ok_or_else(|| err(format!("no reveal from {}", commitment.participant)))?;
+        if matching.next().is_some() {
+            return Err(err(format!("{} revealed twice", commitment.participant)));
+        }
+        if reveal.commitment()? != *commitment {
+            return Err(err(format!(
+                "{}'s reveal does not match their commitment",
+                commitment.participant
+            )));
+        }
+        put(&mut preimage, reveal.participant.as_bytes());
+        preimage.extend_from_slice(reveal.nonce.as_slice());
+        put(&mut preimage, &reveal.entropy()?);
+    }
+    let seed = Zeroizing::new(keccak256(&*preimage).0);
+
+    let signer = PqSigner::from_seed(&seed);
+    let public_key = signer.verifying_key().encode();
+    let body = TranscriptBody {
+        version: TRANSCRIPT_VERSION,
+        ceremony,
+        participants: commitments,
+        public_key: Bytes::copy_from_slice(&public_key),
+        key_id: keys::address(&public_key),
+        fingerprint: keys::fingerprint(&public_key, HashAlg::Keccak256),
+        created_at,
+    };
+    let signature = signer.sign(body.statement()?.as_slice());
+    Ok(Outcome {
+        seed,
+        transcript: Transcript {
+            body,
+            signature: Bytes::copy_from_slice(&signature.encode()),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reveal with a fixed nonce, so it can be made twice.
+    fn reveal(participant: &str, byte: u8) -> Reveal {
+        let mut reveal = Reveal::new("genesis", participant, &[byte; MIN_HEX_BYTES]);
+        reveal.nonce = B256::repeat_byte(byte);
+        reveal
+    }
+
+    #[test]
+    fn parses_hex_and_dice_contributions() {
+        let hex = "ab".repeat(MIN_HEX_BYTES);
+        assert_eq!(
+            *parse_entropy(
+                &format!("0x{} {}", &hex[..10], &hex[10..]),
+                EntropyFormat::Hex
+            )
+            .unwrap(),
+            [0xab; MIN_HEX_BYTES]
+        );
+        assert!(parse_entropy(&hex[2..], EntropyFormat::Hex).is_err());
+        let rolls = "123456".repeat(17);
+        assert_eq!(
+            parse_entropy(&rolls, EntropyFormat::Dice).unwrap().len(),
+            102
+        );
+        assert!(parse_entropy(&rolls[..99], EntropyFormat::Dice).is_err());
+        assert!(parse_entropy(&rolls.replace('6', "7"), EntropyFormat::Dice).is_err());
+    }
+
+    #[test]
+    fn derives_one_seed_from_all_reveals_and_signs_the_transcript() {
+        let reveals = [reveal("bob", 2), reveal("alice", 1), reveal("carol", 3)];
+        let commitments: Vec<_> = reveals.iter().map(|r| r.commitment().unwrap()).collect();
+        let outcome = finalize(&commitments, &reveals, 1_700_000_000).unwrap();
+        let transcript = Transcript::from_json(outcome.transcript.to_json().as_bytes()).unwrap();
+        transcript.verify().unwrap();
+        assert!(commitments.iter().all(|c| transcript.includes(c)));
+        assert_eq!(transcript.body.participants[0].participant, "alice");
+        assert_eq!(
+            transcript.body.public_key[..],
+            keys::keygen_from_seed(&outcome.seed)
+                .verifying_key()
+                .encode()[..]
+        );
+        // The order reveals arrive in does not matter.
+        let reversed: Vec<_> = commitments.iter().rev().cloned().collect();
+        let again = finalize(&reversed, &reveals, 1_700_000_000).unwrap();
+        assert_eq!(*again.seed, *outcome.seed);
+
+        let mut forged = transcript.clone();
+        forged.body.participants.pop();
+        assert!(forged.verify().is_err());
+    }
+
+    #[test]
+    fn refuses_reveals_that_do_not_match() {
+        let reveals = [reveal("alice", 1), reveal("bob", 2)];
+        let commitments: Vec<_> = reveals.iter().map(|r| r.commitment().unwrap()).collect();
+        // Bob changes his mind after seeing Alice's reveal.
+        let changed = [reveal("alice", 1), reveal("bob", 9)];
+        assert!(finalize(&commitments, &changed, 0).is_err());
+        assert!(finalize(&commitments, &reveals[..1], 0).is_err());
+        assert!(finalize(&commitments[..1], &reveals[..1], 0).is_err());
+        let extra = [reveal("alice", 1), reveal("bob", 2), reveal("mallory", 3)];
+        assert!(finalize(&commitments, &extra, 0).is_err());
+    }
+}
@@ -0,0 +1,439 @@
+//! Offline checks for a UserOperation, for `pq-userop check`.
+//!
+//! Everything here runs without a node: field ranges, nonce layout, the
+//! signature's length against what the verifier expects, gas limits against
+//! what ML-DSA-65 verification costs, and, given a [`Snapshot`] of the
+//! accounts involved, initCode against deployed code, the nonce against the
+//! EntryPoint's, and the prefund against the sender's funds. It catches the
+//! mistakes that otherwise surface as an `AAxx` revert from simulation.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use alloy_primitives::{Address, Bytes, U256};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, WalletError};
+use crate::gas::cost;
+use crate::gas::pre_verification::{self, Calibration};
+use crate::keys::{PUBLIC_KEY_LEN, SIGNATURE_LEN};
+use crate::precompile::OnChainVerifier;
+use crate::sig_transport::Reference;
+use crate::userop::PackedUserOperation;
+
+/// Below this, verification cannot fit the Stylus `verify` call (374K
+/// measured) plus the cross-runtime call into it (~46K).
+pub const MIN_VERIFICATION_GAS: u128 = 420_000;
+/// What the deployment guide budgets for account validation with ML-DSA-65.
+pub const RECOMMENDED_VERIFICATION_GAS: u128 = 2_000_000;
+/// A sequence this high is almost always a key/sequence mix-up or a
+/// timestamp.
+const MAX_PLAUSIBLE_SEQUENCE: u64 = 1 << 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    /// Which check: `fields`, `nonce`, `signature`, `gas`, `deployment`,
+    /// `paymaster` or `prefund`.
+    pub check: &'static str,
+    /// The `AAxx` code the EntryPoint would fail with, where there is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<&'static str>,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{severity}: {}: ", self.check)?;
+        if let Some(code) = self.code {
+            write!(f, "{code} ")?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+/// Chain state for the accounts an op touches, as known to the caller.
+/// Accounts left out are not checked.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Snapshot {
+    pub accounts: BTreeMap<Address, AccountState>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AccountState {
+    /// Deployed code; `0x` for none.
+    #[serde(default)]
+    pub code: Bytes,
+    pub balance: Option<U256>,
+    /// EntryPoint `balanceOf`.
+    pub deposit: Option<U256>,
+    /// EntryPoint `getNonce(sender, key)` for the op's nonce key.
+    pub nonce: Option<U256>,
+}
+
+impl Snapshot {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| WalletError::Io(path.display().to_string(), e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| WalletError::InvalidUserOp(format!("{}: {e}", path.display())))
+    }
+}
+
+/// An op's findings, most severe first.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Report {
+    pub ok: bool,
+    pub findings: Vec<Finding>,
+}
+
+struct Findings(Vec<Finding>);
+
+impl Findings {
+    fn push(
+        &mut self,
+        severity: Severity,
+        check: &'static str,
+        code: Option<&'static str>,
+        message: String,
+    ) {
+        self.0.push(Finding {
+            severity,
+            check,
+            code,
+            message,
+        });
+    }
+
+    fn error(&mut self, check: &'static str, code: Option<&'static str>, message: String) {
+        self.push(Severity::Error, check, code, message);
+    }
+
+    fn warn(&mut self, check: &'static str, message: String) {
+        self.push(Severity::Warning, check, None, message);
+    }
+}
+
+/// Run every check on `op`. `verifier` is the account's on-chain verifier;
+/// `None` expects a bare signature, as `pq-userop sign` produces by default.
+pub fn check(
+    op: &PackedUserOperation,
+    chain_id: u64,
+    verifier: Option<&dyn OnChainVerifier>,
+    snapshot: &Snapshot,
+) -> Report {
+    let mut f = Findings(Vec::new());
+    fields(op, &mut f);
+    nonce(op, snapshot, &mut f);
+    signature(op, verifier, &mut f);
+    gas(op, chain_id, &mut f);
+    deployment(op, snapshot, &mut f);
+    prefund(op, snapshot, &mut f);
+    let mut findings = f.0;
+    findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    Report {
+        ok: findings.iter().all(|f| f.severity != Severity::Error),
+        findings,
+    }
+}
+
+fn fields(op: &PackedUserOperation, f: &mut Findings) {
+    if !op.init_code.is_empty() && op.init_code.len() < 20 {
+        f.error(
+            "fields",
+            Some("AA13"),
+            format!(
+                "initCode is {} bytes; it must start with a 20-byte factory",
+                op.init_code.len()
+            ),
+        );
+    }
+    if !op.paymaster_and_data.is_empty() && op.paymaster_and_data.len() < 52 {
+        f.error(
+            "fields",
+            Some("AA93"),
+            format!(
+                "paymasterAndData is {} bytes; it needs paymaster(20) and two 16-byte gas limits",
+                op.paymaster_and_data.len()
+            ),
+        );
+    }
+    if op.max_priority_fee_per_gas() > op.max_fee_per_gas() {
+        f.error(
+            "fields",
+            None,
+            format!(
+                "maxPriorityFeePerGas {} exceeds maxFeePerGas {}",
+                op.max_priority_fee_per_gas(),
+                op.max_fee_per_gas()
+            ),
+        );
+    }
+    if op.max_fee_per_gas() == 0 {
+        f.warn(
+            "fields",
+            "maxFeePerGas is 0; bundlers will not include the op".into(),
+        );
+    }
+    if op.call_gas_limit() == 0 && !op.call_data.is_empty() {
+        f.error(
+            "fields",
+            None,
+            "callGasLimit is 0 but the op has callData".into(),
+        );
+    }
+}
+
+fn nonce(op: &PackedUserOperation, snapshot: &Snapshot, f: &mut Findings) {
+    let key: U256 = op.nonce >> 64;
+    let sequence = op.nonce.as_limbs()[0];
+    if sequence >= MAX_PLAUSIBLE_SEQUENCE {
+        f.warn(
+            "nonce",
+            format!(
+                "sequence {sequence} (key {key}) is implausibly high; the nonce is key << 64 | sequence"
+            ),
+        );
+    }
+    let Some(expected) = snapshot.accounts.get(&op.sender).and_then(|a| a.nonce) else {
+        return;
+    };
+    if op.nonce != expected {
+        let (what, hint) = if op.nonce < expected {
+            (
+                "already used",
+                "the op was sent before or the nonce is stale",
+            )
+        } else {
+            ("ahead of the EntryPoint", "earlier ops have not landed yet")
+        };
+        f.error(
+            "nonce",
+            Some("AA25"),
+            format!("nonce {} is {what} (expected {expected}): {hint}", op.nonce),
+        );
+    }
+}
+
+fn signature(op: &PackedUserOperation, verifier: Option<&dyn OnChainVerifier>, f: &mut Findings) {
+    if op.signature.is_empty() {
+        f.warn("signature", "op is unsigned".into());
+        return;
+    }
+    let (expected, layout) = match verifier {
+        Some(verifier) => (
+            verifier
+                .signature_field(&[0; PUBLIC_KEY_LEN], &[0; SIGNATURE_LEN])
+                .map_or(SIGNATURE_LEN, |field| field.len()),
+            verifier.to_string(),
+        ),
+        None => (SIGNATURE_LEN, "a bare signature".to_string()),
+    };
+    let (len, carried) = match Reference::decode(&op.signature) {
+        Reference::Inline(sig) => (sig.len(), "signature"),
+        Reference::Chunked { len, .. } => (len as usize, "chunked signature reference"),
+        Reference::Blob { len, .. } => (len as usize, "blob signature reference"),
+    };
+    if len != expected {
+        f.error(
+            "signature",
+            Some("AA24"),
+            format!("{carried} is {len} bytes; expected {expected} for {layout}"),
+        );
+    }
+}
+
+fn gas(op: &PackedUserOperation, chain_id: u64, f: &mut Findings) {
+    let verification = op.verification_gas_limit();
+    if verification < MIN_VERIFICATION_GAS {
+        f.error(
+            "gas",
+            Some("AA26"),
+            format!(
+                "verificationGasLimit {verification} cannot cover ML-DSA-65 verification \
+                 (at least {MIN_VERIFICATION_GAS})"
+            ),
+        );
+    } else if verification < RECOMMENDED_VERIFICATION_GAS {
+        f.warn(
+            "gas",
+            format!(
+                "verificationGasLimit {verification} is below the recommended \
+                 {RECOMMENDED_VERIFICATION_GAS} for ML-DSA-65 accounts"
+            ),
+        );
+    }
+    let calibration = Calibration {
+        margin_percent: 0,
+        ..Calibration::for_chain(chain_id)
+    };
+    if let Ok(estimate) = pre_verification::estimate(op, &calibration, None) {
+        let floor = U256::from(estimate.pre_verification_gas);
+        if op.pre_verification_gas < floor {
+            f.error(
+                "gas",
+                None,
+                format!(
+                    "preVerificationGas {} is below the {floor} the op's calldata alone costs \
+                     (before any L1 fee)",
+                    op.pre_verification_gas
+                ),
+            );
+        }
+    }
+}
+
+fn deployment(op: &PackedUserOperation, snapshot: &Snapshot, f: &mut Findings) {
+    let has_code = |address: &Address| {
+        snapshot
+            .accounts
+            .get(address)
+            .map(|account| !account.code.is_empty())
+    };
+    match (has_code(&op.sender), op.init_code.is_empty()) {
+        (Some(true), false) => f.error(
+            "deployment",
+            Some("AA10"),
+            format!(
+                "{} is already deployed; drop factory/factoryData",
+                op.sender
+            ),
+        ),
+        (Some(false), true) => f.error(
+            "deployment",
+            Some("AA20"),
+            format!(
+                "{} has no code; set factory/factoryData to deploy it",
+                op.sender
+            ),
+        ),
+        _ => {}
+    }
+    if op.init_code.len() >= 20 {
+        let factory = Address::from_slice(&op.init_code[..20]);
+        if has_code(&factory) == Some(false) {
+            f.error(
+                "deployment",
+                Some("AA13"),
+                format!("factory {factory} has no code"),
+            );
+        }
+    }
+    if op.paymaster_and_data.len() >= 20 {
+        let paymaster = Address::from_slice(&op.paymaster_and_data[..20]);
+        if has_code(&paymaster) == Some(false) {
+            f.error(
+                "paymaster",
+                Some("AA30"),
+                format!("paymaster {paymaster} has no code"),
+            );
+        }
+    }
+}
+
+fn prefund(op: &PackedUserOperation, snapshot: &Snapshot, f: &mut Findings) {
+    if !op.paymaster_and_data.is_empty() {
+        return;
+    }
+    let Some(account) = snapshot.accounts.get(&op.sender) else {
+        return;
+    };
+    let (Some(balance), Some(deposit)) = (account.balance, account.deposit) else {
+        return;
+    };
+    let required = U256::from(cost::cost(op, 0).max_wei);
+    if balance.saturating_add(deposit) < required {
+        f.error(
+            "prefund",
+            Some("AA21"),
+            format!(
+                "the op can cost {required} wei but {} holds {balance} wei plus a {deposit} wei deposit",
+                op.sender
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::precompile::parse_verifier;
+    use crate::userop::pack_u128_pair;
+
+    fn op() -> PackedUserOperation {
+        PackedUserOperation {
+            sender: Address::repeat_byte(0xaa),
+            nonce: U256::from(3),
+            init_code: Vec::new(),
+            call_data: vec![0xb6, 0x1d, 0x27, 0xf6],
+            account_gas_limits: pack_u128_pair(2_000_000, 100_000),
+            pre_verification_gas: U256::from(400_000),
+            gas_fees: pack_u128_pair(1, 1_000_000_000),
+            paymaster_and_data: Vec::new(),
+            signature: vec![0x11; SIGNATURE_LEN],
+        }
+    }
+
+    fn codes(report: &Report) -> Vec<&'static str> {
+        report.findings.iter().filter_map(|f| f.code).collect()
+    }
+
+    #[test]
+    fn well_formed_op_passes() {
+        let contract = parse_verifier(&format!("contract:{}", Address::ZERO)).unwrap();
+        let snapshot: Snapshot = serde_json::from_value(serde_json::json!({
+            "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa": {
+                "code": "0x6080", "nonce": "0x3", "balance": "0xde0b6b3a7640000", "deposit": "0x0"
+            }
+        }))
+        .unwrap();
+        let report = check(&op(), 421614, Some(contract.as_ref()), &snapshot);
+        assert!(
+            report.ok && report.findings.is_empty(),
+            "{:?}",
+            report.findings
+        );
+    }
+
+    #[test]
+    fn common_mistakes_are_caught() {
+        let packed = parse_verifier(&format!("precompile:{}", Address::ZERO)).unwrap();
+        let mut bad = op();
+        bad.account_gas_limits = pack_u128_pair(300_000, 100_000);
+        bad.pre_verification_gas = U256::from(21_000);
+        bad.init_code = Address::repeat_byte(0xfa).to_vec();
+        let snapshot: Snapshot = serde_json::from_value(serde_json::json!({
+            "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa": {
+                "code": "0x6080", "nonce": "0x4", "balance": "0x0", "deposit": "0x0"
+            },
+            "0xfafafafafafafafafafafafafafafafafafafafa": {}
+        }))
+        .unwrap();
+        let report = check(&bad, 421614, Some(packed.as_ref()), &snapshot);
+        assert!(!report.ok);
+        let codes = codes(&report);
+        for code in ["AA10", "AA13", "AA21", "AA24", "AA25", "AA26"] {
+            assert!(codes.contains(&code), "{code} missing from {codes:?}");
+        }
+        assert!(
+            report
+                .findings
+                .iter()
+                .any(|f| f.check == "gas" && f.message.starts_with("preVerificationGas"))
+        );
+    }
+}
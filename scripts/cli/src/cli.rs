@@ -0,0 +1,437 @@
+//! clap argument groups shared by the binaries.
+
+use std::path::PathBuf;
+
+use alloy_primitives::{Address, B256};
+use zeroize::Zeroizing;
+
+use crate::ct;
+use crate::error::{Result, WalletError};
+use crate::gas::cost::{self, FeeQuote};
+use crate::gas::fee_oracle::{FeeOracle, FeeSpeed};
+use crate::keys::{self, PUBLIC_KEY_LEN, SEED_LEN};
+use crate::keystore::{Account, Keystore};
+use crate::message::{Erc1271Binding, HashAlg, MessageInput, PersonalMessage, Source};
+use crate::pkix::{self, KeyFormat};
+use crate::registry::{self, Deployment, SignedRegistry};
+use crate::rpc::Transport;
+
+/// Environment variable consulted before prompting for a keystore password.
+pub const PASSWORD_ENV: &str = "PQ_WALLET_PASSWORD";
+
+/// Keystore password from [`PASSWORD_ENV`], else an interactive prompt.
+/// With `confirm`, the prompt asks twice and rejects a mismatch.
+pub fn read_password(prompt: &str, confirm: bool) -> Result<Zeroizing<String>> {
+    if let Ok(pw) = std::env::var(PASSWORD_ENV) {
+        return Ok(Zeroizing::new(pw));
+    }
+    let io_err = |e| WalletError::Io("password prompt".to_string(), e);
+    let pw = Zeroizing::new(rpassword::prompt_password(prompt).map_err(io_err)?);
+    if confirm {
+        let again =
+            Zeroizing::new(rpassword::prompt_password("Repeat password: ").map_err(io_err)?);
+        if *pw != *again {
+            return Err(WalletError::Usage("passwords do not match"));
+        }
+    }
+    Ok(pw)
+}
+
+/// Where `pq-account new` keeps the key that opens a seed.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeystoreBackend {
+    /// Derived from a password with Argon2id
+    #[default]
+    Password,
+    /// Sealed to this host's TPM 2.0 and its PCR values (`tpm` feature)
+    Tpm,
+}
+
+/// Decrypt `account`'s seed: a TPM-sealed entry unseals on this host without
+/// a prompt, a password entry asks for its password.
+pub fn unlock_account(account: &Account) -> Result<Zeroizing<[u8; SEED_LEN]>> {
+    account.require_seed()?;
+    let platform = account
+        .crypto
+        .wrapped_key
+        .as_ref()
+        .map(|w| w.platform.as_str());
+    match platform {
+        #[cfg(feature = "tpm")]
+        Some(crate::platform::TPM) => {
+            account.unlock_wrapped(&crate::tpm::Tpm::from_environment()?, "unseal")
+        }
+        #[cfg(not(feature = "tpm"))]
+        Some(crate::platform::TPM) => Err(WalletError::Keystore(format!(
+            "account {:?} is sealed to a TPM; rebuild with --features tpm to unseal it",
+            account.name
+        ))),
+        // Another device's entry; decrypt_seed says so.
+        Some(_) => account.decrypt_seed(b""),
+        None => {
+            let pw = read_password(&format!("Password for {}: ", account.name), false)?;
+            account.decrypt_seed(pw.as_bytes())
+        }
+    }
+}
+
+/// Ask a yes/no question on the controlling terminal, so it works when stdin
+/// carries the input. Anything but `y`/`yes` is a no.
+pub fn confirm(prompt: &str) -> Result<bool> {
+    use std::io::{BufRead, BufReader, Write};
+
+    let io_err = |e| WalletError::Io("confirmation prompt".to_string(), e);
+    let tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(io_err)?;
+    write!(&tty, "{prompt} [y/N] ").map_err(io_err)?;
+    let mut answer = String::new();
+    BufReader::new(&tty)
+        .read_line(&mut answer)
+        .map_err(io_err)?;
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+/// How `pq-sign` writes a signature and `pq-verify` reads one.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SignatureFormat {
+    /// The bare 3,309-byte ML-DSA-65 signature
+    #[default]
+    Raw,
+    /// A COSE_Sign1 carrying the message, with the key's fingerprint as kid
+    Cose,
+}
+
+/// Where the signing seed comes from: a raw `sk.bin` or a keystore account.
+#[derive(clap::Args, Debug)]
+pub struct SigningKeyArgs {
+    /// Path to seed file (sk.bin, 32 bytes)
+    #[arg(long, conflicts_with = "account")]
+    pub key: Option<PathBuf>,
+
+    /// Keystore account to sign with (see pq-account)
+    #[arg(long)]
+    pub account: Option<String>,
+
+    /// Encoding of --key: raw (32-byte seed), der or pem (PKCS#8), jwk or cose
+    #[arg(long, default_value_t = KeyFormat::Raw, requires = "key")]
+    pub key_format: KeyFormat,
+}
+
+impl SigningKeyArgs {
+    pub fn is_present(&self) -> bool {
+        self.key.is_some() || self.account.is_some()
+    }
+
+    /// Load the seed, unlocking the keystore account if needed.
+    pub fn seed(&self) -> Result<Zeroizing<[u8; SEED_LEN]>> {
+        match (&self.key, &self.account) {
+            (Some(path), None) => {
+                let bytes = Zeroizing::new(
+                    std::fs::read(path)
+                        .map_err(|e| WalletError::Io(path.display().to_string(), e))?,
+                );
+                pkix::decode_private_key(&bytes, self.key_format)
+            }
+            (None, Some(name)) => unlock_account(&Keystore::open_default()?.load(name)?),
+            _ => Err(WalletError::Usage(
+                "exactly one of --key or --account is required",
+            )),
+        }
+    }
+}
+
+/// How gas fees are quoted from a node.
+#[derive(clap::Args, Debug)]
+pub struct FeeArgs {
+    /// Quote fees from recent blocks' eth_feeHistory: slow, normal, fast or
+    /// pNN (tip at the NNth percentile); without it, twice the base fee plus
+    /// the node's suggested tip
+    #[arg(long)]
+    pub fee_speed: Option<FeeSpeed>,
+
+    /// Lowest maxPriorityFeePerGas to quote, in wei [default: the chain's
+    /// minimum, 0 on most chains]
+    #[arg(long)]
+    pub min_priority_fee: Option<u128>,
+}
+
+impl FeeArgs {
+    pub fn quote<T: Transport>(&self, rpc: &T, chain_id: u64) -> Result<FeeQuote> {
+        let mut oracle = FeeOracle::for_chain(chain_id);
+        if let Some(min) = self.min_priority_fee {
+            oracle.min_priority_fee = min;
+        }
+        match self.fee_speed {
+            Some(speed) => oracle.quote(rpc, speed),
+            None => Ok(oracle.floor(cost::suggest_fees(rpc)?)),
+        }
+    }
+}
+
+/// A signed deployment registry the chain's contracts are checked against
+/// before anything is built for them.
+#[derive(clap::Args, Debug)]
+pub struct RegistryArgs {
+    /// Signed deployment registry (`registry.json` from a release); every
+    /// contract it pins for the chain must hold the pinned code
+    #[arg(long, requires = "registry_keys")]
+    pub registry: Option<PathBuf>,
+
+    /// Fingerprint of a release key the registry may be signed with
+    /// (repeatable)
+    #[arg(
+        long = "registry-key",
+        value_name = "FINGERPRINT",
+        value_delimiter = ','
+    )]
+    pub registry_keys: Vec<B256>,
+}
+
+impl RegistryArgs {
+    /// The registry, its signature checked; `None` without `--registry`.
+    pub fn load(&self) -> Result<Option<SignedRegistry>> {
+        let Some(path) = &self.registry else {
+            return Ok(None);
+        };
+        let bytes =
+            std::fs::read(path).map_err(|e| WalletError::Io(path.display().to_string(), e))?;
+        let signed = SignedRegistry::from_json(&bytes)?;
+        signed.verify(&self.registry_keys)?;
+        Ok(Some(signed))
+    }
+
+    /// Load the registry and check the code of the contracts it pins for
+    /// `chain_id` on `rpc`. `undeployed` may have no code yet. Returns the
+    /// chain's deployment, or `None` without `--registry`.
+    pub fn check<T: Transport>(
+        &self,
+        rpc: &T,
+        chain_id: u64,
+        undeployed: Option<Address>,
+    ) -> Result<Option<Deployment>> {
+        let Some(signed) = self.load()? else {
+            return Ok(None);
+        };
+        let checks = signed.check_chain(rpc, chain_id)?;
+        registry::require(&checks, undeployed)?;
+        Ok(Some(*signed.deployment(chain_id)?))
+    }
+}
+
+/// Deterministic keygen seed, for reproducible test keys. Without any of
+/// these flags the caller falls back to OS randomness.
+#[derive(clap::Args, Debug)]
+#[group(multiple = false)]
+pub struct SeedSourceArgs {
+    /// 32-byte seed as hex
+    #[arg(long)]
+    pub seed_hex: Option<String>,
+
+    /// File holding the 32-byte seed (raw, like sk.bin, or hex)
+    #[arg(long)]
+    pub seed_file: Option<PathBuf>,
+
+    /// Derive the seed from a passphrase with Argon2id (prompted, or read
+    /// from PQ_WALLET_PASSWORD)
+    #[arg(long)]
+    pub from_passphrase: bool,
+}
+
+impl SeedSourceArgs {
+    pub fn is_present(&self) -> bool {
+        self.seed_hex.is_some() || self.seed_file.is_some() || self.from_passphrase
+    }
+
+    /// The requested seed, or `None` if no seed flag was given.
+    pub fn seed(&self, salt: &[u8]) -> Result<Option<Zeroizing<[u8; SEED_LEN]>>> {
+        if let Some(hex) = &self.seed_hex {
+            return ct::decode_hex_array("seed", hex).map(|s| Some(Zeroizing::new(s)));
+        }
+        if let Some(path) = &self.seed_file {
+            let bytes = Zeroizing::new(
+                std::fs::read(path).map_err(|e| WalletError::Io(path.display().to_string(), e))?,
+            );
+            if let Ok(seed) = <[u8; SEED_LEN]>::try_from(bytes.as_slice()) {
+                return Ok(Some(Zeroizing::new(seed)));
+            }
+            let text = std::str::from_utf8(&bytes).map_err(|_| WalletError::InvalidLength {
+                what: "seed",
+                expected: SEED_LEN,
+                actual: bytes.len(),
+            })?;
+            return ct::decode_hex_array("seed", text.trim()).map(|s| Some(Zeroizing::new(s)));
+        }
+        if self.from_passphrase {
+            let pw = read_password("Passphrase: ", true)?;
+            return keys::seed_from_passphrase(pw.as_bytes(), salt).map(Some);
+        }
+        Ok(None)
+    }
+}
+
+/// Where the public key comes from: a raw `pk.bin` or a keystore account.
+#[derive(clap::Args, Debug)]
+pub struct PublicKeyArgs {
+    /// Path to public key (pk.bin)
+    #[arg(long, conflicts_with = "account")]
+    pub key: Option<PathBuf>,
+
+    /// Keystore account whose public key to use (no password needed)
+    #[arg(long)]
+    pub account: Option<String>,
+
+    /// Encoding of --key: raw (1,952 bytes), der or pem (SubjectPublicKeyInfo),
+    /// jwk or cose
+    #[arg(long, default_value_t = KeyFormat::Raw, requires = "key")]
+    pub key_format: KeyFormat,
+}
+
+impl PublicKeyArgs {
+    pub fn is_present(&self) -> bool {
+        self.key.is_some() || self.account.is_some()
+    }
+
+    pub fn public_key(&self) -> Result<[u8; PUBLIC_KEY_LEN]> {
+        match (&self.key, &self.account) {
+            (Some(path), None) => {
+                let bytes = std::fs::read(path)
+                    .map_err(|e| WalletError::Io(path.display().to_string(), e))?;
+                pkix::decode_public_key(&bytes, self.key_format)
+            }
+            (None, Some(name)) => Keystore::open_default()?.load(name)?.public_key(),
+            _ => Err(WalletError::Usage(
+                "exactly one of --key or --account is required",
+            )),
+        }
+    }
+
+    /// The key's bytes whatever its scheme: a raw `--key` file as it is,
+    /// anything else decoded as ML-DSA-65.
+    pub fn public_key_bytes(&self) -> Result<Vec<u8>> {
+        match (&self.key, self.key_format) {
+            (Some(path), KeyFormat::Raw) => {
+                std::fs::read(path).map_err(|e| WalletError::Io(path.display().to_string(), e))
+            }
+            _ => self.public_key().map(|pk| pk.to_vec()),
+        }
+    }
+}
+
+/// Selects what is being signed or verified.
+#[derive(clap::Args, Debug)]
+pub struct MessageArgs {
+    /// Hex-encoded 32-byte hash (with or without 0x prefix)
+    #[arg(long, conflicts_with_all = ["message_file", "stdin", "raw"])]
+    pub hash: Option<String>,
+
+    /// Read the message from a file of any length
+    #[arg(long, conflicts_with = "stdin")]
+    pub message_file: Option<PathBuf>,
+
+    /// Read the message from stdin
+    #[arg(long)]
+    pub stdin: bool,
+
+    /// Digest applied to --message-file/--stdin input: keccak256 or sha3-256
+    #[arg(long, default_value = "keccak256")]
+    pub digest: HashAlg,
+
+    /// Use the input bytes directly as the ML-DSA message instead of a digest
+    #[arg(long, conflicts_with = "digest")]
+    pub raw: bool,
+}
+
+impl MessageArgs {
+    pub fn is_present(&self) -> bool {
+        self.hash.is_some() || self.message_file.is_some() || self.stdin
+    }
+
+    /// Resolve the flags into a [`MessageInput`]. Exactly one of `--hash`,
+    /// `--message-file` or `--stdin` must be given.
+    pub fn resolve(&self) -> Result<MessageInput> {
+        let source = match (&self.message_file, self.stdin) {
+            (Some(path), false) => Some(Source::File(path.clone())),
+            (None, true) => Some(Source::Stdin),
+            _ => None,
+        };
+        match (&self.hash, source) {
+            (Some(hash), None) => Ok(MessageInput::Hash(ct::decode_hex_array("hash", hash)?)),
+            (None, Some(src)) if self.raw => Ok(MessageInput::Raw(src)),
+            (None, Some(src)) => Ok(MessageInput::Digest(src, self.digest)),
+            _ => Err(WalletError::Usage(
+                "exactly one of --hash, --message-file or --stdin is required",
+            )),
+        }
+    }
+}
+
+/// An EIP-191 personal message, for `pq-sign personal` and `pq-verify
+/// personal`.
+#[derive(clap::Args, Debug)]
+pub struct PersonalMessageArgs {
+    /// The message text
+    #[arg(
+        long,
+        required_unless_present = "message_file",
+        conflicts_with = "message_file"
+    )]
+    pub message: Option<String>,
+
+    /// Read the message bytes from a file
+    #[arg(long)]
+    pub message_file: Option<PathBuf>,
+
+    /// Bind the hash the way this PQValidatorModule's
+    /// isValidSignatureWithSender does
+    #[arg(long, requires_all = ["smart_account", "caller", "chain_id"])]
+    pub module: Option<Address>,
+
+    /// Smart account the module is installed on, for --module
+    #[arg(long, requires = "module")]
+    pub smart_account: Option<Address>,
+
+    /// Contract that calls isValidSignature on the account, for --module
+    #[arg(long, requires = "module")]
+    pub caller: Option<Address>,
+
+    /// Chain ID, for --module
+    #[arg(long, requires = "module")]
+    pub chain_id: Option<u64>,
+}
+
+impl PersonalMessageArgs {
+    pub fn resolve(&self) -> Result<PersonalMessage> {
+        let message = match (&self.message, &self.message_file) {
+            (Some(text), None) => text.as_bytes().to_vec(),
+            (None, Some(path)) => {
+                std::fs::read(path).map_err(|e| WalletError::Io(path.display().to_string(), e))?
+            }
+            _ => {
+                return Err(WalletError::Usage(
+                    "exactly one of --message or --message-file is required",
+                ));
+            }
+        };
+        let binding = match (self.module, self.smart_account, self.caller, self.chain_id) {
+            (Some(module), Some(account), Some(sender), Some(chain_id)) => Some(Erc1271Binding {
+                module,
+                chain_id,
+                account,
+                sender,
+            }),
+            (None, ..) => None,
+            _ => {
+                return Err(WalletError::Usage(
+                    "--module needs --smart-account, --caller and --chain-id",
+                ));
+            }
+        };
+        Ok(PersonalMessage { message, binding })
+    }
+}
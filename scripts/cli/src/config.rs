@@ -0,0 +1,428 @@
+//! Defaults for the common flags from `pq-wallet.toml` and `PQ_WALLET_*`.
+//!
+//! Each key is named after the flag it fills in, so `chain_id` sets
+//! `--chain-id` on every binary and subcommand that has it:
+//!
+//! ```toml
+//! network = "arbitrum-sepolia"
+//! keystore = "/srv/pq-wallet"
+//! json = true
+//!
+//! [networks.arbitrum-sepolia]
+//! chain_id = 421614
+//! rpc = "https://sepolia-rollup.arbitrum.io/rpc"
+//! bundler = ["https://bundler.example/arbitrum-sepolia", "https://backup.example/421614"]
+//! ```
+//!
+//! A value is taken from, in order: the flag itself, the flag's own
+//! environment variable where it has one (`BUNDLER_RPC`, `LOCAL_RPC`),
+//! `PQ_WALLET_<KEY>` (e.g. `PQ_WALLET_CHAIN_ID`), the selected network, the
+//! top level of the file, and the built-in default. `--network NAME`, on
+//! every binary, or else `PQ_WALLET_NETWORK` picks the network, and
+//! `PQ_WALLET_HOME` still overrides `keystore`. `bundler` takes one URL or
+//! a list, which becomes the comma-separated `--bundler` that
+//! [`crate::failover`] fails over along.
+//!
+//! The file is `$PQ_WALLET_CONFIG` if set, else `./pq-wallet.toml`, else
+//! `pq-wallet.toml` in the keystore root; a missing file is no config.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::{Address, B256};
+use clap::{Command, CommandFactory, FromArgMatches};
+use serde::{Deserialize, Deserializer};
+
+use crate::error::{Result, WalletError};
+use crate::finality::Finality;
+use crate::gas::fee_oracle::FeeSpeed;
+use crate::keystore::Keystore;
+use crate::logging::{LogFormat, Verbosity};
+use crate::output;
+use crate::signing_scheme::SigningScheme;
+
+/// Explicit config file path.
+pub const CONFIG_ENV: &str = "PQ_WALLET_CONFIG";
+pub const CONFIG_FILE: &str = "pq-wallet.toml";
+/// Prefix of the per-key environment variables.
+pub const ENV_PREFIX: &str = "PQ_WALLET_";
+
+fn config_err(reason: impl Into<String>) -> WalletError {
+    WalletError::Config(reason.into())
+}
+
+/// Flag defaults for one network.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Network {
+    pub chain_id: Option<u64>,
+    pub entry_point: Option<Address>,
+    /// Node JSON-RPC URL (`--rpc`).
+    pub rpc: Option<String>,
+    /// Bundler JSON-RPC URLs, in order of preference (`--bundler`).
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub bundler: Option<Vec<String>>,
+    /// What the chain's validator signs over (`--signing-scheme`).
+    pub signing_scheme: Option<SigningScheme>,
+    /// How fees are quoted (`--fee-speed`).
+    pub fee_speed: Option<FeeSpeed>,
+    /// Floor for quoted tips, in wei (`--min-priority-fee`).
+    pub min_priority_fee: Option<u128>,
+    /// When an included op counts as final (`--finality`).
+    pub finality: Option<Finality>,
+    /// ERC-20s to show balances of besides the bundled ones (`--tokens`).
+    pub tokens: Option<Vec<Address>>,
+}
+
+/// A string, or a list of them.
+fn one_or_many<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Vec<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(Some(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(url) => vec![url],
+        OneOrMany::Many(urls) => urls,
+    }))
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Entry of `networks` to use.
+    pub network: Option<String>,
+    #[serde(flatten)]
+    pub defaults: Network,
+    #[serde(default)]
+    pub networks: BTreeMap<String, Network>,
+    /// Keystore root, instead of `~/.pq-wallet`.
+    pub keystore: Option<PathBuf>,
+    /// Signed deployment registry (`--registry`).
+    pub registry: Option<PathBuf>,
+    /// Release keys it may be signed with (`--registry-key`).
+    pub registry_key: Option<Vec<B256>>,
+    /// `--json` by default.
+    pub json: Option<bool>,
+    pub verbosity: Option<Verbosity>,
+    pub log_format: Option<LogFormat>,
+}
+
+impl Config {
+    /// Where the config is read from, if anywhere.
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os(CONFIG_ENV) {
+            return Some(PathBuf::from(path));
+        }
+        let local = PathBuf::from(CONFIG_FILE);
+        if local.is_file() {
+            return Some(local);
+        }
+        Keystore::default_root()
+            .ok()
+            .map(|root| root.join(CONFIG_FILE))
+            .filter(|path| path.is_file())
+    }
+
+    /// The config at [`Config::default_path`]; empty if there is none.
+    pub fn load_default() -> Result<Self> {
+        match Self::default_path() {
+            Some(path) => Self::load(&path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| WalletError::Io(path.display().to_string(), e))?;
+        toml::from_str(&text).map_err(|e| config_err(format!("{}: {e}", path.display())))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| config_err(e.to_string()))
+    }
+
+    /// Flag defaults by long name, after applying `PQ_WALLET_*` and the
+    /// selected network.
+    pub fn resolve(
+        &self,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<BTreeMap<String, String>> {
+        let selected = env("PQ_WALLET_NETWORK").or_else(|| self.network.clone());
+        let network = match &selected {
+            Some(name) => self
+                .networks
+                .get(name)
+                .ok_or_else(|| config_err(format!("network {name:?} is not in [networks]")))?,
+            None => &Network::default(),
+        };
+        let pick = |a: &Option<String>, b: &Option<String>| a.clone().or_else(|| b.clone());
+        let file = [
+            (
+                "chain_id",
+                network
+                    .chain_id
+                    .or(self.defaults.chain_id)
+                    .map(|id| id.to_string()),
+            ),
+            (
+                "entry_point",
+                network
+                    .entry_point
+                    .or(self.defaults.entry_point)
+                    .map(|a| a.to_string()),
+            ),
+            ("rpc", pick(&network.rpc, &self.defaults.rpc)),
+            (
+                "bundler",
+                network
+                    .bundler
+                    .as_ref()
+                    .or(self.defaults.bundler.as_ref())
+                    .map(|urls| urls.join(",")),
+            ),
+            (
+                "signing_scheme",
+                network
+                    .signing_scheme
+                    .or(self.defaults.signing_scheme)
+                    .map(|s| s.to_string()),
+            ),
+            (
+                "fee_speed",
+                network
+                    .fee_speed
+                    .or(self.defaults.fee_speed)
+                    .map(|s| s.to_string()),
+            ),
+            (
+                "min_priority_fee",
+                network
+                    .min_priority_fee
+                    .or(self.defaults.min_priority_fee)
+                    .map(|wei| wei.to_string()),
+            ),
+            (
+                "finality",
+                network
+                    .finality
+                    .or(self.defaults.finality)
+                    .map(|f| f.to_string()),
+            ),
+            (
+                "tokens",
+                network
+                    .tokens
+                    .as_ref()
+                    .or(self.defaults.tokens.as_ref())
+                    .map(|tokens| {
+                        let tokens: Vec<String> = tokens.iter().map(Address::to_string).collect();
+                        tokens.join(",")
+                    }),
+            ),
+            (
+                "registry",
+                self.registry.as_ref().map(|p| p.display().to_string()),
+            ),
+            (
+                "registry_key",
+                self.registry_key.as_ref().map(|keys| {
+                    let keys: Vec<String> = keys.iter().map(B256::to_string).collect();
+                    keys.join(",")
+                }),
+            ),
+            ("json", self.json.map(|j| j.to_string())),
+            ("verbosity", self.verbosity.map(|v| v.to_string())),
+            ("log_format", self.log_format.map(|f| f.to_string())),
+        ];
+        Ok(file
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let value = env(&format!("{ENV_PREFIX}{}", key.to_uppercase())).or(value)?;
+                Some((key.replace('_', "-"), value))
+            })
+            .collect())
+    }
+}
+
+/// Set `defaults` (long name → value) on every matching argument of `cmd`
+/// and its subcommands. A default satisfies a required argument.
+pub fn apply(cmd: Command, defaults: &BTreeMap<String, String>) -> Command {
+    let matching: Vec<_> = cmd
+        .get_arguments()
+        .filter_map(|arg| {
+            let value = defaults.get(arg.get_long()?)?;
+            Some((arg.get_id().clone(), value.clone()))
+        })
+        .collect();
+    let mut cmd = matching.into_iter().fold(cmd, |cmd, (id, value)| {
+        cmd.mut_arg(id, |arg| arg.default_value(value).required(false))
+    });
+    let subcommands: Vec<String> = cmd
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .collect();
+    for name in subcommands {
+        cmd = cmd.mut_subcommand(name, |sub| apply(sub, defaults));
+    }
+    cmd
+}
+
+/// The value of `--network` in `args`, read ahead of parsing because it
+/// decides the other flags' defaults.
+pub fn network_flag(args: impl IntoIterator<Item = String>) -> Option<String> {
+    let mut args = args.into_iter().take_while(|arg| arg != "--");
+    while let Some(arg) = args.next() {
+        if arg == "--network" {
+            return args.next();
+        }
+        if let Some(name) = arg.strip_prefix("--network=") {
+            return Some(name.to_string());
+        }
+    }
+    None
+}
+
+/// `--schema`: print the schema of the subcommand named on the command
+/// line, whatever else it is missing, and exit.
+fn print_schema(cmd: Command) -> ! {
+    let tool = std::env::args()
+        .next()
+        .and_then(|arg0| Some(Path::new(&arg0).file_stem()?.to_str()?.to_string()))
+        .unwrap_or_default();
+    let matches = cmd.ignore_errors(true).try_get_matches().ok();
+    let mut command = Vec::new();
+    let mut next = matches.as_ref().and_then(|m| m.subcommand());
+    while let Some((name, sub)) = next {
+        command.push(name);
+        next = sub.subcommand();
+    }
+    match crate::schema::output(&tool, &command) {
+        Some(schema) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&schema).expect("schemas serialize")
+            );
+            std::process::exit(output::EXIT_OK)
+        }
+        None => {
+            let name = [&[tool.as_str()][..], &command].concat().join(" ");
+            eprintln!("error: {name} has no --json output");
+            std::process::exit(output::EXIT_USAGE)
+        }
+    }
+}
+
+/// `A::parse()` with the config layered underneath the command line. Also
+/// points [`Keystore::default_root`] at the configured `keystore`.
+pub fn parse<A: CommandFactory + FromArgMatches>() -> A {
+    let network = network_flag(std::env::args());
+    let defaults = Config::load_default().and_then(|config| {
+        if let Some(root) = &config.keystore {
+            Keystore::set_default_root(root.clone());
+        }
+        config.resolve(|name| match (name, &network) {
+            ("PQ_WALLET_NETWORK", Some(network)) => Some(network.clone()),
+            _ => std::env::var(name).ok(),
+        })
+    });
+    let defaults = defaults.unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        std::process::exit(output::EXIT_USAGE);
+    });
+    let cmd = A::command().arg(
+        clap::Arg::new("network")
+            .long("network")
+            .global(true)
+            .value_name("NAME")
+            .help("Take flag defaults from [networks.NAME] in pq-wallet.toml"),
+    );
+    let cmd = apply(cmd, &defaults);
+    if std::env::args()
+        .take_while(|arg| arg != "--")
+        .any(|arg| arg == "--schema")
+    {
+        print_schema(cmd);
+    }
+    let matches = cmd.get_matches();
+    A::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = r#"
+        network = "sepolia"
+        chain_id = 1
+        bundler = "http://top"
+        json = true
+
+        [networks.sepolia]
+        chain_id = 421614
+        rpc = "http://node"
+
+        [networks.base]
+        bundler = ["http://a", "http://b"]
+    "#;
+
+    #[test]
+    fn env_beats_network_beats_top_level() {
+        let config = Config::from_toml(FILE).unwrap();
+        let defaults = config.resolve(|_| None).unwrap();
+        assert_eq!(defaults["chain-id"], "421614");
+        assert_eq!(defaults["bundler"], "http://top");
+        assert_eq!(defaults["json"], "true");
+        assert!(!defaults.contains_key("entry-point"));
+
+        let env = |name: &str| (name == "PQ_WALLET_CHAIN_ID").then(|| "10".to_string());
+        assert_eq!(config.resolve(env).unwrap()["chain-id"], "10");
+
+        let base = |name: &str| (name == "PQ_WALLET_NETWORK").then(|| "base".to_string());
+        assert_eq!(
+            config.resolve(base).unwrap()["bundler"],
+            "http://a,http://b"
+        );
+
+        let unknown = |name: &str| (name == "PQ_WALLET_NETWORK").then(|| "nope".to_string());
+        assert!(config.resolve(unknown).is_err());
+        let argv = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            network_flag(argv(&["pq", "balance", "--network", "base"])).as_deref(),
+            Some("base")
+        );
+        assert_eq!(
+            network_flag(argv(&["pq", "--network=base"])).as_deref(),
+            Some("base")
+        );
+        assert_eq!(network_flag(argv(&["pq", "--", "--network", "base"])), None);
+        assert!(Config::from_toml("chainid = 1").is_err());
+    }
+
+    #[test]
+    fn defaults_fill_flags_in_subcommands_and_the_command_line_wins() {
+        #[derive(clap::Parser)]
+        struct Args {
+            #[command(subcommand)]
+            command: Sub,
+        }
+        #[derive(clap::Subcommand)]
+        enum Sub {
+            Hash {
+                #[arg(long)]
+                chain_id: u64,
+            },
+        }
+        let defaults = BTreeMap::from([("chain-id".to_string(), "421614".to_string())]);
+        let parse = |argv: &[&str]| {
+            let matches = apply(Args::command(), &defaults).get_matches_from(argv);
+            let Sub::Hash { chain_id } = Args::from_arg_matches(&matches).unwrap().command;
+            chain_id
+        };
+        assert_eq!(parse(&["pq", "hash"]), 421614);
+        assert_eq!(parse(&["pq", "hash", "--chain-id", "1"]), 1);
+    }
+}
@@ -0,0 +1,177 @@
+//! Differential testing between the `ml-dsa` and `fips204` backends.
+//!
+//! For each randomized case (seed, message, context, signing randomness) we
+//! check that both backends derive the same public key, produce byte-identical
+//! signatures for the same randomness, accept each other's signatures, and
+//! both reject a tampered signature. Any disagreement is a [`Divergence`].
+
+use rand::{Rng, RngExt};
+use serde::Serialize;
+
+use crate::backend::Backend;
+use crate::ct;
+use crate::keys::SEED_LEN;
+
+/// One randomized input to the harness.
+#[derive(Clone, Debug)]
+pub struct Case {
+    pub seed: [u8; SEED_LEN],
+    pub message: Vec<u8>,
+    pub context: Vec<u8>,
+    pub rnd: [u8; 32],
+}
+
+impl Case {
+    pub fn random<R: Rng>(rng: &mut R, max_message_len: usize) -> Self {
+        let msg_len = rng.random_range(0..=max_message_len);
+        // Most callers use an empty context; exercise it half the time.
+        let ctx_len = if rng.random_bool(0.5) {
+            0
+        } else {
+            rng.random_range(1..=255)
+        };
+        let mut message = vec![0u8; msg_len];
+        rng.fill(&mut message[..]);
+        let mut context = vec![0u8; ctx_len];
+        rng.fill(&mut context[..]);
+        Case {
+            seed: rng.random(),
+            message,
+            context,
+            rnd: rng.random(),
+        }
+    }
+}
+
+/// What the two backends disagreed on.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DivergenceKind {
+    PublicKeyMismatch,
+    SignatureMismatch,
+    SignFailed { backend: Backend, error: String },
+    CrossVerifyFailed { signer: Backend, verifier: Backend },
+    TamperedAccepted { verifier: Backend },
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Divergence {
+    pub case: usize,
+    /// Hex seed so a failing case can be replayed with `pq-keygen`.
+    pub seed: String,
+    pub message_len: usize,
+    pub context_len: usize,
+    #[serde(flatten)]
+    pub kind: DivergenceKind,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ConformanceReport {
+    pub cases: usize,
+    pub divergences: Vec<Divergence>,
+}
+
+impl ConformanceReport {
+    pub fn is_clean(&self) -> bool {
+        self.divergences.is_empty()
+    }
+}
+
+/// Run every cross-check for a single case and return the disagreements.
+pub fn check_case(case: &Case) -> Vec<DivergenceKind> {
+    let mut out = Vec::new();
+
+    let pks: Vec<Vec<u8>> = Backend::ALL
+        .iter()
+        .map(|b| b.public_key(&case.seed))
+        .collect();
+    if !ct::eq(&pks[0], &pks[1]) {
+        out.push(DivergenceKind::PublicKeyMismatch);
+        // Without an agreed key the remaining checks are meaningless.
+        return out;
+    }
+    let pk = &pks[0];
+
+    let mut sigs = Vec::new();
+    for &backend in Backend::ALL {
+        match backend.sign(&case.seed, &case.message, &case.context, &case.rnd) {
+            Ok(sig) => sigs.push((backend, sig)),
+            Err(e) => out.push(DivergenceKind::SignFailed {
+                backend,
+                error: e.to_string(),
+            }),
+        }
+    }
+    if sigs.len() == 2 && sigs[0].1 != sigs[1].1 {
+        out.push(DivergenceKind::SignatureMismatch);
+    }
+
+    for (signer, sig) in &sigs {
+        for &verifier in Backend::ALL {
+            if !verifier.verify(pk, &case.message, &case.context, sig) {
+                out.push(DivergenceKind::CrossVerifyFailed {
+                    signer: *signer,
+                    verifier,
+                });
+            }
+        }
+    }
+
+    if let Some((_, sig)) = sigs.first() {
+        let mut tampered = sig.clone();
+        tampered[0] ^= 0x01;
+        for &verifier in Backend::ALL {
+            if verifier.verify(pk, &case.message, &case.context, &tampered) {
+                out.push(DivergenceKind::TamperedAccepted { verifier });
+            }
+        }
+    }
+
+    out
+}
+
+/// Run `cases` randomized cases with messages up to `max_message_len` bytes.
+pub fn run<R: Rng>(cases: usize, max_message_len: usize, rng: &mut R) -> ConformanceReport {
+    let mut report = ConformanceReport {
+        cases,
+        ..Default::default()
+    };
+    for i in 0..cases {
+        let case = Case::random(rng, max_message_len);
+        for kind in check_case(&case) {
+            report.divergences.push(Divergence {
+                case: i,
+                seed: ct::encode_hex(&case.seed),
+                message_len: case.message.len(),
+                context_len: case.context.len(),
+                kind,
+            });
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn backends_agree_on_random_cases() {
+        let report = run(4, 256, &mut StdRng::seed_from_u64(7));
+        assert_eq!(report.cases, 4);
+        assert!(report.is_clean(), "{:?}", report.divergences);
+    }
+
+    #[test]
+    fn zero_rnd_and_empty_context_agree() {
+        let case = Case {
+            seed: [0x42; SEED_LEN],
+            message: vec![0xAB; 32],
+            context: vec![],
+            rnd: [0u8; 32],
+        };
+        assert!(check_case(&case).is_empty());
+    }
+}
@@ -0,0 +1,368 @@
+//! The address book at `~/.pq-wallet/contacts.json` (`pq-contacts`).
+//!
+//! Contacts name addresses so commands can take `--to alice` instead of 20
+//! bytes of hex, and so reviews print `0x… (alice)` next to every address
+//! they know. The book is canonical both ways: names are lowercase and
+//! unique, and an address has at most one name, so a label in a review
+//! always means the same thing. [`Target`] is what a `--to` argument can be:
+//! an address (checksummed if mixed-case), a contact, or an ENS name (see
+//! [`crate::ens`]).
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use alloy_primitives::Address;
+use serde::{Deserialize, Serialize};
+
+use crate::ens;
+use crate::error::{Result, WalletError};
+use crate::keystore::Keystore;
+use crate::rpc::Transport;
+
+pub const CONTACTS_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Contact {
+    pub address: Address,
+    /// The ENS name the address was resolved from when added.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ens: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct File {
+    version: u32,
+    contacts: BTreeMap<String, Contact>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AddressBook {
+    path: PathBuf,
+    contacts: BTreeMap<String, Contact>,
+}
+
+/// Contact names: 1-64 of `[a-z0-9_-]`, so they never look like an address
+/// or an ENS name.
+pub fn normalize_name(name: &str) -> Result<String> {
+    let lower = name.to_ascii_lowercase();
+    let ok = !lower.is_empty()
+        && lower.len() <= 64
+        && !lower.starts_with("0x")
+        && lower
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if ok {
+        Ok(lower)
+    } else {
+        Err(WalletError::Contacts(format!(
+            "invalid contact name {name:?} (use 1-64 of [a-z0-9_-], not starting with 0x)"
+        )))
+    }
+}
+
+impl AddressBook {
+    /// `contacts.json` next to the keystore (see [`Keystore::default_root`]).
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(Keystore::default_root()?.join("contacts.json"))
+    }
+
+    pub fn open_default() -> Result<Self> {
+        Self::open(Self::default_path()?)
+    }
+
+    /// The book at `path`, empty if the file does not exist yet.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let contacts = match std::fs::read(&path) {
+            Ok(bytes) => {
+                let file: File = serde_json::from_slice(&bytes)
+                    .map_err(|e| WalletError::Contacts(format!("{}: {e}", path.display())))?;
+                if file.version != CONTACTS_VERSION {
+                    return Err(WalletError::Contacts(format!(
+                        "{}: unsupported version {}",
+                        path.display(),
+                        file.version
+                    )));
+                }
+                file.contacts
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(WalletError::Io(path.display().to_string(), e)),
+        };
+        Ok(AddressBook { path, contacts })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Add `name`, refusing a name or address that is already taken.
+    pub fn add(&mut self, name: &str, contact: Contact) -> Result<()> {
+        let name = normalize_name(name)?;
+        if self.contacts.contains_key(&name) {
+            return Err(WalletError::Contacts(format!("{name} already exists")));
+        }
+        if let Some(existing) = self.name_of(contact.address) {
+            return Err(WalletError::Contacts(format!(
+                "{} is already in the address book as {existing}",
+                contact.address
+            )));
+        }
+        self.contacts.insert(name, contact);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<Contact> {
+        let name = normalize_name(name)?;
+        self.contacts
+            .remove(&name)
+            .ok_or_else(|| WalletError::Contacts(format!("no contact named {name}")))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Contact> {
+        self.contacts.get(&name.to_ascii_lowercase())
+    }
+
+    pub fn name_of(&self, address: Address) -> Option<&str> {
+        self.contacts
+            .iter()
+            .find(|(_, c)| c.address == address)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// All contacts, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Contact)> {
+        self.contacts.iter().map(|(name, c)| (name.as_str(), c))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| WalletError::Io(dir.display().to_string(), e))?;
+        }
+        let file = File {
+            version: CONTACTS_VERSION,
+            contacts: self.contacts.clone(),
+        };
+        let json = serde_json::to_string_pretty(&file).expect("address book serializes");
+        std::fs::write(&self.path, json)
+            .map_err(|e| WalletError::Io(self.path.display().to_string(), e))
+    }
+}
+
+/// A `--to` argument.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    Address(Address),
+    Contact(String),
+    Ens(String),
+}
+
+impl FromStr for Target {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.starts_with("0x") || s.starts_with("0X") {
+            let hex = &s[2..];
+            let mixed = hex.bytes().any(|b| b.is_ascii_lowercase())
+                && hex.bytes().any(|b| b.is_ascii_uppercase());
+            let parsed = if mixed {
+                Address::parse_checksummed(s, None).map_err(|_| {
+                    WalletError::Contacts(format!(
+                        "{s} fails its EIP-55 checksum; check for a typo"
+                    ))
+                })?
+            } else {
+                s.parse()
+                    .map_err(|_| WalletError::Contacts(format!("{s} is not a 20-byte address")))?
+            };
+            Ok(Target::Address(parsed))
+        } else if ens::is_name(s) {
+            Ok(Target::Ens(ens::normalize(s)?))
+        } else {
+            Ok(Target::Contact(normalize_name(s)?))
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Address(a) => write!(f, "{a}"),
+            Target::Contact(name) | Target::Ens(name) => f.write_str(name),
+        }
+    }
+}
+
+impl Target {
+    /// The address, looking contacts up in `book` and ENS names up on
+    /// `ens_rpc`.
+    pub fn resolve<T: Transport>(
+        &self,
+        book: &AddressBook,
+        ens_rpc: Option<&T>,
+    ) -> Result<Address> {
+        match self {
+            Target::Address(a) => Ok(*a),
+            Target::Contact(name) => book.get(name).map(|c| c.address).ok_or_else(|| {
+                WalletError::Contacts(format!(
+                    "no contact named {name} (add it with `pq-contacts add`)"
+                ))
+            }),
+            Target::Ens(name) => match ens_rpc {
+                Some(rpc) => ens::resolve(rpc, name),
+                None => Err(WalletError::Ens(format!(
+                    "resolving {name} needs a node (--ens-rpc)"
+                ))),
+            },
+        }
+    }
+}
+
+/// Names to print next to addresses in a review.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Names(BTreeMap<Address, String>);
+
+impl Names {
+    pub fn from_book(book: &AddressBook) -> Self {
+        Names(
+            book.iter()
+                .map(|(name, c)| (c.address, name.to_string()))
+                .collect(),
+        )
+    }
+
+    pub fn insert(&mut self, address: Address, name: impl Into<String>) {
+        self.0.entry(address).or_insert_with(|| name.into());
+    }
+
+    pub fn get(&self, address: Address) -> Option<&str> {
+        self.0.get(&address).map(String::as_str)
+    }
+
+    /// Add the verified ENS primary name of each unnamed address in `lines`.
+    pub fn add_reverse<T: Transport>(&mut self, rpc: &T, lines: &[String]) -> Result<()> {
+        for address in addresses_in(lines) {
+            if self.get(address).is_none()
+                && let Some(name) = ens::reverse(rpc, address)?
+            {
+                self.insert(address, name);
+            }
+        }
+        Ok(())
+    }
+
+    /// `address`, followed by its name if it has one.
+    pub fn label(&self, address: Address) -> String {
+        match self.get(address) {
+            Some(name) => format!("{address} ({name})"),
+            None => address.to_string(),
+        }
+    }
+
+    /// `line` with every named address labelled.
+    pub fn annotate(&self, line: &str) -> String {
+        let mut out = line.to_string();
+        for (address, name) in &self.0 {
+            out = out.replace(&address.to_string(), &format!("{address} ({name})"));
+        }
+        out
+    }
+}
+
+/// The distinct `0x`-prefixed addresses printed in `lines`.
+pub fn addresses_in(lines: &[String]) -> Vec<Address> {
+    let mut found = Vec::new();
+    for line in lines {
+        let bytes = line.as_bytes();
+        let mut i = 0;
+        while let Some(at) = line[i..].find("0x") {
+            let start = i + at;
+            let end = start + 42;
+            let is_hex = |j: usize| j < bytes.len() && bytes[j].is_ascii_hexdigit();
+            if end <= bytes.len()
+                && (start + 2..end).all(is_hex)
+                && !is_hex(end)
+                && let Ok(address) = line[start..end].parse::<Address>()
+                && !found.contains(&address)
+            {
+                found.push(address);
+            }
+            i = start + 2;
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::address;
+
+    const ALICE: Address = address!("a11ce00000000000000000000000000000000001");
+
+    #[test]
+    fn book_is_canonical_and_survives_a_reload() {
+        let path = std::env::temp_dir().join(format!("pq-contacts-{}.json", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let mut book = AddressBook::open(&path).unwrap();
+        let contact = Contact {
+            address: ALICE,
+            ens: None,
+            note: Some("ops multisig".into()),
+        };
+        book.add("Alice", contact.clone()).unwrap();
+        assert!(book.add("alice", contact.clone()).is_err(), "name taken");
+        assert!(book.add("bob", contact.clone()).is_err(), "address taken");
+        assert!(book.add("0xbob", contact.clone()).is_err());
+        book.save().unwrap();
+
+        let book = AddressBook::open(&path).unwrap();
+        assert_eq!(book.get("ALICE"), Some(&contact));
+        assert_eq!(book.name_of(ALICE), Some("alice"));
+        assert_eq!(
+            "alice".parse::<Target>().unwrap(),
+            Target::Contact("alice".into())
+        );
+        let resolved =
+            Target::Contact("alice".into()).resolve::<crate::rpc::HttpTransport>(&book, None);
+        assert_eq!(resolved.unwrap(), ALICE);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn targets_reject_bad_checksums_and_reviews_get_labels() {
+        let checksummed = ALICE.to_string();
+        assert_eq!(
+            checksummed.parse::<Target>().unwrap(),
+            Target::Address(ALICE)
+        );
+        assert!(checksummed.to_lowercase().parse::<Target>().is_ok());
+        let mut typo = checksummed.clone().into_bytes();
+        let flip = typo.iter().rposition(u8::is_ascii_alphabetic).unwrap();
+        typo[flip] ^= 0x20;
+        assert!(String::from_utf8(typo).unwrap().parse::<Target>().is_err());
+        assert_eq!(
+            "Alice.ETH".parse::<Target>().unwrap(),
+            Target::Ens("alice.eth".into())
+        );
+
+        let mut names = Names::default();
+        names.insert(ALICE, "alice");
+        let line = format!("transfer 5 USDC to {ALICE}");
+        assert_eq!(
+            names.annotate(&line),
+            format!("transfer 5 USDC to {ALICE} (alice)")
+        );
+        let other = Address::repeat_byte(0x42);
+        assert_eq!(
+            addresses_in(&[line, format!("send 1 ETH to {other}, {other}")]),
+            [ALICE, other]
+        );
+    }
+}
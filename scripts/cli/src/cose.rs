@@ -0,0 +1,517 @@
+//! COSE_Key encoding of ML-DSA-65 keys and COSE_Sign1 signatures
+//! (draft-ietf-cose-dilithium), and the small CBOR subset they need.
+//!
+//! ML-DSA keys use the Algorithm Key Pair key type:
+//!
+//! ```text
+//! { 1 (kty): 7 (AKP), 3 (alg): -49 (ML-DSA-65), -1 (pub): bstr, ?-2 (priv): bstr seed }
+//! ```
+//!
+//! A signature is a tagged COSE_Sign1 (RFC 9052 §4.2) whose protected
+//! header carries the algorithm and the key's fingerprint as `kid`:
+//!
+//! ```text
+//! 18([ bstr .cbor { 1 (alg): -49, 4 (kid): bstr }, {}, payload: bstr / nil, signature: bstr ])
+//! ```
+//!
+//! ML-DSA signs the `Signature1` Sig_structure in pure mode with an empty
+//! context, so the payload is covered whole rather than prehashed.
+//!
+//! Encoding is deterministic (RFC 8949 §4.2: shortest-form heads, map keys
+//! sorted bytewise on their encodings), so the same key always gives the
+//! same bytes. Decoding accepts any map order and ignores labels it does not
+//! know, such as `kid`.
+
+use ml_dsa::{MlDsa65, Signature, SigningKey, VerifyingKey};
+use zeroize::Zeroizing;
+
+use crate::backend::Backend;
+use crate::error::{Result, WalletError};
+use crate::keys::{PUBLIC_KEY_LEN, SEED_LEN};
+
+/// COSE key type "AKP" (Algorithm Key Pair).
+pub const KTY_AKP: i64 = 7;
+/// COSE algorithm identifier for ML-DSA-65.
+pub const ALG_ML_DSA_65: i64 = -49;
+
+const LABEL_KTY: i64 = 1;
+const LABEL_ALG: i64 = 3;
+const LABEL_PUB: i64 = -1;
+const LABEL_PRIV: i64 = -2;
+
+/// CBOR tag for COSE_Sign1_Tagged.
+pub const TAG_COSE_SIGN1: u64 = 18;
+
+const HEADER_ALG: i64 = 1;
+const HEADER_CRIT: i64 = 2;
+const HEADER_KID: i64 = 4;
+
+/// Nesting limit when decoding, so hostile input cannot blow the stack.
+const MAX_DEPTH: usize = 16;
+
+/// A CBOR data item, restricted to the major types COSE structures use.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cbor {
+    Int(i64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Cbor>),
+    Map(Vec<(Cbor, Cbor)>),
+    Tag(u64, Box<Cbor>),
+    Null,
+}
+
+fn cbor_err(reason: &str) -> WalletError {
+    WalletError::InvalidEnvelope(format!("CBOR: {reason}"))
+}
+
+fn head(out: &mut Vec<u8>, major: u8, n: u64) {
+    let major = major << 5;
+    match n {
+        0..24 => out.push(major | n as u8),
+        24..0x100 => out.extend([major | 24, n as u8]),
+        0x100..0x1_0000 => {
+            out.push(major | 25);
+            out.extend((n as u16).to_be_bytes());
+        }
+        0x1_0000..0x1_0000_0000 => {
+            out.push(major | 26);
+            out.extend((n as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend(n.to_be_bytes());
+        }
+    }
+}
+
+impl Cbor {
+    /// Deterministic encoding.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            Cbor::Int(n) if *n >= 0 => head(out, 0, *n as u64),
+            Cbor::Int(n) => head(out, 1, (-1 - *n) as u64),
+            Cbor::Bytes(b) => {
+                head(out, 2, b.len() as u64);
+                out.extend_from_slice(b);
+            }
+            Cbor::Text(t) => {
+                head(out, 3, t.len() as u64);
+                out.extend_from_slice(t.as_bytes());
+            }
+            Cbor::Array(items) => {
+                head(out, 4, items.len() as u64);
+                for item in items {
+                    item.encode_into(out);
+                }
+            }
+            Cbor::Map(entries) => {
+                let mut encoded: Vec<(Vec<u8>, Vec<u8>)> = entries
+                    .iter()
+                    .map(|(k, v)| (k.encode(), v.encode()))
+                    .collect();
+                encoded.sort();
+                head(out, 5, encoded.len() as u64);
+                for (k, v) in encoded {
+                    out.extend(k);
+                    out.extend(v);
+                }
+            }
+            Cbor::Tag(tag, item) => {
+                head(out, 6, *tag);
+                item.encode_into(out);
+            }
+            Cbor::Null => out.push(0xf6),
+        }
+    }
+
+    /// Decode exactly one item from `input`.
+    pub fn decode(input: &[u8]) -> Result<Cbor> {
+        let (item, rest) = Cbor::decode_prefix(input, 0)?;
+        if !rest.is_empty() {
+            return Err(cbor_err("trailing data"));
+        }
+        Ok(item)
+    }
+
+    fn decode_prefix(input: &[u8], depth: usize) -> Result<(Cbor, &[u8])> {
+        if depth > MAX_DEPTH {
+            return Err(cbor_err("nested too deeply"));
+        }
+        let (&first, rest) = input.split_first().ok_or_else(|| cbor_err("truncated"))?;
+        let (major, info) = (first >> 5, first & 0x1f);
+        let (n, mut rest) = match info {
+            0..24 => (info as u64, rest),
+            24..=27 => {
+                let width = 1usize << (info - 24);
+                if rest.len() < width {
+                    return Err(cbor_err("truncated"));
+                }
+                let n = rest[..width]
+                    .iter()
+                    .fold(0u64, |acc, &b| acc << 8 | b as u64);
+                (n, &rest[width..])
+            }
+            _ => return Err(cbor_err("indefinite lengths are not supported")),
+        };
+        let take = |rest: &[u8], len: u64| -> Result<(Vec<u8>, usize)> {
+            let len = usize::try_from(len).map_err(|_| cbor_err("length overflow"))?;
+            if rest.len() < len {
+                return Err(cbor_err("truncated"));
+            }
+            Ok((rest[..len].to_vec(), len))
+        };
+        let int = |n: u64| i64::try_from(n).map_err(|_| cbor_err("integer out of range"));
+        let item = match major {
+            0 => Cbor::Int(int(n)?),
+            1 => Cbor::Int(-1 - int(n)?),
+            2 => {
+                let (bytes, len) = take(rest, n)?;
+                rest = &rest[len..];
+                Cbor::Bytes(bytes)
+            }
+            3 => {
+                let (bytes, len) = take(rest, n)?;
+                rest = &rest[len..];
+                Cbor::Text(String::from_utf8(bytes).map_err(|_| cbor_err("text is not UTF-8"))?)
+            }
+            4 | 5 => {
+                // Every item takes at least one byte.
+                if n > rest.len() as u64 {
+                    return Err(cbor_err("truncated"));
+                }
+                let mut items = Vec::with_capacity(n as usize);
+                let mut entries = Vec::with_capacity(if major == 5 { n as usize } else { 0 });
+                for _ in 0..n {
+                    let (item, r) = Cbor::decode_prefix(rest, depth + 1)?;
+                    if major == 5 {
+                        let (value, r) = Cbor::decode_prefix(r, depth + 1)?;
+                        entries.push((item, value));
+                        rest = r;
+                    } else {
+                        items.push(item);
+                        rest = r;
+                    }
+                }
+                if major == 4 {
+                    Cbor::Array(items)
+                } else {
+                    Cbor::Map(entries)
+                }
+            }
+            6 => {
+                let (item, r) = Cbor::decode_prefix(rest, depth + 1)?;
+                rest = r;
+                Cbor::Tag(n, Box::new(item))
+            }
+            7 if info == 22 => Cbor::Null,
+            _ => return Err(cbor_err("unsupported major type")),
+        };
+        Ok((item, rest))
+    }
+
+    /// Look up an integer label in a map.
+    pub fn get(&self, label: i64) -> Option<&Cbor> {
+        match self {
+            Cbor::Map(entries) => entries
+                .iter()
+                .find(|(k, _)| *k == Cbor::Int(label))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+fn key_map(pk: &[u8; PUBLIC_KEY_LEN], seed: Option<&[u8; SEED_LEN]>) -> Cbor {
+    let mut entries = vec![
+        (Cbor::Int(LABEL_KTY), Cbor::Int(KTY_AKP)),
+        (Cbor::Int(LABEL_ALG), Cbor::Int(ALG_ML_DSA_65)),
+        (Cbor::Int(LABEL_PUB), Cbor::Bytes(pk.to_vec())),
+    ];
+    if let Some(seed) = seed {
+        entries.push((Cbor::Int(LABEL_PRIV), Cbor::Bytes(seed.to_vec())));
+    }
+    Cbor::Map(entries)
+}
+
+/// COSE_Key for a public key.
+pub fn public_key_to_cose(pk: &[u8; PUBLIC_KEY_LEN]) -> Vec<u8> {
+    key_map(pk, None).encode()
+}
+
+/// COSE_Key carrying both the public key and the seed.
+pub fn private_key_to_cose(pk: &[u8; PUBLIC_KEY_LEN], seed: &[u8; SEED_LEN]) -> Zeroizing<Vec<u8>> {
+    Zeroizing::new(key_map(pk, Some(seed)).encode())
+}
+
+fn parse_key(bytes: &[u8]) -> Result<Cbor> {
+    let key = Cbor::decode(bytes)?;
+    if key.get(LABEL_KTY) != Some(&Cbor::Int(KTY_AKP)) {
+        return Err(WalletError::InvalidEnvelope(
+            "COSE_Key kty is not AKP".to_string(),
+        ));
+    }
+    // alg is optional in COSE_Key, but if present it must be ML-DSA-65.
+    match key.get(LABEL_ALG) {
+        None | Some(Cbor::Int(ALG_ML_DSA_65)) => Ok(key),
+        Some(other) => Err(WalletError::InvalidEnvelope(format!(
+            "COSE_Key alg {other:?} is not ML-DSA-65 (-49)"
+        ))),
+    }
+}
+
+fn bytes_param<const N: usize>(key: &Cbor, label: i64, what: &'static str) -> Result<[u8; N]> {
+    match key.get(label) {
+        Some(Cbor::Bytes(b)) => b
+            .as_slice()
+            .try_into()
+            .map_err(|_| WalletError::InvalidLength {
+                what,
+                expected: N,
+                actual: b.len(),
+            }),
+        _ => Err(WalletError::InvalidEnvelope(format!(
+            "COSE_Key has no {what}"
+        ))),
+    }
+}
+
+/// Public key from a COSE_Key (public or private).
+pub fn public_key_from_cose(bytes: &[u8]) -> Result<[u8; PUBLIC_KEY_LEN]> {
+    bytes_param(&parse_key(bytes)?, LABEL_PUB, "public key")
+}
+
+/// Seed from a private COSE_Key.
+pub fn private_key_from_cose(bytes: &[u8]) -> Result<Zeroizing<[u8; SEED_LEN]>> {
+    let key = parse_key(bytes)?;
+    bytes_param(&key, LABEL_PRIV, "seed").map(Zeroizing::new)
+}
+
+fn sign1_err(reason: impl std::fmt::Display) -> WalletError {
+    WalletError::InvalidEnvelope(format!("COSE_Sign1: {reason}"))
+}
+
+/// A decoded COSE_Sign1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sign1 {
+    /// The protected header exactly as encoded, which the signature covers.
+    pub protected: Vec<u8>,
+    /// Key ID from the protected header, else the unprotected one.
+    pub kid: Option<Vec<u8>>,
+    /// `None` when the payload is detached.
+    pub payload: Option<Vec<u8>>,
+    pub signature: Vec<u8>,
+}
+
+/// The bytes ML-DSA signs: `["Signature1", protected, h'', payload]`.
+pub fn sig_structure(protected: &[u8], payload: &[u8]) -> Vec<u8> {
+    Cbor::Array(vec![
+        Cbor::Text("Signature1".to_string()),
+        Cbor::Bytes(protected.to_vec()),
+        Cbor::Bytes(Vec::new()),
+        Cbor::Bytes(payload.to_vec()),
+    ])
+    .encode()
+}
+
+fn protected_header(kid: &[u8]) -> Vec<u8> {
+    Cbor::Map(vec![
+        (Cbor::Int(HEADER_ALG), Cbor::Int(ALG_ML_DSA_65)),
+        (Cbor::Int(HEADER_KID), Cbor::Bytes(kid.to_vec())),
+    ])
+    .encode()
+}
+
+/// Sign `payload` into a COSE_Sign1 that carries it attached.
+pub fn sign1(sk: &SigningKey<MlDsa65>, kid: &[u8], payload: &[u8]) -> Sign1 {
+    use ml_dsa::signature::Signer;
+
+    let protected = protected_header(kid);
+    let signature = sk.sign(&sig_structure(&protected, payload)).encode();
+    Sign1 {
+        protected,
+        kid: Some(kid.to_vec()),
+        payload: Some(payload.to_vec()),
+        signature: signature.to_vec(),
+    }
+}
+
+/// [`sign1`] with the seed, on `backend`.
+pub fn sign1_on(
+    backend: Backend,
+    seed: &[u8; SEED_LEN],
+    kid: &[u8],
+    payload: &[u8],
+) -> Result<Sign1> {
+    let protected = protected_header(kid);
+    let signature = backend.sign(seed, &sig_structure(&protected, payload), &[], &[0; 32])?;
+    Ok(Sign1 {
+        protected,
+        kid: Some(kid.to_vec()),
+        payload: Some(payload.to_vec()),
+        signature,
+    })
+}
+
+/// Decode a COSE_Sign1, tagged or not, whose protected header names
+/// ML-DSA-65. Critical headers are refused, since none are understood.
+pub fn decode_sign1(bytes: &[u8]) -> Result<Sign1> {
+    let item = match Cbor::decode(bytes)? {
+        Cbor::Tag(TAG_COSE_SIGN1, item) => *item,
+        Cbor::Tag(tag, _) => return Err(sign1_err(format!("tag {tag} is not COSE_Sign1 (18)"))),
+        item => item,
+    };
+    let Cbor::Array(parts) = item else {
+        return Err(sign1_err("not an array"));
+    };
+    let [
+        Cbor::Bytes(protected),
+        unprotected @ Cbor::Map(_),
+        payload,
+        Cbor::Bytes(signature),
+    ] = <[Cbor; 4]>::try_from(parts).map_err(|_| sign1_err("not four elements"))?
+    else {
+        return Err(sign1_err("malformed structure"));
+    };
+    let header = if protected.is_empty() {
+        Cbor::Map(Vec::new())
+    } else {
+        Cbor::decode(&protected)?
+    };
+    match header.get(HEADER_ALG) {
+        Some(Cbor::Int(ALG_ML_DSA_65)) => {}
+        Some(other) => return Err(sign1_err(format!("alg {other:?} is not ML-DSA-65 (-49)"))),
+        None => return Err(sign1_err("protected header has no alg")),
+    }
+    if header.get(HEADER_CRIT).is_some() {
+        return Err(sign1_err("critical headers are not supported"));
+    }
+    let kid = match header
+        .get(HEADER_KID)
+        .or_else(|| unprotected.get(HEADER_KID))
+    {
+        Some(Cbor::Bytes(kid)) => Some(kid.clone()),
+        Some(_) => return Err(sign1_err("kid is not a byte string")),
+        None => None,
+    };
+    let payload = match payload {
+        Cbor::Bytes(payload) => Some(payload),
+        Cbor::Null => None,
+        _ => return Err(sign1_err("payload is not a byte string or nil")),
+    };
+    Ok(Sign1 {
+        protected,
+        kid,
+        payload,
+        signature,
+    })
+}
+
+impl Sign1 {
+    /// COSE_Sign1_Tagged, with an empty unprotected header.
+    pub fn encode(&self) -> Vec<u8> {
+        Cbor::Tag(
+            TAG_COSE_SIGN1,
+            Box::new(Cbor::Array(vec![
+                Cbor::Bytes(self.protected.clone()),
+                Cbor::Map(Vec::new()),
+                self.payload.clone().map_or(Cbor::Null, Cbor::Bytes),
+                Cbor::Bytes(self.signature.clone()),
+            ])),
+        )
+        .encode()
+    }
+
+    /// Whether the signature verifies under `vk` over `payload`, which is
+    /// the attached payload unless it was detached.
+    pub fn verify(&self, vk: &VerifyingKey<MlDsa65>, payload: &[u8]) -> bool {
+        let Ok(sig) = Signature::<MlDsa65>::try_from(self.signature.as_slice()) else {
+            return false;
+        };
+        vk.verify_with_context(&sig_structure(&self.protected, payload), &[], &sig)
+    }
+
+    /// [`verify`](Self::verify) on `backend`, with the encoded key.
+    pub fn verify_on(&self, backend: Backend, pk: &[u8], payload: &[u8]) -> bool {
+        backend.verify(
+            pk,
+            &sig_structure(&self.protected, payload),
+            &[],
+            &self.signature,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cbor_heads_are_shortest_form() {
+        assert_eq!(Cbor::Int(ALG_ML_DSA_65).encode(), [0x38, 0x30]);
+        assert_eq!(Cbor::Int(23).encode(), [0x17]);
+        assert_eq!(Cbor::Int(-1).encode(), [0x20]);
+        let pk = Cbor::Bytes(vec![0; PUBLIC_KEY_LEN]).encode();
+        assert_eq!(pk[..3], [0x59, 0x07, 0xa0]);
+        // Keys sort on their encodings: 1, 3, then -1 (0x20) and -2 (0x21).
+        let encoded = key_map(&[0; PUBLIC_KEY_LEN], Some(&[0; SEED_LEN])).encode();
+        assert_eq!(encoded[..6], [0xa4, 0x01, 0x07, 0x03, 0x38, 0x30]);
+        assert!(Cbor::decode(&[0x9f]).is_err());
+        assert!(Cbor::decode(&[0x81; 64]).is_err());
+    }
+
+    #[test]
+    fn cose_key_round_trips_and_checks_alg() {
+        let pk = [5u8; PUBLIC_KEY_LEN];
+        let seed = [6u8; SEED_LEN];
+        assert_eq!(public_key_from_cose(&public_key_to_cose(&pk)).unwrap(), pk);
+        let private = private_key_to_cose(&pk, &seed);
+        assert_eq!(public_key_from_cose(&private).unwrap(), pk);
+        assert_eq!(*private_key_from_cose(&private).unwrap(), seed);
+        assert!(private_key_from_cose(&public_key_to_cose(&pk)).is_err());
+
+        let ml_dsa_44 = Cbor::Map(vec![
+            (Cbor::Int(LABEL_KTY), Cbor::Int(KTY_AKP)),
+            (Cbor::Int(LABEL_ALG), Cbor::Int(-48)),
+            (Cbor::Int(LABEL_PUB), Cbor::Bytes(pk.to_vec())),
+        ]);
+        assert!(public_key_from_cose(&ml_dsa_44.encode()).is_err());
+    }
+
+    #[test]
+    fn sign1_round_trips_and_binds_its_headers() {
+        let sk = SigningKey::<MlDsa65>::from_seed(&[7u8; SEED_LEN].into());
+        let vk = sk.verifying_key();
+        let encoded = sign1(&sk, b"kid", b"payload").encode();
+        // Tag 18, then a four-element array.
+        assert_eq!(encoded[..2], [0xd2, 0x84]);
+        let cose = decode_sign1(&encoded).unwrap();
+        assert_eq!(cose.kid.as_deref(), Some(&b"kid"[..]));
+        assert_eq!(cose.payload.as_deref(), Some(&b"payload"[..]));
+        assert!(cose.verify(&vk, b"payload"));
+        assert!(!cose.verify(&vk, b"payloaf"));
+
+        // Swapping in another kid changes the protected bytes it signed.
+        let mut relabelled = cose.clone();
+        relabelled.protected = Cbor::Map(vec![
+            (Cbor::Int(HEADER_ALG), Cbor::Int(ALG_ML_DSA_65)),
+            (Cbor::Int(HEADER_KID), Cbor::Bytes(b"other".to_vec())),
+        ])
+        .encode();
+        assert!(!relabelled.verify(&vk, b"payload"));
+
+        let es256 = Cbor::Array(vec![
+            Cbor::Bytes(Cbor::Map(vec![(Cbor::Int(HEADER_ALG), Cbor::Int(-7))]).encode()),
+            Cbor::Map(Vec::new()),
+            Cbor::Null,
+            Cbor::Bytes(cose.signature),
+        ]);
+        assert!(decode_sign1(&es256.encode()).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+//! Constant-time encoding and comparison helpers.
+//!
+//! Anything that may carry secret material (seeds, expanded keys, MACs) should
+//! be decoded and compared through these functions rather than `hex::decode`
+//! and `==`, which branch on the data. Public values such as hashes may use
+//! them too; the cost is negligible.
+
+use std::time::{Duration, Instant};
+
+use ml_dsa::{MlDsa65, SigningKey, signature::Signer};
+use subtle::ConstantTimeEq;
+
+use crate::error::{Result, WalletError};
+
+/// Strip an optional `0x`/`0X` prefix. Only the prefix is inspected, never the
+/// payload, so this does not leak anything about the encoded bytes.
+fn strip_0x(input: &str) -> &str {
+    input
+        .strip_prefix("0x")
+        .or_else(|| input.strip_prefix("0X"))
+        .unwrap_or(input)
+}
+
+/// Decode a hex string (with or without `0x` prefix) in constant time with
+/// respect to its contents.
+pub fn decode_hex(input: &str) -> Result<Vec<u8>> {
+    base16ct::mixed::decode_vec(strip_0x(input)).map_err(|_| WalletError::InvalidHex)
+}
+
+/// Decode a hex string into a fixed-size array, failing if the decoded length
+/// is not exactly `N`.
+pub fn decode_hex_array<const N: usize>(what: &'static str, input: &str) -> Result<[u8; N]> {
+    let hex = strip_0x(input);
+    if hex.len() != N * 2 {
+        return Err(WalletError::InvalidLength {
+            what,
+            expected: N,
+            actual: hex.len() / 2,
+        });
+    }
+    let mut out = [0u8; N];
+    base16ct::mixed::decode(hex, &mut out).map_err(|_| WalletError::InvalidHex)?;
+    Ok(out)
+}
+
+/// Lowercase hex encoding without a prefix, computed in constant time.
+pub fn encode_hex(bytes: &[u8]) -> String {
+    base16ct::lower::encode_string(bytes)
+}
+
+/// Constant-time equality. Lengths are treated as public: slices of different
+/// length compare unequal immediately.
+pub fn eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Sign-latency statistics collected by [`probe_sign_timing`].
+#[derive(Debug)]
+pub struct TimingReport {
+    pub samples: usize,
+    pub mean: Duration,
+    pub stddev: Duration,
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl TimingReport {
+    /// Coefficient of variation (stddev / mean). ML-DSA signing uses rejection
+    /// sampling, so some variance is expected; a sudden jump after a
+    /// dependency bump is what this exists to catch.
+    pub fn coefficient_of_variation(&self) -> f64 {
+        let mean = self.mean.as_secs_f64();
+        if mean == 0.0 {
+            return 0.0;
+        }
+        self.stddev.as_secs_f64() / mean
+    }
+}
+
+/// Measure ML-DSA-65 sign latency across `samples` freshly drawn random seeds,
+/// signing the same 32-byte message each time.
+pub fn probe_sign_timing(samples: usize) -> TimingReport {
+    assert!(samples > 0, "timing probe needs at least one sample");
+
+    let msg = [0xAB_u8; 32];
+    let mut timings = Vec::with_capacity(samples);
+    for _ in 0..samples {
+        let seed: [u8; 32] = rand::random();
+        let sk = SigningKey::<MlDsa65>::from_seed(&seed.into());
+        let start = Instant::now();
+        let sig = sk.sign(&msg);
+        timings.push(start.elapsed());
+        std::hint::black_box(sig);
+    }
+
+    let secs: Vec<f64> = timings.iter().map(Duration::as_secs_f64).collect();
+    let mean = secs.iter().sum::<f64>() / samples as f64;
+    let var = secs.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / samples as f64;
+
+    TimingReport {
+        samples,
+        mean: Duration::from_secs_f64(mean),
+        stddev: Duration::from_secs_f64(var.sqrt()),
+        min: *timings.iter().min().unwrap(),
+        max: *timings.iter().max().unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_hex_accepts_prefix_and_mixed_case() {
+        assert_eq!(
+            decode_hex("0xDeAdbeEF").unwrap(),
+            vec![0xde, 0xad, 0xbe, 0xef]
+        );
+        assert_eq!(
+            decode_hex("deadbeef").unwrap(),
+            vec![0xde, 0xad, 0xbe, 0xef]
+        );
+        assert!(decode_hex("0xzz").is_err());
+        assert!(decode_hex("abc").is_err());
+    }
+
+    #[test]
+    fn decode_hex_array_checks_length() {
+        let ok: [u8; 2] = decode_hex_array("test", "0x0102").unwrap();
+        assert_eq!(ok, [1, 2]);
+        let err = decode_hex_array::<32>("hash", "0x0102").unwrap_err();
+        assert!(matches!(
+            err,
+            WalletError::InvalidLength {
+                expected: 32,
+                actual: 2,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn hex_round_trip_matches_hex_crate() {
+        let bytes: [u8; 64] = std::array::from_fn(|i| i as u8 * 3);
+        assert_eq!(encode_hex(&bytes), hex::encode(bytes));
+        assert_eq!(decode_hex(&encode_hex(&bytes)).unwrap(), bytes);
+    }
+
+    #[test]
+    fn eq_compares_contents_and_length() {
+        assert!(eq(b"seed", b"seed"));
+        assert!(!eq(b"seed", b"seeD"));
+        assert!(!eq(b"seed", b"seeds"));
+    }
+}
@@ -0,0 +1,1226 @@
+//! The signing core of `pq-signerd`, shared by its transports (`async`
+//! feature).
+//!
+//! A [`Daemon`] holds the keystore accounts it was started with, unlocked,
+//! each behind its own [`SignerPool`]. Transports only translate requests:
+//! every signature takes the same path through here. UserOperations are
+//! checked against the signing policy, if there is one, and what they move
+//! is added to the spend ledger once signed. Every signature, hash or op, is
+//! appended to the audit log before it is returned, and one the log refuses
+//! is withheld, as with the CLI tools.
+//!
+//! With an [`AuthConfig`], callers must first [`Daemon::authenticate`] as a
+//! principal, and may only sign with the accounts it is scoped to. Without
+//! one, anyone who can reach the transport may use every account.
+//!
+//! An account can be a robot key the owner delegated to
+//! ([`Daemon::add_delegation`]). Its ops are checked against the
+//! certificate too, and signed into a V3 envelope that carries it. With a
+//! [`RevocationList`] ([`Daemon::with_revocations`]), they are refused once
+//! the certificate is revoked on chain, or while the list is stale.
+//!
+//! The policy's rate limits and anomaly heuristics ([`crate::guard`]) are
+//! enforced here too, per account: an account over its rate is refused,
+//! and one that trips a heuristic is cooled down or made to need approval.
+//!
+//! Under dual control ([`crate::proposal`]), the caller's word that an op
+//! was approved counts for nothing: an op that needs approval is parked as a
+//! proposal ([`Daemon::with_proposals`]) and only signed once an approver
+//! signs off on it ([`Daemon::decide`]).
+//!
+//! With a [`Notifier`] ([`Daemon::with_notifier`]), each signature, each
+//! policy denial and each hold is also sent to its sinks, in the
+//! background: a sink that is down delays nothing and refuses nothing.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use alloy_primitives::{Address, B256, U256, keccak256};
+use tokio::sync::Mutex;
+use zeroize::Zeroizing;
+
+use crate::attestation;
+use crate::audit::{AuditLog, Record};
+use crate::auth::{AuthConfig, Authenticator, Credentials, Principal};
+use crate::backend::Backend;
+use crate::error::{Result, WalletError};
+use crate::guard::{Action, Guard, Hold};
+use crate::keys::{self, SEED_LEN};
+use crate::keystore::Account;
+use crate::notify::{Event, Notification, Notifier};
+use crate::policy::{Denial, Policy, Rule, SpendLedger};
+use crate::pool::SignerPool;
+use crate::profile::{self, Profile};
+use crate::proposal::{Decision, DualControl, Proposal, ProposalStore, Status};
+use crate::review::Review;
+use crate::revocation::RevocationList;
+use crate::robot::Certificate;
+use crate::signing_scheme::SigningScheme;
+use crate::userop::{self, PackedUserOperation};
+
+/// Tool name in the audit log.
+pub const TOOL: &str = "pq-signerd";
+
+/// An account the daemon can sign with.
+#[derive(Clone)]
+pub struct UnlockedAccount {
+    pub name: String,
+    pub public_key: Vec<u8>,
+    pub key_id: Address,
+    pub addresses: BTreeMap<u64, Address>,
+    pub signing_schemes: BTreeMap<u64, SigningScheme>,
+    pub profiles: BTreeMap<u64, Profile>,
+    /// The owner's grant, when this is a robot key.
+    pub delegation: Option<Arc<Certificate>>,
+    pool: SignerPool,
+}
+
+impl UnlockedAccount {
+    pub fn available(&self) -> usize {
+        self.pool.available()
+    }
+}
+
+struct Revocations {
+    list: std::sync::RwLock<RevocationList>,
+    /// Seconds the list may go without a refresh.
+    max_age: u64,
+}
+
+struct Spending {
+    policy: Policy,
+    ledger: Mutex<SpendLedger>,
+    ledger_path: PathBuf,
+    guard: std::sync::Mutex<Guard>,
+}
+
+struct Proposals {
+    store: std::sync::Mutex<ProposalStore>,
+    path: PathBuf,
+    /// Held through a decision, so a proposal is signed at most once.
+    deciding: Mutex<()>,
+}
+
+/// A signed digest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedHash {
+    pub signature: Vec<u8>,
+    pub key_id: Address,
+}
+
+/// A UserOperation to sign.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpRequest {
+    pub user_op: PackedUserOperation,
+    pub entry_point: Address,
+    pub chain_id: u64,
+    /// A human approved the op, for policies with `approval_above`.
+    /// Ignored under dual control.
+    pub approved: bool,
+}
+
+/// A signed op, `signature` filled in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedOp {
+    pub user_op: PackedUserOperation,
+    pub user_op_hash: B256,
+}
+
+pub struct Daemon {
+    accounts: BTreeMap<String, UnlockedAccount>,
+    spending: Option<Spending>,
+    proposals: Option<Proposals>,
+    revocations: Option<Revocations>,
+    notifier: Option<Arc<Notifier>>,
+    audit: Arc<AuditLog>,
+    /// Serializes appends to the hash-chained log.
+    audit_lock: Mutex<()>,
+    auth: Option<Authenticator>,
+    anonymous: Arc<Principal>,
+    backend: Backend,
+    started: Instant,
+}
+
+impl Daemon {
+    /// A daemon with no accounts yet, logging to `audit`.
+    pub fn new(audit: AuditLog, backend: Backend) -> Self {
+        Daemon {
+            accounts: BTreeMap::new(),
+            spending: None,
+            proposals: None,
+            revocations: None,
+            notifier: None,
+            audit: Arc::new(audit),
+            audit_lock: Mutex::new(()),
+            auth: None,
+            anonymous: Arc::new(Principal::anonymous()),
+            backend,
+            started: Instant::now(),
+        }
+    }
+
+    /// Check every op against `policy`, keeping daily spend in the ledger
+    /// at `ledger_path`, and every signature against its rate limits and
+    /// anomaly heuristics.
+    pub fn with_policy(mut self, policy: Policy, ledger_path: PathBuf) -> Result<Self> {
+        let ledger = SpendLedger::load(&ledger_path)?;
+        self.spending = Some(Spending {
+            guard: std::sync::Mutex::new(Guard::new(&policy)),
+            policy,
+            ledger: Mutex::new(ledger),
+            ledger_path,
+        });
+        Ok(self)
+    }
+
+    /// Keep proposals in the store at `path`, for a policy with dual
+    /// control.
+    pub fn with_proposals(mut self, path: PathBuf) -> Result<Self> {
+        let store = ProposalStore::load(&path)?;
+        self.proposals = Some(Proposals {
+            store: std::sync::Mutex::new(store),
+            path,
+            deciding: Mutex::new(()),
+        });
+        Ok(self)
+    }
+
+    /// Refuse delegated ops whose certificate is on `list`, and every
+    /// delegated op while `list` is more than `max_age` old. Keep it fresh
+    /// with [`Daemon::update_revocations`].
+    pub fn with_revocations(mut self, list: RevocationList, max_age: Duration) -> Self {
+        self.revocations = Some(Revocations {
+            list: std::sync::RwLock::new(list),
+            max_age: max_age.as_secs(),
+        });
+        self
+    }
+
+    /// The revocation list in use, to refresh.
+    pub fn revocations(&self) -> Option<RevocationList> {
+        let revocations = self.revocations.as_ref()?;
+        Some(revocations.list.read().expect("revocation lock").clone())
+    }
+
+    pub fn update_revocations(&self, list: RevocationList) {
+        if let Some(revocations) = &self.revocations {
+            *revocations.list.write().expect("revocation lock") = list;
+        }
+    }
+
+    /// Send signatures and policy denials to `notifier`'s sinks.
+    pub fn with_notifier(mut self, notifier: Notifier) -> Self {
+        self.notifier = (!notifier.is_empty()).then(|| Arc::new(notifier));
+        self
+    }
+
+    /// Only serve the principals in `config`.
+    pub fn with_auth(mut self, config: &AuthConfig) -> Self {
+        self.auth = Some(Authenticator::new(config));
+        self
+    }
+
+    /// The principal behind a request, charged against its rate limit.
+    pub fn authenticate(&self, credentials: &Credentials<'_>) -> Result<Arc<Principal>> {
+        match &self.auth {
+            Some(auth) => auth
+                .authenticate(credentials, Instant::now())
+                .map_err(WalletError::Refused),
+            None => Ok(self.anonymous.clone()),
+        }
+    }
+
+    /// Sign as `account` from now on, with up to `max_concurrent`
+    /// signatures at once.
+    pub fn add_account(
+        &mut self,
+        account: &Account,
+        seed: Zeroizing<[u8; SEED_LEN]>,
+        max_concurrent: usize,
+    ) -> Result<()> {
+        let public_key = self.backend.public_key(&seed);
+        if public_key[..] != account.public_key()?[..] {
+            return Err(WalletError::Keystore(format!(
+                "{}: seed does not match the stored public key",
+                account.name
+            )));
+        }
+        self.accounts.insert(
+            account.name.clone(),
+            UnlockedAccount {
+                name: account.name.clone(),
+                key_id: keys::address(&public_key),
+                public_key,
+                addresses: account.addresses.clone(),
+                signing_schemes: account.signing_schemes.clone(),
+                profiles: account.profiles.clone(),
+                delegation: None,
+                pool: SignerPool::new(seed, self.backend, max_concurrent),
+            },
+        );
+        Ok(())
+    }
+
+    /// Sign the ops of the account holding `certificate`'s robot key under
+    /// it. A certificate that pins a policy needs the daemon to run that
+    /// policy.
+    pub fn add_delegation(&mut self, certificate: Certificate) -> Result<()> {
+        certificate.verify(None)?;
+        if !certificate.policy_hash.is_zero() {
+            let running = match &self.spending {
+                Some(spending) => spending.policy.hash()?,
+                None => B256::ZERO,
+            };
+            if running != certificate.policy_hash {
+                return Err(WalletError::Delegation(format!(
+                    "the certificate requires policy {}, the daemon runs {running}",
+                    certificate.policy_hash
+                )));
+            }
+        }
+        let account = self
+            .accounts
+            .values_mut()
+            .find(|a| a.public_key[..] == certificate.robot_key[..])
+            .ok_or_else(|| {
+                WalletError::Delegation(format!(
+                    "no unlocked account holds robot key {}",
+                    certificate.robot_fingerprint()
+                ))
+            })?;
+        account.delegation = Some(Arc::new(certificate));
+        Ok(())
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = &UnlockedAccount> {
+        self.accounts.values()
+    }
+
+    /// `name`, if `caller` may use it. Out-of-scope accounts are refused
+    /// whether or not the daemon holds them.
+    pub fn account(&self, caller: &Principal, name: &str) -> Result<&UnlockedAccount> {
+        caller.authorize(name).map_err(WalletError::Refused)?;
+        self.accounts
+            .get(name)
+            .ok_or_else(|| WalletError::Keystore(format!("{name} is not unlocked in the daemon")))
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Sign `digest` as `name`.
+    pub async fn sign_hash(
+        &self,
+        caller: &Principal,
+        name: &str,
+        digest: B256,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<SignedHash> {
+        let account = self.account(caller, name)?;
+        if let Some(spending) = &self.spending {
+            let admitted =
+                spending
+                    .guard
+                    .lock()
+                    .expect("guard lock")
+                    .admit(name, attestation::now(), false);
+            admitted.map_err(|denial| self.denied(caller, name, denial, None))?;
+        }
+        let signature = account.pool.sign(digest, cancel).await?;
+        self.log(Record {
+            tool: TOOL.to_string(),
+            account: Some(name.to_string()),
+            key_id: account.key_id,
+            digest: Some(digest),
+            chain_id: None,
+            signature: keccak256(&signature),
+            summary: self.summary(caller, vec![format!("sign 32-byte message {digest}")]),
+            backend: self.backend.to_string(),
+        })
+        .await?;
+        self.notify(Notification::new(
+            Event::Signed,
+            TOOL,
+            format!("{name} signed {digest}"),
+            serde_json::json!({
+                "account": name,
+                "keyId": account.key_id,
+                "digest": digest,
+            }),
+        ));
+        Ok(SignedHash {
+            signature,
+            key_id: account.key_id,
+        })
+    }
+
+    /// Check `op` against the policy and sign its userOpHash as `name`.
+    /// Only the hash signing scheme is served; accounts set to another for
+    /// `chain_id` are refused. Under dual control, an op that needs
+    /// approval is parked instead, and the error is
+    /// [`WalletError::PendingApproval`].
+    pub async fn sign_user_op(
+        &self,
+        caller: &Principal,
+        name: &str,
+        mut request: OpRequest,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<SignedOp> {
+        if self.dual_control().is_some() {
+            request.approved = false;
+        }
+        self.sign_op(caller, name, request, cancel, None).await
+    }
+
+    /// [`Daemon::sign_user_op`], for a `proposal` an approver decided on
+    /// when there is one: (approver, proposal ID).
+    async fn sign_op(
+        &self,
+        caller: &Principal,
+        name: &str,
+        request: OpRequest,
+        cancel: impl Future<Output = ()>,
+        proposal: Option<(B256, B256)>,
+    ) -> Result<SignedOp> {
+        let OpRequest {
+            user_op: mut op,
+            entry_point,
+            chain_id,
+            approved,
+        } = request;
+        let account = self.account(caller, name)?;
+        let profile = account.profiles.get(&chain_id);
+        let scheme = match profile {
+            Some(profile) => {
+                profile.check(chain_id, &profile::Request::new(entry_point))?;
+                profile.signing_scheme
+            }
+            None => account
+                .signing_schemes
+                .get(&chain_id)
+                .copied()
+                .unwrap_or_default(),
+        };
+        if scheme != SigningScheme::HashAsMessage {
+            return Err(WalletError::Usage(
+                "the daemon only signs with the hash signing scheme",
+            ));
+        }
+        if let Some(cert) = &account.delegation {
+            let now = attestation::now();
+            cert.check_op(op.sender, chain_id, now)?;
+            if let Some(revocations) = &self.revocations {
+                revocations.list.read().expect("revocation lock").check(
+                    cert.account,
+                    cert.hash(),
+                    now,
+                    revocations.max_age,
+                )?;
+            }
+        }
+        let hash = userop::compute_user_op_hash(&op, entry_point, U256::from(chain_id));
+        // Held from the check until the spend is recorded, so concurrent ops
+        // cannot both fit under the same daily limit.
+        let mut spend = match &self.spending {
+            Some(spending) => {
+                let ledger = spending.ledger.lock().await;
+                let now = attestation::now();
+                let spent = ledger.spent_on(chain_id, now);
+                let context = Some((chain_id, op.sender, hash));
+                let dual = spending.policy.dual_control.as_ref();
+                let park = |reason: String| {
+                    self.park(Proposal {
+                        id: Proposal::id_for(name, hash),
+                        account: name.to_string(),
+                        principal: caller.name.clone(),
+                        chain_id,
+                        entry_point,
+                        user_op: op.clone(),
+                        user_op_hash: hash,
+                        reason,
+                        created_at: now,
+                        expires_at: now + dual.map_or(0, |d| d.ttl_secs),
+                        status: Status::Pending,
+                        decided_by: None,
+                    })
+                };
+                let dual = dual.is_some() && !approved;
+                let value = match spending.policy.check(&op, chain_id, spent, approved) {
+                    Ok(value) => value,
+                    Err(denial) if dual && denial.rule == Rule::Approval => {
+                        return Err(park(denial.reason));
+                    }
+                    Err(denial) => {
+                        let hold = spending
+                            .guard
+                            .lock()
+                            .expect("guard lock")
+                            .record_denial(name, now);
+                        if let Some(hold) = hold {
+                            self.held(name, &hold);
+                        }
+                        return Err(self.denied(caller, name, denial, context));
+                    }
+                };
+                let calls = Policy::calls(&op);
+                let mut guard = spending.guard.lock().expect("guard lock");
+                let known = ledger.destinations(op.sender);
+                let admitted = guard
+                    .check_op(name, chain_id, &calls, &known, approved, now)
+                    .inspect_err(|_| {
+                        if let Some(hold) = guard.hold(name, now) {
+                            self.held(name, hold);
+                        }
+                    })
+                    .and_then(|()| guard.admit(name, now, approved));
+                if let Err(denial) = admitted {
+                    // An approval hold is approval the op needs.
+                    let approval_hold = guard
+                        .hold(name, now)
+                        .is_some_and(|hold| hold.action == Action::Approval);
+                    drop(guard);
+                    return Err(if dual && approval_hold {
+                        park(denial.reason)
+                    } else {
+                        self.denied(caller, name, denial, context)
+                    });
+                }
+                drop(guard);
+                Some((spending, ledger, now, value, calls))
+            }
+            None => None,
+        };
+        op.signature = account.pool.sign(hash, cancel).await?;
+        match (&account.delegation, profile) {
+            (Some(cert), _) => op.signature = cert.envelope(&op.signature)?,
+            (None, Some(profile)) => {
+                op.signature = profile.signature_field(&account.public_key, &op.signature)?;
+            }
+            (None, None) => {}
+        }
+        self.log(Record {
+            tool: TOOL.to_string(),
+            account: Some(name.to_string()),
+            key_id: account.key_id,
+            digest: Some(hash),
+            chain_id: Some(chain_id),
+            signature: keccak256(&op.signature),
+            summary: self.summary(caller, {
+                let mut lines = Review::new(&op, chain_id, hash).calls;
+                if let Some((approver, id)) = proposal {
+                    lines.push(format!("approved by {approver} (proposal {id})"));
+                }
+                lines
+            }),
+            backend: self.backend.to_string(),
+        })
+        .await?;
+        if let Some((spending, ledger, now, value, calls)) = &mut spend {
+            ledger.record(chain_id, *now, *value);
+            ledger.record_destinations(op.sender, calls);
+            ledger.save(&spending.ledger_path)?;
+            let mut guard = spending.guard.lock().expect("guard lock");
+            guard.record(name, chain_id, *value, *now);
+        }
+        self.notify(Notification::new(
+            Event::Signed,
+            TOOL,
+            format!("{name} signed userOp {hash} on chain {chain_id}"),
+            serde_json::json!({
+                "account": name,
+                "keyId": account.key_id,
+                "chainId": chain_id,
+                "sender": op.sender,
+                "nonce": op.nonce,
+                "userOpHash": hash,
+                "delegated": account.delegation.is_some(),
+            }),
+        ));
+        Ok(SignedOp {
+            user_op: op,
+            user_op_hash: hash,
+        })
+    }
+
+    /// The policy's dual control, if it has any.
+    pub fn dual_control(&self) -> Option<&DualControl> {
+        self.spending.as_ref()?.policy.dual_control.as_ref()
+    }
+
+    fn proposal_store(&self) -> Result<&Proposals> {
+        self.proposals.as_ref().ok_or_else(|| {
+            WalletError::Proposal("the daemon keeps no proposals (no [dual_control])".into())
+        })
+    }
+
+    /// The proposals for accounts `caller` may use, expiring those past
+    /// their deadline.
+    pub fn proposals(&self, caller: &Principal) -> Result<Vec<Proposal>> {
+        let proposals = self.proposal_store()?;
+        let mut store = proposals.store.lock().expect("proposal lock");
+        if store.expire(attestation::now()) {
+            store.save(&proposals.path)?;
+        }
+        Ok(store
+            .proposals
+            .values()
+            .filter(|p| caller.may_use(&p.account))
+            .cloned()
+            .collect())
+    }
+
+    /// Proposal `id`, if its account is one `caller` may use.
+    pub fn proposal(&self, caller: &Principal, id: B256) -> Result<Proposal> {
+        let proposals = self.proposal_store()?;
+        let mut store = proposals.store.lock().expect("proposal lock");
+        if store.expire(attestation::now()) {
+            store.save(&proposals.path)?;
+        }
+        let proposal = store.get(id)?;
+        caller
+            .authorize(&proposal.account)
+            .map_err(WalletError::Refused)?;
+        Ok(proposal.clone())
+    }
+
+    /// Decide proposal `id` on an approver's signed `decision`. An approved
+    /// op is signed, through every check any other op takes, and the
+    /// returned proposal carries it.
+    pub async fn decide(
+        &self,
+        caller: &Principal,
+        id: B256,
+        decision: &Decision,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<Proposal> {
+        let proposals = self.proposal_store()?;
+        let dual = self.dual_control().ok_or_else(|| {
+            WalletError::Proposal("the daemon's policy has no [dual_control]".into())
+        })?;
+        let _turn = proposals.deciding.lock().await;
+        let mut proposal = self.proposal(caller, id)?;
+        if proposal.status != Status::Pending {
+            return Err(WalletError::Proposal(format!(
+                "proposal {id} is {}",
+                proposal.status
+            )));
+        }
+        let account = self.account(caller, &proposal.account)?;
+        let approver = keccak256(&decision.public_key);
+        if !dual.is_approver(&decision.public_key) {
+            return Err(WalletError::Proposal(format!(
+                "key {approver} is not an approver"
+            )));
+        }
+        if decision.public_key == account.public_key {
+            return Err(WalletError::Proposal(format!(
+                "{}'s own key cannot approve its ops",
+                account.name
+            )));
+        }
+        let digest = proposal.digest(decision.approve);
+        if !self.backend.verify(
+            &decision.public_key,
+            digest.as_slice(),
+            &[],
+            &decision.signature,
+        ) {
+            return Err(WalletError::Proposal(format!(
+                "the signature does not verify under {approver}"
+            )));
+        }
+        if decision.approve {
+            let request = OpRequest {
+                user_op: proposal.user_op.clone(),
+                entry_point: proposal.entry_point,
+                chain_id: proposal.chain_id,
+                approved: true,
+            };
+            let signed = self
+                .sign_op(
+                    caller,
+                    &proposal.account,
+                    request,
+                    cancel,
+                    Some((approver, id)),
+                )
+                .await?;
+            proposal.user_op = signed.user_op;
+            proposal.status = Status::Approved;
+        } else {
+            proposal.status = Status::Rejected;
+        }
+        proposal.decided_by = Some(approver);
+        tracing::info!(%id, %approver, status = %proposal.status, "proposal decided");
+        let mut store = proposals.store.lock().expect("proposal lock");
+        store.proposals.insert(id, proposal.clone());
+        store.save(&proposals.path)?;
+        Ok(proposal)
+    }
+
+    /// Park `proposal` for an approver and turn it into the error returned.
+    fn park(&self, proposal: Proposal) -> WalletError {
+        let Ok(proposals) = self.proposal_store() else {
+            return WalletError::Config("dual control needs a proposal store".into());
+        };
+        let now = proposal.created_at;
+        let mut store = proposals.store.lock().expect("proposal lock");
+        let proposal = store.propose(proposal, now).clone();
+        if let Err(e) = store.save(&proposals.path) {
+            return e;
+        }
+        self.notify(Notification::new(
+            Event::ApprovalNeeded,
+            TOOL,
+            format!(
+                "{} needs approval for userOp {} on chain {}: {}",
+                proposal.account, proposal.user_op_hash, proposal.chain_id, proposal.reason
+            ),
+            serde_json::json!({
+                "proposal": proposal.id,
+                "account": proposal.account,
+                "principal": proposal.principal,
+                "chainId": proposal.chain_id,
+                "sender": proposal.user_op.sender,
+                "userOpHash": proposal.user_op_hash,
+                "reason": proposal.reason,
+                "expiresAt": proposal.expires_at,
+            }),
+        ));
+        WalletError::PendingApproval(proposal.id)
+    }
+
+    /// `lines`, naming the principal when there are principals to tell
+    /// apart.
+    fn summary(&self, caller: &Principal, mut lines: Vec<String>) -> Vec<String> {
+        if self.auth.is_some() {
+            lines.push(format!("requested by {}", caller.name));
+        }
+        lines
+    }
+
+    /// Report `denial` of a request from `name`, the chain, sender and
+    /// userOpHash with it for an op, and turn it into the error returned.
+    fn denied(
+        &self,
+        caller: &Principal,
+        name: &str,
+        denial: Denial,
+        op: Option<(u64, Address, B256)>,
+    ) -> WalletError {
+        let mut data = serde_json::json!({
+            "account": name,
+            "rule": denial.rule,
+            "reason": denial.reason,
+            "principal": caller.name,
+        });
+        let what = match op {
+            Some((chain_id, sender, hash)) => {
+                data["chainId"] = chain_id.into();
+                data["sender"] = serde_json::json!(sender);
+                data["userOpHash"] = serde_json::json!(hash);
+                "an op"
+            }
+            None => "a hash",
+        };
+        self.notify(Notification::new(
+            Event::PolicyDenied,
+            TOOL,
+            format!("refused {what} from {name}: {}", denial.reason),
+            data,
+        ));
+        WalletError::Policy(denial.reason)
+    }
+
+    /// Report that `name` was put on `hold`.
+    fn held(&self, name: &str, hold: &Hold) {
+        tracing::warn!(account = name, until = hold.until, reason = %hold.reason, "account on hold");
+        self.notify(Notification::new(
+            Event::Anomaly,
+            TOOL,
+            format!("{name} is on hold until {}: {}", hold.until, hold.reason),
+            serde_json::json!({
+                "account": name,
+                "until": hold.until,
+                "action": hold.action,
+                "reason": hold.reason,
+            }),
+        ));
+    }
+
+    /// Hand `notification` to the notifier without waiting for it.
+    fn notify(&self, notification: Notification) {
+        if let Some(notifier) = &self.notifier {
+            let notifier = notifier.clone();
+            tokio::task::spawn_blocking(move || notifier.notify(&notification));
+        }
+    }
+
+    async fn log(&self, record: Record) -> Result<()> {
+        let _turn = self.audit_lock.lock().await;
+        let audit = self.audit.clone();
+        tokio::task::spawn_blocking(move || audit.append(record))
+            .await
+            .map_err(|e| WalletError::Audit(format!("audit task failed: {e}")))?
+            .map_err(|e| WalletError::Audit(format!("signature withheld: {e}")))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calldata::{self, Call};
+    use crate::envelope::Envelope;
+    use crate::keys::PUBLIC_KEY_LEN;
+    use crate::keystore::KdfParams;
+    use crate::userop::ENTRY_POINT_V07;
+    use crate::validity::ValidityWindow;
+
+    fn daemon(name: &str) -> Daemon {
+        daemon_with_policy(name, "max_value_per_day = \"100\"")
+    }
+
+    fn daemon_with_policy(name: &str, policy: &str) -> Daemon {
+        let dir = std::env::temp_dir().join(format!("pq-daemon-{name}-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        let seed = Zeroizing::new([9u8; SEED_LEN]);
+        let kdf = KdfParams {
+            m_cost: 64,
+            t_cost: 1,
+            p_cost: 1,
+        };
+        let account = Account::encrypt("alice", &seed, b"pw", kdf).unwrap();
+        let mut daemon = Daemon::new(AuditLog::open(dir.join("audit.jsonl")), Backend::RustCrypto)
+            .with_policy(Policy::from_toml(policy).unwrap(), dir.join("spend.json"))
+            .unwrap();
+        daemon.add_account(&account, seed, 2).unwrap();
+        daemon
+    }
+
+    #[tokio::test]
+    async fn signs_through_policy_and_audit_log() {
+        let events = std::env::temp_dir().join(format!("pq-daemon-events-{}", std::process::id()));
+        std::fs::remove_file(&events).ok();
+        let hook = crate::notify::Sink {
+            webhook: None,
+            command: vec![
+                "sh".into(),
+                "-c".into(),
+                r#"echo "$PQ_NOTIFY_EVENT" >> "$1""#.into(),
+                "hook".into(),
+                events.display().to_string(),
+            ],
+            ..crate::notify::Sink::webhook("", [])
+        };
+        let daemon = daemon("sign").with_notifier(Notifier::new(vec![hook]).unwrap());
+        let caller = Principal::anonymous();
+        let alice = daemon.account(&caller, "alice").unwrap().clone();
+        let signed = daemon
+            .sign_hash(
+                &caller,
+                "alice",
+                B256::repeat_byte(1),
+                std::future::pending(),
+            )
+            .await
+            .unwrap();
+        assert!(Backend::RustCrypto.verify(&alice.public_key, &[1; 32], &[], &signed.signature));
+        assert!(
+            daemon
+                .sign_hash(&caller, "bob", B256::ZERO, std::future::pending())
+                .await
+                .is_err()
+        );
+
+        let op = |value: u64| PackedUserOperation {
+            sender: Address::repeat_byte(0xaa),
+            nonce: U256::ZERO,
+            init_code: vec![],
+            call_data: calldata::execute(&[Call {
+                target: Address::repeat_byte(0x22),
+                value: U256::from(value),
+                data: vec![],
+            }]),
+            account_gas_limits: B256::ZERO,
+            pre_verification_gas: U256::ZERO,
+            gas_fees: B256::ZERO,
+            paymaster_and_data: vec![],
+            signature: vec![],
+        };
+        let sign = |value| {
+            daemon.sign_user_op(
+                &caller,
+                "alice",
+                OpRequest {
+                    user_op: op(value),
+                    entry_point: ENTRY_POINT_V07,
+                    chain_id: 1,
+                    approved: false,
+                },
+                std::future::pending(),
+            )
+        };
+        let signed = sign(60).await.unwrap();
+        assert!(Backend::RustCrypto.verify(
+            &alice.public_key,
+            signed.user_op_hash.as_slice(),
+            &[],
+            &signed.user_op.signature
+        ));
+        // 60 of the day's 100 is spent.
+        assert!(matches!(sign(50).await, Err(WalletError::Policy(_))));
+        sign(40).await.unwrap();
+
+        let log = daemon.audit.entries().unwrap();
+        assert_eq!(log.len(), 3);
+        assert!(log.iter().all(|e| e.record.tool == TOOL));
+
+        // Notifications go out in the background.
+        let mut sent = Vec::new();
+        for _ in 0..200 {
+            sent = std::fs::read_to_string(&events)
+                .unwrap_or_default()
+                .lines()
+                .map(String::from)
+                .collect();
+            if sent.len() == 4 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(25));
+        }
+        sent.sort();
+        assert_eq!(sent, ["policy-denied", "signed", "signed", "signed"]);
+    }
+
+    #[tokio::test]
+    async fn a_burst_of_denials_cools_the_account_down() {
+        let daemon = daemon_with_policy(
+            "guard",
+            r#"
+            max_value_per_op = "10"
+            [rate]
+            signatures_per_minute = 3
+            [anomaly]
+            max_denials = 2
+            "#,
+        );
+        let caller = Principal::anonymous();
+        let pay = |target: u8, value: u64| OpRequest {
+            user_op: PackedUserOperation {
+                sender: Address::repeat_byte(0xaa),
+                nonce: U256::ZERO,
+                init_code: vec![],
+                call_data: calldata::execute(&[Call {
+                    target: Address::repeat_byte(target),
+                    value: U256::from(value),
+                    data: vec![],
+                }]),
+                account_gas_limits: B256::ZERO,
+                pre_verification_gas: U256::ZERO,
+                gas_fees: B256::ZERO,
+                paymaster_and_data: vec![],
+                signature: vec![],
+            },
+            entry_point: ENTRY_POINT_V07,
+            chain_id: 1,
+            approved: false,
+        };
+        let sign = |request| daemon.sign_user_op(&caller, "alice", request, std::future::pending());
+        let hash = || daemon.sign_hash(&caller, "alice", B256::ZERO, std::future::pending());
+
+        hash().await.unwrap();
+        sign(pay(0x22, 5)).await.unwrap();
+        sign(pay(0x22, 10)).await.unwrap();
+        let refused = hash().await.unwrap_err();
+        assert!(refused.to_string().contains("last minute"), "{refused}");
+        // A full-size payment to a target the account never paid is an
+        // anomaly.
+        let refused = sign(pay(0x33, 10)).await.unwrap_err();
+        assert!(refused.to_string().contains("first-time"), "{refused}");
+        let refused = hash().await.unwrap_err();
+        assert!(refused.to_string().contains("cooling down"), "{refused}");
+
+        let daemon = daemon_with_policy(
+            "guard-burst",
+            r#"
+            max_value_per_op = "10"
+            [anomaly]
+            new_destination_value = "1000"
+            max_denials = 2
+            "#,
+        );
+        let sign = |request| daemon.sign_user_op(&caller, "alice", request, std::future::pending());
+        sign(pay(0x22, 10)).await.unwrap();
+        assert!(sign(pay(0x22, 11)).await.is_err());
+        daemon
+            .sign_hash(&caller, "alice", B256::ZERO, std::future::pending())
+            .await
+            .unwrap();
+        assert!(sign(pay(0x22, 11)).await.is_err());
+        let refused = daemon
+            .sign_hash(&caller, "alice", B256::ZERO, std::future::pending())
+            .await
+            .unwrap_err();
+        assert!(refused.to_string().contains("cooling down"), "{refused}");
+        assert!(sign(pay(0x22, 1)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn dual_control_parks_ops_until_an_approver_signs() {
+        let approver = [5u8; SEED_LEN];
+        let approver_key = Backend::RustCrypto.public_key(&approver);
+        let alice_key = Backend::RustCrypto.public_key(&[9u8; SEED_LEN]);
+        let daemon = daemon_with_policy(
+            "dual",
+            &format!(
+                "approval_above = \"10\"\n[dual_control]\napprovers = [\"{}\", \"{}\"]",
+                keccak256(&approver_key),
+                keccak256(&alice_key)
+            ),
+        );
+        let dir = std::env::temp_dir().join(format!("pq-daemon-dual-{}", std::process::id()));
+        let daemon = daemon.with_proposals(dir.join("proposals.json")).unwrap();
+        let caller = Principal::anonymous();
+        let request = OpRequest {
+            user_op: PackedUserOperation {
+                sender: Address::repeat_byte(0xaa),
+                nonce: U256::ZERO,
+                init_code: vec![],
+                call_data: calldata::execute(&[Call {
+                    target: Address::repeat_byte(0x22),
+                    value: U256::from(50),
+                    data: vec![],
+                }]),
+                account_gas_limits: B256::ZERO,
+                pre_verification_gas: U256::ZERO,
+                gas_fees: B256::ZERO,
+                paymaster_and_data: vec![],
+                signature: vec![],
+            },
+            entry_point: ENTRY_POINT_V07,
+            chain_id: 1,
+            // The caller's say-so counts for nothing.
+            approved: true,
+        };
+        let parked = daemon
+            .sign_user_op(&caller, "alice", request.clone(), std::future::pending())
+            .await;
+        let Err(WalletError::PendingApproval(id)) = parked else {
+            panic!("not parked: {parked:?}");
+        };
+        let proposal = daemon.proposal(&caller, id).unwrap();
+        assert_eq!(daemon.proposals(&caller).unwrap(), [proposal.clone()][..]);
+
+        let (daemon, caller) = (&daemon, &caller);
+        let decide = |seed: &[u8; SEED_LEN], approve: bool, signed: B256| {
+            let decision = Decision {
+                approve,
+                public_key: Backend::RustCrypto.public_key(seed),
+                signature: Backend::RustCrypto
+                    .sign(seed, signed.as_slice(), &[], &[0; 32])
+                    .unwrap(),
+            };
+            async move {
+                daemon
+                    .decide(caller, id, &decision, std::future::pending())
+                    .await
+            }
+        };
+        // Not the account's own key, nor a signature over the other decision.
+        let own = decide(&[9u8; SEED_LEN], true, proposal.digest(true)).await;
+        assert!(own.unwrap_err().to_string().contains("own key"));
+        let stranger = decide(&[6u8; SEED_LEN], true, proposal.digest(true)).await;
+        assert!(
+            stranger
+                .unwrap_err()
+                .to_string()
+                .contains("not an approver")
+        );
+        let swapped = decide(&approver, true, proposal.digest(false)).await;
+        assert!(swapped.unwrap_err().to_string().contains("does not verify"));
+
+        let approved = decide(&approver, true, proposal.digest(true))
+            .await
+            .unwrap();
+        assert_eq!(approved.status, Status::Approved);
+        assert_eq!(approved.decided_by, Some(keccak256(&approver_key)));
+        assert!(Backend::RustCrypto.verify(
+            &alice_key,
+            proposal.user_op_hash.as_slice(),
+            &[],
+            &approved.user_op.signature
+        ));
+        let log = daemon.audit.entries().unwrap();
+        assert!(
+            log[0]
+                .record
+                .summary
+                .iter()
+                .any(|l| l.contains("approved by"))
+        );
+        // Decided once, and kept across restarts.
+        let again = decide(&approver, false, proposal.digest(false)).await;
+        assert!(matches!(again, Err(WalletError::Proposal(_))));
+        let store = ProposalStore::load(&dir.join("proposals.json")).unwrap();
+        assert_eq!(store.get(id).unwrap(), &approved);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn a_robot_key_signs_into_a_delegated_envelope() {
+        let mut daemon = daemon("robot");
+        let caller = Principal::anonymous();
+        let robot = daemon.account(&caller, "alice").unwrap().public_key.clone();
+        let now = attestation::now();
+        let issue = |policy_hash| {
+            Certificate::issue(
+                &[1u8; SEED_LEN],
+                &robot,
+                Address::repeat_byte(0xaa),
+                1,
+                ValidityWindow::new(now - 60, now + 3600).unwrap(),
+                policy_hash,
+            )
+            .unwrap()
+        };
+        let pinned = issue(B256::repeat_byte(1));
+        let cert = issue(B256::ZERO);
+        assert!(matches!(
+            daemon.add_delegation(pinned),
+            Err(WalletError::Delegation(_))
+        ));
+        daemon.add_delegation(cert.clone()).unwrap();
+
+        let op = |sender| PackedUserOperation {
+            sender,
+            nonce: U256::ZERO,
+            init_code: vec![],
+            call_data: vec![],
+            account_gas_limits: B256::ZERO,
+            pre_verification_gas: U256::ZERO,
+            gas_fees: B256::ZERO,
+            paymaster_and_data: vec![],
+            signature: vec![],
+        };
+        let sign = |sender| {
+            daemon.sign_user_op(
+                &caller,
+                "alice",
+                OpRequest {
+                    user_op: op(sender),
+                    entry_point: ENTRY_POINT_V07,
+                    chain_id: 1,
+                    approved: false,
+                },
+                std::future::pending(),
+            )
+        };
+        let signed = sign(cert.account).await.unwrap();
+        let Envelope::V3(env) = Envelope::decode(&signed.user_op.signature).unwrap() else {
+            panic!("not a delegated envelope");
+        };
+        assert_eq!(env.delegation.hash(), cert.hash());
+        assert!(Backend::RustCrypto.verify(
+            &cert.robot_key,
+            signed.user_op_hash.as_slice(),
+            &[],
+            &env.pq.signature
+        ));
+        assert!(matches!(
+            sign(Address::ZERO).await,
+            Err(WalletError::Delegation(_))
+        ));
+
+        let mut list = RevocationList::new(Address::ZERO, [cert.account].into());
+        list.fetched_at = now;
+        let daemon = daemon.with_revocations(list.clone(), Duration::from_secs(600));
+        let sign = || {
+            daemon.sign_user_op(
+                &caller,
+                "alice",
+                OpRequest {
+                    user_op: op(cert.account),
+                    entry_point: ENTRY_POINT_V07,
+                    chain_id: 1,
+                    approved: false,
+                },
+                std::future::pending(),
+            )
+        };
+        sign().await.unwrap();
+        list.revoked
+            .entry(cert.account)
+            .or_default()
+            .insert(cert.hash());
+        daemon.update_revocations(list.clone());
+        assert!(matches!(sign().await, Err(WalletError::Delegation(_))));
+        list.revoked.clear();
+        list.fetched_at = now - 3600;
+        daemon.update_revocations(list);
+        assert!(matches!(sign().await, Err(WalletError::Delegation(_))));
+    }
+
+    #[tokio::test]
+    async fn ops_are_signed_in_the_chain_profiles_construction() {
+        let seed = Zeroizing::new([9u8; SEED_LEN]);
+        let kdf = KdfParams {
+            m_cost: 64,
+            t_cost: 1,
+            p_cost: 1,
+        };
+        let mut account = Account::encrypt("alice", &seed, b"pw", kdf).unwrap();
+        let precompile: Profile = serde_json::from_str(
+            r#"{"validator":"precompile:0x0000000000000000000000000000000000000100"}"#,
+        )
+        .unwrap();
+        account.profiles.insert(1, precompile);
+        let dir = std::env::temp_dir().join(format!("pq-daemon-profile-{}", std::process::id()));
+        let mut daemon = Daemon::new(AuditLog::open(dir.join("audit.jsonl")), Backend::RustCrypto);
+        daemon.add_account(&account, seed, 1).unwrap();
+        let caller = Principal::anonymous();
+        let op = PackedUserOperation {
+            sender: Address::repeat_byte(0xaa),
+            nonce: U256::ZERO,
+            init_code: vec![],
+            call_data: vec![],
+            account_gas_limits: B256::ZERO,
+            pre_verification_gas: U256::ZERO,
+            gas_fees: B256::ZERO,
+            paymaster_and_data: vec![],
+            signature: vec![],
+        };
+        let sign = |entry_point| {
+            daemon.sign_user_op(
+                &caller,
+                "alice",
+                OpRequest {
+                    user_op: op.clone(),
+                    entry_point,
+                    chain_id: 1,
+                    approved: false,
+                },
+                std::future::pending(),
+            )
+        };
+        // The precompile gets the key with each signature.
+        let signed = sign(ENTRY_POINT_V07).await.unwrap();
+        let public_key = account.public_key().unwrap();
+        assert_eq!(signed.user_op.signature[..PUBLIC_KEY_LEN], public_key[..]);
+        assert!(Backend::RustCrypto.verify(
+            &public_key,
+            signed.user_op_hash.as_slice(),
+            &[],
+            &signed.user_op.signature[PUBLIC_KEY_LEN..]
+        ));
+        assert!(matches!(
+            sign(Address::repeat_byte(1)).await,
+            Err(WalletError::Profile(_))
+        ));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
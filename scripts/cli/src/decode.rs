@@ -0,0 +1,383 @@
+//! Human-readable previews of what a UserOperation does, shown before it is
+//! signed so the key never signs a bare hash blind.
+//!
+//! `callData` is decoded as ERC-7579 `execute` or SimpleAccount-style
+//! `execute`/`executeBatch`, recursing into nested account calls. Inner calls
+//! are matched against a small bundled registry: ERC-20/721 and WETH
+//! functions with their arguments, plus well-known selectors by name only.
+//! Amounts for the bundled tokens are shown in whole units ("transfer 100
+//! USDC to 0x..."); anything else is shown as raw integers.
+
+use alloy_primitives::{Address, U256, address, keccak256};
+use alloy_sol_types::{SolCall, sol};
+
+use crate::calldata::{self, Call, ModuleType, installModuleCall, uninstallModuleCall};
+use crate::userop::PackedUserOperation;
+
+sol! {
+    function transfer(address to, uint256 amount);
+    function approve(address spender, uint256 amount);
+    function transferFrom(address from, address to, uint256 amount);
+    function safeTransferFrom(address from, address to, uint256 tokenId);
+    function setApprovalForAll(address operator, bool approved);
+    function deposit();
+    function withdraw(uint256 amount);
+    /// SimpleAccount and most v0.6-era accounts.
+    function execute(address dest, uint256 value, bytes func);
+    function executeBatch(address[] dest, uint256[] value, bytes[] func);
+}
+
+/// Nested account calls deeper than this are shown as opaque.
+const MAX_DEPTH: usize = 4;
+
+/// A token amounts are formatted for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub chain_id: u64,
+    pub address: Address,
+    pub symbol: &'static str,
+    pub decimals: u8,
+}
+
+/// Bundled tokens on the chains this repo deploys to.
+pub const TOKENS: &[Token] = &[
+    Token {
+        chain_id: 1,
+        address: address!("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"),
+        symbol: "USDC",
+        decimals: 6,
+    },
+    Token {
+        chain_id: 1,
+        address: address!("0xdAC17F958D2ee523a2206206994597C13D831ec7"),
+        symbol: "USDT",
+        decimals: 6,
+    },
+    Token {
+        chain_id: 1,
+        address: address!("0x6B175474E89094C44Da98b954EedeAC495271d0F"),
+        symbol: "DAI",
+        decimals: 18,
+    },
+    Token {
+        chain_id: 1,
+        address: address!("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"),
+        symbol: "WETH",
+        decimals: 18,
+    },
+    Token {
+        chain_id: 42161,
+        address: address!("0xaf88d065e77c8cC2239327C5EDb3A432268e5831"),
+        symbol: "USDC",
+        decimals: 6,
+    },
+    Token {
+        chain_id: 42161,
+        address: address!("0x82aF49447D8a07e3bd95BD0d56f35241523fBab1"),
+        symbol: "WETH",
+        decimals: 18,
+    },
+    Token {
+        chain_id: 421614,
+        address: address!("0x75faf114eafb1BDbe2F0316DF893fd58CE46AA4d"),
+        symbol: "USDC",
+        decimals: 6,
+    },
+];
+
+/// Functions recognised by name only; their arguments are not decoded.
+pub const KNOWN_SIGNATURES: &[&str] = &[
+    "multicall(bytes[])",
+    "multicall(uint256,bytes[])",
+    "permit(address,address,uint256,uint256,uint8,bytes32,bytes32)",
+    "increaseAllowance(address,uint256)",
+    "decreaseAllowance(address,uint256)",
+    "mint(address,uint256)",
+    "burn(uint256)",
+    "safeTransferFrom(address,address,uint256,uint256,bytes)",
+    "safeBatchTransferFrom(address,address,uint256[],uint256[],bytes)",
+    "swapExactTokensForTokens(uint256,uint256,address[],address,uint256)",
+    "swapExactETHForTokens(uint256,address[],address,uint256)",
+    "exactInputSingle((address,address,uint24,address,uint256,uint256,uint160))",
+    "exactInput((bytes,address,uint256,uint256))",
+    "storeChunk(bytes32,uint8,uint8,bytes)",
+];
+
+pub fn token(chain_id: u64, address: Address) -> Option<&'static Token> {
+    TOKENS
+        .iter()
+        .find(|t| t.chain_id == chain_id && t.address == address)
+}
+
+/// `value / 10^decimals` with trailing zeros dropped.
+pub fn format_units(value: U256, decimals: u8) -> String {
+    let scale = U256::from(10u64).pow(U256::from(decimals));
+    let (whole, frac) = value.div_rem(scale);
+    if frac.is_zero() {
+        return whole.to_string();
+    }
+    let frac = format!("{frac:0>width$}", width = decimals as usize);
+    format!("{whole}.{}", frac.trim_end_matches('0'))
+}
+
+fn eth(value: U256) -> String {
+    format!("{} ETH", format_units(value, 18))
+}
+
+fn amount(chain_id: u64, token_address: Address, value: U256) -> String {
+    match token(chain_id, token_address) {
+        Some(t) if value == U256::MAX => format!("unlimited {}", t.symbol),
+        Some(t) => format!("{} {}", format_units(value, t.decimals), t.symbol),
+        None if value == U256::MAX => format!("unlimited of token {token_address}"),
+        None => format!("{value} of token {token_address}"),
+    }
+}
+
+fn named(selector: &[u8]) -> Option<&'static str> {
+    KNOWN_SIGNATURES
+        .iter()
+        .find(|sig| keccak256(sig.as_bytes())[..4] == *selector)
+        .copied()
+}
+
+/// One line per action, nested calls indented two spaces per level.
+pub fn preview(op: &PackedUserOperation, chain_id: u64) -> Vec<String> {
+    let mut lines = Vec::new();
+    if op.init_code.len() >= 20 {
+        lines.push(format!(
+            "deploy account {} via factory {}",
+            op.sender,
+            Address::from_slice(&op.init_code[..20])
+        ));
+    }
+    if op.call_data.is_empty() {
+        lines.push("no call".to_string());
+    } else {
+        let call = Call {
+            target: op.sender,
+            value: U256::ZERO,
+            data: op.call_data.clone(),
+        };
+        describe(chain_id, op.sender, &call, 0, &mut lines);
+    }
+    if op.paymaster_and_data.len() >= 20 {
+        lines.push(format!(
+            "gas paid by paymaster {}",
+            Address::from_slice(&op.paymaster_and_data[..20])
+        ));
+    }
+    lines
+}
+
+fn describe(chain_id: u64, account: Address, call: &Call, depth: usize, out: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
+    let to = call.target;
+    let data = call.data.as_slice();
+    let push_inner = |calls: &[Call], out: &mut Vec<String>| {
+        out.push(format!(
+            "{indent}execute {} call{} from {to}:",
+            calls.len(),
+            if calls.len() == 1 { "" } else { "s" }
+        ));
+        for inner in calls {
+            describe(chain_id, to, inner, depth + 1, out);
+        }
+    };
+
+    if data.is_empty() {
+        out.push(format!("{indent}send {} to {to}", eth(call.value)));
+        return;
+    }
+    let with_value = |text: String| {
+        if call.value.is_zero() {
+            format!("{indent}{text}")
+        } else {
+            format!("{indent}{text} (+{})", eth(call.value))
+        }
+    };
+    // Calls into an account (this one or, nested, another) can carry calls.
+    if depth < MAX_DEPTH && (to == account || depth == 0) {
+        if let Some(calls) = calldata::decode_execute(data) {
+            return push_inner(&calls, out);
+        }
+        if let Ok(c) = executeCall::abi_decode(data) {
+            let inner = Call {
+                target: c.dest,
+                value: c.value,
+                data: c.func.to_vec(),
+            };
+            return push_inner(std::slice::from_ref(&inner), out);
+        }
+        if let Ok(c) = executeBatchCall::abi_decode(data) {
+            let calls: Vec<Call> = c
+                .dest
+                .iter()
+                .enumerate()
+                .map(|(i, &target)| Call {
+                    target,
+                    value: c.value.get(i).copied().unwrap_or_default(),
+                    data: c.func.get(i).map(|f| f.to_vec()).unwrap_or_default(),
+                })
+                .collect();
+            return push_inner(&calls, out);
+        }
+    }
+
+    let text = if let Ok(c) = transferCall::abi_decode(data) {
+        format!("transfer {} to {}", amount(chain_id, to, c.amount), c.to)
+    } else if let Ok(c) = approveCall::abi_decode(data) {
+        format!(
+            "approve {} to spend {}",
+            c.spender,
+            amount(chain_id, to, c.amount)
+        )
+    } else if let Ok(c) = transferFromCall::abi_decode(data) {
+        format!(
+            "transferFrom {} to {}: {}",
+            c.from,
+            c.to,
+            amount(chain_id, to, c.amount)
+        )
+    } else if let Ok(c) = safeTransferFromCall::abi_decode(data) {
+        format!(
+            "transfer NFT #{} of {to} from {} to {}",
+            c.tokenId, c.from, c.to
+        )
+    } else if let Ok(c) = setApprovalForAllCall::abi_decode(data) {
+        let verb = if c.approved { "allow" } else { "revoke" };
+        format!("{verb} {} as operator for all of {to}", c.operator)
+    } else if depositCall::abi_decode(data).is_ok() {
+        format!("wrap into {to}")
+    } else if let Ok(c) = withdrawCall::abi_decode(data) {
+        format!("unwrap {} from {to}", format_units(c.amount, 18))
+    } else if let Ok(c) = installModuleCall::abi_decode(data) {
+        format!(
+            "install {} module {} on {to}",
+            module_type(c.moduleTypeId),
+            c.module
+        )
+    } else if let Ok(c) = uninstallModuleCall::abi_decode(data) {
+        format!(
+            "uninstall {} module {} from {to}",
+            module_type(c.moduleTypeId),
+            c.module
+        )
+    } else if let Some(sig) = named(&data[..data.len().min(4)]) {
+        format!("call {sig} on {to}")
+    } else {
+        format!(
+            "call unknown function 0x{} on {to} ({} bytes)",
+            crate::ct::encode_hex(&data[..data.len().min(4)]),
+            data.len()
+        )
+    };
+    out.push(with_value(text));
+}
+
+fn module_type(id: U256) -> String {
+    [
+        ModuleType::Validator,
+        ModuleType::Executor,
+        ModuleType::Fallback,
+        ModuleType::Hook,
+    ]
+    .into_iter()
+    .find(|t| U256::from(t.id()) == id)
+    .map_or_else(|| format!("type-{id}"), |t| t.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::B256;
+
+    fn op(call_data: Vec<u8>) -> PackedUserOperation {
+        PackedUserOperation {
+            sender: Address::repeat_byte(0xaa),
+            nonce: U256::ZERO,
+            init_code: Vec::new(),
+            call_data,
+            account_gas_limits: B256::ZERO,
+            pre_verification_gas: U256::ZERO,
+            gas_fees: B256::ZERO,
+            paymaster_and_data: Vec::new(),
+            signature: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn token_transfer_reads_in_whole_units() {
+        let usdc = TOKENS[0].address;
+        let to = Address::repeat_byte(0xbc);
+        let call = Call {
+            target: usdc,
+            value: U256::ZERO,
+            data: transferCall {
+                to,
+                amount: U256::from(100_500_000u64),
+            }
+            .abi_encode(),
+        };
+        let lines = preview(&op(calldata::execute(&[call])), 1);
+        assert_eq!(
+            lines[0],
+            format!("execute 1 call from {}:", Address::repeat_byte(0xaa))
+        );
+        assert_eq!(lines[1], format!("  transfer 100.5 USDC to {to}"));
+        // Same token address on another chain is not assumed to be USDC.
+        assert!(preview(&op(transfer_one(usdc, to)), 5)[1].contains("of token"));
+        assert_eq!(format_units(U256::from(10u64).pow(U256::from(18)), 18), "1");
+    }
+
+    fn transfer_one(token: Address, to: Address) -> Vec<u8> {
+        calldata::execute(&[Call {
+            target: token,
+            value: U256::ZERO,
+            data: transferCall {
+                to,
+                amount: U256::from(1),
+            }
+            .abi_encode(),
+        }])
+    }
+
+    #[test]
+    fn batches_nesting_and_unknown_calls() {
+        let account = Address::repeat_byte(0xaa);
+        let other = Address::repeat_byte(0x22);
+        let nested = executeBatchCall {
+            dest: vec![other],
+            value: vec![U256::from(10u64).pow(U256::from(17))],
+            func: vec![Default::default()],
+        }
+        .abi_encode();
+        let calls = [
+            Call {
+                target: account,
+                value: U256::ZERO,
+                data: nested,
+            },
+            Call {
+                target: other,
+                value: U256::ZERO,
+                data: vec![0xde, 0xad, 0xbe, 0xef, 0],
+            },
+            Call {
+                target: account,
+                value: U256::ZERO,
+                data: calldata::install_module(ModuleType::Validator, other, &[]),
+            },
+        ];
+        let lines = preview(&op(calldata::execute(&calls)), 1);
+        assert_eq!(
+            lines,
+            [
+                format!("execute 3 calls from {account}:"),
+                format!("  execute 1 call from {account}:"),
+                format!("    send 0.1 ETH to {other}"),
+                format!("  call unknown function 0xdeadbeef on {other} (5 bytes)"),
+                format!("  install validator module {other} on {account}"),
+            ]
+        );
+    }
+}
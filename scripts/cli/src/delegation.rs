@@ -0,0 +1,281 @@
+//! EIP-7702 delegation: let an existing EOA run the PQ account's code.
+//!
+//! The EOA's legacy secp256k1 key signs an authorization tuple
+//! `(chain_id, address, nonce)` naming the delegate contract. The signed
+//! tuple then travels either in the `authorization_list` of a type-4
+//! transaction ([`Eip7702Transaction`]) or, for bundlers that support it,
+//! as a UserOperation's `eip7702Auth` field — [`SignedAuthorization`]
+//! serializes to that shape. Once included, the EOA's code is the
+//! delegation designator `0xef0100 || address` and its ECDSA key is only
+//! needed again to re-delegate.
+
+use alloy_primitives::{Address, B256, Bytes, Signature, U8, U64, U128, U256, keccak256};
+use alloy_rlp::{Encodable, Header, RlpEncodable};
+use k256::ecdsa::SigningKey;
+use serde::{Deserialize, Serialize};
+
+use crate::ct;
+use crate::error::{Result, WalletError};
+
+/// Prefix of the authorization signing preimage.
+pub const AUTHORIZATION_MAGIC: u8 = 0x05;
+
+/// EIP-2718 type of a set-code transaction.
+pub const SET_CODE_TX_TYPE: u8 = 0x04;
+
+/// Prefix the EOA's code takes once delegated.
+pub const DELEGATION_PREFIX: [u8; 3] = [0xef, 0x01, 0x00];
+
+/// An unsigned authorization tuple. `chain_id` 0 is valid on every chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, RlpEncodable, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Authorization {
+    pub chain_id: U256,
+    pub address: Address,
+    pub nonce: U64,
+}
+
+/// A signed authorization tuple, in the JSON-RPC form of `eip7702Auth`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, RlpEncodable, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedAuthorization {
+    pub chain_id: U256,
+    pub address: Address,
+    pub nonce: U64,
+    pub y_parity: U8,
+    pub r: U256,
+    pub s: U256,
+}
+
+/// Parse a hex secp256k1 private key (`0x` optional).
+pub fn signing_key_from_hex(hex: &str) -> Result<SigningKey> {
+    let bytes = zeroize::Zeroizing::new(ct::decode_hex_array::<32>("ECDSA private key", hex)?);
+    SigningKey::from_slice(bytes.as_slice())
+        .map_err(|_| WalletError::Ecdsa("private key out of range".into()))
+}
+
+/// The EOA controlled by `key`.
+pub fn address_of(key: &SigningKey) -> Address {
+    Address::from_private_key(key)
+}
+
+/// Sign a 32-byte prehash, returning (y_parity, r, s) with low `s`.
+pub(crate) fn sign_hash(key: &SigningKey, hash: B256) -> Result<(bool, U256, U256)> {
+    let (sig, recid) = key
+        .sign_prehash_recoverable(hash.as_slice())
+        .map_err(|e| WalletError::Ecdsa(e.to_string()))?;
+    let (r, s) = sig.split_bytes();
+    Ok((
+        recid.is_y_odd(),
+        U256::from_be_slice(&r),
+        U256::from_be_slice(&s),
+    ))
+}
+
+impl Authorization {
+    /// `keccak256(0x05 || rlp([chain_id, address, nonce]))`.
+    pub fn signing_hash(&self) -> B256 {
+        let mut buf = vec![AUTHORIZATION_MAGIC];
+        self.encode(&mut buf);
+        keccak256(buf)
+    }
+
+    pub fn sign(&self, key: &SigningKey) -> Result<SignedAuthorization> {
+        let (y_parity, r, s) = sign_hash(key, self.signing_hash())?;
+        Ok(SignedAuthorization {
+            chain_id: self.chain_id,
+            address: self.address,
+            nonce: self.nonce,
+            y_parity: U8::from(y_parity as u8),
+            r,
+            s,
+        })
+    }
+}
+
+impl SignedAuthorization {
+    pub fn unsigned(&self) -> Authorization {
+        Authorization {
+            chain_id: self.chain_id,
+            address: self.address,
+            nonce: self.nonce,
+        }
+    }
+
+    /// The EOA that signed this authorization.
+    pub fn authority(&self) -> Result<Address> {
+        let parity = match self.y_parity.to::<u8>() {
+            0 => false,
+            1 => true,
+            other => return Err(WalletError::Ecdsa(format!("yParity {other} is not 0 or 1"))),
+        };
+        Signature::new(self.r, self.s, parity)
+            .recover_address_from_prehash(&self.unsigned().signing_hash())
+            .map_err(|e| WalletError::Ecdsa(e.to_string()))
+    }
+}
+
+/// The code an EOA carries once delegated to `delegate`.
+pub fn delegation_designator(delegate: Address) -> [u8; 23] {
+    let mut code = [0u8; 23];
+    code[..3].copy_from_slice(&DELEGATION_PREFIX);
+    code[3..].copy_from_slice(delegate.as_slice());
+    code
+}
+
+/// An EIP-7702 set-code transaction with an empty access list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Eip7702Transaction {
+    pub chain_id: U256,
+    pub nonce: U64,
+    pub max_priority_fee_per_gas: U128,
+    pub max_fee_per_gas: U128,
+    pub gas_limit: U64,
+    pub to: Address,
+    pub value: U256,
+    pub data: Bytes,
+    pub authorization_list: Vec<SignedAuthorization>,
+}
+
+pub(crate) fn rlp_list(fields: &[&dyn Encodable]) -> Vec<u8> {
+    let payload_length = fields.iter().map(|f| f.length()).sum();
+    let mut out = Vec::with_capacity(payload_length + 9);
+    Header {
+        list: true,
+        payload_length,
+    }
+    .encode(&mut out);
+    for field in fields {
+        field.encode(&mut out);
+    }
+    out
+}
+
+impl Eip7702Transaction {
+    fn encode_with(&self, signature: Option<(U8, U256, U256)>) -> Vec<u8> {
+        let access_list: Vec<Bytes> = Vec::new();
+        let mut fields: Vec<&dyn Encodable> = vec![
+            &self.chain_id,
+            &self.nonce,
+            &self.max_priority_fee_per_gas,
+            &self.max_fee_per_gas,
+            &self.gas_limit,
+            &self.to,
+            &self.value,
+            &self.data,
+            &access_list,
+            &self.authorization_list,
+        ];
+        if let Some((y_parity, r, s)) = &signature {
+            fields.extend([y_parity as &dyn Encodable, r, s]);
+        }
+        let mut out = vec![SET_CODE_TX_TYPE];
+        out.extend(rlp_list(&fields));
+        out
+    }
+
+    /// Hash the sender signs: `keccak256(0x04 || rlp(fields))`.
+    pub fn signing_hash(&self) -> B256 {
+        keccak256(self.encode_with(None))
+    }
+
+    /// Sign and return the raw transaction for `eth_sendRawTransaction`.
+    pub fn sign(&self, key: &SigningKey) -> Result<Bytes> {
+        let (y_parity, r, s) = sign_hash(key, self.signing_hash())?;
+        Ok(self
+            .encode_with(Some((U8::from(y_parity as u8), r, s)))
+            .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{address, b256};
+
+    fn key_one() -> SigningKey {
+        signing_key_from_hex(&format!("0x{:064x}", 1)).unwrap()
+    }
+
+    #[test]
+    fn authorization_preimage_is_magic_then_rlp() {
+        let auth = Authorization {
+            chain_id: U256::from(1),
+            address: Address::repeat_byte(0x11),
+            nonce: U64::ZERO,
+        };
+        let mut expected = vec![0x05, 0xd7, 0x01, 0x94];
+        expected.extend([0x11; 20]);
+        expected.push(0x80);
+        assert_eq!(auth.signing_hash(), keccak256(&expected));
+    }
+
+    #[test]
+    fn signed_authorization_recovers_the_eoa() {
+        let key = key_one();
+        assert_eq!(
+            address_of(&key),
+            address!("0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf")
+        );
+        let unsigned = Authorization {
+            chain_id: U256::from(412346),
+            address: Address::repeat_byte(0x22),
+            nonce: U64::from(7),
+        };
+        // Cross-checked against alloy-eips' `Authorization::signature_hash`.
+        assert_eq!(
+            unsigned.signing_hash(),
+            b256!("0xe01dfd86d92697ef7c2557a298128c74cbbc2ba8a87aa20eddd65910bb2a6a53")
+        );
+        let auth = unsigned.sign(&key).unwrap();
+        assert_eq!(auth.authority().unwrap(), address_of(&key));
+
+        let json = serde_json::to_value(auth).unwrap();
+        assert_eq!(json["chainId"], "0x64aba");
+        assert_eq!(json["nonce"], "0x7");
+        let tampered = SignedAuthorization {
+            nonce: U64::from(8),
+            ..auth
+        };
+        assert_ne!(tampered.authority().unwrap(), address_of(&key));
+    }
+
+    #[test]
+    fn set_code_transaction_is_type_4() {
+        let key = key_one();
+        let auth = Authorization {
+            chain_id: U256::from(1),
+            address: Address::repeat_byte(0x33),
+            nonce: U64::from(1),
+        }
+        .sign(&key)
+        .unwrap();
+        let tx = Eip7702Transaction {
+            chain_id: U256::from(1),
+            nonce: U64::ZERO,
+            max_priority_fee_per_gas: U128::from(1),
+            max_fee_per_gas: U128::from(2),
+            gas_limit: U64::from(100_000),
+            to: address_of(&key),
+            value: U256::ZERO,
+            data: Bytes::new(),
+            authorization_list: vec![auth],
+        };
+        let raw = tx.sign(&key).unwrap();
+        assert_eq!(raw[0], SET_CODE_TX_TYPE);
+        // Same bytes as alloy-consensus' TxEip7702::encoded_2718 (RFC 6979
+        // nonces make the signatures deterministic).
+        assert_eq!(
+            raw.to_string(),
+            "0x04f8c101800102830186a0947e5f4552091a69125d5dfcb7b8c2659029395bdf8080c0f85cf85a01943333\
+             3333333333333333333333333333333333330101a0f425435192f51e12f6220becdf61bd6f460f252179d8\
+             16914220f1df635ddd7ca04a63a0cbb87454036bf420c29f8fe2cf97d6627989ff51b5c8766df09894c849\
+             01a03a5d77e0d469b3a321fe8af2feeb73f48497edc7bef66cc620c5d932b0a9e077a014f73ceaae851b46\
+             6733f6aa798434d1fe338060589678dad671dd3bec5b9232"
+        );
+        assert_eq!(
+            delegation_designator(auth.address)[..3],
+            DELEGATION_PREFIX[..]
+        );
+    }
+}
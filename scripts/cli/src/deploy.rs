@@ -0,0 +1,204 @@
+//! Deterministic contract deployment through the canonical CREATE2 deployer.
+//!
+//! The deployer at [`CREATE2_DEPLOYER`] (Arachnid's deterministic deployment
+//! proxy, also what `forge script` uses) takes `salt || initCode` as
+//! calldata and CREATE2s it, so a contract lands at the same address on
+//! every chain the proxy exists on. [`Plan`] computes that address from a
+//! Foundry artifact, its constructor arguments and a salt; `pq-deploy` signs
+//! the call as an EIP-1559 transaction ([`Eip1559Transaction`]) from a
+//! funded ECDSA key and broadcasts it.
+
+use std::path::Path;
+
+use alloy_primitives::{Address, B256, Bytes, U64, U128, U256, address, keccak256};
+use alloy_rlp::Encodable;
+use k256::ecdsa::SigningKey;
+use serde::{Deserialize, Serialize};
+
+use crate::delegation;
+use crate::error::{Result, WalletError};
+use crate::rpc::Transport;
+
+/// Arachnid's deterministic deployment proxy.
+pub const CREATE2_DEPLOYER: Address = address!("0x4e59b44847b379578588920cA78FbF26c0B4956C");
+
+/// EIP-2718 type of a dynamic-fee transaction.
+pub const DYNAMIC_FEE_TX_TYPE: u8 = 0x02;
+
+/// Creation bytecode from a Foundry artifact (`out/<File>.sol/<Name>.json`).
+#[derive(Clone, Debug)]
+pub struct Artifact {
+    pub name: String,
+    pub bytecode: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct ArtifactFile {
+    bytecode: BytecodeObject,
+}
+
+#[derive(Deserialize)]
+struct BytecodeObject {
+    object: Bytes,
+}
+
+impl Artifact {
+    pub fn from_json(name: &str, text: &str) -> Result<Self> {
+        let file: ArtifactFile = serde_json::from_str(text)
+            .map_err(|e| WalletError::Manifest(format!("artifact {name}: {e}")))?;
+        if file.bytecode.object.is_empty() {
+            return Err(WalletError::Manifest(format!(
+                "artifact {name} has no bytecode (abstract contract or interface?)"
+            )));
+        }
+        Ok(Artifact {
+            name: name.to_string(),
+            bytecode: file.bytecode.object.to_vec(),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| WalletError::Io(path.display().to_string(), e))?;
+        let name = path
+            .file_stem()
+            .map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+        Self::from_json(&name, &text)
+    }
+}
+
+/// Where a contract will land, and the deployer call that puts it there.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Plan {
+    pub contract: String,
+    pub address: Address,
+    pub salt: B256,
+    pub init_code_hash: B256,
+    /// Calldata for [`CREATE2_DEPLOYER`]: `salt || initCode`.
+    #[serde(skip)]
+    pub data: Vec<u8>,
+}
+
+impl Plan {
+    /// Deploy `artifact` with ABI-encoded `constructor_args` under `salt`.
+    pub fn new(artifact: &Artifact, constructor_args: &[u8], salt: B256) -> Self {
+        let mut init_code = artifact.bytecode.clone();
+        init_code.extend_from_slice(constructor_args);
+        let init_code_hash = keccak256(&init_code);
+        let mut data = salt.to_vec();
+        data.extend(init_code);
+        Plan {
+            contract: artifact.name.clone(),
+            address: CREATE2_DEPLOYER.create2(salt, init_code_hash),
+            salt,
+            init_code_hash,
+            data,
+        }
+    }
+}
+
+/// Whether `address` has code on the node behind `rpc`.
+pub fn has_code<T: Transport>(rpc: &T, address: Address) -> Result<bool> {
+    let code: Bytes = rpc.call("eth_getCode", serde_json::json!([address, "latest"]))?;
+    Ok(!code.is_empty())
+}
+
+/// An EIP-1559 transaction with an empty access list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Eip1559Transaction {
+    pub chain_id: U256,
+    pub nonce: U64,
+    pub max_priority_fee_per_gas: U128,
+    pub max_fee_per_gas: U128,
+    pub gas_limit: U64,
+    pub to: Address,
+    pub value: U256,
+    pub data: Bytes,
+}
+
+impl Eip1559Transaction {
+    fn encode_with(&self, signature: Option<(U64, U256, U256)>) -> Vec<u8> {
+        let access_list: Vec<Bytes> = Vec::new();
+        let mut fields: Vec<&dyn Encodable> = vec![
+            &self.chain_id,
+            &self.nonce,
+            &self.max_priority_fee_per_gas,
+            &self.max_fee_per_gas,
+            &self.gas_limit,
+            &self.to,
+            &self.value,
+            &self.data,
+            &access_list,
+        ];
+        if let Some((y_parity, r, s)) = &signature {
+            fields.extend([y_parity as &dyn Encodable, r, s]);
+        }
+        let mut out = vec![DYNAMIC_FEE_TX_TYPE];
+        out.extend(delegation::rlp_list(&fields));
+        out
+    }
+
+    /// Hash the sender signs: `keccak256(0x02 || rlp(fields))`.
+    pub fn signing_hash(&self) -> B256 {
+        keccak256(self.encode_with(None))
+    }
+
+    /// Sign and return the raw transaction for `eth_sendRawTransaction`.
+    pub fn sign(&self, key: &SigningKey) -> Result<Bytes> {
+        let (y_parity, r, s) = delegation::sign_hash(key, self.signing_hash())?;
+        Ok(self
+            .encode_with(Some((U64::from(y_parity as u8), r, s)))
+            .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan_matches_create2_and_the_deployer_calldata() {
+        let artifact = Artifact::from_json(
+            "Stub",
+            r#"{"abi": [], "bytecode": {"object": "0x6080", "sourceMap": ""}}"#,
+        )
+        .unwrap();
+        assert!(Artifact::from_json("IFace", r#"{"bytecode": {"object": "0x"}}"#).is_err());
+        let salt = B256::repeat_byte(0x01);
+        let plan = Plan::new(&artifact, &[0xaa], salt);
+        assert_eq!(plan.data[..32], salt[..]);
+        assert_eq!(plan.data[32..], [0x60, 0x80, 0xaa]);
+        assert_eq!(
+            plan.address,
+            CREATE2_DEPLOYER.create2_from_code(salt, [0x60, 0x80, 0xaa])
+        );
+        // Constructor arguments are part of the address.
+        assert_ne!(Plan::new(&artifact, &[], salt).address, plan.address);
+    }
+
+    #[test]
+    fn dynamic_fee_transaction_is_type_2() {
+        let key = delegation::signing_key_from_hex(&format!("0x{:064x}", 1)).unwrap();
+        let tx = Eip1559Transaction {
+            chain_id: U256::from(1),
+            nonce: U64::ZERO,
+            max_priority_fee_per_gas: U128::from(1),
+            max_fee_per_gas: U128::from(2),
+            gas_limit: U64::from(100_000),
+            to: CREATE2_DEPLOYER,
+            value: U256::ZERO,
+            data: Bytes::from_static(&[0; 33]),
+        };
+        let raw = tx.sign(&key).unwrap();
+        assert_eq!(raw[0], DYNAMIC_FEE_TX_TYPE);
+        // Same bytes as alloy-consensus' TxEip1559::encoded_2718.
+        assert_eq!(
+            raw.to_string(),
+            "0x02f88401800102830186a0944e59b44847b379578588920ca78fbf26c0b4956c80a10000000000000000\
+             00000000000000000000000000000000000000000000000000c080a02757dcc5332a5bf96bb52fdc4e5fe8\
+             7a4c360b6f70949e1a6b1aeceb0a41e5dba078b14a9ae81481634e4f15143f0505d10aa10ae066502c1c0c\
+             065d7e86e335f0"
+        );
+    }
+}
@@ -0,0 +1,567 @@
+//! A minimal ERC-4337 bundler for local development (`devnet` feature).
+//!
+//! `pq-devnet --rpc http://127.0.0.1:8545` serves the bundler JSON-RPC
+//! methods in front of an Anvil node, so the whole PQ flow (`pq-userop
+//! estimate`, `submit`, `status`) runs locally without installing Rundler or
+//! Alto:
+//!
+//! - `eth_sendUserOperation` simulates `handleOps([op])` against the node
+//!   and, if it passes, sends exactly that `handleOps` call as an EIP-1559
+//!   transaction signed with the dev key (Anvil's first account unless told
+//!   otherwise). Every op is its own bundle, sent as soon as it arrives, so
+//!   there is no mempool to reason about.
+//! - `eth_estimateUserOperationGas` computes preVerificationGas like
+//!   `pq-userop estimate` does offline, and asks the node for the call's gas.
+//!   Verification gas is a generous fixed allowance, since an EVM ML-DSA
+//!   verifier runs to several million gas.
+//! - `eth_getUserOperationReceipt` and `eth_getUserOperationByHash` answer
+//!   from the node's receipts for the bundles sent here.
+//! - `eth_supportedEntryPoints` and `eth_chainId`.
+//!
+//! None of the ERC-7562 validation rules are enforced and nothing is
+//! persisted; this is a test harness, not a public bundler.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use alloy_primitives::{Address, B256, Bytes, U64, U256};
+use k256::ecdsa::SigningKey;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::delegation;
+use crate::error::{Result, WalletError};
+use crate::gas::pre_verification::{self, Calibration};
+use crate::rpc::Transport;
+use crate::self_bundle;
+use crate::simulate::{self, Simulation};
+use crate::userop::{self, PackedUserOperation};
+
+/// Anvil's first prefunded account.
+pub const ANVIL_DEV_KEY: &str =
+    "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+/// Verification gas quoted by `eth_estimateUserOperationGas`.
+pub const VERIFICATION_GAS_ALLOWANCE: u64 = 15_000_000;
+
+/// Call gas quoted when the node cannot estimate the call, e.g. because the
+/// account is not deployed yet.
+pub const DEFAULT_CALL_GAS: u64 = 1_000_000;
+
+/// ERC-7769: the op was rejected during validation.
+pub const REJECTED_BY_ENTRY_POINT: i64 = -32500;
+/// ERC-7769: the paymaster rejected the op.
+pub const REJECTED_BY_PAYMASTER: i64 = -32501;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+/// A JSON-RPC error answered to the client.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fault {
+    pub code: i64,
+    pub message: String,
+}
+
+impl Fault {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Fault {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<WalletError> for Fault {
+    /// Node or encoding failures: the client's request was fine.
+    fn from(e: WalletError) -> Self {
+        Fault::new(-32603, e.to_string())
+    }
+}
+
+/// A bundle sent for an op.
+#[derive(Clone, Debug)]
+struct Bundled {
+    op: PackedUserOperation,
+    transaction_hash: B256,
+}
+
+/// The bundler behind `pq-devnet`.
+pub struct DevBundler<T> {
+    node: T,
+    chain_id: u64,
+    entry_point: Address,
+    key: SigningKey,
+    from: Address,
+    bundled: HashMap<B256, Bundled>,
+    /// How long to wait for a bundle's receipt.
+    pub receipt_timeout: Duration,
+}
+
+impl<T: Transport> DevBundler<T> {
+    /// Bundle for `entry_point` on `node`, paying with `key`.
+    pub fn new(node: T, entry_point: Address, key: SigningKey) -> Result<Self> {
+        let chain_id = node.call::<U64>("eth_chainId", json!([]))?.to::<u64>();
+        let from = delegation::address_of(&key);
+        Ok(DevBundler {
+            node,
+            chain_id,
+            entry_point,
+            key,
+            from,
+            bundled: HashMap::new(),
+            receipt_timeout: Duration::from_secs(30),
+        })
+    }
+
+    /// The account that sends bundles and collects their fees.
+    pub fn bundler_address(&self) -> Address {
+        self.from
+    }
+
+    /// Answer one JSON-RPC call.
+    pub fn handle(&mut self, method: &str, params: &Value) -> std::result::Result<Value, Fault> {
+        match method {
+            "eth_chainId" => Ok(json!(U64::from(self.chain_id))),
+            "eth_supportedEntryPoints" => Ok(json!([self.entry_point])),
+            "eth_sendUserOperation" => {
+                let op = self.op_param(params)?;
+                Ok(json!(self.send(op)?))
+            }
+            "eth_estimateUserOperationGas" => {
+                let op = self.op_param(params)?;
+                Ok(self.estimate(&op)?)
+            }
+            "eth_getUserOperationReceipt" => {
+                let hash = hash_param(params)?;
+                Ok(self.receipt(hash)?.unwrap_or(Value::Null))
+            }
+            "eth_getUserOperationByHash" => {
+                let hash = hash_param(params)?;
+                Ok(self.lookup(hash)?.unwrap_or(Value::Null))
+            }
+            other => Err(Fault::new(
+                METHOD_NOT_FOUND,
+                format!("{other} is not served by pq-devnet"),
+            )),
+        }
+    }
+
+    /// `[op, entryPoint]`, for this bundler's EntryPoint.
+    fn op_param(&self, params: &Value) -> std::result::Result<PackedUserOperation, Fault> {
+        #[derive(Deserialize)]
+        struct Params(PackedUserOperation, Address);
+
+        let Params(op, entry_point) = serde_json::from_value(params.clone()).map_err(|e| {
+            Fault::new(
+                INVALID_PARAMS,
+                format!("expected [userOp, entryPoint]: {e}"),
+            )
+        })?;
+        if entry_point != self.entry_point {
+            return Err(Fault::new(
+                INVALID_PARAMS,
+                format!(
+                    "unsupported EntryPoint {entry_point}; this bundler serves {}",
+                    self.entry_point
+                ),
+            ));
+        }
+        Ok(op)
+    }
+
+    fn user_op_hash(&self, op: &PackedUserOperation) -> B256 {
+        userop::compute_user_op_hash(op, self.entry_point, U256::from(self.chain_id))
+    }
+
+    /// Simulate, then bundle `op` on its own and wait for the receipt.
+    fn send(&mut self, op: PackedUserOperation) -> std::result::Result<B256, Fault> {
+        let hash = self.user_op_hash(&op);
+        let simulation =
+            simulate::simulate_handle_ops(&self.node, &op, self.entry_point, self.from)?;
+        if let Simulation::Reverted { revert } = &simulation {
+            let code = match revert.aa_code() {
+                Some(aa) if aa.starts_with("AA3") => REJECTED_BY_PAYMASTER,
+                _ => REJECTED_BY_ENTRY_POINT,
+            };
+            return Err(Fault::new(code, revert.to_string()));
+        }
+
+        let transaction_hash = self_bundle::send(
+            &self.node,
+            self.chain_id,
+            std::slice::from_ref(&op),
+            self.entry_point,
+            self.from,
+            &self.key,
+        )?
+        .transaction_hash;
+        tracing::info!(%hash, %transaction_hash, sender = %op.sender, "bundled");
+        self.bundled.insert(
+            hash,
+            Bundled {
+                op,
+                transaction_hash,
+            },
+        );
+        // Anvil mines on arrival; waiting here means a client that polls
+        // right after sending finds the receipt.
+        self.wait_for_receipt(transaction_hash)?;
+        Ok(hash)
+    }
+
+    fn wait_for_receipt(&self, transaction_hash: B256) -> Result<Value> {
+        let deadline = Instant::now() + self.receipt_timeout;
+        loop {
+            let receipt = self
+                .node
+                .request("eth_getTransactionReceipt", json!([transaction_hash]))?;
+            if !receipt.is_null() {
+                return Ok(receipt);
+            }
+            if Instant::now() >= deadline {
+                return Err(WalletError::Rpc(format!(
+                    "bundle {transaction_hash} was not mined"
+                )));
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    fn estimate(&self, op: &PackedUserOperation) -> std::result::Result<Value, Fault> {
+        let pvg = pre_verification::estimate(op, &Calibration::for_chain(self.chain_id), None)?;
+        // The account executes callData as called by the EntryPoint.
+        let call_gas = self
+            .node
+            .call::<U64>(
+                "eth_estimateGas",
+                json!([{
+                    "from": self.entry_point,
+                    "to": op.sender,
+                    "data": Bytes::copy_from_slice(&op.call_data),
+                }]),
+            )
+            .map(|gas| gas.to::<u64>())
+            .unwrap_or(DEFAULT_CALL_GAS);
+        Ok(json!({
+            "preVerificationGas": U64::from(pvg.pre_verification_gas),
+            "verificationGasLimit": U64::from(VERIFICATION_GAS_ALLOWANCE),
+            "callGasLimit": U64::from(call_gas),
+        }))
+    }
+
+    /// The receipt of the bundle that carried `hash`, in bundler shape.
+    fn receipt(&self, hash: B256) -> Result<Option<Value>> {
+        let Some(bundled) = self.bundled.get(&hash) else {
+            return Ok(None);
+        };
+        let receipt = self.node.request(
+            "eth_getTransactionReceipt",
+            json!([bundled.transaction_hash]),
+        )?;
+        if receipt.is_null() {
+            return Ok(None);
+        }
+        let event = receipt["logs"].as_array().and_then(|logs| {
+            logs.iter()
+                .filter_map(|log| serde_json::from_value::<crate::bundler::Log>(log.clone()).ok())
+                .find_map(|log| user_operation_event(&log, hash))
+        });
+        let mut out = json!({
+            "userOpHash": hash,
+            "entryPoint": self.entry_point,
+            "sender": bundled.op.sender,
+            "nonce": bundled.op.nonce,
+            // One op per bundle: every log in the receipt is this op's.
+            "logs": receipt["logs"].clone(),
+            "receipt": receipt,
+        });
+        if let Some(event) = event {
+            out["success"] = json!(event.success);
+            out["actualGasCost"] = json!(event.actualGasCost);
+            out["actualGasUsed"] = json!(event.actualGasUsed);
+            out["paymaster"] = json!(event.paymaster);
+        }
+        Ok(Some(out))
+    }
+
+    fn lookup(&self, hash: B256) -> Result<Option<Value>> {
+        let Some(bundled) = self.bundled.get(&hash) else {
+            return Ok(None);
+        };
+        let receipt = self.node.request(
+            "eth_getTransactionReceipt",
+            json!([bundled.transaction_hash]),
+        )?;
+        Ok(Some(json!({
+            "userOperation": bundled.op,
+            "entryPoint": self.entry_point,
+            "transactionHash": bundled.transaction_hash,
+            "blockHash": receipt["blockHash"],
+            "blockNumber": receipt["blockNumber"],
+        })))
+    }
+
+    /// Serve HTTP JSON-RPC on `listener`, one request at a time so bundles
+    /// never race for the dev key's nonce.
+    pub fn serve(&mut self, listener: TcpListener) -> Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream.map_err(|e| WalletError::Io("devnet listener".into(), e))?;
+            if let Err(e) = self.answer(stream) {
+                tracing::debug!("dropped connection: {e}");
+            }
+        }
+        Ok(())
+    }
+
+    fn answer(&mut self, mut stream: TcpStream) -> std::io::Result<()> {
+        let mut reader = BufReader::new(&stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':')
+                && name.eq_ignore_ascii_case("content-length")
+            {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+        // Browser dapps (the demo) preflight their bundler calls.
+        let (status, body) = if request_line.starts_with("OPTIONS ") {
+            ("204 No Content", String::new())
+        } else if request_line.starts_with("POST ") {
+            let mut body = vec![0u8; content_length];
+            reader.read_exact(&mut body)?;
+            ("200 OK", self.dispatch(&body).to_string())
+        } else {
+            ("405 Method Not Allowed", String::new())
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\n\
+             Access-Control-Allow-Origin: *\r\n\
+             Access-Control-Allow-Headers: content-type\r\n\
+             Access-Control-Allow-Methods: POST, OPTIONS\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    /// A JSON-RPC request body, single or batched.
+    pub fn dispatch(&mut self, body: &[u8]) -> Value {
+        match serde_json::from_slice::<Value>(body) {
+            Ok(Value::Array(calls)) => calls.iter().map(|call| self.call(call)).collect(),
+            Ok(call) => self.call(&call),
+            Err(e) => json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": { "code": -32700, "message": e.to_string() },
+            }),
+        }
+    }
+
+    fn call(&mut self, call: &Value) -> Value {
+        let id = call["id"].clone();
+        let method = call["method"].as_str().unwrap_or_default();
+        let params = call.get("params").cloned().unwrap_or(json!([]));
+        match self.handle(method, &params) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(fault) => {
+                tracing::info!(method, code = fault.code, "{}", fault.message);
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": fault.code, "message": fault.message },
+                })
+            }
+        }
+    }
+}
+
+fn hash_param(params: &Value) -> std::result::Result<B256, Fault> {
+    serde_json::from_value::<(B256,)>(params.clone())
+        .map(|(hash,)| hash)
+        .map_err(|e| Fault::new(INVALID_PARAMS, format!("expected [userOpHash]: {e}")))
+}
+
+fn user_operation_event(
+    log: &crate::bundler::Log,
+    hash: B256,
+) -> Option<crate::bundler::UserOperationEvent> {
+    use alloy_sol_types::SolEvent;
+
+    let event =
+        crate::bundler::UserOperationEvent::decode_raw_log(log.topics.iter().copied(), &log.data)
+            .ok()?;
+    (event.userOpHash == hash).then_some(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::{self, State, UserOperationEvent};
+    use crate::simulate::EntryPointErrors;
+    use crate::userop::ENTRY_POINT_V07 as EP;
+    use alloy_primitives::LogData;
+    use alloy_sol_types::{SolError, SolEvent};
+    use std::cell::RefCell;
+
+    /// An Anvil stand-in that mines every raw transaction at once.
+    #[derive(Default)]
+    struct Node {
+        revert: Option<Bytes>,
+        sent: RefCell<Vec<Bytes>>,
+        event: RefCell<Option<Value>>,
+    }
+
+    impl Transport for Node {
+        fn request(&self, method: &str, params: Value) -> Result<Value> {
+            Ok(match method {
+                "eth_chainId" => json!("0x7a69"),
+                "eth_call" => match &self.revert {
+                    Some(data) => {
+                        return Err(WalletError::RpcError {
+                            method: method.to_string(),
+                            code: 3,
+                            message: "execution reverted".into(),
+                            data: Some(json!(data)),
+                        });
+                    }
+                    None => json!("0x"),
+                },
+                "eth_getTransactionCount" => json!("0x3"),
+                "eth_estimateGas" => json!("0x30d40"),
+                "eth_getBlockByNumber" => json!({ "baseFeePerGas": "0x3b9aca00" }),
+                "eth_maxPriorityFeePerGas" => json!("0x1"),
+                "eth_sendRawTransaction" => {
+                    let raw: Bytes = serde_json::from_value(params[0].clone()).unwrap();
+                    self.sent.borrow_mut().push(raw);
+                    json!(B256::repeat_byte(0xbb))
+                }
+                "eth_getTransactionReceipt" => json!({
+                    "transactionHash": B256::repeat_byte(0xbb),
+                    "blockHash": B256::repeat_byte(0xcc),
+                    "blockNumber": "0x9",
+                    "status": "0x1",
+                    "logs": self.event.borrow().iter().cloned().collect::<Vec<_>>(),
+                }),
+                other => panic!("unexpected {other}"),
+            })
+        }
+    }
+
+    /// The bundler as a client sees it.
+    struct Client<'a>(RefCell<&'a mut DevBundler<Node>>);
+
+    impl Transport for Client<'_> {
+        fn request(&self, method: &str, params: Value) -> Result<Value> {
+            self.0
+                .borrow_mut()
+                .handle(method, &params)
+                .map_err(|f| WalletError::RpcError {
+                    method: method.to_string(),
+                    code: f.code,
+                    message: f.message,
+                    data: None,
+                })
+        }
+    }
+
+    fn op() -> PackedUserOperation {
+        serde_json::from_value(json!({
+            "sender": "0x1111111111111111111111111111111111111111",
+            "nonce": "0x0",
+            "callData": "0x",
+            "callGasLimit": "0x1",
+            "verificationGasLimit": "0x1",
+            "preVerificationGas": "0x1",
+            "maxFeePerGas": "0x1",
+            "maxPriorityFeePerGas": "0x1",
+            "signature": "0x01"
+        }))
+        .unwrap()
+    }
+
+    fn bundler(node: Node) -> DevBundler<Node> {
+        let key = delegation::signing_key_from_hex(ANVIL_DEV_KEY).unwrap();
+        DevBundler::new(node, EP, key).unwrap()
+    }
+
+    #[test]
+    fn bundles_ops_into_handle_ops_and_reports_receipts() {
+        let mut dev = bundler(Node::default());
+        assert_eq!(
+            dev.bundler_address().to_string(),
+            "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+        );
+        let op = op();
+        let hash = dev.user_op_hash(&op);
+        let event = UserOperationEvent {
+            userOpHash: hash,
+            sender: op.sender,
+            paymaster: Address::ZERO,
+            nonce: U256::ZERO,
+            success: true,
+            actualGasCost: U256::from(7),
+            actualGasUsed: U256::from(5),
+        };
+        let LogData { data, .. } = event.encode_log_data();
+        let topics: Vec<B256> = event.encode_topics().into_iter().map(|t| t.0).collect();
+        *dev.node.event.borrow_mut() =
+            Some(json!({ "address": EP, "topics": topics, "data": data }));
+
+        let client = Client(RefCell::new(&mut dev));
+        assert_eq!(
+            bundler::send_user_operation(&client, &op, EP, None).unwrap(),
+            hash
+        );
+        let status = bundler::status(&client, hash).unwrap();
+        assert_eq!(status.state, State::Included);
+        assert_eq!(status.success, Some(true));
+        assert_eq!(status.actual_gas_used, Some(U256::from(5)));
+        assert!(matches!(
+            bundler::send_user_operation(&client, &op, Address::ZERO, None),
+            Err(WalletError::RpcError {
+                code: INVALID_PARAMS,
+                ..
+            })
+        ));
+        let estimate = bundler::estimate_gas(&client, &op, EP).unwrap();
+        assert_eq!(estimate.call_gas_limit, U256::from(200_000));
+
+        // The transaction is handleOps([op], bundler) to the EntryPoint.
+        let sent = dev.node.sent.borrow();
+        let raw = &sent[0];
+        assert_eq!(raw[0], 0x02);
+        let calldata = simulate::encode_handle_ops(&[op], dev.from);
+        assert!(raw.windows(calldata.len()).any(|w| w == calldata));
+    }
+
+    #[test]
+    fn rejects_ops_that_fail_simulation() {
+        let revert = EntryPointErrors::FailedOp {
+            opIndex: U256::ZERO,
+            reason: "AA24 signature error".into(),
+        }
+        .abi_encode();
+        let mut dev = bundler(Node {
+            revert: Some(revert.into()),
+            ..Node::default()
+        });
+        let fault = dev
+            .handle("eth_sendUserOperation", &json!([op(), EP]))
+            .unwrap_err();
+        assert_eq!(fault.code, REJECTED_BY_ENTRY_POINT);
+        assert!(fault.message.contains("AA24"), "{}", fault.message);
+        assert!(dev.node.sent.borrow().is_empty());
+
+        let reply = dev.dispatch(br#"[{"jsonrpc":"2.0","id":1,"method":"eth_supportedEntryPoints"},{"jsonrpc":"2.0","id":2,"method":"debug_bundler_dumpMempool"}]"#);
+        assert_eq!(reply[0]["result"][0], json!(EP));
+        assert_eq!(reply[1]["error"]["code"], METHOD_NOT_FOUND);
+    }
+}
@@ -0,0 +1,427 @@
+//! End-to-end runs against a throwaway Anvil node (`e2e` feature).
+//!
+//! [`Anvil::spawn`] starts a node on a free port and kills it on drop.
+//! [`run`] then exercises the whole PQ path from Rust, with no bundler and
+//! no shell scripts:
+//!
+//! 1. deploy the EntryPoint, the ML-DSA verifier, `PQValidatorModule`, and
+//!    Kernel v3 with its factory, from Foundry artifacts;
+//! 2. derive the counterfactual Kernel account whose root validator is the
+//!    PQ module holding the test key, and fund it;
+//! 3. build a UserOperation that deploys the account through its initCode
+//!    and sends ETH to a recipient, cross-check its hash against
+//!    `getUserOpHash`, and sign it with ML-DSA-65;
+//! 4. submit it with `handleOps` and check the `UserOperationEvent`, the
+//!    account's code and the recipient's balance.
+//!
+//! Anvil runs EVM bytecode only, so the verifier artifact must be an EVM
+//! implementation of `IMLDSAVerifier` rather than the Stylus contract.
+
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use alloy_primitives::{Address, B256, Bytes, FixedBytes, U64, U256};
+use alloy_sol_types::{SolCall, SolValue, sol};
+use ml_dsa::signature::Signer;
+use ml_dsa::{MlDsa65, SigningKey};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::bundler::{self, Log, UserOpStatus, UserOperationReceipt};
+use crate::calldata::{self, Call};
+use crate::deploy::Artifact;
+use crate::error::{Result, WalletError};
+use crate::keys::SEED_LEN;
+use crate::rpc::{HttpTransport, Transport};
+use crate::simulate;
+use crate::userop::{self, PackedUserOperation};
+
+/// Overrides the `anvil` found on `PATH`.
+pub const ANVIL_ENV: &str = "ANVIL";
+
+sol! {
+    function initialize(
+        bytes21 rootValidator,
+        address hook,
+        bytes validatorData,
+        bytes hookData,
+        bytes[] initConfig
+    );
+    function createAccount(bytes data, bytes32 salt) returns (address);
+    function getAddress(bytes data, bytes32 salt) returns (address);
+}
+
+/// Kernel's "no hook" sentinel.
+const NO_HOOK: Address = Address::with_last_byte(1);
+/// Kernel `ValidationId` type byte for a validator module.
+const VALIDATION_TYPE_VALIDATOR: u8 = 0x01;
+
+fn e2e_err(reason: impl Into<String>) -> WalletError {
+    WalletError::E2e(reason.into())
+}
+
+/// A running Anvil node, killed on drop.
+pub struct Anvil {
+    child: Child,
+    pub url: String,
+    pub rpc: HttpTransport,
+    pub chain_id: u64,
+}
+
+impl Anvil {
+    /// Start `anvil` on a free local port and wait until it answers.
+    pub fn spawn() -> Result<Self> {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .map_err(|e| WalletError::Io("free port".into(), e))?
+            .port();
+        let program = std::env::var(ANVIL_ENV).unwrap_or_else(|_| "anvil".into());
+        let child = Command::new(&program)
+            .args(["--port", &port.to_string(), "--silent"])
+            // ML-DSA verifiers in Solidity run past the 24KB limit.
+            .arg("--disable-code-size-limit")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| WalletError::Io(program, e))?;
+        let url = format!("http://127.0.0.1:{port}");
+        let mut anvil = Anvil {
+            child,
+            rpc: HttpTransport::new(url.clone(), Duration::from_secs(30)),
+            url,
+            chain_id: 0,
+        };
+        let deadline = Instant::now() + Duration::from_secs(10);
+        anvil.chain_id = loop {
+            match anvil.rpc.call::<U64>("eth_chainId", json!([])) {
+                Ok(id) => break id.to::<u64>(),
+                Err(_) if Instant::now() < deadline => {
+                    if let Ok(Some(status)) = anvil.child.try_wait() {
+                        return Err(e2e_err(format!("anvil exited with {status}")));
+                    }
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                Err(e) => return Err(e2e_err(format!("anvil did not come up: {e}"))),
+            }
+        };
+        Ok(anvil)
+    }
+
+    /// The first of Anvil's unlocked, prefunded accounts.
+    pub fn dev_account(&self) -> Result<Address> {
+        let accounts: Vec<Address> = self.rpc.call("eth_accounts", json!([]))?;
+        accounts
+            .first()
+            .copied()
+            .ok_or_else(|| e2e_err("anvil has no unlocked accounts"))
+    }
+}
+
+impl Drop for Anvil {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Contract `name` in the first of `out_dirs` that has it, as Foundry
+/// lays artifacts out when the file is named after the contract.
+pub fn find_artifact(out_dirs: &[PathBuf], name: &str) -> Result<Artifact> {
+    out_dirs
+        .iter()
+        .map(|dir| dir.join(format!("{name}.sol")).join(format!("{name}.json")))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            e2e_err(format!(
+                "no artifact for {name} in {}",
+                out_dirs
+                    .iter()
+                    .map(|d| d.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        })
+        .and_then(|path| Artifact::load(&path))
+}
+
+/// The contracts [`run`] deploys.
+#[derive(Clone, Debug)]
+pub struct Artifacts {
+    pub entry_point: Artifact,
+    /// EVM `IMLDSAVerifier` implementation.
+    pub verifier: Artifact,
+    pub module: Artifact,
+    pub kernel: Artifact,
+    pub factory: Artifact,
+}
+
+impl Artifacts {
+    /// `EntryPoint`, `PQValidatorModule`, `Kernel`, `KernelFactory` and
+    /// the contract named `verifier` from Foundry `out` directories.
+    pub fn find(out_dirs: &[PathBuf], verifier: &str) -> Result<Self> {
+        Ok(Artifacts {
+            entry_point: find_artifact(out_dirs, "EntryPoint")?,
+            verifier: find_artifact(out_dirs, verifier)?,
+            module: find_artifact(out_dirs, "PQValidatorModule")?,
+            kernel: find_artifact(out_dirs, "Kernel")?,
+            factory: find_artifact(out_dirs, "KernelFactory")?,
+        })
+    }
+}
+
+/// The receipt fields a run looks at.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Receipt {
+    pub transaction_hash: B256,
+    pub block_number: U256,
+    pub status: U64,
+    pub contract_address: Option<Address>,
+    pub gas_used: U256,
+    #[serde(default)]
+    pub logs: Vec<Log>,
+}
+
+/// `eth_sendTransaction` from one of the node's unlocked accounts, then
+/// wait for the receipt. A reverted transaction is an error.
+pub fn send<T: Transport>(
+    rpc: &T,
+    from: Address,
+    to: Option<Address>,
+    value: U256,
+    data: &[u8],
+) -> Result<Receipt> {
+    let mut tx = json!({ "from": from, "value": value, "data": Bytes::copy_from_slice(data) });
+    if let Some(to) = to {
+        tx["to"] = json!(to);
+    }
+    let hash: B256 = rpc.call("eth_sendTransaction", json!([tx]))?;
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let receipt = loop {
+        let receipt: Option<Receipt> = rpc.call("eth_getTransactionReceipt", json!([hash]))?;
+        match receipt {
+            Some(receipt) => break receipt,
+            None if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(100)),
+            None => return Err(e2e_err(format!("{hash} was not mined"))),
+        }
+    };
+    if receipt.status != U64::from(1) {
+        return Err(e2e_err(format!("{hash} reverted")));
+    }
+    Ok(receipt)
+}
+
+/// Deploy `artifact` with ABI-encoded `constructor_args`.
+pub fn deploy<T: Transport>(
+    rpc: &T,
+    from: Address,
+    artifact: &Artifact,
+    constructor_args: &[u8],
+) -> Result<Address> {
+    let mut code = artifact.bytecode.clone();
+    code.extend_from_slice(constructor_args);
+    let receipt = send(rpc, from, None, U256::ZERO, &code)
+        .map_err(|e| e2e_err(format!("deploying {}: {e}", artifact.name)))?;
+    let address = receipt
+        .contract_address
+        .ok_or_else(|| e2e_err(format!("{} receipt has no contractAddress", artifact.name)))?;
+    tracing::debug!(contract = artifact.name, %address, "deployed");
+    Ok(address)
+}
+
+fn eth_call<T: Transport>(rpc: &T, to: Address, data: Vec<u8>) -> Result<Bytes> {
+    rpc.call(
+        "eth_call",
+        json!([{ "to": to, "data": Bytes::from(data) }, "latest"]),
+    )
+}
+
+fn balance<T: Transport>(rpc: &T, address: Address) -> Result<U256> {
+    rpc.call("eth_getBalance", json!([address, "latest"]))
+}
+
+/// Kernel `initialize` calldata with the PQ module as root validator.
+pub fn kernel_init_data(module: Address, public_key: &[u8]) -> Vec<u8> {
+    let mut root = [0u8; 21];
+    root[0] = VALIDATION_TYPE_VALIDATOR;
+    root[1..].copy_from_slice(module.as_slice());
+    initializeCall {
+        rootValidator: FixedBytes(root),
+        hook: NO_HOOK,
+        validatorData: Bytes::copy_from_slice(public_key),
+        hookData: Bytes::new(),
+        initConfig: Vec::new(),
+    }
+    .abi_encode()
+}
+
+/// What a run deployed and sent.
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    pub entry_point: Address,
+    pub module: Address,
+    pub account: Address,
+    pub user_op_hash: B256,
+    pub transaction_hash: B256,
+    pub status: UserOpStatus,
+}
+
+/// What [`run`] sends, and how much gas it allows for verification.
+#[derive(Clone, Debug)]
+pub struct Scenario {
+    pub seed: [u8; SEED_LEN],
+    pub recipient: Address,
+    pub value: U256,
+    pub fund: U256,
+    pub verification_gas_limit: u128,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Scenario {
+            seed: [7u8; SEED_LEN],
+            recipient: Address::repeat_byte(0x11),
+            value: U256::from(1_000_000_000_000_000u64),
+            fund: U256::from(1_000_000_000_000_000_000u64),
+            // An EVM ML-DSA-65 verifier costs several million gas.
+            verification_gas_limit: 15_000_000,
+        }
+    }
+}
+
+/// Deploy everything, send one PQ-signed op and check what it did.
+pub fn run(anvil: &Anvil, artifacts: &Artifacts, scenario: &Scenario) -> Result<Outcome> {
+    let rpc = &anvil.rpc;
+    let dev = anvil.dev_account()?;
+
+    let entry_point = deploy(rpc, dev, &artifacts.entry_point, &[])?;
+    let verifier = deploy(rpc, dev, &artifacts.verifier, &[])?;
+    let module = deploy(rpc, dev, &artifacts.module, &verifier.abi_encode())?;
+    let kernel = deploy(rpc, dev, &artifacts.kernel, &entry_point.abi_encode())?;
+    let factory = deploy(rpc, dev, &artifacts.factory, &kernel.abi_encode())?;
+
+    let sk = SigningKey::<MlDsa65>::from_seed(&scenario.seed.into());
+    let init_data = Bytes::from(kernel_init_data(module, &sk.verifying_key().encode()));
+    let salt = B256::ZERO;
+    let predicted = eth_call(
+        rpc,
+        factory,
+        getAddressCall {
+            data: init_data.clone(),
+            salt,
+        }
+        .abi_encode(),
+    )?;
+    let account = getAddressCall::abi_decode_returns(&predicted)
+        .map_err(|e| e2e_err(format!("getAddress: {e}")))?;
+    send(rpc, dev, Some(account), scenario.fund, &[])?;
+
+    let mut init_code = factory.to_vec();
+    init_code.extend(
+        createAccountCall {
+            data: init_data,
+            salt,
+        }
+        .abi_encode(),
+    );
+    let mut op = PackedUserOperation {
+        sender: account,
+        nonce: U256::ZERO,
+        init_code,
+        call_data: calldata::execute(&[Call {
+            target: scenario.recipient,
+            value: scenario.value,
+            data: Vec::new(),
+        }]),
+        account_gas_limits: userop::pack_u128_pair(scenario.verification_gas_limit, 100_000),
+        pre_verification_gas: U256::from(100_000),
+        gas_fees: userop::pack_u128_pair(1_000_000_000, 10_000_000_000),
+        paymaster_and_data: Vec::new(),
+        signature: Vec::new(),
+    };
+    let user_op_hash = simulate::cross_check_user_op_hash(rpc, &op, entry_point, anvil.chain_id)?;
+    op.signature = sk.sign(user_op_hash.as_slice()).encode().to_vec();
+
+    let before = balance(rpc, scenario.recipient)?;
+    let receipt = send(
+        rpc,
+        dev,
+        Some(entry_point),
+        U256::ZERO,
+        &simulate::encode_handle_ops(std::slice::from_ref(&op), dev),
+    )
+    .map_err(|e| e2e_err(format!("handleOps: {e}")))?;
+    let status = UserOpStatus::from_receipt(
+        user_op_hash,
+        &UserOperationReceipt {
+            user_op_hash,
+            entry_point,
+            receipt: bundler::TransactionReceipt {
+                transaction_hash: receipt.transaction_hash,
+                block_number: receipt.block_number,
+                logs: receipt.logs,
+            },
+            logs: Vec::new(),
+        },
+    )?;
+    if status.success != Some(true) {
+        return Err(e2e_err(format!(
+            "op {user_op_hash} was included but its call failed"
+        )));
+    }
+    let code: Bytes = rpc.call("eth_getCode", json!([account, "latest"]))?;
+    if code.is_empty() {
+        return Err(e2e_err(format!("account {account} was not deployed")));
+    }
+    let received = balance(rpc, scenario.recipient)? - before;
+    if received != scenario.value {
+        return Err(e2e_err(format!(
+            "recipient received {received} wei, expected {}",
+            scenario.value
+        )));
+    }
+    Ok(Outcome {
+        entry_point,
+        module,
+        account,
+        user_op_hash,
+        transaction_hash: receipt.transaction_hash,
+        status,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernel_init_data_installs_the_module_as_root() {
+        let module = Address::repeat_byte(0xab);
+        let data = kernel_init_data(module, &[1, 2, 3]);
+        let call = initializeCall::abi_decode(&data).unwrap();
+        assert_eq!(call.rootValidator[0], VALIDATION_TYPE_VALIDATOR);
+        assert_eq!(&call.rootValidator[1..], module.as_slice());
+        assert_eq!(call.hook, NO_HOOK);
+        assert_eq!(call.validatorData.as_ref(), [1, 2, 3]);
+    }
+
+    /// Needs `anvil` and built artifacts: `PQ_E2E_OUT` lists Foundry `out`
+    /// directories (default `../../evm/out`), `PQ_E2E_VERIFIER` names the
+    /// EVM verifier contract (default `MLDSAVerifier`). Run with
+    /// `cargo test --features e2e -- --ignored`.
+    #[test]
+    #[ignore = "needs anvil and compiled contracts"]
+    fn pq_userop_deploys_account_and_transfers() {
+        let out_dirs: Vec<PathBuf> = std::env::var("PQ_E2E_OUT")
+            .unwrap_or_else(|_| concat!(env!("CARGO_MANIFEST_DIR"), "/../../evm/out").into())
+            .split(':')
+            .map(PathBuf::from)
+            .collect();
+        let verifier = std::env::var("PQ_E2E_VERIFIER").unwrap_or_else(|_| "MLDSAVerifier".into());
+        let artifacts = Artifacts::find(&out_dirs, &verifier).unwrap();
+        let anvil = Anvil::spawn().unwrap();
+        let outcome = run(&anvil, &artifacts, &Scenario::default()).unwrap();
+        assert_eq!(outcome.status.success, Some(true));
+    }
+}
@@ -0,0 +1,187 @@
+//! ENS forward and reverse resolution over a node's `eth_call`.
+//!
+//! [`resolve`] turns `alice.eth` into an address through the registry's
+//! resolver; [`reverse`] finds an address's primary name and only returns it
+//! if the name resolves back to the same address, since anyone can claim
+//! any reverse name. Names are limited to ASCII letters, digits, `-` and
+//! `_`, compared lowercase: Unicode names (and their look-alikes) need full
+//! ENSIP-15 normalization, which this module does not do. Offchain (CCIP-read)
+//! and wildcard resolvers are not followed.
+//!
+//! ENS lives on Ethereum mainnet and its testnets; resolve against one of
+//! those even when the op targets an L2.
+
+use alloy_primitives::{Address, B256, Bytes, address, hex, keccak256};
+use alloy_sol_types::{SolCall, sol};
+
+use crate::error::{Result, WalletError};
+use crate::rpc::Transport;
+
+/// The ENS registry, at the same address on mainnet, Sepolia and Holesky.
+pub const ENS_REGISTRY: Address = address!("00000000000C2E074eC69A0dFb2997BA6C7d2e1e");
+
+sol! {
+    function resolver(bytes32 node) returns (address);
+    function addr(bytes32 node) returns (address);
+    function name(bytes32 node) returns (string);
+}
+
+/// Whether `target` is meant as an ENS name rather than a contact.
+pub fn is_name(target: &str) -> bool {
+    target.contains('.')
+}
+
+/// EIP-137 `namehash` of a normalized name.
+pub fn namehash(name: &str) -> B256 {
+    name.rsplit('.')
+        .filter(|label| !label.is_empty())
+        .fold(B256::ZERO, |node, label| {
+            let mut buf = [0u8; 64];
+            buf[..32].copy_from_slice(node.as_slice());
+            buf[32..].copy_from_slice(keccak256(label.as_bytes()).as_slice());
+            keccak256(buf)
+        })
+}
+
+/// `name` lowercased, or an error if it is not a plain ASCII name.
+pub fn normalize(name: &str) -> Result<String> {
+    let lower = name.to_ascii_lowercase();
+    let plain = |label: &str| {
+        !label.is_empty()
+            && label
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    };
+    if !lower.split('.').all(plain) {
+        return Err(WalletError::Ens(format!(
+            "{name:?} is not a plain ASCII name (letters, digits, - and _ between dots)"
+        )));
+    }
+    Ok(lower)
+}
+
+/// `eth_call` returning `None` for an account without code.
+fn call<T: Transport, C: SolCall>(rpc: &T, to: Address, call: C) -> Result<Option<C::Return>> {
+    let ret: Bytes = rpc.call(
+        "eth_call",
+        serde_json::json!([{ "to": to, "data": Bytes::from(call.abi_encode()) }, "latest"]),
+    )?;
+    if ret.is_empty() {
+        return Ok(None);
+    }
+    C::abi_decode_returns(&ret)
+        .map(Some)
+        .map_err(|e| WalletError::Ens(format!("undecodable {} result: {e}", C::SIGNATURE)))
+}
+
+fn resolver_of<T: Transport>(rpc: &T, node: B256) -> Result<Option<Address>> {
+    match call(rpc, ENS_REGISTRY, resolverCall { node })? {
+        None => Err(WalletError::Ens(format!(
+            "no ENS registry at {ENS_REGISTRY} on this node; resolve names against \
+             Ethereum mainnet or Sepolia"
+        ))),
+        Some(resolver) if resolver.is_zero() => Ok(None),
+        Some(resolver) => Ok(Some(resolver)),
+    }
+}
+
+/// The address `name` resolves to.
+pub fn resolve<T: Transport>(rpc: &T, name: &str) -> Result<Address> {
+    let name = normalize(name)?;
+    let node = namehash(&name);
+    let resolver = resolver_of(rpc, node)?
+        .ok_or_else(|| WalletError::Ens(format!("{name} has no resolver")))?;
+    match call(rpc, resolver, addrCall { node })? {
+        Some(address) if !address.is_zero() => Ok(address),
+        _ => Err(WalletError::Ens(format!("{name} has no address set"))),
+    }
+}
+
+/// `address`'s primary name, if it has one that resolves back to it.
+pub fn reverse<T: Transport>(rpc: &T, address: Address) -> Result<Option<String>> {
+    let node = namehash(&format!("{}.addr.reverse", hex::encode(address)));
+    let Some(resolver) = resolver_of(rpc, node)? else {
+        return Ok(None);
+    };
+    let Some(claimed) = call(rpc, resolver, nameCall { node })? else {
+        return Ok(None);
+    };
+    if claimed.is_empty() || normalize(&claimed).is_err() {
+        return Ok(None);
+    }
+    match resolve(rpc, &claimed) {
+        Ok(forward) if forward == address => Ok(Some(claimed.to_ascii_lowercase())),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_sol_types::SolValue;
+    use serde_json::Value;
+
+    #[test]
+    fn namehash_matches_eip137() {
+        assert_eq!(namehash(""), B256::ZERO);
+        assert_eq!(
+            namehash("eth").to_string(),
+            "0x93cdeb708b7545dc668eb9280176169d1c33cfd8ed6f04690a0bcc88a93fc4ae"
+        );
+        assert_eq!(
+            namehash("foo.eth").to_string(),
+            "0xde9b09fd7c5f901e23a3f19fecc54828e9c848539801e86591bd9801b019f84f"
+        );
+        assert_eq!(normalize("Alice.ETH").unwrap(), "alice.eth");
+        assert!(normalize("аlice.eth").is_err(), "Cyrillic а");
+        assert!(normalize("alice..eth").is_err());
+    }
+
+    /// A registry pointing every node at one resolver that knows one
+    /// name, plus a reverse record.
+    struct Ens {
+        name: &'static str,
+        address: Address,
+        reverse: &'static str,
+    }
+
+    const RESOLVER: Address = Address::repeat_byte(0xee);
+
+    impl Transport for Ens {
+        fn request(&self, method: &str, params: Value) -> Result<Value> {
+            assert_eq!(method, "eth_call");
+            let to: Address = serde_json::from_value(params[0]["to"].clone()).unwrap();
+            let data: Bytes = serde_json::from_value(params[0]["data"].clone()).unwrap();
+            let ret = if to == ENS_REGISTRY {
+                RESOLVER.abi_encode()
+            } else if let Ok(c) = addrCall::abi_decode(&data) {
+                let known = c.node == namehash(self.name);
+                (if known { self.address } else { Address::ZERO }).abi_encode()
+            } else {
+                nameCall::abi_decode(&data).unwrap();
+                self.reverse.to_string().abi_encode()
+            };
+            Ok(serde_json::json!(Bytes::from(ret)))
+        }
+    }
+
+    #[test]
+    fn resolves_names_and_checks_reverse_records() {
+        let alice = Address::repeat_byte(0xa1);
+        let ens = Ens {
+            name: "alice.eth",
+            address: alice,
+            reverse: "alice.eth",
+        };
+        assert_eq!(resolve(&ens, "ALICE.eth").unwrap(), alice);
+        assert!(resolve(&ens, "bob.eth").is_err());
+        assert_eq!(reverse(&ens, alice).unwrap().as_deref(), Some("alice.eth"));
+
+        // A reverse record claiming a name that points elsewhere is ignored.
+        let spoofed = Ens {
+            reverse: "vitalik.eth",
+            ..ens
+        };
+        assert_eq!(reverse(&spoofed, alice).unwrap(), None);
+    }
+}
@@ -0,0 +1,548 @@
+//! Signature envelope carried in `PackedUserOperation.signature`.
+//!
+//! Two layouts are supported:
+//!
+//! - **ABI**: `abi.encode(uint8 schemeId, bytes pubKey, bytes sig)`, decoded on
+//!   the Solidity side with `abi.decode(userOp.signature, (uint8, bytes, bytes))`.
+//! - **Packed**: `pubKey || sig` with no header. The scheme is implied by the
+//!   total length (5,261 bytes for ML-DSA-65), which is why
+//!   [`encode_signature_envelope_packed`] takes no scheme ID.
+//!
+//! The deployed `PQValidatorModule` stores the public key at install time and
+//! reads a bare signature, or a V3 envelope below; the other envelopes are
+//! for account contracts that ship the key alongside each signature.
+//!
+//! Layouts that will change carry a version byte instead: [`Envelope`] is
+//! `version || abi.encode(...)`, one body per version:
+//!
+//! - **V1**: `abi.encode(uint8 schemeId, bytes pubKey, bytes sig)`, the ABI
+//!   layout above.
+//! - **V2**: `abi.encode(uint8 schemeId, bytes pubKey, bytes sig, bytes
+//!   ecdsaSig)`, adding the 65-byte `r || s || v` secp256k1 half of a hybrid
+//!   signature.
+//! - **V3** (delegated): `abi.encode(Delegation d, bytes ownerSig, uint8
+//!   schemeId, bytes robotKey, bytes robotSig)`. The account's owner key
+//!   signed [`Delegation::hash`], granting the robot key the right to sign
+//!   for `d.account` on `d.chainId` (0 for any chain) between `validAfter`
+//!   and `validUntil`. `robotSig` is the robot key's signature over the
+//!   userOpHash. The validator checks both signatures, that `keccak256(robotKey)`
+//!   is `d.robotKeyHash` and that the delegation is not revoked, and returns
+//!   the window in its `validationData`.
+//!
+//! Decoding is strict for the versions this build knows and fails with
+//! [`WalletError::UnsupportedEnvelopeVersion`] for the rest, so an old CLI
+//! refuses a newer envelope instead of misreading it. Unversioned ABI
+//! envelopes start with `0x00`, which is never a version.
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use alloy_primitives::aliases::U48;
+use alloy_primitives::{Address, B256, Bytes, U256, keccak256};
+use alloy_sol_types::{SolType, sol_data};
+
+use crate::error::{Result, WalletError};
+use crate::keys::{PUBLIC_KEY_LEN, SIGNATURE_LEN};
+
+/// Scheme ID for ML-DSA-65, as understood by the validator.
+pub const SCHEME_ML_DSA_65: u8 = 1;
+
+/// Length of the packed ML-DSA-65 envelope (`pk || sig`).
+pub const PACKED_ML_DSA_65_LEN: usize = PUBLIC_KEY_LEN + SIGNATURE_LEN;
+
+/// [`Envelope::V1`].
+pub const ENVELOPE_V1: u8 = 1;
+/// [`Envelope::V2`].
+pub const ENVELOPE_V2: u8 = 2;
+/// [`Envelope::V3`].
+pub const ENVELOPE_V3: u8 = 3;
+/// The newest version this build encodes and decodes.
+pub const LATEST_ENVELOPE_VERSION: u8 = ENVELOPE_V3;
+
+/// The EIP-712-style type string behind [`Delegation::hash`].
+pub const DELEGATION_TYPE: &str = "PQDelegation(address account,uint256 chainId,bytes32 robotKeyHash,uint48 validAfter,uint48 validUntil,bytes32 policyHash)";
+
+/// Length of the ECDSA half in a V2 envelope (`r || s || v`).
+pub const ECDSA_SIGNATURE_LEN: usize = 65;
+
+/// `(uint8 schemeId, bytes pubKey, bytes sig)`
+type EnvelopeAbi = (sol_data::Uint<8>, sol_data::Bytes, sol_data::Bytes);
+
+/// `(uint8 schemeId, bytes pubKey, bytes sig, bytes ecdsaSig)`
+type EnvelopeV2Abi = (
+    sol_data::Uint<8>,
+    sol_data::Bytes,
+    sol_data::Bytes,
+    sol_data::Bytes,
+);
+
+/// `(address account, uint256 chainId, bytes32 robotKeyHash, uint48
+/// validAfter, uint48 validUntil, bytes32 policyHash)`, a static struct.
+type DelegationAbi = (
+    sol_data::Address,
+    sol_data::Uint<256>,
+    sol_data::FixedBytes<32>,
+    sol_data::Uint<48>,
+    sol_data::Uint<48>,
+    sol_data::FixedBytes<32>,
+);
+
+/// `(Delegation d, bytes ownerSig, uint8 schemeId, bytes robotKey, bytes
+/// robotSig)`
+type EnvelopeV3Abi = (
+    DelegationAbi,
+    sol_data::Bytes,
+    sol_data::Uint<8>,
+    sol_data::Bytes,
+    sol_data::Bytes,
+);
+
+/// A decoded signature envelope.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignatureEnvelope {
+    pub scheme_id: u8,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+/// Expected `(public key, signature)` lengths for a scheme ID.
+pub fn scheme_lengths(scheme_id: u8) -> Result<(usize, usize)> {
+    match scheme_id {
+        SCHEME_ML_DSA_65 => Ok((PUBLIC_KEY_LEN, SIGNATURE_LEN)),
+        other => Err(WalletError::UnknownScheme(other)),
+    }
+}
+
+fn check_lengths(scheme_id: u8, pk: &[u8], sig: &[u8]) -> Result<()> {
+    let (pk_len, sig_len) = scheme_lengths(scheme_id)?;
+    if pk.len() != pk_len {
+        return Err(WalletError::InvalidLength {
+            what: "envelope public key",
+            expected: pk_len,
+            actual: pk.len(),
+        });
+    }
+    if sig.len() != sig_len {
+        return Err(WalletError::InvalidLength {
+            what: "envelope signature",
+            expected: sig_len,
+            actual: sig.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Encode `abi.encode(uint8 schemeId, bytes pubKey, bytes sig)`.
+pub fn encode_signature_envelope(pk: &[u8], sig: &[u8], scheme_id: u8) -> Result<Vec<u8>> {
+    check_lengths(scheme_id, pk, sig)?;
+    Ok(EnvelopeAbi::abi_encode_params(&(
+        scheme_id,
+        Bytes::copy_from_slice(pk),
+        Bytes::copy_from_slice(sig),
+    )))
+}
+
+/// Decode an ABI envelope, rejecting non-canonical encodings and lengths that
+/// do not match the declared scheme.
+pub fn decode_signature_envelope(data: &[u8]) -> Result<SignatureEnvelope> {
+    let (scheme_id, pk, sig) = EnvelopeAbi::abi_decode_params_validate(data)
+        .map_err(|e| WalletError::InvalidEnvelope(e.to_string()))?;
+    check_lengths(scheme_id, &pk, &sig)?;
+    Ok(SignatureEnvelope {
+        scheme_id,
+        public_key: pk.to_vec(),
+        signature: sig.to_vec(),
+    })
+}
+
+/// Encode the packed ML-DSA-65 envelope `pk || sig`.
+pub fn encode_signature_envelope_packed(pk: &[u8], sig: &[u8]) -> Result<Vec<u8>> {
+    check_lengths(SCHEME_ML_DSA_65, pk, sig)?;
+    let mut out = Vec::with_capacity(PACKED_ML_DSA_65_LEN);
+    out.extend_from_slice(pk);
+    out.extend_from_slice(sig);
+    Ok(out)
+}
+
+/// Decode the packed ML-DSA-65 envelope `pk || sig`.
+pub fn decode_signature_envelope_packed(data: &[u8]) -> Result<SignatureEnvelope> {
+    if data.len() != PACKED_ML_DSA_65_LEN {
+        return Err(WalletError::InvalidLength {
+            what: "packed envelope",
+            expected: PACKED_ML_DSA_65_LEN,
+            actual: data.len(),
+        });
+    }
+    let (pk, sig) = data.split_at(PUBLIC_KEY_LEN);
+    Ok(SignatureEnvelope {
+        scheme_id: SCHEME_ML_DSA_65,
+        public_key: pk.to_vec(),
+        signature: sig.to_vec(),
+    })
+}
+
+/// A V2 envelope: the PQ signature plus the ECDSA co-signature from the
+/// same signer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnvelopeV2 {
+    pub pq: SignatureEnvelope,
+    /// `r || s || v` over the same hash.
+    pub ecdsa_signature: Vec<u8>,
+}
+
+/// The grant an owner key signs for a robot key, as the validator reads it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Delegation {
+    /// The smart account the robot key may sign for.
+    pub account: Address,
+    /// 0 for any chain.
+    pub chain_id: u64,
+    /// keccak256 of the robot's public key.
+    pub robot_key_hash: B256,
+    pub valid_after: u64,
+    pub valid_until: u64,
+    /// Hash of the signing policy the robot's signer enforces; zero for
+    /// none.
+    pub policy_hash: B256,
+}
+
+impl Delegation {
+    fn to_abi(self) -> (Address, U256, B256, U48, U48, B256) {
+        // `encode` rejects times past uint48 before they get here.
+        (
+            self.account,
+            U256::from(self.chain_id),
+            self.robot_key_hash,
+            U48::saturating_from(self.valid_after),
+            U48::saturating_from(self.valid_until),
+            self.policy_hash,
+        )
+    }
+
+    /// `keccak256(abi.encode(keccak256(DELEGATION_TYPE), d))`: what the
+    /// owner key signs, and what a revocation names.
+    pub fn hash(&self) -> B256 {
+        let encoded = <(sol_data::FixedBytes<32>, DelegationAbi)>::abi_encode_params(&(
+            keccak256(DELEGATION_TYPE),
+            self.to_abi(),
+        ));
+        keccak256(encoded)
+    }
+}
+
+/// A V3 envelope: a robot key's signature, with the owner's delegation to
+/// it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnvelopeV3 {
+    pub delegation: Delegation,
+    /// The owner key's ML-DSA-65 signature over [`Delegation::hash`].
+    pub owner_signature: Vec<u8>,
+    /// The robot key and its signature over the userOpHash.
+    pub pq: SignatureEnvelope,
+}
+
+/// A versioned signature envelope, `version || body`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Envelope {
+    V1(SignatureEnvelope),
+    V2(EnvelopeV2),
+    V3(EnvelopeV3),
+}
+
+impl Envelope {
+    pub fn version(&self) -> u8 {
+        match self {
+            Envelope::V1(_) => ENVELOPE_V1,
+            Envelope::V2(_) => ENVELOPE_V2,
+            Envelope::V3(_) => ENVELOPE_V3,
+        }
+    }
+
+    /// The post-quantum part, which every version carries. In a V3
+    /// envelope it is the robot key's.
+    pub fn pq(&self) -> &SignatureEnvelope {
+        match self {
+            Envelope::V1(env) => env,
+            Envelope::V2(env) => &env.pq,
+            Envelope::V3(env) => &env.pq,
+        }
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let pq = self.pq();
+        check_lengths(pq.scheme_id, &pq.public_key, &pq.signature)?;
+        let pk = Bytes::copy_from_slice(&pq.public_key);
+        let sig = Bytes::copy_from_slice(&pq.signature);
+        let body = match self {
+            Envelope::V1(_) => EnvelopeAbi::abi_encode_params(&(pq.scheme_id, pk, sig)),
+            Envelope::V2(env) => {
+                check_ecdsa_length(&env.ecdsa_signature)?;
+                EnvelopeV2Abi::abi_encode_params(&(
+                    pq.scheme_id,
+                    pk,
+                    sig,
+                    Bytes::copy_from_slice(&env.ecdsa_signature),
+                ))
+            }
+            Envelope::V3(env) => {
+                check_delegation(env)?;
+                EnvelopeV3Abi::abi_encode_params(&(
+                    env.delegation.to_abi(),
+                    Bytes::copy_from_slice(&env.owner_signature),
+                    pq.scheme_id,
+                    pk,
+                    sig,
+                ))
+            }
+        };
+        let mut out = Vec::with_capacity(1 + body.len());
+        out.push(self.version());
+        out.extend_from_slice(&body);
+        Ok(out)
+    }
+
+    /// Decode a versioned envelope. The body must be the canonical encoding
+    /// for its version, with nothing after it.
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let (&version, body) = data
+            .split_first()
+            .ok_or_else(|| WalletError::InvalidEnvelope("empty".to_string()))?;
+        let invalid = |e: alloy_sol_types::Error| WalletError::InvalidEnvelope(e.to_string());
+        let envelope = match version {
+            ENVELOPE_V1 => {
+                let (scheme_id, pk, sig) =
+                    EnvelopeAbi::abi_decode_params_validate(body).map_err(invalid)?;
+                Envelope::V1(SignatureEnvelope {
+                    scheme_id,
+                    public_key: pk.to_vec(),
+                    signature: sig.to_vec(),
+                })
+            }
+            ENVELOPE_V2 => {
+                let (scheme_id, pk, sig, ecdsa) =
+                    EnvelopeV2Abi::abi_decode_params_validate(body).map_err(invalid)?;
+                Envelope::V2(EnvelopeV2 {
+                    pq: SignatureEnvelope {
+                        scheme_id,
+                        public_key: pk.to_vec(),
+                        signature: sig.to_vec(),
+                    },
+                    ecdsa_signature: ecdsa.to_vec(),
+                })
+            }
+            ENVELOPE_V3 => {
+                let (d, owner_sig, scheme_id, pk, sig) =
+                    EnvelopeV3Abi::abi_decode_params_validate(body).map_err(invalid)?;
+                let (account, chain_id, robot_key_hash, valid_after, valid_until, policy_hash) = d;
+                let chain_id = u64::try_from(chain_id).map_err(|_| {
+                    WalletError::InvalidEnvelope("delegation chain ID exceeds 64 bits".to_string())
+                })?;
+                Envelope::V3(EnvelopeV3 {
+                    delegation: Delegation {
+                        account,
+                        chain_id,
+                        robot_key_hash,
+                        valid_after: valid_after.to(),
+                        valid_until: valid_until.to(),
+                        policy_hash,
+                    },
+                    owner_signature: owner_sig.to_vec(),
+                    pq: SignatureEnvelope {
+                        scheme_id,
+                        public_key: pk.to_vec(),
+                        signature: sig.to_vec(),
+                    },
+                })
+            }
+            other => return Err(WalletError::UnsupportedEnvelopeVersion(other)),
+        };
+        // Lengths first, for the clearer error.
+        if data != envelope.encode()? {
+            return Err(WalletError::InvalidEnvelope(
+                "not the canonical encoding (padding, offsets or trailing bytes)".to_string(),
+            ));
+        }
+        Ok(envelope)
+    }
+}
+
+fn check_delegation(env: &EnvelopeV3) -> Result<()> {
+    if env.owner_signature.len() != SIGNATURE_LEN {
+        return Err(WalletError::InvalidLength {
+            what: "envelope owner signature",
+            expected: SIGNATURE_LEN,
+            actual: env.owner_signature.len(),
+        });
+    }
+    let max = U48::MAX.to::<u64>();
+    if env.delegation.valid_after > max || env.delegation.valid_until > max {
+        return Err(WalletError::InvalidEnvelope(
+            "delegation window exceeds uint48".to_string(),
+        ));
+    }
+    if keccak256(&env.pq.public_key) != env.delegation.robot_key_hash {
+        return Err(WalletError::InvalidEnvelope(
+            "robot key does not match the delegation".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn check_ecdsa_length(sig: &[u8]) -> Result<()> {
+    if sig.len() != ECDSA_SIGNATURE_LEN {
+        return Err(WalletError::InvalidLength {
+            what: "envelope ECDSA signature",
+            expected: ECDSA_SIGNATURE_LEN,
+            actual: sig.len(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> (Vec<u8>, Vec<u8>) {
+        let pk: Vec<u8> = (0..PUBLIC_KEY_LEN).map(|i| i as u8).collect();
+        let sig: Vec<u8> = (0..SIGNATURE_LEN).map(|i| (i * 7) as u8).collect();
+        (pk, sig)
+    }
+
+    #[test]
+    fn abi_envelope_round_trips() {
+        let (pk, sig) = sample();
+        let enc = encode_signature_envelope(&pk, &sig, SCHEME_ML_DSA_65).unwrap();
+        let dec = decode_signature_envelope(&enc).unwrap();
+        assert_eq!(dec.scheme_id, SCHEME_ML_DSA_65);
+        assert_eq!(dec.public_key, pk);
+        assert_eq!(dec.signature, sig);
+    }
+
+    #[test]
+    fn abi_envelope_head_matches_solidity_layout() {
+        // abi.encode(uint8, bytes, bytes): three 32-byte head words
+        // (scheme, offset(pk), offset(sig)) followed by length-prefixed tails.
+        let (pk, sig) = sample();
+        let enc = encode_signature_envelope(&pk, &sig, SCHEME_ML_DSA_65).unwrap();
+        let word = |i: usize| &enc[i * 32..(i + 1) * 32];
+        assert_eq!(word(0)[31], SCHEME_ML_DSA_65);
+        assert_eq!(word(1)[30..], [0x00, 0x60]);
+        let pk_padded = PUBLIC_KEY_LEN.div_ceil(32) * 32;
+        let sig_offset = 0x60 + 32 + pk_padded;
+        assert_eq!(
+            u16::from_be_bytes([word(2)[30], word(2)[31]]) as usize,
+            sig_offset
+        );
+        let sig_padded = SIGNATURE_LEN.div_ceil(32) * 32;
+        assert_eq!(enc.len(), sig_offset + 32 + sig_padded);
+    }
+
+    #[test]
+    fn packed_envelope_round_trips() {
+        let (pk, sig) = sample();
+        let enc = encode_signature_envelope_packed(&pk, &sig).unwrap();
+        assert_eq!(enc.len(), 5261);
+        assert_eq!(
+            decode_signature_envelope_packed(&enc).unwrap().signature,
+            sig
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_lengths_and_unknown_scheme() {
+        let (pk, sig) = sample();
+        assert!(encode_signature_envelope(&pk[1..], &sig, SCHEME_ML_DSA_65).is_err());
+        assert!(matches!(
+            encode_signature_envelope(&pk, &sig, 0xEE),
+            Err(WalletError::UnknownScheme(0xEE))
+        ));
+        assert!(decode_signature_envelope(&[0u8; 64]).is_err());
+        assert!(decode_signature_envelope_packed(&pk).is_err());
+    }
+
+    #[test]
+    fn versioned_envelopes_round_trip_and_refuse_unknown_versions() {
+        let (pk, sig) = sample();
+        let pq = SignatureEnvelope {
+            scheme_id: SCHEME_ML_DSA_65,
+            public_key: pk.clone(),
+            signature: sig.clone(),
+        };
+        let v1 = Envelope::V1(pq.clone());
+        let enc = v1.encode().unwrap();
+        assert_eq!(enc[0], ENVELOPE_V1);
+        assert_eq!(
+            enc[1..],
+            encode_signature_envelope(&pk, &sig, SCHEME_ML_DSA_65).unwrap()
+        );
+        assert_eq!(Envelope::decode(&enc).unwrap(), v1);
+
+        let v2 = Envelope::V2(EnvelopeV2 {
+            pq,
+            ecdsa_signature: vec![0x1b; ECDSA_SIGNATURE_LEN],
+        });
+        let enc = v2.encode().unwrap();
+        assert_eq!(Envelope::decode(&enc).unwrap(), v2);
+        let mut trailing = enc.clone();
+        trailing.extend([0; 32]);
+        assert!(matches!(
+            Envelope::decode(&trailing),
+            Err(WalletError::InvalidEnvelope(_))
+        ));
+
+        let mut future = enc;
+        future[0] = LATEST_ENVELOPE_VERSION + 1;
+        assert!(matches!(
+            Envelope::decode(&future),
+            Err(WalletError::UnsupportedEnvelopeVersion(4))
+        ));
+        // An unversioned ABI envelope is refused, not read as garbage.
+        let unversioned = encode_signature_envelope(&pk, &sig, SCHEME_ML_DSA_65).unwrap();
+        assert!(matches!(
+            Envelope::decode(&unversioned),
+            Err(WalletError::UnsupportedEnvelopeVersion(0))
+        ));
+    }
+
+    #[test]
+    fn delegated_envelope_round_trips_and_binds_the_robot_key() {
+        let (pk, sig) = sample();
+        let delegation = Delegation {
+            account: Address::repeat_byte(0x11),
+            chain_id: 421614,
+            robot_key_hash: keccak256(&pk),
+            valid_after: 1_700_000_000,
+            valid_until: 1_700_086_400,
+            policy_hash: B256::ZERO,
+        };
+        let v3 = Envelope::V3(EnvelopeV3 {
+            delegation,
+            owner_signature: sig.clone(),
+            pq: SignatureEnvelope {
+                scheme_id: SCHEME_ML_DSA_65,
+                public_key: pk.clone(),
+                signature: sig.clone(),
+            },
+        });
+        let enc = v3.encode().unwrap();
+        assert_eq!(enc[0], ENVELOPE_V3);
+        // The static struct is inline: six head words, then three offsets.
+        assert_eq!(enc[1 + 12..1 + 32], [0x11; 20]);
+        assert_eq!(Envelope::decode(&enc).unwrap(), v3);
+
+        let other = Delegation {
+            chain_id: 1,
+            ..delegation
+        };
+        assert_ne!(other.hash(), delegation.hash());
+        let mut swapped = v3.clone();
+        if let Envelope::V3(env) = &mut swapped {
+            env.pq.public_key[0] ^= 1;
+        }
+        assert!(matches!(
+            swapped.encode(),
+            Err(WalletError::InvalidEnvelope(_))
+        ));
+    }
+}
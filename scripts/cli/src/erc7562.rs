@@ -0,0 +1,596 @@
+//! ERC-7562 validation-rule checks, run locally before a bundler does.
+//!
+//! Bundlers refuse ops whose validation touches state that could change
+//! between simulation and inclusion, and report little more than "opcode
+//! banned". [`check`] traces the same simulation [`crate::simulate`] runs
+//! (`handleOps`, or `simulateValidation` with the simulations bytecode)
+//! through `debug_traceCall` with a small JS tracer, then names the rule each
+//! entity (factory, account, paymaster) breaks, in which contract and why.
+//! The tracer only reports the steps the rules look at, so a trace through an
+//! ML-DSA verifier stays small.
+//!
+//! Checked:
+//!
+//! - OP-011: block-environment opcodes (`TIMESTAMP`, `NUMBER`, `BASEFEE`,
+//!   `ORIGIN`, ...) and `SELFDESTRUCT`/`INVALID`.
+//! - OP-012: `GAS` not immediately followed by a call.
+//! - OP-031/OP-032: `CREATE2` outside the factory or more than once;
+//!   `CREATE` outside a sender being deployed.
+//! - OP-041: `EXTCODESIZE`/`EXTCODEHASH` of an address without code, other
+//!   than the sender.
+//! - OP-054: calls into the EntryPoint other than `depositTo` and the
+//!   fallback.
+//! - OP-061: value transfers to anything but the EntryPoint.
+//! - OP-080: `BALANCE`/`SELFBALANCE` from an unstaked entity.
+//! - STO-010/021/031/032/033: storage outside the sender's own and
+//!   associated slots, and an unstaked factory or paymaster's own storage.
+//!
+//! "Staked" means `getDepositInfo` reports a stake with at least a day's
+//! unstake delay; the chain's minimum stake value is not checked.
+//!
+//! The node must support JS tracers in `debug_traceCall` (geth, Anvil).
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use alloy_primitives::{Address, B256, Bytes, U256};
+use alloy_sol_types::SolCall;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, WalletError};
+use crate::rpc::Transport;
+use crate::simulate;
+use crate::userop::PackedUserOperation;
+
+mod calls {
+    alloy_sol_types::sol! {
+        struct PackedUserOperation {
+            address sender;
+            uint256 nonce;
+            bytes initCode;
+            bytes callData;
+            bytes32 accountGasLimits;
+            uint256 preVerificationGas;
+            bytes32 gasFees;
+            bytes paymasterAndData;
+            bytes signature;
+        }
+
+        struct DepositInfo {
+            uint256 deposit;
+            bool staked;
+            uint112 stake;
+            uint32 unstakeDelaySec;
+            uint48 withdrawTime;
+        }
+
+        function getDepositInfo(address account) returns (DepositInfo info);
+        function depositTo(address account);
+        function validateUserOp(PackedUserOperation userOp, bytes32 userOpHash, uint256 missingAccountFunds) returns (uint256 validationData);
+        function validatePaymasterUserOp(PackedUserOperation userOp, bytes32 userOpHash, uint256 maxCost) returns (bytes context, uint256 validationData);
+        function createSender(bytes initCode) returns (address sender);
+    }
+}
+
+use calls::{
+    createSenderCall, depositToCall, getDepositInfoCall, validatePaymasterUserOpCall,
+    validateUserOpCall,
+};
+
+/// ERC-7562's MIN_UNSTAKE_DELAY.
+pub const MIN_UNSTAKE_DELAY: u32 = 86_400;
+
+/// Slots past `keccak256(A || x)` that still count as associated with `A`.
+const ASSOCIATED_OFFSET: u64 = 128;
+
+/// `debug_traceCall` tracer: one event per step the rules look at, in
+/// validation frames (the EntryPoint's own calls are kept to tell which
+/// entity is being validated).
+pub const TRACER: &str = r#"{
+  events: [],
+  pending: null,
+  watched: {
+    GASPRICE: 1, GASLIMIT: 1, DIFFICULTY: 1, PREVRANDAO: 1, TIMESTAMP: 1,
+    BASEFEE: 1, BLOCKHASH: 1, NUMBER: 1, ORIGIN: 1, COINBASE: 1,
+    SELFDESTRUCT: 1, INVALID: 1, BLOBHASH: 1, BLOBBASEFEE: 1, GAS: 1,
+    BALANCE: 1, SELFBALANCE: 1, CREATE: 1, CREATE2: 1, EXTCODESIZE: 1,
+    EXTCODEHASH: 1, EXTCODECOPY: 1, SLOAD: 1, SSTORE: 1, KECCAK256: 1,
+    SHA3: 1, CALL: 1, CALLCODE: 1, DELEGATECALL: 1, STATICCALL: 1
+  },
+  hex: function (n) { return "0x" + n.toString(16); },
+  num: function (n) { return parseInt(n.toString()); },
+  slice: function (log, offset, len) {
+    if (len <= 0 || offset + len > log.memory.length()) return "0x";
+    return toHex(log.memory.slice(offset, offset + len));
+  },
+  step: function (log, db) {
+    var op = log.op.toString();
+    if (this.pending !== null) {
+      if (this.pending.op === "GAS") this.pending.next = op;
+      else this.pending.result = this.hex(log.stack.peek(0));
+      this.events.push(this.pending);
+      this.pending = null;
+    }
+    if (this.watched[op] === undefined) return;
+    var depth = log.getDepth();
+    var call = op === "CALL" || op === "CALLCODE" || op === "DELEGATECALL" || op === "STATICCALL";
+    if (depth === 1 && !call) return;
+    var s = log.stack;
+    var e = { op: op, depth: depth, contract: toHex(log.contract.getAddress()) };
+    if (call) {
+      var args = op === "CALL" || op === "CALLCODE" ? 3 : 2;
+      e.to = toHex(toAddress(s.peek(1).toString(16)));
+      if (args === 3) e.value = this.hex(s.peek(2));
+      e.selector = this.slice(log, this.num(s.peek(args)), Math.min(this.num(s.peek(args + 1)), 4));
+    } else if (op === "SLOAD" || op === "SSTORE") {
+      e.slot = this.hex(s.peek(0));
+    } else if (op === "KECCAK256" || op === "SHA3") {
+      if (this.num(s.peek(1)) >= 32) e.word = this.slice(log, this.num(s.peek(0)), 32);
+      this.pending = e;
+    } else if (op === "EXTCODESIZE" || op === "EXTCODEHASH" || op === "EXTCODECOPY" || op === "BALANCE") {
+      e.to = toHex(toAddress(s.peek(0).toString(16)));
+      if (op !== "EXTCODECOPY" && op !== "BALANCE") this.pending = e;
+    } else if (op === "GAS") {
+      this.pending = e;
+    }
+    if (this.pending !== e) this.events.push(e);
+  },
+  fault: function (log, db) {},
+  result: function (ctx, db) {
+    if (this.pending !== null) this.events.push(this.pending);
+    return this.events;
+  }
+}"#;
+
+/// One step reported by [`TRACER`].
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Event {
+    pub op: String,
+    pub depth: u64,
+    /// Whose storage and balance the step sees.
+    pub contract: Address,
+    #[serde(default)]
+    pub to: Option<Address>,
+    #[serde(default)]
+    pub value: Option<U256>,
+    #[serde(default)]
+    pub selector: Option<Bytes>,
+    #[serde(default)]
+    pub slot: Option<U256>,
+    /// First 32 bytes of a `KECCAK256` preimage.
+    #[serde(default)]
+    pub word: Option<B256>,
+    /// What the step left on the stack.
+    #[serde(default)]
+    pub result: Option<U256>,
+    /// For `GAS`: the op that ran next.
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+/// The op's entities, which the rules treat differently.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Entity {
+    Factory,
+    Sender,
+    Paymaster,
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Entity::Factory => "factory",
+            Entity::Sender => "account",
+            Entity::Paymaster => "paymaster",
+        })
+    }
+}
+
+/// A broken rule.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Violation {
+    /// ERC-7562 rule id, e.g. `OP-011`.
+    pub rule: &'static str,
+    pub entity: Entity,
+    /// The contract whose code broke it.
+    pub contract: Address,
+    pub opcode: String,
+    pub detail: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} validation, {} in {}: {}",
+            self.rule, self.entity, self.opcode, self.contract, self.detail
+        )
+    }
+}
+
+/// Who is staked with the EntryPoint.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stakes {
+    pub factory: bool,
+    pub sender: bool,
+    pub paymaster: bool,
+}
+
+impl Stakes {
+    fn of(&self, entity: Entity) -> bool {
+        match entity {
+            Entity::Factory => self.factory,
+            Entity::Sender => self.sender,
+            Entity::Paymaster => self.paymaster,
+        }
+    }
+}
+
+fn factory(op: &PackedUserOperation) -> Option<Address> {
+    (op.init_code.len() >= 20).then(|| Address::from_slice(&op.init_code[..20]))
+}
+
+fn paymaster(op: &PackedUserOperation) -> Option<Address> {
+    (op.paymaster_and_data.len() >= 20).then(|| Address::from_slice(&op.paymaster_and_data[..20]))
+}
+
+/// Trace `op`'s validation on `rpc` and list the rules it breaks, worst
+/// first by entity. With `simulations_code` the trace runs
+/// `simulateValidation`, as [`simulate::simulate_validation`] does.
+pub fn check<T: Transport>(
+    rpc: &T,
+    op: &PackedUserOperation,
+    entry_point: Address,
+    simulations_code: Option<&[u8]>,
+) -> Result<Vec<Violation>> {
+    let (data, overrides) = match simulations_code {
+        Some(code) => (
+            simulate::abi::simulateValidationCall { userOp: op.into() }.abi_encode(),
+            serde_json::json!({
+                entry_point.to_string(): { "code": Bytes::copy_from_slice(code) }
+            }),
+        ),
+        None => (
+            simulate::encode_handle_ops(std::slice::from_ref(op), op.sender),
+            serde_json::json!({}),
+        ),
+    };
+    let events: Vec<Event> = rpc
+        .call(
+            "debug_traceCall",
+            serde_json::json!([
+                { "to": entry_point, "data": Bytes::from(data) },
+                "latest",
+                { "tracer": TRACER, "stateOverrides": overrides },
+            ]),
+        )
+        .map_err(|e| match e {
+            WalletError::RpcError { code: -32601, .. } => WalletError::Rpc(
+                "the node does not serve debug_traceCall; run Anvil or geth with the debug API"
+                    .into(),
+            ),
+            e => e,
+        })?;
+    let stakes = Stakes {
+        factory: factory(op).map_or(Ok(false), |a| is_staked(rpc, entry_point, a))?,
+        sender: is_staked(rpc, entry_point, op.sender)?,
+        paymaster: paymaster(op).map_or(Ok(false), |a| is_staked(rpc, entry_point, a))?,
+    };
+    Ok(analyze(op, entry_point, &events, stakes))
+}
+
+/// Whether `account` has a stake locked for at least [`MIN_UNSTAKE_DELAY`].
+pub fn is_staked<T: Transport>(rpc: &T, entry_point: Address, account: Address) -> Result<bool> {
+    let data = getDepositInfoCall { account }.abi_encode();
+    let ret: Bytes = rpc.call(
+        "eth_call",
+        serde_json::json!([{ "to": entry_point, "data": Bytes::from(data) }, "latest"]),
+    )?;
+    let info = getDepositInfoCall::abi_decode_returns(&ret)
+        .map_err(|e| WalletError::Rpc(format!("getDepositInfo: undecodable result: {e}")))?;
+    Ok(info.staked && !info.stake.is_zero() && info.unstakeDelaySec >= MIN_UNSTAKE_DELAY)
+}
+
+/// Apply the rules to a trace from [`TRACER`].
+pub fn analyze(
+    op: &PackedUserOperation,
+    entry_point: Address,
+    events: &[Event],
+    stakes: Stakes,
+) -> Vec<Violation> {
+    let factory = factory(op);
+    let paymaster = paymaster(op);
+    let sender = U256::from_be_slice(op.sender.as_slice());
+    // keccak256 results with the first word of their preimage.
+    let mut hashes: Vec<(U256, B256)> = Vec::new();
+    let mut violations = BTreeSet::new();
+    let mut phase = None;
+    let mut create2 = 0;
+
+    for e in events {
+        // The EntryPoint calls each entity from the top frame.
+        if e.depth == 1 {
+            let selector: &[u8] = e.selector.as_deref().map_or(&[], |s| s);
+            phase = if selector == validateUserOpCall::SELECTOR {
+                Some(Entity::Sender)
+            } else if selector == validatePaymasterUserOpCall::SELECTOR {
+                Some(Entity::Paymaster)
+            } else if selector == createSenderCall::SELECTOR {
+                Some(Entity::Factory)
+            } else {
+                None
+            };
+            continue;
+        }
+        let Some(entity) = phase else { continue };
+        let staked = stakes.of(entity);
+        let mut flag = |rule, detail: String| {
+            violations.insert(Violation {
+                rule,
+                entity,
+                contract: e.contract,
+                opcode: e.op.clone(),
+                detail,
+            });
+        };
+
+        match e.op.as_str() {
+            "GASPRICE" | "GASLIMIT" | "DIFFICULTY" | "PREVRANDAO" | "TIMESTAMP" | "BASEFEE"
+            | "BLOCKHASH" | "NUMBER" | "ORIGIN" | "COINBASE" | "BLOBHASH" | "BLOBBASEFEE" => flag(
+                "OP-011",
+                "banned during validation: the value at inclusion differs from simulation".into(),
+            ),
+            "SELFDESTRUCT" | "INVALID" => flag("OP-011", "banned during validation".into()),
+            "GAS" => match e.next.as_deref() {
+                Some("CALL" | "CALLCODE" | "DELEGATECALL" | "STATICCALL") => {}
+                next => flag(
+                    "OP-012",
+                    format!(
+                        "GAS may only feed a call, but is followed by {}",
+                        next.unwrap_or("nothing")
+                    ),
+                ),
+            },
+            "BALANCE" | "SELFBALANCE" if !staked => flag(
+                "OP-080",
+                format!("reading balances needs the {entity} to be staked"),
+            ),
+            "CREATE2" => {
+                create2 += 1;
+                if entity != Entity::Factory {
+                    flag("OP-031", "only the factory may CREATE2".into());
+                } else if create2 > 1 {
+                    flag("OP-031", "the factory may CREATE2 only once".into());
+                }
+            }
+            "CREATE" if !(entity == Entity::Sender && factory.is_some()) => flag(
+                "OP-032",
+                "CREATE is only allowed to the account while its factory deploys it".into(),
+            ),
+            "EXTCODESIZE" | "EXTCODEHASH"
+                if e.to != Some(op.sender) && e.result == Some(U256::ZERO) =>
+            {
+                flag(
+                    "OP-041",
+                    format!(
+                        "{} has no code; its code could be deployed before inclusion",
+                        e.to.unwrap_or_default()
+                    ),
+                )
+            }
+            "CALL" | "CALLCODE" | "DELEGATECALL" | "STATICCALL" => {
+                let to = e.to.unwrap_or_default();
+                let selector: &[u8] = e.selector.as_deref().map_or(&[], |s| s);
+                if to == entry_point && !selector.is_empty() && selector != depositToCall::SELECTOR
+                {
+                    flag(
+                        "OP-054",
+                        format!(
+                            "calls the EntryPoint with selector {}; only depositTo and the \
+                             fallback are allowed",
+                            Bytes::copy_from_slice(selector)
+                        ),
+                    );
+                }
+                if let Some(value) = e.value.filter(|v| !v.is_zero())
+                    && to != entry_point
+                {
+                    flag(
+                        "OP-061",
+                        format!("sends {value} wei to {to}; only the EntryPoint may get value"),
+                    );
+                }
+            }
+            "KECCAK256" | "SHA3" => {
+                if let (Some(word), Some(hash)) = (e.word, e.result) {
+                    hashes.push((hash, word));
+                }
+            }
+            "SLOAD" | "SSTORE" => {
+                let slot = e.slot.unwrap_or_default();
+                let write = e.op == "SSTORE";
+                let own = match entity {
+                    Entity::Factory => factory,
+                    Entity::Sender => Some(op.sender),
+                    Entity::Paymaster => paymaster,
+                };
+                let owner = |a: Address| U256::from_be_slice(a.as_slice());
+                if e.contract == op.sender || associated(slot, sender, &hashes) {
+                    // STO-010, STO-021.
+                } else if let Some(own) = own.filter(|&own| {
+                    own != op.sender && (e.contract == own || associated(slot, owner(own), &hashes))
+                }) {
+                    if !staked {
+                        flag(
+                            "STO-031",
+                            format!(
+                                "slot {slot:#x} belongs to the {entity} ({own}); using its own \
+                                 storage needs the {entity} to be staked"
+                            ),
+                        );
+                    }
+                } else if staked && !write {
+                    // STO-032: staked entities may read anything.
+                } else {
+                    let mut detail = format!(
+                        "slot {slot:#x} of {} is not associated with the account",
+                        e.contract
+                    );
+                    if nested(slot, sender, &hashes) {
+                        detail.push_str(
+                            "; it is derived from a slot that is (dynamic bytes or a nested \
+                             mapping under the account's key), but only keccak256(account || x) \
+                             + n for n <= 128 counts, so keep the data at fixed offsets from \
+                             keccak256(account || x)",
+                        );
+                    } else if !staked {
+                        detail.push_str(&format!("; staking the {entity} would allow reading it"));
+                    }
+                    flag("STO-033", detail);
+                }
+            }
+            _ => {}
+        }
+    }
+    violations.into_iter().collect()
+}
+
+/// ERC-7562 associated storage: `A` itself, or `keccak256(A || x) + n`.
+fn associated(slot: U256, owner: U256, hashes: &[(U256, B256)]) -> bool {
+    slot == owner
+        || hashes.iter().any(|&(hash, word)| {
+            U256::from_be_bytes(word.0) == owner && in_offset(slot, hash, ASSOCIATED_OFFSET)
+        })
+}
+
+/// A slot one hash removed from an associated one, like the data of
+/// `mapping(address => bytes)`.
+fn nested(slot: U256, owner: U256, hashes: &[(U256, B256)]) -> bool {
+    hashes.iter().any(|&(hash, word)| {
+        in_offset(slot, hash, u64::MAX) && associated(U256::from_be_bytes(word.0), owner, hashes)
+    })
+}
+
+fn in_offset(slot: U256, base: U256, max: u64) -> bool {
+    slot.checked_sub(base).is_some_and(|n| n <= U256::from(max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{address, keccak256};
+
+    const EP: Address = crate::userop::ENTRY_POINT_V07;
+    const SENDER: Address = address!("1111111111111111111111111111111111111111");
+    const VALIDATOR: Address = address!("2222222222222222222222222222222222222222");
+
+    fn op() -> PackedUserOperation {
+        serde_json::from_value(serde_json::json!({
+            "sender": SENDER,
+            "nonce": "0x0",
+            "callData": "0x",
+            "callGasLimit": "0x1",
+            "verificationGasLimit": "0x1",
+            "preVerificationGas": "0x1",
+            "maxFeePerGas": "0x1",
+            "maxPriorityFeePerGas": "0x1",
+            "signature": "0x"
+        }))
+        .unwrap()
+    }
+
+    fn step(op: &str, contract: Address) -> Event {
+        Event {
+            op: op.into(),
+            depth: 3,
+            contract,
+            ..Event::default()
+        }
+    }
+
+    fn enter(selector: [u8; 4], to: Address) -> Event {
+        Event {
+            op: "CALL".into(),
+            depth: 1,
+            contract: EP,
+            to: Some(to),
+            selector: Some(Bytes::copy_from_slice(&selector)),
+            ..Event::default()
+        }
+    }
+
+    #[test]
+    fn flags_environment_opcodes_only_inside_validation() {
+        let events = vec![
+            step("TIMESTAMP", SENDER),
+            enter(validateUserOpCall::SELECTOR, SENDER),
+            step("TIMESTAMP", VALIDATOR),
+            step("TIMESTAMP", VALIDATOR),
+            Event {
+                next: Some("CALL".into()),
+                ..step("GAS", SENDER)
+            },
+            Event {
+                next: Some("ADD".into()),
+                ..step("GAS", VALIDATOR)
+            },
+            Event {
+                to: Some(EP),
+                selector: Some(Bytes::copy_from_slice(&depositToCall::SELECTOR)),
+                value: Some(U256::from(1)),
+                ..step("CALL", SENDER)
+            },
+            enter([0xde, 0xad, 0xbe, 0xef], SENDER),
+            step("NUMBER", SENDER),
+        ];
+        let violations = analyze(&op(), EP, &events, Stakes::default());
+        let rules: Vec<_> = violations.iter().map(|v| (v.rule, v.contract)).collect();
+        assert_eq!(rules, [("OP-011", VALIDATOR), ("OP-012", VALIDATOR)]);
+        assert_eq!(violations[0].entity, Entity::Sender);
+    }
+
+    #[test]
+    fn classifies_validator_storage_by_association() {
+        // mapping(address => bytes) publicKeys at slot 0: the length word
+        // sits at keccak256(sender || 0), the key bytes one hash further.
+        let mut preimage = [0u8; 64];
+        preimage[12..32].copy_from_slice(SENDER.as_slice());
+        let length_slot = keccak256(preimage);
+        let data_slot = keccak256(length_slot);
+        let hashed = |word: B256, hash: B256| Event {
+            word: Some(word),
+            result: Some(hash.into()),
+            ..step("KECCAK256", VALIDATOR)
+        };
+        let sload = |slot: U256| Event {
+            slot: Some(slot),
+            ..step("SLOAD", VALIDATOR)
+        };
+        let data = U256::from_be_bytes(data_slot.0);
+        let events = vec![
+            enter(validateUserOpCall::SELECTOR, SENDER),
+            hashed(B256::left_padding_from(SENDER.as_slice()), length_slot),
+            sload(length_slot.into()),
+            sload(U256::from_be_bytes(length_slot.0) + U256::from(3)),
+            hashed(length_slot, data_slot),
+            sload(data),
+            sload(data + U256::from(60)),
+            sload(U256::from(7)),
+        ];
+        let violations = analyze(&op(), EP, &events, Stakes::default());
+        assert_eq!(violations.len(), 3, "{violations:#?}");
+        assert!(violations.iter().all(|v| v.rule == "STO-033"));
+        let nested = violations
+            .iter()
+            .filter(|v| v.detail.contains("fixed offsets"));
+        assert_eq!(nested.count(), 2);
+
+        // A staked account may read unassociated slots.
+        let stakes = Stakes {
+            sender: true,
+            ..Stakes::default()
+        };
+        assert!(analyze(&op(), EP, &events, stakes).is_empty());
+    }
+}
@@ -0,0 +1,230 @@
+use alloc::string::String;
+use core::fmt;
+
+/// Errors surfaced by the `pq_cli` library.
+///
+/// Binaries generally `expect()` on these with a short context string, so the
+/// `Display` output is written to read well after a colon.
+#[derive(Debug)]
+pub enum WalletError {
+    /// Input was not valid hexadecimal.
+    InvalidHex,
+    /// A byte string had the wrong length for its role.
+    InvalidLength {
+        what: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    /// Signature envelope bytes could not be decoded.
+    InvalidEnvelope(String),
+    /// Scheme ID not known to this build.
+    UnknownScheme(u8),
+    /// Versioned envelope whose version byte this build does not know.
+    UnsupportedEnvelopeVersion(u8),
+    /// An ML-DSA backend reported a failure.
+    Backend(String),
+    /// ACVP vector file could not be loaded or fetched.
+    Acvp(String),
+    /// Filesystem error, with the path involved.
+    #[cfg(feature = "std")]
+    Io(String, std::io::Error),
+    /// Unrecognized digest name.
+    UnknownDigest(String),
+    /// Invalid combination of command-line options.
+    Usage(&'static str),
+    /// Keystore lookup or format problem (missing account, bad name, ...).
+    Keystore(String),
+    /// The keystore account has no seed to sign with.
+    WatchOnly(String),
+    /// Wrong password or corrupted ciphertext.
+    Decrypt,
+    /// An async signing call was cancelled before it returned.
+    Cancelled,
+    /// Key backup is malformed, sealed to another KEM key, or tampered with.
+    Backup(String),
+    /// Key escrow or a released share is malformed, tampered with, or not
+    /// enough to recover.
+    Escrow(String),
+    /// Threshold signing round was malformed, out of order or inconsistent.
+    Threshold(String),
+    /// Signer attestation record is malformed, untrusted, expired or forged.
+    Attestation(String),
+    /// Release manifest is malformed, signed by an untrusted key, or forged.
+    Release(String),
+    /// Deployment registry is malformed, untrusted or forged, or a contract
+    /// it pins holds other code.
+    Registry(String),
+    /// Key ceremony commitment, reveal or transcript is malformed or does
+    /// not match.
+    Ceremony(String),
+    /// Delegation certificate is malformed, forged, expired or does not
+    /// cover the op.
+    Delegation(String),
+    /// Notification config is malformed, or a sink could not be reached.
+    Notify(String),
+    /// A transaction could not be replayed: not a bundle, or not found.
+    Replay(String),
+    /// Sign-In with Ethereum message is malformed, or a session is not
+    /// valid for this service.
+    Siwe(String),
+    /// Proposal store is unreadable, or a decision on a proposal is not
+    /// valid: unknown or closed proposal, key not an approver, bad
+    /// signature.
+    Proposal(String),
+    /// Under dual control, the op was parked as the given proposal until
+    /// an approver decides it.
+    PendingApproval(alloy_primitives::B256),
+    /// An account's chain profile is inconsistent, or a signature was
+    /// asked for in another construction than the profile expects.
+    Profile(String),
+    /// Signing policy file could not be read or parsed.
+    Policy(String),
+    /// Audit log could not be read, or its hash chain is broken.
+    Audit(String),
+    /// `pq-wallet.toml` could not be read or names an unknown network.
+    Config(String),
+    /// A value has no RFC 8785 canonical JSON form (e.g. an integer beyond
+    /// 2^53).
+    Canonical(String),
+    /// Batch manifest could not be parsed.
+    Manifest(String),
+    /// Verification cache file is unreadable or not a cache.
+    VerifyCache(String),
+    /// Multi-account operation plan could not be read or parsed.
+    Plan(String),
+    /// Address book could not be read, or has no such contact.
+    Contacts(String),
+    /// An ENS name did not resolve, or resolved inconsistently.
+    Ens(String),
+    /// The queue of signed ops could not be read or updated.
+    Queue(String),
+    /// An end-to-end run against a local node failed a step or an assertion.
+    E2e(String),
+    /// UserOperation fields are inconsistent or out of range.
+    InvalidUserOp(String),
+    /// Legacy ECDSA key or signature problem (EIP-7702 authorizations).
+    Ecdsa(String),
+    /// Ledger transport failure or device error.
+    #[cfg(feature = "ledger")]
+    Ledger(String),
+    /// Passkey assertion is malformed or was not made over the userOpHash.
+    WebAuthn(String),
+    /// JSON-RPC transport failure or unexpected response shape.
+    Rpc(String),
+    /// `pq-signerd` turned the caller away.
+    #[cfg(feature = "async")]
+    Refused(crate::auth::Refusal),
+    /// The endpoint answered with a JSON-RPC `error` member.
+    #[cfg(feature = "std")]
+    RpcError {
+        method: String,
+        code: i64,
+        message: String,
+        data: Option<serde_json::Value>,
+    },
+}
+
+impl fmt::Display for WalletError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalletError::InvalidHex => write!(f, "invalid hex"),
+            WalletError::InvalidLength {
+                what,
+                expected,
+                actual,
+            } => write!(f, "{what} must be exactly {expected} bytes (got {actual})"),
+            WalletError::InvalidEnvelope(reason) => {
+                write!(f, "invalid signature envelope: {reason}")
+            }
+            WalletError::UnknownScheme(id) => write!(f, "unknown signature scheme id {id}"),
+            WalletError::UnsupportedEnvelopeVersion(0) => write!(
+                f,
+                "signature envelope has no version byte (it starts with 0x00, like an \
+                 unversioned abi envelope)"
+            ),
+            WalletError::UnsupportedEnvelopeVersion(v) => write!(
+                f,
+                "signature envelope version {v} is newer than this build understands (up to \
+                 {}); upgrade pq-cli",
+                crate::envelope::LATEST_ENVELOPE_VERSION
+            ),
+            WalletError::Backend(reason) => write!(f, "backend error: {reason}"),
+            WalletError::Acvp(reason) => write!(f, "ACVP: {reason}"),
+            #[cfg(feature = "std")]
+            WalletError::Io(path, e) => write!(f, "{path}: {e}"),
+            WalletError::UnknownDigest(name) => {
+                write!(
+                    f,
+                    "unknown digest {name:?} (expected keccak256 or sha3-256)"
+                )
+            }
+            WalletError::Usage(msg) => write!(f, "{msg}"),
+            WalletError::Keystore(reason) => write!(f, "keystore: {reason}"),
+            WalletError::WatchOnly(name) => write!(
+                f,
+                "account {name:?} is watch-only; export for offline signing: \
+                 `pq-userop hash` the op, sign the hash where the seed is \
+                 (`pq-sign --hash ... --json`), then `pq-userop attach --response`"
+            ),
+            WalletError::Ecdsa(reason) => write!(f, "ECDSA: {reason}"),
+            #[cfg(feature = "ledger")]
+            WalletError::Ledger(reason) => write!(f, "ledger: {reason}"),
+            WalletError::WebAuthn(reason) => write!(f, "WebAuthn: {reason}"),
+            WalletError::Rpc(reason) => write!(f, "rpc: {reason}"),
+            #[cfg(feature = "async")]
+            WalletError::Refused(refusal) => write!(f, "refused: {refusal}"),
+            #[cfg(feature = "std")]
+            WalletError::RpcError {
+                method,
+                code,
+                message,
+                data,
+            } => match data {
+                Some(data) => write!(f, "{method}: {message} ({code}) {data}"),
+                None => write!(f, "{method}: {message} ({code})"),
+            },
+            WalletError::InvalidUserOp(reason) => write!(f, "invalid UserOperation: {reason}"),
+            WalletError::Manifest(reason) => write!(f, "invalid manifest: {reason}"),
+            WalletError::VerifyCache(reason) => write!(f, "verification cache: {reason}"),
+            WalletError::Plan(reason) => write!(f, "plan: {reason}"),
+            WalletError::Contacts(reason) => write!(f, "address book: {reason}"),
+            WalletError::Ens(reason) => write!(f, "ENS: {reason}"),
+            WalletError::Queue(reason) => write!(f, "queue: {reason}"),
+            WalletError::E2e(reason) => write!(f, "e2e: {reason}"),
+            WalletError::Backup(reason) => write!(f, "backup: {reason}"),
+            WalletError::Escrow(reason) => write!(f, "escrow: {reason}"),
+            WalletError::Threshold(reason) => write!(f, "threshold: {reason}"),
+            WalletError::Attestation(reason) => write!(f, "attestation: {reason}"),
+            WalletError::Release(reason) => write!(f, "release manifest: {reason}"),
+            WalletError::Registry(reason) => write!(f, "registry: {reason}"),
+            WalletError::Ceremony(reason) => write!(f, "key ceremony: {reason}"),
+            WalletError::Delegation(reason) => write!(f, "delegation: {reason}"),
+            WalletError::Notify(reason) => write!(f, "notify: {reason}"),
+            WalletError::Replay(reason) => write!(f, "replay: {reason}"),
+            WalletError::Siwe(reason) => write!(f, "siwe: {reason}"),
+            WalletError::Proposal(reason) => write!(f, "proposal: {reason}"),
+            WalletError::PendingApproval(id) => {
+                write!(f, "op needs a second operator's approval: proposal {id}")
+            }
+            WalletError::Profile(reason) => write!(f, "profile: {reason}"),
+            WalletError::Policy(reason) => write!(f, "policy: {reason}"),
+            WalletError::Audit(reason) => write!(f, "audit log: {reason}"),
+            WalletError::Config(reason) => write!(f, "config: {reason}"),
+            WalletError::Canonical(reason) => write!(f, "canonical JSON: {reason}"),
+            WalletError::Decrypt => write!(f, "wrong password or corrupted keystore entry"),
+            WalletError::Cancelled => write!(f, "signing cancelled"),
+        }
+    }
+}
+
+impl core::error::Error for WalletError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            WalletError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, WalletError>;
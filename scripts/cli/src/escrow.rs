@@ -0,0 +1,656 @@
+//! Seed escrow to a guardian set, for recovery without a seed phrase.
+//!
+//! [`Escrow::deposit`] encrypts the 32-byte ML-DSA seed with ChaCha20-Poly1305
+//! under a fresh content key, splits that key with [`shamir`] into one share
+//! per guardian, and seals each share to the guardian's ML-KEM-768 key the
+//! way [`crate::backup`] seals a seed, under `keccak256("pq-wallet/escrow/v1"
+//! || sharedSecret)`. Any `threshold` guardians together recover the seed;
+//! fewer learn nothing about it, and no guardian ever holds it alone.
+//!
+//! Guardians do not hand their KEM secrets over. Each one
+//! [`release`](Escrow::release)s their share re-sealed to the recovering
+//! device's KEM key, and [`Escrow::recover`] opens the releases there. Every
+//! share carries a keccak256 commitment in the escrow, so a wrong or
+//! tampered share is named rather than silently producing a wrong key. The
+//! escrow's [`id`](Escrow::id) covers the account, the threshold and the
+//! guardian list and is bound into every ciphertext as associated data.
+//!
+//! A recovery ends with a [`SignedTranscript`]: which escrow was opened,
+//! with which guardians' shares and when, signed by the recovered key. It
+//! complements on-chain social recovery for a user who lost every device
+//! but still controls the account's key through their guardians.
+
+use alloy_primitives::{Address, B256, Bytes, keccak256};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ml_kem::array::Array;
+use ml_kem::{Decapsulate, Encapsulate, ml_kem_768};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::backend::Backend;
+use crate::backup::{self, KEM_CIPHERTEXT_LEN, KEM_ML_KEM_768, KEM_PUBLIC_KEY_LEN, KEM_SEED_LEN};
+use crate::canonical_json;
+use crate::ct;
+use crate::error::{Result, WalletError};
+use crate::keys::{self, SEED_LEN};
+use crate::keystore::SCHEME_ML_DSA_65;
+use crate::shamir::{self, Share};
+use crate::signer::PqSigner;
+
+pub const ESCROW_VERSION: u32 = 1;
+/// Domain separator for the keys shares are sealed to guardians under.
+pub const WRAP_DOMAIN: &[u8] = b"pq-wallet/escrow/v1";
+/// Domain separator for the keys released shares are sealed under.
+pub const RELEASE_DOMAIN: &[u8] = b"pq-wallet/escrow-release/v1";
+/// Domain separator for share commitments.
+pub const COMMITMENT_DOMAIN: &[u8] = b"pq-wallet/escrow-share/v1";
+/// Domain separator for the signed recovery transcript.
+pub const TRANSCRIPT_DOMAIN: &[u8] = b"pq-wallet/escrow-transcript/v1";
+
+/// Length of the content key the shares split.
+const CEK_LEN: usize = 32;
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::Escrow(reason.into())
+}
+
+fn wrapping_key(domain: &[u8], shared: &[u8]) -> Zeroizing<[u8; 32]> {
+    let mut preimage = Zeroizing::new(domain.to_vec());
+    preimage.extend_from_slice(shared);
+    Zeroizing::new(keccak256(preimage.as_slice()).0)
+}
+
+fn encrypt(key: &[u8], nonce: &[u8; 12], msg: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(Nonce::from_slice(nonce), Payload { msg, aad })
+        .map_err(|_| err("encryption failed"))
+}
+
+fn decrypt(key: &[u8], nonce: &[u8], msg: &[u8], aad: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    let nonce: [u8; 12] = nonce.try_into().map_err(|_| err("nonce is not 12 bytes"))?;
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(&nonce), Payload { msg, aad })
+        .map(Zeroizing::new)
+        .map_err(|_| err("does not decrypt (corrupted or tampered)"))
+}
+
+/// A message sealed to one ML-KEM-768 key.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sealed {
+    /// ML-KEM-768 ciphertext.
+    pub encapsulation: Bytes,
+    pub nonce: Bytes,
+    /// The message plus a 16-byte tag.
+    pub ciphertext: Bytes,
+}
+
+impl Sealed {
+    fn seal(
+        domain: &[u8],
+        recipient: &[u8; KEM_PUBLIC_KEY_LEN],
+        msg: &[u8],
+        aad: &[u8],
+    ) -> Result<Self> {
+        let ek = ml_kem_768::EncapsulationKey::new(&Array::from(*recipient))
+            .map_err(|_| err("not an ML-KEM-768 encapsulation key"))?;
+        let (encapsulation, shared) = ek.encapsulate_with_rng(&mut rand::rng());
+        let nonce: [u8; 12] = rand::random();
+        let ciphertext = encrypt(wrapping_key(domain, &shared).as_ref(), &nonce, msg, aad)?;
+        Ok(Sealed {
+            encapsulation: Bytes::copy_from_slice(&encapsulation),
+            nonce: Bytes::copy_from_slice(&nonce),
+            ciphertext: ciphertext.into(),
+        })
+    }
+
+    fn open(
+        &self,
+        domain: &[u8],
+        kem_seed: &[u8; KEM_SEED_LEN],
+        aad: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>> {
+        let encapsulation: [u8; KEM_CIPHERTEXT_LEN] = self.encapsulation[..]
+            .try_into()
+            .map_err(|_| err("KEM ciphertext is not 1088 bytes"))?;
+        let dk = ml_kem_768::DecapsulationKey::from_seed(Array::from(*kem_seed));
+        let shared = dk.decapsulate(&Array::from(encapsulation));
+        decrypt(
+            wrapping_key(domain, &shared).as_ref(),
+            &self.nonce,
+            &self.ciphertext,
+            aad,
+        )
+    }
+}
+
+/// A guardian to deposit with.
+#[derive(Clone, Debug)]
+pub struct Guardian {
+    pub label: String,
+    pub kem_public_key: [u8; KEM_PUBLIC_KEY_LEN],
+}
+
+/// One guardian's share, sealed to their KEM key.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GuardianShare {
+    /// The share's evaluation point, 1..=255.
+    pub index: u8,
+    pub label: String,
+    /// [`backup::kem_fingerprint`] of the guardian's encapsulation key.
+    pub guardian: B256,
+    /// keccak256(domain ‖ index ‖ share).
+    pub commitment: B256,
+    #[serde(flatten)]
+    pub sealed: Sealed,
+}
+
+fn commitment(share: &Share) -> B256 {
+    let mut preimage = Zeroizing::new(COMMITMENT_DOMAIN.to_vec());
+    preimage.push(share.index);
+    preimage.extend_from_slice(&share.value);
+    keccak256(preimage.as_slice())
+}
+
+/// What [`Escrow::id`] covers: everything but the ciphertexts.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Header<'a> {
+    version: u32,
+    kem: &'a str,
+    name: &'a str,
+    scheme: &'a str,
+    public_key: &'a Bytes,
+    threshold: u8,
+    guardians: Vec<HeaderGuardian<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HeaderGuardian<'a> {
+    index: u8,
+    label: &'a str,
+    guardian: B256,
+    commitment: B256,
+}
+
+/// A seed escrowed to a guardian set, as written by `pq-escrow deposit`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Escrow {
+    pub version: u32,
+    pub kem: String,
+    pub cipher: String,
+    /// Account name on the depositing device, suggested for the recovery.
+    pub name: String,
+    pub scheme: String,
+    /// The ML-DSA-65 public key the seed derives.
+    pub public_key: Bytes,
+    /// Shares needed to recover.
+    pub threshold: u8,
+    pub guardians: Vec<GuardianShare>,
+    /// The seed under the content key.
+    pub nonce: Bytes,
+    pub ciphertext: Bytes,
+}
+
+impl Escrow {
+    /// Escrow `seed` to `guardians`, any `threshold` of whom can recover it.
+    pub fn deposit(
+        name: &str,
+        seed: &[u8; SEED_LEN],
+        threshold: u8,
+        guardians: &[Guardian],
+    ) -> Result<Self> {
+        let count = u8::try_from(guardians.len()).map_err(|_| err("at most 255 guardians"))?;
+        let fingerprints: Vec<B256> = guardians
+            .iter()
+            .map(|g| backup::kem_fingerprint(&g.kem_public_key))
+            .collect();
+        if let Some(i) =
+            (1..fingerprints.len()).find(|&i| fingerprints[..i].contains(&fingerprints[i]))
+        {
+            return Err(err(format!(
+                "guardian {} is listed twice",
+                guardians[i].label
+            )));
+        }
+        let cek = Zeroizing::new(rand::random::<[u8; CEK_LEN]>());
+        let shares = shamir::split(cek.as_ref(), threshold, count)?;
+        let public_key = keys::keygen_from_seed(seed).verifying_key().encode();
+        let mut escrow = Escrow {
+            version: ESCROW_VERSION,
+            kem: KEM_ML_KEM_768.to_string(),
+            cipher: "chacha20poly1305".to_string(),
+            name: name.to_string(),
+            scheme: SCHEME_ML_DSA_65.to_string(),
+            public_key: Bytes::copy_from_slice(&public_key),
+            threshold,
+            guardians: guardians
+                .iter()
+                .zip(&shares)
+                .zip(fingerprints)
+                .map(|((g, share), guardian)| GuardianShare {
+                    index: share.index,
+                    label: g.label.clone(),
+                    guardian,
+                    commitment: commitment(share),
+                    sealed: Sealed {
+                        encapsulation: Bytes::new(),
+                        nonce: Bytes::new(),
+                        ciphertext: Bytes::new(),
+                    },
+                })
+                .collect(),
+            nonce: Bytes::new(),
+            ciphertext: Bytes::new(),
+        };
+        let id = escrow.id()?;
+        for ((entry, share), g) in escrow.guardians.iter_mut().zip(&shares).zip(guardians) {
+            entry.sealed = Sealed::seal(
+                WRAP_DOMAIN,
+                &g.kem_public_key,
+                &share.value,
+                &share_aad(id, share.index),
+            )?;
+        }
+        let nonce: [u8; 12] = rand::random();
+        escrow.ciphertext = encrypt(cek.as_ref(), &nonce, seed, id.as_slice())?.into();
+        escrow.nonce = Bytes::copy_from_slice(&nonce);
+        Ok(escrow)
+    }
+
+    /// keccak256 of the canonical JSON of the version, KEM, account,
+    /// threshold and guardian list.
+    pub fn id(&self) -> Result<B256> {
+        let header = Header {
+            version: self.version,
+            kem: &self.kem,
+            name: &self.name,
+            scheme: &self.scheme,
+            public_key: &self.public_key,
+            threshold: self.threshold,
+            guardians: self
+                .guardians
+                .iter()
+                .map(|g| HeaderGuardian {
+                    index: g.index,
+                    label: &g.label,
+                    guardian: g.guardian,
+                    commitment: g.commitment,
+                })
+                .collect(),
+        };
+        Ok(keccak256(canonical_json::to_vec(&header)?))
+    }
+
+    pub fn key_id(&self) -> Address {
+        keys::address(&self.public_key)
+    }
+
+    fn check_version(&self) -> Result<()> {
+        if self.version != ESCROW_VERSION || self.kem != KEM_ML_KEM_768 {
+            return Err(err(format!(
+                "unsupported escrow v{} ({})",
+                self.version, self.kem
+            )));
+        }
+        Ok(())
+    }
+
+    fn guardian(&self, fingerprint: B256) -> Result<&GuardianShare> {
+        self.guardians
+            .iter()
+            .find(|g| g.guardian == fingerprint)
+            .ok_or_else(|| err(format!("{fingerprint} is not a guardian of this escrow")))
+    }
+
+    fn check_share(&self, entry: &GuardianShare, value: Zeroizing<Vec<u8>>) -> Result<Share> {
+        let share = Share {
+            index: entry.index,
+            value,
+        };
+        if share.value.len() != CEK_LEN
+            || !ct::eq(commitment(&share).as_slice(), entry.commitment.as_slice())
+        {
+            return Err(err(format!(
+                "share {} from {} does not match its commitment",
+                entry.index, entry.label
+            )));
+        }
+        Ok(share)
+    }
+
+    /// Open the share sealed to the guardian with `kem_seed`.
+    pub fn open_share(&self, kem_seed: &[u8; KEM_SEED_LEN]) -> Result<Share> {
+        self.check_version()?;
+        let entry = self.guardian(backup::kem_fingerprint(&backup::kem_public_key(kem_seed)))?;
+        let value = entry
+            .sealed
+            .open(WRAP_DOMAIN, kem_seed, &share_aad(self.id()?, entry.index))
+            .map_err(|e| err(format!("share {}: {e}", entry.index)))?;
+        self.check_share(entry, value)
+    }
+
+    /// As the guardian with `kem_seed`, re-seal their share to the
+    /// recovering device's KEM key.
+    pub fn release(
+        &self,
+        kem_seed: &[u8; KEM_SEED_LEN],
+        recipient: &[u8; KEM_PUBLIC_KEY_LEN],
+    ) -> Result<ReleasedShare> {
+        let share = self.open_share(kem_seed)?;
+        let entry = self.guardian(backup::kem_fingerprint(&backup::kem_public_key(kem_seed)))?;
+        let id = self.id()?;
+        Ok(ReleasedShare {
+            version: ESCROW_VERSION,
+            escrow: id,
+            index: entry.index,
+            guardian: entry.guardian,
+            recipient: backup::kem_fingerprint(recipient),
+            sealed: Sealed::seal(
+                RELEASE_DOMAIN,
+                recipient,
+                &share.value,
+                &release_aad(id, entry.index, entry.guardian),
+            )?,
+        })
+    }
+
+    /// Open a guardian's release with the recovering device's `kem_seed`.
+    pub fn open_release(
+        &self,
+        released: &ReleasedShare,
+        kem_seed: &[u8; KEM_SEED_LEN],
+    ) -> Result<Share> {
+        self.check_version()?;
+        let id = self.id()?;
+        if released.escrow != id {
+            return Err(err(format!(
+                "share {} was released from escrow {}, not {id}",
+                released.index, released.escrow
+            )));
+        }
+        if released.recipient != backup::kem_fingerprint(&backup::kem_public_key(kem_seed)) {
+            return Err(err(format!(
+                "share {} is released to {}, not this KEM key",
+                released.index, released.recipient
+            )));
+        }
+        let entry = self.guardian(released.guardian)?;
+        if entry.index != released.index {
+            return Err(err(format!(
+                "{} holds share {}, not {}",
+                entry.label, entry.index, released.index
+            )));
+        }
+        let value = released
+            .sealed
+            .open(
+                RELEASE_DOMAIN,
+                kem_seed,
+                &release_aad(id, entry.index, entry.guardian),
+            )
+            .map_err(|e| err(format!("share {}: {e}", entry.index)))?;
+        self.check_share(entry, value)
+    }
+
+    /// Recombine the content key from `shares` and decrypt the seed,
+    /// checking it still derives the escrowed public key.
+    pub fn recover(&self, shares: &[Share]) -> Result<Zeroizing<[u8; SEED_LEN]>> {
+        self.check_version()?;
+        if shares.len() < self.threshold as usize {
+            return Err(err(format!(
+                "{} of {} shares needed, {} given",
+                self.threshold,
+                self.guardians.len(),
+                shares.len()
+            )));
+        }
+        for share in shares {
+            let entry = self
+                .guardians
+                .iter()
+                .find(|g| g.index == share.index)
+                .ok_or_else(|| err(format!("no share {}", share.index)))?;
+            self.check_share(entry, share.value.clone())?;
+        }
+        let cek = shamir::combine(shares)?;
+        let plaintext = decrypt(&cek, &self.nonce, &self.ciphertext, self.id()?.as_slice())?;
+        let seed: [u8; SEED_LEN] = plaintext[..]
+            .try_into()
+            .map_err(|_| err("escrowed seed has the wrong length"))?;
+        let seed = Zeroizing::new(seed);
+        let derived = keys::keygen_from_seed(&seed).verifying_key().encode();
+        if !ct::eq(&derived, &self.public_key) {
+            return Err(err("seed does not match public key"));
+        }
+        Ok(seed)
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).map_err(|e| err(format!("invalid escrow: {e}")))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("escrow serializes")
+    }
+}
+
+fn share_aad(id: B256, index: u8) -> Vec<u8> {
+    [id.as_slice(), &[index]].concat()
+}
+
+fn release_aad(id: B256, index: u8, guardian: B256) -> Vec<u8> {
+    [id.as_slice(), &[index], guardian.as_slice()].concat()
+}
+
+/// A guardian's share re-sealed to the recovering device, as written by
+/// `pq-escrow release`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReleasedShare {
+    pub version: u32,
+    /// [`Escrow::id`] of the escrow it was released from.
+    pub escrow: B256,
+    pub index: u8,
+    pub guardian: B256,
+    /// [`backup::kem_fingerprint`] of the recovering device's key.
+    pub recipient: B256,
+    #[serde(flatten)]
+    pub sealed: Sealed,
+}
+
+impl ReleasedShare {
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).map_err(|e| err(format!("invalid released share: {e}")))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("released share serializes")
+    }
+}
+
+/// One share a recovery used.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscriptShare {
+    pub index: u8,
+    pub label: String,
+    pub guardian: B256,
+}
+
+/// What a recovery did.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transcript {
+    pub version: u32,
+    pub escrow: B256,
+    pub name: String,
+    pub key_id: Address,
+    pub public_key: Bytes,
+    pub threshold: u8,
+    pub shares: Vec<TranscriptShare>,
+    pub recovered_at: u64,
+}
+
+impl Transcript {
+    /// The transcript of recovering `escrow` from `shares` at `now`.
+    pub fn new(escrow: &Escrow, shares: &[Share], now: u64) -> Result<Self> {
+        Ok(Transcript {
+            version: ESCROW_VERSION,
+            escrow: escrow.id()?,
+            name: escrow.name.clone(),
+            key_id: escrow.key_id(),
+            public_key: escrow.public_key.clone(),
+            threshold: escrow.threshold,
+            shares: shares
+                .iter()
+                .filter_map(|s| escrow.guardians.iter().find(|g| g.index == s.index))
+                .map(|g| TranscriptShare {
+                    index: g.index,
+                    label: g.label.clone(),
+                    guardian: g.guardian,
+                })
+                .collect(),
+            recovered_at: now,
+        })
+    }
+
+    pub fn statement(&self) -> Result<B256> {
+        let mut preimage = TRANSCRIPT_DOMAIN.to_vec();
+        preimage.extend_from_slice(&canonical_json::to_vec(self)?);
+        Ok(keccak256(preimage))
+    }
+
+    /// Sign with the recovered seed, which proves the recovery produced the
+    /// escrowed key.
+    pub fn sign(self, seed: &[u8; SEED_LEN]) -> Result<SignedTranscript> {
+        let signature = PqSigner::from_seed(seed).sign(self.statement()?.as_slice());
+        Ok(SignedTranscript {
+            signature: Bytes::copy_from_slice(&signature.encode()),
+            transcript: self,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedTranscript {
+    #[serde(flatten)]
+    pub transcript: Transcript,
+    /// Signature over [`Transcript::statement`] by the recovered key.
+    pub signature: Bytes,
+}
+
+impl SignedTranscript {
+    /// Check the signature, and with `escrow`, that the transcript is of it.
+    pub fn verify(&self, escrow: Option<&Escrow>) -> Result<()> {
+        let t = &self.transcript;
+        if t.version != ESCROW_VERSION {
+            return Err(err(format!("unsupported transcript v{}", t.version)));
+        }
+        if keys::address(&t.public_key) != t.key_id {
+            return Err(err("key ID does not match the public key"));
+        }
+        if !Backend::RustCrypto.verify(
+            &t.public_key,
+            t.statement()?.as_slice(),
+            &[],
+            &self.signature,
+        ) {
+            return Err(err("transcript signature does not verify"));
+        }
+        if let Some(escrow) = escrow
+            && (escrow.id()? != t.escrow || escrow.public_key != t.public_key)
+        {
+            return Err(err(format!(
+                "transcript is of escrow {}, not this one",
+                t.escrow
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).map_err(|e| err(format!("invalid transcript: {e}")))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("transcript serializes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guardian(label: &str, byte: u8) -> (Guardian, [u8; KEM_SEED_LEN]) {
+        let seed = [byte; KEM_SEED_LEN];
+        let guardian = Guardian {
+            label: label.into(),
+            kem_public_key: backup::kem_public_key(&seed),
+        };
+        (guardian, seed)
+    }
+
+    #[test]
+    fn any_two_of_three_guardians_recover_the_seed() {
+        let seed = [7u8; SEED_LEN];
+        let (guardians, kem_seeds): (Vec<_>, Vec<_>) =
+            [guardian("ann", 1), guardian("bo", 2), guardian("cy", 3)]
+                .into_iter()
+                .unzip();
+        let escrow = Escrow::deposit("alice", &seed, 2, &guardians).unwrap();
+        let escrow = Escrow::from_json(escrow.to_json().as_bytes()).unwrap();
+        let device = [9u8; KEM_SEED_LEN];
+        let device_pk = backup::kem_public_key(&device);
+
+        let releases: Vec<_> = [&kem_seeds[0], &kem_seeds[2]]
+            .iter()
+            .map(|kem| escrow.release(kem, &device_pk).unwrap())
+            .collect();
+        let read = ReleasedShare::from_json(releases[1].to_json().as_bytes()).unwrap();
+        let shares = vec![
+            escrow.open_release(&releases[0], &device).unwrap(),
+            escrow.open_release(&read, &device).unwrap(),
+        ];
+        assert_eq!(*escrow.recover(&shares).unwrap(), seed);
+        assert!(escrow.recover(&shares[..1]).is_err());
+        // A guardian's own key opens their share; the device's does not.
+        assert_eq!(escrow.open_share(&kem_seeds[1]).unwrap().index, 2);
+        assert!(escrow.open_share(&device).is_err());
+        assert!(escrow.open_release(&releases[0], &kem_seeds[0]).is_err());
+
+        // A forged share is caught by its commitment.
+        let mut forged = shares.clone();
+        forged[0].value[0] ^= 1;
+        assert!(escrow.recover(&forged).is_err());
+        // Raising the threshold changes the ID every ciphertext is bound to.
+        let mut edited = escrow.clone();
+        edited.threshold = 1;
+        assert!(edited.open_share(&kem_seeds[0]).is_err());
+
+        let transcript = Transcript::new(&escrow, &shares, 1_700_000_000)
+            .unwrap()
+            .sign(&seed)
+            .unwrap();
+        let read = SignedTranscript::from_json(transcript.to_json().as_bytes()).unwrap();
+        read.verify(Some(&escrow)).unwrap();
+        let labels: Vec<_> = read
+            .transcript
+            .shares
+            .iter()
+            .map(|s| &s.label[..])
+            .collect();
+        assert_eq!(labels, ["ann", "cy"]);
+        let mut tampered = read.clone();
+        tampered.transcript.recovered_at += 1;
+        assert!(tampered.verify(None).is_err());
+
+        let twice = [guardians[0].clone(), guardians[0].clone()];
+        assert!(Escrow::deposit("alice", &seed, 2, &twice).is_err());
+    }
+}
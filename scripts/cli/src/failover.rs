@@ -0,0 +1,340 @@
+//! Several bundlers behind one [`Transport`], with health checks and
+//! circuit breakers.
+//!
+//! A network can list more than one bundler (`bundler = ["https://a",
+//! "https://b"]` in `pq-wallet.toml`, or `--bundler https://a,https://b`).
+//! [`Failover`] sends each request to the endpoints in that order and moves
+//! on when one fails in transport: a refused connection, a timeout, or a
+//! reply that is not JSON-RPC. A JSON-RPC error is the bundler's answer (an
+//! AA code, a fee it will not take) and comes back as it is.
+//!
+//! After [`FAILURE_THRESHOLD`] transport failures in a row an endpoint's
+//! breaker opens, and for [`COOLDOWN`] it is tried only after every closed
+//! one has failed, so a dead bundler does not cost each request its
+//! timeout. Once the cooldown is over it is tried in order again, and one
+//! success closes the breaker.
+//!
+//! [`Failover::check`] asks each endpoint for `eth_chainId` and
+//! `eth_supportedEntryPoints` and times them. An endpoint on another chain,
+//! or without the EntryPoint, is taken out of rotation; one that does not
+//! answer has its breaker opened. Sending an op to a second bundler after
+//! the first timed out is safe: its hash is the same on both, and the
+//! EntryPoint includes it at most once.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use alloy_primitives::{Address, U64};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::error::{Result, WalletError};
+use crate::rpc::{HttpTransport, Transport};
+
+/// Transport failures in a row that open an endpoint's breaker.
+pub const FAILURE_THRESHOLD: u32 = 3;
+/// How long an open breaker demotes its endpoint.
+pub const COOLDOWN: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+struct Breaker {
+    failures: u32,
+    open_until: Option<Instant>,
+    /// Why [`Failover::check`] took the endpoint out of rotation.
+    excluded: Option<String>,
+}
+
+impl Breaker {
+    fn closed(&self, now: Instant) -> bool {
+        self.open_until.is_none_or(|until| now >= until)
+    }
+
+    fn failed(&mut self, now: Instant) {
+        self.failures += 1;
+        if self.failures >= FAILURE_THRESHOLD {
+            self.open_until = Some(now + COOLDOWN);
+        }
+    }
+
+    fn succeeded(&mut self) {
+        self.failures = 0;
+        self.open_until = None;
+    }
+}
+
+#[derive(Debug)]
+struct Endpoint<T> {
+    url: String,
+    rpc: T,
+    breaker: Mutex<Breaker>,
+}
+
+impl<T> Endpoint<T> {
+    fn breaker(&self) -> std::sync::MutexGuard<'_, Breaker> {
+        self.breaker.lock().expect("breaker lock poisoned")
+    }
+}
+
+/// What [`Failover::check`] found out about one endpoint.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Health {
+    pub url: String,
+    pub chain_id: Option<u64>,
+    pub entry_points: Vec<Address>,
+    /// Round trip of the slower of the two calls, in milliseconds.
+    pub latency_ms: Option<u64>,
+    /// Why the endpoint is unhealthy; `None` if it is not.
+    pub problem: Option<String>,
+}
+
+/// Bundler endpoints tried in order.
+#[derive(Debug)]
+pub struct Failover<T = HttpTransport> {
+    endpoints: Vec<Endpoint<T>>,
+}
+
+impl Failover {
+    /// HTTP endpoints from comma-separated `urls`, as `--bundler` takes them.
+    pub fn new(urls: &str, timeout: Duration) -> Self {
+        Failover::from_transports(
+            urls.split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(|url| (url.to_string(), HttpTransport::new(url, timeout))),
+        )
+    }
+}
+
+impl<T: Transport> Failover<T> {
+    pub fn from_transports(endpoints: impl IntoIterator<Item = (String, T)>) -> Self {
+        Failover {
+            endpoints: endpoints
+                .into_iter()
+                .map(|(url, rpc)| Endpoint {
+                    url,
+                    rpc,
+                    breaker: Mutex::default(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn urls(&self) -> impl Iterator<Item = &str> {
+        self.endpoints.iter().map(|e| e.url.as_str())
+    }
+
+    /// [`Transport::request`] as of `now`, for the breakers' clock.
+    pub fn request_at(&self, method: &str, params: Value, now: Instant) -> Result<Value> {
+        let mut excluded = Vec::new();
+        let mut closed = Vec::new();
+        let mut open = Vec::new();
+        for endpoint in &self.endpoints {
+            let breaker = endpoint.breaker();
+            match &breaker.excluded {
+                Some(reason) => excluded.push(format!("{}: {reason}", endpoint.url)),
+                None if breaker.closed(now) => closed.push(endpoint),
+                None => open.push(endpoint),
+            }
+        }
+        if closed.is_empty() && open.is_empty() {
+            return Err(WalletError::Rpc(if excluded.is_empty() {
+                format!("{method}: no bundler URL")
+            } else {
+                format!("{method}: no usable bundler ({})", excluded.join("; "))
+            }));
+        }
+        let mut failures = Vec::new();
+        for endpoint in closed.into_iter().chain(open) {
+            match endpoint.rpc.request(method, params.clone()) {
+                Err(e @ WalletError::Rpc(_)) => {
+                    tracing::warn!(url = %endpoint.url, error = %e, "bundler failed");
+                    endpoint.breaker().failed(now);
+                    failures.push(e.to_string());
+                }
+                answered => {
+                    endpoint.breaker().succeeded();
+                    return answered;
+                }
+            }
+        }
+        Err(WalletError::Rpc(failures.join("; ")))
+    }
+
+    /// Check every endpoint against `chain_id` (the first healthy one's, if
+    /// `None`) and `entry_point`, and take those that fail out of rotation.
+    pub fn check(&self, chain_id: Option<u64>, entry_point: Address) -> Vec<Health> {
+        let mut expected = chain_id;
+        let mut report = Vec::with_capacity(self.endpoints.len());
+        for endpoint in &self.endpoints {
+            let mut health = Health {
+                url: endpoint.url.clone(),
+                chain_id: None,
+                entry_points: Vec::new(),
+                latency_ms: None,
+                problem: None,
+            };
+            let mut latency = Duration::ZERO;
+            let mut timed = |method: &str| {
+                let started = Instant::now();
+                let result = endpoint.rpc.request(method, json!([]));
+                latency = latency.max(started.elapsed());
+                result
+            };
+            let answers = timed("eth_chainId")
+                .and_then(|id| decode::<U64>("eth_chainId", id))
+                .and_then(|id| {
+                    let entry_points = timed("eth_supportedEntryPoints")?;
+                    Ok((id, decode("eth_supportedEntryPoints", entry_points)?))
+                });
+            let mut breaker = endpoint.breaker();
+            match answers {
+                Err(e) => {
+                    breaker.failures = breaker.failures.max(FAILURE_THRESHOLD - 1);
+                    breaker.failed(Instant::now());
+                    health.problem = Some(e.to_string());
+                }
+                Ok((id, entry_points)) => {
+                    let id = id.to::<u64>();
+                    health.chain_id = Some(id);
+                    health.entry_points = entry_points;
+                    health.latency_ms = Some(latency.as_millis() as u64);
+                    let expected = *expected.get_or_insert(id);
+                    breaker.excluded = if id != expected {
+                        Some(format!("on chain {id}, not {expected}"))
+                    } else if !health.entry_points.contains(&entry_point) {
+                        Some(format!("does not support EntryPoint {entry_point}"))
+                    } else {
+                        None
+                    };
+                    health.problem = breaker.excluded.clone();
+                    if health.problem.is_none() {
+                        breaker.succeeded();
+                    }
+                }
+            }
+            report.push(health);
+        }
+        report
+    }
+}
+
+fn decode<D: serde::de::DeserializeOwned>(method: &str, value: Value) -> Result<D> {
+    serde_json::from_value(value)
+        .map_err(|e| WalletError::Rpc(format!("{method}: unexpected result: {e}")))
+}
+
+impl<T: Transport> Transport for Failover<T> {
+    fn request(&self, method: &str, params: Value) -> Result<Value> {
+        self.request_at(method, params, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::userop::ENTRY_POINT_V07;
+
+    /// A bundler on `chain_id` that counts its requests, or is down.
+    struct Bundler {
+        chain_id: u64,
+        up: bool,
+        requests: AtomicUsize,
+    }
+
+    fn bundler(chain_id: u64, up: bool) -> Bundler {
+        Bundler {
+            chain_id,
+            up,
+            requests: AtomicUsize::new(0),
+        }
+    }
+
+    impl Transport for Bundler {
+        fn request(&self, method: &str, _params: Value) -> Result<Value> {
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            if !self.up {
+                return Err(WalletError::Rpc("connection refused".into()));
+            }
+            Ok(match method {
+                "eth_chainId" => json!(U64::from(self.chain_id)),
+                "eth_supportedEntryPoints" => json!([ENTRY_POINT_V07]),
+                "eth_sendUserOperation" => Err(WalletError::RpcError {
+                    method: method.into(),
+                    code: -32602,
+                    message: "AA21 didn't pay prefund".into(),
+                    data: None,
+                })?,
+                _ => json!(self.chain_id),
+            })
+        }
+    }
+
+    fn requests(failover: &Failover<Bundler>, i: usize) -> usize {
+        failover.endpoints[i].rpc.requests.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn fails_over_and_skips_an_open_breaker() {
+        let failover = Failover::from_transports([
+            ("down".to_string(), bundler(1, false)),
+            ("up".to_string(), bundler(1, true)),
+        ]);
+        let now = Instant::now();
+        for _ in 0..FAILURE_THRESHOLD + 2 {
+            assert_eq!(failover.request_at("x", json!([]), now).unwrap(), json!(1));
+        }
+        // The breaker opened after three failures; the cooldown lets it
+        // back in first.
+        assert_eq!(
+            requests(&failover, 0),
+            FAILURE_THRESHOLD as usize,
+            "an open breaker is skipped"
+        );
+        failover.request_at("x", json!([]), now + COOLDOWN).unwrap();
+        assert_eq!(requests(&failover, 0), FAILURE_THRESHOLD as usize + 1);
+
+        // A JSON-RPC error is an answer, not a failure.
+        let refused = failover.request_at("eth_sendUserOperation", json!([]), now);
+        assert!(matches!(refused, Err(WalletError::RpcError { .. })));
+
+        let all_down = Failover::from_transports([("a".to_string(), bundler(1, false))]);
+        assert!(matches!(
+            all_down.request("x", json!([])),
+            Err(WalletError::Rpc(_))
+        ));
+    }
+
+    #[test]
+    fn check_takes_the_wrong_chain_out_of_rotation() {
+        let failover = Failover::from_transports([
+            ("other".to_string(), bundler(10, true)),
+            ("down".to_string(), bundler(1, false)),
+            ("right".to_string(), bundler(1, true)),
+        ]);
+        let health = failover.check(Some(1), ENTRY_POINT_V07);
+        assert_eq!(health[0].problem.as_deref(), Some("on chain 10, not 1"));
+        assert!(health[1].problem.is_some() && health[1].chain_id.is_none());
+        assert_eq!(health[2].problem, None);
+        assert_eq!(health[2].entry_points, [ENTRY_POINT_V07]);
+
+        let before = requests(&failover, 1);
+        assert_eq!(failover.request("x", json!([])).unwrap(), json!(1));
+        assert_eq!(
+            requests(&failover, 0),
+            2,
+            "excluded endpoints are never tried"
+        );
+        assert_eq!(
+            requests(&failover, 1),
+            before,
+            "a failed check opens the breaker"
+        );
+
+        let missing = failover.check(Some(1), Address::ZERO);
+        assert!(missing[2].problem.as_ref().unwrap().contains("EntryPoint"));
+        assert!(failover.request("x", json!([])).is_err());
+    }
+}
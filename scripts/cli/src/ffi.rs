@@ -0,0 +1,454 @@
+//! C interface for mobile host apps (`ffi` feature).
+//!
+//! Build a static library for the target and link it into the app:
+//!
+//! ```text
+//! cargo rustc --lib --release --features ffi --target aarch64-apple-ios --crate-type staticlib
+//! ```
+//!
+//! `include/pq_wallet.h` declares everything here. The host provides a
+//! [`PqPlatform`]: two callbacks over the OS keystore that wrap and unwrap a
+//! 32-byte key with a hardware-bound key (see [`crate::platform`]). Then:
+//!
+//! 1. `pq_account_create` generates a seed inside the library, seals it as a
+//!    platform keystore entry and returns the entry's JSON for the app to
+//!    store. The seed never crosses the interface.
+//! 2. `pq_unlock` takes that JSON and a prompt reason. The library calls the
+//!    host's `unwrap`, which shows the biometric prompt, and returns a
+//!    session holding the decrypted seed.
+//! 3. `pq_session_sign_hash` signs userOpHashes (or any 32-byte digest) with
+//!    the session.
+//! 4. `pq_session_free` zeroizes and frees the seed; the app should call it
+//!    once it has signed, or when it moves to the background.
+//!
+//! Every function returns a `PQ_*` status. After a failure,
+//! `pq_last_error` describes it, on the same thread.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_void};
+
+use zeroize::Zeroizing;
+
+use crate::backend::Backend;
+use crate::error::{Result, WalletError};
+use crate::keys::{PUBLIC_KEY_LEN, SEED_LEN, SIGNATURE_LEN};
+use crate::keystore::Account;
+use crate::platform::PlatformKeystore;
+
+pub const PQ_OK: i32 = 0;
+/// A null pointer, invalid UTF-8 or malformed account JSON.
+pub const PQ_ERR_ARGUMENT: i32 = 1;
+/// The user dismissed or failed the biometric prompt.
+pub const PQ_ERR_CANCELLED: i32 = 2;
+/// The unwrapped key does not open the entry.
+pub const PQ_ERR_DECRYPT: i32 = 3;
+/// The OS keystore failed, or the entry belongs to another platform.
+pub const PQ_ERR_KEYSTORE: i32 = 4;
+/// An output buffer is too small; `wrap` sets `*out_len` to the size needed.
+pub const PQ_ERR_BUFFER: i32 = 5;
+
+/// Initial room for a wrapped key; enough for Secure Enclave ECIES and
+/// RSA-4096 OAEP alike.
+const WRAPPED_CAPACITY: usize = 1024;
+
+/// The host's OS keystore.
+#[repr(C)]
+pub struct PqPlatform {
+    /// Passed back to the callbacks unchanged.
+    pub ctx: *mut c_void,
+    /// NUL-terminated platform name, e.g. `secure-enclave`.
+    pub name: *const c_char,
+    /// Wrap the 32 bytes at `key` with the OS key `alias`, creating it if it
+    /// does not exist. Writes at most `*out_len` bytes to `out` and sets
+    /// `*out_len` to the length written.
+    pub wrap: unsafe extern "C" fn(
+        ctx: *mut c_void,
+        alias: *const c_char,
+        key: *const u8,
+        out: *mut u8,
+        out_len: *mut usize,
+    ) -> i32,
+    /// Authenticate the user, showing `reason`, then unwrap `wrapped` with
+    /// the OS key `alias` into the 32 bytes at `out`.
+    pub unwrap: unsafe extern "C" fn(
+        ctx: *mut c_void,
+        alias: *const c_char,
+        wrapped: *const u8,
+        wrapped_len: usize,
+        reason: *const c_char,
+        out: *mut u8,
+    ) -> i32,
+}
+
+/// An unlocked account.
+pub struct PqSession {
+    seed: Zeroizing<[u8; SEED_LEN]>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn code(e: &WalletError) -> i32 {
+    match e {
+        WalletError::Cancelled => PQ_ERR_CANCELLED,
+        WalletError::Decrypt => PQ_ERR_DECRYPT,
+        WalletError::Usage(_)
+        | WalletError::InvalidHex
+        | WalletError::InvalidLength { .. }
+        | WalletError::Config(_) => PQ_ERR_ARGUMENT,
+        _ => PQ_ERR_KEYSTORE,
+    }
+}
+
+fn run(f: impl FnOnce() -> Result<()>) -> i32 {
+    match f() {
+        Ok(()) => PQ_OK,
+        Err(e) => {
+            let message = CString::new(e.to_string().replace('\0', " ")).unwrap_or_default();
+            LAST_ERROR.with(|last| *last.borrow_mut() = message);
+            code(&e)
+        }
+    }
+}
+
+/// # Safety
+///
+/// `s` is null or a NUL-terminated string valid for the call.
+unsafe fn string<'a>(s: *const c_char, what: &'static str) -> Result<&'a str> {
+    if s.is_null() {
+        return Err(WalletError::Usage(what));
+    }
+    // SAFETY: non-null and NUL-terminated per the caller's contract.
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map_err(|_| WalletError::Usage(what))
+}
+
+struct Callbacks<'a> {
+    platform: &'a PqPlatform,
+    name: &'a str,
+}
+
+fn status(rc: i32, op: &str) -> Result<()> {
+    match rc {
+        PQ_OK => Ok(()),
+        PQ_ERR_CANCELLED => Err(WalletError::Cancelled),
+        rc => Err(WalletError::Keystore(format!(
+            "platform keystore: {op} failed ({rc})"
+        ))),
+    }
+}
+
+impl PlatformKeystore for Callbacks<'_> {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn wrap(&self, alias: &str, key: &[u8; 32]) -> Result<Vec<u8>> {
+        let alias = CString::new(alias).map_err(|_| WalletError::Usage("alias contains NUL"))?;
+        let mut out = vec![0u8; WRAPPED_CAPACITY];
+        for _ in 0..2 {
+            let mut len = out.len();
+            // SAFETY: the host's callback, given buffers of the advertised
+            // sizes.
+            let rc = unsafe {
+                (self.platform.wrap)(
+                    self.platform.ctx,
+                    alias.as_ptr(),
+                    key.as_ptr(),
+                    out.as_mut_ptr(),
+                    &mut len,
+                )
+            };
+            if rc == PQ_ERR_BUFFER && len > out.len() {
+                out.resize(len, 0);
+                continue;
+            }
+            status(rc, "wrap")?;
+            out.truncate(len);
+            return Ok(out);
+        }
+        Err(WalletError::Keystore(
+            "platform keystore: wrap kept asking for more room".to_string(),
+        ))
+    }
+
+    fn unwrap(&self, alias: &str, wrapped: &[u8], reason: &str) -> Result<Zeroizing<[u8; 32]>> {
+        let alias = CString::new(alias).map_err(|_| WalletError::Usage("alias contains NUL"))?;
+        let reason = CString::new(reason).map_err(|_| WalletError::Usage("reason contains NUL"))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        // SAFETY: as for `wrap`; `key` has room for the 32 bytes.
+        let rc = unsafe {
+            (self.platform.unwrap)(
+                self.platform.ctx,
+                alias.as_ptr(),
+                wrapped.as_ptr(),
+                wrapped.len(),
+                reason.as_ptr(),
+                key.as_mut_ptr(),
+            )
+        };
+        status(rc, "unwrap")?;
+        Ok(key)
+    }
+}
+
+/// # Safety
+///
+/// `platform` is null or points to a valid [`PqPlatform`].
+unsafe fn callbacks<'a>(platform: *const PqPlatform) -> Result<Callbacks<'a>> {
+    // SAFETY: per the caller's contract.
+    let platform = unsafe { platform.as_ref() }.ok_or(WalletError::Usage("platform is null"))?;
+    // SAFETY: `name` is a NUL-terminated string per the PqPlatform contract.
+    let name = unsafe { string(platform.name, "platform name is not UTF-8") }?;
+    Ok(Callbacks { platform, name })
+}
+
+/// Create a platform keystore entry `name` for a new seed, wrapped with the
+/// OS key `alias`. On success `*out_json` is the entry, to be freed with
+/// [`pq_string_free`].
+///
+/// # Safety
+///
+/// `platform` points to a valid [`PqPlatform`], `name` and `alias` are
+/// NUL-terminated strings and `out_json` is writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pq_account_create(
+    platform: *const PqPlatform,
+    name: *const c_char,
+    alias: *const c_char,
+    out_json: *mut *mut c_char,
+) -> i32 {
+    run(|| {
+        // SAFETY: per this function's contract.
+        let (platform, name, alias) = unsafe {
+            (
+                callbacks(platform)?,
+                string(name, "name is not UTF-8")?,
+                string(alias, "alias is not UTF-8")?,
+            )
+        };
+        if out_json.is_null() {
+            return Err(WalletError::Usage("out_json is null"));
+        }
+        let seed = Zeroizing::new(rand::random::<[u8; SEED_LEN]>());
+        let account = Account::encrypt_wrapped(name, &seed, &platform, alias)?;
+        let json = serde_json::to_string(&account).expect("accounts serialize");
+        let json = CString::new(json).expect("JSON has no NUL");
+        // SAFETY: checked non-null above.
+        unsafe { *out_json = json.into_raw() };
+        Ok(())
+    })
+}
+
+/// Unlock the entry in `account_json`: the host's `unwrap` prompts the user
+/// with `reason`. On success `*out_session` must later be passed to
+/// [`pq_session_free`].
+///
+/// # Safety
+///
+/// `platform` points to a valid [`PqPlatform`], `account_json` and `reason`
+/// are NUL-terminated strings and `out_session` is writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pq_unlock(
+    platform: *const PqPlatform,
+    account_json: *const c_char,
+    reason: *const c_char,
+    out_session: *mut *mut PqSession,
+) -> i32 {
+    run(|| {
+        // SAFETY: per this function's contract.
+        let (platform, json, reason) = unsafe {
+            (
+                callbacks(platform)?,
+                string(account_json, "account JSON is not UTF-8")?,
+                string(reason, "reason is not UTF-8")?,
+            )
+        };
+        if out_session.is_null() {
+            return Err(WalletError::Usage("out_session is null"));
+        }
+        let account: Account = serde_json::from_str(json)
+            .map_err(|e| WalletError::Config(format!("account JSON: {e}")))?;
+        let seed = account.unlock_wrapped(&platform, reason)?;
+        let session = Box::new(PqSession { seed });
+        // SAFETY: checked non-null above.
+        unsafe { *out_session = Box::into_raw(session) };
+        Ok(())
+    })
+}
+
+/// Deterministic ML-DSA-65 over the 32 bytes at `hash`, as `pq-sign --hash`
+/// signs; writes [`SIGNATURE_LEN`] bytes to `out_signature`.
+///
+/// # Safety
+///
+/// `session` came from [`pq_unlock`] and is not freed, `hash` points to 32
+/// readable bytes and `out_signature` to [`SIGNATURE_LEN`] writable ones.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pq_session_sign_hash(
+    session: *const PqSession,
+    hash: *const u8,
+    out_signature: *mut u8,
+) -> i32 {
+    run(|| {
+        if hash.is_null() || out_signature.is_null() {
+            return Err(WalletError::Usage("null buffer"));
+        }
+        // SAFETY: per this function's contract.
+        let session = unsafe { session.as_ref() }.ok_or(WalletError::Usage("session is null"))?;
+        // SAFETY: 32 readable bytes per this function's contract.
+        let hash = unsafe { std::slice::from_raw_parts(hash, 32) };
+        let signature = Backend::RustCrypto.sign(&session.seed, hash, &[], &[0u8; 32])?;
+        // SAFETY: SIGNATURE_LEN writable bytes per this function's contract.
+        unsafe { std::ptr::copy_nonoverlapping(signature.as_ptr(), out_signature, SIGNATURE_LEN) };
+        Ok(())
+    })
+}
+
+/// Write the session's [`PUBLIC_KEY_LEN`]-byte public key to `out`.
+///
+/// # Safety
+///
+/// `session` came from [`pq_unlock`] and is not freed, and `out` points to
+/// [`PUBLIC_KEY_LEN`] writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pq_session_public_key(session: *const PqSession, out: *mut u8) -> i32 {
+    run(|| {
+        // SAFETY: per this function's contract.
+        let session = unsafe { session.as_ref() }.ok_or(WalletError::Usage("session is null"))?;
+        if out.is_null() {
+            return Err(WalletError::Usage("null buffer"));
+        }
+        let public_key = Backend::RustCrypto.public_key(&session.seed);
+        // SAFETY: PUBLIC_KEY_LEN writable bytes per this function's contract.
+        unsafe { std::ptr::copy_nonoverlapping(public_key.as_ptr(), out, PUBLIC_KEY_LEN) };
+        Ok(())
+    })
+}
+
+/// Zeroize and free a session. Null is ignored.
+///
+/// # Safety
+///
+/// `session` came from [`pq_unlock`] and is not used again.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pq_session_free(session: *mut PqSession) {
+    if !session.is_null() {
+        // SAFETY: allocated by `pq_unlock` with Box::into_raw.
+        drop(unsafe { Box::from_raw(session) });
+    }
+}
+
+/// Free a string returned by the library. Null is ignored.
+///
+/// # Safety
+///
+/// `s` came from this library and is not used again.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pq_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: allocated by CString::into_raw.
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// The last failure on this thread; valid until the next call that fails.
+#[unsafe(no_mangle)]
+pub extern "C" fn pq_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::ptr;
+
+    /// Wraps by XOR with 0xA5.
+    unsafe extern "C" fn wrap(
+        _: *mut c_void,
+        _: *const c_char,
+        key: *const u8,
+        out: *mut u8,
+        out_len: *mut usize,
+    ) -> i32 {
+        unsafe {
+            for i in 0..32 {
+                *out.add(i) = *key.add(i) ^ 0xA5;
+            }
+            *out_len = 32;
+        }
+        PQ_OK
+    }
+
+    /// Unwraps, unless the user declines: `ctx` is a `Cell<bool>`.
+    unsafe extern "C" fn unwrap(
+        ctx: *mut c_void,
+        _: *const c_char,
+        wrapped: *const u8,
+        wrapped_len: usize,
+        _: *const c_char,
+        out: *mut u8,
+    ) -> i32 {
+        let approve = unsafe { &*(ctx as *const Cell<bool>) };
+        if !approve.get() {
+            return PQ_ERR_CANCELLED;
+        }
+        unsafe {
+            for i in 0..wrapped_len {
+                *out.add(i) = *wrapped.add(i) ^ 0xA5;
+            }
+        }
+        PQ_OK
+    }
+
+    #[test]
+    fn creates_unlocks_and_signs_through_the_c_interface() {
+        let approve = Cell::new(true);
+        let platform = PqPlatform {
+            ctx: &approve as *const Cell<bool> as *mut c_void,
+            name: c"android-keystore".as_ptr(),
+            wrap,
+            unwrap,
+        };
+        let mut json = ptr::null_mut();
+        let rc = unsafe {
+            pq_account_create(
+                &platform,
+                c"phone".as_ptr(),
+                c"pq.phone".as_ptr(),
+                &mut json,
+            )
+        };
+        assert_eq!(rc, PQ_OK);
+
+        let mut session = ptr::null_mut();
+        let rc = unsafe { pq_unlock(&platform, json, c"Sign in".as_ptr(), &mut session) };
+        assert_eq!(rc, PQ_OK);
+        let mut public_key = vec![0u8; PUBLIC_KEY_LEN];
+        let mut signature = vec![0u8; SIGNATURE_LEN];
+        unsafe {
+            assert_eq!(
+                pq_session_public_key(session, public_key.as_mut_ptr()),
+                PQ_OK
+            );
+            assert_eq!(
+                pq_session_sign_hash(session, [8u8; 32].as_ptr(), signature.as_mut_ptr()),
+                PQ_OK
+            );
+            pq_session_free(session);
+        }
+        assert!(Backend::RustCrypto.verify(&public_key, &[8; 32], &[], &signature));
+
+        approve.set(false);
+        let mut session = ptr::null_mut();
+        let rc = unsafe { pq_unlock(&platform, json, c"Sign in".as_ptr(), &mut session) };
+        assert_eq!(rc, PQ_ERR_CANCELLED);
+        assert!(session.is_null());
+        let error = unsafe { CStr::from_ptr(pq_last_error()) };
+        assert_eq!(error.to_str().unwrap(), "signing cancelled");
+        unsafe { pq_string_free(json) };
+    }
+}
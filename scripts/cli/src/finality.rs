@@ -0,0 +1,295 @@
+//! Reorg-aware confirmation of included ops (`--finality`).
+//!
+//! A bundler calls an op included as soon as its bundle transaction has a
+//! receipt, but the block holding it can still be reorged away. With a
+//! finality requirement, [`Tracker`] follows the transaction on a node until
+//! either
+//!
+//! - `N`: its block has `N` confirmations (the block itself is the first),
+//!   or
+//! - `safe` / `finalized`: the node's `safe` or `finalized` block has
+//!   reached it. On rollups these tags follow L1: an OP Stack block is safe
+//!   once derived from a posted batch and finalized with that L1 block.
+//!
+//! Each poll re-reads the receipt. A receipt in a different block means the
+//! transaction was reorged into it, and counting restarts there. No receipt
+//! for a block the node already has means the transaction was dropped, and
+//! the op needs sending again.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use alloy_primitives::{B256, U64};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, WalletError};
+use crate::rpc::Transport;
+
+/// When an included op counts as final.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Finality {
+    /// This many confirmations.
+    Depth(u64),
+    Safe,
+    Finalized,
+}
+
+impl fmt::Display for Finality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Finality::Depth(n) => write!(f, "{n}"),
+            Finality::Safe => f.write_str("safe"),
+            Finality::Finalized => f.write_str("finalized"),
+        }
+    }
+}
+
+impl std::str::FromStr for Finality {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s {
+            "safe" => Ok(Finality::Safe),
+            "finalized" => Ok(Finality::Finalized),
+            _ => match s.parse::<u64>() {
+                Ok(n) if n > 0 => Ok(Finality::Depth(n)),
+                _ => Err(format!(
+                    "invalid finality {s:?} (expected a number of blocks, safe or finalized)"
+                )),
+            },
+        }
+    }
+}
+
+/// `finality = 12` or `finality = "safe"` in `pq-wallet.toml`.
+impl<'de> Deserialize<'de> for Finality {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Depth(u64),
+            Tag(String),
+        }
+        match Raw::deserialize(d)? {
+            Raw::Depth(n) => n.to_string().parse(),
+            Raw::Tag(s) => s.parse(),
+        }
+        .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Where the transaction stands after a poll.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Confirmation {
+    pub block_number: u64,
+    /// `None` until the node has a receipt, and once it is dropped.
+    pub block_hash: Option<B256>,
+    pub confirmations: u64,
+    /// The finality requirement is met.
+    #[serde(rename = "final")]
+    pub is_final: bool,
+    /// Reorged out of the canonical chain.
+    pub dropped: bool,
+    /// Times the transaction moved to another block.
+    pub reorgs: u32,
+    /// True if waiting stopped at the timeout.
+    pub timed_out: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Receipt {
+    block_hash: B256,
+    block_number: U64,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    number: U64,
+}
+
+/// Follows one transaction towards finality.
+#[derive(Clone, Debug)]
+pub struct Tracker {
+    pub transaction_hash: B256,
+    pub finality: Finality,
+    last: Confirmation,
+}
+
+impl Tracker {
+    /// Track `transaction_hash`, which the bundler saw in block
+    /// `block_number`.
+    pub fn new(transaction_hash: B256, block_number: u64, finality: Finality) -> Self {
+        Tracker {
+            transaction_hash,
+            finality,
+            last: Confirmation {
+                block_number,
+                block_hash: None,
+                confirmations: 0,
+                is_final: false,
+                dropped: false,
+                reorgs: 0,
+                timed_out: false,
+            },
+        }
+    }
+
+    /// Look at the chain once.
+    pub fn poll<T: Transport>(&mut self, node: &T) -> Result<Confirmation> {
+        let receipt: Option<Receipt> = node.call(
+            "eth_getTransactionReceipt",
+            serde_json::json!([self.transaction_hash]),
+        )?;
+        let head = node
+            .call::<U64>("eth_blockNumber", serde_json::json!([]))?
+            .to::<u64>();
+        let last = &mut self.last;
+        let Some(receipt) = receipt else {
+            // A node behind the bundler may not have the block yet.
+            last.dropped = head >= last.block_number;
+            last.block_hash = None;
+            last.confirmations = 0;
+            last.is_final = false;
+            return Ok(*last);
+        };
+        if last
+            .block_hash
+            .is_some_and(|hash| hash != receipt.block_hash)
+        {
+            tracing::warn!(
+                from = last.block_number,
+                to = %receipt.block_number,
+                "transaction moved by a reorg"
+            );
+            last.reorgs += 1;
+        }
+        last.block_number = receipt.block_number.to::<u64>();
+        last.block_hash = Some(receipt.block_hash);
+        last.dropped = false;
+        last.confirmations = (head + 1).saturating_sub(last.block_number);
+        last.is_final = match self.finality {
+            Finality::Depth(n) => last.confirmations >= n,
+            Finality::Safe => tagged(node, "safe")? >= last.block_number,
+            Finality::Finalized => tagged(node, "finalized")? >= last.block_number,
+        };
+        Ok(*last)
+    }
+
+    /// [`Tracker::poll`] every `interval` until final, dropped, or `timeout`
+    /// elapses. `on_poll` sees every intermediate confirmation.
+    pub fn wait<T: Transport>(
+        &mut self,
+        node: &T,
+        timeout: Duration,
+        interval: Duration,
+        mut on_poll: impl FnMut(&Confirmation),
+    ) -> Result<Confirmation> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let mut current = self.poll(node)?;
+            if current.is_final || current.dropped {
+                return Ok(current);
+            }
+            on_poll(&current);
+            if Instant::now() + interval > deadline {
+                current.timed_out = true;
+                return Ok(current);
+            }
+            std::thread::sleep(interval);
+        }
+    }
+}
+
+/// Number of the block `tag` points at.
+fn tagged<T: Transport>(node: &T, tag: &str) -> Result<u64> {
+    let header: Option<Header> =
+        node.call("eth_getBlockByNumber", serde_json::json!([tag, false]))?;
+    header
+        .map(|h| h.number.to::<u64>())
+        .ok_or_else(|| WalletError::Rpc(format!("node has no {tag} block")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+    use std::cell::RefCell;
+
+    /// A chain whose head and receipt can be changed between polls.
+    struct Chain {
+        head: RefCell<u64>,
+        receipt: RefCell<Value>,
+        safe: u64,
+    }
+
+    impl Transport for Chain {
+        fn request(&self, method: &str, params: Value) -> Result<Value> {
+            match method {
+                "eth_getTransactionReceipt" => Ok(self.receipt.borrow().clone()),
+                "eth_blockNumber" => Ok(json!(format!("{:#x}", *self.head.borrow()))),
+                "eth_getBlockByNumber" => {
+                    assert_eq!(params[0], "safe");
+                    Ok(json!({ "number": format!("{:#x}", self.safe) }))
+                }
+                other => panic!("unexpected {other}"),
+            }
+        }
+    }
+
+    fn receipt(block: u64, hash: u8) -> Value {
+        json!({ "blockNumber": format!("{block:#x}"), "blockHash": B256::repeat_byte(hash) })
+    }
+
+    #[test]
+    fn follows_reorgs_to_finality() {
+        let chain = Chain {
+            head: RefCell::new(100),
+            receipt: RefCell::new(receipt(100, 1)),
+            safe: 99,
+        };
+        let mut tracker = Tracker::new(B256::repeat_byte(0xEE), 100, Finality::Depth(3));
+        let first = tracker.poll(&chain).unwrap();
+        assert_eq!((first.confirmations, first.is_final), (1, false));
+
+        // Reorged into block 101; counting restarts there.
+        *chain.head.borrow_mut() = 102;
+        *chain.receipt.borrow_mut() = receipt(101, 2);
+        let moved = tracker.poll(&chain).unwrap();
+        assert_eq!((moved.block_number, moved.confirmations), (101, 2));
+        assert_eq!(moved.reorgs, 1);
+
+        *chain.head.borrow_mut() = 103;
+        assert!(tracker.poll(&chain).unwrap().is_final);
+
+        // Gone from the canonical chain.
+        *chain.receipt.borrow_mut() = Value::Null;
+        let dropped = tracker.poll(&chain).unwrap();
+        assert!(dropped.dropped && !dropped.is_final);
+
+        // The `safe` tag is behind block 100, then past it.
+        *chain.receipt.borrow_mut() = receipt(100, 1);
+        let mut safe = Tracker::new(B256::repeat_byte(0xEE), 100, Finality::Safe);
+        assert!(!safe.poll(&chain).unwrap().is_final);
+        let chain = Chain { safe: 100, ..chain };
+        assert!(safe.poll(&chain).unwrap().is_final);
+    }
+
+    #[test]
+    fn parses_finality() {
+        assert_eq!("12".parse::<Finality>(), Ok(Finality::Depth(12)));
+        assert_eq!("safe".parse::<Finality>(), Ok(Finality::Safe));
+        assert_eq!("finalized".parse::<Finality>(), Ok(Finality::Finalized));
+        assert!("0".parse::<Finality>().is_err());
+        assert!("latest".parse::<Finality>().is_err());
+        #[derive(Deserialize)]
+        struct Net {
+            finality: Finality,
+        }
+        let net: Net = toml::from_str("finality = 6").unwrap();
+        assert_eq!(net.finality, Finality::Depth(6));
+        let net: Net = toml::from_str("finality = \"finalized\"").unwrap();
+        assert_eq!(net.finality, Finality::Finalized);
+    }
+}
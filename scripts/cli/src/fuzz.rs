@@ -0,0 +1,263 @@
+//! Fuzzing entry points for the decoders that face untrusted input.
+//!
+//! The cargo-fuzz targets in `fuzz/fuzz_targets/` are thin wrappers over the
+//! functions here, so the properties they check live next to the code and
+//! build (and run, in the tests below) with the rest of the crate. Each
+//! entry point must return normally for every input; a panic is a finding.
+//!
+//! The [`Arbitrary`] constructors bias inputs toward the shapes the decoders
+//! expect: envelopes are usually well-formed ABI or packed encodings with a
+//! byte or two changed, and keystore blobs have correctly sized fields and
+//! cheap Argon2 parameters, so the fuzzer spends its time past the first
+//! length check.
+
+use std::path::PathBuf;
+
+use alloy_primitives::{Address, B256, U256};
+use arbitrary::{Arbitrary, Unstructured};
+
+use crate::batch::Manifest;
+use crate::ct;
+use crate::envelope::{self, Envelope, EnvelopeV2, SCHEME_ML_DSA_65, SignatureEnvelope};
+use crate::keys::{PUBLIC_KEY_LEN, SIGNATURE_LEN};
+use crate::keystore::{Account, Crypto, KEYSTORE_VERSION, KdfParams, SCHEME_ML_DSA_65 as SCHEME};
+use crate::strict::{self, EnvelopeLayout};
+use crate::userop::{self, PackedUserOperation};
+
+/// Argon2 memory cost ceiling for fuzzed keystores, in KiB. Real entries
+/// cost 19 MiB per attempt, which would stall the fuzzer.
+pub const MAX_FUZZ_M_COST: u32 = 64;
+
+impl<'a> Arbitrary<'a> for PackedUserOperation {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(PackedUserOperation {
+            sender: Address::from(u.arbitrary::<[u8; 20]>()?),
+            nonce: U256::from_be_bytes(u.arbitrary::<[u8; 32]>()?),
+            init_code: u.arbitrary()?,
+            call_data: u.arbitrary()?,
+            account_gas_limits: B256::from(u.arbitrary::<[u8; 32]>()?),
+            pre_verification_gas: U256::from_be_bytes(u.arbitrary::<[u8; 32]>()?),
+            gas_fees: B256::from(u.arbitrary::<[u8; 32]>()?),
+            paymaster_and_data: u.arbitrary()?,
+            signature: u.arbitrary()?,
+        })
+    }
+}
+
+/// Bytes that are probably, but not certainly, a signature envelope.
+#[derive(Clone, Debug)]
+pub struct EnvelopeInput {
+    pub layout: EnvelopeLayout,
+    pub data: Vec<u8>,
+}
+
+impl<'a> Arbitrary<'a> for EnvelopeInput {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let layout = *u.choose(&[
+            EnvelopeLayout::Abi,
+            EnvelopeLayout::Packed,
+            EnvelopeLayout::Versioned,
+        ])?;
+        if u.ratio(1, 8)? {
+            return Ok(EnvelopeInput {
+                layout,
+                data: u.arbitrary()?,
+            });
+        }
+        let pk = u.bytes(PUBLIC_KEY_LEN)?;
+        let sig = u.bytes(SIGNATURE_LEN)?;
+        let mut data = match layout {
+            EnvelopeLayout::Abi => envelope::encode_signature_envelope(pk, sig, SCHEME_ML_DSA_65),
+            EnvelopeLayout::Packed => envelope::encode_signature_envelope_packed(pk, sig),
+            EnvelopeLayout::Versioned => versioned(pk, sig, u.arbitrary()?)?.encode(),
+        }
+        .expect("lengths match the scheme");
+        for _ in 0..u.int_in_range(0..=2)? {
+            let idx = u.choose_index(data.len())?;
+            data[idx] = u.arbitrary()?;
+        }
+        if u.ratio(1, 8)? {
+            let extra: Vec<u8> = u.arbitrary()?;
+            data.extend(extra);
+        }
+        Ok(EnvelopeInput { layout, data })
+    }
+}
+
+/// A V1 envelope, or with `ecdsa` a V2 one.
+fn versioned(pk: &[u8], sig: &[u8], ecdsa: Option<[u8; 65]>) -> arbitrary::Result<Envelope> {
+    let pq = SignatureEnvelope {
+        scheme_id: SCHEME_ML_DSA_65,
+        public_key: pk.to_vec(),
+        signature: sig.to_vec(),
+    };
+    Ok(match ecdsa {
+        None => Envelope::V1(pq),
+        Some(ecdsa) => Envelope::V2(EnvelopeV2 {
+            pq,
+            ecdsa_signature: ecdsa.to_vec(),
+        }),
+    })
+}
+
+/// A keystore entry with arbitrary contents and affordable KDF costs.
+#[derive(Clone, Debug)]
+pub struct KeystoreBlob(pub Account);
+
+impl<'a> Arbitrary<'a> for KeystoreBlob {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let hex_of = |u: &mut Unstructured<'a>, len: usize| -> arbitrary::Result<String> {
+            let bytes = if u.ratio(1, 8)? {
+                u.arbitrary::<Vec<u8>>()?
+            } else {
+                u.bytes(len)?.to_vec()
+            };
+            Ok(ct::encode_hex(&bytes))
+        };
+        Ok(KeystoreBlob(Account {
+            version: KEYSTORE_VERSION,
+            name: "fuzz".to_string(),
+            scheme: SCHEME.to_string(),
+            public_key: hex_of(u, PUBLIC_KEY_LEN)?,
+            addresses: Default::default(),
+            signing_schemes: Default::default(),
+            profiles: Default::default(),
+            watch_only: false,
+            crypto: Crypto {
+                kdf: "argon2id".to_string(),
+                kdf_params: KdfParams {
+                    m_cost: u.int_in_range(8..=MAX_FUZZ_M_COST)?,
+                    t_cost: 1,
+                    p_cost: 1,
+                },
+                salt: hex_of(u, 16)?,
+                cipher: "chacha20poly1305".to_string(),
+                nonce: hex_of(u, 12)?,
+                ciphertext: hex_of(u, 48)?,
+                wrapped_key: None,
+            },
+        }))
+    }
+}
+
+/// The userOpHash never panics and, as on-chain, ignores the signature.
+pub fn user_op_hash(op: &PackedUserOperation, entry_point: Address, chain_id: U256) {
+    let hash = userop::compute_user_op_hash(op, entry_point, chain_id);
+    let mut unsigned = op.clone();
+    unsigned.signature.clear();
+    assert_eq!(
+        hash,
+        userop::compute_user_op_hash(&unsigned, entry_point, chain_id)
+    );
+}
+
+/// Whatever the strict checks accept also decodes leniently, to the same
+/// signature, and re-encodes to the input.
+pub fn envelope_decode(input: &EnvelopeInput) {
+    let lenient = match input.layout {
+        EnvelopeLayout::Abi => envelope::decode_signature_envelope(&input.data),
+        EnvelopeLayout::Packed => envelope::decode_signature_envelope_packed(&input.data),
+        EnvelopeLayout::Versioned => Envelope::decode(&input.data).map(|env| env.pq().clone()),
+    };
+    let Ok(strict_sig) = strict::check_envelope(&input.data, input.layout, None) else {
+        return;
+    };
+    let decoded = lenient.expect("strictly valid envelopes decode");
+    assert_eq!(decoded.signature, strict_sig);
+    let reencoded = match input.layout {
+        EnvelopeLayout::Abi => envelope::encode_signature_envelope(
+            &decoded.public_key,
+            &decoded.signature,
+            decoded.scheme_id,
+        ),
+        EnvelopeLayout::Packed => {
+            envelope::encode_signature_envelope_packed(&decoded.public_key, &decoded.signature)
+        }
+        EnvelopeLayout::Versioned => Envelope::decode(&input.data).and_then(|env| env.encode()),
+    };
+    assert_eq!(reencoded.as_deref().ok(), Some(&input.data[..]));
+}
+
+/// Decrypting a corrupted entry fails cleanly. Entries over
+/// [`MAX_FUZZ_M_COST`] (only reachable through [`json`]) are skipped.
+pub fn keystore_decrypt(blob: &KeystoreBlob, password: &[u8]) {
+    let account = &blob.0;
+    if account.crypto.kdf_params.m_cost > MAX_FUZZ_M_COST
+        || account.crypto.kdf_params.t_cost > 2
+        || account.crypto.kdf_params.p_cost > 2
+    {
+        return;
+    }
+    // A forged entry that decrypts would need a ChaCha20-Poly1305 forgery.
+    assert!(account.decrypt_seed(password).is_err());
+}
+
+/// The JSON documents the tools read: UserOperations, keystore entries and
+/// verification manifests. A parsed UserOperation re-serializes to itself.
+pub fn json(data: &[u8]) {
+    if let Ok(op) = serde_json::from_slice::<PackedUserOperation>(data) {
+        let text = serde_json::to_vec(&op).expect("UserOperations serialize");
+        let again: PackedUserOperation =
+            serde_json::from_slice(&text).expect("serialized UserOperations parse");
+        assert_eq!(again, op);
+    }
+    if let Ok(account) = serde_json::from_slice::<Account>(data) {
+        let _ = account.public_key();
+        let _ = account.key_id();
+        keystore_decrypt(&KeystoreBlob(account), b"");
+    }
+    let _ = Manifest::from_json(data, PathBuf::from("."));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ml_dsa::signature::Signer;
+    use rand::RngExt;
+
+    /// A few hundred random inputs through every entry point, as a smoke
+    /// test that the properties hold off the fuzzer too.
+    #[test]
+    fn entry_points_hold_on_random_input() {
+        let mut rng = rand::rng();
+        for _ in 0..200 {
+            let mut raw = vec![0u8; 8192];
+            rng.fill(&mut raw[..]);
+            let mut u = Unstructured::new(&raw);
+            let op = PackedUserOperation::arbitrary(&mut u).unwrap();
+            user_op_hash(&op, Address::ZERO, U256::from(1));
+            envelope_decode(&EnvelopeInput::arbitrary(&mut u).unwrap());
+            json(&serde_json::to_vec(&op).unwrap());
+            json(&raw[..64]);
+        }
+        let mut u = Unstructured::new(&[7u8; 4096]);
+        keystore_decrypt(&KeystoreBlob::arbitrary(&mut u).unwrap(), b"pw");
+    }
+
+    #[test]
+    fn unmodified_envelopes_pass_the_round_trip() {
+        let kp = crate::keys::keygen_from_seed(&[1; 32]);
+        let pk = kp.verifying_key().encode().to_vec();
+        let signature = kp.signing_key().sign(b"fuzz").encode().to_vec();
+        for layout in [
+            EnvelopeLayout::Abi,
+            EnvelopeLayout::Packed,
+            EnvelopeLayout::Versioned,
+        ] {
+            let data = match layout {
+                EnvelopeLayout::Abi => {
+                    envelope::encode_signature_envelope(&pk, &signature, SCHEME_ML_DSA_65)
+                }
+                EnvelopeLayout::Packed => {
+                    envelope::encode_signature_envelope_packed(&pk, &signature)
+                }
+                EnvelopeLayout::Versioned => versioned(&pk, &signature, Some([0x1c; 65]))
+                    .unwrap()
+                    .encode(),
+            }
+            .unwrap();
+            assert!(strict::check_envelope(&data, layout, None).is_ok());
+            envelope_decode(&EnvelopeInput { layout, data });
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! Where an op's calldata gas goes, field by field, for `pq-userop analyze`.
+//!
+//! With a 3,309-byte ML-DSA-65 signature in every op, each byte of
+//! overhead around it is paid for at calldata prices. [`analyze`] splits
+//! `abi.encode(op)` into the words and padding each field takes, prices
+//! them the way [`estimate`](pre_verification::estimate) does (at the
+//! EIP-7623 floor when that binds) and attaches [`Suggestion`]s for bytes
+//! that need not be there.
+
+use std::fmt;
+
+use alloy_primitives::U256;
+use serde::Serialize;
+
+use crate::calldata;
+use crate::error::Result;
+use crate::gas::pre_verification::{self, Calibration, FLOOR_GAS_PER_TOKEN};
+use crate::keys::{PUBLIC_KEY_LEN, SIGNATURE_LEN};
+use crate::simulate;
+use crate::userop::PackedUserOperation;
+
+/// `abi.encode(PackedUserOperation)`'s fields, in order.
+const FIELDS: [&str; 9] = [
+    "sender",
+    "nonce",
+    "initCode",
+    "callData",
+    "accountGasLimits",
+    "preVerificationGas",
+    "gasFees",
+    "paymasterAndData",
+    "signature",
+];
+
+/// paymaster address, verificationGasLimit and postOpGasLimit.
+const PAYMASTER_HEADER_LEN: usize = 20 + 16 + 16;
+
+/// One field's share of the encoded op.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldSize {
+    pub field: &'static str,
+    /// The value's own length.
+    pub bytes: usize,
+    /// With its head word, and for `bytes` fields the length word and the
+    /// padding to a whole word.
+    pub encoded_bytes: usize,
+    pub zero_bytes: usize,
+    pub calldata_gas: u64,
+}
+
+/// A way to make the op smaller.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Suggestion {
+    pub field: &'static str,
+    /// Calldata gas it would save, where that can be known from the op.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub saves_gas: Option<u64>,
+    pub message: String,
+}
+
+impl fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)?;
+        if let Some(gas) = self.saves_gas {
+            write!(f, " (saves ~{gas} gas)")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Breakdown {
+    /// The fields, then `abi`: the tuple's offset word.
+    pub fields: Vec<FieldSize>,
+    pub encoded_bytes: usize,
+    pub zero_bytes: usize,
+    pub calldata_gas: u64,
+    /// Whether bytes are priced at the EIP-7623 floor (10/40 gas per
+    /// zero/non-zero byte) rather than the chain's standard calldata cost.
+    pub floor: bool,
+    /// An unsigned op is analyzed with a signature-sized placeholder.
+    pub placeholder_signature: bool,
+    pub pre_verification_gas: u64,
+    pub suggestions: Vec<Suggestion>,
+}
+
+/// Gas per zero and non-zero byte under the pricing that binds.
+#[derive(Clone, Copy)]
+struct Pricing {
+    zero: u64,
+    non_zero: u64,
+}
+
+impl Pricing {
+    fn gas(self, data: &[u8]) -> u64 {
+        data.iter()
+            .map(|&b| if b == 0 { self.zero } else { self.non_zero })
+            .sum()
+    }
+}
+
+fn word(encoded: &[u8], at: usize) -> usize {
+    U256::from_be_slice(&encoded[at..at + 32]).to::<usize>()
+}
+
+/// Break `op` down under `calibration`.
+pub fn analyze(op: &PackedUserOperation, calibration: &Calibration) -> Result<Breakdown> {
+    let estimate = pre_verification::estimate(op, calibration, None)?;
+    let placeholder_signature = op.signature.is_empty();
+    let costed;
+    let op = if placeholder_signature {
+        costed = PackedUserOperation {
+            signature: vec![0xff; SIGNATURE_LEN],
+            ..op.clone()
+        };
+        &costed
+    } else {
+        op
+    };
+    let floor = estimate.floor_gas.is_some();
+    let pricing = if floor {
+        Pricing {
+            zero: FLOOR_GAS_PER_TOKEN,
+            non_zero: 4 * FLOOR_GAS_PER_TOKEN,
+        }
+    } else {
+        Pricing {
+            zero: calibration.zero_byte,
+            non_zero: calibration.non_zero_byte,
+        }
+    };
+
+    let lengths = [
+        20,
+        32,
+        op.init_code.len(),
+        op.call_data.len(),
+        32,
+        32,
+        32,
+        op.paymaster_and_data.len(),
+        op.signature.len(),
+    ];
+    // A dynamic tuple is encoded behind an offset word; the head follows.
+    let encoded = simulate::encode_user_op(op);
+    let head = word(&encoded, 0);
+    let mut fields = Vec::with_capacity(FIELDS.len() + 1);
+    for (i, (&field, &bytes)) in FIELDS.iter().zip(&lengths).enumerate() {
+        let at = head + 32 * i;
+        let mut slices = vec![&encoded[at..at + 32]];
+        if matches!(
+            field,
+            "initCode" | "callData" | "paymasterAndData" | "signature"
+        ) {
+            let tail = head + word(&encoded, at);
+            slices.push(&encoded[tail..tail + 32 + bytes.next_multiple_of(32)]);
+        }
+        fields.push(FieldSize {
+            field,
+            bytes,
+            encoded_bytes: slices.iter().map(|s| s.len()).sum(),
+            zero_bytes: slices
+                .iter()
+                .flat_map(|s| s.iter())
+                .filter(|&&b| b == 0)
+                .count(),
+            calldata_gas: slices.iter().map(|s| pricing.gas(s)).sum(),
+        });
+    }
+    fields.push(FieldSize {
+        field: "abi",
+        bytes: 0,
+        encoded_bytes: head,
+        zero_bytes: encoded[..head].iter().filter(|&&b| b == 0).count(),
+        calldata_gas: pricing.gas(&encoded[..head]),
+    });
+
+    let calldata_gas = fields.iter().map(|f| f.calldata_gas).sum();
+    let suggestions = suggest(op, &fields, calldata_gas, pricing, floor);
+    Ok(Breakdown {
+        fields,
+        encoded_bytes: encoded.len(),
+        zero_bytes: estimate.zero_bytes,
+        calldata_gas,
+        floor,
+        placeholder_signature,
+        pre_verification_gas: estimate.pre_verification_gas,
+        suggestions,
+    })
+}
+
+fn suggest(
+    op: &PackedUserOperation,
+    fields: &[FieldSize],
+    calldata_gas: u64,
+    pricing: Pricing,
+    floor: bool,
+) -> Vec<Suggestion> {
+    let gas_of = |name| {
+        fields
+            .iter()
+            .find(|f| f.field == name)
+            .map_or(0, |f| f.calldata_gas)
+    };
+    let mut suggestions = Vec::new();
+
+    let payload = op
+        .paymaster_and_data
+        .get(PAYMASTER_HEADER_LEN..)
+        .unwrap_or_default();
+    let padding = payload.iter().rev().take_while(|&&b| b == 0).count();
+    if padding > 0 {
+        suggestions.push(Suggestion {
+            field: "paymasterAndData",
+            saves_gas: Some(padding as u64 * pricing.zero),
+            message: format!(
+                "paymasterAndData has {padding} padding bytes after the paymaster's data; drop them if the paymaster does not read them"
+            ),
+        });
+    }
+
+    let overhead = op.signature.len().saturating_sub(SIGNATURE_LEN);
+    if overhead >= PUBLIC_KEY_LEN {
+        suggestions.push(Suggestion {
+            field: "signature",
+            saves_gas: Some(pricing.gas(&op.signature[..PUBLIC_KEY_LEN])),
+            message: format!(
+                "the signature carries the {PUBLIC_KEY_LEN}-byte public key; a validator holding the key on-chain takes the bare signature"
+            ),
+        });
+    } else if overhead > 0 {
+        suggestions.push(Suggestion {
+            field: "signature",
+            saves_gas: Some(pricing.gas(&op.signature[..overhead])),
+            message: format!(
+                "the signature has {overhead} bytes of envelope around ML-DSA-65; send it bare where the validator accepts that"
+            ),
+        });
+    }
+
+    if !op.init_code.is_empty() {
+        suggestions.push(Suggestion {
+            field: "initCode",
+            saves_gas: None,
+            message: format!(
+                "initCode ({} bytes, {} gas) is only needed on the account's first op",
+                op.init_code.len(),
+                gas_of("initCode")
+            ),
+        });
+    }
+
+    if calldata::decode_execute(&op.call_data).is_some_and(|calls| calls.len() == 1) {
+        let share = gas_of("signature") * 100 / calldata_gas.max(1);
+        suggestions.push(Suggestion {
+            field: "callData",
+            saves_gas: None,
+            message: format!(
+                "the signature is {share}% of the op's calldata gas; use executeBatch to amortize it over several calls"
+            ),
+        });
+    }
+
+    if floor {
+        suggestions.push(Suggestion {
+            field: "calldata",
+            saves_gas: None,
+            message: format!(
+                "the EIP-7623 floor binds: every byte costs {} (zero) or {} (non-zero) gas, so each byte saved counts at that rate",
+                pricing.zero, pricing.non_zero
+            ),
+        });
+    }
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{Address, B256};
+
+    use crate::calldata::Call;
+
+    #[test]
+    fn fields_add_up_to_the_estimate() {
+        let call = Call {
+            target: Address::repeat_byte(2),
+            value: U256::from(1),
+            data: vec![],
+        };
+        let mut paymaster_and_data = vec![0x11; PAYMASTER_HEADER_LEN + 8];
+        paymaster_and_data.extend([0; 40]);
+        let op = PackedUserOperation {
+            sender: Address::repeat_byte(1),
+            nonce: U256::ZERO,
+            init_code: vec![],
+            call_data: calldata::execute(&[call]),
+            account_gas_limits: B256::ZERO,
+            pre_verification_gas: U256::ZERO,
+            gas_fees: B256::ZERO,
+            paymaster_and_data,
+            signature: vec![],
+        };
+        let calibration = Calibration {
+            calldata_floor: false,
+            ..Calibration::default()
+        };
+        let breakdown = analyze(&op, &calibration).unwrap();
+        let estimate = pre_verification::estimate(&op, &calibration, None).unwrap();
+        assert!(breakdown.placeholder_signature && !breakdown.floor);
+        assert_eq!(breakdown.encoded_bytes, estimate.encoded_bytes);
+        assert_eq!(breakdown.calldata_gas, estimate.calldata_gas);
+        let encoded: usize = breakdown.fields.iter().map(|f| f.encoded_bytes).sum();
+        assert_eq!(encoded, breakdown.encoded_bytes);
+        let signature = &breakdown.fields[8];
+        assert_eq!((signature.bytes, signature.encoded_bytes), (3309, 3392));
+
+        let fields: Vec<_> = breakdown.suggestions.iter().map(|s| s.field).collect();
+        assert_eq!(fields, ["paymasterAndData", "callData"]);
+        assert!(
+            breakdown.suggestions[0]
+                .message
+                .contains("40 padding bytes")
+        );
+
+        // At the floor, the same bytes cost more and the report says so.
+        let floored = analyze(&op, &Calibration::default()).unwrap();
+        assert!(floored.floor && floored.calldata_gas > breakdown.calldata_gas);
+        assert_eq!(floored.suggestions.last().unwrap().field, "calldata");
+    }
+}
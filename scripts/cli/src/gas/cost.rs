@@ -0,0 +1,162 @@
+//! Fee suggestions and what an op can cost at them.
+
+use alloy_primitives::U256;
+use serde::Serialize;
+
+use crate::error::{Result, WalletError};
+use crate::rpc::Transport;
+use crate::userop::PackedUserOperation;
+
+/// Suggested `gasFees` for an op, from the node's latest block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeQuote {
+    pub base_fee: u128,
+    pub max_priority_fee_per_gas: u128,
+    /// The base fee with headroom plus the tip: survives several full
+    /// blocks of base-fee increases before the op stops being includable.
+    /// [`suggest_fees`] allows twice the base fee.
+    pub max_fee_per_gas: u128,
+}
+
+impl FeeQuote {
+    /// Price per gas if included at the current base fee.
+    pub fn gas_price(&self) -> u128 {
+        self.base_fee.saturating_add(self.max_priority_fee_per_gas)
+    }
+}
+
+fn quantity(method: &str, value: U256) -> Result<u128> {
+    u128::try_from(value).map_err(|_| WalletError::Rpc(format!("{method}: fee out of range")))
+}
+
+pub fn suggest_fees<T: Transport>(rpc: &T) -> Result<FeeQuote> {
+    let block = rpc.request("eth_getBlockByNumber", serde_json::json!(["latest", false]))?;
+    let base_fee: U256 = serde_json::from_value(block["baseFeePerGas"].clone())
+        .map_err(|_| WalletError::Rpc("latest block has no baseFeePerGas".into()))?;
+    let base_fee = quantity("eth_getBlockByNumber", base_fee)?;
+    // Nodes without the method (some devnets) get no tip.
+    let tip = match rpc.call::<U256>("eth_maxPriorityFeePerGas", serde_json::json!([])) {
+        Ok(tip) => quantity("eth_maxPriorityFeePerGas", tip)?,
+        Err(WalletError::RpcError { code: -32601, .. }) => 0,
+        Err(e) => return Err(e),
+    };
+    Ok(FeeQuote {
+        base_fee,
+        max_priority_fee_per_gas: tip,
+        max_fee_per_gas: base_fee.saturating_mul(2).saturating_add(tip),
+    })
+}
+
+/// Smallest raise, as a percentage of each fee, that bundlers accept from an
+/// op replacing a pending one with the same sender and nonce (Rundler, Alto
+/// and Skandha all require 10%).
+pub const MIN_REPLACEMENT_INCREASE_PERCENT: u128 = 10;
+
+/// `(maxPriorityFeePerGas, maxFeePerGas)` for a replacement of an op paying
+/// `priority_fee` and `max_fee`: both scaled by `multiplier` and raised by at
+/// least [`MIN_REPLACEMENT_INCREASE_PERCENT`], rounding up.
+pub fn replacement_fees(priority_fee: u128, max_fee: u128, multiplier: f64) -> (u128, u128) {
+    let per_mille = (multiplier.max(0.0) * 1000.0).round() as u128;
+    let bump = |fee: u128| {
+        let scaled = fee.saturating_mul(per_mille).div_ceil(1000);
+        let minimum = fee.saturating_add(
+            fee.saturating_mul(MIN_REPLACEMENT_INCREASE_PERCENT)
+                .div_ceil(100),
+        );
+        scaled.max(minimum)
+    };
+    (bump(priority_fee), bump(max_fee))
+}
+
+/// Cost of an op if it uses every unit of its gas limits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Cost {
+    /// preVerificationGas plus the account and paymaster limits.
+    pub gas: u128,
+    /// At the current base fee plus the op's tip.
+    pub current_wei: u128,
+    /// At the op's `maxFeePerGas`; the prefund the EntryPoint will demand.
+    pub max_wei: u128,
+}
+
+pub fn cost(op: &PackedUserOperation, base_fee: u128) -> Cost {
+    let (pm_verification, pm_post_op) = op.paymaster_gas_limits();
+    let gas = u128::try_from(op.pre_verification_gas)
+        .unwrap_or(u128::MAX)
+        .saturating_add(op.verification_gas_limit())
+        .saturating_add(op.call_gas_limit())
+        .saturating_add(pm_verification)
+        .saturating_add(pm_post_op);
+    let price = op
+        .max_fee_per_gas()
+        .min(base_fee.saturating_add(op.max_priority_fee_per_gas()));
+    Cost {
+        gas,
+        current_wei: gas.saturating_mul(price),
+        max_wei: gas.saturating_mul(op.max_fee_per_gas()),
+    }
+}
+
+/// `wei` as a decimal ETH amount, trailing zeros trimmed.
+pub fn format_ether(wei: u128) -> String {
+    const WEI_PER_ETH: u128 = 1_000_000_000_000_000_000;
+    let frac = format!("{:018}", wei % WEI_PER_ETH);
+    let frac = frac.trim_end_matches('0');
+    if frac.is_empty() {
+        format!("{}", wei / WEI_PER_ETH)
+    } else {
+        format!("{}.{frac}", wei / WEI_PER_ETH)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    struct Node;
+
+    impl Transport for Node {
+        fn request(&self, method: &str, _params: Value) -> Result<Value> {
+            match method {
+                "eth_getBlockByNumber" => Ok(json!({ "baseFeePerGas": "0x3b9aca00" })),
+                _ => Err(WalletError::RpcError {
+                    method: method.into(),
+                    code: -32601,
+                    message: "method not found".into(),
+                    data: None,
+                }),
+            }
+        }
+    }
+
+    #[test]
+    fn suggests_double_base_fee_without_tip_method() {
+        let quote = suggest_fees(&Node).unwrap();
+        assert_eq!(quote.base_fee, 1_000_000_000);
+        assert_eq!(quote.max_priority_fee_per_gas, 0);
+        assert_eq!(quote.max_fee_per_gas, 2_000_000_000);
+    }
+
+    #[test]
+    fn replacement_fees_clear_the_minimum_increase() {
+        let gwei = 1_000_000_000;
+        assert_eq!(
+            replacement_fees(gwei, 10 * gwei, 1.2),
+            (1_200_000_000, 12 * gwei)
+        );
+        // A smaller multiplier still raises both fees by 10%, rounding up.
+        assert_eq!(replacement_fees(101, 1000, 1.05), (112, 1100));
+        assert_eq!(replacement_fees(0, 7, 1.0), (0, 8));
+    }
+
+    #[test]
+    fn formats_ether() {
+        assert_eq!(format_ether(0), "0");
+        assert_eq!(format_ether(1_500_000_000_000_000_000), "1.5");
+        assert_eq!(format_ether(123_000_000_000_000), "0.000123");
+        assert_eq!(format_ether(1), "0.000000000000000001");
+    }
+}
@@ -0,0 +1,260 @@
+//! EIP-1559 fee suggestions from recent blocks (`--fee-speed`).
+//!
+//! [`FeeOracle::quote`] asks the node for `eth_feeHistory` over the last
+//! [`FeeOracle::blocks`] blocks, takes the median of each block's tip at the
+//! speed's reward percentile, and sets
+//! `maxFeePerGas = next base fee × headroom + tip`. Empty blocks are left
+//! out of the median, since their tips say nothing about competition. The
+//! speeds:
+//!
+//! | speed    | tip percentile | base fee headroom |
+//! |----------|----------------|-------------------|
+//! | `slow`   | 10th           | 125%              |
+//! | `normal` | 50th           | 200%              |
+//! | `fast`   | 90th           | 300%              |
+//! | `pNN`    | NNth           | 200%              |
+//!
+//! The tip is never below the chain's minimum: some chains refuse
+//! transactions under a fixed priority fee (Polygon PoS requires 30 gwei).
+//! `--min-priority-fee` raises or lowers it.
+
+use std::fmt;
+
+use alloy_primitives::U256;
+use serde::Deserialize;
+
+use super::cost::{self, FeeQuote};
+use crate::error::{Result, WalletError};
+use crate::rpc::Transport;
+
+/// Blocks of history a quote looks at.
+pub const DEFAULT_BLOCKS: u64 = 20;
+
+const GWEI: u128 = 1_000_000_000;
+
+/// How quickly the op should be included.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum FeeSpeed {
+    Slow,
+    #[default]
+    Normal,
+    Fast,
+    /// Tip at this reward percentile, with normal headroom.
+    Percentile(u8),
+}
+
+/// What a speed means in fee terms.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FeeStrategy {
+    /// `eth_feeHistory` reward percentile the tip is taken at.
+    pub reward_percentile: f64,
+    /// `maxFeePerGas` allows the next base fee to grow to this percentage.
+    pub base_fee_headroom_percent: u128,
+}
+
+impl FeeSpeed {
+    pub fn strategy(self) -> FeeStrategy {
+        let (reward_percentile, base_fee_headroom_percent) = match self {
+            FeeSpeed::Slow => (10.0, 125),
+            FeeSpeed::Normal => (50.0, 200),
+            FeeSpeed::Fast => (90.0, 300),
+            FeeSpeed::Percentile(p) => (f64::from(p), 200),
+        };
+        FeeStrategy {
+            reward_percentile,
+            base_fee_headroom_percent,
+        }
+    }
+}
+
+impl fmt::Display for FeeSpeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeeSpeed::Slow => f.write_str("slow"),
+            FeeSpeed::Normal => f.write_str("normal"),
+            FeeSpeed::Fast => f.write_str("fast"),
+            FeeSpeed::Percentile(p) => write!(f, "p{p}"),
+        }
+    }
+}
+
+impl std::str::FromStr for FeeSpeed {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let percentile = s
+            .strip_prefix('p')
+            .and_then(|p| p.parse::<u8>().ok())
+            .filter(|p| *p <= 100);
+        match (s, percentile) {
+            ("slow", _) => Ok(FeeSpeed::Slow),
+            ("normal", _) => Ok(FeeSpeed::Normal),
+            ("fast", _) => Ok(FeeSpeed::Fast),
+            (_, Some(p)) => Ok(FeeSpeed::Percentile(p)),
+            _ => Err(format!(
+                "unknown fee speed {s:?} (expected slow, normal, fast or p0..p100)"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for FeeSpeed {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, String> {
+        s.parse()
+    }
+}
+
+/// Lowest priority fee `chain_id` accepts, in wei.
+pub fn min_priority_fee(chain_id: u64) -> u128 {
+    match chain_id {
+        // Polygon PoS and Amoy.
+        137 | 80002 => 30 * GWEI,
+        _ => 0,
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FeeHistory {
+    /// One entry per block plus the next block's.
+    base_fee_per_gas: Vec<U256>,
+    #[serde(default)]
+    gas_used_ratio: Vec<f64>,
+    #[serde(default)]
+    reward: Vec<Vec<U256>>,
+}
+
+fn to_wei(value: U256) -> Result<u128> {
+    u128::try_from(value).map_err(|_| WalletError::Rpc("eth_feeHistory: fee out of range".into()))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeOracle {
+    pub blocks: u64,
+    /// Floor for the quoted tip, in wei.
+    pub min_priority_fee: u128,
+}
+
+impl FeeOracle {
+    pub fn for_chain(chain_id: u64) -> Self {
+        FeeOracle {
+            blocks: DEFAULT_BLOCKS,
+            min_priority_fee: min_priority_fee(chain_id),
+        }
+    }
+
+    /// Quote fees for `speed`.
+    pub fn quote<T: Transport>(&self, rpc: &T, speed: FeeSpeed) -> Result<FeeQuote> {
+        self.quote_with(rpc, speed.strategy())
+    }
+
+    /// Quote fees for an explicit strategy. Nodes without `eth_feeHistory`
+    /// get [`cost::suggest_fees`], floored at the minimum tip.
+    pub fn quote_with<T: Transport>(&self, rpc: &T, strategy: FeeStrategy) -> Result<FeeQuote> {
+        let params = serde_json::json!([
+            format!("{:#x}", self.blocks.max(1)),
+            "latest",
+            [strategy.reward_percentile]
+        ]);
+        let history: FeeHistory = match rpc.call("eth_feeHistory", params) {
+            Ok(history) => history,
+            Err(WalletError::RpcError { code: -32601, .. }) => {
+                return Ok(self.floor(cost::suggest_fees(rpc)?));
+            }
+            Err(e) => return Err(e),
+        };
+        let next_base = history
+            .base_fee_per_gas
+            .last()
+            .copied()
+            .ok_or_else(|| WalletError::Rpc("eth_feeHistory: no baseFeePerGas".into()))?;
+        let base_fee = to_wei(next_base)?;
+
+        let mut tips = Vec::with_capacity(history.reward.len());
+        for (i, rewards) in history.reward.iter().enumerate() {
+            let empty = history.gas_used_ratio.get(i).is_some_and(|r| *r == 0.0);
+            if let (false, Some(tip)) = (empty, rewards.first()) {
+                tips.push(to_wei(*tip)?);
+            }
+        }
+        tips.sort_unstable();
+        let tip = tips.get(tips.len() / 2).copied().unwrap_or(0);
+
+        let headroom = base_fee.saturating_mul(strategy.base_fee_headroom_percent) / 100;
+        Ok(self.floor(FeeQuote {
+            base_fee,
+            max_priority_fee_per_gas: tip,
+            max_fee_per_gas: headroom.saturating_add(tip),
+        }))
+    }
+
+    /// Raise `quote`'s tip (and max fee with it) to the minimum.
+    pub fn floor(&self, quote: FeeQuote) -> FeeQuote {
+        let raise = self
+            .min_priority_fee
+            .saturating_sub(quote.max_priority_fee_per_gas);
+        FeeQuote {
+            max_priority_fee_per_gas: quote.max_priority_fee_per_gas + raise,
+            max_fee_per_gas: quote.max_fee_per_gas.saturating_add(raise),
+            ..quote
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    /// Five blocks: tips of 1, 3, 2 and 9 gwei, and one empty block.
+    struct History;
+
+    impl Transport for History {
+        fn request(&self, method: &str, params: Value) -> Result<Value> {
+            assert_eq!(method, "eth_feeHistory");
+            assert_eq!(params[0], "0x14");
+            Ok(json!({
+                "oldestBlock": "0x100",
+                "baseFeePerGas": ["0x1", "0x1", "0x1", "0x1", "0x1", "0x3b9aca00"],
+                "gasUsedRatio": [0.5, 0.9, 0.0, 0.4, 0.7],
+                "reward": [["0x3b9aca00"], ["0xb2d05e00"], ["0x0"], ["0x77359400"], ["0x218711a00"]],
+            }))
+        }
+    }
+
+    #[test]
+    fn quotes_from_fee_history() {
+        let oracle = FeeOracle::for_chain(421614);
+        let quote = oracle.quote(&History, FeeSpeed::Fast).unwrap();
+        // Median of 1, 2, 3, 9 gwei; the empty block's zero is ignored.
+        assert_eq!(quote.base_fee, GWEI);
+        assert_eq!(quote.max_priority_fee_per_gas, 3 * GWEI);
+        assert_eq!(quote.max_fee_per_gas, 3 * GWEI + 3 * GWEI);
+
+        let polygon = FeeOracle::for_chain(137)
+            .quote(&History, FeeSpeed::Slow)
+            .unwrap();
+        assert_eq!(polygon.max_priority_fee_per_gas, 30 * GWEI);
+        assert_eq!(polygon.max_fee_per_gas, GWEI * 125 / 100 + 30 * GWEI);
+    }
+
+    #[test]
+    fn parses_speeds() {
+        for speed in ["slow", "normal", "fast", "p75"] {
+            assert_eq!(speed.parse::<FeeSpeed>().unwrap().to_string(), speed);
+        }
+        assert_eq!(
+            "p75"
+                .parse::<FeeSpeed>()
+                .unwrap()
+                .strategy()
+                .reward_percentile,
+            75.0
+        );
+        assert!("p101".parse::<FeeSpeed>().is_err());
+        assert!("ludicrous".parse::<FeeSpeed>().is_err());
+    }
+}
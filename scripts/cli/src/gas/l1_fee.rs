@@ -0,0 +1,134 @@
+//! Live [`L1DataFee`] estimators that ask the rollup's own precompiles.
+//!
+//! - Arbitrum: `NodeInterface.gasEstimateL1Component` at `0xc8` returns the
+//!   L1 component directly in L2 gas units. `NodeInterface` only exists
+//!   under `eth_call`; it has no code on chain.
+//! - OP stack: `GasPriceOracle.getL1Fee` at `0x4200…000F` returns the fee
+//!   in wei, which is converted to L2 gas at the op's expected gas price.
+//!   The oracle expects a serialized transaction; passing the calldata
+//!   alone leaves out the envelope's ~100 bytes, which the calibration
+//!   margin absorbs.
+
+use alloy_primitives::{Address, Bytes, U256, address};
+use alloy_sol_types::{SolCall, sol};
+
+use super::pre_verification::L1DataFee;
+use crate::error::{Result, WalletError};
+use crate::rpc::Transport;
+
+/// Arbitrum's virtual `NodeInterface` contract.
+pub const NODE_INTERFACE: Address = address!("0x00000000000000000000000000000000000000C8");
+
+/// OP-stack `GasPriceOracle` predeploy.
+pub const GAS_PRICE_ORACLE: Address = address!("0x420000000000000000000000000000000000000F");
+
+sol! {
+    function gasEstimateL1Component(address to, bool contractCreation, bytes data)
+        returns (uint64 gasEstimateForL1, uint256 baseFee, uint256 l1BaseFeeEstimate);
+
+    function getL1Fee(bytes data) returns (uint256);
+}
+
+fn eth_call<T: Transport>(rpc: &T, to: Address, data: Vec<u8>) -> Result<Bytes> {
+    let call = serde_json::json!({ "to": to, "data": Bytes::from(data) });
+    rpc.call("eth_call", serde_json::json!([call, "latest"]))
+}
+
+/// L1 component via `NodeInterface.gasEstimateL1Component`, for a
+/// transaction sent to `entry_point`.
+#[derive(Debug)]
+pub struct ArbitrumL1Fee<'a, T> {
+    pub rpc: &'a T,
+    pub entry_point: Address,
+}
+
+impl<T: Transport> L1DataFee for ArbitrumL1Fee<'_, T> {
+    fn data_gas(&self, calldata: &[u8]) -> Result<u64> {
+        let call = gasEstimateL1ComponentCall {
+            to: self.entry_point,
+            contractCreation: false,
+            data: Bytes::copy_from_slice(calldata),
+        };
+        let ret = eth_call(self.rpc, NODE_INTERFACE, call.abi_encode())?;
+        let decoded = gasEstimateL1ComponentCall::abi_decode_returns(&ret)
+            .map_err(|e| WalletError::Rpc(format!("gasEstimateL1Component: {e}")))?;
+        Ok(decoded.gasEstimateForL1)
+    }
+}
+
+/// L1 fee via `GasPriceOracle.getL1Fee`, converted to gas at `gas_price`.
+#[derive(Debug)]
+pub struct OpStackL1Fee<'a, T> {
+    pub rpc: &'a T,
+    /// Effective L2 gas price the op will pay (base fee plus tip), in wei.
+    pub gas_price: u128,
+}
+
+impl<T: Transport> L1DataFee for OpStackL1Fee<'_, T> {
+    fn data_gas(&self, calldata: &[u8]) -> Result<u64> {
+        if self.gas_price == 0 {
+            return Err(WalletError::Usage("L2 gas price must be non-zero"));
+        }
+        let call = getL1FeeCall {
+            data: Bytes::copy_from_slice(calldata),
+        };
+        let ret = eth_call(self.rpc, GAS_PRICE_ORACLE, call.abi_encode())?;
+        let fee = getL1FeeCall::abi_decode_returns(&ret)
+            .map_err(|e| WalletError::Rpc(format!("getL1Fee: {e}")))?;
+        let gas = fee.div_ceil(U256::from(self.gas_price));
+        Ok(u64::try_from(gas).unwrap_or(u64::MAX))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_sol_types::SolValue;
+    use serde_json::Value;
+
+    /// Answers every `eth_call` with `ret`, checking the target.
+    struct Node {
+        to: Address,
+        ret: Vec<u8>,
+    }
+
+    impl Transport for Node {
+        fn request(&self, method: &str, params: Value) -> Result<Value> {
+            assert_eq!(method, "eth_call");
+            assert_eq!(params[0]["to"], serde_json::json!(self.to));
+            Ok(serde_json::json!(Bytes::from(self.ret.clone())))
+        }
+    }
+
+    #[test]
+    fn arbitrum_returns_l2_gas_units() {
+        let node = Node {
+            to: NODE_INTERFACE,
+            ret: (123_456u64, U256::from(100_000_000u64), U256::from(30u64)).abi_encode_params(),
+        };
+        let l1 = ArbitrumL1Fee {
+            rpc: &node,
+            entry_point: Address::ZERO,
+        };
+        assert_eq!(l1.data_gas(&[1, 2, 3]).unwrap(), 123_456);
+    }
+
+    #[test]
+    fn op_stack_converts_wei_to_gas() {
+        let node = Node {
+            to: GAS_PRICE_ORACLE,
+            ret: U256::from(1_000_000_001u64).abi_encode(),
+        };
+        let l1 = OpStackL1Fee {
+            rpc: &node,
+            gas_price: 1_000,
+        };
+        // Rounded up so the op never under-pays the bundler.
+        assert_eq!(l1.data_gas(&[1]).unwrap(), 1_000_001);
+        let free = OpStackL1Fee {
+            rpc: &node,
+            gas_price: 0,
+        };
+        assert!(free.data_gas(&[1]).is_err());
+    }
+}
@@ -0,0 +1,8 @@
+//! Local gas estimation for UserOperations, used when a bundler can't (or
+//! won't) estimate for us.
+
+pub mod breakdown;
+pub mod cost;
+pub mod fee_oracle;
+pub mod l1_fee;
+pub mod pre_verification;
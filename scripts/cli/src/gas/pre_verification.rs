@@ -0,0 +1,295 @@
+//! preVerificationGas from the op's serialized size.
+//!
+//! preVerificationGas pays for what the EntryPoint can't meter: the op's
+//! share of the bundle transaction's intrinsic gas, its calldata, and on
+//! rollups its share of the L1 data fee. [`estimate`] follows the same model
+//! as eth-infinitism's `calcPreVerificationGas`, plus the EIP-7623 calldata
+//! floor and an optional [`L1DataFee`] hook, with per-chain constants in
+//! [`Calibration::for_chain`].
+//!
+//! An unsigned op is costed with a placeholder of [`SIGNATURE_LEN`] non-zero
+//! bytes, so the estimate already covers the ML-DSA-65 signature it will
+//! carry — which is most of the op and dwarfs an ECDSA account's 65 bytes.
+
+use alloy_primitives::Address;
+use serde::Serialize;
+
+use crate::error::{Result, WalletError};
+use crate::keys::SIGNATURE_LEN;
+use crate::simulate;
+use crate::userop::PackedUserOperation;
+
+/// EIP-7623 floor price per calldata token (a zero byte is one token, a
+/// non-zero byte four).
+pub const FLOOR_GAS_PER_TOKEN: u64 = 10;
+
+/// How a rollup charges for posting the bundle to L1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rollup {
+    /// L1 cost folded into L2 gas (`NodeInterface.gasEstimateL1Component`).
+    Arbitrum,
+    /// L1 fee charged separately in wei (`GasPriceOracle.getL1Fee`).
+    OpStack,
+}
+
+/// Per-chain constants for [`estimate`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Calibration {
+    pub chain: &'static str,
+    /// Intrinsic transaction gas, split across the bundle.
+    pub fixed: u64,
+    /// EntryPoint bookkeeping per op not charged to its gas limits.
+    pub per_user_op: u64,
+    /// Extra per 32-byte word of the encoded op (memory copies).
+    pub per_user_op_word: u64,
+    pub zero_byte: u64,
+    pub non_zero_byte: u64,
+    /// Ops sharing one `handleOps` transaction; 1 makes the op pay the whole
+    /// intrinsic cost, which is what a lone op on a quiet chain gets.
+    pub bundle_size: u64,
+    /// Whether the chain enforces the EIP-7623 calldata floor.
+    pub calldata_floor: bool,
+    /// Safety margin added on top, in percent.
+    pub margin_percent: u64,
+    /// `Some` if an L1 data fee applies on top of L2 execution.
+    pub rollup: Option<Rollup>,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration {
+            chain: "unknown",
+            fixed: 21_000,
+            per_user_op: 18_300,
+            per_user_op_word: 4,
+            zero_byte: 4,
+            non_zero_byte: 16,
+            bundle_size: 1,
+            calldata_floor: true,
+            margin_percent: 0,
+            rollup: None,
+        }
+    }
+}
+
+impl Calibration {
+    /// Constants for `chain_id`; unknown chains get L1 defaults with the
+    /// EIP-7623 floor on, which over- rather than under-estimates.
+    pub fn for_chain(chain_id: u64) -> Self {
+        let base = Calibration::default();
+        match chain_id {
+            1 => Calibration {
+                chain: "ethereum",
+                ..base
+            },
+            11155111 => Calibration {
+                chain: "sepolia",
+                ..base
+            },
+            // L1 prices move between estimate and inclusion, hence the margin.
+            42161 | 42170 | 421614 | 412346 => Calibration {
+                chain: match chain_id {
+                    42161 => "arbitrum-one",
+                    42170 => "arbitrum-nova",
+                    421614 => "arbitrum-sepolia",
+                    _ => "nitro-devnode",
+                },
+                calldata_floor: false,
+                margin_percent: 10,
+                rollup: Some(Rollup::Arbitrum),
+                ..base
+            },
+            10 | 8453 | 11155420 | 84532 => Calibration {
+                chain: match chain_id {
+                    10 => "optimism",
+                    8453 => "base",
+                    11155420 => "optimism-sepolia",
+                    _ => "base-sepolia",
+                },
+                margin_percent: 10,
+                rollup: Some(Rollup::OpStack),
+                ..base
+            },
+            _ => base,
+        }
+    }
+}
+
+/// Supplies the L1 data component of an op's cost, in L2 gas units.
+pub trait L1DataFee {
+    /// L2 gas covering the L1 fee for a transaction with this calldata (a
+    /// single-op `handleOps` call).
+    fn data_gas(&self, calldata: &[u8]) -> Result<u64>;
+}
+
+/// Offline [`L1DataFee`] from fixed prices: the calldata's L1 gas (4/16 per
+/// zero/non-zero byte, uncompressed) at `l1_base_fee`, converted to L2 gas at
+/// `l2_gas_price`. An upper bound, since rollups compress before posting.
+#[derive(Clone, Copy, Debug)]
+pub struct StaticL1Fee {
+    pub l1_base_fee: u128,
+    pub l2_gas_price: u128,
+}
+
+impl L1DataFee for StaticL1Fee {
+    fn data_gas(&self, calldata: &[u8]) -> Result<u64> {
+        if self.l2_gas_price == 0 {
+            return Err(WalletError::Usage("L2 gas price must be non-zero"));
+        }
+        let (zero, non_zero) = count_bytes(calldata);
+        let l1_gas = (zero * 4 + non_zero * 16) as u128;
+        let fee = l1_gas.saturating_mul(self.l1_base_fee);
+        Ok(u64::try_from(fee.div_ceil(self.l2_gas_price)).unwrap_or(u64::MAX))
+    }
+}
+
+/// An [`estimate`] and how it was reached.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreVerificationGas {
+    pub pre_verification_gas: u64,
+    /// Length of `abi.encode(op)`, signature placeholder included.
+    pub encoded_bytes: usize,
+    pub zero_bytes: usize,
+    pub calldata_gas: u64,
+    /// Intrinsic share plus per-op and per-word overhead.
+    pub overhead_gas: u64,
+    /// EIP-7623 floor, when it exceeded the standard cost.
+    pub floor_gas: Option<u64>,
+    pub l1_data_gas: u64,
+    pub calibration: Calibration,
+}
+
+fn count_bytes(data: &[u8]) -> (u64, u64) {
+    let zero = data.iter().filter(|&&b| b == 0).count() as u64;
+    (zero, data.len() as u64 - zero)
+}
+
+/// Estimate preVerificationGas for `op` under `calibration`, adding the L1
+/// data component from `l1` if given.
+pub fn estimate(
+    op: &PackedUserOperation,
+    calibration: &Calibration,
+    l1: Option<&dyn L1DataFee>,
+) -> Result<PreVerificationGas> {
+    let costed;
+    let op = if op.signature.is_empty() {
+        costed = PackedUserOperation {
+            signature: vec![0xff; SIGNATURE_LEN],
+            ..op.clone()
+        };
+        &costed
+    } else {
+        op
+    };
+    let c = calibration;
+
+    let encoded = simulate::encode_user_op(op);
+    let (zero, non_zero) = count_bytes(&encoded);
+    let calldata_gas = zero * c.zero_byte + non_zero * c.non_zero_byte;
+    let fixed_share = c.fixed.div_ceil(c.bundle_size.max(1));
+    let words = encoded.len().div_ceil(32) as u64;
+    let overhead_gas = fixed_share + c.per_user_op + c.per_user_op_word * words;
+    let standard = calldata_gas + overhead_gas;
+
+    // EIP-7623 charges max(standard + execution, floor). Execution isn't
+    // credited here: an op whose validation turns out cheap must still pay.
+    let floor = (zero + 4 * non_zero) * FLOOR_GAS_PER_TOKEN + fixed_share;
+    let floor_gas = (c.calldata_floor && floor > standard).then_some(floor);
+    let l2_gas = floor_gas.unwrap_or(standard);
+
+    let l1_data_gas = match l1 {
+        Some(l1) => l1.data_gas(&simulate::encode_handle_ops(
+            std::slice::from_ref(op),
+            Address::ZERO,
+        ))?,
+        None => 0,
+    };
+
+    let total = l2_gas.saturating_add(l1_data_gas);
+    Ok(PreVerificationGas {
+        pre_verification_gas: total.saturating_add(total * c.margin_percent / 100),
+        encoded_bytes: encoded.len(),
+        zero_bytes: zero as usize,
+        calldata_gas,
+        overhead_gas,
+        floor_gas,
+        l1_data_gas,
+        calibration: calibration.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{B256, U256, address};
+
+    fn op(call_data: Vec<u8>, signature: Vec<u8>) -> PackedUserOperation {
+        PackedUserOperation {
+            sender: address!("0x1111111111111111111111111111111111111111"),
+            nonce: U256::ZERO,
+            init_code: vec![],
+            call_data,
+            account_gas_limits: B256::ZERO,
+            pre_verification_gas: U256::ZERO,
+            gas_fees: B256::ZERO,
+            paymaster_and_data: vec![],
+            signature,
+        }
+    }
+
+    #[test]
+    fn prices_zero_and_non_zero_bytes() {
+        let cal = Calibration {
+            calldata_floor: false,
+            ..Calibration::default()
+        };
+        let zeros = estimate(&op(vec![0; 64], vec![1]), &cal, None).unwrap();
+        let ones = estimate(&op(vec![1; 64], vec![1]), &cal, None).unwrap();
+        assert_eq!(ones.encoded_bytes, zeros.encoded_bytes);
+        assert_eq!(
+            ones.pre_verification_gas - zeros.pre_verification_gas,
+            64 * (16 - 4)
+        );
+        assert_eq!(
+            zeros.pre_verification_gas,
+            zeros.calldata_gas + zeros.overhead_gas
+        );
+    }
+
+    #[test]
+    fn unsigned_op_is_costed_with_full_signature() {
+        let cal = Calibration::for_chain(1);
+        let unsigned = estimate(&op(vec![], vec![]), &cal, None).unwrap();
+        let signed = estimate(&op(vec![], vec![0xff; SIGNATURE_LEN]), &cal, None).unwrap();
+        assert_eq!(unsigned, signed);
+        // A signature-sized op is calldata-heavy enough for the floor to bind.
+        assert!(unsigned.floor_gas.is_some());
+        assert!(unsigned.pre_verification_gas > SIGNATURE_LEN as u64 * 40);
+    }
+
+    #[test]
+    fn rollups_add_l1_data_gas_and_margin() {
+        let cal = Calibration::for_chain(412346);
+        assert_eq!(cal.rollup, Some(Rollup::Arbitrum));
+        let user_op = op(vec![], vec![]);
+        let without = estimate(&user_op, &cal, None).unwrap();
+        let l1 = StaticL1Fee {
+            l1_base_fee: 30_000_000_000,
+            l2_gas_price: 100_000_000,
+        };
+        let with = estimate(&user_op, &cal, Some(&l1)).unwrap();
+        assert!(with.l1_data_gas > 0);
+        assert_eq!(
+            with.pre_verification_gas,
+            (without.calldata_gas + without.overhead_gas + with.l1_data_gas) * 110 / 100
+        );
+        let free = StaticL1Fee {
+            l2_gas_price: 0,
+            ..l1
+        };
+        assert!(estimate(&user_op, &cal, Some(&free)).is_err());
+    }
+}
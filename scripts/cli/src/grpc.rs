@@ -0,0 +1,460 @@
+//! gRPC transport for [`Daemon`] (`grpc` feature).
+//!
+//! Serves the `pq.signer.v1.Signer` service from `proto/pq/signer/v1/`.
+//! Requests are only decoded here; signing, the policy check and the audit
+//! log are the daemon's. With [`tls_config`] the server presents a
+//! certificate, and given a client CA it also requires clients to present
+//! one signed by it (mTLS).
+//!
+//! Callers authenticate with an `authorization: Bearer <token>` header, their
+//! client certificate, or both, as the daemon's principals require. `Health`
+//! is open to anyone, for load balancers; it names no accounts.
+//!
+//! [`connect`] is the other end, for tools such as `pq-approve`.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use alloy_primitives::{Address, B256};
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::{
+    Certificate, Channel, ClientTlsConfig, Endpoint, Identity, Server, ServerTlsConfig,
+};
+use tonic::{Request, Response, Status};
+
+use crate::auth::{Credentials, Principal, Refusal};
+use crate::daemon::{Daemon, OpRequest};
+use crate::error::{Result, WalletError};
+use crate::proposal::{Decision, Proposal, Status as ProposalStatus};
+use crate::userop::{ENTRY_POINT_V07, PackedUserOperation};
+
+pub mod pb {
+    tonic::include_proto!("pq.signer.v1");
+}
+
+use pb::signer_client::SignerClient;
+use pb::signer_server::{Signer, SignerServer};
+
+pub struct SignerService {
+    daemon: Arc<Daemon>,
+}
+
+impl SignerService {
+    pub fn new(daemon: Arc<Daemon>) -> Self {
+        SignerService { daemon }
+    }
+
+    fn caller<T>(&self, request: &Request<T>) -> std::result::Result<Arc<Principal>, Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let certs = request.peer_certs();
+        let credentials = Credentials {
+            token,
+            client_cert: certs.as_ref().and_then(|c| c.first()).map(|c| c.as_ref()),
+        };
+        self.daemon.authenticate(&credentials).map_err(status)
+    }
+}
+
+/// Policy denials, ops a delegation does not cover and out-of-scope accounts
+/// are `PERMISSION_DENIED`, refused decisions on proposals
+/// `FAILED_PRECONDITION`, and
+/// accounts the daemon does not hold `NOT_FOUND`; the message is the error as
+/// the CLI prints it.
+fn status(e: WalletError) -> Status {
+    let message = e.to_string();
+    match e {
+        WalletError::Refused(Refusal::Unauthenticated) => Status::unauthenticated(message),
+        WalletError::Refused(Refusal::Forbidden { .. })
+        | WalletError::Policy(_)
+        | WalletError::Delegation(_) => Status::permission_denied(message),
+        WalletError::Refused(Refusal::RateLimited { .. }) => Status::resource_exhausted(message),
+        WalletError::Keystore(_) => Status::not_found(message),
+        WalletError::Proposal(_) => Status::failed_precondition(message),
+        WalletError::Cancelled => Status::cancelled(message),
+        WalletError::Usage(_)
+        | WalletError::InvalidLength { .. }
+        | WalletError::InvalidUserOp(_) => Status::invalid_argument(message),
+        _ => Status::internal(message),
+    }
+}
+
+fn proposal(proposal: Proposal) -> std::result::Result<pb::Proposal, Status> {
+    let user_op_json =
+        serde_json::to_string(&proposal.user_op).map_err(|e| Status::internal(e.to_string()))?;
+    Ok(pb::Proposal {
+        id: proposal.id.to_vec(),
+        account: proposal.account,
+        principal: proposal.principal,
+        chain_id: proposal.chain_id,
+        entry_point: proposal.entry_point.to_vec(),
+        user_op_json,
+        user_op_hash: proposal.user_op_hash.to_vec(),
+        reason: proposal.reason,
+        created_at: proposal.created_at,
+        expires_at: proposal.expires_at,
+        status: proposal.status.to_string(),
+        decided_by: proposal.decided_by.map(|h| h.to_vec()).unwrap_or_default(),
+    })
+}
+
+fn fixed<const N: usize>(what: &'static str, bytes: &[u8]) -> std::result::Result<[u8; N], Status> {
+    bytes.try_into().map_err(|_| {
+        status(WalletError::InvalidLength {
+            what,
+            expected: N,
+            actual: bytes.len(),
+        })
+    })
+}
+
+#[tonic::async_trait]
+impl Signer for SignerService {
+    async fn sign_hash(
+        &self,
+        request: Request<pb::SignHashRequest>,
+    ) -> std::result::Result<Response<pb::SignHashResponse>, Status> {
+        let caller = self.caller(&request)?;
+        let request = request.into_inner();
+        let digest = B256::from(fixed::<32>("digest", &request.digest)?);
+        let signed = self
+            .daemon
+            .sign_hash(&caller, &request.account, digest, std::future::pending())
+            .await
+            .map_err(status)?;
+        Ok(Response::new(pb::SignHashResponse {
+            signature: signed.signature,
+            key_id: signed.key_id.to_vec(),
+        }))
+    }
+
+    async fn sign_user_op(
+        &self,
+        request: Request<pb::SignUserOpRequest>,
+    ) -> std::result::Result<Response<pb::SignUserOpResponse>, Status> {
+        let caller = self.caller(&request)?;
+        let request = request.into_inner();
+        let op: PackedUserOperation = serde_json::from_str(&request.user_op_json)
+            .map_err(|e| status(WalletError::InvalidUserOp(e.to_string())))?;
+        let entry_point = if request.entry_point.is_empty() {
+            ENTRY_POINT_V07
+        } else {
+            Address::from(fixed::<20>("entry_point", &request.entry_point)?)
+        };
+        let signed = self
+            .daemon
+            .sign_user_op(
+                &caller,
+                &request.account,
+                OpRequest {
+                    user_op: op,
+                    entry_point,
+                    chain_id: request.chain_id,
+                    approved: request.approved,
+                },
+                std::future::pending(),
+            )
+            .await;
+        let signed = match signed {
+            Err(WalletError::PendingApproval(id)) => {
+                return Ok(Response::new(pb::SignUserOpResponse {
+                    proposal_id: id.to_vec(),
+                    ..Default::default()
+                }));
+            }
+            signed => signed.map_err(status)?,
+        };
+        let user_op_json =
+            serde_json::to_string(&signed.user_op).map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(pb::SignUserOpResponse {
+            user_op_json,
+            user_op_hash: signed.user_op_hash.to_vec(),
+            signature: signed.user_op.signature,
+            proposal_id: Vec::new(),
+        }))
+    }
+
+    async fn list_proposals(
+        &self,
+        request: Request<pb::ListProposalsRequest>,
+    ) -> std::result::Result<Response<pb::ListProposalsResponse>, Status> {
+        let caller = self.caller(&request)?;
+        let pending_only = request.get_ref().pending_only;
+        let proposals = self
+            .daemon
+            .proposals(&caller)
+            .map_err(status)?
+            .into_iter()
+            .filter(|p| !pending_only || p.status == ProposalStatus::Pending)
+            .map(proposal)
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(Response::new(pb::ListProposalsResponse { proposals }))
+    }
+
+    async fn get_proposal(
+        &self,
+        request: Request<pb::GetProposalRequest>,
+    ) -> std::result::Result<Response<pb::Proposal>, Status> {
+        let caller = self.caller(&request)?;
+        let id = B256::from(fixed::<32>("id", &request.get_ref().id)?);
+        let found = self.daemon.proposal(&caller, id).map_err(status)?;
+        Ok(Response::new(proposal(found)?))
+    }
+
+    async fn decide_proposal(
+        &self,
+        request: Request<pb::DecideProposalRequest>,
+    ) -> std::result::Result<Response<pb::Proposal>, Status> {
+        let caller = self.caller(&request)?;
+        let request = request.into_inner();
+        let id = B256::from(fixed::<32>("id", &request.id)?);
+        let decision = Decision {
+            approve: request.approve,
+            public_key: request.public_key,
+            signature: request.signature,
+        };
+        let decided = self
+            .daemon
+            .decide(&caller, id, &decision, std::future::pending())
+            .await
+            .map_err(status)?;
+        Ok(Response::new(proposal(decided)?))
+    }
+
+    async fn list_accounts(
+        &self,
+        request: Request<pb::ListAccountsRequest>,
+    ) -> std::result::Result<Response<pb::ListAccountsResponse>, Status> {
+        let caller = self.caller(&request)?;
+        let accounts = self
+            .daemon
+            .accounts()
+            .filter(|account| caller.may_use(&account.name))
+            .map(|account| pb::Account {
+                name: account.name.clone(),
+                key_id: account.key_id.to_vec(),
+                addresses: account
+                    .addresses
+                    .iter()
+                    .map(|(chain_id, address)| (*chain_id, address.to_vec()))
+                    .collect(),
+            })
+            .collect();
+        Ok(Response::new(pb::ListAccountsResponse { accounts }))
+    }
+
+    async fn get_public_key(
+        &self,
+        request: Request<pb::GetPublicKeyRequest>,
+    ) -> std::result::Result<Response<pb::GetPublicKeyResponse>, Status> {
+        let caller = self.caller(&request)?;
+        let account = self
+            .daemon
+            .account(&caller, &request.get_ref().account)
+            .map_err(status)?;
+        Ok(Response::new(pb::GetPublicKeyResponse {
+            public_key: account.public_key.clone(),
+            key_id: account.key_id.to_vec(),
+        }))
+    }
+
+    async fn health(
+        &self,
+        _request: Request<pb::HealthRequest>,
+    ) -> std::result::Result<Response<pb::HealthResponse>, Status> {
+        Ok(Response::new(pb::HealthResponse {
+            status: "SERVING".to_string(),
+            accounts: self.daemon.accounts().count() as u32,
+            uptime_seconds: self.daemon.uptime().as_secs(),
+            available_slots: self.daemon.accounts().map(|a| a.available() as u64).sum(),
+        }))
+    }
+}
+
+/// Server TLS with `cert_pem` and `key_pem`; with `client_ca_pem`, clients
+/// must present a certificate it signed.
+pub fn tls_config(
+    cert_pem: &[u8],
+    key_pem: &[u8],
+    client_ca_pem: Option<&[u8]>,
+) -> ServerTlsConfig {
+    let tls = ServerTlsConfig::new().identity(Identity::from_pem(cert_pem, key_pem));
+    match client_ca_pem {
+        Some(ca) => tls.client_ca_root(Certificate::from_pem(ca)),
+        None => tls,
+    }
+}
+
+/// Client TLS trusting `ca_pem`, presenting `identity` (certificate and key
+/// PEM) to daemons that require client certificates.
+pub fn client_tls_config(ca_pem: &[u8], identity: Option<(&[u8], &[u8])>) -> ClientTlsConfig {
+    let tls = ClientTlsConfig::new().ca_certificate(Certificate::from_pem(ca_pem));
+    match identity {
+        Some((cert, key)) => tls.identity(Identity::from_pem(cert, key)),
+        None => tls,
+    }
+}
+
+/// Sends `authorization: Bearer <token>` with each request, when there is a
+/// token.
+#[derive(Clone)]
+pub struct Bearer(Option<MetadataValue<Ascii>>);
+
+impl Interceptor for Bearer {
+    fn call(&mut self, mut request: Request<()>) -> std::result::Result<Request<()>, Status> {
+        if let Some(token) = &self.0 {
+            request
+                .metadata_mut()
+                .insert("authorization", token.clone());
+        }
+        Ok(request)
+    }
+}
+
+pub type Client = SignerClient<InterceptedService<Channel, Bearer>>;
+
+/// A client of the daemon at `url` (`http://` or, with `tls`, `https://`).
+pub async fn connect(
+    url: &str,
+    tls: Option<ClientTlsConfig>,
+    token: Option<&str>,
+) -> Result<Client> {
+    let mut endpoint = Endpoint::from_shared(url.to_string())
+        .map_err(|e| WalletError::Config(format!("{url}: {e}")))?;
+    if let Some(tls) = tls {
+        endpoint = endpoint
+            .tls_config(tls)
+            .map_err(|e| WalletError::Config(format!("tls: {e}")))?;
+    }
+    let token = token
+        .map(|token| format!("Bearer {token}").parse())
+        .transpose()
+        .map_err(|_| WalletError::Usage("the token is not a valid header value"))?;
+    let channel = endpoint
+        .connect()
+        .await
+        .map_err(|e| WalletError::Rpc(format!("{url}: {e}")))?;
+    Ok(SignerClient::with_interceptor(channel, Bearer(token)))
+}
+
+/// Serve `daemon` on `listener` until `shutdown` completes.
+pub async fn serve(
+    daemon: Arc<Daemon>,
+    listener: tokio::net::TcpListener,
+    tls: Option<ServerTlsConfig>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    let mut server = Server::builder();
+    if let Some(tls) = tls {
+        server = server
+            .tls_config(tls)
+            .map_err(|e| WalletError::Config(format!("tls: {e}")))?;
+    }
+    server
+        .add_service(SignerServer::new(SignerService::new(daemon)))
+        .serve_with_incoming_shutdown(
+            tonic::transport::server::TcpIncoming::from(listener),
+            shutdown,
+        )
+        .await
+        .map_err(|e| WalletError::Rpc(format!("grpc server: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditLog;
+    use crate::auth::AuthConfig;
+    use crate::backend::Backend;
+    use crate::keys::SEED_LEN;
+    use crate::keystore::{Account, KdfParams};
+    use sha2::{Digest, Sha256};
+    use zeroize::Zeroizing;
+
+    #[tokio::test]
+    async fn serves_the_signer_over_grpc() {
+        let dir = std::env::temp_dir().join(format!("pq-grpc-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        let seed = Zeroizing::new([3u8; SEED_LEN]);
+        let kdf = KdfParams {
+            m_cost: 64,
+            t_cost: 1,
+            p_cost: 1,
+        };
+        let account = Account::encrypt("alice", &seed, b"pw", kdf).unwrap();
+        let auth = AuthConfig::from_toml(&format!(
+            "[[principal]]\nname = \"app\"\ntoken_sha256 = \"0x{}\"\naccounts = [\"alice\", \"bob\"]",
+            crate::ct::encode_hex(&Sha256::digest("t0ken"))
+        ))
+        .unwrap();
+        let mut daemon = Daemon::new(AuditLog::open(dir.join("audit.jsonl")), Backend::RustCrypto)
+            .with_auth(&auth);
+        daemon.add_account(&account, seed, 1).unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve(Arc::new(daemon), listener, None, async {
+            stopped.await.ok();
+        }));
+
+        let channel = tonic::transport::Endpoint::from_shared(format!("http://{addr}"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let mut anonymous = SignerClient::new(channel.clone());
+        let mut client = SignerClient::with_interceptor(channel, |mut request: Request<()>| {
+            let token = "Bearer t0ken".parse().unwrap();
+            request.metadata_mut().insert("authorization", token);
+            Ok(request)
+        });
+        let health = anonymous
+            .health(pb::HealthRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!((health.status.as_str(), health.accounts), ("SERVING", 1));
+
+        let signed = client
+            .sign_hash(pb::SignHashRequest {
+                account: "alice".to_string(),
+                digest: vec![7; 32],
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let public_key = account.public_key().unwrap();
+        assert!(Backend::RustCrypto.verify(&public_key, &[7; 32], &[], &signed.signature));
+        assert_eq!(signed.key_id, account.key_id().unwrap().to_vec());
+
+        let missing = client
+            .get_public_key(pb::GetPublicKeyRequest {
+                account: "bob".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(missing.code(), tonic::Code::NotFound);
+        let short = client
+            .sign_hash(pb::SignHashRequest {
+                account: "alice".to_string(),
+                digest: vec![7; 31],
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(short.code(), tonic::Code::InvalidArgument);
+        let refused = anonymous
+            .list_accounts(pb::ListAccountsRequest {})
+            .await
+            .unwrap_err();
+        assert_eq!(refused.code(), tonic::Code::Unauthenticated);
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,361 @@
+//! Rate limits and anomaly heuristics in `pq-signerd`'s signing path.
+//!
+//! They live in the signing policy, next to the rules they back up, and the
+//! policy hash covers them:
+//!
+//! ```toml
+//! [rate]
+//! signatures_per_minute = 30
+//! value_per_hour = "2000000000000000000"     # wei, per chain
+//!
+//! [anomaly]
+//! new_destination_value = "500000000000000000"
+//! max_denials = 5                            # within denial_window_secs
+//! denial_window_secs = 300
+//! cooldown_secs = 900
+//! action = "cooldown"                        # or "approval"
+//! ```
+//!
+//! Limits apply to each account on its own. Every signature counts towards
+//! `signatures_per_minute`, hashes and ops alike. `value_per_hour` is a
+//! sliding hour of what the account's ops moved on a chain.
+//!
+//! An account trips an anomaly when one of its ops sends at least
+//! `new_destination_value` (by default the policy's `max_value_per_op`) to
+//! a target it has never called, or when `max_denials` of its ops are
+//! refused by the policy within the window. With `action = "cooldown"` the
+//! account then signs nothing for `cooldown_secs`. With `"approval"` its
+//! ops need the same human approval as ops over `approval_above` until
+//! then, and it signs no bare hashes.
+//!
+//! The contract enforces none of this; it is defense in depth for a
+//! compromised caller. Windows are kept in memory and start empty when the
+//! daemon does. The targets an account has called are kept in the
+//! [`SpendLedger`](crate::policy::SpendLedger).
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use alloy_primitives::{Address, U256};
+use serde::{Deserialize, Serialize};
+
+use crate::calldata::Call;
+use crate::policy::{Denial, Policy, Rule};
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = 3600;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimits {
+    /// Most signatures an account may make in any minute.
+    pub signatures_per_minute: Option<u32>,
+    /// Most native value (wei) an account's ops may move on a chain in any
+    /// hour.
+    pub value_per_hour: Option<U256>,
+}
+
+/// What tripping an anomaly does to the account.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    /// Refuse everything until the cool-down ends.
+    #[default]
+    Cooldown,
+    /// Sign only approved ops until it ends.
+    Approval,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnomalyRules {
+    /// Value that makes a call to a first-time target an anomaly
+    /// [default: the policy's `max_value_per_op`].
+    pub new_destination_value: Option<U256>,
+    /// Policy denials within `denial_window_secs` that are an anomaly.
+    pub max_denials: Option<u32>,
+    #[serde(default = "default_denial_window")]
+    pub denial_window_secs: u64,
+    #[serde(default = "default_cooldown")]
+    pub cooldown_secs: u64,
+    #[serde(default)]
+    pub action: Action,
+}
+
+fn default_denial_window() -> u64 {
+    300
+}
+
+fn default_cooldown() -> u64 {
+    900
+}
+
+/// An account put on hold.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hold {
+    pub until: u64,
+    pub action: Action,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+struct AccountState {
+    signatures: VecDeque<u64>,
+    /// chain ID -> (time, value) of the last hour's ops.
+    spends: BTreeMap<u64, VecDeque<(u64, U256)>>,
+    denials: VecDeque<u64>,
+    hold: Option<Hold>,
+}
+
+/// Drop entries older than `window` before `now`.
+fn expire<T>(entries: &mut VecDeque<T>, now: u64, window: u64, time: impl Fn(&T) -> u64) {
+    while entries.front().is_some_and(|e| time(e) + window <= now) {
+        entries.pop_front();
+    }
+}
+
+/// Per-account rate and anomaly state for one policy.
+#[derive(Debug)]
+pub struct Guard {
+    rate: RateLimits,
+    anomaly: Option<AnomalyRules>,
+    new_destination_value: Option<U256>,
+    accounts: BTreeMap<String, AccountState>,
+}
+
+impl Guard {
+    pub fn new(policy: &Policy) -> Self {
+        let anomaly = policy.anomaly.clone();
+        let new_destination_value = anomaly
+            .as_ref()
+            .and_then(|rules| rules.new_destination_value.or(policy.max_value_per_op));
+        Guard {
+            rate: policy.rate.clone().unwrap_or_default(),
+            anomaly,
+            new_destination_value,
+            accounts: BTreeMap::new(),
+        }
+    }
+
+    /// The hold on `account`, if one is in force at `now`.
+    pub fn hold(&self, account: &str, now: u64) -> Option<&Hold> {
+        let hold = self.accounts.get(account)?.hold.as_ref()?;
+        (hold.until > now).then_some(hold)
+    }
+
+    /// Let `account` make one more signature at `now`, counting it. An op
+    /// that is `approved` passes an approval hold; a bare hash never is.
+    pub fn admit(&mut self, account: &str, now: u64, approved: bool) -> Result<(), Denial> {
+        let state = self.accounts.entry(account.to_string()).or_default();
+        if let Some(hold) = &state.hold {
+            if hold.until <= now {
+                state.hold = None;
+            } else if hold.action == Action::Cooldown {
+                return Err(Denial::new(
+                    Rule::Anomaly,
+                    format!(
+                        "{account} is cooling down until {}: {}",
+                        hold.until, hold.reason
+                    ),
+                ));
+            } else if !approved {
+                return Err(Denial::new(
+                    Rule::Anomaly,
+                    format!(
+                        "{account} needs approval until {}: {}",
+                        hold.until, hold.reason
+                    ),
+                ));
+            }
+        }
+        if let Some(max) = self.rate.signatures_per_minute {
+            expire(&mut state.signatures, now, MINUTE, |&t| t);
+            if state.signatures.len() >= max as usize {
+                return Err(Denial::new(
+                    Rule::Rate,
+                    format!("{account} made {max} signatures in the last minute"),
+                ));
+            }
+        }
+        state.signatures.push_back(now);
+        Ok(())
+    }
+
+    /// Check an op the policy allowed: its value against the hourly limit,
+    /// and its calls against the targets `account` has called before.
+    pub fn check_op(
+        &mut self,
+        account: &str,
+        chain_id: u64,
+        calls: &[Call],
+        known: &BTreeSet<Address>,
+        approved: bool,
+        now: u64,
+    ) -> Result<(), Denial> {
+        let state = self.accounts.entry(account.to_string()).or_default();
+        if let Some(max) = self.rate.value_per_hour {
+            let value = calls
+                .iter()
+                .fold(U256::ZERO, |sum, c| sum.saturating_add(c.value));
+            let spends = state.spends.entry(chain_id).or_default();
+            expire(spends, now, HOUR, |&(t, _)| t);
+            let spent = spends
+                .iter()
+                .fold(U256::ZERO, |sum, (_, v)| sum.saturating_add(*v));
+            if spent.saturating_add(value) > max {
+                return Err(Denial::new(
+                    Rule::Rate,
+                    format!("{spent} wei moved in the last hour, op adds {value}, limit {max}"),
+                ));
+            }
+        }
+        let (Some(rules), Some(threshold)) = (&self.anomaly, self.new_destination_value) else {
+            return Ok(());
+        };
+        let Some(call) = calls
+            .iter()
+            .find(|c| c.value >= threshold && !known.contains(&c.target))
+        else {
+            return Ok(());
+        };
+        let reason = format!(
+            "moves {} wei to first-time target {}",
+            call.value, call.target
+        );
+        if rules.action == Action::Approval && approved {
+            return Ok(());
+        }
+        state.hold = Some(Hold {
+            until: now + rules.cooldown_secs,
+            action: rules.action,
+            reason: reason.clone(),
+        });
+        Err(Denial::new(Rule::Anomaly, reason))
+    }
+
+    /// Count a policy denial of one of `account`'s ops. Returns the hold it
+    /// puts the account on, if it was one too many.
+    pub fn record_denial(&mut self, account: &str, now: u64) -> Option<Hold> {
+        let rules = self.anomaly.as_ref()?;
+        let max = rules.max_denials?;
+        let state = self.accounts.entry(account.to_string()).or_default();
+        expire(&mut state.denials, now, rules.denial_window_secs, |&t| t);
+        state.denials.push_back(now);
+        if state.denials.len() < max as usize {
+            return None;
+        }
+        state.denials.clear();
+        let hold = Hold {
+            until: now + rules.cooldown_secs,
+            action: rules.action,
+            reason: format!("{max} policy denials within {}s", rules.denial_window_secs),
+        };
+        state.hold = Some(hold.clone());
+        Some(hold)
+    }
+
+    /// Count the value of a signed op.
+    pub fn record(&mut self, account: &str, chain_id: u64, value: U256, now: u64) {
+        if self.rate.value_per_hour.is_some() {
+            let state = self.accounts.entry(account.to_string()).or_default();
+            state
+                .spends
+                .entry(chain_id)
+                .or_default()
+                .push_back((now, value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pay(target: u8, value: u64) -> Call {
+        Call {
+            target: Address::repeat_byte(target),
+            value: U256::from(value),
+            data: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn limits_and_holds_are_per_account() {
+        let policy = Policy::from_toml(
+            r#"
+            max_value_per_op = "100"
+            [rate]
+            signatures_per_minute = 2
+            value_per_hour = "150"
+            [anomaly]
+            max_denials = 2
+            cooldown_secs = 600
+            "#,
+        )
+        .unwrap();
+        let mut guard = Guard::new(&policy);
+        let known: BTreeSet<_> = [Address::repeat_byte(1)].into();
+
+        guard.admit("alice", 0, false).unwrap();
+        guard.admit("alice", 10, false).unwrap();
+        assert_eq!(
+            guard.admit("alice", 20, false).unwrap_err().rule,
+            Rule::Rate
+        );
+        guard.admit("bob", 20, false).unwrap();
+        guard.admit("alice", 60, false).unwrap();
+
+        let check = |guard: &mut Guard, calls: &[Call], now| {
+            guard.check_op("alice", 1, calls, &known, false, now)
+        };
+        check(&mut guard, &[pay(1, 100)], 60).unwrap();
+        guard.record("alice", 1, U256::from(100), 60);
+        let over = check(&mut guard, &[pay(1, 60)], 61).unwrap_err();
+        assert_eq!(over.rule, Rule::Rate);
+        check(&mut guard, &[pay(1, 60)], 60 + HOUR).unwrap();
+        assert!(guard.hold("alice", 70).is_none());
+
+        // A full-size payment to a stranger cools the account down.
+        let stranger = check(&mut guard, &[pay(2, 100)], 4000).unwrap_err();
+        assert_eq!(stranger.rule, Rule::Anomaly);
+        assert_eq!(guard.hold("alice", 4000).unwrap().until, 4600);
+        let cooling = guard.admit("alice", 4100, true).unwrap_err();
+        assert_eq!(cooling.rule, Rule::Anomaly);
+        guard.admit("alice", 4600, false).unwrap();
+
+        assert!(guard.record_denial("bob", 5000).is_none());
+        assert!(guard.record_denial("bob", 5400).is_none());
+        let hold = guard.record_denial("bob", 5500).unwrap();
+        assert_eq!((hold.until, hold.action), (6100, Action::Cooldown));
+        assert!(guard.admit("bob", 5501, false).is_err());
+        guard.admit("alice", 5501, false).unwrap();
+    }
+
+    #[test]
+    fn an_approval_hold_passes_approved_ops() {
+        let policy = Policy::from_toml(
+            r#"
+            [anomaly]
+            new_destination_value = "10"
+            action = "approval"
+            "#,
+        )
+        .unwrap();
+        let mut guard = Guard::new(&policy);
+        let known = BTreeSet::new();
+        let denied = guard
+            .check_op("alice", 1, &[pay(3, 10)], &known, false, 0)
+            .unwrap_err();
+        assert_eq!(denied.rule, Rule::Anomaly);
+        assert_eq!(guard.hold("alice", 1).unwrap().action, Action::Approval);
+        assert!(guard.admit("alice", 1, false).is_err());
+        guard.admit("alice", 1, true).unwrap();
+        guard
+            .check_op("alice", 1, &[pay(3, 10)], &known, true, 1)
+            .unwrap();
+        assert!(
+            Guard::new(&Policy::default())
+                .record_denial("alice", 0)
+                .is_none()
+        );
+    }
+}
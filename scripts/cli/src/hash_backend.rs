@@ -0,0 +1,126 @@
+//! Which Keccak implementations this build and this CPU ended up with.
+//!
+//! Two code paths do the hashing. `keccak256` (userOpHash, key IDs,
+//! addresses, audit digests) goes through alloy. By default that is
+//! tiny-keccak; with the `fast-keccak` feature it is the CRYPTOGAMS
+//! assembly from keccak-asm. The Keccak-f[1600] permutation behind the
+//! `sha3` crate runs ML-DSA's SHAKE128/256 and the streaming `--hash`
+//! digests. It is portable Rust unless `fast-keccak` is on and the target
+//! is aarch64, in which case it checks once at runtime for the ARMv8.2
+//! SHA3 instructions and uses them when present.
+//!
+//! There is no x86_64 SIMD path. A single sponge is a serial dependency
+//! chain, and CRYPTOGAMS' AVX2 and AVX-512F variants lose to its scalar
+//! code there, so keccak-asm builds the scalar one.
+
+use std::fmt;
+
+use serde::Serialize;
+
+/// Implementation behind `alloy_primitives::keccak256`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Keccak256Backend {
+    TinyKeccak,
+    Cryptogams,
+}
+
+/// Implementation of the permutation inside the `sha3` crate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PermutationBackend {
+    Portable,
+    Armv8Sha3,
+}
+
+/// What [`detect`] found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HashBackends {
+    pub keccak256: Keccak256Backend,
+    pub keccak_f1600: PermutationBackend,
+}
+
+/// The backends in use on this host.
+pub fn detect() -> HashBackends {
+    let keccak256 = if cfg!(feature = "fast-keccak") {
+        Keccak256Backend::Cryptogams
+    } else {
+        Keccak256Backend::TinyKeccak
+    };
+    HashBackends {
+        keccak256,
+        keccak_f1600: permutation(),
+    }
+}
+
+#[cfg(all(feature = "fast-keccak", target_arch = "aarch64"))]
+fn permutation() -> PermutationBackend {
+    // The same check the keccak crate makes before its first permutation.
+    if std::arch::is_aarch64_feature_detected!("sha3") {
+        PermutationBackend::Armv8Sha3
+    } else {
+        PermutationBackend::Portable
+    }
+}
+
+#[cfg(not(all(feature = "fast-keccak", target_arch = "aarch64")))]
+fn permutation() -> PermutationBackend {
+    PermutationBackend::Portable
+}
+
+impl fmt::Display for Keccak256Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Keccak256Backend::TinyKeccak => "tiny-keccak",
+            Keccak256Backend::Cryptogams => "cryptogams asm",
+        })
+    }
+}
+
+impl fmt::Display for PermutationBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PermutationBackend::Portable => "portable",
+            PermutationBackend::Armv8Sha3 => "armv8 sha3",
+        })
+    }
+}
+
+impl fmt::Display for HashBackends {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "keccak256: {}, keccak-f[1600]: {}",
+            self.keccak256, self.keccak_f1600
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha3::{Digest, Keccak256};
+
+    #[test]
+    fn both_paths_agree_whatever_the_backend() {
+        // tiny-keccak or CRYPTOGAMS on one side, the `sha3` permutation on
+        // the other; lengths straddle the 136-byte rate.
+        for len in [0, 1, 135, 136, 137, 3309, 5261] {
+            let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            assert_eq!(
+                alloy_primitives::keccak256(&data).as_slice(),
+                &Keccak256::digest(&data)[..],
+                "{len} bytes"
+            );
+        }
+        let backends = detect();
+        assert_eq!(
+            backends.keccak256 == Keccak256Backend::Cryptogams,
+            cfg!(feature = "fast-keccak")
+        );
+        if !cfg!(target_arch = "aarch64") {
+            assert_eq!(backends.keccak_f1600, PermutationBackend::Portable);
+        }
+    }
+}
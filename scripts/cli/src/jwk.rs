@@ -0,0 +1,145 @@
+//! JSON Web Key encoding of ML-DSA-65 keys (draft-ietf-cose-dilithium).
+//!
+//! ```json
+//! {"kty": "AKP", "alg": "ML-DSA-65", "pub": "<base64url pk>", "priv": "<base64url seed>"}
+//! ```
+//!
+//! `priv` is the 32-byte seed, never the expanded key, and is only present
+//! in private JWKs. `kid` is filled with the key's keccak256 fingerprint, so
+//! a JWK can be matched against the on-chain owner commitment.
+
+use base64ct::{Base64UrlUnpadded, Encoding};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::error::{Result, WalletError};
+use crate::keys::{self, PUBLIC_KEY_LEN, SEED_LEN};
+use crate::message::HashAlg;
+
+/// JWK key type for ML-DSA (and other algorithm key pairs).
+pub const KTY_AKP: &str = "AKP";
+/// JOSE algorithm name.
+pub const ALG_ML_DSA_65: &str = "ML-DSA-65";
+
+/// An ML-DSA-65 JWK.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    pub alg: String,
+    #[serde(rename = "pub")]
+    pub public_key: String,
+    #[serde(rename = "priv", default, skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+}
+
+impl Jwk {
+    /// Public JWK.
+    pub fn public(pk: &[u8; PUBLIC_KEY_LEN]) -> Self {
+        Jwk {
+            kty: KTY_AKP.to_string(),
+            alg: ALG_ML_DSA_65.to_string(),
+            public_key: Base64UrlUnpadded::encode_string(pk),
+            private_key: None,
+            kid: Some(keys::fingerprint(pk, HashAlg::Keccak256).to_string()),
+        }
+    }
+
+    /// Private JWK carrying the seed.
+    pub fn private(pk: &[u8; PUBLIC_KEY_LEN], seed: &[u8; SEED_LEN]) -> Self {
+        Jwk {
+            private_key: Some(Base64UrlUnpadded::encode_string(seed)),
+            ..Jwk::public(pk)
+        }
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.kty != KTY_AKP || self.alg != ALG_ML_DSA_65 {
+            return Err(WalletError::InvalidEnvelope(format!(
+                "JWK is {}/{}, expected {KTY_AKP}/{ALG_ML_DSA_65}",
+                self.kty, self.alg
+            )));
+        }
+        Ok(())
+    }
+
+    /// Decoded public key.
+    pub fn public_key(&self) -> Result<[u8; PUBLIC_KEY_LEN]> {
+        self.check()?;
+        let bytes = decode_b64url(&self.public_key)?;
+        bytes
+            .as_slice()
+            .try_into()
+            .map_err(|_| WalletError::InvalidLength {
+                what: "public key",
+                expected: PUBLIC_KEY_LEN,
+                actual: bytes.len(),
+            })
+    }
+
+    /// Decoded seed; an error for public JWKs.
+    pub fn seed(&self) -> Result<Zeroizing<[u8; SEED_LEN]>> {
+        self.check()?;
+        let encoded = self
+            .private_key
+            .as_deref()
+            .ok_or_else(|| WalletError::InvalidEnvelope("JWK has no priv".to_string()))?;
+        let bytes = decode_b64url(encoded)?;
+        <[u8; SEED_LEN]>::try_from(bytes.as_slice())
+            .map(Zeroizing::new)
+            .map_err(|_| WalletError::InvalidLength {
+                what: "seed",
+                expected: SEED_LEN,
+                actual: bytes.len(),
+            })
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes)
+            .map_err(|e| WalletError::InvalidEnvelope(format!("invalid JWK: {e}")))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("JWK serializes")
+    }
+}
+
+fn decode_b64url(s: &str) -> Result<Zeroizing<Vec<u8>>> {
+    Base64UrlUnpadded::decode_vec(s)
+        .map(Zeroizing::new)
+        .map_err(|_| WalletError::InvalidEnvelope("invalid base64url in JWK".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_jwk_round_trips() {
+        let seed = [3u8; SEED_LEN];
+        let pk: [u8; PUBLIC_KEY_LEN] = keys::keygen_from_seed(&seed)
+            .verifying_key()
+            .encode()
+            .into();
+        let jwk = Jwk::private(&pk, &seed);
+        let json: serde_json::Value = serde_json::from_str(&jwk.to_json()).unwrap();
+        assert_eq!(json["kty"], "AKP");
+        assert_eq!(json["alg"], "ML-DSA-65");
+        assert_eq!(json["pub"].as_str().unwrap().len(), 2603);
+        assert_eq!(json["priv"].as_str().unwrap().len(), 43);
+
+        let parsed = Jwk::from_json(jwk.to_json().as_bytes()).unwrap();
+        assert_eq!(parsed.public_key().unwrap(), pk);
+        assert_eq!(*parsed.seed().unwrap(), seed);
+        assert!(Jwk::public(&pk).seed().is_err());
+    }
+
+    #[test]
+    fn rejects_other_algorithms() {
+        let mut jwk = Jwk::public(&[0u8; PUBLIC_KEY_LEN]);
+        jwk.alg = "ML-DSA-44".to_string();
+        assert!(jwk.public_key().is_err());
+        assert!(Jwk::from_json(br#"{"kty":"OKP","crv":"Ed25519","x":"AA"}"#).is_err());
+    }
+}
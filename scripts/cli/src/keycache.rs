@@ -0,0 +1,289 @@
+//! Unlocked signing keys held in memory, for long-running signers.
+//!
+//! Decrypting a keystore entry costs a full Argon2id derivation, which is
+//! too slow to pay per request. [`KeyCache::unlock`] decrypts once and keeps
+//! the expanded ML-DSA-65 signing key until the account is locked again:
+//! explicitly with [`KeyCache::lock`], when its `ttl` runs out, or after
+//! `idle` without a signature. The key lives in a [`PqSigner`], and locking
+//! drops it, which wipes it.
+//!
+//! The key never leaves the cache; [`KeyCache::with_key`] lends it to a
+//! closure, which runs without holding the cache lock. Expiry is checked on every access, and [`KeyCache::sweep`]
+//! relocks expired accounts for callers that run it on a timer.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use alloy_primitives::Address;
+use ml_dsa::{MlDsa65, SigningKey};
+use serde::Serialize;
+
+use crate::error::{Result, WalletError};
+use crate::keystore::Keystore;
+use crate::signer::PqSigner;
+
+struct Entry {
+    key: Arc<PqSigner>,
+    key_id: Address,
+    unlocked_at: Instant,
+    expires_at: Instant,
+    last_used: Instant,
+    signatures: u64,
+}
+
+impl Entry {
+    fn expired(&self, idle: Duration, now: Instant) -> bool {
+        now >= self.expires_at || now >= self.last_used + idle
+    }
+}
+
+/// An unlocked account, as `status` and `list` report it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Unlocked {
+    pub account: String,
+    pub key_id: Address,
+    /// Seconds since the account was unlocked.
+    pub unlocked_for: u64,
+    /// Seconds until the account relocks, whichever of its TTL and the idle
+    /// timeout comes first.
+    pub relocks_in: u64,
+    pub signatures: u64,
+}
+
+/// One keystore account and whether it is unlocked.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountState {
+    pub account: String,
+    pub key_id: Address,
+    pub unlocked: Option<Unlocked>,
+}
+
+pub struct KeyCache {
+    keystore: Keystore,
+    idle: Duration,
+    entries: Mutex<BTreeMap<String, Entry>>,
+}
+
+impl KeyCache {
+    /// Accounts relock after `idle` without a signature.
+    pub fn new(keystore: Keystore, idle: Duration) -> Self {
+        KeyCache {
+            keystore,
+            idle,
+            entries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, Entry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Decrypt `account` and keep its key for at most `ttl`. Unlocking an
+    /// unlocked account replaces the entry and restarts its TTL.
+    pub fn unlock(&self, account: &str, password: &[u8], ttl: Duration) -> Result<Unlocked> {
+        self.unlock_at(account, password, ttl, Instant::now())
+    }
+
+    fn unlock_at(
+        &self,
+        account: &str,
+        password: &[u8],
+        ttl: Duration,
+        now: Instant,
+    ) -> Result<Unlocked> {
+        // Argon2 runs outside the lock so other accounts keep signing.
+        let entry = self.keystore.load(account)?;
+        let seed = entry.decrypt_seed(password)?;
+        let key_id = entry.key_id()?;
+        let unlocked = Entry {
+            key: Arc::new(PqSigner::from_seed(&seed)),
+            key_id,
+            unlocked_at: now,
+            expires_at: now + ttl,
+            last_used: now,
+            signatures: 0,
+        };
+        let mut entries = self.entries();
+        entries.insert(account.to_string(), unlocked);
+        Ok(self.report(account, &entries[account], now))
+    }
+
+    /// Lock `account`, wiping its key. Returns whether it was unlocked.
+    pub fn lock(&self, account: &str) -> bool {
+        self.entries().remove(account).is_some()
+    }
+
+    pub fn lock_all(&self) {
+        self.entries().clear();
+    }
+
+    /// Lock every account past its TTL or idle timeout; returns their names.
+    pub fn sweep(&self) -> Vec<String> {
+        self.sweep_at(Instant::now())
+    }
+
+    fn sweep_at(&self, now: Instant) -> Vec<String> {
+        let mut entries = self.entries();
+        let expired: Vec<String> = entries
+            .iter()
+            .filter(|(_, entry)| entry.expired(self.idle, now))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in &expired {
+            entries.remove(name);
+        }
+        expired
+    }
+
+    /// Lend `account`'s signing key to `f`, counting it as a use for the
+    /// idle timeout.
+    pub fn with_key<R>(
+        &self,
+        account: &str,
+        f: impl FnOnce(&SigningKey<MlDsa65>) -> R,
+    ) -> Result<R> {
+        self.with_key_at(account, Instant::now(), f)
+    }
+
+    fn with_key_at<R>(
+        &self,
+        account: &str,
+        now: Instant,
+        f: impl FnOnce(&SigningKey<MlDsa65>) -> R,
+    ) -> Result<R> {
+        let mut entries = self.entries();
+        if entries
+            .get(account)
+            .is_some_and(|entry| entry.expired(self.idle, now))
+        {
+            entries.remove(account);
+        }
+        let entry = entries
+            .get_mut(account)
+            .ok_or_else(|| WalletError::Keystore(format!("account {account:?} is locked")))?;
+        entry.last_used = now;
+        entry.signatures += 1;
+        // Signing runs outside the lock; a key locked meanwhile is wiped
+        // once this last reference drops.
+        let key = entry.key.clone();
+        drop(entries);
+        Ok(f(key.signing_key()))
+    }
+
+    fn report(&self, account: &str, entry: &Entry, now: Instant) -> Unlocked {
+        let relocks_at = entry.expires_at.min(entry.last_used + self.idle);
+        Unlocked {
+            account: account.to_string(),
+            key_id: entry.key_id,
+            unlocked_for: now.saturating_duration_since(entry.unlocked_at).as_secs(),
+            relocks_in: relocks_at.saturating_duration_since(now).as_secs(),
+            signatures: entry.signatures,
+        }
+    }
+
+    /// `account`'s state if it is unlocked.
+    pub fn status(&self, account: &str) -> Option<Unlocked> {
+        let now = Instant::now();
+        let entries = self.entries();
+        let entry = entries.get(account)?;
+        (!entry.expired(self.idle, now)).then(|| self.report(account, entry, now))
+    }
+
+    /// Every keystore account, with its unlock state.
+    pub fn list(&self) -> Result<Vec<AccountState>> {
+        let now = Instant::now();
+        let entries = self.entries();
+        self.keystore
+            .list()?
+            .into_iter()
+            .map(|account| {
+                let unlocked = entries
+                    .get(&account.name)
+                    .filter(|entry| !entry.expired(self.idle, now))
+                    .map(|entry| self.report(&account.name, entry, now));
+                Ok(AccountState {
+                    key_id: account.key_id()?,
+                    account: account.name,
+                    unlocked,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keystore::{Account, KdfParams};
+    use ml_dsa::signature::Signer;
+
+    const TEST_KDF: KdfParams = KdfParams {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    fn cache(tag: &str) -> KeyCache {
+        let root = std::env::temp_dir().join(format!("pq-keycache-{}-{tag}", std::process::id()));
+        std::fs::remove_dir_all(&root).ok();
+        let store = Keystore::open(root);
+        store
+            .insert(&Account::encrypt("alice", &[3u8; 32], b"pw", TEST_KDF).unwrap())
+            .unwrap();
+        KeyCache::new(store, Duration::from_secs(60))
+    }
+
+    #[test]
+    fn unlocked_key_signs_until_ttl_or_idle_timeout() {
+        let cache = cache("expiry");
+        let t0 = Instant::now();
+        assert!(matches!(
+            cache.unlock_at("alice", b"wrong", Duration::from_secs(600), t0),
+            Err(WalletError::Decrypt)
+        ));
+        assert!(cache.with_key_at("alice", t0, |_| ()).is_err());
+
+        let status = cache
+            .unlock_at("alice", b"pw", Duration::from_secs(600), t0)
+            .unwrap();
+        assert_eq!(status.relocks_in, 60);
+        let sig = cache
+            .with_key_at("alice", t0 + Duration::from_secs(50), |sk| sk.sign(b"m"))
+            .unwrap();
+        assert_eq!(sig.encode().len(), crate::keys::SIGNATURE_LEN);
+        // Using the key pushed the idle deadline out.
+        assert!(
+            cache
+                .with_key_at("alice", t0 + Duration::from_secs(100), |_| ())
+                .is_ok()
+        );
+        assert!(
+            cache
+                .with_key_at("alice", t0 + Duration::from_secs(161), |_| ())
+                .is_err()
+        );
+
+        cache
+            .unlock_at("alice", b"pw", Duration::from_secs(30), t0)
+            .unwrap();
+        assert!(cache.sweep_at(t0 + Duration::from_secs(10)).is_empty());
+        assert_eq!(cache.sweep_at(t0 + Duration::from_secs(30)), ["alice"]);
+    }
+
+    #[test]
+    fn list_reports_lock_state() {
+        let cache = cache("list");
+        assert_eq!(cache.list().unwrap()[0].unlocked, None);
+        cache
+            .unlock("alice", b"pw", Duration::from_secs(600))
+            .unwrap();
+        let listed = cache.list().unwrap();
+        assert_eq!(listed[0].unlocked.as_ref().unwrap().account, "alice");
+        assert_eq!(cache.status("alice").unwrap().signatures, 0);
+        assert!(cache.lock("alice"));
+        assert!(cache.status("alice").is_none());
+    }
+}
@@ -0,0 +1,100 @@
+//! ML-DSA-65 key material helpers shared by the binaries. Key sizes,
+//! [`keygen_from_seed`] and [`address`] are available without `std`.
+
+use alloy_primitives::{Address, keccak256};
+use ml_dsa::{KeyGen, KeyPair, MlDsa65};
+#[cfg(feature = "std")]
+use {
+    crate::error::Result,
+    crate::keystore::KdfParams,
+    crate::message::{self, HashAlg},
+    alloy_primitives::B256,
+    zeroize::Zeroizing,
+};
+
+/// Length of the FIPS 204 keygen seed (ξ) we persist as `sk.bin`.
+pub const SEED_LEN: usize = 32;
+/// Encoded ML-DSA-65 verifying key length.
+pub const PUBLIC_KEY_LEN: usize = 1952;
+/// Encoded ML-DSA-65 signature length.
+pub const SIGNATURE_LEN: usize = 3309;
+
+/// Deterministically derive an ML-DSA-65 keypair from a 32-byte seed
+/// (ML-DSA.KeyGen_internal). This is the path ACVP keyGen vectors exercise.
+#[cfg_attr(
+    feature = "std",
+    tracing::instrument(level = "debug", name = "keygen", skip_all)
+)]
+pub fn keygen_from_seed(seed: &[u8; SEED_LEN]) -> KeyPair<MlDsa65> {
+    <MlDsa65 as KeyGen>::from_seed(&(*seed).into())
+}
+
+/// Salt [`seed_from_passphrase`] uses unless the caller supplies one.
+#[cfg(feature = "std")]
+pub const PASSPHRASE_SALT: &[u8] = b"pq-wallet/passphrase-seed/v1";
+
+/// Stretch a passphrase into a keygen seed with Argon2id at the keystore's
+/// default cost. The parameters are fixed so the same passphrase and salt
+/// give the same key on every host; changing them changes every derived key.
+#[cfg(feature = "std")]
+pub fn seed_from_passphrase(passphrase: &[u8], salt: &[u8]) -> Result<Zeroizing<[u8; SEED_LEN]>> {
+    KdfParams::default().derive(passphrase, salt)
+}
+
+/// Short identifier for an encoded public key: the last 20 bytes of
+/// keccak256(pk), rendered like an Ethereum address. It is not the smart
+/// account address, which depends on the factory and salt.
+pub fn address(public_key: &[u8]) -> Address {
+    Address::from_slice(&keccak256(public_key)[12..])
+}
+
+/// Hash of the encoded public key, as the account contract stores it in
+/// place of the full 1,952 bytes (keccak256 by default).
+#[cfg(feature = "std")]
+pub fn fingerprint(public_key: &[u8], alg: HashAlg) -> B256 {
+    message::hash_reader(alg, public_key)
+        .expect("reading a slice cannot fail")
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keygen_from_seed_is_deterministic() {
+        let a = keygen_from_seed(&[7u8; SEED_LEN]);
+        let b = keygen_from_seed(&[7u8; SEED_LEN]);
+        assert_eq!(a.verifying_key().encode(), b.verifying_key().encode());
+        assert_eq!(a.verifying_key().encode().len(), PUBLIC_KEY_LEN);
+        assert_eq!(a.to_seed().as_slice(), &[7u8; SEED_LEN]);
+    }
+
+    #[test]
+    fn fingerprint_matches_key_id_and_sha3() {
+        let pk = keygen_from_seed(&[7u8; SEED_LEN]).verifying_key().encode();
+        let fp = fingerprint(&pk, HashAlg::Keccak256);
+        assert_eq!(fp, keccak256(&pk[..]));
+        assert_eq!(address(&pk).as_slice(), &fp[12..]);
+        assert_ne!(fingerprint(&pk, HashAlg::Sha3_256), fp);
+    }
+
+    #[test]
+    fn passphrase_seed_depends_on_passphrase_and_salt() {
+        let a = seed_from_passphrase(b"correct horse", PASSPHRASE_SALT).unwrap();
+        assert_eq!(
+            *a,
+            *seed_from_passphrase(b"correct horse", PASSPHRASE_SALT).unwrap()
+        );
+        assert_ne!(
+            *a,
+            *seed_from_passphrase(b"correct horse", b"other-salt").unwrap()
+        );
+        assert_ne!(
+            *a,
+            *seed_from_passphrase(b"battery staple", PASSPHRASE_SALT).unwrap()
+        );
+        // Argon2 rejects salts shorter than 8 bytes.
+        assert!(seed_from_passphrase(b"x", b"short").is_err());
+    }
+}
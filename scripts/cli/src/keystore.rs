@@ -0,0 +1,612 @@
+//! Named accounts under `~/.pq-wallet/accounts/`.
+//!
+//! Each account is one JSON file, `<name>.json`, holding the public key in
+//! the clear and the 32-byte keygen seed encrypted with ChaCha20-Poly1305
+//! under an Argon2id-derived key. The public key is bound as associated data,
+//! so an entry whose public key was swapped fails to decrypt.
+//!
+//! On phones the seed can instead be encrypted under a random key that the
+//! OS keystore wraps (`kdf: "platform"`, see [`crate::platform`]); such an
+//! entry has no password and only unlocks on the device that made it.
+//!
+//! Set `PQ_WALLET_HOME` to use a store other than `~/.pq-wallet`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use alloy_primitives::Address;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::ct;
+use crate::error::{Result, WalletError};
+use crate::keys::{self, PUBLIC_KEY_LEN, SEED_LEN};
+use crate::platform::PlatformKeystore;
+use crate::profile::Profile;
+use crate::signing_scheme::SigningScheme;
+
+/// Current on-disk format version.
+pub const KEYSTORE_VERSION: u32 = 1;
+/// Only scheme stored today.
+pub const SCHEME_ML_DSA_65: &str = "ml-dsa-65";
+/// `kdf` of entries whose key is wrapped by the OS keystore.
+pub const KDF_PLATFORM: &str = "platform";
+
+/// Argon2id cost parameters, persisted with each entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KdfParams {
+    /// Memory cost in KiB.
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    /// The argon2 crate's defaults (OWASP's 19 MiB / 2 passes / 1 lane).
+    fn default() -> Self {
+        KdfParams {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl KdfParams {
+    pub(crate) fn derive(&self, password: &[u8], salt: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+            .map_err(|e| WalletError::Keystore(format!("argon2 params: {e}")))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password, salt, key.as_mut())
+            .map_err(|e| WalletError::Keystore(format!("argon2: {e}")))?;
+        Ok(key)
+    }
+}
+
+/// Encrypted seed; empty for watch-only entries.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Crypto {
+    /// `argon2id`, or [`KDF_PLATFORM`].
+    pub kdf: String,
+    /// Unused for platform entries.
+    pub kdf_params: KdfParams,
+    /// Hex; empty for platform entries.
+    pub salt: String,
+    pub cipher: String,
+    /// Hex, 12 bytes.
+    pub nonce: String,
+    /// Hex; seed plus 16-byte tag.
+    pub ciphertext: String,
+    /// Platform entries only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wrapped_key: Option<WrappedKey>,
+}
+
+/// The seed's encryption key, wrapped by a hardware-bound OS key.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WrappedKey {
+    /// [`PlatformKeystore::name`], e.g. `secure-enclave`.
+    pub platform: String,
+    /// Name of the OS key that wraps it.
+    pub alias: String,
+    /// Hex, in whatever format the platform produced.
+    pub key: String,
+}
+
+/// One keystore entry.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Account {
+    pub version: u32,
+    pub name: String,
+    pub scheme: String,
+    /// Hex-encoded ML-DSA-65 public key.
+    pub public_key: String,
+    /// Smart account address per chain ID.
+    #[serde(default)]
+    pub addresses: BTreeMap<u64, Address>,
+    /// How the validator on each chain expects ops to be signed; chains
+    /// not listed use [`SigningScheme::HashAsMessage`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub signing_schemes: BTreeMap<u64, SigningScheme>,
+    /// The construction each chain's validator expects; a chain's profile
+    /// overrides its entry in `signing_schemes`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<u64, Profile>,
+    /// Public key only: ops are built here and signed elsewhere.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub watch_only: bool,
+    #[serde(default, skip_serializing_if = "Crypto::is_empty")]
+    pub crypto: Crypto,
+}
+
+impl Crypto {
+    fn is_empty(&self) -> bool {
+        self.ciphertext.is_empty()
+    }
+}
+
+impl Account {
+    /// Encrypt `seed` under `password` into a new entry.
+    pub fn encrypt(
+        name: &str,
+        seed: &[u8; SEED_LEN],
+        password: &[u8],
+        kdf: KdfParams,
+    ) -> Result<Self> {
+        validate_name(name)?;
+        let public_key = keys::keygen_from_seed(seed).verifying_key().encode();
+        let salt: [u8; 16] = rand::random();
+        let key = kdf.derive(password, &salt)?;
+        let (nonce, ciphertext) = seal(&key, seed, &public_key)?;
+        Ok(Account {
+            version: KEYSTORE_VERSION,
+            name: name.to_string(),
+            scheme: SCHEME_ML_DSA_65.to_string(),
+            public_key: ct::encode_hex(&public_key),
+            addresses: BTreeMap::new(),
+            signing_schemes: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            watch_only: false,
+            crypto: Crypto {
+                kdf: "argon2id".to_string(),
+                kdf_params: kdf,
+                salt: ct::encode_hex(&salt),
+                cipher: "chacha20poly1305".to_string(),
+                nonce: ct::encode_hex(&nonce),
+                ciphertext: ct::encode_hex(&ciphertext),
+                wrapped_key: None,
+            },
+        })
+    }
+
+    /// Encrypt `seed` under a fresh random key and have `platform` wrap
+    /// that key with its OS key `alias`.
+    pub fn encrypt_wrapped(
+        name: &str,
+        seed: &[u8; SEED_LEN],
+        platform: &dyn PlatformKeystore,
+        alias: &str,
+    ) -> Result<Self> {
+        validate_name(name)?;
+        let public_key = keys::keygen_from_seed(seed).verifying_key().encode();
+        let key = Zeroizing::new(rand::random::<[u8; 32]>());
+        let (nonce, ciphertext) = seal(&key, seed, &public_key)?;
+        let wrapped = platform.wrap(alias, &key)?;
+        Ok(Account {
+            version: KEYSTORE_VERSION,
+            name: name.to_string(),
+            scheme: SCHEME_ML_DSA_65.to_string(),
+            public_key: ct::encode_hex(&public_key),
+            addresses: BTreeMap::new(),
+            signing_schemes: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            watch_only: false,
+            crypto: Crypto {
+                kdf: KDF_PLATFORM.to_string(),
+                kdf_params: KdfParams::default(),
+                salt: String::new(),
+                cipher: "chacha20poly1305".to_string(),
+                nonce: ct::encode_hex(&nonce),
+                ciphertext: ct::encode_hex(&ciphertext),
+                wrapped_key: Some(WrappedKey {
+                    platform: platform.name().to_string(),
+                    alias: alias.to_string(),
+                    key: ct::encode_hex(&wrapped),
+                }),
+            },
+        })
+    }
+
+    /// An entry holding only `public_key`, for coordinators that compute
+    /// addresses, build and simulate ops, and hand them to an external
+    /// signer.
+    pub fn watch_only(name: &str, public_key: &[u8; PUBLIC_KEY_LEN]) -> Result<Self> {
+        validate_name(name)?;
+        Ok(Account {
+            version: KEYSTORE_VERSION,
+            name: name.to_string(),
+            scheme: SCHEME_ML_DSA_65.to_string(),
+            public_key: ct::encode_hex(public_key),
+            addresses: BTreeMap::new(),
+            signing_schemes: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            watch_only: true,
+            crypto: Crypto::default(),
+        })
+    }
+
+    /// How ops for `chain_id` are signed: the chain's profile, else its
+    /// `signing_schemes` entry, else [`SigningScheme::HashAsMessage`].
+    pub fn signing_scheme(&self, chain_id: u64) -> SigningScheme {
+        match self.profiles.get(&chain_id) {
+            Some(profile) => profile.signing_scheme,
+            None => self
+                .signing_schemes
+                .get(&chain_id)
+                .copied()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// [`WalletError::WatchOnly`] unless the entry holds a seed.
+    pub fn require_seed(&self) -> Result<()> {
+        if self.watch_only {
+            return Err(WalletError::WatchOnly(self.name.clone()));
+        }
+        Ok(())
+    }
+
+    pub fn public_key(&self) -> Result<[u8; PUBLIC_KEY_LEN]> {
+        ct::decode_hex_array("public key", &self.public_key)
+    }
+
+    /// Key identifier (see [`keys::address`]).
+    pub fn key_id(&self) -> Result<Address> {
+        Ok(keys::address(&self.public_key()?))
+    }
+
+    /// Decrypt the seed and check it still derives the stored public key.
+    pub fn decrypt_seed(&self, password: &[u8]) -> Result<Zeroizing<[u8; SEED_LEN]>> {
+        self.require_seed()?;
+        if let Some(wrapped) = &self.crypto.wrapped_key {
+            return Err(WalletError::Keystore(format!(
+                "account {:?} is wrapped by {} and unlocks only on its device",
+                self.name, wrapped.platform
+            )));
+        }
+        let salt = ct::decode_hex(&self.crypto.salt)?;
+        let key = self.crypto.kdf_params.derive(password, &salt)?;
+        self.open(&key)
+    }
+
+    /// Have `platform` unwrap the entry's key, which asks the user to
+    /// authenticate with `reason` shown, and decrypt the seed with it.
+    pub fn unlock_wrapped(
+        &self,
+        platform: &dyn PlatformKeystore,
+        reason: &str,
+    ) -> Result<Zeroizing<[u8; SEED_LEN]>> {
+        self.require_seed()?;
+        let wrapped = self.crypto.wrapped_key.as_ref().ok_or_else(|| {
+            WalletError::Keystore(format!("account {:?} is password-protected", self.name))
+        })?;
+        if wrapped.platform != platform.name() {
+            return Err(WalletError::Keystore(format!(
+                "account {:?} is wrapped by {}, not {}",
+                self.name,
+                wrapped.platform,
+                platform.name()
+            )));
+        }
+        let key = platform.unwrap(&wrapped.alias, &ct::decode_hex(&wrapped.key)?, reason)?;
+        self.open(&key)
+    }
+
+    fn open(&self, key: &[u8; 32]) -> Result<Zeroizing<[u8; SEED_LEN]>> {
+        let public_key = self.public_key()?;
+        let nonce: [u8; 12] = ct::decode_hex_array("nonce", &self.crypto.nonce)?;
+        let ciphertext = ct::decode_hex(&self.crypto.ciphertext)?;
+        let plaintext = Zeroizing::new(
+            ChaCha20Poly1305::new(Key::from_slice(key))
+                .decrypt(
+                    Nonce::from_slice(&nonce),
+                    Payload {
+                        msg: &ciphertext,
+                        aad: &public_key,
+                    },
+                )
+                .map_err(|_| {
+                    #[cfg(feature = "metrics")]
+                    crate::metrics::unlock_failure();
+                    WalletError::Decrypt
+                })?,
+        );
+        let mut seed = Zeroizing::new([0u8; SEED_LEN]);
+        if plaintext.len() != SEED_LEN {
+            return Err(WalletError::Decrypt);
+        }
+        seed.copy_from_slice(&plaintext);
+        let derived = keys::keygen_from_seed(&seed).verifying_key().encode();
+        if !ct::eq(&derived, &public_key) {
+            return Err(WalletError::Keystore(format!(
+                "account {:?}: seed does not match public key",
+                self.name
+            )));
+        }
+        Ok(seed)
+    }
+}
+
+/// Encrypt `seed` under `key`, bound to `public_key`.
+fn seal(key: &[u8; 32], seed: &[u8; SEED_LEN], public_key: &[u8]) -> Result<([u8; 12], Vec<u8>)> {
+    let nonce: [u8; 12] = rand::random();
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: seed,
+                aad: public_key,
+            },
+        )
+        .map_err(|_| WalletError::Keystore("encryption failed".to_string()))?;
+    Ok((nonce, ciphertext))
+}
+
+/// Account names are used as file names, so keep them boring.
+pub fn validate_name(name: &str) -> Result<()> {
+    let ok = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if ok {
+        Ok(())
+    } else {
+        Err(WalletError::Keystore(format!(
+            "invalid account name {name:?} (use 1-64 of [A-Za-z0-9_-])"
+        )))
+    }
+}
+
+/// A directory of [`Account`] files.
+#[derive(Clone, Debug)]
+pub struct Keystore {
+    root: PathBuf,
+}
+
+static CONFIGURED_ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+impl Keystore {
+    /// `$PQ_WALLET_HOME`, else the configured `keystore` (see
+    /// [`crate::config`]), else `$HOME/.pq-wallet`.
+    pub fn default_root() -> Result<PathBuf> {
+        if let Some(home) = std::env::var_os("PQ_WALLET_HOME") {
+            return Ok(PathBuf::from(home));
+        }
+        if let Some(root) = CONFIGURED_ROOT.get() {
+            return Ok(root.clone());
+        }
+        std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".pq-wallet"))
+            .ok_or_else(|| WalletError::Keystore("neither PQ_WALLET_HOME nor HOME is set".into()))
+    }
+
+    /// Use `root` as the default store when `PQ_WALLET_HOME` is unset. Only
+    /// the first call has an effect.
+    pub fn set_default_root(root: PathBuf) {
+        let _ = CONFIGURED_ROOT.set(root);
+    }
+
+    /// The store at [`Keystore::default_root`].
+    pub fn open_default() -> Result<Self> {
+        Ok(Self::open(Self::default_root()?))
+    }
+
+    /// A store rooted at `root`; nothing is created until the first write.
+    pub fn open(root: impl Into<PathBuf>) -> Self {
+        Keystore { root: root.into() }
+    }
+
+    pub fn accounts_dir(&self) -> PathBuf {
+        self.root.join("accounts")
+    }
+
+    pub fn path_of(&self, name: &str) -> PathBuf {
+        self.accounts_dir().join(format!("{name}.json"))
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.path_of(name).exists()
+    }
+
+    pub fn load(&self, name: &str) -> Result<Account> {
+        validate_name(name)?;
+        let path = self.path_of(name);
+        if !path.exists() {
+            return Err(WalletError::Keystore(format!("no account named {name:?}")));
+        }
+        read_account(&path)
+    }
+
+    /// All accounts, sorted by name.
+    pub fn list(&self) -> Result<Vec<Account>> {
+        let dir = self.accounts_dir();
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let entries =
+            std::fs::read_dir(&dir).map_err(|e| WalletError::Io(dir.display().to_string(), e))?;
+        let mut accounts = Vec::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|e| WalletError::Io(dir.display().to_string(), e))?
+                .path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                accounts.push(read_account(&path)?);
+            }
+        }
+        accounts.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(accounts)
+    }
+
+    /// Write a new account, refusing to overwrite an existing one.
+    pub fn insert(&self, account: &Account) -> Result<PathBuf> {
+        validate_name(&account.name)?;
+        if self.exists(&account.name) {
+            return Err(WalletError::Keystore(format!(
+                "account {:?} already exists",
+                account.name
+            )));
+        }
+        self.save(account)
+    }
+
+    /// Write `account`, replacing any entry with the same name.
+    pub fn save(&self, account: &Account) -> Result<PathBuf> {
+        let dir = self.accounts_dir();
+        std::fs::create_dir_all(&dir).map_err(|e| WalletError::Io(dir.display().to_string(), e))?;
+        let path = self.path_of(&account.name);
+        let json = serde_json::to_string_pretty(account).expect("account serializes");
+        write_private(&path, json.as_bytes())?;
+        Ok(path)
+    }
+
+    pub fn rename(&self, from: &str, to: &str) -> Result<Account> {
+        validate_name(to)?;
+        let mut account = self.load(from)?;
+        if self.exists(to) {
+            return Err(WalletError::Keystore(format!(
+                "account {to:?} already exists"
+            )));
+        }
+        account.name = to.to_string();
+        self.save(&account)?;
+        self.delete(from)?;
+        Ok(account)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<()> {
+        validate_name(name)?;
+        let path = self.path_of(name);
+        std::fs::remove_file(&path).map_err(|e| WalletError::Io(path.display().to_string(), e))
+    }
+}
+
+fn read_account(path: &Path) -> Result<Account> {
+    let bytes = std::fs::read(path).map_err(|e| WalletError::Io(path.display().to_string(), e))?;
+    let account: Account = serde_json::from_slice(&bytes)
+        .map_err(|e| WalletError::Keystore(format!("{}: {e}", path.display())))?;
+    if account.version != KEYSTORE_VERSION {
+        return Err(WalletError::Keystore(format!(
+            "{}: unsupported version {}",
+            path.display(),
+            account.version
+        )));
+    }
+    Ok(account)
+}
+
+/// Create `path` readable only by the owner where the platform allows it.
+pub(crate) fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let io_err = |e| WalletError::Io(path.display().to_string(), e);
+    let mut file = options.open(path).map_err(io_err)?;
+    file.write_all(contents).map_err(io_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap parameters so debug-mode tests stay fast.
+    const TEST_KDF: KdfParams = KdfParams {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    fn temp_store(tag: &str) -> Keystore {
+        let root = std::env::temp_dir().join(format!("pq-keystore-{}-{tag}", std::process::id()));
+        std::fs::remove_dir_all(&root).ok();
+        Keystore::open(root)
+    }
+
+    #[test]
+    fn seed_roundtrips_and_wrong_password_fails() {
+        let seed = [9u8; SEED_LEN];
+        let account = Account::encrypt("alice", &seed, b"hunter2", TEST_KDF).unwrap();
+        assert_eq!(*account.decrypt_seed(b"hunter2").unwrap(), seed);
+        assert!(matches!(
+            account.decrypt_seed(b"hunter3"),
+            Err(WalletError::Decrypt)
+        ));
+
+        // Swapping in another public key breaks the AEAD binding.
+        let mut swapped = account.clone();
+        swapped.public_key = ct::encode_hex(
+            &keys::keygen_from_seed(&[1u8; SEED_LEN])
+                .verifying_key()
+                .encode(),
+        );
+        assert!(matches!(
+            swapped.decrypt_seed(b"hunter2"),
+            Err(WalletError::Decrypt)
+        ));
+    }
+
+    #[test]
+    fn insert_list_rename_delete() {
+        let store = temp_store("crud");
+        let a = Account::encrypt("bob", &[1u8; SEED_LEN], b"pw", TEST_KDF).unwrap();
+        let b = Account::encrypt("alice", &[2u8; SEED_LEN], b"pw", TEST_KDF).unwrap();
+        store.insert(&a).unwrap();
+        store.insert(&b).unwrap();
+        assert!(store.insert(&a).is_err());
+
+        let names: Vec<_> = store.list().unwrap().into_iter().map(|a| a.name).collect();
+        assert_eq!(names, ["alice", "bob"]);
+
+        store.rename("bob", "carol").unwrap();
+        assert!(!store.exists("bob"));
+        assert_eq!(
+            *store.load("carol").unwrap().decrypt_seed(b"pw").unwrap(),
+            [1u8; SEED_LEN]
+        );
+
+        store.delete("alice").unwrap();
+        assert_eq!(store.list().unwrap().len(), 1);
+        std::fs::remove_dir_all(&store.root).ok();
+    }
+
+    #[test]
+    fn watch_only_entries_hold_no_seed() {
+        let pk = keys::keygen_from_seed(&[3u8; SEED_LEN])
+            .verifying_key()
+            .encode();
+        let account = Account::watch_only("coordinator", &pk.into()).unwrap();
+        let json = serde_json::to_value(&account).unwrap();
+        assert_eq!(json["watchOnly"], true);
+        assert!(json.get("crypto").is_none());
+
+        let account: Account = serde_json::from_value(json).unwrap();
+        assert_eq!(account.public_key().unwrap()[..], pk[..]);
+        assert!(matches!(
+            account.decrypt_seed(b""),
+            Err(WalletError::WatchOnly(name)) if name == "coordinator"
+        ));
+        // Entries written before the flag are not watch-only.
+        let old = Account::encrypt("alice", &[9u8; SEED_LEN], b"pw", TEST_KDF).unwrap();
+        assert!(
+            !serde_json::to_value(&old)
+                .unwrap()
+                .as_object()
+                .unwrap()
+                .contains_key("watchOnly")
+        );
+        assert!(old.require_seed().is_ok());
+    }
+
+    #[test]
+    fn rejects_path_like_names() {
+        for name in ["", "../evil", "a/b", "has space"] {
+            assert!(validate_name(name).is_err(), "{name:?}");
+        }
+        assert!(validate_name("alice_2-main").is_ok());
+    }
+}
@@ -0,0 +1,447 @@
+//! A Ledger as the classical half of hybrid signing (`ledger` feature).
+//!
+//! Accounts behind a hybrid validator need a secp256k1 signature next to the
+//! ML-DSA one, so that breaking either scheme alone is not enough. With
+//! `pq-userop sign --ledger`, that ECDSA signature comes from a Ledger
+//! running the Ethereum app, and its key never leaves the device. The device
+//! signs the userOpHash as an EIP-191 personal message (`eth_sign` of the
+//! hash, which is what Kernel's and Safe's ECDSA validators recover from);
+//! [`Ledger::sign_eip712_hashed`] signs an EIP-712 domain separator and
+//! struct hash for validators that want typed data. [`hybrid_signature`]
+//! packs both signatures the way the hybrid validator decodes them:
+//!
+//! ```text
+//! abi.encode(bytes ecdsaSignature, bytes pqSignature)
+//! ```
+//!
+//! with `ecdsaSignature` as `r || s || v`. APDUs travel in the Ledger HID
+//! framing over Linux hidraw (`/dev/hidraw*`), which needs no extra
+//! libraries; other transports implement [`Exchange`].
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use alloy_primitives::{Address, B256, Bytes, Signature, U256, keccak256};
+use alloy_sol_types::SolValue;
+
+use crate::error::{Result, WalletError};
+
+/// Ledger's USB vendor ID.
+pub const VENDOR_ID: u16 = 0x2c97;
+/// First account of the Ledger Live derivation.
+pub const DEFAULT_PATH: &str = "m/44'/60'/0'/0/0";
+
+const CLA: u8 = 0xe0;
+const INS_GET_ADDRESS: u8 = 0x02;
+const INS_SIGN_PERSONAL: u8 = 0x08;
+const INS_SIGN_EIP712: u8 = 0x0c;
+
+const CHANNEL: u16 = 0x0101;
+const TAG_APDU: u8 = 0x05;
+const PACKET_LEN: usize = 64;
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::Ledger(reason.into())
+}
+
+/// Sends one APDU and returns the response, status word included.
+pub trait Exchange {
+    fn exchange(&mut self, apdu: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// A BIP-32 path such as `m/44'/60'/0'/0/0`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl FromStr for DerivationPath {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bad = || WalletError::Config(format!("invalid derivation path {s:?}"));
+        let rest = s.strip_prefix("m/").ok_or_else(bad)?;
+        let indices = rest
+            .split('/')
+            .map(|part| {
+                let (n, hardened) = match part.strip_suffix(['\'', 'h']) {
+                    Some(n) => (n, true),
+                    None => (part, false),
+                };
+                let n = n
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|n| n >> 31 == 0)
+                    .ok_or_else(bad)?;
+                Ok(if hardened { n | 1 << 31 } else { n })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if indices.len() > 10 {
+            return Err(bad());
+        }
+        Ok(DerivationPath(indices))
+    }
+}
+
+impl DerivationPath {
+    /// Depth byte, then each index big-endian, as the Ethereum app reads it.
+    fn encode(&self) -> Vec<u8> {
+        let mut out = vec![self.0.len() as u8];
+        for index in &self.0 {
+            out.extend_from_slice(&index.to_be_bytes());
+        }
+        out
+    }
+}
+
+/// `apdu` as HID packets: channel, tag and sequence number on each, the
+/// total length on the first, zero-padded.
+fn frame(apdu: &[u8]) -> Vec<[u8; PACKET_LEN]> {
+    let mut payload = (apdu.len() as u16).to_be_bytes().to_vec();
+    payload.extend_from_slice(apdu);
+    payload
+        .chunks(PACKET_LEN - 5)
+        .enumerate()
+        .map(|(seq, chunk)| {
+            let mut packet = [0u8; PACKET_LEN];
+            packet[..2].copy_from_slice(&CHANNEL.to_be_bytes());
+            packet[2] = TAG_APDU;
+            packet[3..5].copy_from_slice(&(seq as u16).to_be_bytes());
+            packet[5..5 + chunk.len()].copy_from_slice(chunk);
+            packet
+        })
+        .collect()
+}
+
+/// Reassembles a response from HID packets.
+#[derive(Default)]
+struct Reassembly {
+    seq: u16,
+    len: usize,
+    data: Vec<u8>,
+}
+
+impl Reassembly {
+    /// The response once `packet` completes it.
+    fn push(&mut self, packet: &[u8]) -> Result<Option<Vec<u8>>> {
+        if packet.len() < 5
+            || packet[..2] != CHANNEL.to_be_bytes()
+            || packet[2] != TAG_APDU
+            || packet[3..5] != self.seq.to_be_bytes()
+        {
+            return Err(err("unexpected HID packet from the device"));
+        }
+        let mut body = &packet[5..];
+        if self.seq == 0 {
+            let (len, rest) = body
+                .split_first_chunk::<2>()
+                .ok_or_else(|| err("short HID packet"))?;
+            self.len = u16::from_be_bytes(*len) as usize;
+            body = rest;
+        }
+        self.seq += 1;
+        let take = body.len().min(self.len - self.data.len());
+        self.data.extend_from_slice(&body[..take]);
+        Ok((self.data.len() == self.len).then(|| std::mem::take(&mut self.data)))
+    }
+}
+
+/// A Ledger's generic HID interface through Linux hidraw.
+pub struct Hidraw {
+    file: File,
+}
+
+impl Hidraw {
+    /// The first connected Ledger.
+    pub fn open() -> Result<Self> {
+        let id = format!("HID_ID=0003:0000{VENDOR_ID:04X}:");
+        let dir = std::fs::read_dir("/sys/class/hidraw")
+            .map_err(|e| WalletError::Io("/sys/class/hidraw".to_string(), e))?;
+        for entry in dir.flatten() {
+            let uevent =
+                std::fs::read_to_string(entry.path().join("device/uevent")).unwrap_or_default();
+            // Interface 0 is the APDU channel; the others are FIDO and the like.
+            let apdu_interface = uevent
+                .lines()
+                .any(|l| l.starts_with("HID_PHYS=") && l.ends_with("input0"));
+            if uevent.lines().any(|l| l.starts_with(&id)) && apdu_interface {
+                let path = format!("/dev/{}", entry.file_name().to_string_lossy());
+                let file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(&path)
+                    .map_err(|e| WalletError::Io(path, e))?;
+                return Ok(Hidraw { file });
+            }
+        }
+        Err(err("no Ledger connected (plug it in and unlock it)"))
+    }
+}
+
+impl Exchange for Hidraw {
+    fn exchange(&mut self, apdu: &[u8]) -> Result<Vec<u8>> {
+        let io_err = |e| WalletError::Io("ledger".to_string(), e);
+        for packet in frame(apdu) {
+            // Report ID 0, then the packet.
+            let mut report = [0u8; PACKET_LEN + 1];
+            report[1..].copy_from_slice(&packet);
+            self.file.write_all(&report).map_err(io_err)?;
+        }
+        let mut reassembly = Reassembly::default();
+        loop {
+            let mut packet = [0u8; PACKET_LEN];
+            let n = self.file.read(&mut packet).map_err(io_err)?;
+            if let Some(response) = reassembly.push(&packet[..n])? {
+                return Ok(response);
+            }
+        }
+    }
+}
+
+/// The Ethereum app on a Ledger.
+pub struct Ledger<T> {
+    transport: T,
+}
+
+impl Ledger<Hidraw> {
+    pub fn open() -> Result<Self> {
+        Ok(Ledger::new(Hidraw::open()?))
+    }
+}
+
+impl<T: Exchange> Ledger<T> {
+    pub fn new(transport: T) -> Self {
+        Ledger { transport }
+    }
+
+    fn call(&mut self, ins: u8, p1: u8, data: &[u8]) -> Result<Vec<u8>> {
+        let len = u8::try_from(data.len()).map_err(|_| err("APDU too long"))?;
+        let mut apdu = vec![CLA, ins, p1, 0x00, len];
+        apdu.extend_from_slice(data);
+        let mut response = self.transport.exchange(&apdu)?;
+        if response.len() < 2 {
+            return Err(err("short response from the device"));
+        }
+        let sw = response.split_off(response.len() - 2);
+        match u16::from_be_bytes([sw[0], sw[1]]) {
+            0x9000 => Ok(response),
+            0x6985 => Err(WalletError::Cancelled),
+            0x5515 | 0x6b0c => Err(err("the device is locked; unlock it")),
+            0x6d00 | 0x6e00 | 0x6e01 | 0x6511 => Err(err("open the Ethereum app on the device")),
+            0x6a80 => Err(err(
+                "the Ethereum app refused the data (EIP-712 hashes need blind signing enabled)",
+            )),
+            sw => Err(err(format!("device returned status {sw:#06x}"))),
+        }
+    }
+
+    /// The address at `path`, without showing it on the device.
+    pub fn address(&mut self, path: &DerivationPath) -> Result<Address> {
+        let response = self.call(INS_GET_ADDRESS, 0x00, &path.encode())?;
+        // pubkey length, pubkey, address length, address as ASCII hex.
+        let bad = || err("malformed address response");
+        let pk_len = *response.first().ok_or_else(bad)? as usize;
+        let addr_len = *response.get(1 + pk_len).ok_or_else(bad)? as usize;
+        let hex = response
+            .get(2 + pk_len..2 + pk_len + addr_len)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .ok_or_else(bad)?;
+        Address::from_str(hex).map_err(|_| bad())
+    }
+
+    /// Sign `hash` as a personal message: the device shows the hash and
+    /// signs `keccak256("\x19Ethereum Signed Message:\n32" || hash)`.
+    pub fn sign_hash(&mut self, path: &DerivationPath, hash: B256) -> Result<Signature> {
+        let mut data = path.encode();
+        data.extend_from_slice(&32u32.to_be_bytes());
+        data.extend_from_slice(hash.as_slice());
+        parse_vrs(&self.call(INS_SIGN_PERSONAL, 0x00, &data)?)
+    }
+
+    /// Sign `keccak256(0x1901 || domain_separator || struct_hash)`.
+    pub fn sign_eip712_hashed(
+        &mut self,
+        path: &DerivationPath,
+        domain_separator: B256,
+        struct_hash: B256,
+    ) -> Result<Signature> {
+        let mut data = path.encode();
+        data.extend_from_slice(domain_separator.as_slice());
+        data.extend_from_slice(struct_hash.as_slice());
+        parse_vrs(&self.call(INS_SIGN_EIP712, 0x00, &data)?)
+    }
+
+    /// Sign a userOpHash with the key at `path`, and check the signature
+    /// recovers to that key's address, which is returned with it.
+    pub fn sign_user_op_hash(
+        &mut self,
+        path: &DerivationPath,
+        hash: B256,
+    ) -> Result<(Address, Signature)> {
+        let address = self.address(path)?;
+        let signature = self.sign_hash(path, hash)?;
+        let recovered = signature
+            .recover_address_from_msg(hash)
+            .map_err(|e| WalletError::Ecdsa(e.to_string()))?;
+        if recovered != address {
+            return Err(err(format!(
+                "signature recovers to {recovered}, not the device's {address}"
+            )));
+        }
+        Ok((address, signature))
+    }
+}
+
+/// `v || r || s`, with `v` as 27/28 or 0/1.
+fn parse_vrs(response: &[u8]) -> Result<Signature> {
+    let [v, rs @ ..] = response else {
+        return Err(err("empty signature response"));
+    };
+    if rs.len() != 64 {
+        return Err(err("malformed signature response"));
+    }
+    let parity = match v {
+        0 | 27 => false,
+        1 | 28 => true,
+        other => return Err(err(format!("signature v {other} is not 27 or 28"))),
+    };
+    Ok(Signature::new(
+        U256::from_be_slice(&rs[..32]),
+        U256::from_be_slice(&rs[32..]),
+        parity,
+    ))
+}
+
+/// `userOp.signature` for the hybrid validator.
+pub fn hybrid_signature(ecdsa: &Signature, pq_signature: &[u8]) -> Vec<u8> {
+    (
+        Bytes::copy_from_slice(&ecdsa.as_bytes()),
+        Bytes::copy_from_slice(pq_signature),
+    )
+        .abi_encode_params()
+}
+
+/// The EIP-712 digest [`Ledger::sign_eip712_hashed`] signs.
+pub fn eip712_digest(domain_separator: B256, struct_hash: B256) -> B256 {
+    let mut preimage = [0u8; 66];
+    preimage[..2].copy_from_slice(&[0x19, 0x01]);
+    preimage[2..34].copy_from_slice(domain_separator.as_slice());
+    preimage[34..].copy_from_slice(struct_hash.as_slice());
+    keccak256(preimage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::eip191_hash_message;
+    use k256::ecdsa::SigningKey;
+
+    /// An Ethereum app holding one key, answering through the HID framing.
+    struct FakeLedger {
+        key: SigningKey,
+        approve: bool,
+    }
+
+    impl FakeLedger {
+        fn answer(&self, apdu: &[u8]) -> Vec<u8> {
+            let (header, data) = apdu.split_at(5);
+            assert_eq!(header[4] as usize, data.len());
+            let path_len = 1 + 4 * data[0] as usize;
+            let sign = |digest: B256| {
+                let (sig, id) = self
+                    .key
+                    .sign_prehash_recoverable(digest.as_slice())
+                    .unwrap();
+                let mut out = vec![27 + id.is_y_odd() as u8];
+                out.extend_from_slice(&sig.to_bytes());
+                out
+            };
+            let mut out = match header[1] {
+                INS_GET_ADDRESS => {
+                    let point = self.key.verifying_key().to_encoded_point(false);
+                    let address = Address::from_public_key(self.key.verifying_key());
+                    let hex = address.to_string();
+                    let mut out = vec![65];
+                    out.extend_from_slice(point.as_bytes());
+                    out.push(hex.len() as u8);
+                    out.extend_from_slice(hex.as_bytes());
+                    out
+                }
+                _ if !self.approve => return vec![0x69, 0x85],
+                INS_SIGN_PERSONAL => sign(eip191_hash_message(&data[path_len + 4..])),
+                INS_SIGN_EIP712 => {
+                    let hashes = &data[path_len..];
+                    sign(eip712_digest(
+                        B256::from_slice(&hashes[..32]),
+                        B256::from_slice(&hashes[32..]),
+                    ))
+                }
+                _ => return vec![0x6d, 0x00],
+            };
+            out.extend_from_slice(&[0x90, 0x00]);
+            out
+        }
+    }
+
+    impl Exchange for FakeLedger {
+        fn exchange(&mut self, apdu: &[u8]) -> Result<Vec<u8>> {
+            let mut request = Reassembly::default();
+            let apdu = frame(apdu)
+                .iter()
+                .find_map(|p| request.push(p).unwrap())
+                .unwrap();
+            let mut response = Reassembly::default();
+            Ok(frame(&self.answer(&apdu))
+                .iter()
+                .find_map(|p| response.push(p).unwrap())
+                .unwrap())
+        }
+    }
+
+    #[test]
+    fn parses_paths_and_frames_long_apdus() {
+        let path: DerivationPath = DEFAULT_PATH.parse().unwrap();
+        assert_eq!(path.encode()[..9], [5, 0x80, 0, 0, 44, 0x80, 0, 0, 60]);
+        assert_eq!("m/44h/60h/1".parse::<DerivationPath>().unwrap().0[2], 1);
+        for bad in ["44'/60'", "m/x", "m/2147483648"] {
+            assert!(bad.parse::<DerivationPath>().is_err(), "{bad}");
+        }
+
+        let apdu: Vec<u8> = (0..150).map(|i| i as u8).collect();
+        let packets = frame(&apdu);
+        assert_eq!(packets.len(), 3);
+        let mut reassembly = Reassembly::default();
+        assert_eq!(reassembly.push(&packets[0]).unwrap(), None);
+        assert!(reassembly.push(&packets[2]).is_err(), "out of order");
+    }
+
+    #[test]
+    fn signs_user_op_hashes_on_the_device() {
+        let key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let expected = Address::from_public_key(key.verifying_key());
+        let mut ledger = Ledger::new(FakeLedger { key, approve: true });
+        let path = DEFAULT_PATH.parse().unwrap();
+        let hash = keccak256(b"userOp");
+
+        let (address, signature) = ledger.sign_user_op_hash(&path, hash).unwrap();
+        assert_eq!(address, expected);
+        let packed = hybrid_signature(&signature, &[1, 2, 3]);
+        let (ecdsa, pq) = <(Bytes, Bytes)>::abi_decode_params(&packed).unwrap();
+        assert_eq!(ecdsa.len(), 65);
+        assert_eq!(pq[..], [1, 2, 3]);
+
+        let (domain, message) = (keccak256(b"domain"), keccak256(b"message"));
+        let typed = ledger.sign_eip712_hashed(&path, domain, message).unwrap();
+        assert_eq!(
+            typed
+                .recover_address_from_prehash(&eip712_digest(domain, message))
+                .unwrap(),
+            expected
+        );
+
+        ledger.transport.approve = false;
+        assert!(matches!(
+            ledger.sign_hash(&path, hash),
+            Err(WalletError::Cancelled)
+        ));
+    }
+}
@@ -1 +1,196 @@
-pub mod userop;
\ No newline at end of file
+//! Post-quantum smart wallet tooling.
+//!
+//! Without the default `std` feature only the pieces an embedded signer
+//! needs are built, under `no_std` + `alloc`: userOpHash computation and
+//! UserOperation packing ([`userop`]), signature envelopes ([`envelope`]),
+//! key sizes and key IDs ([`keys`]) and [`WalletError`]. Everything that
+//! touches the filesystem, the network or a terminal needs `std`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod aa_errors;
+#[cfg(feature = "std")]
+pub mod acvp;
+#[cfg(feature = "std")]
+pub mod aggregator;
+#[cfg(feature = "std")]
+pub mod assets;
+#[cfg(feature = "std")]
+pub mod attestation;
+#[cfg(feature = "std")]
+pub mod audit;
+#[cfg(feature = "async")]
+pub mod auth;
+#[cfg(feature = "std")]
+pub mod backend;
+#[cfg(feature = "std")]
+pub mod backup;
+#[cfg(feature = "std")]
+pub mod balance;
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod bench;
+#[cfg(feature = "std")]
+pub mod bundler;
+#[cfg(feature = "std")]
+pub mod calldata;
+#[cfg(feature = "std")]
+pub mod canonical_json;
+#[cfg(feature = "std")]
+pub mod ceremony;
+#[cfg(feature = "std")]
+pub mod check;
+#[cfg(feature = "std")]
+pub mod cli;
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(feature = "fips204")]
+pub mod conformance;
+#[cfg(feature = "std")]
+pub mod contacts;
+#[cfg(feature = "std")]
+pub mod cose;
+#[cfg(feature = "std")]
+pub mod ct;
+#[cfg(feature = "async")]
+pub mod daemon;
+#[cfg(feature = "std")]
+pub mod decode;
+#[cfg(feature = "std")]
+pub mod delegation;
+#[cfg(feature = "std")]
+pub mod deploy;
+#[cfg(feature = "devnet")]
+pub mod devnet;
+#[cfg(feature = "e2e")]
+pub mod e2e;
+#[cfg(feature = "std")]
+pub mod ens;
+pub mod envelope;
+#[cfg(feature = "std")]
+pub mod erc7562;
+pub mod error;
+#[cfg(feature = "std")]
+pub mod escrow;
+#[cfg(feature = "std")]
+pub mod failover;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod finality;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+#[cfg(feature = "std")]
+pub mod gas;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "std")]
+pub mod guard;
+#[cfg(feature = "std")]
+pub mod hash_backend;
+#[cfg(feature = "std")]
+pub mod jwk;
+#[cfg(feature = "std")]
+pub mod keycache;
+pub mod keys;
+#[cfg(feature = "std")]
+pub mod keystore;
+#[cfg(feature = "ledger")]
+pub mod ledger;
+#[cfg(feature = "std")]
+pub mod logging;
+#[cfg(feature = "std")]
+pub mod message;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod notify;
+#[cfg(feature = "std")]
+pub mod output;
+#[cfg(feature = "std")]
+pub mod paymaster;
+#[cfg(feature = "std")]
+pub mod pipeline;
+#[cfg(feature = "std")]
+pub mod pkix;
+#[cfg(feature = "std")]
+pub mod plan;
+#[cfg(feature = "std")]
+pub mod platform;
+#[cfg(feature = "std")]
+pub mod policy;
+#[cfg(feature = "async")]
+pub mod pool;
+#[cfg(feature = "std")]
+pub mod precompile;
+#[cfg(feature = "std")]
+pub mod profile;
+#[cfg(feature = "std")]
+pub mod proposal;
+#[cfg(feature = "std")]
+pub mod provision;
+#[cfg(feature = "queue")]
+pub mod queue;
+#[cfg(feature = "std")]
+pub mod registry;
+#[cfg(feature = "std")]
+pub mod release;
+#[cfg(feature = "std")]
+pub mod replay;
+#[cfg(feature = "std")]
+pub mod review;
+#[cfg(feature = "std")]
+pub mod revocation;
+#[cfg(feature = "std")]
+pub mod robot;
+#[cfg(feature = "std")]
+pub mod rpc;
+#[cfg(feature = "std")]
+pub mod schema;
+#[cfg(feature = "std")]
+pub mod schemes;
+#[cfg(feature = "std")]
+pub mod self_bundle;
+#[cfg(feature = "std")]
+pub mod shamir;
+#[cfg(feature = "std")]
+pub mod sig_transport;
+#[cfg(feature = "std")]
+pub mod signer;
+#[cfg(feature = "std")]
+pub mod signing_scheme;
+#[cfg(feature = "std")]
+pub mod simulate;
+#[cfg(feature = "std")]
+pub mod siwe;
+#[cfg(feature = "std")]
+pub mod state_bundle;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod strict;
+#[cfg(feature = "unstable-threshold")]
+pub mod threshold;
+#[cfg(feature = "tpm")]
+pub mod tpm;
+#[cfg(feature = "std")]
+pub mod trace;
+pub mod userop;
+#[cfg(feature = "std")]
+pub mod validity;
+#[cfg(feature = "std")]
+pub mod vectors;
+#[cfg(feature = "std")]
+pub mod verify_cache;
+#[cfg(feature = "std")]
+pub mod watch;
+#[cfg(feature = "std")]
+pub mod webauthn;
+#[cfg(feature = "std")]
+pub mod x509;
+
+pub use error::{Result, WalletError};
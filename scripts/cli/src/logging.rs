@@ -0,0 +1,157 @@
+//! Diagnostic logging via `tracing`, on stderr so stdout stays clean for
+//! `--json` and piped output.
+//!
+//! The library opens spans around keygen, signing, verification, RPC
+//! requests and simulation; binaries add `userop_build` and `submit`. Pick
+//! the level with `--verbosity` (or `RUST_LOG`, which wins and accepts full
+//! filter directives such as `pq_cli::rpc=trace`), and switch to one JSON
+//! object per event with `--log-format json` for log collectors.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+
+use crate::error::WalletError;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verbosity {
+    /// Nothing but errors.
+    #[serde(alias = "quiet")]
+    Error,
+    /// Warnings; the default.
+    #[default]
+    Warn,
+    Info,
+    /// Spans and RPC traffic.
+    Debug,
+    Trace,
+}
+
+impl Verbosity {
+    fn directive(self) -> &'static str {
+        match self {
+            Verbosity::Error => "error",
+            Verbosity::Warn => "warn",
+            Verbosity::Info => "info",
+            Verbosity::Debug => "debug",
+            Verbosity::Trace => "trace",
+        }
+    }
+}
+
+impl fmt::Display for Verbosity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.directive())
+    }
+}
+
+impl FromStr for Verbosity {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self, WalletError> {
+        match s {
+            "error" | "quiet" => Ok(Verbosity::Error),
+            "warn" => Ok(Verbosity::Warn),
+            "info" => Ok(Verbosity::Info),
+            "debug" => Ok(Verbosity::Debug),
+            "trace" => Ok(Verbosity::Trace),
+            _ => Err(WalletError::Usage(
+                "verbosity must be error, warn, info, debug or trace",
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per event, with span context.
+    Json,
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        })
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self, WalletError> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(WalletError::Usage("log format must be text or json")),
+        }
+    }
+}
+
+/// `--verbosity` and `--log-format`, shared by every binary (through
+/// [`crate::output::OutputArgs`] where it has one).
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub struct LogArgs {
+    /// Diagnostics on stderr: error, warn, info, debug or trace (RUST_LOG
+    /// overrides)
+    #[arg(long, global = true, default_value_t = Verbosity::Warn)]
+    pub verbosity: Verbosity,
+
+    /// Diagnostic format: text or json
+    #[arg(long, global = true, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+}
+
+impl LogArgs {
+    pub fn init(&self) {
+        init(self.verbosity, self.log_format);
+    }
+}
+
+/// Filter for `verbosity`, unless `RUST_LOG` is set. The level applies to
+/// this crate and the `pq_*` binaries (targets match by prefix); dependencies
+/// such as the HTTP client stay at `warn` so `debug` shows our spans rather
+/// than connection internals.
+pub fn filter(verbosity: Verbosity) -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        let level = verbosity.directive();
+        EnvFilter::new(format!("warn,pq_={level}"))
+    })
+}
+
+/// Install the global subscriber. Later calls are no-ops.
+pub fn init(verbosity: Verbosity, format: LogFormat) {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter(verbosity))
+        .with_writer(std::io::stderr);
+    let _ = match format {
+        LogFormat::Text => builder
+            .without_time()
+            .with_target(false)
+            .with_ansi(std::io::IsTerminal::is_terminal(&std::io::stderr()))
+            .try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).try_init(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_and_formats_parse() {
+        assert_eq!("debug".parse::<Verbosity>().unwrap(), Verbosity::Debug);
+        assert_eq!("quiet".parse::<Verbosity>().unwrap(), Verbosity::Error);
+        assert!("loud".parse::<Verbosity>().is_err());
+        assert_eq!(Verbosity::default().to_string(), "warn");
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert!(Verbosity::Trace > Verbosity::Info);
+    }
+}
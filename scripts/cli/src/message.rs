@@ -0,0 +1,368 @@
+//! What gets signed: a precomputed 32-byte hash, a streamed input reduced to
+//! a 32-byte digest, or the raw input bytes as the ML-DSA message.
+//!
+//! Raw mode never buffers the input: bytes are absorbed straight into the
+//! SHAKE256 state that computes μ (FIPS 204, Algorithm 7 line 6).
+//!
+//! [`PersonalMessage`] is the EIP-191 `personal_sign` flavour: the message is
+//! prefixed and keccak-hashed the way wallets do before anything is signed.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use alloy_primitives::{Address, B256, U256, eip191_hash_message, keccak256};
+use ml_dsa::{MlDsa65, Signature, SigningKey, VerifyingKey};
+use sha3::digest::Update;
+use sha3::digest::array::Array;
+use sha3::digest::consts::{U32, U64};
+use sha3::{Digest, Keccak256, Sha3_256};
+
+use crate::backend::Backend;
+use crate::error::{Result, WalletError};
+use crate::keys::SEED_LEN;
+
+/// Digest applied to streamed input before signing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HashAlg {
+    /// Ethereum's keccak256 (pre-NIST padding).
+    #[default]
+    Keccak256,
+    /// FIPS 202 SHA3-256.
+    Sha3_256,
+}
+
+impl std::str::FromStr for HashAlg {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "keccak256" | "keccak" => Ok(HashAlg::Keccak256),
+            "sha3-256" | "sha3" => Ok(HashAlg::Sha3_256),
+            other => Err(WalletError::UnknownDigest(other.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for HashAlg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HashAlg::Keccak256 => write!(f, "keccak256"),
+            HashAlg::Sha3_256 => write!(f, "sha3-256"),
+        }
+    }
+}
+
+/// Where streamed input comes from.
+#[derive(Clone, Debug)]
+pub enum Source {
+    File(PathBuf),
+    Stdin,
+}
+
+impl Source {
+    pub fn label(&self) -> String {
+        match self {
+            Source::File(path) => path.display().to_string(),
+            Source::Stdin => "stdin".to_string(),
+        }
+    }
+
+    fn open(&self) -> Result<Box<dyn Read>> {
+        match self {
+            Source::File(path) => File::open(path)
+                .map(|f| Box::new(f) as Box<dyn Read>)
+                .map_err(|e| WalletError::Io(self.label(), e)),
+            Source::Stdin => Ok(Box::new(io::stdin().lock())),
+        }
+    }
+}
+
+/// A fully resolved signing/verification input.
+#[derive(Clone, Debug)]
+pub enum MessageInput {
+    /// A 32-byte hash supplied directly (the userOpHash flow).
+    Hash([u8; 32]),
+    /// Input hashed with `HashAlg`; the 32-byte digest is the ML-DSA message.
+    Digest(Source, HashAlg),
+    /// Input bytes are the ML-DSA message.
+    Raw(Source),
+}
+
+/// Stream `reader` through `alg`.
+pub fn hash_reader<R: Read>(alg: HashAlg, mut reader: R) -> io::Result<[u8; 32]> {
+    fn run<D: Digest<OutputSize = U32>, R: Read>(
+        mut hasher: D,
+        reader: &mut R,
+    ) -> io::Result<[u8; 32]> {
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            Digest::update(&mut hasher, &buf[..n]);
+        }
+        Ok(hasher.finalize().into())
+    }
+    match alg {
+        HashAlg::Keccak256 => run(Keccak256::new(), &mut reader),
+        HashAlg::Sha3_256 => run(Sha3_256::new(), &mut reader),
+    }
+}
+
+/// Compute μ for the pure (empty-context) ML-DSA message read from `reader`.
+fn mu_from_reader<R: Read>(
+    vk: &VerifyingKey<MlDsa65>,
+    mut reader: R,
+) -> io::Result<Array<u8, U64>> {
+    let mut io_err = None;
+    let mu = vk
+        .compute_mu(
+            |shake| {
+                let mut buf = [0u8; 8192];
+                loop {
+                    match reader.read(&mut buf) {
+                        Ok(0) => return Ok(()),
+                        Ok(n) => shake.update(&buf[..n]),
+                        Err(e) => {
+                            io_err = Some(e);
+                            return Err(ml_dsa::Error::new());
+                        }
+                    }
+                }
+            },
+            &[],
+        )
+        .map_err(|_| io_err.take().unwrap_or_else(|| io::Error::other("mu")))?;
+    Ok(mu)
+}
+
+impl MessageInput {
+    /// The 32-byte message for hash/digest inputs; `None` in raw mode.
+    pub fn digest(&self) -> Result<Option<[u8; 32]>> {
+        match self {
+            MessageInput::Hash(h) => Ok(Some(*h)),
+            MessageInput::Digest(src, alg) => hash_reader(*alg, src.open()?)
+                .map(Some)
+                .map_err(|e| WalletError::Io(src.label(), e)),
+            MessageInput::Raw(_) => Ok(None),
+        }
+    }
+
+    /// The message itself: the 32-byte digest, or the whole raw input read
+    /// into memory.
+    pub fn payload(&self) -> Result<Vec<u8>> {
+        match self {
+            MessageInput::Raw(src) => {
+                let mut buf = Vec::new();
+                src.open()?
+                    .read_to_end(&mut buf)
+                    .map_err(|e| WalletError::Io(src.label(), e))?;
+                Ok(buf)
+            }
+            _ => Ok(self.digest()?.expect("non-raw input").to_vec()),
+        }
+    }
+
+    /// Deterministic ML-DSA-65 signature over this input. Equivalent to
+    /// `Signer::sign` on the resolved message bytes.
+    #[tracing::instrument(level = "debug", name = "sign", skip_all)]
+    pub fn sign(&self, sk: &SigningKey<MlDsa65>) -> Result<Signature<MlDsa65>> {
+        use ml_dsa::signature::Signer;
+        match self {
+            MessageInput::Raw(src) => {
+                let mu = mu_from_reader(&sk.verifying_key(), src.open()?)
+                    .map_err(|e| WalletError::Io(src.label(), e))?;
+                Ok(sk.sign_mu_deterministic(&mu))
+            }
+            _ => Ok(sk.sign(&self.digest()?.expect("non-raw input"))),
+        }
+    }
+
+    /// [`sign`](Self::sign) with the seed, on `backend`. fips204 has no
+    /// external-μ interface, so it reads raw input whole.
+    pub fn sign_on(&self, backend: Backend, seed: &[u8; SEED_LEN]) -> Result<Vec<u8>> {
+        match backend {
+            Backend::RustCrypto => {
+                let sk = SigningKey::<MlDsa65>::from_seed(&(*seed).into());
+                Ok(self.sign(&sk)?.encode().to_vec())
+            }
+            #[cfg(feature = "fips204")]
+            Backend::Fips204 => backend.sign(seed, &self.payload()?, &[], &[0; 32]),
+        }
+    }
+
+    /// μ for this input, and the keccak256 of the ML-DSA message that
+    /// [`crate::verify_cache`] keys it by, from one pass over the input.
+    pub fn mu_and_hash(&self, vk: &VerifyingKey<MlDsa65>) -> Result<(Array<u8, U64>, B256)> {
+        struct Tee<R> {
+            inner: R,
+            hasher: Keccak256,
+        }
+        impl<R: Read> Read for Tee<R> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let n = self.inner.read(buf)?;
+                Digest::update(&mut self.hasher, &buf[..n]);
+                Ok(n)
+            }
+        }
+        let (inner, label): (Box<dyn Read>, _) = match self {
+            MessageInput::Raw(src) => (src.open()?, src.label()),
+            _ => (
+                Box::new(io::Cursor::new(self.digest()?.expect("non-raw input"))),
+                "message".to_string(),
+            ),
+        };
+        let mut tee = Tee {
+            inner,
+            hasher: Keccak256::new(),
+        };
+        let mu = mu_from_reader(vk, &mut tee).map_err(|e| WalletError::Io(label, e))?;
+        let hash: [u8; 32] = tee.hasher.finalize().into();
+        Ok((mu, hash.into()))
+    }
+
+    /// Verify `sig` over this input.
+    pub fn verify(&self, vk: &VerifyingKey<MlDsa65>, sig: &Signature<MlDsa65>) -> Result<bool> {
+        match self {
+            MessageInput::Raw(src) => {
+                let mu =
+                    mu_from_reader(vk, src.open()?).map_err(|e| WalletError::Io(src.label(), e))?;
+                Ok(vk.verify_mu(&mu, sig))
+            }
+            _ => {
+                let msg = self.digest()?.expect("non-raw input");
+                Ok(vk.verify_with_context(&msg, &[], sig))
+            }
+        }
+    }
+}
+
+/// The context `PQValidatorModule.isValidSignatureWithSender` binds a hash
+/// to before verifying it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Erc1271Binding {
+    pub module: Address,
+    pub chain_id: u64,
+    /// The smart account the module is installed on.
+    pub account: Address,
+    /// The contract asking the account whether the signature is valid.
+    pub sender: Address,
+}
+
+impl Erc1271Binding {
+    /// `keccak256(abi.encodePacked(module, chainid, account, sender, hash))`.
+    pub fn hash(&self, hash: B256) -> B256 {
+        let mut packed = Vec::with_capacity(20 + 32 + 20 + 20 + 32);
+        packed.extend_from_slice(self.module.as_slice());
+        packed.extend_from_slice(&U256::from(self.chain_id).to_be_bytes::<32>());
+        packed.extend_from_slice(self.account.as_slice());
+        packed.extend_from_slice(self.sender.as_slice());
+        packed.extend_from_slice(hash.as_slice());
+        keccak256(packed)
+    }
+}
+
+/// An EIP-191 personal message: `"\x19Ethereum Signed Message:\n" + len`
+/// and the bytes, keccak-hashed.
+#[derive(Clone, Debug)]
+pub struct PersonalMessage {
+    pub message: Vec<u8>,
+    pub binding: Option<Erc1271Binding>,
+}
+
+impl PersonalMessage {
+    /// The hash a contract's `isValidSignature` is asked about.
+    pub fn hash(&self) -> B256 {
+        eip191_hash_message(&self.message)
+    }
+
+    /// The 32-byte ML-DSA message: [`Self::hash`], bound to the module's
+    /// context if there is one.
+    pub fn signed_hash(&self) -> B256 {
+        match &self.binding {
+            Some(binding) => binding.hash(self.hash()),
+            None => self.hash(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys;
+    use ml_dsa::signature::{Signer, Verifier};
+
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("pq-cli-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn digests_match_reference_values() {
+        let keccak = hash_reader(HashAlg::Keccak256, &b""[..]).unwrap();
+        assert_eq!(keccak, alloy_primitives::keccak256([]).0);
+        let sha3 = hash_reader(HashAlg::Sha3_256, &b"abc"[..]).unwrap();
+        assert_eq!(
+            hex::encode(sha3),
+            "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532"
+        );
+    }
+
+    #[test]
+    fn raw_streaming_matches_in_memory_signature() {
+        let msg: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+        let path = temp_file("raw", &msg);
+        let kp = keys::keygen_from_seed(&[5u8; 32]);
+
+        let input = MessageInput::Raw(Source::File(path.clone()));
+        let streamed = input.sign(kp.signing_key()).unwrap();
+        assert_eq!(streamed.encode(), kp.signing_key().sign(&msg).encode());
+        assert!(kp.verifying_key().verify(&msg, &streamed).is_ok());
+        assert!(input.verify(kp.verifying_key(), &streamed).unwrap());
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn digest_input_signs_the_digest() {
+        let path = temp_file("digest", b"hello");
+        let kp = keys::keygen_from_seed(&[6u8; 32]);
+        let input = MessageInput::Digest(Source::File(path.clone()), HashAlg::Keccak256);
+        let sig = input.sign(kp.signing_key()).unwrap();
+        let digest = alloy_primitives::keccak256(b"hello");
+        assert!(kp.verifying_key().verify(digest.as_slice(), &sig).is_ok());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn personal_message_is_prefixed_and_bound() {
+        let mut personal = PersonalMessage {
+            message: b"hello".to_vec(),
+            binding: None,
+        };
+        let prefixed = keccak256(b"\x19Ethereum Signed Message:\n5hello");
+        assert_eq!(personal.hash(), prefixed);
+        assert_eq!(personal.signed_hash(), prefixed);
+
+        let binding = Erc1271Binding {
+            module: Address::repeat_byte(1),
+            chain_id: 31337,
+            account: Address::repeat_byte(2),
+            sender: Address::repeat_byte(3),
+        };
+        personal.binding = Some(binding);
+        let packed = [
+            &[1u8; 20][..],
+            &U256::from(31337).to_be_bytes::<32>(),
+            &[2u8; 20],
+            &[3u8; 20],
+            prefixed.as_slice(),
+        ]
+        .concat();
+        assert_eq!(personal.signed_hash(), keccak256(packed));
+        assert_eq!(personal.hash(), prefixed);
+    }
+}
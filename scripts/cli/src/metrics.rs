@@ -0,0 +1,282 @@
+//! Prometheus metrics for the signing path (`metrics` feature).
+//!
+//! | Metric | Type | Labels |
+//! |---|---|---|
+//! | `pq_signatures_total` | counter | `tool` |
+//! | `pq_sign_duration_seconds` | histogram | |
+//! | `pq_policy_denials_total` | counter | `rule` |
+//! | `pq_keystore_unlock_failures_total` | counter | |
+//! | `pq_verification_failures_total` | counter | `tool` |
+//! | `pq_verify_cache_lookups_total` | counter | `tool`, `result` |
+//!
+//! A long-running process exposes them with [`serve`] on `GET /metrics`. The
+//! CLI tools exit after one operation, so set `PQ_WALLET_METRICS_FILE` to a
+//! file in node_exporter's textfile-collector directory instead: every event
+//! is added to the totals already in that file. All series are counters or
+//! cumulative histogram buckets, so adding samples is always sound.
+//! Concurrent writers can lose an update; point each host's tools at their
+//! own file.
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::error::{Result, WalletError};
+use crate::policy::Rule;
+use crate::verify_cache::CacheStats;
+
+/// Textfile the CLI adds its events to.
+pub const METRICS_FILE_ENV: &str = "PQ_WALLET_METRICS_FILE";
+
+/// `pq_sign_duration_seconds` upper bounds. Signing takes about a
+/// millisecond; the tail covers keystore decryption and slow hosts.
+pub const SIGN_BUCKETS: [f64; 8] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.05, 0.25, 1.0];
+
+const HELP: &[(&str, &str, &str)] = &[
+    ("pq_signatures_total", "counter", "Signatures produced."),
+    (
+        "pq_sign_duration_seconds",
+        "histogram",
+        "Time to produce one signature.",
+    ),
+    (
+        "pq_policy_denials_total",
+        "counter",
+        "Operations refused by the signing policy.",
+    ),
+    (
+        "pq_keystore_unlock_failures_total",
+        "counter",
+        "Keystore decryptions that failed (wrong password or corruption).",
+    ),
+    (
+        "pq_verification_failures_total",
+        "counter",
+        "Signatures that failed verification.",
+    ),
+    (
+        "pq_verify_cache_lookups_total",
+        "counter",
+        "Verification cache lookups, by `result` hit or miss.",
+    ),
+];
+
+/// Series (`name{labels}`) to value.
+type Samples = BTreeMap<String, f64>;
+
+static REGISTRY: Mutex<Samples> = Mutex::new(BTreeMap::new());
+
+fn record(delta: Samples) {
+    {
+        let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+        add(&mut registry, &delta);
+    }
+    if let Some(path) = std::env::var_os(METRICS_FILE_ENV)
+        && let Err(e) = merge_into(Path::new(&path), &delta)
+    {
+        tracing::warn!("metrics file: {e}");
+    }
+}
+
+fn add(into: &mut Samples, delta: &Samples) {
+    for (series, value) in delta {
+        *into.entry(series.clone()).or_default() += value;
+    }
+}
+
+pub fn signature(tool: &str, elapsed: Duration) {
+    let secs = elapsed.as_secs_f64();
+    let mut delta = Samples::new();
+    delta.insert(format!("pq_signatures_total{{tool=\"{tool}\"}}"), 1.0);
+    for bound in SIGN_BUCKETS {
+        if secs <= bound {
+            delta.insert(
+                format!("pq_sign_duration_seconds_bucket{{le=\"{bound}\"}}"),
+                1.0,
+            );
+        }
+    }
+    delta.insert("pq_sign_duration_seconds_bucket{le=\"+Inf\"}".into(), 1.0);
+    delta.insert("pq_sign_duration_seconds_sum".into(), secs);
+    delta.insert("pq_sign_duration_seconds_count".into(), 1.0);
+    record(delta);
+}
+
+pub fn policy_denial(rule: Rule) {
+    record(Samples::from([(
+        format!("pq_policy_denials_total{{rule=\"{rule}\"}}"),
+        1.0,
+    )]));
+}
+
+pub fn unlock_failure() {
+    record(Samples::from([(
+        "pq_keystore_unlock_failures_total".to_string(),
+        1.0,
+    )]));
+}
+
+pub fn verification_failure(tool: &str) {
+    record(Samples::from([(
+        format!("pq_verification_failures_total{{tool=\"{tool}\"}}"),
+        1.0,
+    )]));
+}
+
+/// Add `stats`' hits and misses; the hit rate is
+/// `hit / (hit + miss)` over the two series.
+pub fn verify_cache_lookups(tool: &str, stats: &CacheStats) {
+    let mut delta = Samples::new();
+    for (result, n) in [("hit", stats.hits), ("miss", stats.misses)] {
+        if n > 0 {
+            delta.insert(
+                format!("pq_verify_cache_lookups_total{{tool=\"{tool}\",result=\"{result}\"}}"),
+                n as f64,
+            );
+        }
+    }
+    if !delta.is_empty() {
+        record(delta);
+    }
+}
+
+fn base_name(series: &str) -> &str {
+    let name = series.split('{').next().unwrap_or(series);
+    ["_bucket", "_sum", "_count"]
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .filter(|base| {
+            HELP.iter()
+                .any(|(n, kind, _)| n == base && *kind == "histogram")
+        })
+        .unwrap_or(name)
+}
+
+fn bucket_bound(series: &str) -> f64 {
+    series
+        .split_once("le=\"")
+        .and_then(|(_, rest)| rest.split('"').next())
+        .map_or(0.0, |le| le.parse().unwrap_or(f64::INFINITY))
+}
+
+/// Prometheus text exposition format (0.0.4).
+fn render_samples(samples: &Samples) -> String {
+    let mut out = String::new();
+    for (name, kind, help) in HELP {
+        let mut series: Vec<_> = samples
+            .iter()
+            .filter(|(s, _)| base_name(s) == *name)
+            .collect();
+        // Buckets in ascending `le` order, `+Inf` last.
+        series.sort_by(|(a, _), (b, _)| bucket_bound(a).total_cmp(&bucket_bound(b)));
+        if series.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("# HELP {name} {help}\n# TYPE {name} {kind}\n"));
+        for (s, value) in series {
+            out.push_str(&format!("{s} {value}\n"));
+        }
+    }
+    out
+}
+
+fn parse_samples(text: &str) -> Samples {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (series, value) = line.rsplit_once(' ')?;
+            Some((series.to_string(), value.parse().ok()?))
+        })
+        .collect()
+}
+
+/// This process's metrics.
+pub fn render() -> String {
+    render_samples(&REGISTRY.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// Add `delta` to the totals in a textfile, replacing it atomically.
+fn merge_into(path: &Path, delta: &Samples) -> Result<()> {
+    let io_err = |e| WalletError::Io(path.display().to_string(), e);
+    let mut samples = match std::fs::read_to_string(path) {
+        Ok(text) => parse_samples(&text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Samples::new(),
+        Err(e) => return Err(io_err(e)),
+    };
+    add(&mut samples, delta);
+    // node_exporter ignores files not ending in .prom, so the temp file is
+    // never scraped half-written.
+    let tmp = path.with_extension("prom.tmp");
+    std::fs::write(&tmp, render_samples(&samples)).map_err(io_err)?;
+    std::fs::rename(&tmp, path).map_err(io_err)
+}
+
+/// Serve [`render`] on `GET /metrics` from a background thread.
+pub fn serve(addr: impl ToSocketAddrs) -> Result<std::thread::JoinHandle<()>> {
+    let listener =
+        TcpListener::bind(addr).map_err(|e| WalletError::Io("metrics listener".into(), e))?;
+    Ok(std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request_line = String::new();
+            if BufReader::new(&stream)
+                .read_line(&mut request_line)
+                .is_err()
+            {
+                continue;
+            }
+            let response = if request_line.starts_with("GET /metrics ") {
+                let body = render();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".into()
+            };
+            let _ = stream.write_all(response.as_bytes());
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative_and_render_grouped() {
+        signature("pq-sign", Duration::from_millis(3));
+        let text = render();
+        assert!(text.contains("# TYPE pq_sign_duration_seconds histogram\n"));
+        assert!(text.contains("pq_sign_duration_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(!text.contains("pq_sign_duration_seconds_bucket{le=\"0.0025\"}"));
+        assert!(text.contains("pq_sign_duration_seconds_bucket{le=\"1\"} 1\npq_sign_duration_seconds_bucket{le=\"+Inf\"} 1\n"));
+        assert!(text.contains("pq_signatures_total{tool=\"pq-sign\"} 1\n"));
+        assert_eq!(
+            base_name("pq_sign_duration_seconds_count"),
+            "pq_sign_duration_seconds"
+        );
+        assert_eq!(
+            base_name("pq_signatures_total{tool=\"x\"}"),
+            "pq_signatures_total"
+        );
+    }
+
+    #[test]
+    fn textfile_accumulates_across_runs() {
+        let path = std::env::temp_dir().join(format!("pq-metrics-{}.prom", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let denial = Samples::from([("pq_policy_denials_total{rule=\"chain\"}".to_string(), 1.0)]);
+        merge_into(&path, &denial).unwrap();
+        merge_into(&path, &denial).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("# HELP pq_policy_denials_total"));
+        assert_eq!(
+            parse_samples(&text)["pq_policy_denials_total{rule=\"chain\"}"],
+            2.0
+        );
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,451 @@
+//! Notifications for wallet backends: HTTP webhooks and command hooks.
+//!
+//! A [`Notifier`] is a list of sinks read from a TOML or JSON file. Each
+//! sink is a webhook URL to POST to or a command to run with the payload on
+//! its stdin, optionally restricted to some [`Event`]s:
+//!
+//! ```toml
+//! [[sink]]
+//! webhook = "https://hooks.example/pq"
+//! events = ["policy-denied", "watch-alert"]
+//! headers = { Authorization = "Bearer ..." }
+//! template = { text = "{{tool}}: {{summary}}", account = "{{data.account}}" }
+//!
+//! [[sink]]
+//! command = ["/usr/local/bin/on-signed"]
+//! events = ["signed"]
+//! ```
+//!
+//! The payload is the [`Notification`] as JSON, or the sink's `template`
+//! with each `{{path}}` filled in from it: a string that is only a
+//! placeholder takes the value as is, and one inside other text takes its
+//! text. A missing path is null, or empty in text. A delivery that fails
+//! (an error status, a command exiting non-zero) is retried `retries` times,
+//! waiting `backoff_ms` and then twice as long each time. A notification is
+//! never a reason to refuse a signature: callers log failures and go on.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::attestation;
+use crate::error::{Result, WalletError};
+
+/// Environment variable naming the event, for command hooks.
+pub const EVENT_ENV: &str = "PQ_NOTIFY_EVENT";
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::Notify(reason.into())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Event {
+    /// `pq-signerd` signed a hash or an op.
+    Signed,
+    /// The signing policy refused an op.
+    PolicyDenied,
+    /// A bundler reported the op included.
+    OpIncluded,
+    /// `pq-watch` raised an alert.
+    WatchAlert,
+    /// `pq-signerd` put an account on hold (see [`crate::guard`]).
+    Anomaly,
+    /// `pq-signerd` parked an op for an approver (see
+    /// [`crate::proposal`]).
+    ApprovalNeeded,
+}
+
+impl Event {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Event::Signed => "signed",
+            Event::PolicyDenied => "policy-denied",
+            Event::OpIncluded => "op-included",
+            Event::WatchAlert => "watch-alert",
+            Event::Anomaly => "anomaly",
+            Event::ApprovalNeeded => "approval-needed",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Notification {
+    pub event: Event,
+    pub time: u64,
+    pub tool: String,
+    /// One line for a human.
+    pub summary: String,
+    /// The event's details.
+    pub data: Value,
+}
+
+impl Notification {
+    pub fn new(event: Event, tool: &str, summary: impl Into<String>, data: Value) -> Self {
+        Notification {
+            event,
+            time: attestation::now(),
+            tool: tool.to_string(),
+            summary: summary.into(),
+            data,
+        }
+    }
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+fn default_backoff_ms() -> u64 {
+    1_000
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+/// Where notifications go.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sink {
+    /// POST the payload here.
+    #[serde(default)]
+    pub webhook: Option<String>,
+    /// Run this program and its arguments with the payload on stdin.
+    #[serde(default)]
+    pub command: Vec<String>,
+    /// Extra headers for the webhook.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// The events to send; empty for all.
+    #[serde(default)]
+    pub events: BTreeSet<Event>,
+    /// The payload, with `{{path}}` placeholders; the notification itself
+    /// when absent.
+    #[serde(default)]
+    pub template: Option<Value>,
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+    /// Longest one attempt may take.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Sink {
+    /// A webhook for `events`, with the default retries.
+    pub fn webhook(url: impl Into<String>, events: impl IntoIterator<Item = Event>) -> Self {
+        Sink {
+            webhook: Some(url.into()),
+            command: Vec::new(),
+            headers: BTreeMap::new(),
+            events: events.into_iter().collect(),
+            template: None,
+            retries: default_retries(),
+            backoff_ms: default_backoff_ms(),
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+
+    pub fn wants(&self, event: Event) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+
+    fn target(&self) -> String {
+        match &self.webhook {
+            Some(url) => url.clone(),
+            None => self.command.join(" "),
+        }
+    }
+
+    /// The payload for `notification`.
+    pub fn payload(&self, notification: &Notification) -> Value {
+        let value = serde_json::to_value(notification).expect("notification serializes");
+        match &self.template {
+            Some(template) => render(template, &value),
+            None => value,
+        }
+    }
+
+    /// Deliver `notification`, retrying with backoff.
+    pub fn deliver(&self, notification: &Notification) -> Result<()> {
+        let payload = self.payload(notification);
+        let mut delay = Duration::from_millis(self.backoff_ms);
+        let mut attempt = 0;
+        loop {
+            match self.attempt(notification.event, &payload) {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= self.retries => return Err(e),
+                Err(e) => {
+                    tracing::debug!(error = %e, attempt, target = %self.target(), "notification failed, retrying");
+                    std::thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    fn attempt(&self, event: Event, payload: &Value) -> Result<()> {
+        let timeout = Duration::from_secs(self.timeout_secs);
+        if let Some(url) = &self.webhook {
+            let agent: ureq::Agent = ureq::Agent::config_builder()
+                .timeout_global(Some(timeout))
+                .build()
+                .into();
+            let mut request = agent.post(url);
+            for (name, value) in &self.headers {
+                request = request.header(name, value);
+            }
+            request
+                .send_json(payload)
+                .map_err(|e| err(format!("{url}: {e}")))?;
+            return Ok(());
+        }
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or_else(|| err("a sink needs a webhook or a command"))?;
+        let mut child = Command::new(program)
+            .args(args)
+            .env(EVENT_ENV, event.as_str())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|e| err(format!("{program}: {e}")))?;
+        let body = serde_json::to_vec(payload).expect("payload serializes");
+        if let Some(mut stdin) = child.stdin.take() {
+            // A hook that ignores its input is fine.
+            let _ = stdin.write_all(&body);
+        }
+        let deadline = Instant::now() + timeout;
+        loop {
+            match child
+                .try_wait()
+                .map_err(|e| err(format!("{program}: {e}")))?
+            {
+                Some(status) if status.success() => return Ok(()),
+                Some(status) => return Err(err(format!("{program} exited with {status}"))),
+                None if Instant::now() >= deadline => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(err(format!("{program} took more than {timeout:?}")));
+                }
+                None => std::thread::sleep(Duration::from_millis(20)),
+            }
+        }
+    }
+}
+
+/// `template` with its `{{path}}` placeholders filled in from `value`.
+pub fn render(template: &Value, value: &Value) -> Value {
+    match template {
+        Value::String(text) => {
+            let trimmed = text.trim();
+            if let Some(path) = trimmed
+                .strip_prefix("{{")
+                .and_then(|rest| rest.strip_suffix("}}"))
+                .filter(|path| !path.contains("{{"))
+            {
+                return lookup(value, path.trim()).cloned().unwrap_or(Value::Null);
+            }
+            let mut out = String::new();
+            let mut rest = text.as_str();
+            while let Some(start) = rest.find("{{") {
+                let Some(end) = rest[start..].find("}}") else {
+                    break;
+                };
+                out.push_str(&rest[..start]);
+                match lookup(value, rest[start + 2..start + end].trim()) {
+                    Some(Value::String(s)) => out.push_str(s),
+                    Some(Value::Null) | None => {}
+                    Some(other) => out.push_str(&other.to_string()),
+                }
+                rest = &rest[start + end + 2..];
+            }
+            out.push_str(rest);
+            Value::String(out)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(|t| render(t, value)).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(k, t)| (k.clone(), render(t, value)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |v, key| match v {
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => v.get(key),
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    #[serde(default)]
+    sink: Vec<Sink>,
+}
+
+/// The sinks to send notifications to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Notifier {
+    pub sinks: Vec<Sink>,
+}
+
+impl Notifier {
+    pub fn new(sinks: Vec<Sink>) -> Result<Self> {
+        for sink in &sinks {
+            if sink.webhook.is_none() == sink.command.is_empty() {
+                return Err(err("each sink needs exactly one of webhook and command"));
+            }
+        }
+        Ok(Notifier { sinks })
+    }
+
+    /// Read sinks, as TOML if the file ends in `.toml` and JSON otherwise.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| WalletError::Io(path.display().to_string(), e))?;
+        let config: Config = if path.extension().is_some_and(|ext| ext == "toml") {
+            toml::from_str(&text).map_err(|e| err(format!("{}: {e}", path.display())))?
+        } else {
+            serde_json::from_str(&text).map_err(|e| err(format!("{}: {e}", path.display())))?
+        };
+        Self::new(config.sink)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Deliver `notification` to every sink that wants it. Every sink is
+    /// tried; the error names the ones that failed.
+    pub fn send(&self, notification: &Notification) -> Result<()> {
+        let failures: Vec<String> = self
+            .sinks
+            .iter()
+            .filter(|sink| sink.wants(notification.event))
+            .filter_map(|sink| sink.deliver(notification).err())
+            .map(|e| e.to_string())
+            .collect();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(err(failures.join("; ")))
+        }
+    }
+
+    /// [`Notifier::send`], logging a failure instead of returning it.
+    pub fn notify(&self, notification: &Notification) {
+        if let Err(e) = self.send(notification) {
+            tracing::warn!(error = %e, event = notification.event.as_str(), "notification not delivered");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn notification() -> Notification {
+        Notification::new(
+            Event::PolicyDenied,
+            "pq-signerd",
+            "over the daily limit",
+            json!({ "account": "alice", "chainId": 421614 }),
+        )
+    }
+
+    #[test]
+    fn renders_templates_and_filters_events() {
+        let config = r#"
+            [[sink]]
+            webhook = "http://127.0.0.1:1/hook"
+            events = ["policy-denied"]
+            template = { text = "{{tool}}: {{summary}} ({{data.chainId}})", chain = "{{ data.chainId }}", missing = "{{data.nope}}" }
+        "#;
+        let config: Config = toml::from_str(config).unwrap();
+        let notifier = Notifier::new(config.sink).unwrap();
+        let sink = &notifier.sinks[0];
+        assert!(sink.wants(Event::PolicyDenied));
+        assert!(!sink.wants(Event::Signed));
+        assert_eq!(
+            sink.payload(&notification()),
+            json!({
+                "text": "pq-signerd: over the daily limit (421614)",
+                "chain": 421614,
+                "missing": null,
+            })
+        );
+        let plain = Sink::webhook("http://x", []);
+        assert_eq!(plain.payload(&notification())["event"], "policy-denied");
+        assert!(
+            Notifier::new(vec![Sink {
+                command: vec!["true".into()],
+                ..plain
+            }])
+            .is_err()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runs_command_hooks_and_retries_failures() {
+        let dir = std::env::temp_dir().join(format!("pq-notify-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("payloads");
+        let _ = std::fs::remove_file(&out);
+        let hook = |script: &str| Sink {
+            webhook: None,
+            command: vec![
+                "sh".into(),
+                "-c".into(),
+                script.into(),
+                "hook".into(),
+                out.display().to_string(),
+            ],
+            retries: 2,
+            backoff_ms: 0,
+            ..Sink::webhook("", [])
+        };
+        let notifier = Notifier::new(vec![hook(
+            r#"cat >> "$1"; echo "$PQ_NOTIFY_EVENT" >> "$1""#,
+        )])
+        .unwrap();
+        notifier.send(&notification()).unwrap();
+        let written = std::fs::read_to_string(&out).unwrap();
+        assert!(written.contains(r#""summary":"over the daily limit""#));
+        assert!(written.ends_with("policy-denied\n"));
+
+        // Fails twice, then succeeds on the last retry.
+        let count = dir.join("count");
+        let _ = std::fs::remove_file(&count);
+        let flaky = format!(
+            r#"echo x >> {0}; [ "$(wc -l < {0})" -ge 3 ]"#,
+            count.display()
+        );
+        Notifier::new(vec![hook(&flaky)])
+            .unwrap()
+            .send(&notification())
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&count).unwrap().lines().count(), 3);
+        assert!(
+            Notifier::new(vec![hook("exit 1")])
+                .unwrap()
+                .send(&notification())
+                .is_err()
+        );
+    }
+}
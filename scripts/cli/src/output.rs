@@ -0,0 +1,262 @@
+//! Machine-readable output for the `--json` flag.
+//!
+//! Each binary prints exactly one JSON object on stdout in JSON mode. Field
+//! names are part of the CLI contract: add fields, never rename or remove
+//! them. [`emit`] adds `schemaVersion` to each, and `--schema` prints the
+//! contract as a JSON Schema ([`crate::schema`]). Human-readable progress
+//! goes to stderr so stdout stays parseable.
+//!
+//! Exit codes are shared by every binary:
+//!
+//! | Code | Meaning |
+//! |------|---------|
+//! | [`EXIT_OK`] | Success; for `pq-verify`, the signature is valid |
+//! | [`EXIT_FAILURE`] | The check ran and failed: invalid signature, ACVP failure, backend divergence |
+//! | [`EXIT_USAGE`] | Bad arguments (clap's own exit code) |
+//! | [`EXIT_ERROR`] | I/O or decoding error before the check could run |
+
+use std::collections::BTreeMap;
+
+use alloy_primitives::{Address, B256};
+use serde::Serialize;
+
+use crate::attestation::Attestation;
+use crate::ct;
+use crate::envelope::SCHEME_ML_DSA_65;
+use crate::error::WalletError;
+use crate::keys;
+use crate::keystore::Account;
+use crate::logging::LogArgs;
+use crate::message::HashAlg;
+use crate::profile::Profile;
+use crate::schema;
+use crate::signing_scheme::SigningScheme;
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_ERROR: i32 = 3;
+
+/// The shared `--json` and `--schema` flags, plus the logging flags.
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub struct OutputArgs {
+    /// Print a single JSON object on stdout instead of human-readable text
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Print the JSON Schema of this command's --json output and exit
+    #[arg(long, global = true)]
+    pub schema: bool,
+
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+/// `pq-keygen --json`.
+#[derive(Debug, Serialize)]
+pub struct KeygenOutput {
+    /// Key identifier: last 20 bytes of keccak256(pubkey), 0x-prefixed.
+    pub address: String,
+    /// 0x-prefixed encoded public key.
+    pub pubkey: String,
+    /// keccak256(pubkey), the on-chain owner commitment.
+    pub fingerprint: String,
+    /// Directory holding `pk.bin` and `sk.bin`.
+    pub path: String,
+}
+
+impl KeygenOutput {
+    pub fn new(public_key: &[u8], path: &std::path::Path) -> Self {
+        KeygenOutput {
+            address: keys::address(public_key).to_string(),
+            pubkey: format!("0x{}", ct::encode_hex(public_key)),
+            fingerprint: keys::fingerprint(public_key, HashAlg::Keccak256).to_string(),
+            path: path.display().to_string(),
+        }
+    }
+}
+
+/// `pq-account show/list --json`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountOutput {
+    pub name: String,
+    pub scheme: String,
+    /// Key identifier, as in [`KeygenOutput::address`].
+    pub address: String,
+    pub pubkey: String,
+    /// keccak256(pubkey), as in [`KeygenOutput::fingerprint`].
+    pub fingerprint: String,
+    /// Smart account address per chain ID.
+    pub addresses: BTreeMap<u64, Address>,
+    /// Signing scheme per chain ID, where not the default.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub signing_schemes: BTreeMap<u64, SigningScheme>,
+    /// Validator profile per chain ID.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<u64, Profile>,
+    /// No seed: the account builds ops but cannot sign them.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub watch_only: bool,
+    /// Keystore file.
+    pub path: String,
+}
+
+impl AccountOutput {
+    pub fn new(account: &Account, path: &std::path::Path) -> crate::Result<Self> {
+        Ok(AccountOutput {
+            name: account.name.clone(),
+            scheme: account.scheme.clone(),
+            address: account.key_id()?.to_string(),
+            pubkey: format!("0x{}", account.public_key),
+            fingerprint: keys::fingerprint(&account.public_key()?, HashAlg::Keccak256).to_string(),
+            addresses: account.addresses.clone(),
+            signing_schemes: account.signing_schemes.clone(),
+            profiles: account.profiles.clone(),
+            watch_only: account.watch_only,
+            path: path.display().to_string(),
+        })
+    }
+}
+
+/// `pq-sign --json`.
+#[derive(Debug, Serialize)]
+pub struct SignOutput {
+    /// 0x-prefixed encoded signature.
+    pub signature: String,
+    /// Signature length in bytes.
+    pub length: usize,
+    /// Envelope scheme identifier (see [`crate::envelope`]).
+    pub scheme: u8,
+    /// Host attestation for the signing key (`pq-sign --attestation`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<Attestation>,
+    /// 0x-prefixed COSE_Sign1 holding `signature` (`pq-sign --format cose`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cose: Option<String>,
+    /// EIP-191 hash of the message (`pq-sign personal`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_hash: Option<B256>,
+    /// 0x-prefixed envelope holding `signature` (`pq-sign personal`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub envelope: Option<String>,
+    /// The Sign-In with Ethereum message that was signed (`pq-sign siwe`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl SignOutput {
+    pub fn ml_dsa_65(signature: &[u8]) -> Self {
+        SignOutput {
+            signature: format!("0x{}", ct::encode_hex(signature)),
+            length: signature.len(),
+            scheme: SCHEME_ML_DSA_65,
+            attestation: None,
+            cose: None,
+            message_hash: None,
+            envelope: None,
+            message: None,
+        }
+    }
+}
+
+/// `pq-verify --json`.
+#[derive(Debug, Serialize)]
+pub struct VerifyOutput {
+    pub valid: bool,
+    /// Why verification failed; `null` when `valid`.
+    pub reason: Option<String>,
+    /// The scheme the signature was checked under (see [`crate::schemes`]).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<&'static str>,
+    /// The result came from the verification cache (see
+    /// [`crate::verify_cache`]).
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+}
+
+impl VerifyOutput {
+    pub fn valid() -> Self {
+        VerifyOutput {
+            valid: true,
+            reason: None,
+            scheme: None,
+            cached: false,
+        }
+    }
+
+    pub fn invalid(reason: impl Into<String>) -> Self {
+        VerifyOutput {
+            valid: false,
+            reason: Some(reason.into()),
+            scheme: None,
+            cached: false,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        if self.valid { EXIT_OK } else { EXIT_FAILURE }
+    }
+}
+
+/// Body printed for an [`EXIT_ERROR`] in JSON mode.
+#[derive(Debug, Serialize)]
+pub struct ErrorOutput {
+    pub error: String,
+}
+
+/// Print `value` as a single line of JSON on stdout, with its
+/// [`schema::SCHEMA_VERSION`].
+pub fn emit<T: Serialize>(value: &T) {
+    let line = serde_json::to_string(value).expect("output types always serialize");
+    println!("{}", schema::stamp(&line));
+}
+
+/// Report `err` (as JSON when `json` is set, else on stderr) and exit with
+/// [`EXIT_ERROR`].
+pub fn fail(json: bool, err: impl std::fmt::Display) -> ! {
+    if json {
+        emit(&ErrorOutput {
+            error: err.to_string(),
+        });
+    } else {
+        eprintln!("error: {err}");
+    }
+    std::process::exit(EXIT_ERROR)
+}
+
+/// Unwrap `result`, or [`fail`] with `what` as context.
+pub fn or_fail<T>(json: bool, what: &str, result: std::result::Result<T, WalletError>) -> T {
+    result.unwrap_or_else(|e| fail(json, format!("{what}: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_names_are_stable() {
+        let v = serde_json::to_value(VerifyOutput::invalid("signature mismatch")).unwrap();
+        assert_eq!(
+            v,
+            serde_json::json!({"valid": false, "reason": "signature mismatch"})
+        );
+        let s = serde_json::to_value(SignOutput::ml_dsa_65(&[0xAB; 3])).unwrap();
+        assert_eq!(
+            s,
+            serde_json::json!({"signature": "0xababab", "length": 3, "scheme": 1})
+        );
+    }
+
+    #[test]
+    fn keygen_address_is_keccak_suffix() {
+        let pk = [7u8; keys::PUBLIC_KEY_LEN];
+        let out = KeygenOutput::new(&pk, std::path::Path::new("/tmp/keys"));
+        let hash = alloy_primitives::keccak256(pk);
+        assert_eq!(
+            out.address,
+            format!("{}", alloy_primitives::Address::from_slice(&hash[12..]))
+        );
+        assert!(out.pubkey.starts_with("0x0707"));
+    }
+}
@@ -0,0 +1,222 @@
+//! Paying gas in an ERC-20 through a token paymaster (`pq-userop batch` and
+//! `pq-userop send` with `--paymaster-service` and `--fee-token`).
+//!
+//! A token paymaster fronts the op's ETH prefund and, in `postOp`, takes
+//! the actual cost from the account in the token at a rate its service
+//! quotes. [`quote`] asks the service (`pimlico_getTokenQuotes`) for the
+//! paymaster, the rate in token units per ETH and the gas its `postOp`
+//! needs. [`TokenQuote::paymaster_and_data`] builds the op's
+//! `paymasterAndData` from that: paymaster ‖ verification gas ‖ postOp gas
+//! ‖ token ‖ rate, the rate being what the paymaster holds its own oracle
+//! to.
+//!
+//! The paymaster pulls the token with `transferFrom`, so the account must
+//! have approved it for the fee. [`sponsor`] checks the allowance against
+//! the op's worst-case fee and, when it is short, puts an
+//! `approve(paymaster, fee)` first in the op's own batch: the calls run
+//! before `postOp`, so the approval is in place when the paymaster charges.
+
+use alloy_primitives::{Address, U64, U256};
+use alloy_sol_types::SolCall;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::calldata::{self, Call};
+use crate::decode::{self, approveCall};
+use crate::error::{Result, WalletError};
+use crate::gas::cost;
+use crate::rpc::Transport;
+use crate::userop::{self, PackedUserOperation};
+
+/// `paymasterVerificationGasLimit` until the bundler estimates it.
+pub const DEFAULT_VERIFICATION_GAS: u128 = 100_000;
+
+/// [`TokenQuote::exchange_rate`] is token units per this many wei (1 ETH).
+const RATE_SCALE: u128 = 1_000_000_000_000_000_000;
+
+/// One token's quote from a paymaster service.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenQuote {
+    pub paymaster: Address,
+    pub token: Address,
+    pub post_op_gas: U256,
+    /// Token base units per ETH.
+    pub exchange_rate: U256,
+}
+
+#[derive(Deserialize)]
+struct Quotes {
+    quotes: Vec<TokenQuote>,
+}
+
+/// Ask `service` what its paymaster charges in `token` for ops through
+/// `entry_point` on `chain_id`.
+pub fn quote<T: Transport>(
+    service: &T,
+    token: Address,
+    entry_point: Address,
+    chain_id: u64,
+) -> Result<TokenQuote> {
+    let Quotes { quotes } = service.call(
+        "pimlico_getTokenQuotes",
+        json!([{ "tokens": [token] }, entry_point, U64::from(chain_id)]),
+    )?;
+    let quote = quotes
+        .into_iter()
+        .find(|q| q.token == token)
+        .ok_or_else(|| WalletError::Rpc(format!("the paymaster does not take {token}")))?;
+    if quote.exchange_rate.is_zero() {
+        return Err(WalletError::Rpc(format!("zero exchange rate for {token}")));
+    }
+    Ok(quote)
+}
+
+impl TokenQuote {
+    /// `paymasterAndData` for the quoted paymaster, with `verification_gas`
+    /// and the quoted postOp gas.
+    pub fn paymaster_and_data(&self, verification_gas: u128) -> Vec<u8> {
+        let post_op_gas = u128::try_from(self.post_op_gas).unwrap_or(u128::MAX);
+        let mut data = self.paymaster.to_vec();
+        data.extend_from_slice(&userop::pack_u128_pair(verification_gas, post_op_gas).0);
+        data.extend_from_slice(self.token.as_slice());
+        data.extend_from_slice(&self.exchange_rate.to_be_bytes::<32>());
+        data
+    }
+
+    /// `wei` in the token at the quoted rate, rounded up.
+    pub fn fee(&self, wei: u128) -> U256 {
+        (U256::from(wei) * self.exchange_rate).div_ceil(U256::from(RATE_SCALE))
+    }
+}
+
+/// The most an op costs in the fee token, for the review.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenFee {
+    pub token: Address,
+    pub symbol: String,
+    pub decimals: u8,
+    /// At the op's `maxFeePerGas`.
+    pub max: U256,
+    /// Whether the op approves the paymaster for `max` first.
+    pub approves: bool,
+}
+
+impl TokenFee {
+    pub fn amount(&self) -> String {
+        format!(
+            "{} {}",
+            decode::format_units(self.max, self.decimals),
+            self.symbol
+        )
+    }
+}
+
+/// Build the op making `calls`, paid for in the quoted token, with `build`
+/// (which fills in the nonce, fees and gas, `paymasterAndData` included).
+/// When `allowance` does not cover the fee, the op is rebuilt with an
+/// approval ahead of `calls`.
+pub fn sponsor<F>(
+    quote: &TokenQuote,
+    allowance: U256,
+    calls: &[Call],
+    mut build: F,
+) -> Result<(PackedUserOperation, U256, bool)>
+where
+    F: FnMut(&[Call]) -> Result<PackedUserOperation>,
+{
+    let op = build(calls)?;
+    let fee = quote.fee(cost::cost(&op, 0).max_wei);
+    if allowance >= fee {
+        return Ok((op, fee, false));
+    }
+    // Estimate with the widest amount: the encoding is the same length and
+    // fewer nonzero bytes only lower preVerificationGas.
+    let mut approved = vec![approval(quote, U256::MAX)];
+    approved.extend_from_slice(calls);
+    let mut op = build(&approved)?;
+    let fee = quote.fee(cost::cost(&op, 0).max_wei);
+    approved[0] = approval(quote, fee);
+    op.call_data = calldata::execute(&approved);
+    Ok((op, fee, true))
+}
+
+fn approval(quote: &TokenQuote, amount: U256) -> Call {
+    Call {
+        target: quote.token,
+        value: U256::ZERO,
+        data: approveCall {
+            spender: quote.paymaster,
+            amount,
+        }
+        .abi_encode(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    const USDC: Address = Address::repeat_byte(0xcc);
+
+    struct Service;
+
+    impl Transport for Service {
+        fn request(&self, method: &str, params: Value) -> Result<Value> {
+            assert_eq!(method, "pimlico_getTokenQuotes");
+            assert_eq!(params[2], json!("0x66eee"));
+            Ok(json!({ "quotes": [{
+                "paymaster": Address::repeat_byte(0x77),
+                "token": USDC,
+                "postOpGas": "0xc350",
+                // 2,500 USDC (6 decimals) per ETH.
+                "exchangeRate": "0x9502f900",
+                "exchangeRateNativeToUsd": "0x1",
+            }] }))
+        }
+    }
+
+    #[test]
+    fn sponsor_approves_the_fee_when_the_allowance_is_short() {
+        let quote = quote(&Service, USDC, userop::ENTRY_POINT_V07, 421614).unwrap();
+        assert!(super::quote(&Service, Address::ZERO, userop::ENTRY_POINT_V07, 421614).is_err());
+        let pd = quote.paymaster_and_data(DEFAULT_VERIFICATION_GAS);
+        assert_eq!(pd.len(), 20 + 32 + 20 + 32);
+        let build = |calls: &[Call]| {
+            Ok(PackedUserOperation {
+                sender: Address::repeat_byte(1),
+                nonce: U256::ZERO,
+                init_code: vec![],
+                call_data: calldata::execute(calls),
+                account_gas_limits: userop::pack_u128_pair(100_000, 50_000 + calls.len() as u128),
+                pre_verification_gas: U256::from(50_000),
+                gas_fees: userop::pack_u128_pair(1, 1_000_000_000),
+                paymaster_and_data: pd.clone(),
+                signature: vec![],
+            })
+        };
+        let op = build(&[]).unwrap();
+        assert_eq!(op.paymaster_gas_limits(), (100_000, 50_000));
+        let call = Call {
+            target: Address::repeat_byte(2),
+            value: U256::ZERO,
+            data: vec![],
+        };
+
+        let (_, fee, approves) =
+            sponsor(&quote, U256::MAX, std::slice::from_ref(&call), build).unwrap();
+        // 350,001 gas at 1 gwei at 2,500 USDC/ETH, rounded up.
+        assert_eq!((fee, approves), (U256::from(875_003), false));
+
+        let (op, fee, approves) =
+            sponsor(&quote, U256::ZERO, std::slice::from_ref(&call), build).unwrap();
+        assert_eq!((fee, approves), (U256::from(875_005), true));
+        let calls = calldata::decode_execute(&op.call_data).unwrap();
+        assert_eq!(calls[1], call);
+        let approve = approveCall::abi_decode(&calls[0].data).unwrap();
+        assert_eq!((calls[0].target, approve.spender), (USDC, quote.paymaster));
+        assert_eq!(approve.amount, fee);
+    }
+}
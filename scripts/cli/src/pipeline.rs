@@ -0,0 +1,401 @@
+//! Bulk userOp construction and signing on a thread pool, for airdrops
+//! and other jobs where thousands of ops are signed at once.
+//!
+//! [`Pipeline::run`] takes any iterator of [`Intent`]s. The iterator is
+//! read lazily on a feeder thread, which gives each intent the next nonce
+//! of its sender, in iterator order. `workers` threads then build, hash and
+//! sign the ops in parallel. The returned [`Stream`] yields each op as soon
+//! as it is signed, with one exception: an op waits until every earlier op
+//! from the same sender has been yielded. A bundler therefore sees each
+//! account's nonces in order, while different accounts never wait on each
+//! other. The channels between the stages are bounded, so a slow consumer
+//! slows the feeder down rather than piling up signed ops in memory.
+//! Dropping the stream early stops the threads.
+//!
+//! Signatures are bare, deterministic ML-DSA-65 over the userOpHash, the
+//! same bytes `pq-userop sign` puts in `signature`. Each sender's signing
+//! key is expanded once into a [`PqSigner`], when it is added with
+//! [`Pipeline::account`].
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use alloy_primitives::{Address, B256, U256};
+use zeroize::Zeroizing;
+
+use crate::calldata::{self, Call};
+use crate::check::RECOMMENDED_VERIFICATION_GAS;
+use crate::error::{Result, WalletError};
+use crate::keys::SEED_LEN;
+use crate::plan::DEFAULT_CALL_GAS_LIMIT;
+use crate::signer::PqSigner;
+use crate::userop::{self, ENTRY_POINT_V07, PackedUserOperation};
+
+/// Jobs buffered between stages, per worker.
+const QUEUE_PER_WORKER: usize = 16;
+
+/// Calls one sender wants made in one op.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Intent {
+    pub sender: Address,
+    pub calls: Vec<Call>,
+}
+
+/// A signed op, as the stream yields it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedOp {
+    /// Position of the intent in the input.
+    pub index: usize,
+    pub user_op_hash: B256,
+    pub op: PackedUserOperation,
+}
+
+/// Gas fields every op gets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Gas {
+    pub verification_gas_limit: u128,
+    pub call_gas_limit: u128,
+    pub pre_verification_gas: U256,
+    pub max_priority_fee_per_gas: u128,
+    pub max_fee_per_gas: u128,
+}
+
+impl Default for Gas {
+    fn default() -> Self {
+        Gas {
+            verification_gas_limit: RECOMMENDED_VERIFICATION_GAS,
+            call_gas_limit: DEFAULT_CALL_GAS_LIMIT,
+            pre_verification_gas: U256::ZERO,
+            max_priority_fee_per_gas: 0,
+            max_fee_per_gas: 0,
+        }
+    }
+}
+
+struct Account {
+    key: Arc<PqSigner>,
+    next_nonce: U256,
+    /// Intents handed out so far, for the stream's per-sender order.
+    queued: u64,
+}
+
+pub struct Pipeline {
+    entry_point: Address,
+    chain_id: U256,
+    gas: Gas,
+    workers: usize,
+    accounts: HashMap<Address, Account>,
+}
+
+/// One op to build and sign.
+struct Job {
+    index: usize,
+    seq: u64,
+    key: Arc<PqSigner>,
+    op: PackedUserOperation,
+}
+
+/// A finished job, or an intent refused before it became one.
+struct Done {
+    sender: Address,
+    seq: Option<u64>,
+    result: Result<SignedOp>,
+}
+
+impl Pipeline {
+    /// A pipeline signing for `chain_id` against EntryPoint v0.7, on one
+    /// worker per CPU.
+    pub fn new(chain_id: u64) -> Self {
+        Pipeline {
+            entry_point: ENTRY_POINT_V07,
+            chain_id: U256::from(chain_id),
+            gas: Gas::default(),
+            workers: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            accounts: HashMap::new(),
+        }
+    }
+
+    pub fn entry_point(mut self, entry_point: Address) -> Self {
+        self.entry_point = entry_point;
+        self
+    }
+
+    pub fn gas(mut self, gas: Gas) -> Self {
+        self.gas = gas;
+        self
+    }
+
+    /// Signing threads (at least 1).
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Sign `sender`'s ops with `seed`, numbering them from `next_nonce`
+    /// (the EntryPoint's `getNonce` for the sender).
+    pub fn account(
+        mut self,
+        sender: Address,
+        seed: &Zeroizing<[u8; SEED_LEN]>,
+        next_nonce: U256,
+    ) -> Self {
+        let key = Arc::new(PqSigner::from_seed(seed));
+        self.accounts.insert(
+            sender,
+            Account {
+                key,
+                next_nonce,
+                queued: 0,
+            },
+        );
+        self
+    }
+
+    fn unsigned_op(&self, intent: &Intent, nonce: U256) -> PackedUserOperation {
+        let gas = &self.gas;
+        PackedUserOperation {
+            sender: intent.sender,
+            nonce,
+            init_code: Vec::new(),
+            call_data: calldata::execute(&intent.calls),
+            account_gas_limits: userop::pack_u128_pair(
+                gas.verification_gas_limit,
+                gas.call_gas_limit,
+            ),
+            pre_verification_gas: gas.pre_verification_gas,
+            gas_fees: userop::pack_u128_pair(gas.max_priority_fee_per_gas, gas.max_fee_per_gas),
+            paymaster_and_data: Vec::new(),
+            signature: Vec::new(),
+        }
+    }
+
+    /// Start signing `intents`. Ops come out of the returned stream as
+    /// they finish, in nonce order per sender.
+    pub fn run<I>(mut self, intents: I) -> Stream
+    where
+        I: IntoIterator<Item = Intent>,
+        I::IntoIter: Send + 'static,
+    {
+        let bound = self.workers * QUEUE_PER_WORKER;
+        let (job_tx, job_rx) = mpsc::sync_channel::<Job>(bound);
+        let (done_tx, done_rx) = mpsc::sync_channel::<Done>(bound);
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let (entry_point, chain_id) = (self.entry_point, self.chain_id);
+
+        let mut threads = Vec::with_capacity(self.workers + 1);
+        for _ in 0..self.workers {
+            let (job_rx, done_tx) = (job_rx.clone(), done_tx.clone());
+            threads.push(std::thread::spawn(move || {
+                worker(&job_rx, &done_tx, entry_point, chain_id)
+            }));
+        }
+        let intents = intents.into_iter();
+        threads.push(std::thread::spawn(move || {
+            self.feed(intents, &job_tx, &done_tx)
+        }));
+
+        Stream {
+            done: done_rx,
+            order: HashMap::new(),
+            ready: VecDeque::new(),
+            threads,
+        }
+    }
+
+    fn feed(
+        &mut self,
+        intents: impl Iterator<Item = Intent>,
+        jobs: &SyncSender<Job>,
+        done: &SyncSender<Done>,
+    ) {
+        for (index, intent) in intents.enumerate() {
+            let Some(account) = self.accounts.get(&intent.sender) else {
+                let refused = Done {
+                    sender: intent.sender,
+                    seq: None,
+                    result: Err(WalletError::InvalidUserOp(format!(
+                        "intent {index}: no signing key for sender {}",
+                        intent.sender
+                    ))),
+                };
+                if done.send(refused).is_err() {
+                    return;
+                }
+                continue;
+            };
+            let op = self.unsigned_op(&intent, account.next_nonce);
+            let account = self
+                .accounts
+                .get_mut(&intent.sender)
+                .expect("looked up above");
+            let job = Job {
+                index,
+                seq: account.queued,
+                key: account.key.clone(),
+                op,
+            };
+            account.next_nonce += U256::from(1);
+            account.queued += 1;
+            // Fails once the stream is dropped.
+            if jobs.send(job).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn worker(
+    jobs: &Mutex<Receiver<Job>>,
+    done: &SyncSender<Done>,
+    entry_point: Address,
+    chain_id: U256,
+) {
+    loop {
+        let job = {
+            let jobs = jobs.lock().unwrap_or_else(|e| e.into_inner());
+            match jobs.recv() {
+                Ok(job) => job,
+                Err(_) => return,
+            }
+        };
+        let Job {
+            index,
+            seq,
+            key,
+            mut op,
+        } = job;
+        let user_op_hash = userop::compute_user_op_hash(&op, entry_point, chain_id);
+        op.signature = key.sign(user_op_hash.as_slice()).encode().to_vec();
+        let finished = Done {
+            sender: op.sender,
+            seq: Some(seq),
+            result: Ok(SignedOp {
+                index,
+                user_op_hash,
+                op,
+            }),
+        };
+        if done.send(finished).is_err() {
+            return;
+        }
+    }
+}
+
+/// Per-sender reorder buffer.
+#[derive(Default)]
+struct SenderOrder {
+    next: u64,
+    waiting: BTreeMap<u64, Result<SignedOp>>,
+}
+
+/// Signed ops from [`Pipeline::run`], ending once every intent is done.
+pub struct Stream {
+    done: Receiver<Done>,
+    order: HashMap<Address, SenderOrder>,
+    ready: VecDeque<Result<SignedOp>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Iterator for Stream {
+    type Item = Result<SignedOp>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.ready.pop_front() {
+                return Some(item);
+            }
+            let Ok(done) = self.done.recv() else {
+                // Every thread has exited, so nothing is left waiting.
+                debug_assert!(self.order.values().all(|o| o.waiting.is_empty()));
+                for thread in self.threads.drain(..) {
+                    let _ = thread.join();
+                }
+                return None;
+            };
+            let Some(seq) = done.seq else {
+                return Some(done.result);
+            };
+            let order = self.order.entry(done.sender).or_default();
+            order.waiting.insert(seq, done.result);
+            while let Some(result) = order.waiting.remove(&order.next) {
+                self.ready.push_back(result);
+                order.next += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Backend;
+
+    fn seed(n: u8) -> Zeroizing<[u8; SEED_LEN]> {
+        Zeroizing::new([n; SEED_LEN])
+    }
+
+    fn intent(sender: Address, n: u8) -> Intent {
+        Intent {
+            sender,
+            calls: vec![Call {
+                target: Address::repeat_byte(n),
+                value: U256::from(n),
+                data: Vec::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn signs_everything_in_nonce_order_per_sender() {
+        let (a, b) = (Address::repeat_byte(0xaa), Address::repeat_byte(0xbb));
+        let pipeline = Pipeline::new(421614)
+            .workers(4)
+            .account(a, &seed(1), U256::from(7))
+            .account(b, &seed(2), U256::ZERO);
+        let intents: Vec<Intent> = (0..40u8)
+            .map(|n| intent(if n % 3 == 0 { b } else { a }, n))
+            .collect();
+        let signed: Vec<SignedOp> = pipeline
+            .run(intents.clone())
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(signed.len(), intents.len());
+
+        let mut next = HashMap::from([(a, U256::from(7)), (b, U256::ZERO)]);
+        for s in &signed {
+            let nonce = next.get_mut(&s.op.sender).unwrap();
+            assert_eq!(s.op.nonce, *nonce, "op {} out of order", s.index);
+            *nonce += U256::from(1);
+            assert_eq!(s.op.call_data, calldata::execute(&intents[s.index].calls));
+            let hash = userop::compute_user_op_hash(&s.op, ENTRY_POINT_V07, U256::from(421614));
+            assert_eq!(s.user_op_hash, hash);
+            let key = if s.op.sender == a { seed(1) } else { seed(2) };
+            let pk = Backend::RustCrypto.public_key(&key);
+            assert!(Backend::RustCrypto.verify(&pk, hash.as_slice(), &[], &s.op.signature));
+        }
+        assert_eq!(next[&a], U256::from(7 + 26));
+    }
+
+    #[test]
+    fn unknown_senders_are_refused_and_an_early_drop_stops() {
+        let a = Address::repeat_byte(0xaa);
+        let pipeline = Pipeline::new(1).workers(2).account(a, &seed(1), U256::ZERO);
+        let intents = vec![
+            intent(a, 1),
+            intent(Address::repeat_byte(0xcc), 2),
+            intent(a, 3),
+        ];
+        let results: Vec<_> = pipeline.run(intents).collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results.iter().filter(|r| r.is_err()).count(), 1);
+
+        // An endless input: the stream is dropped after a few ops and the
+        // threads wind down instead of signing forever.
+        let pipeline = Pipeline::new(1).workers(2).account(a, &seed(1), U256::ZERO);
+        let endless = (0u32..).map(move |n| intent(a, n as u8));
+        let first: Vec<_> = pipeline.run(endless).take(5).collect();
+        assert_eq!(first.len(), 5);
+    }
+}
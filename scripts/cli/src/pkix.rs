@@ -0,0 +1,467 @@
+//! PKCS#8 and SubjectPublicKeyInfo encodings of ML-DSA-65 keys, as OpenSSL
+//! 3.5 and other PQ tooling read and write them (RFC 9881, formerly
+//! draft-ietf-lamps-dilithium-certificates).
+//!
+//! ```text
+//! SubjectPublicKeyInfo ::= SEQUENCE { AlgorithmIdentifier, BIT STRING pk }
+//! OneAsymmetricKey     ::= SEQUENCE { INTEGER 0, AlgorithmIdentifier,
+//!                                     OCTET STRING ML-DSA-PrivateKey }
+//! ML-DSA-PrivateKey    ::= CHOICE {
+//!     seed        [0] OCTET STRING (SIZE (32)),
+//!     expandedKey     OCTET STRING (SIZE (4032)),
+//!     both            SEQUENCE { seed OCTET STRING, expandedKey OCTET STRING } }
+//! ```
+//!
+//! The AlgorithmIdentifier is the bare `id-ml-dsa-65` OID with parameters
+//! absent. Everything else in this crate works from the 32-byte seed, so an
+//! `expandedKey`-only private key is rejected: it cannot be turned back into
+//! a seed. `both` is accepted when its expanded key matches the seed.
+//!
+//! [`KeyFormat`] picks between these, the raw files, and the JWK and
+//! COSE_Key encodings in [`crate::jwk`] and [`crate::cose`].
+
+use std::fmt;
+use std::str::FromStr;
+
+use base64ct::{Base64, Encoding};
+use zeroize::Zeroizing;
+
+use crate::cose;
+use crate::error::{Result, WalletError};
+use crate::jwk::Jwk;
+use crate::keys::{self, PUBLIC_KEY_LEN, SEED_LEN};
+
+/// DER of OID 2.16.840.1.101.3.4.3.18 (`id-ml-dsa-65`).
+pub const ML_DSA_65_OID: [u8; 11] = [
+    0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x03, 0x12,
+];
+
+/// ML-DSA-65 expanded signing key length (FIPS 204 skEncode).
+pub const EXPANDED_KEY_LEN: usize = 4032;
+
+pub(crate) const SEQUENCE: u8 = 0x30;
+pub(crate) const INTEGER: u8 = 0x02;
+pub(crate) const BIT_STRING: u8 = 0x03;
+pub(crate) const OCTET_STRING: u8 = 0x04;
+const SEED_TAG: u8 = 0x80;
+
+const PUBLIC_KEY_LABEL: &str = "PUBLIC KEY";
+const PRIVATE_KEY_LABEL: &str = "PRIVATE KEY";
+
+/// How a key file is encoded on disk.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyFormat {
+    /// The bare 1,952-byte public key or 32-byte seed (`pk.bin`, `sk.bin`).
+    #[default]
+    Raw,
+    /// DER SubjectPublicKeyInfo or PKCS#8.
+    Der,
+    /// PEM-armoured DER.
+    Pem,
+    /// JSON Web Key (see [`crate::jwk`]).
+    Jwk,
+    /// COSE_Key (see [`crate::cose`]).
+    Cose,
+}
+
+impl KeyFormat {
+    /// File extension for keys written in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            KeyFormat::Raw => "bin",
+            KeyFormat::Der => "der",
+            KeyFormat::Pem => "pem",
+            KeyFormat::Jwk => "jwk",
+            KeyFormat::Cose => "cose",
+        }
+    }
+}
+
+impl fmt::Display for KeyFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            KeyFormat::Raw => "raw",
+            KeyFormat::Der => "der",
+            KeyFormat::Pem => "pem",
+            KeyFormat::Jwk => "jwk",
+            KeyFormat::Cose => "cose",
+        })
+    }
+}
+
+impl FromStr for KeyFormat {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "raw" | "bin" => Ok(KeyFormat::Raw),
+            "der" => Ok(KeyFormat::Der),
+            "pem" => Ok(KeyFormat::Pem),
+            "jwk" => Ok(KeyFormat::Jwk),
+            "cose" => Ok(KeyFormat::Cose),
+            _ => Err(WalletError::Usage(
+                "key format must be raw, der, pem, jwk or cose",
+            )),
+        }
+    }
+}
+
+/// Which `ML-DSA-PrivateKey` choice to write.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PrivateKeyForm {
+    /// `seed [0]`, 54 bytes of DER. What RFC 9881 recommends.
+    #[default]
+    Seed,
+    /// `both`, for consumers that want the expanded key precomputed.
+    Both,
+}
+
+fn der_err(reason: &str) -> WalletError {
+    WalletError::InvalidEnvelope(format!("DER: {reason}"))
+}
+
+pub(crate) fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    match content.len() {
+        n @ 0..0x80 => out.push(n as u8),
+        n @ 0x80..0x100 => out.extend([0x81, n as u8]),
+        n => out.extend([0x82, (n >> 8) as u8, n as u8]),
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// Split one TLV with tag `tag` off the front of `input`.
+pub(crate) fn read_tlv(input: &[u8], tag: u8) -> Result<(&[u8], &[u8])> {
+    let (&actual, rest) = input.split_first().ok_or_else(|| der_err("truncated"))?;
+    if actual != tag {
+        return Err(der_err(&format!(
+            "expected tag {tag:#04x}, found {actual:#04x}"
+        )));
+    }
+    let (&first, rest) = rest.split_first().ok_or_else(|| der_err("truncated"))?;
+    let (len, rest) = match first {
+        n if n < 0x80 => (n as usize, rest),
+        0x81 | 0x82 => {
+            let width = (first & 0x7f) as usize;
+            if rest.len() < width {
+                return Err(der_err("truncated"));
+            }
+            let len = rest[..width]
+                .iter()
+                .fold(0usize, |acc, &b| acc << 8 | b as usize);
+            // DER requires the shortest length encoding.
+            if len < 0x80 || (width == 2 && len < 0x100) {
+                return Err(der_err("non-minimal length"));
+            }
+            (len, &rest[width..])
+        }
+        _ => return Err(der_err("unsupported length")),
+    };
+    if rest.len() < len {
+        return Err(der_err("truncated"));
+    }
+    Ok(rest.split_at(len))
+}
+
+pub(crate) fn expect_end(rest: &[u8]) -> Result<()> {
+    if rest.is_empty() {
+        Ok(())
+    } else {
+        Err(der_err("trailing data"))
+    }
+}
+
+pub(crate) fn algorithm_identifier() -> Vec<u8> {
+    tlv(SEQUENCE, &ML_DSA_65_OID)
+}
+
+pub(crate) fn read_algorithm_identifier(input: &[u8]) -> Result<&[u8]> {
+    let (alg, rest) = read_tlv(input, SEQUENCE)?;
+    if alg != ML_DSA_65_OID {
+        return Err(der_err("algorithm is not id-ml-dsa-65"));
+    }
+    Ok(rest)
+}
+
+/// DER SubjectPublicKeyInfo for an encoded public key.
+pub fn public_key_to_der(pk: &[u8; PUBLIC_KEY_LEN]) -> Vec<u8> {
+    let mut bits = vec![0u8];
+    bits.extend_from_slice(pk);
+    let mut body = algorithm_identifier();
+    body.extend(tlv(BIT_STRING, &bits));
+    tlv(SEQUENCE, &body)
+}
+
+/// Parse a DER SubjectPublicKeyInfo.
+pub fn public_key_from_der(der: &[u8]) -> Result<[u8; PUBLIC_KEY_LEN]> {
+    let (spki, rest) = read_tlv(der, SEQUENCE)?;
+    expect_end(rest)?;
+    let (bits, rest) = read_tlv(read_algorithm_identifier(spki)?, BIT_STRING)?;
+    expect_end(rest)?;
+    match bits.split_first() {
+        Some((0, pk)) => pk.try_into().map_err(|_| WalletError::InvalidLength {
+            what: "public key",
+            expected: PUBLIC_KEY_LEN,
+            actual: pk.len(),
+        }),
+        _ => Err(der_err("public key BIT STRING has unused bits")),
+    }
+}
+
+/// FIPS 204 expanded signing key for `seed`.
+#[allow(deprecated)] // ml-dsa discourages the expanded form, which `both` carries.
+fn expanded_key(seed: &[u8; SEED_LEN]) -> Zeroizing<[u8; EXPANDED_KEY_LEN]> {
+    Zeroizing::new(
+        keys::keygen_from_seed(seed)
+            .signing_key()
+            .to_expanded()
+            .into(),
+    )
+}
+
+/// DER PKCS#8 `OneAsymmetricKey` for a seed.
+pub fn private_key_to_der(seed: &[u8; SEED_LEN], form: PrivateKeyForm) -> Zeroizing<Vec<u8>> {
+    let inner = Zeroizing::new(match form {
+        PrivateKeyForm::Seed => tlv(SEED_TAG, seed),
+        PrivateKeyForm::Both => {
+            let mut both = Zeroizing::new(tlv(OCTET_STRING, seed));
+            both.extend(tlv(OCTET_STRING, expanded_key(seed).as_slice()));
+            tlv(SEQUENCE, &both)
+        }
+    });
+    let mut body = Zeroizing::new(tlv(INTEGER, &[0]));
+    body.extend(algorithm_identifier());
+    body.extend(tlv(OCTET_STRING, &inner));
+    Zeroizing::new(tlv(SEQUENCE, &body))
+}
+
+/// Parse a DER PKCS#8 private key into its seed.
+pub fn private_key_from_der(der: &[u8]) -> Result<Zeroizing<[u8; SEED_LEN]>> {
+    let seed_of = |bytes: &[u8]| -> Result<Zeroizing<[u8; SEED_LEN]>> {
+        <[u8; SEED_LEN]>::try_from(bytes)
+            .map(Zeroizing::new)
+            .map_err(|_| WalletError::InvalidLength {
+                what: "seed",
+                expected: SEED_LEN,
+                actual: bytes.len(),
+            })
+    };
+
+    let (key, rest) = read_tlv(der, SEQUENCE)?;
+    expect_end(rest)?;
+    let (version, rest) = read_tlv(key, INTEGER)?;
+    // v1 (0) or v2 (1, with an optional public key we ignore).
+    if version != [0] && version != [1] {
+        return Err(der_err("unsupported PKCS#8 version"));
+    }
+    let (private_key, _attributes) = read_tlv(read_algorithm_identifier(rest)?, OCTET_STRING)?;
+
+    match private_key.first() {
+        Some(&SEED_TAG) => {
+            let (seed, rest) = read_tlv(private_key, SEED_TAG)?;
+            expect_end(rest)?;
+            seed_of(seed)
+        }
+        Some(&SEQUENCE) => {
+            let (both, rest) = read_tlv(private_key, SEQUENCE)?;
+            expect_end(rest)?;
+            let (seed, rest) = read_tlv(both, OCTET_STRING)?;
+            let (expanded, rest) = read_tlv(rest, OCTET_STRING)?;
+            expect_end(rest)?;
+            let seed = seed_of(seed)?;
+            if expanded != expanded_key(&seed).as_slice() {
+                return Err(der_err("expanded key does not match seed"));
+            }
+            Ok(seed)
+        }
+        Some(&OCTET_STRING) => Err(der_err(
+            "expandedKey-only private keys are not supported; export with the seed",
+        )),
+        _ => Err(der_err("unrecognised ML-DSA-PrivateKey")),
+    }
+}
+
+/// PEM-armour `der` under `label`, 64 columns as OpenSSL writes it.
+pub fn to_pem(label: &str, der: &[u8]) -> String {
+    let b64 = Base64::encode_string(der);
+    let mut out = format!("-----BEGIN {label}-----\n");
+    for line in b64.as_bytes().chunks(64) {
+        out.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+        out.push('\n');
+    }
+    out.push_str(&format!("-----END {label}-----\n"));
+    out
+}
+
+/// Decode the first PEM block labelled `label`.
+pub fn from_pem(label: &str, pem: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let begin = format!("-----BEGIN {label}-----");
+    let end = format!("-----END {label}-----");
+    let start = pem
+        .find(&begin)
+        .ok_or_else(|| WalletError::InvalidEnvelope(format!("no {begin} block")))?
+        + begin.len();
+    let stop = pem[start..]
+        .find(&end)
+        .ok_or_else(|| WalletError::InvalidEnvelope(format!("no {end} line")))?;
+    let body: Zeroizing<String> = Zeroizing::new(
+        pem[start..start + stop]
+            .chars()
+            .filter(|c| !c.is_ascii_whitespace())
+            .collect(),
+    );
+    Base64::decode_vec(&body)
+        .map(Zeroizing::new)
+        .map_err(|_| WalletError::InvalidEnvelope("invalid base64 in PEM".to_string()))
+}
+
+/// Encode a public key file.
+pub fn encode_public_key(pk: &[u8; PUBLIC_KEY_LEN], format: KeyFormat) -> Vec<u8> {
+    match format {
+        KeyFormat::Raw => pk.to_vec(),
+        KeyFormat::Der => public_key_to_der(pk),
+        KeyFormat::Pem => to_pem(PUBLIC_KEY_LABEL, &public_key_to_der(pk)).into_bytes(),
+        KeyFormat::Jwk => Jwk::public(pk).to_json().into_bytes(),
+        KeyFormat::Cose => cose::public_key_to_cose(pk),
+    }
+}
+
+/// Decode a public key file.
+pub fn decode_public_key(bytes: &[u8], format: KeyFormat) -> Result<[u8; PUBLIC_KEY_LEN]> {
+    match format {
+        KeyFormat::Raw => bytes.try_into().map_err(|_| WalletError::InvalidLength {
+            what: "public key",
+            expected: PUBLIC_KEY_LEN,
+            actual: bytes.len(),
+        }),
+        KeyFormat::Der => public_key_from_der(bytes),
+        KeyFormat::Pem => {
+            let text = std::str::from_utf8(bytes)
+                .map_err(|_| WalletError::InvalidEnvelope("PEM is not UTF-8".to_string()))?;
+            public_key_from_der(&from_pem(PUBLIC_KEY_LABEL, text)?)
+        }
+        KeyFormat::Jwk => Jwk::from_json(bytes)?.public_key(),
+        KeyFormat::Cose => cose::public_key_from_cose(bytes),
+    }
+}
+
+/// Encode a private key file holding `seed`.
+pub fn encode_private_key(seed: &[u8; SEED_LEN], format: KeyFormat) -> Zeroizing<Vec<u8>> {
+    match format {
+        KeyFormat::Raw => Zeroizing::new(seed.to_vec()),
+        KeyFormat::Der => private_key_to_der(seed, PrivateKeyForm::Seed),
+        KeyFormat::Pem => Zeroizing::new(
+            to_pem(
+                PRIVATE_KEY_LABEL,
+                &private_key_to_der(seed, PrivateKeyForm::Seed),
+            )
+            .into_bytes(),
+        ),
+        KeyFormat::Jwk | KeyFormat::Cose => {
+            let pk: [u8; PUBLIC_KEY_LEN] =
+                keys::keygen_from_seed(seed).verifying_key().encode().into();
+            match format {
+                KeyFormat::Jwk => Zeroizing::new(Jwk::private(&pk, seed).to_json().into_bytes()),
+                _ => cose::private_key_to_cose(&pk, seed),
+            }
+        }
+    }
+}
+
+/// Decode a private key file into its seed.
+pub fn decode_private_key(bytes: &[u8], format: KeyFormat) -> Result<Zeroizing<[u8; SEED_LEN]>> {
+    match format {
+        KeyFormat::Raw => <[u8; SEED_LEN]>::try_from(bytes)
+            .map(Zeroizing::new)
+            .map_err(|_| WalletError::InvalidLength {
+                what: "seed",
+                expected: SEED_LEN,
+                actual: bytes.len(),
+            }),
+        KeyFormat::Der => private_key_from_der(bytes),
+        KeyFormat::Pem => {
+            let text = std::str::from_utf8(bytes)
+                .map_err(|_| WalletError::InvalidEnvelope("PEM is not UTF-8".to_string()))?;
+            private_key_from_der(&from_pem(PRIVATE_KEY_LABEL, text)?)
+        }
+        KeyFormat::Jwk => Jwk::from_json(bytes)?.seed(),
+        KeyFormat::Cose => cose::private_key_from_cose(bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `openssl genpkey -algorithm ML-DSA-65 -pkeyopt hexseed:000102…1f`
+    /// (OpenSSL 3.5), written with its default `both` private key form, as
+    /// seed-only, and as the SubjectPublicKeyInfo.
+    const OPENSSL_BOTH: &str = include_str!("../testdata/openssl-ml-dsa-65-both.pem");
+    const OPENSSL_SEED: &str = include_str!("../testdata/openssl-ml-dsa-65-seed.pem");
+    const OPENSSL_PUB: &str = include_str!("../testdata/openssl-ml-dsa-65-pub.pem");
+
+    fn fixture_seed() -> [u8; SEED_LEN] {
+        std::array::from_fn(|i| i as u8)
+    }
+
+    #[test]
+    fn reads_openssl_keys() {
+        let seed = fixture_seed();
+        let pk = keys::keygen_from_seed(&seed).verifying_key().encode();
+        for pem in [OPENSSL_BOTH, OPENSSL_SEED] {
+            let decoded = decode_private_key(pem.as_bytes(), KeyFormat::Pem).unwrap();
+            assert_eq!(*decoded, seed);
+        }
+        let decoded_pk = decode_public_key(OPENSSL_PUB.as_bytes(), KeyFormat::Pem).unwrap();
+        assert_eq!(decoded_pk[..], pk[..]);
+    }
+
+    #[test]
+    fn writes_what_openssl_writes() {
+        let seed = fixture_seed();
+        let pk: [u8; PUBLIC_KEY_LEN] = keys::keygen_from_seed(&seed)
+            .verifying_key()
+            .encode()
+            .into();
+        assert_eq!(
+            String::from_utf8(encode_private_key(&seed, KeyFormat::Pem).to_vec()).unwrap(),
+            OPENSSL_SEED
+        );
+        assert_eq!(
+            String::from_utf8(encode_public_key(&pk, KeyFormat::Pem)).unwrap(),
+            OPENSSL_PUB
+        );
+        assert_eq!(
+            to_pem(
+                PRIVATE_KEY_LABEL,
+                &private_key_to_der(&seed, PrivateKeyForm::Both)
+            ),
+            OPENSSL_BOTH
+        );
+        assert_eq!(private_key_to_der(&seed, PrivateKeyForm::Seed).len(), 54);
+    }
+
+    #[test]
+    fn rejects_mismatched_or_foreign_keys() {
+        let seed = fixture_seed();
+        let mut both = private_key_to_der(&seed, PrivateKeyForm::Both).to_vec();
+        let last = both.len() - 1;
+        both[last] ^= 1;
+        assert!(private_key_from_der(&both).is_err());
+
+        // id-ml-dsa-44 instead of -65.
+        let mut der = private_key_to_der(&seed, PrivateKeyForm::Seed).to_vec();
+        der[17] = 0x11;
+        assert!(private_key_from_der(&der).is_err());
+
+        let mut expanded_only = tlv(INTEGER, &[0]);
+        expanded_only.extend(algorithm_identifier());
+        expanded_only.extend(tlv(
+            OCTET_STRING,
+            &tlv(OCTET_STRING, &[0; EXPANDED_KEY_LEN]),
+        ));
+        assert!(private_key_from_der(&tlv(SEQUENCE, &expanded_only)).is_err());
+        assert!("ssh".parse::<KeyFormat>().is_err());
+    }
+}
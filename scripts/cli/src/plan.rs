@@ -0,0 +1,297 @@
+//! Multi-account operation plans (`pq-userop batch --plan`).
+//!
+//! A plan lists calls to make from keystore accounts on one chain. It is a
+//! YAML, TOML or JSON file:
+//!
+//! ```yaml
+//! chain_id: 421614
+//! calls:
+//!   - account: treasury-1
+//!     target: "0x1111111111111111111111111111111111111111"
+//!     value: "1000000000000000000"   # wei
+//!   - account: treasury-2
+//!     target: "0x2222222222222222222222222222222222222222"
+//!     data: "0xa9059cbb..."
+//! ```
+//!
+//! `entry_point` defaults to v0.7. Calls from the same account become one
+//! UserOperation, an ERC-7579 `execute` in single or batch mode, in plan
+//! order. The sender is the account's address for `chain_id` in the
+//! keystore. Gas limits come from the bundler's estimate when there is one.
+//! Otherwise `verification_gas_limit` and `call_gas_limit` default to
+//! [`RECOMMENDED_VERIFICATION_GAS`] and [`DEFAULT_CALL_GAS_LIMIT`]. Either
+//! can be set in the plan to override the estimate.
+
+use std::path::Path;
+
+use alloy_primitives::{Address, B256, Bytes, U256};
+use serde::{Deserialize, Serialize};
+
+use crate::aa_errors::Diagnostic;
+use crate::bundler::{GasEstimate, UserOpStatus};
+use crate::calldata::{self, Call};
+use crate::check::RECOMMENDED_VERIFICATION_GAS;
+use crate::error::{Result, WalletError};
+use crate::userop::{self, ENTRY_POINT_V07, PackedUserOperation};
+
+/// `callGasLimit` for ops built without a bundler estimate.
+pub const DEFAULT_CALL_GAS_LIMIT: u128 = 200_000;
+
+fn plan_err(reason: impl Into<String>) -> WalletError {
+    WalletError::Plan(reason.into())
+}
+
+fn default_entry_point() -> Address {
+    ENTRY_POINT_V07
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Plan {
+    pub chain_id: u64,
+    #[serde(default = "default_entry_point")]
+    pub entry_point: Address,
+    /// Overrides the estimated `verificationGasLimit` of every op.
+    pub verification_gas_limit: Option<u128>,
+    /// Overrides the estimated `callGasLimit` of every op.
+    pub call_gas_limit: Option<u128>,
+    pub calls: Vec<PlannedCall>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlannedCall {
+    /// Keystore account the call is made from.
+    pub account: String,
+    pub target: Address,
+    /// Native value in wei.
+    #[serde(default)]
+    pub value: U256,
+    #[serde(default)]
+    pub data: Bytes,
+}
+
+/// One account's share of a plan: the calls its op will make.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccountCalls {
+    pub account: String,
+    pub calls: Vec<Call>,
+}
+
+impl Plan {
+    pub fn from_yaml(text: &str) -> Result<Self> {
+        serde_yaml::from_str::<Plan>(text)
+            .map_err(|e| plan_err(e.to_string()))?
+            .validated()
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str::<Plan>(text)
+            .map_err(|e| plan_err(e.to_string()))?
+            .validated()
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        serde_json::from_str::<Plan>(text)
+            .map_err(|e| plan_err(e.to_string()))?
+            .validated()
+    }
+
+    /// Read a plan, choosing the format by extension (`.yaml`/`.yml`,
+    /// `.toml`, anything else JSON).
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| WalletError::Io(path.display().to_string(), e))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => Self::from_yaml(&text),
+            Some("toml") => Self::from_toml(&text),
+            _ => Self::from_json(&text),
+        }
+    }
+
+    fn validated(self) -> Result<Self> {
+        if self.calls.is_empty() {
+            return Err(plan_err("no calls"));
+        }
+        Ok(self)
+    }
+
+    /// The calls grouped by account, accounts in order of first appearance.
+    pub fn accounts(&self) -> Vec<AccountCalls> {
+        let mut out: Vec<AccountCalls> = Vec::new();
+        for planned in &self.calls {
+            let call = Call {
+                target: planned.target,
+                value: planned.value,
+                data: planned.data.to_vec(),
+            };
+            match out.iter_mut().find(|a| a.account == planned.account) {
+                Some(group) => group.calls.push(call),
+                None => out.push(AccountCalls {
+                    account: planned.account.clone(),
+                    calls: vec![call],
+                }),
+            }
+        }
+        out
+    }
+
+    /// An unsigned op making `calls` from `sender`, with `gasFees` and the
+    /// plan's gas limits (or the defaults) filled in.
+    pub fn unsigned_op(
+        &self,
+        sender: Address,
+        nonce: U256,
+        calls: &[Call],
+        gas_fees: B256,
+    ) -> PackedUserOperation {
+        PackedUserOperation {
+            sender,
+            nonce,
+            init_code: vec![],
+            call_data: calldata::execute(calls),
+            account_gas_limits: userop::pack_u128_pair(
+                self.verification_gas_limit
+                    .unwrap_or(RECOMMENDED_VERIFICATION_GAS),
+                self.call_gas_limit.unwrap_or(DEFAULT_CALL_GAS_LIMIT),
+            ),
+            pre_verification_gas: U256::ZERO,
+            gas_fees,
+            paymaster_and_data: vec![],
+            signature: vec![],
+        }
+    }
+
+    /// Take `op`'s gas from a bundler estimate, except where the plan sets
+    /// a limit itself. The paymaster's limits in `paymasterAndData` are
+    /// replaced too, when the bundler estimated them.
+    pub fn apply_estimate(
+        &self,
+        op: &mut PackedUserOperation,
+        estimate: &GasEstimate,
+    ) -> Result<()> {
+        let limit = |what: &str, v: U256| {
+            u128::try_from(v)
+                .map_err(|_| WalletError::Rpc(format!("estimated {what} out of range")))
+        };
+        op.pre_verification_gas = estimate.pre_verification_gas;
+        op.account_gas_limits = userop::pack_u128_pair(
+            match self.verification_gas_limit {
+                Some(gas) => gas,
+                None => limit("verificationGasLimit", estimate.verification_gas_limit)?,
+            },
+            match self.call_gas_limit {
+                Some(gas) => gas,
+                None => limit("callGasLimit", estimate.call_gas_limit)?,
+            },
+        );
+        if op.paymaster_and_data.len() >= 52 {
+            let (verification, post_op) = op.paymaster_gas_limits();
+            let limits = userop::pack_u128_pair(
+                match estimate.paymaster_verification_gas_limit {
+                    Some(gas) => limit("paymasterVerificationGasLimit", gas)?,
+                    None => verification,
+                },
+                match estimate.paymaster_post_op_gas_limit {
+                    Some(gas) => limit("paymasterPostOpGasLimit", gas)?,
+                    None => post_op,
+                },
+            );
+            op.paymaster_and_data[20..52].copy_from_slice(&limits.0);
+        }
+        Ok(())
+    }
+}
+
+/// What happened to one account's op.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpOutcome {
+    pub account: String,
+    pub sender: Address,
+    pub user_op_hash: B256,
+    /// Why the bundler refused the op, if it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Diagnostic>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<UserOpStatus>,
+}
+
+impl OpOutcome {
+    /// Included, and the op's call succeeded.
+    pub fn succeeded(&self) -> bool {
+        self.status.as_ref().and_then(|s| s.success) == Some(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calldata::executeCall;
+    use alloy_sol_types::SolCall;
+
+    const PLAN: &str = r#"
+chain_id: 421614
+call_gas_limit: 90000
+calls:
+  - account: treasury-1
+    target: "0x1111111111111111111111111111111111111111"
+    value: "1000000000000000000"
+  - account: treasury-2
+    target: "0x2222222222222222222222222222222222222222"
+    data: "0xa9059cbb"
+  - account: treasury-1
+    target: "0x3333333333333333333333333333333333333333"
+"#;
+
+    #[test]
+    fn plan_groups_calls_into_one_op_per_account() {
+        let plan = Plan::from_yaml(PLAN).unwrap();
+        assert_eq!(plan.entry_point, ENTRY_POINT_V07);
+        let accounts = plan.accounts();
+        let names: Vec<_> = accounts.iter().map(|a| a.account.as_str()).collect();
+        assert_eq!(names, ["treasury-1", "treasury-2"]);
+        assert_eq!(accounts[0].calls.len(), 2);
+        assert_eq!(accounts[0].calls[0].value, U256::from(10u64.pow(18)));
+        assert_eq!(accounts[1].calls[0].data, [0xa9, 0x05, 0x9c, 0xbb]);
+
+        let op = plan.unsigned_op(Address::ZERO, U256::from(3), &accounts[0].calls, B256::ZERO);
+        // Two calls: batch mode.
+        let call = executeCall::abi_decode(&op.call_data).unwrap();
+        assert_eq!(call.mode[0], 0x01);
+        assert_eq!(op.call_gas_limit(), 90_000);
+        assert_eq!(op.verification_gas_limit(), RECOMMENDED_VERIFICATION_GAS);
+
+        let mut estimated = op.clone();
+        let estimate = GasEstimate {
+            pre_verification_gas: U256::from(80_000),
+            verification_gas_limit: U256::from(1_500_000),
+            call_gas_limit: U256::from(50_000),
+            paymaster_verification_gas_limit: None,
+            paymaster_post_op_gas_limit: None,
+        };
+        plan.apply_estimate(&mut estimated, &estimate).unwrap();
+        assert_eq!(estimated.pre_verification_gas, U256::from(80_000));
+        assert_eq!(estimated.verification_gas_limit(), 1_500_000);
+        // The plan's own limit wins over the estimate.
+        assert_eq!(estimated.call_gas_limit(), 90_000);
+
+        let mut sponsored = op.clone();
+        sponsored.paymaster_and_data = Address::repeat_byte(0x77).to_vec();
+        sponsored
+            .paymaster_and_data
+            .extend_from_slice(&userop::pack_u128_pair(100_000, 40_000).0);
+        sponsored.paymaster_and_data.extend_from_slice(&[0xcc; 20]);
+        let estimate = GasEstimate {
+            paymaster_verification_gas_limit: Some(U256::from(60_000)),
+            ..estimate
+        };
+        plan.apply_estimate(&mut sponsored, &estimate).unwrap();
+        assert_eq!(sponsored.paymaster_gas_limits(), (60_000, 40_000));
+        assert_eq!(sponsored.paymaster_and_data[52..], [0xcc; 20]);
+
+        let toml = "chain_id = 1\ncalls = []\n";
+        assert!(matches!(Plan::from_toml(toml), Err(WalletError::Plan(_))));
+        assert!(Plan::from_json(r#"{"chain_id":1,"calls":[],"extra":1}"#).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+//! Hardware-bound keystore entries for mobile builds.
+//!
+//! A password-derived key is only as strong as the password, and on a phone
+//! the OS offers better: a key generated inside the Secure Enclave (iOS) or
+//! StrongBox / TEE-backed Android Keystore that never leaves the hardware and
+//! only decrypts after the user passes a biometric check. A platform entry
+//! ([`Account::encrypt_wrapped`](crate::keystore::Account::encrypt_wrapped))
+//! encrypts the ML-DSA seed under a random content key, and that content key
+//! under such an OS key. Unlocking
+//! ([`Account::unlock_wrapped`](crate::keystore::Account::unlock_wrapped))
+//! has the OS unwrap the content key, which is where the user is prompted.
+//!
+//! The host app implements [`PlatformKeystore`] with the OS APIs (`SecKey`
+//! with `kSecAttrTokenIDSecureEnclave` and a `.biometryCurrentSet` access
+//! control; a `KeyGenParameterSpec` with `setIsStrongBoxBacked` and
+//! `setUserAuthenticationRequired`), directly in Rust or through the C
+//! callbacks of [`crate::ffi`].
+
+use zeroize::Zeroizing;
+
+use crate::error::Result;
+
+/// The Secure Enclave, through the iOS / macOS keychain.
+pub const SECURE_ENCLAVE: &str = "secure-enclave";
+/// Android Keystore, StrongBox-backed where the device has it.
+pub const ANDROID_KEYSTORE: &str = "android-keystore";
+/// A TPM 2.0, sealing to PCR values; see `crate::tpm` (`tpm` feature).
+pub const TPM: &str = "tpm";
+
+/// An OS keystore that can wrap 32-byte keys under a hardware-bound key.
+pub trait PlatformKeystore {
+    /// Recorded in the entry, e.g. [`SECURE_ENCLAVE`].
+    fn name(&self) -> &str;
+
+    /// Encrypt `key` under the OS key `alias`, creating it if needed.
+    fn wrap(&self, alias: &str, key: &[u8; 32]) -> Result<Vec<u8>>;
+
+    /// Decrypt `wrapped` with the OS key `alias` after authenticating the
+    /// user, showing `reason` in the prompt. A prompt the user dismisses is
+    /// [`WalletError::Cancelled`](crate::WalletError::Cancelled).
+    fn unwrap(&self, alias: &str, wrapped: &[u8], reason: &str) -> Result<Zeroizing<[u8; 32]>>;
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::error::WalletError;
+    use crate::keys::SEED_LEN;
+    use crate::keystore::Account;
+    use std::cell::Cell;
+
+    /// Wraps by XOR with a per-alias pad; the user approves `approve` times.
+    pub(crate) struct FakeEnclave {
+        pub(crate) approve: Cell<u32>,
+    }
+
+    fn pad(alias: &str) -> u8 {
+        alias.bytes().fold(0x5a, |a, b| a ^ b)
+    }
+
+    impl PlatformKeystore for FakeEnclave {
+        fn name(&self) -> &str {
+            SECURE_ENCLAVE
+        }
+
+        fn wrap(&self, alias: &str, key: &[u8; 32]) -> Result<Vec<u8>> {
+            Ok(key.iter().map(|b| b ^ pad(alias)).collect())
+        }
+
+        fn unwrap(&self, alias: &str, wrapped: &[u8], _: &str) -> Result<Zeroizing<[u8; 32]>> {
+            if self.approve.get() == 0 {
+                return Err(WalletError::Cancelled);
+            }
+            self.approve.set(self.approve.get() - 1);
+            let mut key = Zeroizing::new([0u8; 32]);
+            for (k, w) in key.iter_mut().zip(wrapped) {
+                *k = w ^ pad(alias);
+            }
+            Ok(key)
+        }
+    }
+
+    #[test]
+    fn wrapped_entries_unlock_only_through_the_platform() {
+        let enclave = FakeEnclave {
+            approve: Cell::new(1),
+        };
+        let seed = [4u8; SEED_LEN];
+        let account = Account::encrypt_wrapped("phone", &seed, &enclave, "pq.phone").unwrap();
+        let json = serde_json::to_string(&account).unwrap();
+        let account: Account = serde_json::from_str(&json).unwrap();
+
+        assert!(matches!(
+            account.decrypt_seed(b""),
+            Err(WalletError::Keystore(_))
+        ));
+        assert_eq!(*account.unlock_wrapped(&enclave, "Sign").unwrap(), seed);
+        // Dismissed prompt.
+        assert!(matches!(
+            account.unlock_wrapped(&enclave, "Sign"),
+            Err(WalletError::Cancelled)
+        ));
+        // The key under another alias does not open it.
+        let mut moved = account.clone();
+        moved.crypto.wrapped_key.as_mut().unwrap().alias = "other".to_string();
+        enclave.approve.set(1);
+        assert!(matches!(
+            moved.unlock_wrapped(&enclave, "Sign"),
+            Err(WalletError::Decrypt)
+        ));
+    }
+}
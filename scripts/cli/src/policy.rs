@@ -0,0 +1,418 @@
+//! Signing policy: declarative rules checked before a UserOperation is
+//! signed (`pq-userop sign --policy`).
+//!
+//! A policy is a TOML or JSON file:
+//!
+//! ```toml
+//! chains = [412346, 421614]
+//! max_value_per_op = "1000000000000000000"   # wei
+//! max_value_per_day = "5000000000000000000"
+//! approval_above = "100000000000000000"      # needs --approved above this
+//!
+//! [[targets]]
+//! address = "0x1111111111111111111111111111111111111111"
+//! selectors = ["0xa9059cbb"]                 # empty or absent: any
+//! ```
+//!
+//! An empty or absent list allows everything; an absent limit is no limit.
+//! Calls are read from the op's `callData` as ERC-7579 `execute`. Any other
+//! callData (`installModule`, ...) counts as a zero-value call to the account
+//! itself, so list the account as a target to allow those. Spending per day
+//! is tracked in a [`SpendLedger`] next to the keystore.
+//!
+//! `[rate]` and `[anomaly]` sections add per-account rate limits and
+//! anomaly heuristics that `pq-signerd` enforces; see [`crate::guard`].
+//! `[dual_control]` makes its ops that need approval wait for an approver's
+//! signature; see [`crate::proposal`].
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::{Address, B256, FixedBytes, U256, keccak256};
+use serde::{Deserialize, Serialize};
+
+use crate::calldata::{self, Call};
+use crate::canonical_json;
+use crate::error::{Result, WalletError};
+use crate::guard::{AnomalyRules, RateLimits};
+use crate::keystore::Keystore;
+use crate::proposal::DualControl;
+use crate::userop::PackedUserOperation;
+
+const DAY: u64 = 86_400;
+
+fn policy_err(reason: impl Into<String>) -> WalletError {
+    WalletError::Policy(reason.into())
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Chain IDs the key may sign for.
+    #[serde(default)]
+    pub chains: Vec<u64>,
+    /// Most native value (wei) a single op may move.
+    pub max_value_per_op: Option<U256>,
+    /// Most native value (wei) all ops on a chain may move per UTC day.
+    pub max_value_per_day: Option<U256>,
+    /// Ops moving more than this need explicit human approval.
+    pub approval_above: Option<U256>,
+    /// Contracts the account may call.
+    #[serde(default)]
+    pub targets: Vec<TargetRule>,
+    /// Per-account signing rate limits (`pq-signerd` only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate: Option<RateLimits>,
+    /// Heuristics that put an account on hold (`pq-signerd` only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anomaly: Option<AnomalyRules>,
+    /// Approvers who must sign off ops that need approval (`pq-signerd`
+    /// only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dual_control: Option<DualControl>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetRule {
+    pub address: Address,
+    /// Allowed 4-byte selectors; empty allows any call, including plain
+    /// value transfers.
+    #[serde(default)]
+    pub selectors: Vec<FixedBytes<4>>,
+}
+
+/// Which rule refused the op.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    Chain,
+    Target,
+    Selector,
+    ValuePerOp,
+    ValuePerDay,
+    Approval,
+    Rate,
+    Anomaly,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Rule::Chain => "chain",
+            Rule::Target => "target",
+            Rule::Selector => "selector",
+            Rule::ValuePerOp => "value-per-op",
+            Rule::ValuePerDay => "value-per-day",
+            Rule::Approval => "approval",
+            Rule::Rate => "rate",
+            Rule::Anomaly => "anomaly",
+        })
+    }
+}
+
+/// A refusal to sign, printed instead of a signature.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Denial {
+    pub denied: bool,
+    pub rule: Rule,
+    pub reason: String,
+}
+
+impl Denial {
+    pub(crate) fn new(rule: Rule, reason: impl Into<String>) -> Self {
+        Denial {
+            denied: true,
+            rule,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for Denial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "denied by policy ({}): {}", self.rule, self.reason)
+    }
+}
+
+impl Policy {
+    /// Read a policy, as TOML if the file ends in `.toml` and JSON otherwise.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| WalletError::Io(path.display().to_string(), e))?;
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            Self::from_toml(&text)
+        } else {
+            Self::from_json(&text)
+        }
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| policy_err(e.to_string()))
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        serde_json::from_str(text).map_err(|e| policy_err(e.to_string()))
+    }
+
+    /// keccak256 of the policy's RFC 8785 encoding; the same rules hash the
+    /// same whether they were written in TOML or JSON. A delegation
+    /// certificate can pin it.
+    pub fn hash(&self) -> Result<B256> {
+        Ok(keccak256(canonical_json::to_vec(self)?))
+    }
+
+    /// The calls `op` makes, as the rules see them.
+    pub fn calls(op: &PackedUserOperation) -> Vec<Call> {
+        if op.call_data.is_empty() {
+            return Vec::new();
+        }
+        calldata::decode_execute(&op.call_data).unwrap_or_else(|| {
+            vec![Call {
+                target: op.sender,
+                value: U256::ZERO,
+                data: op.call_data.clone(),
+            }]
+        })
+    }
+
+    /// Check `op` for `chain_id`, given what the chain has already spent
+    /// today. Returns the value the op moves, to be added to the ledger once
+    /// it is signed.
+    pub fn check(
+        &self,
+        op: &PackedUserOperation,
+        chain_id: u64,
+        spent_today: U256,
+        approved: bool,
+    ) -> std::result::Result<U256, Denial> {
+        if !self.chains.is_empty() && !self.chains.contains(&chain_id) {
+            return Err(Denial::new(
+                Rule::Chain,
+                format!("chain {chain_id} is not allowed"),
+            ));
+        }
+        let calls = Self::calls(op);
+        if !self.targets.is_empty() {
+            for call in &calls {
+                let Some(rule) = self.targets.iter().find(|t| t.address == call.target) else {
+                    return Err(Denial::new(
+                        Rule::Target,
+                        format!("{} is not an allowed target", call.target),
+                    ));
+                };
+                if rule.selectors.is_empty() {
+                    continue;
+                }
+                let selector = call.data.get(..4).map(FixedBytes::<4>::from_slice);
+                if !selector.is_some_and(|s| rule.selectors.contains(&s)) {
+                    return Err(Denial::new(
+                        Rule::Selector,
+                        match selector {
+                            Some(s) => format!("{s} is not allowed on {}", call.target),
+                            None => format!("plain transfer to {} is not allowed", call.target),
+                        },
+                    ));
+                }
+            }
+        }
+        let value = calls
+            .iter()
+            .fold(U256::ZERO, |sum, c| sum.saturating_add(c.value));
+        if let Some(max) = self.max_value_per_op
+            && value > max
+        {
+            return Err(Denial::new(
+                Rule::ValuePerOp,
+                format!("op moves {value} wei, limit {max}"),
+            ));
+        }
+        if let Some(max) = self.max_value_per_day
+            && spent_today.saturating_add(value) > max
+        {
+            return Err(Denial::new(
+                Rule::ValuePerDay,
+                format!("{spent_today} wei already spent today, op adds {value}, limit {max}"),
+            ));
+        }
+        if let Some(threshold) = self.approval_above
+            && value > threshold
+            && !approved
+        {
+            return Err(Denial::new(
+                Rule::Approval,
+                format!("op moves {value} wei, above {threshold}; needs approval"),
+            ));
+        }
+        Ok(value)
+    }
+}
+
+/// Native value signed for, per chain and UTC day.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpendLedger {
+    /// chain ID -> day number (Unix time / 86400) -> wei.
+    pub spent: BTreeMap<u64, BTreeMap<u64, U256>>,
+    /// Targets each sender's signed ops have called, for the
+    /// new-destination heuristic.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub destinations: BTreeMap<Address, BTreeSet<Address>>,
+}
+
+impl SpendLedger {
+    /// `policy-spend.json` in the keystore root.
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(Keystore::default_root()?.join("policy-spend.json"))
+    }
+
+    /// Read the ledger; a missing file is an empty ledger.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| policy_err(format!("{}: {e}", path.display()))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(WalletError::Io(path.display().to_string(), e)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| WalletError::Io(dir.display().to_string(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self).expect("ledger serializes");
+        std::fs::write(path, json).map_err(|e| WalletError::Io(path.display().to_string(), e))
+    }
+
+    pub fn spent_on(&self, chain_id: u64, now: u64) -> U256 {
+        self.spent
+            .get(&chain_id)
+            .and_then(|days| days.get(&(now / DAY)))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The targets `sender`'s signed ops have called.
+    pub fn destinations(&self, sender: Address) -> BTreeSet<Address> {
+        self.destinations.get(&sender).cloned().unwrap_or_default()
+    }
+
+    /// Remember the targets of a signed op from `sender`.
+    pub fn record_destinations(&mut self, sender: Address, calls: &[Call]) {
+        let known = self.destinations.entry(sender).or_default();
+        known.extend(calls.iter().map(|c| c.target));
+    }
+
+    /// Add `value` to today's total and drop earlier days.
+    pub fn record(&mut self, chain_id: u64, now: u64, value: U256) {
+        let today = now / DAY;
+        let days = self.spent.entry(chain_id).or_default();
+        days.retain(|&day, _| day >= today);
+        let total = days.entry(today).or_default();
+        *total = total.saturating_add(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::B256;
+
+    fn op(calls: &[Call]) -> PackedUserOperation {
+        PackedUserOperation {
+            sender: Address::repeat_byte(0xaa),
+            nonce: U256::ZERO,
+            init_code: Vec::new(),
+            call_data: calldata::execute(calls),
+            account_gas_limits: B256::ZERO,
+            pre_verification_gas: U256::ZERO,
+            gas_fees: B256::ZERO,
+            paymaster_and_data: Vec::new(),
+            signature: Vec::new(),
+        }
+    }
+
+    const POLICY: &str = r#"
+        chains = [1]
+        max_value_per_op = "100"
+        max_value_per_day = "150"
+        approval_above = "50"
+
+        [[targets]]
+        address = "0x1111111111111111111111111111111111111111"
+        selectors = ["0xa9059cbb"]
+
+        [[targets]]
+        address = "0x2222222222222222222222222222222222222222"
+    "#;
+
+    #[test]
+    fn rules_are_applied_in_order() {
+        let policy = Policy::from_toml(POLICY).unwrap();
+        let as_json = serde_json::to_string(&policy).unwrap();
+        assert_eq!(
+            Policy::from_json(&as_json).unwrap().hash().unwrap(),
+            policy.hash().unwrap()
+        );
+        assert_ne!(policy.hash().unwrap(), Policy::default().hash().unwrap());
+        let token = Call {
+            target: Address::repeat_byte(0x11),
+            value: U256::ZERO,
+            data: vec![0xa9, 0x05, 0x9c, 0xbb, 0],
+        };
+        let pay = |value: u64| Call {
+            target: Address::repeat_byte(0x22),
+            value: U256::from(value),
+            data: Vec::new(),
+        };
+        let deny = |op: &PackedUserOperation, chain, spent, approved| {
+            policy
+                .check(op, chain, U256::from(spent), approved)
+                .unwrap_err()
+                .rule
+        };
+
+        assert_eq!(
+            policy.check(&op(&[token.clone(), pay(40)]), 1, U256::ZERO, false),
+            Ok(U256::from(40))
+        );
+        assert_eq!(deny(&op(&[pay(1)]), 5, 0, false), Rule::Chain);
+        let stranger = Call {
+            target: Address::repeat_byte(0x33),
+            ..pay(0)
+        };
+        assert_eq!(deny(&op(&[stranger]), 1, 0, false), Rule::Target);
+        let approve = Call {
+            data: vec![0x09, 0x5e, 0xa7, 0xb3],
+            ..token
+        };
+        assert_eq!(deny(&op(&[approve]), 1, 0, false), Rule::Selector);
+        assert_eq!(deny(&op(&[pay(101)]), 1, 0, true), Rule::ValuePerOp);
+        assert_eq!(deny(&op(&[pay(60)]), 1, 100, true), Rule::ValuePerDay);
+        assert_eq!(deny(&op(&[pay(60)]), 1, 0, false), Rule::Approval);
+        assert!(policy.check(&op(&[pay(60)]), 1, U256::ZERO, true).is_ok());
+
+        // Non-execute callData is a call to the account itself.
+        let mut install = op(&[]);
+        install.call_data = vec![0x95, 0x17, 0xe2, 0x9f];
+        assert_eq!(deny(&install, 1, 0, false), Rule::Target);
+        assert!(Policy::from_json(r#"{"chains": [1], "limits": {}}"#).is_err());
+    }
+
+    #[test]
+    fn ledger_keeps_only_today() {
+        let mut ledger = SpendLedger::default();
+        ledger.record(1, 10, U256::from(5));
+        ledger.record(1, 20, U256::from(7));
+        assert_eq!(ledger.spent_on(1, 30), U256::from(12));
+        assert_eq!(ledger.spent_on(2, 30), U256::ZERO);
+
+        ledger.record(1, DAY + 1, U256::from(3));
+        assert_eq!(ledger.spent_on(1, DAY + 1), U256::from(3));
+        assert_eq!(ledger.spent[&1].len(), 1);
+
+        let json = serde_json::to_string(&ledger).unwrap();
+        assert_eq!(serde_json::from_str::<SpendLedger>(&json).unwrap(), ledger);
+    }
+}
@@ -0,0 +1,162 @@
+//! Async signing for servers (`async` feature).
+//!
+//! An ML-DSA-65 signature costs about a millisecond of CPU, long enough to
+//! stall an async executor under load. [`SignerPool`] runs each signature on
+//! tokio's blocking pool and admits at most `max_concurrent` at a time;
+//! callers past the limit wait for a slot instead of piling up threads.
+//!
+//! Every call takes a `cancel` future (a shutdown signal, a request's
+//! deadline, `std::future::pending()` for none). If it completes while the
+//! call is still waiting for a slot, nothing is signed. Once signing has
+//! started it runs to completion on its thread, but the result is dropped
+//! and the call returns [`WalletError::Cancelled`]. Dropping the returned
+//! future cancels the same way.
+//!
+//! With the RustCrypto backend the pool expands the key once, into a
+//! [`PqSigner`], and every signature reuses it. The fips204 backend still
+//! expands it from the seed per signature.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use alloy_primitives::{Address, B256, U256};
+use tokio::sync::Semaphore;
+use zeroize::Zeroizing;
+
+use crate::backend::Backend;
+use crate::error::{Result, WalletError};
+use crate::keys::SEED_LEN;
+use crate::signer::PqSigner;
+use crate::userop::{self, PackedUserOperation};
+
+#[derive(Clone)]
+enum Key {
+    Expanded(Arc<PqSigner>),
+    #[cfg(feature = "fips204")]
+    Seed(Arc<Zeroizing<[u8; SEED_LEN]>>, Backend),
+}
+
+#[derive(Clone)]
+pub struct SignerPool {
+    key: Key,
+    permits: Arc<Semaphore>,
+}
+
+impl SignerPool {
+    /// A pool signing with `seed`, running at most `max_concurrent`
+    /// signatures at once (at least 1).
+    pub fn new(seed: Zeroizing<[u8; SEED_LEN]>, backend: Backend, max_concurrent: usize) -> Self {
+        let key = match backend {
+            Backend::RustCrypto => Key::Expanded(Arc::new(PqSigner::from_seed(&seed))),
+            #[cfg(feature = "fips204")]
+            Backend::Fips204 => Key::Seed(Arc::new(seed), backend),
+        };
+        SignerPool {
+            key,
+            permits: Arc::new(Semaphore::new(max_concurrent.max(1))),
+        }
+    }
+
+    /// Slots free right now.
+    pub fn available(&self) -> usize {
+        self.permits.available_permits()
+    }
+
+    /// Deterministic pure ML-DSA over `message` with an empty context, the
+    /// same signature `pq-sign` produces.
+    pub async fn sign(&self, message: B256, cancel: impl Future<Output = ()>) -> Result<Vec<u8>> {
+        let work = async {
+            let permit = self
+                .permits
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("semaphore is never closed");
+            let key = self.key.clone();
+            tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                match key {
+                    Key::Expanded(signer) => signer.sign_with(message.as_slice(), &[], &[0u8; 32]),
+                    #[cfg(feature = "fips204")]
+                    Key::Seed(seed, backend) => {
+                        backend.sign(&seed, message.as_slice(), &[], &[0u8; 32])
+                    }
+                }
+            })
+            .await
+            .map_err(|e| WalletError::Backend(format!("signing task failed: {e}")))?
+        };
+        tokio::select! {
+            biased;
+            () = cancel => Err(WalletError::Cancelled),
+            signature = work => signature,
+        }
+    }
+
+    /// Sign `op`'s userOpHash and fill in `signature` with the bare
+    /// signature, as `pq-userop sign` does for the default contract verifier.
+    pub async fn sign_userop_async(
+        &self,
+        mut op: PackedUserOperation,
+        entry_point: Address,
+        chain_id: u64,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<PackedUserOperation> {
+        let hash = userop::compute_user_op_hash(&op, entry_point, U256::from(chain_id));
+        op.signature = self.sign(hash, cancel).await?;
+        Ok(op)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys;
+    use crate::userop::ENTRY_POINT_V07;
+
+    fn pool(max_concurrent: usize) -> SignerPool {
+        SignerPool::new(
+            Zeroizing::new([5u8; SEED_LEN]),
+            Backend::RustCrypto,
+            max_concurrent,
+        )
+    }
+
+    #[tokio::test]
+    async fn signs_userops_concurrently_within_the_limit() {
+        let pool = pool(2);
+        let op = PackedUserOperation {
+            sender: Address::repeat_byte(1),
+            nonce: U256::ZERO,
+            init_code: Vec::new(),
+            call_data: Vec::new(),
+            account_gas_limits: B256::ZERO,
+            pre_verification_gas: U256::ZERO,
+            gas_fees: B256::ZERO,
+            paymaster_and_data: Vec::new(),
+            signature: Vec::new(),
+        };
+        let signed = tokio::join!(
+            pool.sign_userop_async(op.clone(), ENTRY_POINT_V07, 1, std::future::pending()),
+            pool.sign_userop_async(op.clone(), ENTRY_POINT_V07, 2, std::future::pending()),
+        );
+        let pk = Backend::RustCrypto.public_key(&[5u8; SEED_LEN]);
+        for (chain_id, signed) in [(1, signed.0), (2, signed.1)] {
+            let signed = signed.unwrap();
+            let hash = userop::compute_user_op_hash(&op, ENTRY_POINT_V07, U256::from(chain_id));
+            assert_eq!(signed.signature.len(), keys::SIGNATURE_LEN);
+            assert!(Backend::RustCrypto.verify(&pk, hash.as_slice(), &[], &signed.signature));
+        }
+        assert_eq!(pool.available(), 2);
+    }
+
+    #[tokio::test]
+    async fn cancelled_while_waiting_for_a_slot() {
+        let pool = pool(1);
+        let held = pool.permits.clone().acquire_owned().await.unwrap();
+        let err = pool.sign(B256::ZERO, std::future::ready(())).await;
+        assert!(matches!(err, Err(WalletError::Cancelled)));
+        drop(held);
+        assert!(pool.sign(B256::ZERO, std::future::pending()).await.is_ok());
+    }
+}
@@ -0,0 +1,255 @@
+//! On-chain verification strategies: the `IMLDSAVerifier` contract we deploy
+//! today, or a native ML-DSA precompile on chains that add one.
+//!
+//! Precompile proposals follow RIP-7212's shape: fixed-width input at a
+//! reserved address, a 32-byte `1` on success and empty output otherwise.
+//! Drafts disagree on the input layout, so [`InputLayout`] covers both the
+//! packed `hash || pk || sig` form and `abi.encode(hash, pk, sig)`.
+//!
+//! The strategy also decides what goes in `userOp.signature`: the deployed
+//! module stores the key and takes a bare signature, while a precompile
+//! account forwards the envelope straight into the precompile input.
+
+use std::fmt;
+
+use alloy_primitives::{Address, B256, Bytes};
+use alloy_sol_types::{SolCall, SolValue, sol};
+
+use crate::envelope::{self, SCHEME_ML_DSA_65};
+use crate::error::{Result, WalletError};
+use crate::keys::{PUBLIC_KEY_LEN, SIGNATURE_LEN};
+use crate::rpc::Transport;
+
+sol! {
+    /// `IMLDSAVerifier.verify`, as exported by pq-validator.
+    function verify(bytes publicKey, bytes32 message, bytes signature) returns (bool);
+}
+
+/// A call that verifies one signature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifierCall {
+    pub to: Address,
+    pub input: Vec<u8>,
+}
+
+/// How a chain verifies ML-DSA-65 signatures on-chain.
+pub trait OnChainVerifier: fmt::Display {
+    /// Bytes for `userOp.signature`.
+    fn signature_field(&self, pk: &[u8], sig: &[u8]) -> Result<Vec<u8>>;
+
+    /// The `staticcall` that verifies `sig` over `hash`.
+    fn call(&self, hash: B256, pk: &[u8], sig: &[u8]) -> Result<VerifierCall>;
+
+    /// Interpret the call's return data.
+    fn is_valid(&self, output: &[u8]) -> bool;
+}
+
+fn check_lengths(pk: &[u8], sig: &[u8]) -> Result<()> {
+    for (what, expected, actual) in [
+        ("public key", PUBLIC_KEY_LEN, pk.len()),
+        ("signature", SIGNATURE_LEN, sig.len()),
+    ] {
+        if actual != expected {
+            return Err(WalletError::InvalidLength {
+                what,
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// A deployed `IMLDSAVerifier` (the Stylus verifier or a Solidity port).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VerifierContract {
+    pub address: Address,
+}
+
+impl fmt::Display for VerifierContract {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "IMLDSAVerifier at {}", self.address)
+    }
+}
+
+impl OnChainVerifier for VerifierContract {
+    fn signature_field(&self, pk: &[u8], sig: &[u8]) -> Result<Vec<u8>> {
+        check_lengths(pk, sig)?;
+        Ok(sig.to_vec())
+    }
+
+    fn call(&self, hash: B256, pk: &[u8], sig: &[u8]) -> Result<VerifierCall> {
+        check_lengths(pk, sig)?;
+        let input = verifyCall {
+            publicKey: Bytes::copy_from_slice(pk),
+            message: hash,
+            signature: Bytes::copy_from_slice(sig),
+        }
+        .abi_encode();
+        Ok(VerifierCall {
+            to: self.address,
+            input,
+        })
+    }
+
+    fn is_valid(&self, output: &[u8]) -> bool {
+        verifyCall::abi_decode_returns(output).unwrap_or(false)
+    }
+}
+
+/// Precompile input layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputLayout {
+    /// `hash(32) || pk(1952) || sig(3309)`, 5,293 bytes.
+    Packed,
+    /// `abi.encode(bytes32 hash, bytes pk, bytes sig)`.
+    Abi,
+}
+
+/// Format a precompile input.
+pub fn format_input(layout: InputLayout, hash: B256, pk: &[u8], sig: &[u8]) -> Result<Vec<u8>> {
+    check_lengths(pk, sig)?;
+    Ok(match layout {
+        InputLayout::Packed => {
+            let mut out = Vec::with_capacity(32 + PUBLIC_KEY_LEN + SIGNATURE_LEN);
+            out.extend_from_slice(hash.as_slice());
+            out.extend_from_slice(pk);
+            out.extend_from_slice(sig);
+            out
+        }
+        InputLayout::Abi => (
+            hash,
+            Bytes::copy_from_slice(pk),
+            Bytes::copy_from_slice(sig),
+        )
+            .abi_encode_params(),
+    })
+}
+
+/// A native ML-DSA-65 verification precompile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Precompile {
+    pub address: Address,
+    pub layout: InputLayout,
+}
+
+impl fmt::Display for Precompile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} precompile at {}", self.layout, self.address)
+    }
+}
+
+impl OnChainVerifier for Precompile {
+    /// The envelope the account appends to the hash: `pk || sig` for the
+    /// packed layout, the ABI envelope otherwise.
+    fn signature_field(&self, pk: &[u8], sig: &[u8]) -> Result<Vec<u8>> {
+        match self.layout {
+            InputLayout::Packed => envelope::encode_signature_envelope_packed(pk, sig),
+            InputLayout::Abi => envelope::encode_signature_envelope(pk, sig, SCHEME_ML_DSA_65),
+        }
+    }
+
+    fn call(&self, hash: B256, pk: &[u8], sig: &[u8]) -> Result<VerifierCall> {
+        Ok(VerifierCall {
+            to: self.address,
+            input: format_input(self.layout, hash, pk, sig)?,
+        })
+    }
+
+    fn is_valid(&self, output: &[u8]) -> bool {
+        output.len() == 32 && output[..31].iter().all(|&b| b == 0) && output[31] == 1
+    }
+}
+
+/// Parse `contract:ADDR`, `precompile:ADDR` or `precompile:ADDR:abi`.
+pub fn parse_verifier(spec: &str) -> Result<Box<dyn OnChainVerifier>> {
+    const USAGE: &str = "verifier must be contract:ADDR, precompile:ADDR or precompile:ADDR:abi";
+    let mut parts = spec.split(':');
+    let kind = parts.next().unwrap_or_default();
+    let address: Address = parts
+        .next()
+        .and_then(|a| a.parse().ok())
+        .ok_or(WalletError::Usage(USAGE))?;
+    let layout = parts.next();
+    if parts.next().is_some() {
+        return Err(WalletError::Usage(USAGE));
+    }
+    match (kind, layout) {
+        ("contract", None) => Ok(Box::new(VerifierContract { address })),
+        ("precompile", None | Some("packed")) => Ok(Box::new(Precompile {
+            address,
+            layout: InputLayout::Packed,
+        })),
+        ("precompile", Some("abi")) => Ok(Box::new(Precompile {
+            address,
+            layout: InputLayout::Abi,
+        })),
+        _ => Err(WalletError::Usage(USAGE)),
+    }
+}
+
+/// `eth_call` the verifier and report whether it accepted the signature.
+pub fn verify_on_chain<T: Transport>(
+    rpc: &T,
+    verifier: &dyn OnChainVerifier,
+    hash: B256,
+    pk: &[u8],
+    sig: &[u8],
+) -> Result<bool> {
+    let call = verifier.call(hash, pk, sig)?;
+    let params = serde_json::json!([
+        { "to": call.to, "data": Bytes::from(call.input) },
+        "latest"
+    ]);
+    let output: Bytes = rpc.call("eth_call", params)?;
+    Ok(verifier.is_valid(&output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> (Vec<u8>, Vec<u8>) {
+        (vec![1u8; PUBLIC_KEY_LEN], vec![2u8; SIGNATURE_LEN])
+    }
+
+    #[test]
+    fn packed_input_is_hash_pk_sig() {
+        let (pk, sig) = sample();
+        let hash = B256::repeat_byte(0xAA);
+        let input = format_input(InputLayout::Packed, hash, &pk, &sig).unwrap();
+        assert_eq!(input.len(), 5293);
+        assert_eq!(input[..32], hash[..]);
+        assert_eq!(input[32], 1);
+        assert_eq!(input[32 + PUBLIC_KEY_LEN], 2);
+        let (h, p, s) = <(B256, Bytes, Bytes)>::abi_decode_params(
+            &format_input(InputLayout::Abi, hash, &pk, &sig).unwrap(),
+        )
+        .unwrap();
+        assert_eq!((h, p.len(), s.len()), (hash, PUBLIC_KEY_LEN, SIGNATURE_LEN));
+        assert!(format_input(InputLayout::Packed, hash, &pk[1..], &sig).is_err());
+    }
+
+    #[test]
+    fn strategies_choose_signature_field_and_result_encoding() {
+        let (pk, sig) = sample();
+        let contract =
+            parse_verifier("contract:0x1111111111111111111111111111111111111111").unwrap();
+        let precompile =
+            parse_verifier("precompile:0x0000000000000000000000000000000000000100").unwrap();
+        assert_eq!(contract.signature_field(&pk, &sig).unwrap(), sig);
+        assert_eq!(precompile.signature_field(&pk, &sig).unwrap().len(), 5261);
+
+        let mut one = [0u8; 32];
+        one[31] = 1;
+        assert!(contract.is_valid(&true.abi_encode()));
+        assert!(!contract.is_valid(&[]));
+        assert!(precompile.is_valid(&one));
+        assert!(!precompile.is_valid(&[]));
+
+        let call = contract.call(B256::ZERO, &pk, &sig).unwrap();
+        assert_eq!(call.input[..4], verifyCall::SELECTOR);
+        assert!(parse_verifier("precompile:0x01:zstd").is_err());
+        assert!(parse_verifier("stylus:0x1111111111111111111111111111111111111111").is_err());
+    }
+}
@@ -0,0 +1,430 @@
+//! Per-chain account profiles: the construction each chain's validator
+//! expects.
+//!
+//! The same key can back smart accounts on chains that run different
+//! validators: the `PQValidatorModule` (pure ML-DSA over the userOpHash), a
+//! hybrid one that also wants a secp256k1 or passkey co-signature, or an
+//! account that forwards `pk || sig` to a native precompile. A signature in
+//! the wrong construction is not just rejected but wastes a bundler
+//! round-trip, so an account can record, per chain ID, a [`Profile`]:
+//!
+//! ```json
+//! "profiles": {
+//!   "1":     { "validator": "ml-dsa" },
+//!   "10":    { "validator": "hybrid-ecdsa", "envelope": 2 },
+//!   "31337": { "validator": "precompile:0x…0100", "signingScheme": "hash" }
+//! }
+//! ```
+//!
+//! `pq-userop` and `pq-signerd` look the profile up for the op's chain,
+//! take the signing scheme and `userOp.signature` layout from it, and
+//! refuse flags that contradict it (another `--verifier`, `--signing-scheme`
+//! or EntryPoint, a co-signature the validator does not check or a missing
+//! one it does) instead of signing. Chains without a profile keep the
+//! per-chain `signingSchemes` and the flags as given.
+
+use std::fmt;
+
+use alloy_primitives::Address;
+use serde::{Deserialize, Serialize};
+
+use crate::envelope::{
+    ENVELOPE_V1, ENVELOPE_V2, ENVELOPE_V3, Envelope, LATEST_ENVELOPE_VERSION, SCHEME_ML_DSA_65,
+    SignatureEnvelope,
+};
+use crate::error::{Result, WalletError};
+use crate::keys::{PUBLIC_KEY_LEN, SIGNATURE_LEN};
+use crate::precompile::{InputLayout, OnChainVerifier, Precompile};
+use crate::signing_scheme::SigningScheme;
+use crate::userop::ENTRY_POINT_V07;
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::Profile(reason.into())
+}
+
+/// What verifies the account's signatures on a chain.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Validator {
+    /// `PQValidatorModule`: the stored key, a bare ML-DSA-65 signature.
+    #[default]
+    MlDsa,
+    /// ML-DSA-65 plus a secp256k1 co-signature over the userOpHash.
+    HybridEcdsa,
+    /// ML-DSA-65 plus a WebAuthn (P-256) co-signature.
+    HybridPasskey,
+    /// A native ML-DSA-65 precompile, which gets the key with each
+    /// signature.
+    Precompile(Precompile),
+}
+
+impl Validator {
+    /// The validator a `--verifier` spec (`contract:ADDR`,
+    /// `precompile:ADDR[:abi]`) stands for.
+    pub fn of_verifier(spec: &str) -> Result<Self> {
+        match spec.split_once(':') {
+            Some(("contract", _)) => {
+                crate::precompile::parse_verifier(spec)?;
+                Ok(Validator::MlDsa)
+            }
+            _ => spec.parse(),
+        }
+    }
+
+    fn is_hybrid(self) -> bool {
+        matches!(self, Validator::HybridEcdsa | Validator::HybridPasskey)
+    }
+}
+
+impl fmt::Display for Validator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Validator::MlDsa => f.write_str("ml-dsa"),
+            Validator::HybridEcdsa => f.write_str("hybrid-ecdsa"),
+            Validator::HybridPasskey => f.write_str("hybrid-passkey"),
+            Validator::Precompile(p) => match p.layout {
+                InputLayout::Packed => write!(f, "precompile:{}", p.address),
+                InputLayout::Abi => write!(f, "precompile:{}:abi", p.address),
+            },
+        }
+    }
+}
+
+impl std::str::FromStr for Validator {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        const USAGE: &str =
+            "expected ml-dsa, hybrid-ecdsa, hybrid-passkey or precompile:ADDR[:abi]";
+        match s {
+            "ml-dsa" => return Ok(Validator::MlDsa),
+            "hybrid-ecdsa" => return Ok(Validator::HybridEcdsa),
+            "hybrid-passkey" => return Ok(Validator::HybridPasskey),
+            _ => {}
+        }
+        let parts: Vec<&str> = s.split(':').collect();
+        let (address, layout) = match parts[..] {
+            ["precompile", address] | ["precompile", address, "packed"] => {
+                (address, InputLayout::Packed)
+            }
+            ["precompile", address, "abi"] => (address, InputLayout::Abi),
+            _ => return Err(err(format!("unknown validator {s:?} ({USAGE})"))),
+        };
+        let address = address
+            .parse()
+            .map_err(|_| err(format!("invalid precompile address in {s:?}")))?;
+        Ok(Validator::Precompile(Precompile { address, layout }))
+    }
+}
+
+impl TryFrom<String> for Validator {
+    type Error = WalletError;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Validator> for String {
+    fn from(validator: Validator) -> String {
+        validator.to_string()
+    }
+}
+
+/// The EntryPoint release the chain's accounts are deployed against; it
+/// decides the address and the userOpHash.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum EntryPointVersion {
+    #[default]
+    V07,
+}
+
+impl EntryPointVersion {
+    pub fn address(self) -> Address {
+        match self {
+            EntryPointVersion::V07 => ENTRY_POINT_V07,
+        }
+    }
+}
+
+impl fmt::Display for EntryPointVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EntryPointVersion::V07 => "v0.7",
+        })
+    }
+}
+
+impl std::str::FromStr for EntryPointVersion {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "v0.7" | "0.7" => Ok(EntryPointVersion::V07),
+            other => Err(err(format!(
+                "unsupported EntryPoint version {other:?} (this build knows v0.7)"
+            ))),
+        }
+    }
+}
+
+impl TryFrom<String> for EntryPointVersion {
+    type Error = WalletError;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<EntryPointVersion> for String {
+    fn from(version: EntryPointVersion) -> String {
+        version.to_string()
+    }
+}
+
+/// How the account's validator on one chain expects ops to be signed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Profile {
+    #[serde(default)]
+    pub validator: Validator,
+    /// Versioned [`Envelope`] the validator decodes; none for the
+    /// validator's bare layout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub envelope: Option<u8>,
+    #[serde(default)]
+    pub entry_point: EntryPointVersion,
+    #[serde(default)]
+    pub signing_scheme: SigningScheme,
+}
+
+/// What a signer's flags ask for; `None` and `false` leave it to the
+/// profile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub entry_point: Address,
+    pub signing_scheme: Option<SigningScheme>,
+    /// From `--verifier`.
+    pub verifier: Option<Validator>,
+    /// [`Validator::HybridEcdsa`] or [`Validator::HybridPasskey`] when a
+    /// co-signature is added.
+    pub co_signature: Option<Validator>,
+    /// The signature carries a validity window.
+    pub windowed: bool,
+}
+
+impl Request {
+    /// Signing at `entry_point` with nothing else asked for.
+    pub fn new(entry_point: Address) -> Self {
+        Request {
+            entry_point,
+            signing_scheme: None,
+            verifier: None,
+            co_signature: None,
+            windowed: false,
+        }
+    }
+}
+
+impl Profile {
+    /// Reject profiles no validator could have: an unknown envelope
+    /// version, or one the validator does not take.
+    pub fn validate(&self) -> Result<()> {
+        let Some(version) = self.envelope else {
+            return Ok(());
+        };
+        if version == 0 || version > LATEST_ENVELOPE_VERSION {
+            return Err(err(format!(
+                "unknown envelope version {version} (1 to {LATEST_ENVELOPE_VERSION})"
+            )));
+        }
+        let fits = match self.validator {
+            Validator::MlDsa => version == ENVELOPE_V1 || version == ENVELOPE_V3,
+            Validator::HybridEcdsa => version == ENVELOPE_V2,
+            Validator::HybridPasskey | Validator::Precompile(_) => false,
+        };
+        if !fits {
+            return Err(err(format!(
+                "a {} validator takes no V{version} envelope",
+                self.validator
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check what a signer was asked to do on `chain_id` against the
+    /// profile.
+    pub fn check(&self, chain_id: u64, request: &Request) -> Result<()> {
+        let on = |what: String| err(format!("chain {chain_id}'s profile {what}"));
+        let entry_point = self.entry_point.address();
+        if request.entry_point != entry_point {
+            return Err(on(format!(
+                "expects EntryPoint {} at {entry_point}, not {}",
+                self.entry_point, request.entry_point
+            )));
+        }
+        if let Some(scheme) = request.signing_scheme
+            && scheme != self.signing_scheme
+        {
+            return Err(on(format!("signs {}, not {scheme}", self.signing_scheme)));
+        }
+        if let Some(verifier) = request.verifier {
+            let expected = match self.validator {
+                Validator::Precompile(_) => self.validator,
+                _ => Validator::MlDsa,
+            };
+            if verifier != expected {
+                return Err(on(format!(
+                    "has a {} validator, not {verifier}",
+                    self.validator
+                )));
+            }
+        }
+        match (self.validator.is_hybrid(), request.co_signature) {
+            (true, None) => {
+                return Err(on(format!(
+                    "has a {} validator, which needs a co-signature",
+                    self.validator
+                )));
+            }
+            (_, Some(co)) if co != self.validator => {
+                return Err(on(format!(
+                    "has a {} validator, which checks no {co} co-signature",
+                    self.validator
+                )));
+            }
+            _ => {}
+        }
+        if request.windowed && (self.validator != Validator::MlDsa || self.envelope.is_some()) {
+            return Err(on(format!(
+                "has a {} validator{}, which reads no validity window",
+                self.validator,
+                self.envelope
+                    .map(|v| format!(" with V{v} envelopes"))
+                    .unwrap_or_default()
+            )));
+        }
+        Ok(())
+    }
+
+    /// `userOp.signature` for `signature` by `public_key`. A hybrid
+    /// validator's co-signature goes around what this returns; V3 envelopes
+    /// carry a delegation, so only a delegated robot key makes them.
+    pub fn signature_field(&self, public_key: &[u8], signature: &[u8]) -> Result<Vec<u8>> {
+        match (self.validator, self.envelope) {
+            (Validator::Precompile(precompile), _) => {
+                precompile.signature_field(public_key, signature)
+            }
+            (Validator::MlDsa, Some(ENVELOPE_V1)) => Envelope::V1(SignatureEnvelope {
+                scheme_id: SCHEME_ML_DSA_65,
+                public_key: public_key.to_vec(),
+                signature: signature.to_vec(),
+            })
+            .encode(),
+            (_, Some(ENVELOPE_V3)) => Err(err(
+                "V3 envelopes are signed by a delegated robot key (pq-delegate, pq-signerd)",
+            )),
+            _ => Ok(signature.to_vec()),
+        }
+    }
+
+    /// A stand-in for the field, as long as the real one, for gas
+    /// estimates.
+    pub fn placeholder_signature(&self) -> Vec<u8> {
+        self.signature_field(&[0xff; PUBLIC_KEY_LEN], &[0xff; SIGNATURE_LEN])
+            .unwrap_or_else(|_| vec![0xff; SIGNATURE_LEN])
+    }
+
+    /// One line for account listings.
+    pub fn summary(&self) -> String {
+        format!(
+            "{} validator{}, EntryPoint {}, signs {}",
+            self.validator,
+            self.envelope
+                .map(|v| format!(", V{v} envelopes"))
+                .unwrap_or_default(),
+            self.entry_point,
+            self.signing_scheme
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_round_trip_and_refuse_the_wrong_construction() {
+        let precompile: Profile = serde_json::from_str(
+            r#"{"validator":"precompile:0x0000000000000000000000000000000000000100:abi"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            precompile.validator.to_string(),
+            "precompile:0x0000000000000000000000000000000000000100:abi"
+        );
+        assert_eq!(precompile.entry_point, EntryPointVersion::V07);
+        let json = serde_json::to_value(precompile).unwrap();
+        assert_eq!(json["entryPoint"], "v0.7");
+        assert_eq!(serde_json::from_value::<Profile>(json).unwrap(), precompile);
+        assert!(serde_json::from_str::<Profile>(r#"{"validator":"rsa"}"#).is_err());
+
+        let mut request = Request::new(ENTRY_POINT_V07);
+        precompile.check(1, &request).unwrap();
+        request.verifier = Some(
+            Validator::of_verifier("contract:0x0000000000000000000000000000000000000001").unwrap(),
+        );
+        assert!(precompile.check(1, &request).is_err());
+        request.verifier = None;
+        request.signing_scheme = Some(SigningScheme::RawMessage);
+        assert!(precompile.check(1, &request).is_err());
+        request.signing_scheme = None;
+        request.entry_point = Address::repeat_byte(1);
+        assert!(precompile.check(1, &request).is_err());
+
+        let field = precompile
+            .signature_field(&[1; PUBLIC_KEY_LEN], &[2; SIGNATURE_LEN])
+            .unwrap();
+        assert_eq!(field.len(), precompile.placeholder_signature().len());
+        assert!(field.len() > PUBLIC_KEY_LEN + SIGNATURE_LEN);
+
+        let hybrid = Profile {
+            validator: Validator::HybridEcdsa,
+            envelope: Some(ENVELOPE_V2),
+            ..Profile::default()
+        };
+        hybrid.validate().unwrap();
+        let mut request = Request::new(ENTRY_POINT_V07);
+        assert!(hybrid.check(1, &request).is_err());
+        request.co_signature = Some(Validator::HybridPasskey);
+        assert!(hybrid.check(1, &request).is_err());
+        request.co_signature = Some(Validator::HybridEcdsa);
+        hybrid.check(1, &request).unwrap();
+        assert!(Profile::default().check(1, &request).is_err());
+
+        let wrapped = Profile {
+            envelope: Some(ENVELOPE_V1),
+            ..Profile::default()
+        };
+        let field = wrapped
+            .signature_field(&[1; PUBLIC_KEY_LEN], &[2; SIGNATURE_LEN])
+            .unwrap();
+        assert_eq!(Envelope::decode(&field).unwrap().version(), ENVELOPE_V1);
+        let windowed = Request {
+            windowed: true,
+            ..Request::new(ENTRY_POINT_V07)
+        };
+        assert!(wrapped.check(1, &windowed).is_err());
+        Profile::default().check(1, &windowed).unwrap();
+        assert!(
+            Profile {
+                validator: Validator::HybridPasskey,
+                envelope: Some(ENVELOPE_V1),
+                ..Profile::default()
+            }
+            .validate()
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! Dual control: ops that need approval wait for a second operator.
+//!
+//! With a `[dual_control]` section in the signing policy, `pq-signerd` no
+//! longer takes a caller's word that an op was approved. An op over
+//! `approval_above`, or from an account an anomaly put on an approval hold,
+//! is parked as a [`Proposal`] instead of signed:
+//!
+//! ```toml
+//! [dual_control]
+//! approvers = ["0x5c1f…"]     # keccak256 of each approver's ML-DSA-65 key
+//! ttl_secs = 86400            # a proposal not decided by then expires
+//! ```
+//!
+//! An approver lists the pending proposals (`pq-approve list`), reviews the
+//! decoded op and signs its [`approval_digest`] with their own key. Only
+//! once the daemon has checked that signature, against a key on the list
+//! that is not the account's own, does it sign the op and keep the
+//! signature on the proposal for the caller to collect. Proposals are kept
+//! in a [`ProposalStore`] next to the keystore, so a restart loses none.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::{Address, B256, keccak256};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, WalletError};
+use crate::keystore::Keystore;
+use crate::userop::PackedUserOperation;
+
+/// Domain separating approval signatures from every other digest an
+/// approver key may sign.
+pub const APPROVAL_DOMAIN: &[u8] = b"pq-wallet/proposal-approval/v1";
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::Proposal(reason.into())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DualControl {
+    /// keccak256 of the encoded public keys that may decide proposals.
+    pub approvers: Vec<B256>,
+    /// Seconds a proposal stays open.
+    #[serde(default = "default_ttl")]
+    pub ttl_secs: u64,
+}
+
+fn default_ttl() -> u64 {
+    86_400
+}
+
+impl DualControl {
+    pub fn is_approver(&self, public_key: &[u8]) -> bool {
+        self.approvers.contains(&keccak256(public_key))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    Pending,
+    Approved,
+    Rejected,
+    Expired,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Pending => "pending",
+            Status::Approved => "approved",
+            Status::Rejected => "rejected",
+            Status::Expired => "expired",
+        })
+    }
+}
+
+/// An op parked until an approver decides it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Proposal {
+    pub id: B256,
+    /// Daemon account to sign with.
+    pub account: String,
+    /// Principal that asked for the signature.
+    pub principal: String,
+    pub chain_id: u64,
+    pub entry_point: Address,
+    /// The op as submitted; `signature` is filled in once approved.
+    pub user_op: PackedUserOperation,
+    pub user_op_hash: B256,
+    /// Why the op needs approval.
+    pub reason: String,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub status: Status,
+    /// keccak256 of the key that decided it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decided_by: Option<B256>,
+}
+
+impl Proposal {
+    /// The ID of `account`'s proposal for the op with `user_op_hash`: the
+    /// same op proposed twice is the same proposal.
+    pub fn id_for(account: &str, user_op_hash: B256) -> B256 {
+        keccak256([account.as_bytes(), user_op_hash.as_slice()].concat())
+    }
+
+    /// What an approver signs to approve (or reject) this proposal.
+    pub fn digest(&self, approve: bool) -> B256 {
+        approval_digest(self.id, self.user_op_hash, approve)
+    }
+
+    pub fn is_open(&self, now: u64) -> bool {
+        self.status == Status::Pending && now < self.expires_at
+    }
+}
+
+/// keccak256(domain ‖ decision ‖ id ‖ userOpHash), the decision byte 1 to
+/// approve and 0 to reject. The userOpHash already binds the chain and
+/// EntryPoint.
+pub fn approval_digest(id: B256, user_op_hash: B256, approve: bool) -> B256 {
+    let mut preimage = APPROVAL_DOMAIN.to_vec();
+    preimage.push(u8::from(approve));
+    preimage.extend_from_slice(id.as_slice());
+    preimage.extend_from_slice(user_op_hash.as_slice());
+    keccak256(preimage)
+}
+
+/// An approver's decision on a proposal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Decision {
+    pub approve: bool,
+    /// The approver's encoded ML-DSA-65 public key.
+    pub public_key: Vec<u8>,
+    /// Over [`Proposal::digest`] for `approve`.
+    pub signature: Vec<u8>,
+}
+
+/// Proposals by ID, open and decided.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProposalStore {
+    pub proposals: BTreeMap<B256, Proposal>,
+}
+
+impl ProposalStore {
+    /// `proposals.json` in the keystore root.
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(Keystore::default_root()?.join("proposals.json"))
+    }
+
+    /// Read the store; a missing file is an empty store.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(bytes) => {
+                serde_json::from_slice(&bytes).map_err(|e| err(format!("{}: {e}", path.display())))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(WalletError::Io(path.display().to_string(), e)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| WalletError::Io(dir.display().to_string(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self).expect("proposals serialize");
+        std::fs::write(path, json).map_err(|e| WalletError::Io(path.display().to_string(), e))
+    }
+
+    pub fn get(&self, id: B256) -> Result<&Proposal> {
+        self.proposals
+            .get(&id)
+            .ok_or_else(|| err(format!("no proposal {id}")))
+    }
+
+    /// Park `proposal`, unless the same one is already open. Returns the
+    /// open one.
+    pub fn propose(&mut self, proposal: Proposal, now: u64) -> &Proposal {
+        let id = proposal.id;
+        match self.proposals.get(&id) {
+            Some(open) if open.is_open(now) => {}
+            _ => {
+                self.proposals.insert(id, proposal);
+            }
+        }
+        &self.proposals[&id]
+    }
+
+    /// Mark pending proposals past their deadline expired. Returns whether
+    /// any were.
+    pub fn expire(&mut self, now: u64) -> bool {
+        let mut changed = false;
+        for proposal in self.proposals.values_mut() {
+            if proposal.status == Status::Pending && now >= proposal.expires_at {
+                proposal.status = Status::Expired;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::U256;
+
+    fn proposal(now: u64) -> Proposal {
+        let hash = B256::repeat_byte(7);
+        Proposal {
+            id: Proposal::id_for("alice", hash),
+            account: "alice".into(),
+            principal: "app".into(),
+            chain_id: 1,
+            entry_point: Address::ZERO,
+            user_op: PackedUserOperation {
+                sender: Address::repeat_byte(0xaa),
+                nonce: U256::ZERO,
+                init_code: vec![],
+                call_data: vec![],
+                account_gas_limits: B256::ZERO,
+                pre_verification_gas: U256::ZERO,
+                gas_fees: B256::ZERO,
+                paymaster_and_data: vec![],
+                signature: vec![],
+            },
+            user_op_hash: hash,
+            reason: "needs approval".into(),
+            created_at: now,
+            expires_at: now + 60,
+            status: Status::Pending,
+            decided_by: None,
+        }
+    }
+
+    #[test]
+    fn proposals_persist_dedupe_and_expire() {
+        let path = std::env::temp_dir().join(format!("pq-proposals-{}.json", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let mut store = ProposalStore::load(&path).unwrap();
+        let id = store.propose(proposal(100), 100).id;
+        // Proposing the same op again keeps the open proposal.
+        assert_eq!(store.propose(proposal(110), 110).created_at, 100);
+        store.save(&path).unwrap();
+
+        let mut store = ProposalStore::load(&path).unwrap();
+        let open = store.get(id).unwrap();
+        assert!(open.is_open(159) && !open.is_open(160));
+        assert_ne!(open.digest(true), open.digest(false));
+        assert!(!store.expire(159));
+        assert!(store.expire(160));
+        assert_eq!(store.get(id).unwrap().status, Status::Expired);
+        // An expired proposal can be made again.
+        assert_eq!(store.propose(proposal(200), 200).status, Status::Pending);
+        assert!(store.get(B256::ZERO).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,156 @@
+//! Bulk keypair generation for load tests and fleet provisioning.
+//!
+//! Each key lands in `<dir>/<name>/{pk.bin,sk.bin}`, the same layout as a
+//! single `pq-keygen --output`, and `<dir>/index.csv` lists every key as
+//! `name,address,pubkeyHash`. With a master seed, key `i`'s seed is
+//! `keccak256("pq-wallet/provision/v1" || master || i as u64 BE)`, so a rerun
+//! with the same master reproduces the whole set.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::{Address, B256, keccak256};
+use rayon::prelude::*;
+use zeroize::Zeroizing;
+
+use crate::error::{Result, WalletError};
+use crate::keys::{self, SEED_LEN};
+
+/// Domain separator for master-seed derivation.
+pub const DERIVATION_DOMAIN: &[u8] = b"pq-wallet/provision/v1";
+
+/// File listing the generated keys.
+pub const INDEX_FILE: &str = "index.csv";
+
+/// Seed of key `index` under `master`.
+pub fn derive_seed(master: &[u8; SEED_LEN], index: u64) -> Zeroizing<[u8; SEED_LEN]> {
+    let mut preimage = Zeroizing::new(Vec::with_capacity(DERIVATION_DOMAIN.len() + 40));
+    preimage.extend_from_slice(DERIVATION_DOMAIN);
+    preimage.extend_from_slice(master);
+    preimage.extend_from_slice(&index.to_be_bytes());
+    Zeroizing::new(keccak256(preimage.as_slice()).0)
+}
+
+/// Directory name of key `index` in a set of `count`: `key-0007` for 10,000.
+pub fn key_name(index: u64, count: u64) -> String {
+    let width = count.saturating_sub(1).max(1).ilog10() as usize + 1;
+    format!("key-{index:0width$}")
+}
+
+/// One generated key, as listed in the index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Provisioned {
+    pub name: String,
+    /// Key identifier, as `pq-keygen` prints it.
+    pub address: Address,
+    /// keccak256 of the encoded public key.
+    pub pubkey_hash: B256,
+    pub path: PathBuf,
+}
+
+fn generate_one(
+    dir: &Path,
+    index: u64,
+    count: u64,
+    master: Option<&[u8; SEED_LEN]>,
+) -> Result<Provisioned> {
+    let seed = match master {
+        Some(master) => derive_seed(master, index),
+        None => Zeroizing::new(rand::random()),
+    };
+    let pk = keys::keygen_from_seed(&seed).verifying_key().encode();
+    let name = key_name(index, count);
+    let path = dir.join(&name);
+    let io_err = |p: &Path| {
+        let p = p.display().to_string();
+        move |e| WalletError::Io(p, e)
+    };
+    std::fs::create_dir_all(&path).map_err(io_err(&path))?;
+    let pk_path = path.join("pk.bin");
+    let sk_path = path.join("sk.bin");
+    std::fs::write(&pk_path, &pk[..]).map_err(io_err(&pk_path))?;
+    std::fs::write(&sk_path, &seed[..]).map_err(io_err(&sk_path))?;
+    Ok(Provisioned {
+        name,
+        address: keys::address(&pk),
+        pubkey_hash: keccak256(&pk[..]),
+        path,
+    })
+}
+
+/// Generate `count` keypairs under `dir` and write the index. With
+/// `parallel`, keys are generated on the current rayon pool; the index is
+/// in key order either way.
+pub fn generate(
+    dir: &Path,
+    count: u64,
+    master: Option<&[u8; SEED_LEN]>,
+    parallel: bool,
+) -> Result<Vec<Provisioned>> {
+    let keys: Vec<Provisioned> = if parallel {
+        (0..count)
+            .into_par_iter()
+            .map(|i| generate_one(dir, i, count, master))
+            .collect::<Result<_>>()?
+    } else {
+        (0..count)
+            .map(|i| generate_one(dir, i, count, master))
+            .collect::<Result<_>>()?
+    };
+    let index = dir.join(INDEX_FILE);
+    std::fs::write(&index, index_csv(&keys))
+        .map_err(|e| WalletError::Io(index.display().to_string(), e))?;
+    Ok(keys)
+}
+
+/// `name,address,pubkeyHash` with a header row.
+pub fn index_csv(keys: &[Provisioned]) -> String {
+    let mut out = String::from("name,address,pubkeyHash\n");
+    for k in keys {
+        writeln!(out, "{},{},{}", k.name, k.address, k.pubkey_hash).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tempdir(tag: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pq-provision-{tag}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn derivation_is_per_index_and_names_sort() {
+        let master = [9u8; SEED_LEN];
+        assert_eq!(*derive_seed(&master, 3), *derive_seed(&master, 3));
+        assert_ne!(*derive_seed(&master, 3), *derive_seed(&master, 4));
+        assert_eq!(key_name(7, 10_000), "key-0007");
+        assert_eq!(key_name(0, 1), "key-0");
+        assert_eq!(key_name(99, 100), "key-99");
+    }
+
+    #[test]
+    fn master_seed_reproduces_the_set_in_parallel() {
+        let (a, b) = (tempdir("a"), tempdir("b"));
+        let master = [1u8; SEED_LEN];
+        let serial = generate(&a, 4, Some(&master), false).unwrap();
+        let parallel = generate(&b, 4, Some(&master), true).unwrap();
+        let strip = |v: &[Provisioned]| {
+            v.iter()
+                .map(|k| (k.name.clone(), k.address, k.pubkey_hash))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(strip(&serial), strip(&parallel));
+        let index = std::fs::read_to_string(b.join(INDEX_FILE)).unwrap();
+        assert_eq!(index.lines().count(), 5);
+        assert_eq!(
+            std::fs::read(b.join("key-2/sk.bin")).unwrap(),
+            derive_seed(&master, 2).to_vec()
+        );
+        std::fs::remove_dir_all(a).unwrap();
+        std::fs::remove_dir_all(b).unwrap();
+    }
+}
@@ -0,0 +1,496 @@
+//! Persistent queue of signed UserOperations (`--features queue`).
+//!
+//! `pq-userop enqueue` stores a signed op in a SQLite database next to the
+//! keystore (`queue.sqlite`, or `$PQ_WALLET_QUEUE`), and `pq-userop submit
+//! --all` works through it:
+//!
+//! - `pending` ops are sent to the bundler and become `submitted`. A
+//!   network failure leaves the op `pending` with the error and an attempt
+//!   count, so the next run picks it up again;
+//! - `submitted` ops are looked up on the bundler and become `included` or
+//!   `failed`, or go back to `pending` if the bundler has dropped them.
+//!
+//! A time-locked op ([`crate::validity`]) stays `pending` without being
+//! sent until its window opens, so a `submit --all` run from cron sends it
+//! once the EntryPoint will take it. One whose window has closed fails
+//! unsent.
+//!
+//! At most one op per (chain, EntryPoint, sender, nonce) can be queued
+//! and not `failed`, so the same nonce is never submitted twice. Entries
+//! enqueued with the keystore account that signed them can be re-priced and
+//! re-signed when a bundler refuses their fees.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::{Address, B256};
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use serde::Serialize;
+
+use crate::error::{Result, WalletError};
+use crate::keystore::Keystore;
+use crate::userop::PackedUserOperation;
+use crate::validity::ValidityWindow;
+
+/// Overrides the queue database path.
+pub const QUEUE_ENV: &str = "PQ_WALLET_QUEUE";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS ops (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    chain_id     INTEGER NOT NULL,
+    entry_point  TEXT NOT NULL,
+    sender       TEXT NOT NULL,
+    nonce        TEXT NOT NULL,
+    account      TEXT,
+    user_op      TEXT NOT NULL,
+    user_op_hash TEXT NOT NULL,
+    status       TEXT NOT NULL,
+    attempts     INTEGER NOT NULL DEFAULT 0,
+    last_error   TEXT,
+    updated_at   INTEGER NOT NULL,
+    valid_after  INTEGER,
+    valid_until  INTEGER
+);
+CREATE UNIQUE INDEX IF NOT EXISTS one_live_op_per_nonce
+    ON ops (chain_id, entry_point, sender, nonce) WHERE status != 'failed';
+";
+
+/// Columns added since the first schema, for queues created before them.
+const ADDED_COLUMNS: &[(&str, &str)] = &[("valid_after", "INTEGER"), ("valid_until", "INTEGER")];
+
+fn queue_err(reason: impl fmt::Display) -> WalletError {
+    WalletError::Queue(reason.to_string())
+}
+
+/// Where a queued op is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// Signed, not yet accepted by a bundler.
+    Pending,
+    /// Accepted by a bundler, not yet seen in a block.
+    Submitted,
+    /// Mined and its call succeeded.
+    Included,
+    /// Mined and reverted, or given up on.
+    Failed,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Submitted => "submitted",
+            Status::Included => "included",
+            Status::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Status {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pending" => Ok(Status::Pending),
+            "submitted" => Ok(Status::Submitted),
+            "included" => Ok(Status::Included),
+            "failed" => Ok(Status::Failed),
+            other => Err(queue_err(format!("unknown status {other:?}"))),
+        }
+    }
+}
+
+/// One queued op.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub id: i64,
+    pub chain_id: u64,
+    pub entry_point: Address,
+    /// Keystore account that may re-sign the op.
+    pub account: Option<String>,
+    pub user_op: PackedUserOperation,
+    pub user_op_hash: B256,
+    pub status: Status,
+    /// Sends that failed without the bundler answering.
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    /// Unix seconds of the last change.
+    pub updated_at: u64,
+    /// The signature's validity window; the op is not sent before it opens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<ValidityWindow>,
+}
+
+impl Entry {
+    /// A pending op whose window has not opened yet at `now`.
+    pub fn is_scheduled(&self, now: u64) -> bool {
+        self.status == Status::Pending && self.window.is_some_and(|w| now < w.valid_after)
+    }
+}
+
+/// A row as stored; text columns are parsed by [`Raw::parse`] so a bad row
+/// is a queue error rather than a SQLite one.
+struct Raw {
+    id: i64,
+    chain_id: u64,
+    entry_point: String,
+    account: Option<String>,
+    user_op: String,
+    user_op_hash: String,
+    status: String,
+    attempts: u32,
+    last_error: Option<String>,
+    updated_at: u64,
+    valid_after: Option<u64>,
+    valid_until: Option<u64>,
+}
+
+impl Raw {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Raw {
+            id: row.get("id")?,
+            chain_id: row.get("chain_id")?,
+            entry_point: row.get("entry_point")?,
+            account: row.get("account")?,
+            user_op: row.get("user_op")?,
+            user_op_hash: row.get("user_op_hash")?,
+            status: row.get("status")?,
+            attempts: row.get("attempts")?,
+            last_error: row.get("last_error")?,
+            updated_at: row.get("updated_at")?,
+            valid_after: row.get("valid_after")?,
+            valid_until: row.get("valid_until")?,
+        })
+    }
+
+    fn parse(self) -> Result<Entry> {
+        let bad = |what: &str| queue_err(format!("entry {} has a malformed {what}", self.id));
+        Ok(Entry {
+            id: self.id,
+            chain_id: self.chain_id,
+            entry_point: self.entry_point.parse().map_err(|_| bad("entry point"))?,
+            account: self.account.clone(),
+            user_op: serde_json::from_str(&self.user_op).map_err(|_| bad("UserOperation"))?,
+            user_op_hash: self.user_op_hash.parse().map_err(|_| bad("userOpHash"))?,
+            status: self.status.parse()?,
+            attempts: self.attempts,
+            last_error: self.last_error.clone(),
+            updated_at: self.updated_at,
+            window: match (self.valid_after, self.valid_until) {
+                (Some(after), Some(until)) => {
+                    Some(ValidityWindow::new(after, until).map_err(|_| bad("validity window"))?)
+                }
+                _ => None,
+            },
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Queue {
+    conn: Connection,
+}
+
+impl Queue {
+    /// `$PQ_WALLET_QUEUE`, else `queue.sqlite` in the keystore root.
+    pub fn default_path() -> Result<PathBuf> {
+        match std::env::var_os(QUEUE_ENV) {
+            Some(path) => Ok(PathBuf::from(path)),
+            None => Ok(Keystore::default_root()?.join("queue.sqlite")),
+        }
+    }
+
+    pub fn open_default() -> Result<Self> {
+        let path = Self::default_path()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| WalletError::Io(dir.display().to_string(), e))?;
+        }
+        Self::open(&path)
+    }
+
+    /// Open (creating if needed) the queue at `path`.
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn =
+            Connection::open(path).map_err(|e| queue_err(format!("{}: {e}", path.display())))?;
+        Self::init(conn)
+    }
+
+    /// A queue that lives only as long as the value, for tests.
+    pub fn in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory().map_err(queue_err)?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA).map_err(queue_err)?;
+        let mut stmt = conn
+            .prepare("SELECT name FROM pragma_table_info('ops')")
+            .map_err(queue_err)?;
+        let columns = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(queue_err)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(queue_err)?;
+        drop(stmt);
+        for (name, kind) in ADDED_COLUMNS {
+            if !columns.iter().any(|c| c == name) {
+                conn.execute_batch(&format!("ALTER TABLE ops ADD COLUMN {name} {kind}"))
+                    .map_err(queue_err)?;
+            }
+        }
+        Ok(Queue { conn })
+    }
+
+    /// Add a signed op as `pending`, to be sent once `window` (if any)
+    /// opens. Fails if another op with the same sender and nonce is queued
+    /// and not `failed`.
+    pub fn enqueue(
+        &self,
+        chain_id: u64,
+        entry_point: Address,
+        account: Option<&str>,
+        op: &PackedUserOperation,
+        user_op_hash: B256,
+        window: Option<ValidityWindow>,
+    ) -> Result<Entry> {
+        let user_op = serde_json::to_string(op).expect("UserOperations serialize");
+        let inserted = self.conn.execute(
+            "INSERT INTO ops (chain_id, entry_point, sender, nonce, account, user_op,
+                              user_op_hash, status, updated_at, valid_after, valid_until)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 'pending', ?8, ?9, ?10)",
+            params![
+                chain_id,
+                entry_point.to_string(),
+                op.sender.to_string(),
+                format!("{:#x}", op.nonce),
+                account,
+                user_op,
+                user_op_hash.to_string(),
+                crate::attestation::now(),
+                window.map(|w| w.valid_after),
+                window.map(|w| w.valid_until),
+            ],
+        );
+        match inserted {
+            Ok(_) => self.get(self.conn.last_insert_rowid()),
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                Err(queue_err(format!(
+                    "{} already has an op queued with nonce {}",
+                    op.sender, op.nonce
+                )))
+            }
+            Err(e) => Err(queue_err(e)),
+        }
+    }
+
+    pub fn get(&self, id: i64) -> Result<Entry> {
+        let row = self
+            .conn
+            .query_row("SELECT * FROM ops WHERE id = ?1", [id], Raw::from_row)
+            .optional()
+            .map_err(queue_err)?;
+        row.ok_or_else(|| queue_err(format!("no entry {id}")))?
+            .parse()
+    }
+
+    /// The most recent entry with this userOpHash.
+    pub fn find_by_hash(&self, user_op_hash: B256) -> Result<Option<Entry>> {
+        self.conn
+            .query_row(
+                "SELECT * FROM ops WHERE user_op_hash = ?1 ORDER BY id DESC LIMIT 1",
+                [user_op_hash.to_string()],
+                Raw::from_row,
+            )
+            .optional()
+            .map_err(queue_err)?
+            .map(Raw::parse)
+            .transpose()
+    }
+
+    /// Entries in any of `statuses` (all if empty), oldest first.
+    pub fn list(&self, statuses: &[Status]) -> Result<Vec<Entry>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM ops ORDER BY id")
+            .map_err(queue_err)?;
+        let rows = stmt.query_map([], Raw::from_row).map_err(queue_err)?;
+        let mut out = Vec::new();
+        for row in rows {
+            let entry = row.map_err(queue_err)?.parse()?;
+            if statuses.is_empty() || statuses.contains(&entry.status) {
+                out.push(entry);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Move entry `id` to `status`, recording `error` (or clearing it).
+    pub fn set_status(&self, id: i64, status: Status, error: Option<&str>) -> Result<()> {
+        self.update(
+            "UPDATE ops SET status = ?2, last_error = ?3, updated_at = ?4 WHERE id = ?1",
+            params![id, status.as_str(), error, crate::attestation::now()],
+        )
+    }
+
+    /// Count a send that failed without an answer from the bundler; the
+    /// entry stays where it was.
+    pub fn record_attempt(&self, id: i64, error: &str) -> Result<()> {
+        self.update(
+            "UPDATE ops SET attempts = attempts + 1, last_error = ?2, updated_at = ?3
+             WHERE id = ?1",
+            params![id, error, crate::attestation::now()],
+        )
+    }
+
+    /// Swap in a re-signed op for the same sender and nonce.
+    pub fn replace_op(&self, id: i64, op: &PackedUserOperation, user_op_hash: B256) -> Result<()> {
+        let current = self.get(id)?;
+        if (op.sender, op.nonce) != (current.user_op.sender, current.user_op.nonce) {
+            return Err(queue_err(format!(
+                "entry {id} is {} nonce {}; a replacement must keep both",
+                current.user_op.sender, current.user_op.nonce
+            )));
+        }
+        let user_op = serde_json::to_string(op).expect("UserOperations serialize");
+        self.update(
+            "UPDATE ops SET user_op = ?2, user_op_hash = ?3, updated_at = ?4 WHERE id = ?1",
+            params![
+                id,
+                user_op,
+                user_op_hash.to_string(),
+                crate::attestation::now()
+            ],
+        )
+    }
+
+    fn update(&self, sql: &str, params: impl rusqlite::Params) -> Result<()> {
+        match self.conn.execute(sql, params).map_err(queue_err)? {
+            0 => Err(queue_err("no such entry")),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether a bundler refused an op over its fees, so that re-pricing and
+/// re-signing it may help.
+pub fn is_fee_rejection(err: &WalletError) -> bool {
+    let WalletError::RpcError { message, .. } = err else {
+        return false;
+    };
+    let message = message.to_ascii_lowercase();
+    ["fee", "underpriced", "gas price"]
+        .iter()
+        .any(|needle| message.contains(needle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::U256;
+
+    fn op(nonce: u64) -> PackedUserOperation {
+        PackedUserOperation {
+            sender: Address::repeat_byte(0x11),
+            nonce: U256::from(nonce),
+            init_code: vec![],
+            call_data: vec![],
+            account_gas_limits: B256::ZERO,
+            pre_verification_gas: U256::from(50_000),
+            gas_fees: B256::ZERO,
+            paymaster_and_data: vec![],
+            signature: vec![7; 3309],
+        }
+    }
+
+    #[test]
+    fn one_live_op_per_nonce() {
+        let queue = Queue::in_memory().unwrap();
+        let ep = crate::userop::ENTRY_POINT_V07;
+        let first = queue
+            .enqueue(1, ep, Some("alice"), &op(0), B256::repeat_byte(1), None)
+            .unwrap();
+        assert_eq!(first.status, Status::Pending);
+        assert_eq!(first.user_op, op(0));
+        assert!(
+            queue
+                .enqueue(1, ep, None, &op(0), B256::repeat_byte(2), None)
+                .is_err()
+        );
+        // Another chain or nonce is a different op.
+        queue
+            .enqueue(2, ep, None, &op(0), B256::repeat_byte(3), None)
+            .unwrap();
+        queue
+            .enqueue(1, ep, None, &op(1), B256::repeat_byte(4), None)
+            .unwrap();
+
+        queue
+            .record_attempt(first.id, "connection refused")
+            .unwrap();
+        queue.set_status(first.id, Status::Submitted, None).unwrap();
+        let submitted = queue.list(&[Status::Submitted]).unwrap();
+        assert_eq!(submitted.len(), 1);
+        assert_eq!(
+            (submitted[0].attempts, submitted[0].last_error.as_deref()),
+            (1, None)
+        );
+
+        let mut bumped = op(0);
+        bumped.gas_fees = B256::repeat_byte(9);
+        queue
+            .replace_op(first.id, &bumped, B256::repeat_byte(5))
+            .unwrap();
+        let found = queue.find_by_hash(B256::repeat_byte(5)).unwrap().unwrap();
+        assert_eq!(
+            (found.id, found.user_op.gas_fees),
+            (first.id, bumped.gas_fees)
+        );
+        assert!(queue.replace_op(first.id, &op(1), B256::ZERO).is_err());
+
+        // Once the op has failed, its nonce can be queued again.
+        queue
+            .set_status(first.id, Status::Failed, Some("reverted"))
+            .unwrap();
+        queue
+            .enqueue(1, ep, None, &op(0), B256::repeat_byte(6), None)
+            .unwrap();
+        assert_eq!(queue.list(&[]).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn time_locked_ops_are_scheduled_and_old_queues_gain_windows() {
+        let path = std::env::temp_dir().join(format!("pq-queue-{}.sqlite", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        // A queue from before validity windows were stored.
+        let old = SCHEMA.replace(",\n    valid_after  INTEGER,\n    valid_until  INTEGER", "");
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch(&old)
+            .unwrap();
+
+        let queue = Queue::open(&path).unwrap();
+        let ep = crate::userop::ENTRY_POINT_V07;
+        let window = ValidityWindow::new(1_000, 2_000).unwrap();
+        let locked = queue
+            .enqueue(1, ep, None, &op(0), B256::repeat_byte(1), Some(window))
+            .unwrap();
+        let plain = queue
+            .enqueue(1, ep, None, &op(1), B256::repeat_byte(2), None)
+            .unwrap();
+        assert_eq!(queue.get(locked.id).unwrap().window, Some(window));
+        assert!(locked.is_scheduled(999) && !locked.is_scheduled(1_000));
+        assert!(!plain.is_scheduled(0));
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,404 @@
+//! Deployment registry: the factory, validator and EntryPoint addresses of
+//! each release on each chain, pinned with their code hashes.
+//!
+//! `cli/registry.json` in the crate is the [`Registry`] for the release it
+//! ships with:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "release": "0.1.0",
+//!   "chains": {
+//!     "421614": {
+//!       "entryPoint": { "address": "0x0000000071727De22E5E9d8BAf0edAc6f37da032", "codeHash": "0x…" },
+//!       "validator":  { "address": "0x…", "codeHash": "0x…" },
+//!       "factory":    { "address": "0x…", "codeHash": "0x…" }
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! `scripts/release-build.sh` signs it with the release key, like the
+//! binaries' manifest: the signature covers `DOMAIN` followed by the RFC
+//! 8785 encoding of the registry. Given a signed registry and the pinned
+//! release key fingerprints, `pq-deploy deploy` and `pq-module install`
+//! check with `eth_getCode` that every contract the registry pins for the
+//! chain holds the code it should before building anything, so an op or a
+//! deployment never points at a spoofed or outdated contract. The code
+//! hash is `keccak256` of the runtime code, what `EXTCODEHASH` returns.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use alloy_primitives::{Address, B256, Bytes, U64, keccak256};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+use crate::canonical_json;
+use crate::error::{Result, WalletError};
+use crate::keys::{self, SEED_LEN};
+use crate::message::HashAlg;
+use crate::rpc::Transport;
+use crate::signer::PqSigner;
+
+pub const REGISTRY_VERSION: u32 = 1;
+/// Domain separator for the signed registry.
+pub const DOMAIN: &[u8] = b"pq-wallet/registry/v1";
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::Registry(reason.into())
+}
+
+/// A contract and the hash of the runtime code it must have.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Pinned {
+    pub address: Address,
+    pub code_hash: B256,
+}
+
+/// One chain's contracts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Deployment {
+    pub entry_point: Pinned,
+    pub validator: Pinned,
+    /// Account factory, on chains where accounts are deployed through one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub factory: Option<Pinned>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    EntryPoint,
+    Validator,
+    Factory,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::EntryPoint => "EntryPoint",
+            Role::Validator => "validator",
+            Role::Factory => "factory",
+        })
+    }
+}
+
+impl Deployment {
+    pub fn contracts(&self) -> impl Iterator<Item = (Role, Pinned)> {
+        [
+            Some((Role::EntryPoint, self.entry_point)),
+            Some((Role::Validator, self.validator)),
+            self.factory.map(|f| (Role::Factory, f)),
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Registry {
+    pub version: u32,
+    /// Release the contracts belong to, e.g. `0.1.0`.
+    pub release: String,
+    /// By chain ID.
+    pub chains: BTreeMap<u64, Deployment>,
+}
+
+impl Registry {
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        let registry: Registry =
+            serde_json::from_slice(bytes).map_err(|e| err(format!("invalid registry: {e}")))?;
+        if registry.version != REGISTRY_VERSION {
+            return Err(err(format!("unsupported version {}", registry.version)));
+        }
+        Ok(registry)
+    }
+
+    /// The 32-byte digest the release key signs.
+    pub fn statement(&self) -> Result<B256> {
+        let mut preimage = DOMAIN.to_vec();
+        preimage.extend_from_slice(&canonical_json::to_vec(self)?);
+        Ok(keccak256(preimage))
+    }
+
+    pub fn sign(self, release_seed: &[u8; SEED_LEN]) -> Result<SignedRegistry> {
+        let signer = PqSigner::from_seed(release_seed);
+        let signature = signer.sign(self.statement()?.as_slice());
+        Ok(SignedRegistry {
+            release_key: Bytes::copy_from_slice(&signer.verifying_key().encode()),
+            signature: Bytes::copy_from_slice(&signature.encode()),
+            registry: self,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedRegistry {
+    #[serde(flatten)]
+    pub registry: Registry,
+    /// The release key's ML-DSA-65 public key.
+    pub release_key: Bytes,
+    /// Signature over [`Registry::statement`].
+    pub signature: Bytes,
+}
+
+/// What a pinned contract's address holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CodeStatus {
+    Ok,
+    Mismatch,
+    /// No code at the address.
+    Missing,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeCheck {
+    pub role: Role,
+    pub address: Address,
+    pub expected: B256,
+    /// keccak256 of the code found, if there was any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub found: Option<B256>,
+    pub status: CodeStatus,
+}
+
+impl fmt::Display for CodeCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.status, self.found) {
+            (CodeStatus::Ok, _) => write!(f, "{} {}: OK", self.role, self.address),
+            (CodeStatus::Missing, _) => write!(f, "{} {}: no code", self.role, self.address),
+            (CodeStatus::Mismatch, found) => write!(
+                f,
+                "{} {}: code hash {} is not the registry's {}",
+                self.role,
+                self.address,
+                found.unwrap_or_default(),
+                self.expected
+            ),
+        }
+    }
+}
+
+/// keccak256 of the code at `address`, or `None` if it has none.
+pub fn code_hash<T: Transport>(rpc: &T, address: Address) -> Result<Option<B256>> {
+    let code: Bytes = rpc.call("eth_getCode", serde_json::json!([address, "latest"]))?;
+    Ok((!code.is_empty()).then(|| keccak256(&code)))
+}
+
+/// Fail unless every check passed. `undeployed` may still be missing: it
+/// is the contract about to be deployed.
+pub fn require(checks: &[CodeCheck], undeployed: Option<Address>) -> Result<()> {
+    let failed: Vec<String> = checks
+        .iter()
+        .filter(|c| match c.status {
+            CodeStatus::Ok => false,
+            CodeStatus::Missing => Some(c.address) != undeployed,
+            CodeStatus::Mismatch => true,
+        })
+        .map(CodeCheck::to_string)
+        .collect();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(err(failed.join("; ")))
+    }
+}
+
+impl SignedRegistry {
+    /// serde cannot read `chains`' integer keys through a flattened
+    /// struct, so the signature fields are split off first.
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        let invalid = |e: serde_json::Error| err(format!("invalid registry: {e}"));
+        let mut value: serde_json::Value = serde_json::from_slice(bytes).map_err(invalid)?;
+        let mut take = |field: &str| -> Result<Bytes> {
+            let found = value
+                .as_object_mut()
+                .and_then(|fields| fields.remove(field))
+                .ok_or_else(|| err(format!("invalid registry: missing {field}")))?;
+            serde_json::from_value(found).map_err(invalid)
+        };
+        let release_key = take("releaseKey")?;
+        let signature = take("signature")?;
+        let registry = Registry::from_json(value.to_string().as_bytes())?;
+        Ok(SignedRegistry {
+            registry,
+            release_key,
+            signature,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("registry serializes")
+    }
+
+    /// Fingerprint of the release key, as `pq-keygen` prints it.
+    pub fn release_key_fingerprint(&self) -> B256 {
+        keys::fingerprint(&self.release_key, HashAlg::Keccak256)
+    }
+
+    /// Check the registry comes from one of `trusted_keys` (release key
+    /// fingerprints) and carries a valid signature.
+    pub fn verify(&self, trusted_keys: &[B256]) -> Result<()> {
+        if self.registry.version != REGISTRY_VERSION {
+            return Err(err(format!(
+                "unsupported version {}",
+                self.registry.version
+            )));
+        }
+        let key = self.release_key_fingerprint();
+        if !trusted_keys.contains(&key) {
+            return Err(err(format!("release key {key} is not trusted")));
+        }
+        if !Backend::RustCrypto.verify(
+            &self.release_key,
+            self.registry.statement()?.as_slice(),
+            &[],
+            &self.signature,
+        ) {
+            return Err(err("signature does not verify"));
+        }
+        Ok(())
+    }
+
+    pub fn deployment(&self, chain_id: u64) -> Result<&Deployment> {
+        self.registry.chains.get(&chain_id).ok_or_else(|| {
+            err(format!(
+                "release {} has no deployment on chain {chain_id}",
+                self.registry.release
+            ))
+        })
+    }
+
+    /// Compare the code of every contract pinned for `chain_id` with what
+    /// the node behind `rpc`, which must be on that chain, holds.
+    pub fn check_chain<T: Transport>(&self, rpc: &T, chain_id: u64) -> Result<Vec<CodeCheck>> {
+        let deployment = self.deployment(chain_id)?;
+        let node_chain: U64 = rpc.call("eth_chainId", serde_json::json!([]))?;
+        if node_chain != U64::from(chain_id) {
+            return Err(err(format!(
+                "node is on chain {node_chain}, not {chain_id}"
+            )));
+        }
+        deployment
+            .contracts()
+            .map(|(role, pinned)| {
+                let found = code_hash(rpc, pinned.address)?;
+                let status = match found {
+                    None => CodeStatus::Missing,
+                    Some(hash) if hash == pinned.code_hash => CodeStatus::Ok,
+                    Some(_) => CodeStatus::Mismatch,
+                };
+                Ok(CodeCheck {
+                    role,
+                    address: pinned.address,
+                    expected: pinned.code_hash,
+                    found,
+                    status,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers `eth_chainId` and `eth_getCode` from a fixed map.
+    struct Node {
+        chain_id: u64,
+        code: BTreeMap<Address, Vec<u8>>,
+    }
+
+    impl Transport for Node {
+        fn request(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+            Ok(match method {
+                "eth_chainId" => serde_json::json!(U64::from(self.chain_id)),
+                "eth_getCode" => {
+                    let address: Address = serde_json::from_value(params[0].clone()).unwrap();
+                    let code = self.code.get(&address).cloned().unwrap_or_default();
+                    serde_json::json!(Bytes::from(code))
+                }
+                other => panic!("unexpected {other}"),
+            })
+        }
+    }
+
+    fn pin(byte: u8, code: &[u8]) -> Pinned {
+        Pinned {
+            address: Address::repeat_byte(byte),
+            code_hash: keccak256(code),
+        }
+    }
+
+    #[test]
+    fn signed_registries_pin_on_chain_code() {
+        let deployment = Deployment {
+            entry_point: pin(1, b"entry point"),
+            validator: pin(2, b"validator"),
+            factory: Some(pin(3, b"factory")),
+        };
+        let registry = Registry {
+            version: REGISTRY_VERSION,
+            release: "0.1.0".into(),
+            chains: [(31337, deployment)].into(),
+        };
+        let bytes = serde_json::to_vec(&registry).unwrap();
+        assert_eq!(Registry::from_json(&bytes).unwrap(), registry);
+        let signed = registry.sign(&[4u8; SEED_LEN]).unwrap();
+        let read = SignedRegistry::from_json(signed.to_json().as_bytes()).unwrap();
+        let trusted = [read.release_key_fingerprint()];
+        read.verify(&trusted).unwrap();
+        assert!(read.verify(&[B256::ZERO]).is_err());
+        let mut forged = read.clone();
+        forged
+            .registry
+            .chains
+            .get_mut(&31337)
+            .unwrap()
+            .validator
+            .address = Address::ZERO;
+        assert!(forged.verify(&trusted).is_err());
+
+        let mut node = Node {
+            chain_id: 31337,
+            code: [
+                (Address::repeat_byte(1), b"entry point".to_vec()),
+                (Address::repeat_byte(2), b"old validator".to_vec()),
+            ]
+            .into(),
+        };
+        let checks = read.check_chain(&node, 31337).unwrap();
+        let statuses: Vec<_> = checks.iter().map(|c| (c.role, c.status)).collect();
+        assert_eq!(
+            statuses,
+            [
+                (Role::EntryPoint, CodeStatus::Ok),
+                (Role::Validator, CodeStatus::Mismatch),
+                (Role::Factory, CodeStatus::Missing),
+            ]
+        );
+        assert!(require(&checks, Some(Address::repeat_byte(3))).is_err());
+
+        node.code
+            .insert(Address::repeat_byte(2), b"validator".to_vec());
+        let checks = read.check_chain(&node, 31337).unwrap();
+        // The factory is about to be deployed.
+        require(&checks, Some(Address::repeat_byte(3))).unwrap();
+        assert!(require(&checks, None).is_err());
+        assert!(read.check_chain(&node, 1).is_err());
+        node.chain_id = 1;
+        assert!(read.check_chain(&node, 31337).is_err());
+
+        // The registry shipped with the crate is this release's.
+        let shipped = Registry::from_json(include_bytes!("../registry.json")).unwrap();
+        assert_eq!(shipped.release, env!("CARGO_PKG_VERSION"));
+    }
+}
@@ -0,0 +1,326 @@
+//! Release manifests: the SHA-256 of every shipped binary, signed with an
+//! ML-DSA-65 release key.
+//!
+//! `pq-release attest` hashes the binaries of a release build into a
+//! [`Manifest`] and signs it. `pq-release verify` is what users run on a
+//! download. It checks the signature against the release key fingerprints
+//! they pinned, then checks their files against the manifest. The
+//! signature covers `DOMAIN` followed by the RFC 8785 encoding of the
+//! manifest. A manifest holds no timestamp or host detail, so builders who
+//! reproduce the same binaries (`scripts/release-build.sh`) produce the same
+//! manifest and can each sign it.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use alloy_primitives::{B256, Bytes, keccak256};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::backend::Backend;
+use crate::canonical_json;
+use crate::error::{Result, WalletError};
+use crate::keys::{self, SEED_LEN};
+use crate::message::HashAlg;
+use crate::signer::PqSigner;
+
+pub const MANIFEST_VERSION: u32 = 1;
+/// Domain separator for the signed manifest.
+pub const DOMAIN: &[u8] = b"pq-wallet/release/v1";
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::Release(reason.into())
+}
+
+/// One shipped file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Artifact {
+    /// File name, without its directory.
+    pub name: String,
+    pub size: u64,
+    /// What `sha256sum` prints for the file.
+    pub sha256: B256,
+}
+
+impl Artifact {
+    /// Hash the file at `path`, naming it by its file name.
+    pub fn hash(path: &Path) -> Result<Self> {
+        let io_err = |e| WalletError::Io(path.display().to_string(), e);
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| err(format!("{}: no usable file name", path.display())))?;
+        let mut file = File::open(path).map_err(io_err)?;
+        let mut hasher = Sha256::new();
+        let mut buf = [0u8; 8192];
+        let mut size = 0u64;
+        loop {
+            let n = file.read(&mut buf).map_err(io_err)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            size += n as u64;
+        }
+        Ok(Artifact {
+            name: name.to_string(),
+            size,
+            sha256: B256::from(<[u8; 32]>::from(hasher.finalize())),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub version: u32,
+    /// Release version, e.g. `0.1.0`.
+    pub release: String,
+    /// Git commit the binaries were built from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// Target triple, e.g. `x86_64-unknown-linux-gnu`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Sorted by name.
+    pub artifacts: Vec<Artifact>,
+}
+
+impl Manifest {
+    /// A manifest over `artifacts`, which must have distinct names.
+    pub fn new(
+        release: String,
+        commit: Option<String>,
+        target: Option<String>,
+        mut artifacts: Vec<Artifact>,
+    ) -> Result<Self> {
+        if artifacts.is_empty() {
+            return Err(err("no artifacts"));
+        }
+        artifacts.sort_by(|a, b| a.name.cmp(&b.name));
+        if let Some(pair) = artifacts.windows(2).find(|w| w[0].name == w[1].name) {
+            return Err(err(format!("two artifacts are named {}", pair[0].name)));
+        }
+        Ok(Manifest {
+            version: MANIFEST_VERSION,
+            release,
+            commit,
+            target,
+            artifacts,
+        })
+    }
+
+    /// The 32-byte digest the release key signs.
+    pub fn statement(&self) -> Result<B256> {
+        let mut preimage = DOMAIN.to_vec();
+        preimage.extend_from_slice(&canonical_json::to_vec(self)?);
+        Ok(keccak256(preimage))
+    }
+
+    pub fn artifact(&self, name: &str) -> Option<&Artifact> {
+        self.artifacts.iter().find(|a| a.name == name)
+    }
+
+    pub fn sign(self, release_seed: &[u8; SEED_LEN]) -> Result<SignedManifest> {
+        let signer = PqSigner::from_seed(release_seed);
+        let signature = signer.sign(self.statement()?.as_slice());
+        Ok(SignedManifest {
+            release_key: Bytes::copy_from_slice(&signer.verifying_key().encode()),
+            signature: Bytes::copy_from_slice(&signature.encode()),
+            manifest: self,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedManifest {
+    #[serde(flatten)]
+    pub manifest: Manifest,
+    /// The release key's ML-DSA-65 public key.
+    pub release_key: Bytes,
+    /// Signature over [`Manifest::statement`].
+    pub signature: Bytes,
+}
+
+/// How a file compares with the manifest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileStatus {
+    Ok,
+    Mismatch,
+    /// Listed, but not in the checked directory.
+    Missing,
+    /// Not in the manifest.
+    Unlisted,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FileCheck {
+    pub name: String,
+    pub status: FileStatus,
+    /// The file's SHA-256, when it was read.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<B256>,
+}
+
+impl SignedManifest {
+    /// Fingerprint of the release key, as `pq-keygen` prints it; this is
+    /// what users pin.
+    pub fn release_key_fingerprint(&self) -> B256 {
+        keys::fingerprint(&self.release_key, HashAlg::Keccak256)
+    }
+
+    /// Check the manifest comes from one of `trusted_keys` (release key
+    /// fingerprints) and carries a valid signature.
+    pub fn verify(&self, trusted_keys: &[B256]) -> Result<()> {
+        if self.manifest.version != MANIFEST_VERSION {
+            return Err(err(format!(
+                "unsupported version {}",
+                self.manifest.version
+            )));
+        }
+        let key = self.release_key_fingerprint();
+        if !trusted_keys.contains(&key) {
+            return Err(err(format!("release key {key} is not trusted")));
+        }
+        if !Backend::RustCrypto.verify(
+            &self.release_key,
+            self.manifest.statement()?.as_slice(),
+            &[],
+            &self.signature,
+        ) {
+            return Err(err("signature does not verify"));
+        }
+        Ok(())
+    }
+
+    /// Compare the file at `path` with the manifest entry of the same name.
+    pub fn check_file(&self, path: &Path) -> Result<FileCheck> {
+        let found = Artifact::hash(path)?;
+        let status = match self.manifest.artifact(&found.name) {
+            None => FileStatus::Unlisted,
+            Some(listed) if *listed == found => FileStatus::Ok,
+            Some(_) => FileStatus::Mismatch,
+        };
+        Ok(FileCheck {
+            name: found.name,
+            status,
+            sha256: Some(found.sha256),
+        })
+    }
+
+    /// Check every listed artifact in `dir`.
+    pub fn check_dir(&self, dir: &Path) -> Result<Vec<FileCheck>> {
+        self.manifest
+            .artifacts
+            .iter()
+            .map(|listed| {
+                let path = dir.join(&listed.name);
+                if path.exists() {
+                    self.check_file(&path)
+                } else {
+                    Ok(FileCheck {
+                        name: listed.name.clone(),
+                        status: FileStatus::Missing,
+                        sha256: None,
+                    })
+                }
+            })
+            .collect()
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).map_err(|e| err(format!("invalid manifest: {e}")))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("manifest serializes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn artifact(name: &str, byte: u8) -> Artifact {
+        Artifact {
+            name: name.to_string(),
+            size: 1,
+            sha256: B256::repeat_byte(byte),
+        }
+    }
+
+    #[test]
+    fn signs_and_verifies_a_manifest() {
+        let seed = [4u8; SEED_LEN];
+        let manifest = Manifest::new(
+            "0.1.0".into(),
+            Some("abc123".into()),
+            None,
+            vec![artifact("pq-verify", 2), artifact("pq-sign", 1)],
+        )
+        .unwrap();
+        assert_eq!(manifest.artifacts[0].name, "pq-sign");
+        let signed = manifest.sign(&seed).unwrap();
+        let trusted = [signed.release_key_fingerprint()];
+        let read = SignedManifest::from_json(signed.to_json().as_bytes()).unwrap();
+        assert_eq!(read, signed);
+        read.verify(&trusted).unwrap();
+        assert!(read.verify(&[B256::ZERO]).is_err());
+
+        let mut forged = read.clone();
+        forged.manifest.artifacts[1].sha256 = B256::repeat_byte(9);
+        assert!(forged.verify(&trusted).is_err());
+
+        assert!(Manifest::new("0.1.0".into(), None, None, vec![]).is_err());
+        let twice = vec![artifact("pq-sign", 1), artifact("pq-sign", 2)];
+        assert!(Manifest::new("0.1.0".into(), None, None, twice).is_err());
+    }
+
+    #[test]
+    fn checks_files_against_the_manifest() {
+        let dir = std::env::temp_dir().join(format!("pq-release-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("pq-sign"), b"sign").unwrap();
+        std::fs::write(dir.join("pq-verify"), b"verify").unwrap();
+        let artifacts = vec![
+            Artifact::hash(&dir.join("pq-sign")).unwrap(),
+            Artifact::hash(&dir.join("pq-verify")).unwrap(),
+            artifact("pq-keygen", 3),
+        ];
+        // sha256("sign")
+        assert_eq!(
+            artifacts[0].sha256.to_string(),
+            "0x0b6f395ca14ac202374d5cff678b71157d0bef7e00c3045e9b80aab4ffb85276"
+        );
+        let signed = Manifest::new("0.1.0".into(), None, None, artifacts)
+            .unwrap()
+            .sign(&[4u8; SEED_LEN])
+            .unwrap();
+
+        std::fs::write(dir.join("pq-verify"), b"tampered").unwrap();
+        std::fs::write(dir.join("extra"), b"x").unwrap();
+        let statuses: Vec<_> = signed
+            .check_dir(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.name, c.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("pq-keygen".to_string(), FileStatus::Missing),
+                ("pq-sign".to_string(), FileStatus::Ok),
+                ("pq-verify".to_string(), FileStatus::Mismatch),
+            ]
+        );
+        assert_eq!(
+            signed.check_file(&dir.join("extra")).unwrap().status,
+            FileStatus::Unlisted
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
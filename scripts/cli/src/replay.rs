@@ -0,0 +1,386 @@
+//! Re-checking historical bundles off chain (`pq-replay`).
+//!
+//! [`replay`] fetches a `handleOps` transaction and its receipt from an
+//! archive node, re-derives each op's userOpHash, and re-verifies its
+//! signature with this library against the ML-DSA-65 key the
+//! `PQValidatorModule` held for the sender in the block before. The key is
+//! read straight from the module's storage, so the check does not trust the
+//! deployed verifier for anything. An op is a divergence when:
+//!
+//! - the EntryPoint logged a different userOpHash for it, or none at all in
+//!   a bundle that succeeded;
+//! - it was included but its signature does not verify here.
+//!
+//! A bundle that reverted has no on-chain verdict to compare with; its ops
+//! are only checked here. Senders that had no key in the module are
+//! reported and skipped. A
+//! delegated (V3) signature is checked as the module checks it: the grant
+//! must cover the sender and chain, the owner key must sign the grant and
+//! the robot key the userOpHash. Revocations and validity windows are the
+//! module's and EntryPoint's to enforce and are not replayed.
+
+use alloy_primitives::{Address, B256, Bytes, U64, U256, keccak256};
+use alloy_sol_types::{SolCall, SolEvent, SolValue};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+use crate::bundler::{Log, UserOperationEvent};
+use crate::envelope::{ENVELOPE_V3, Envelope};
+use crate::error::{Result, WalletError};
+use crate::keys::{self, PUBLIC_KEY_LEN, SIGNATURE_LEN};
+use crate::message::HashAlg;
+use crate::rpc::Transport;
+use crate::simulate::abi;
+use crate::userop::{self, PackedUserOperation};
+
+/// Storage slot of `PQValidatorModule.publicKeys`.
+pub const PUBLIC_KEYS_SLOT: u64 = 0;
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::Replay(reason.into())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Transaction {
+    to: Option<Address>,
+    input: Bytes,
+    block_number: Option<U64>,
+}
+
+#[derive(Deserialize)]
+struct Receipt {
+    status: U64,
+    logs: Vec<Log>,
+}
+
+/// What the signature check found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verdict {
+    Valid,
+    Invalid,
+    /// A delegated signature, both halves valid.
+    ValidDelegated,
+    /// The sender had no key in the module.
+    NoKey,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpReplay {
+    pub index: usize,
+    pub sender: Address,
+    pub nonce: U256,
+    /// As derived here.
+    pub user_op_hash: B256,
+    /// As the EntryPoint logged it.
+    pub logged_hash: Option<B256>,
+    /// The op's call succeeded, when it was executed.
+    pub success: Option<bool>,
+    /// Keccak fingerprint of the key the module held.
+    pub key: Option<B256>,
+    pub verdict: Verdict,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub divergence: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Replay {
+    pub transaction: B256,
+    pub block_number: u64,
+    pub chain_id: u64,
+    pub entry_point: Address,
+    pub module: Address,
+    pub beneficiary: Address,
+    /// The bundle transaction succeeded.
+    pub included: bool,
+    pub ops: Vec<OpReplay>,
+}
+
+impl Replay {
+    pub fn divergences(&self) -> impl Iterator<Item = &OpReplay> {
+        self.ops.iter().filter(|op| op.divergence.is_some())
+    }
+}
+
+fn word(value: U256) -> B256 {
+    B256::from(value.to_be_bytes::<32>())
+}
+
+/// The `bytes` value the module stored for `account` at `block`, read slot
+/// by slot; `None` when it is empty.
+pub fn committed_key<T: Transport>(
+    node: &T,
+    module: Address,
+    account: Address,
+    block: u64,
+) -> Result<Option<Vec<u8>>> {
+    let read = |slot: B256| -> Result<B256> {
+        node.call(
+            "eth_getStorageAt",
+            serde_json::json!([module, slot, format!("{block:#x}")]),
+        )
+    };
+    let slot = keccak256((account, U256::from(PUBLIC_KEYS_SLOT)).abi_encode());
+    let head = read(slot)?;
+    let encoded = U256::from_be_bytes(head.0);
+    if encoded.is_zero() {
+        return Ok(None);
+    }
+    // Solidity's layout: up to 31 bytes inline with length * 2 in the last
+    // byte, otherwise length * 2 + 1 here and the bytes from keccak(slot).
+    if !encoded.bit(0) {
+        let len = usize::from(head[31] / 2);
+        return Ok(Some(head[..len].to_vec()));
+    }
+    let len: U256 = encoded >> 1usize;
+    if len > U256::from(PUBLIC_KEY_LEN) {
+        return Err(err(format!("{account}'s stored key is {len} bytes long")));
+    }
+    let len = len.to::<usize>();
+    let base = U256::from_be_bytes(keccak256(slot).0);
+    let mut bytes = Vec::with_capacity(len.next_multiple_of(32));
+    for i in 0..len.div_ceil(32) {
+        let slot = word(base + U256::from(i));
+        bytes.extend_from_slice(read(slot)?.as_slice());
+    }
+    bytes.truncate(len);
+    Ok(Some(bytes))
+}
+
+/// Check `signature` over `hash` as `PQValidatorModule.validateUserOp` does.
+pub fn verify_signature(
+    key: &[u8],
+    hash: B256,
+    signature: &[u8],
+    sender: Address,
+    chain_id: u64,
+) -> Verdict {
+    if signature.len() != SIGNATURE_LEN && signature.first() == Some(&ENVELOPE_V3) {
+        let Ok(Envelope::V3(env)) = Envelope::decode(signature) else {
+            return Verdict::Invalid;
+        };
+        let d = &env.delegation;
+        let covered = d.account == sender && (d.chain_id == 0 || d.chain_id == chain_id);
+        let valid = covered
+            && Backend::RustCrypto.verify(key, d.hash().as_slice(), &[], &env.owner_signature)
+            && Backend::RustCrypto.verify(
+                &env.pq.public_key,
+                hash.as_slice(),
+                &[],
+                &env.pq.signature,
+            );
+        return if valid {
+            Verdict::ValidDelegated
+        } else {
+            Verdict::Invalid
+        };
+    }
+    if Backend::RustCrypto.verify(key, hash.as_slice(), &[], signature) {
+        Verdict::Valid
+    } else {
+        Verdict::Invalid
+    }
+}
+
+/// Replay the bundle `tx`, with keys from `module`.
+pub fn replay<T: Transport>(node: &T, tx: B256, module: Address) -> Result<Replay> {
+    let transaction: Option<Transaction> =
+        node.call("eth_getTransactionByHash", serde_json::json!([tx]))?;
+    let transaction = transaction.ok_or_else(|| err(format!("no transaction {tx}")))?;
+    let entry_point = transaction
+        .to
+        .ok_or_else(|| err(format!("{tx} creates a contract")))?;
+    let block_number = transaction
+        .block_number
+        .ok_or_else(|| err(format!("{tx} is still pending")))?
+        .to::<u64>();
+    let call = abi::handleOpsCall::abi_decode(&transaction.input)
+        .map_err(|e| err(format!("{tx} is not a handleOps call: {e}")))?;
+    let receipt: Option<Receipt> =
+        node.call("eth_getTransactionReceipt", serde_json::json!([tx]))?;
+    let receipt = receipt.ok_or_else(|| err(format!("no receipt for {tx}")))?;
+    let chain_id = node
+        .call::<U64>("eth_chainId", serde_json::json!([]))?
+        .to::<u64>();
+    let included = receipt.status.to::<u64>() == 1;
+
+    let mut logged = Vec::new();
+    for log in receipt.logs.iter().filter(|log| log.address == entry_point) {
+        if log.topics.first() == Some(&UserOperationEvent::SIGNATURE_HASH) {
+            let e = UserOperationEvent::decode_raw_log(log.topics.iter().copied(), &log.data)
+                .map_err(|e| WalletError::Rpc(format!("UserOperationEvent: {e}")))?;
+            logged.push(e);
+        }
+    }
+
+    let state_block = block_number.saturating_sub(1);
+    let mut ops = Vec::with_capacity(call.ops.len());
+    for (index, abi_op) in call.ops.into_iter().enumerate() {
+        let op = PackedUserOperation {
+            sender: abi_op.sender,
+            nonce: abi_op.nonce,
+            init_code: abi_op.initCode.to_vec(),
+            call_data: abi_op.callData.to_vec(),
+            account_gas_limits: abi_op.accountGasLimits,
+            pre_verification_gas: abi_op.preVerificationGas,
+            gas_fees: abi_op.gasFees,
+            paymaster_and_data: abi_op.paymasterAndData.to_vec(),
+            signature: abi_op.signature.to_vec(),
+        };
+        let user_op_hash = userop::compute_user_op_hash(&op, entry_point, U256::from(chain_id));
+        let event = logged
+            .iter()
+            .find(|e| e.sender == op.sender && e.nonce == op.nonce);
+        let key = committed_key(node, module, op.sender, state_block)?;
+        let verdict = match &key {
+            Some(key) => verify_signature(key, user_op_hash, &op.signature, op.sender, chain_id),
+            None => Verdict::NoKey,
+        };
+        let divergence = match event {
+            Some(e) if e.userOpHash != user_op_hash => Some(format!(
+                "the EntryPoint logged userOpHash {}, derived {user_op_hash}",
+                e.userOpHash
+            )),
+            None if included => Some("included in the bundle but not logged".to_string()),
+            _ if verdict == Verdict::Invalid && included => {
+                Some("validated on chain, but the signature does not verify here".to_string())
+            }
+            _ => None,
+        };
+        ops.push(OpReplay {
+            index,
+            sender: op.sender,
+            nonce: op.nonce,
+            user_op_hash,
+            logged_hash: event.map(|e| e.userOpHash),
+            success: event.map(|e| e.success),
+            key: key.map(|key| keys::fingerprint(&key, HashAlg::Keccak256)),
+            verdict,
+            divergence,
+        });
+    }
+    Ok(Replay {
+        transaction: tx,
+        block_number,
+        chain_id,
+        entry_point,
+        module,
+        beneficiary: call.beneficiary,
+        included,
+        ops,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate;
+    use crate::userop::ENTRY_POINT_V07;
+    use serde_json::{Value, json};
+    use std::collections::BTreeMap;
+
+    struct Node {
+        storage: BTreeMap<B256, B256>,
+        input: Vec<u8>,
+        logs: Value,
+    }
+
+    impl Transport for Node {
+        fn request(&self, method: &str, params: Value) -> Result<Value> {
+            match method {
+                "eth_chainId" => Ok(json!("0x66eee")),
+                "eth_getTransactionByHash" => Ok(json!({
+                    "to": ENTRY_POINT_V07,
+                    "input": Bytes::from(self.input.clone()),
+                    "blockNumber": "0x10",
+                })),
+                "eth_getTransactionReceipt" => Ok(json!({ "status": "0x1", "logs": self.logs })),
+                "eth_getStorageAt" => {
+                    assert_eq!(params[2], "0xf");
+                    let slot: B256 = serde_json::from_value(params[1].clone()).unwrap();
+                    Ok(json!(self.storage.get(&slot).copied().unwrap_or_default()))
+                }
+                other => panic!("unexpected {other}"),
+            }
+        }
+    }
+
+    #[test]
+    fn replays_a_bundle_against_the_stored_keys() {
+        let module = Address::repeat_byte(0x77);
+        let seed = [3u8; keys::SEED_LEN];
+        let pk = Backend::RustCrypto.public_key(&seed);
+        let alice = Address::repeat_byte(0xaa);
+        let bob = Address::repeat_byte(0xbb);
+        let carol = Address::repeat_byte(0xcc);
+
+        // Lay out publicKeys[alice] and publicKeys[bob] as Solidity would.
+        let mut storage = BTreeMap::new();
+        for account in [alice, bob] {
+            let slot = keccak256((account, U256::from(PUBLIC_KEYS_SLOT)).abi_encode());
+            storage.insert(slot, word(U256::from(pk.len() * 2 + 1)));
+            let base = U256::from_be_bytes(keccak256(slot).0);
+            for (i, chunk) in pk.chunks(32).enumerate() {
+                storage.insert(word(base + U256::from(i)), B256::right_padding_from(chunk));
+            }
+        }
+
+        let op = |sender: Address| PackedUserOperation {
+            sender,
+            nonce: U256::from(1),
+            init_code: vec![],
+            call_data: vec![],
+            account_gas_limits: B256::ZERO,
+            pre_verification_gas: U256::ZERO,
+            gas_fees: B256::ZERO,
+            paymaster_and_data: vec![],
+            signature: vec![],
+        };
+        let hash = |op: &PackedUserOperation| {
+            userop::compute_user_op_hash(op, ENTRY_POINT_V07, U256::from(421614))
+        };
+        let sign = |mut op: PackedUserOperation| {
+            op.signature = Backend::RustCrypto
+                .sign(&seed, hash(&op).as_slice(), &[], &[0; 32])
+                .unwrap();
+            op
+        };
+        let mut forged = sign(op(bob));
+        forged.signature[0] ^= 1;
+        let ops = [sign(op(alice)), forged, sign(op(carol))];
+        let logs: Vec<Value> = ops
+            .iter()
+            .map(|op| {
+                json!({
+                    "address": ENTRY_POINT_V07,
+                    "topics": [
+                        UserOperationEvent::SIGNATURE_HASH,
+                        hash(op),
+                        op.sender.into_word(),
+                        B256::ZERO,
+                    ],
+                    "data": Bytes::from((op.nonce, true, U256::ZERO, U256::ZERO).abi_encode_params()),
+                })
+            })
+            .collect();
+        let node = Node {
+            storage,
+            input: simulate::encode_handle_ops(&ops, Address::repeat_byte(0xbe)),
+            logs: json!(logs),
+        };
+
+        let replay = replay(&node, B256::repeat_byte(9), module).unwrap();
+        let verdicts: Vec<_> = replay.ops.iter().map(|op| op.verdict).collect();
+        assert_eq!(verdicts, [Verdict::Valid, Verdict::Invalid, Verdict::NoKey]);
+        assert_eq!(replay.ops[0].logged_hash, Some(replay.ops[0].user_op_hash));
+        assert_eq!(
+            replay.ops[0].key,
+            Some(keys::fingerprint(&pk, HashAlg::Keccak256))
+        );
+        let diverged: Vec<_> = replay.divergences().map(|op| op.sender).collect();
+        assert_eq!(diverged, [bob]);
+    }
+}
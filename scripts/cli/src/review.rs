@@ -0,0 +1,253 @@
+//! What an operator reviews before `pq-userop sign` signs: chain, sender,
+//! nonce, paymaster, worst-case gas cost and the decoded calls.
+//!
+//! Without the `tui` feature the review is printed to stderr and confirmed
+//! with a y/N prompt. With it, [`run`] shows a full-screen review (ratatui) and
+//! only signs once the operator types [`CONFIRM_WORD`].
+
+use alloy_primitives::{Address, B256, U256};
+
+use crate::assets::AssetChanges;
+use crate::contacts::Names;
+use crate::decode;
+use crate::gas::cost;
+use crate::paymaster::TokenFee;
+use crate::userop::PackedUserOperation;
+
+/// What the operator types on the review screen to sign.
+pub const CONFIRM_WORD: &str = "sign";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Review {
+    pub chain_id: u64,
+    pub sender: Address,
+    pub nonce: U256,
+    pub paymaster: Option<Address>,
+    /// Sum of all gas limits.
+    pub gas: u128,
+    /// At `maxFeePerGas`; the most the op can cost.
+    pub max_cost_wei: u128,
+    /// What a token paymaster charges instead, at most.
+    pub token_fee: Option<TokenFee>,
+    pub user_op_hash: B256,
+    /// [`decode::preview`] lines.
+    pub calls: Vec<String>,
+    /// Contact and ENS names shown next to addresses; see [`Review::label`].
+    pub names: Names,
+    /// What simulating the calls showed them move, if they were simulated.
+    pub assets: Option<AssetChanges>,
+}
+
+impl Review {
+    pub fn new(op: &PackedUserOperation, chain_id: u64, user_op_hash: B256) -> Self {
+        let cost = cost::cost(op, 0);
+        Review {
+            chain_id,
+            sender: op.sender,
+            nonce: op.nonce,
+            paymaster: (op.paymaster_and_data.len() >= 20)
+                .then(|| Address::from_slice(&op.paymaster_and_data[..20])),
+            gas: cost.gas,
+            max_cost_wei: cost.max_wei,
+            token_fee: None,
+            user_op_hash,
+            calls: decode::preview(op, chain_id),
+            names: Names::default(),
+            assets: None,
+        }
+    }
+
+    /// Print `names` next to the addresses they name.
+    pub fn label(&mut self, names: Names) {
+        for line in &mut self.calls {
+            *line = names.annotate(line);
+        }
+        self.names = names;
+    }
+
+    /// Label/value pairs for the summary, in display order.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("Chain", self.chain_id.to_string()),
+            ("Sender", self.names.label(self.sender)),
+            ("Nonce", self.nonce.to_string()),
+            (
+                "Paymaster",
+                self.paymaster.map_or_else(
+                    || "none (account pays)".to_string(),
+                    |p| self.names.label(p),
+                ),
+            ),
+            ("Gas limit", self.gas.to_string()),
+            (
+                "Max cost",
+                format!("{} ETH", cost::format_ether(self.max_cost_wei)),
+            ),
+            ("userOpHash", self.user_op_hash.to_string()),
+        ];
+        if let Some(fee) = &self.token_fee {
+            let approval = if fee.approves { ", approved first" } else { "" };
+            fields.insert(6, ("Max fee", format!("{}{approval}", fee.amount())));
+        }
+        if let Some(assets) = &self.assets {
+            fields.push(("Moves", assets.to_string()));
+        }
+        fields
+    }
+
+    /// The review as plain text, for the prompt fallback.
+    pub fn print(&self) {
+        for (label, value) in self.fields() {
+            eprintln!("{:<12} {value}", format!("{label}:"));
+        }
+        eprintln!("This UserOperation will:");
+        for line in &self.calls {
+            eprintln!("  {line}");
+        }
+    }
+}
+
+/// Show the review full-screen on stderr, leaving stdout for the signed op.
+/// Returns whether the operator typed [`CONFIRM_WORD`]; Esc or Ctrl-C
+/// cancels.
+#[cfg(feature = "tui")]
+pub fn run(review: &Review) -> crate::Result<bool> {
+    use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+    use ratatui::crossterm::{execute, terminal};
+    use ratatui::prelude::{CrosstermBackend, Terminal};
+
+    let io_err = |e: std::io::Error| crate::WalletError::Io("review screen".to_string(), e);
+    terminal::enable_raw_mode().map_err(io_err)?;
+    execute!(std::io::stderr(), terminal::EnterAlternateScreen).map_err(io_err)?;
+    let result = (|| {
+        let mut term = Terminal::new(CrosstermBackend::new(std::io::stderr())).map_err(io_err)?;
+        let mut typed = String::new();
+        let mut mistyped = false;
+        loop {
+            term.draw(|frame| draw(frame, review, &typed, mistyped))
+                .map_err(io_err)?;
+            let Event::Key(key) = event::read().map_err(io_err)? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Esc => return Ok(false),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(false);
+                }
+                KeyCode::Enter if typed == CONFIRM_WORD => return Ok(true),
+                KeyCode::Enter => {
+                    typed.clear();
+                    mistyped = true;
+                }
+                KeyCode::Backspace => {
+                    typed.pop();
+                }
+                KeyCode::Char(c) => typed.push(c),
+                _ => {}
+            }
+        }
+    })();
+    let _ = execute!(std::io::stderr(), terminal::LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+    result
+}
+
+#[cfg(feature = "tui")]
+fn draw(frame: &mut ratatui::Frame, review: &Review, typed: &str, mistyped: bool) {
+    use ratatui::layout::{Constraint, Layout};
+    use ratatui::style::{Style, Stylize};
+    use ratatui::text::Line;
+    use ratatui::widgets::{Block, List, Paragraph, Row, Table};
+
+    let fields = review.fields();
+    let [summary, calls, prompt] = Layout::vertical([
+        Constraint::Length(fields.len() as u16 + 2),
+        Constraint::Min(3),
+        Constraint::Length(3),
+    ])
+    .areas(frame.area());
+
+    let rows = fields
+        .into_iter()
+        .map(|(label, value)| Row::new([label.bold().into(), Line::from(value)]));
+    let table = Table::new(rows, [Constraint::Length(12), Constraint::Min(10)])
+        .block(Block::bordered().title(" Review UserOperation "));
+    frame.render_widget(table, summary);
+
+    let list = List::new(review.calls.iter().map(String::as_str))
+        .block(Block::bordered().title(" Calls "));
+    frame.render_widget(list, calls);
+
+    let hint = if mistyped {
+        format!(" Type \"{CONFIRM_WORD}\" exactly, then Enter (Esc cancels) ")
+    } else {
+        format!(" Type \"{CONFIRM_WORD}\" and press Enter to sign, Esc to cancel ")
+    };
+    let input = Paragraph::new(format!("> {typed}")).block(
+        Block::bordered()
+            .title(hint)
+            .border_style(Style::new().yellow()),
+    );
+    frame.render_widget(input, prompt);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::userop::pack_u128_pair;
+
+    #[test]
+    fn review_sums_gas_and_reads_paymaster() {
+        let paymaster = Address::repeat_byte(0x77);
+        let mut paymaster_and_data = paymaster.to_vec();
+        paymaster_and_data.extend_from_slice(&pack_u128_pair(1_000, 500).0);
+        let op = PackedUserOperation {
+            sender: Address::repeat_byte(0xaa),
+            nonce: U256::from(3),
+            init_code: Vec::new(),
+            call_data: Vec::new(),
+            account_gas_limits: pack_u128_pair(100_000, 50_000),
+            pre_verification_gas: U256::from(48_500),
+            gas_fees: pack_u128_pair(1, 2_000_000_000),
+            paymaster_and_data,
+            signature: Vec::new(),
+        };
+        let mut review = Review::new(&op, 421614, B256::repeat_byte(1));
+        assert_eq!(review.paymaster, Some(paymaster));
+        assert_eq!(review.gas, 200_000);
+        let fields = review.fields();
+        assert_eq!(fields[4], ("Gas limit", "200000".to_string()));
+        assert_eq!(fields[5], ("Max cost", "0.0004 ETH".to_string()));
+        assert_eq!(review.calls[0], "no call");
+        review.token_fee = Some(TokenFee {
+            token: Address::repeat_byte(0xcc),
+            symbol: "USDC".into(),
+            decimals: 6,
+            max: U256::from(1_250_000),
+            approves: true,
+        });
+        assert_eq!(
+            review.fields()[6],
+            ("Max fee", "1.25 USDC, approved first".to_string())
+        );
+        review.token_fee = None;
+
+        let mut names = Names::default();
+        names.insert(paymaster, "sponsor");
+        review.label(names);
+        assert_eq!(review.fields()[3].1, format!("{paymaster} (sponsor)"));
+        assert_eq!(
+            review.calls[1],
+            format!("gas paid by paymaster {paymaster} (sponsor)")
+        );
+
+        review.assets = Some(AssetChanges::default());
+        assert_eq!(
+            review.fields().last().unwrap(),
+            &("Moves", "no ETH or tokens will move".to_string())
+        );
+    }
+}
@@ -0,0 +1,241 @@
+//! Revocation lists for delegation certificates, read from the validator
+//! module's `DelegationRevoked` logs.
+//!
+//! A [`RevocationList`] holds the delegation hashes each watched account has
+//! revoked on one `PQValidatorModule`, as of a block. [`RevocationList::refresh`]
+//! reads only the logs since that block. A revocation that is later reorged
+//! out stays on the list, so the signer errs on the side of refusing.
+//! `pq-signerd` keeps the list in `revocations.json` next to the keystore, so
+//! a restart does not need the node. It refuses a delegated op once the
+//! certificate is on the list, and refuses every delegated op while the list
+//! is older than its staleness bound. A signer that cannot reach the chain
+//! cannot tell a revoked grant from a live one.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::{Address, B256, U64};
+use alloy_sol_types::{SolEvent, sol};
+use serde::{Deserialize, Serialize};
+
+use crate::bundler::Log;
+use crate::error::{Result, WalletError};
+use crate::keystore::Keystore;
+use crate::rpc::Transport;
+
+pub const REVOCATIONS_VERSION: u32 = 1;
+
+sol! {
+    /// `PQValidatorModule.DelegationRevoked`.
+    event DelegationRevoked(address indexed account, bytes32 indexed delegationHash);
+}
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::Delegation(reason.into())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevocationList {
+    pub version: u32,
+    pub module: Address,
+    /// The chain the logs came from, 0 before the first refresh.
+    pub chain_id: u64,
+    /// Accounts whose revocations are read.
+    pub accounts: BTreeSet<Address>,
+    /// First block not read yet.
+    pub next_block: u64,
+    /// When the list was last brought up to date, 0 before the first time.
+    pub fetched_at: u64,
+    pub revoked: BTreeMap<Address, BTreeSet<B256>>,
+}
+
+impl RevocationList {
+    /// An empty list that has not been fetched.
+    pub fn new(module: Address, accounts: BTreeSet<Address>) -> Self {
+        RevocationList {
+            version: REVOCATIONS_VERSION,
+            module,
+            chain_id: 0,
+            accounts,
+            next_block: 0,
+            fetched_at: 0,
+            revoked: BTreeMap::new(),
+        }
+    }
+
+    /// `revocations.json` in the keystore root.
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(Keystore::default_root()?.join("revocations.json"))
+    }
+
+    /// The cached list at `path` if it covers the same module and
+    /// accounts, else a new one.
+    pub fn load_or_new(path: &Path, module: Address, accounts: BTreeSet<Address>) -> Result<Self> {
+        let cached: Option<Self> = match std::fs::read(path) {
+            Ok(bytes) => Some(
+                serde_json::from_slice(&bytes)
+                    .map_err(|e| err(format!("{}: {e}", path.display())))?,
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(WalletError::Io(path.display().to_string(), e)),
+        };
+        Ok(match cached {
+            Some(list)
+                if list.version == REVOCATIONS_VERSION
+                    && list.module == module
+                    && accounts.is_subset(&list.accounts) =>
+            {
+                list
+            }
+            _ => Self::new(module, accounts),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| WalletError::Io(dir.display().to_string(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self).expect("revocation list serializes");
+        std::fs::write(path, json).map_err(|e| WalletError::Io(path.display().to_string(), e))
+    }
+
+    /// Read the revocations logged since the last refresh, up to the
+    /// node's head. The node must stay on the chain of the first refresh.
+    pub fn refresh<T: Transport>(&mut self, node: &T, now: u64) -> Result<()> {
+        let chain_id = node
+            .call::<U64>("eth_chainId", serde_json::json!([]))?
+            .to::<u64>();
+        if self.chain_id == 0 {
+            self.chain_id = chain_id;
+        } else if chain_id != self.chain_id {
+            return Err(err(format!(
+                "the node is on chain {chain_id}, the list is for {}",
+                self.chain_id
+            )));
+        }
+        let head = node
+            .call::<U64>("eth_blockNumber", serde_json::json!([]))?
+            .to::<u64>();
+        if head >= self.next_block && !self.accounts.is_empty() {
+            let accounts: Vec<B256> = self.accounts.iter().map(|a| a.into_word()).collect();
+            let logs: Vec<Log> = node.call(
+                "eth_getLogs",
+                serde_json::json!([{
+                    "address": self.module,
+                    "fromBlock": format!("{:#x}", self.next_block),
+                    "toBlock": format!("{head:#x}"),
+                    "topics": [DelegationRevoked::SIGNATURE_HASH, accounts],
+                }]),
+            )?;
+            for log in logs.iter().filter(|log| log.address == self.module) {
+                let event =
+                    DelegationRevoked::decode_raw_log(log.topics.iter().copied(), &log.data)
+                        .map_err(|e| WalletError::Rpc(format!("DelegationRevoked: {e}")))?;
+                if self.accounts.contains(&event.account) {
+                    self.revoked
+                        .entry(event.account)
+                        .or_default()
+                        .insert(event.delegationHash);
+                }
+            }
+            self.next_block = head + 1;
+        }
+        self.fetched_at = now;
+        Ok(())
+    }
+
+    pub fn is_revoked(&self, account: Address, hash: B256) -> bool {
+        self.revoked
+            .get(&account)
+            .is_some_and(|hashes| hashes.contains(&hash))
+    }
+
+    /// Refuse `hash` if `account` revoked it, or if the list is more than
+    /// `max_age` seconds old at `now`.
+    pub fn check(&self, account: Address, hash: B256, now: u64, max_age: u64) -> Result<()> {
+        if self.fetched_at == 0 {
+            return Err(err("the revocation list has not been fetched yet"));
+        }
+        let age = now.saturating_sub(self.fetched_at);
+        if age > max_age {
+            return Err(err(format!(
+                "the revocation list is {age}s old, past its {max_age}s bound"
+            )));
+        }
+        if self.is_revoked(account, hash) {
+            return Err(err(format!("{account} revoked delegation {hash}")));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+    use std::cell::RefCell;
+
+    struct Node {
+        head: u64,
+        requests: RefCell<Vec<Value>>,
+        logs: Value,
+    }
+
+    impl Transport for Node {
+        fn request(&self, method: &str, params: Value) -> Result<Value> {
+            match method {
+                "eth_chainId" => Ok(json!("0x66eee")),
+                "eth_blockNumber" => Ok(json!(format!("{:#x}", self.head))),
+                "eth_getLogs" => {
+                    self.requests.borrow_mut().push(params[0].clone());
+                    Ok(self.logs.clone())
+                }
+                other => panic!("unexpected {other}"),
+            }
+        }
+    }
+
+    #[test]
+    fn reads_logs_incrementally_and_enforces_staleness() {
+        let module = Address::repeat_byte(0x77);
+        let account = Address::repeat_byte(0xaa);
+        let revoked = B256::repeat_byte(1);
+        let log = |account: Address| {
+            json!({
+                "address": module,
+                "topics": [DelegationRevoked::SIGNATURE_HASH, account.into_word(), revoked],
+                "data": "0x",
+            })
+        };
+        let node = Node {
+            head: 10,
+            requests: RefCell::new(vec![]),
+            logs: json!([log(account), log(Address::ZERO)]),
+        };
+        let mut list = RevocationList::new(module, BTreeSet::from([account]));
+        assert!(list.check(account, B256::ZERO, 100, 60).is_err());
+
+        list.refresh(&node, 100).unwrap();
+        assert_eq!((list.chain_id, list.next_block), (421614, 11));
+        assert!(list.is_revoked(account, revoked));
+        assert!(!list.is_revoked(Address::ZERO, revoked));
+        list.check(account, B256::ZERO, 160, 60).unwrap();
+        assert!(list.check(account, revoked, 100, 60).is_err());
+        assert!(list.check(account, B256::ZERO, 161, 60).is_err());
+
+        let next = Node {
+            head: 12,
+            logs: json!([]),
+            ..node
+        };
+        list.refresh(&next, 200).unwrap();
+        assert_eq!(next.requests.borrow()[1]["fromBlock"], "0xb");
+        assert!(list.is_revoked(account, revoked));
+        list.check(account, B256::ZERO, 200, 60).unwrap();
+
+        list.chain_id = 1;
+        assert!(list.refresh(&next, 300).is_err());
+    }
+}
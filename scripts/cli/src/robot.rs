@@ -0,0 +1,345 @@
+//! Delegation certificates: an account's owner key lets an automated
+//! signer (a robot key) sign for it, for a while, on one chain.
+//!
+//! `pq-delegate issue` has the owner key sign a [`Delegation`] naming the
+//! robot's ML-DSA-65 key, the smart account, a chain (0 for any) and a
+//! validity window. The grant can also pin the hash of the signing policy
+//! the robot's signer must run under. The robot never holds the owner key.
+//! `pq-signerd --delegation cert.json` puts the certificate and its own
+//! signature in a V3 envelope ([`crate::envelope`]). `PQValidatorModule`
+//! checks both signatures and that the grant is not revoked, and returns the
+//! window in its `validationData`, so the EntryPoint drops the robot's ops
+//! once it expires.
+//!
+//! The certificates the owner issued are kept in [`DelegationStore`], a
+//! `delegations.json` next to the keystore. Revoking one there marks it and
+//! gives the `revokeDelegation` call to send from the account; the robot's
+//! ops keep validating until that call lands.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::{Address, B256, Bytes, keccak256};
+use alloy_sol_types::{SolCall, sol};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+use crate::envelope::{Delegation, Envelope, EnvelopeV3, SCHEME_ML_DSA_65, SignatureEnvelope};
+use crate::error::{Result, WalletError};
+use crate::keys::{self, PUBLIC_KEY_LEN, SEED_LEN};
+use crate::keystore::Keystore;
+use crate::message::HashAlg;
+use crate::signer::PqSigner;
+use crate::validity::ValidityWindow;
+
+pub const CERTIFICATE_VERSION: u32 = 1;
+
+sol! {
+    function revokeDelegation(bytes32 delegationHash);
+}
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::Delegation(reason.into())
+}
+
+/// Where a certificate stands at a given time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    NotYetValid,
+    Active,
+    Expired,
+}
+
+/// A signed [`Delegation`], with both keys spelled out.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Certificate {
+    pub version: u32,
+    pub account: Address,
+    /// 0 for any chain.
+    pub chain_id: u64,
+    pub valid_after: u64,
+    pub valid_until: u64,
+    /// Zero when the grant does not pin a policy.
+    pub policy_hash: B256,
+    /// The robot's ML-DSA-65 public key.
+    pub robot_key: Bytes,
+    /// The owner's ML-DSA-65 public key, the one the validator holds.
+    pub owner_key: Bytes,
+    /// Signature over [`Certificate::hash`].
+    pub owner_signature: Bytes,
+}
+
+impl Certificate {
+    /// Have the owner key grant `robot_key` the right to sign for
+    /// `account` during `window`.
+    pub fn issue(
+        owner_seed: &[u8; SEED_LEN],
+        robot_key: &[u8],
+        account: Address,
+        chain_id: u64,
+        window: ValidityWindow,
+        policy_hash: B256,
+    ) -> Result<Self> {
+        if robot_key.len() != PUBLIC_KEY_LEN {
+            return Err(WalletError::InvalidLength {
+                what: "robot public key",
+                expected: PUBLIC_KEY_LEN,
+                actual: robot_key.len(),
+            });
+        }
+        let signer = PqSigner::from_seed(owner_seed);
+        let owner_key = signer.verifying_key().encode();
+        if owner_key[..] == robot_key[..] {
+            return Err(err("the robot key is the owner key"));
+        }
+        let mut cert = Certificate {
+            version: CERTIFICATE_VERSION,
+            account,
+            chain_id,
+            valid_after: window.valid_after,
+            valid_until: window.valid_until,
+            policy_hash,
+            robot_key: Bytes::copy_from_slice(robot_key),
+            owner_key: Bytes::copy_from_slice(&owner_key),
+            owner_signature: Bytes::new(),
+        };
+        let signature = signer.sign(cert.hash().as_slice());
+        cert.owner_signature = Bytes::copy_from_slice(&signature.encode());
+        Ok(cert)
+    }
+
+    /// The grant as the validator reads it.
+    pub fn delegation(&self) -> Delegation {
+        Delegation {
+            account: self.account,
+            chain_id: self.chain_id,
+            robot_key_hash: keccak256(&self.robot_key),
+            valid_after: self.valid_after,
+            valid_until: self.valid_until,
+            policy_hash: self.policy_hash,
+        }
+    }
+
+    /// What the owner signs, and the ID a revocation names.
+    pub fn hash(&self) -> B256 {
+        self.delegation().hash()
+    }
+
+    pub fn owner_fingerprint(&self) -> B256 {
+        keys::fingerprint(&self.owner_key, HashAlg::Keccak256)
+    }
+
+    pub fn robot_fingerprint(&self) -> B256 {
+        keys::fingerprint(&self.robot_key, HashAlg::Keccak256)
+    }
+
+    /// Check the owner signature and the window. `owner_key`, when given,
+    /// must be the key the certificate names.
+    pub fn verify(&self, owner_key: Option<&[u8]>) -> Result<()> {
+        if self.version != CERTIFICATE_VERSION {
+            return Err(err(format!("unsupported version {}", self.version)));
+        }
+        ValidityWindow::new(self.valid_after, self.valid_until)?;
+        if owner_key.is_some_and(|pk| pk != &self.owner_key[..]) {
+            return Err(err("issued by a different owner key"));
+        }
+        if !Backend::RustCrypto.verify(
+            &self.owner_key,
+            self.hash().as_slice(),
+            &[],
+            &self.owner_signature,
+        ) {
+            return Err(err("owner signature does not verify"));
+        }
+        Ok(())
+    }
+
+    pub fn status(&self, now: u64) -> Status {
+        if now < self.valid_after {
+            Status::NotYetValid
+        } else if now > self.valid_until {
+            Status::Expired
+        } else {
+            Status::Active
+        }
+    }
+
+    /// Refuse to sign an op the validator would reject under this grant.
+    pub fn check_op(&self, sender: Address, chain_id: u64, now: u64) -> Result<()> {
+        if sender != self.account {
+            return Err(err(format!("granted for {}, not {sender}", self.account)));
+        }
+        if self.chain_id != 0 && self.chain_id != chain_id {
+            return Err(err(format!(
+                "granted for chain {}, not {chain_id}",
+                self.chain_id
+            )));
+        }
+        match self.status(now) {
+            Status::Active => Ok(()),
+            Status::NotYetValid => Err(err(format!("not valid before {}", self.valid_after))),
+            Status::Expired => Err(err(format!("expired at {}", self.valid_until))),
+        }
+    }
+
+    /// The V3 signature field for the robot's `signature` of a userOpHash.
+    pub fn envelope(&self, signature: &[u8]) -> Result<Vec<u8>> {
+        Envelope::V3(EnvelopeV3 {
+            delegation: self.delegation(),
+            owner_signature: self.owner_signature.to_vec(),
+            pq: SignatureEnvelope {
+                scheme_id: SCHEME_ML_DSA_65,
+                public_key: self.robot_key.to_vec(),
+                signature: signature.to_vec(),
+            },
+        })
+        .encode()
+    }
+
+    /// `revokeDelegation(hash)`, for the account to call on the module.
+    pub fn revoke_call(&self) -> Vec<u8> {
+        revokeDelegationCall {
+            delegationHash: self.hash(),
+        }
+        .abi_encode()
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes =
+            std::fs::read(path).map_err(|e| WalletError::Io(path.display().to_string(), e))?;
+        Self::from_json(&bytes)
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).map_err(|e| err(format!("invalid certificate: {e}")))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("certificate serializes")
+    }
+}
+
+/// A certificate the owner issued.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Issued {
+    pub certificate: Certificate,
+    /// When `pq-delegate revoke` marked it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<u64>,
+}
+
+/// The certificates issued from this machine, by hash.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DelegationStore {
+    pub issued: BTreeMap<B256, Issued>,
+}
+
+impl DelegationStore {
+    /// `delegations.json` in the keystore root.
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(Keystore::default_root()?.join("delegations.json"))
+    }
+
+    /// Read the store; a missing file is an empty store.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(bytes) => {
+                serde_json::from_slice(&bytes).map_err(|e| err(format!("{}: {e}", path.display())))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(WalletError::Io(path.display().to_string(), e)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| WalletError::Io(dir.display().to_string(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self).expect("store serializes");
+        std::fs::write(path, json).map_err(|e| WalletError::Io(path.display().to_string(), e))
+    }
+
+    pub fn insert(&mut self, certificate: Certificate) -> B256 {
+        let hash = certificate.hash();
+        self.issued.insert(
+            hash,
+            Issued {
+                certificate,
+                revoked_at: None,
+            },
+        );
+        hash
+    }
+
+    /// Mark `hash` revoked at `now`; revoking twice keeps the first time.
+    pub fn revoke(&mut self, hash: B256, now: u64) -> Result<&Issued> {
+        let issued = self
+            .issued
+            .get_mut(&hash)
+            .ok_or_else(|| err(format!("no certificate {hash} in the store")))?;
+        issued.revoked_at.get_or_insert(now);
+        Ok(issued)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cert() -> Certificate {
+        let robot = Backend::RustCrypto.public_key(&[2u8; SEED_LEN]);
+        let window = ValidityWindow::new(1_000, 2_000).unwrap();
+        Certificate::issue(
+            &[1u8; SEED_LEN],
+            &robot,
+            Address::repeat_byte(0xaa),
+            421614,
+            window,
+            B256::ZERO,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn issues_and_checks_a_certificate() {
+        let cert = cert();
+        let read = Certificate::from_json(cert.to_json().as_bytes()).unwrap();
+        assert_eq!(read, cert);
+        read.verify(Some(&cert.owner_key)).unwrap();
+        assert!(read.verify(Some(&cert.robot_key)).is_err());
+
+        let mut widened = read.clone();
+        widened.valid_until += 1;
+        assert!(widened.verify(None).is_err());
+
+        let sender = cert.account;
+        cert.check_op(sender, 421614, 1_500).unwrap();
+        assert!(cert.check_op(sender, 1, 1_500).is_err());
+        assert!(cert.check_op(Address::ZERO, 421614, 1_500).is_err());
+        assert!(cert.check_op(sender, 421614, 999).is_err());
+        assert!(cert.check_op(sender, 421614, 2_001).is_err());
+
+        let field = cert.envelope(&[7u8; crate::keys::SIGNATURE_LEN]).unwrap();
+        let Envelope::V3(env) = Envelope::decode(&field).unwrap() else {
+            panic!("not a V3 envelope");
+        };
+        assert_eq!(env.delegation.hash(), cert.hash());
+        assert_eq!(&cert.revoke_call()[4..], cert.hash().as_slice());
+    }
+
+    #[test]
+    fn the_store_keeps_the_first_revocation() {
+        let mut store = DelegationStore::default();
+        let hash = store.insert(cert());
+        assert_eq!(store.revoke(hash, 5).unwrap().revoked_at, Some(5));
+        assert_eq!(store.revoke(hash, 9).unwrap().revoked_at, Some(5));
+        assert!(store.revoke(B256::ZERO, 9).is_err());
+        let json = serde_json::to_vec(&store).unwrap();
+        let read: DelegationStore = serde_json::from_slice(&json).unwrap();
+        assert_eq!(read, store);
+    }
+}
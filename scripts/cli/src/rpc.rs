@@ -0,0 +1,162 @@
+//! Minimal JSON-RPC 2.0 client for node and bundler endpoints.
+//!
+//! Callers go through the [`Transport`] trait so polling and decoding logic
+//! can be tested against canned responses without a running bundler.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use alloy_primitives::Bytes;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::ct;
+use crate::error::{Result, WalletError};
+
+/// Something that can answer a JSON-RPC request.
+pub trait Transport {
+    /// Send `method(params)` and return the `result` member. A JSON-RPC
+    /// `error` member becomes [`WalletError::RpcError`]; transport failures
+    /// become [`WalletError::Rpc`].
+    fn request(&self, method: &str, params: Value) -> Result<Value>;
+
+    /// [`Transport::request`] decoded into `T`.
+    fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T>
+    where
+        Self: Sized,
+    {
+        let result = self.request(method, params)?;
+        serde_json::from_value(result)
+            .map_err(|e| WalletError::Rpc(format!("{method}: unexpected result: {e}")))
+    }
+}
+
+#[derive(Deserialize)]
+struct Response {
+    #[serde(default)]
+    result: Option<Value>,
+    #[serde(default)]
+    error: Option<RpcErrorBody>,
+}
+
+#[derive(Deserialize)]
+struct RpcErrorBody {
+    code: i64,
+    message: String,
+    #[serde(default)]
+    data: Option<Value>,
+}
+
+/// JSON-RPC over HTTP POST.
+#[derive(Debug)]
+pub struct HttpTransport {
+    url: String,
+    agent: ureq::Agent,
+    next_id: AtomicU64,
+}
+
+impl HttpTransport {
+    pub fn new(url: impl Into<String>, timeout: Duration) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(timeout))
+            .http_status_as_error(false)
+            .build()
+            .into();
+        HttpTransport {
+            url: url.into(),
+            agent,
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl Transport for HttpTransport {
+    #[tracing::instrument(level = "debug", name = "rpc", skip_all, fields(method = %method, url = %self.url))]
+    fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        tracing::trace!(%params, "request");
+        let started = Instant::now();
+        let body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+        let text = self
+            .agent
+            .post(&self.url)
+            .send_json(&body)
+            .and_then(|mut resp| {
+                resp.body_mut()
+                    .with_config()
+                    .limit(16 << 20)
+                    .read_to_string()
+            })
+            .map_err(|e| WalletError::Rpc(format!("{method} to {}: {e}", self.url)))
+            .inspect_err(|e| tracing::debug!(error = %e, "transport failure"))?;
+        tracing::debug!(
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            bytes = text.len(),
+            "response"
+        );
+        tracing::trace!(body = %text, "response body");
+        let resp: Response = serde_json::from_str(&text)
+            .map_err(|e| WalletError::Rpc(format!("{method}: malformed response: {e}")))?;
+        match (resp.result, resp.error) {
+            (_, Some(err)) => Err(WalletError::RpcError {
+                method: method.to_string(),
+                code: err.code,
+                message: err.message,
+                data: err.data,
+            })
+            .inspect_err(|e| tracing::debug!(error = %e, "error response")),
+            (result, None) => Ok(result.unwrap_or(Value::Null)),
+        }
+    }
+}
+
+/// Revert data carried by an `eth_call`/`eth_estimateGas` error, if any.
+/// Nodes put it in `error.data` either as a hex string or (some clients)
+/// nested one level deeper as `{"data": "0x…"}`.
+pub fn revert_data(err: &WalletError) -> Option<Bytes> {
+    let WalletError::RpcError {
+        data: Some(data), ..
+    } = err
+    else {
+        return None;
+    };
+    let hex = match data {
+        Value::String(s) => s.as_str(),
+        Value::Object(obj) => obj.get("data")?.as_str()?,
+        _ => return None,
+    };
+    ct::decode_hex(hex).ok().map(Bytes::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revert_data_accepts_string_and_nested_forms() {
+        let err = |data| WalletError::RpcError {
+            method: "eth_call".into(),
+            code: 3,
+            message: "execution reverted".into(),
+            data: Some(data),
+        };
+        let flat = err(serde_json::json!("0x08c379a0"));
+        let nested = err(serde_json::json!({"data": "0x08c379a0"}));
+        assert_eq!(
+            revert_data(&flat).unwrap().as_ref(),
+            [0x08, 0xc3, 0x79, 0xa0]
+        );
+        assert_eq!(revert_data(&nested), revert_data(&flat));
+        assert!(revert_data(&WalletError::Rpc("down".into())).is_none());
+    }
+}
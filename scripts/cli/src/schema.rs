@@ -0,0 +1,1356 @@
+//! JSON Schemas for the `--json` outputs, and their version.
+//!
+//! Every `--json` response carries `"schemaVersion": SCHEMA_VERSION`, which
+//! [`output::emit`](crate::output::emit) adds: to the object, or to each
+//! object of a top-level array (an empty array carries none). The version
+//! only moves when a field is renamed, removed or changes type. New fields
+//! keep it, so a consumer should ignore fields it does not know.
+//!
+//! `--schema` on any binary prints the JSON Schema (draft 2020-12) of the
+//! command it is given with, instead of running it:
+//! `pq-userop submit --schema`. [`output`] is the registry behind it. An
+//! error response, `{"error": ...}`, can come from every command and is part
+//! of each schema. `pq-userop sign` and `attach` and `pq-module --op` print
+//! the UserOperation itself, which the other tools read back, so theirs has
+//! no `schemaVersion`.
+
+use serde_json::{Map, Value, json};
+
+/// Version of the `--json` output contract.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// `json`, compact as [`serde_json::to_string`] writes it, with
+/// `schemaVersion` added to it if it is an object, or to each object in it
+/// if it is an array. The fields keep their order, with the version last.
+pub fn stamp(json: &str) -> String {
+    let field = format!("\"schemaVersion\":{SCHEMA_VERSION}");
+    let mut out = String::with_capacity(json.len() + field.len());
+    // The depth at which a `}` closes a versioned object.
+    let target = if json.starts_with('[') { 2 } else { 1 };
+    let (mut depth, mut in_string, mut escaped, mut prev) = (0, false, false, ' ');
+    for c in json.chars() {
+        if in_string {
+            (in_string, escaped) = (escaped || c != '"', !escaped && c == '\\');
+        } else {
+            match c {
+                '"' => in_string = true,
+                '{' | '[' => depth += 1,
+                '}' | ']' => {
+                    if c == '}' && depth == target {
+                        if prev != '{' {
+                            out.push(',');
+                        }
+                        out.push_str(&field);
+                    }
+                    depth -= 1;
+                }
+                _ => {}
+            }
+        }
+        out.push(c);
+        prev = c;
+    }
+    out
+}
+
+/// The schema document for `tool`'s `command` (its subcommand names, outer
+/// first), or `None` if the command has no `--json` output.
+pub fn output(tool: &str, command: &[&str]) -> Option<Value> {
+    // The UserOperation these print is read back by the other tools as it
+    // is, so only their other outputs are versioned.
+    let body = match (tool, command) {
+        ("pq-userop", ["sign" | "attach"]) => any_of([user_operation(), versioned(denial())]),
+        ("pq-module", ["install" | "uninstall"]) => {
+            any_of([versioned(module_call()), user_operation()])
+        }
+        _ => versioned(body(tool, command)?),
+    };
+    Some(json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": format!("{} --json", [&[tool][..], command].concat().join(" ")),
+        "schemaVersion": SCHEMA_VERSION,
+        "anyOf": [body, versioned(object([("error", string())], []))],
+    }))
+}
+
+/// Add the required `schemaVersion` property to every object a response
+/// can be.
+fn versioned(mut schema: Value) -> Value {
+    if let Some(variants) = schema.get_mut("anyOf").and_then(Value::as_array_mut) {
+        for variant in variants {
+            *variant = versioned(variant.take());
+        }
+    } else if schema["type"] == "array" {
+        schema["items"] = versioned(schema["items"].take());
+    } else if schema["type"] == "object" {
+        schema["properties"]["schemaVersion"] = json!({ "const": SCHEMA_VERSION });
+        schema["required"]
+            .as_array_mut()
+            .expect("objects list their required fields")
+            .push("schemaVersion".into());
+    }
+    schema
+}
+
+fn any_of<const N: usize>(variants: [Value; N]) -> Value {
+    json!({ "anyOf": Vec::from(variants) })
+}
+
+/// An object whose `required` fields are always present (`null` where the
+/// type says so) and whose `optional` ones may be left out.
+fn object<const R: usize, const O: usize>(
+    required: [(&str, Value); R],
+    optional: [(&str, Value); O],
+) -> Value {
+    let names: Vec<&str> = required.iter().map(|(name, _)| *name).collect();
+    let properties: Map<String, Value> = required
+        .into_iter()
+        .chain(optional)
+        .map(|(name, schema)| (name.to_string(), schema))
+        .collect();
+    json!({ "type": "object", "properties": properties, "required": names })
+}
+
+/// [`object`] with more required fields in front, for flattened structs.
+fn extend<const R: usize>(mut schema: Value, required: [(&str, Value); R]) -> Value {
+    for (name, field) in required {
+        schema["properties"][name] = field;
+        schema["required"]
+            .as_array_mut()
+            .expect("objects list their required fields")
+            .push(name.into());
+    }
+    schema
+}
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn boolean() -> Value {
+    json!({ "type": "boolean" })
+}
+
+fn integer() -> Value {
+    json!({ "type": "integer", "minimum": 0 })
+}
+
+fn number() -> Value {
+    json!({ "type": "number" })
+}
+
+/// Any JSON value; for nested records documented elsewhere.
+fn any() -> Value {
+    json!({})
+}
+
+fn one_of(values: &[&str]) -> Value {
+    json!({ "type": "string", "enum": values })
+}
+
+fn nullable(schema: Value) -> Value {
+    json!({ "anyOf": [schema, { "type": "null" }] })
+}
+
+fn array(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+/// A map keyed by chain ID (a decimal string, as JSON keys are).
+fn by_chain(values: Value) -> Value {
+    json!({ "type": "object", "propertyNames": { "pattern": "^[0-9]+$" }, "additionalProperties": values })
+}
+
+fn hex(pattern: &str) -> Value {
+    json!({ "type": "string", "pattern": pattern })
+}
+
+fn address() -> Value {
+    hex("^0x[0-9a-fA-F]{40}$")
+}
+
+fn hash() -> Value {
+    hex("^0x[0-9a-fA-F]{64}$")
+}
+
+fn bytes() -> Value {
+    hex("^0x([0-9a-fA-F]{2})*$")
+}
+
+/// A `0x`-prefixed hex integer (`U256`, `U64`).
+fn quantity() -> Value {
+    hex("^0x[0-9a-fA-F]+$")
+}
+
+fn user_operation() -> Value {
+    object(
+        [
+            ("sender", address()),
+            ("nonce", quantity()),
+            ("factory", nullable(address())),
+            ("factoryData", nullable(bytes())),
+            ("callData", bytes()),
+            ("callGasLimit", quantity()),
+            ("verificationGasLimit", quantity()),
+            ("preVerificationGas", quantity()),
+            ("maxFeePerGas", quantity()),
+            ("maxPriorityFeePerGas", quantity()),
+            ("paymaster", nullable(address())),
+            ("paymasterVerificationGasLimit", nullable(quantity())),
+            ("paymasterPostOpGasLimit", nullable(quantity())),
+            ("paymasterData", nullable(bytes())),
+            ("signature", bytes()),
+        ],
+        [],
+    )
+}
+
+fn keygen() -> Value {
+    object(
+        [
+            ("address", address()),
+            ("pubkey", bytes()),
+            ("fingerprint", hash()),
+            ("path", string()),
+        ],
+        [],
+    )
+}
+
+fn account() -> Value {
+    object(
+        [
+            ("name", string()),
+            ("scheme", string()),
+            ("address", address()),
+            ("pubkey", bytes()),
+            ("fingerprint", hash()),
+            ("addresses", by_chain(address())),
+            ("path", string()),
+        ],
+        [
+            ("signingSchemes", by_chain(string())),
+            ("profiles", by_chain(any())),
+            ("watchOnly", boolean()),
+        ],
+    )
+}
+
+fn signature() -> Value {
+    object(
+        [
+            ("signature", bytes()),
+            ("length", integer()),
+            ("scheme", integer()),
+        ],
+        [
+            ("attestation", any()),
+            ("cose", bytes()),
+            ("messageHash", hash()),
+            ("envelope", bytes()),
+            ("message", string()),
+        ],
+    )
+}
+
+fn verification() -> Value {
+    object(
+        [("valid", boolean()), ("reason", nullable(string()))],
+        [("scheme", string()), ("cached", boolean())],
+    )
+}
+
+fn diagnostic() -> Value {
+    object(
+        [
+            ("code", nullable(string())),
+            ("reason", string()),
+            ("description", nullable(string())),
+            ("fix", nullable(string())),
+        ],
+        [],
+    )
+}
+
+fn denial() -> Value {
+    object(
+        [
+            ("denied", boolean()),
+            (
+                "rule",
+                one_of(&[
+                    "chain",
+                    "target",
+                    "selector",
+                    "value-per-op",
+                    "value-per-day",
+                    "approval",
+                    "rate",
+                    "anomaly",
+                ]),
+            ),
+            ("reason", string()),
+        ],
+        [],
+    )
+}
+
+fn rejected() -> Value {
+    object(
+        [("userOpHash", hash()), ("diagnostic", diagnostic())],
+        [("simulation", simulation())],
+    )
+}
+
+fn simulation() -> Value {
+    object(
+        [("outcome", one_of(&["ok", "reverted"]))],
+        [
+            ("validation", any()),
+            ("revert", object([("kind", string())], [])),
+        ],
+    )
+}
+
+fn status() -> Value {
+    object(
+        [
+            ("userOpHash", hash()),
+            ("state", one_of(&["unknown", "pending", "included"])),
+            ("transactionHash", nullable(hash())),
+            ("blockNumber", nullable(quantity())),
+            ("success", nullable(boolean())),
+            ("actualGasUsed", nullable(quantity())),
+            ("actualGasCost", nullable(quantity())),
+            ("revertReason", nullable(bytes())),
+            ("timedOut", boolean()),
+        ],
+        [],
+    )
+}
+
+fn confirmation() -> Value {
+    object(
+        [
+            ("blockNumber", integer()),
+            ("blockHash", nullable(hash())),
+            ("confirmations", integer()),
+            ("final", boolean()),
+            ("dropped", boolean()),
+            ("reorgs", integer()),
+            ("timedOut", boolean()),
+        ],
+        [],
+    )
+}
+
+fn bundle() -> Value {
+    object(
+        [
+            ("transactionHash", hash()),
+            ("from", address()),
+            ("beneficiary", address()),
+            ("nonce", integer()),
+            ("gasLimit", integer()),
+            ("maxFeePerGas", integer()),
+        ],
+        [],
+    )
+}
+
+fn queued() -> Value {
+    object(
+        [
+            ("id", json!({ "type": "integer" })),
+            ("chainId", integer()),
+            ("entryPoint", address()),
+            ("account", nullable(string())),
+            ("userOp", user_operation()),
+            ("userOpHash", hash()),
+            (
+                "status",
+                one_of(&["pending", "submitted", "included", "failed"]),
+            ),
+            ("attempts", integer()),
+            ("lastError", nullable(string())),
+            ("updatedAt", integer()),
+        ],
+        [(
+            "window",
+            object([("validAfter", integer()), ("validUntil", integer())], []),
+        )],
+    )
+}
+
+fn totals<const R: usize>(required: [(&str, Value); R]) -> Value {
+    extend(
+        object(required, []),
+        [
+            ("signed", integer()),
+            ("submitted", integer()),
+            ("included", integer()),
+            ("failed", integer()),
+            ("gasUsed", quantity()),
+            ("gasCost", quantity()),
+            ("value", quantity()),
+        ],
+    )
+}
+
+fn proposal() -> Value {
+    object(
+        [
+            ("id", hash()),
+            ("account", string()),
+            ("principal", string()),
+            ("chainId", integer()),
+            ("entryPoint", address()),
+            ("userOp", user_operation()),
+            ("userOpHash", hash()),
+            ("reason", string()),
+            ("createdAt", integer()),
+            ("expiresAt", integer()),
+            (
+                "status",
+                one_of(&["pending", "approved", "rejected", "expired"]),
+            ),
+        ],
+        [("decidedBy", hash())],
+    )
+}
+
+fn commitment() -> Value {
+    object(
+        [
+            ("ceremony", string()),
+            ("participant", string()),
+            ("commitment", hash()),
+        ],
+        [],
+    )
+}
+
+fn deploy_plan() -> Value {
+    object(
+        [
+            ("contract", string()),
+            ("address", address()),
+            ("salt", hash()),
+            ("initCodeHash", hash()),
+        ],
+        [],
+    )
+}
+
+fn module_call() -> Value {
+    object(
+        [
+            ("callData", bytes()),
+            ("moduleType", string()),
+            ("moduleTypeId", integer()),
+            ("module", address()),
+        ],
+        [],
+    )
+}
+
+fn path() -> Value {
+    object([("path", string())], [])
+}
+
+fn body(tool: &str, command: &[&str]) -> Option<Value> {
+    Some(match (tool, command) {
+        ("pq-7702", ["authorize"]) => {
+            let authorization = object(
+                [
+                    ("chainId", quantity()),
+                    ("address", address()),
+                    ("nonce", quantity()),
+                ],
+                [],
+            );
+            any_of([
+                authorization.clone(),
+                object(
+                    [
+                        ("authorization", authorization),
+                        ("rawTransaction", bytes()),
+                        ("transactionHash", nullable(hash())),
+                    ],
+                    [],
+                ),
+            ])
+        }
+
+        ("pq-account", ["new" | "show" | "profile" | "rename" | "delete"]) => account(),
+        ("pq-account", ["list"]) => array(account()),
+        ("pq-account", ["balance"]) => object(
+            [
+                ("name", string()),
+                ("chainId", integer()),
+                ("address", address()),
+                ("deployed", boolean()),
+                (
+                    "holdings",
+                    array(object(
+                        [
+                            ("token", nullable(address())),
+                            ("symbol", string()),
+                            ("decimals", integer()),
+                            ("amount", quantity()),
+                        ],
+                        [],
+                    )),
+                ),
+            ],
+            [],
+        ),
+        ("pq-account", ["stats"]) => totals([
+            ("account", string()),
+            (
+                "bundlers",
+                array(totals([
+                    ("bundler", string()),
+                    ("averageLatency", nullable(number())),
+                ])),
+            ),
+            ("period", one_of(&["day", "week", "month"])),
+            ("periods", array(totals([("period", string())]))),
+            (
+                "ops",
+                array(object(
+                    [
+                        ("userOpHash", hash()),
+                        ("chainId", integer()),
+                        ("signedAt", integer()),
+                        ("bundler", nullable(string())),
+                        ("state", one_of(&["unknown", "pending", "included"])),
+                        ("success", nullable(boolean())),
+                        ("gasUsed", quantity()),
+                        ("gasCost", quantity()),
+                        ("latency", nullable(integer())),
+                        ("value", quantity()),
+                    ],
+                    [],
+                )),
+            ),
+        ]),
+        ("pq-account", ["export-bundle"]) => object(
+            [
+                ("files", array(string())),
+                ("recipient", hash()),
+                ("path", string()),
+            ],
+            [],
+        ),
+        ("pq-account", ["import-bundle"]) => {
+            object([("files", array(string())), ("createdAt", integer())], [])
+        }
+
+        ("pq-approve", ["list"]) => array(proposal()),
+        ("pq-approve", ["show" | "approve" | "reject"]) => proposal(),
+
+        ("pq-attest", ["issue"]) => object(
+            [
+                ("address", address()),
+                ("host", hash()),
+                ("expiresAt", integer()),
+                ("path", string()),
+            ],
+            [],
+        ),
+        ("pq-attest", ["verify"]) => object(
+            [
+                ("valid", boolean()),
+                ("host", hash()),
+                ("platform", nullable(string())),
+                ("measurement", nullable(hash())),
+                ("expiresAt", integer()),
+            ],
+            [],
+        ),
+
+        ("pq-audit", ["verify"]) => any_of([
+            verification(),
+            object(
+                [
+                    ("valid", boolean()),
+                    ("entries", integer()),
+                    ("head", hash()),
+                ],
+                [],
+            ),
+        ]),
+        // One line per entry.
+        ("pq-audit", ["list"]) => object(
+            [
+                ("seq", integer()),
+                ("timestamp", integer()),
+                ("tool", string()),
+                ("account", nullable(string())),
+                ("keyId", address()),
+                ("digest", nullable(hash())),
+                ("chainId", nullable(integer())),
+                ("signature", hash()),
+                ("summary", array(string())),
+                ("backend", string()),
+                ("prev", hash()),
+                ("hash", hash()),
+            ],
+            [],
+        ),
+
+        ("pq-backup", ["keygen"]) => object([("fingerprint", hash()), ("path", string())], []),
+        ("pq-backup", ["export"]) => object(
+            [
+                ("address", address()),
+                ("recipient", hash()),
+                ("path", string()),
+            ],
+            [],
+        ),
+        ("pq-backup", ["import"]) => object([("address", address()), ("path", string())], []),
+
+        ("pq-bench", []) => object(
+            [
+                (
+                    "hashBackends",
+                    object(
+                        [
+                            ("keccak256", one_of(&["tiny-keccak", "cryptogams"])),
+                            ("keccakF1600", one_of(&["portable", "armv8-sha3"])),
+                        ],
+                        [],
+                    ),
+                ),
+                (
+                    "timings",
+                    array(object(
+                        [
+                            ("stage", string()),
+                            ("iterations", integer()),
+                            ("meanNs", integer()),
+                            ("p50Ns", integer()),
+                            ("p99Ns", integer()),
+                            ("opsPerSec", number()),
+                        ],
+                        [],
+                    )),
+                ),
+            ],
+            [],
+        ),
+
+        ("pq-cert", ["self-signed" | "csr"]) => object(
+            [
+                ("path", string()),
+                ("subject", string()),
+                ("keyId", address()),
+                ("sha256", hash()),
+            ],
+            [],
+        ),
+
+        ("pq-conformance", []) => object(
+            [
+                ("cases", integer()),
+                (
+                    "divergences",
+                    array(object(
+                        [
+                            ("case", integer()),
+                            ("seed", string()),
+                            ("message_len", integer()),
+                            ("context_len", integer()),
+                            (
+                                "kind",
+                                one_of(&[
+                                    "public_key_mismatch",
+                                    "signature_mismatch",
+                                    "sign_failed",
+                                    "cross_verify_failed",
+                                    "tampered_accepted",
+                                ]),
+                            ),
+                        ],
+                        [
+                            ("backend", string()),
+                            ("error", string()),
+                            ("signer", string()),
+                            ("verifier", string()),
+                        ],
+                    )),
+                ),
+            ],
+            [],
+        ),
+
+        ("pq-contacts", ["add"]) => object([("name", string()), ("address", address())], []),
+        ("pq-contacts", ["list"]) => array(object(
+            [
+                ("name", string()),
+                ("address", address()),
+                ("ens", nullable(string())),
+                ("note", nullable(string())),
+            ],
+            [],
+        )),
+        ("pq-contacts", ["remove"]) => object(
+            [("address", address())],
+            [("ens", string()), ("note", string())],
+        ),
+        ("pq-contacts", ["resolve"]) => object(
+            [
+                ("address", address()),
+                ("contact", nullable(string())),
+                ("ens", nullable(string())),
+            ],
+            [],
+        ),
+
+        ("pq-delegate", ["issue"]) => object(
+            [
+                ("hash", hash()),
+                ("wallet", address()),
+                ("chainId", integer()),
+                ("robotKey", hash()),
+                ("validAfter", integer()),
+                ("validUntil", integer()),
+                ("policyHash", hash()),
+                ("path", string()),
+            ],
+            [],
+        ),
+        ("pq-delegate", ["revoke"]) => object(
+            [
+                ("hash", hash()),
+                ("wallet", address()),
+                ("revokedAt", nullable(integer())),
+                ("calldata", bytes()),
+                ("op", nullable(string())),
+            ],
+            [],
+        ),
+        ("pq-delegate", ["list"]) => object(
+            [(
+                "delegations",
+                array(object(
+                    [
+                        ("hash", hash()),
+                        ("status", string()),
+                        ("wallet", address()),
+                        ("chainId", integer()),
+                        ("robotKey", hash()),
+                        ("validUntil", integer()),
+                        ("revokedAt", nullable(integer())),
+                    ],
+                    [],
+                )),
+            )],
+            [],
+        ),
+
+        ("pq-deploy", ["address"]) => deploy_plan(),
+        ("pq-deploy", ["deploy"]) => object(
+            [("plan", deploy_plan()), ("alreadyDeployed", boolean())],
+            [
+                ("from", address()),
+                ("rawTransaction", bytes()),
+                ("transactionHash", nullable(hash())),
+            ],
+        ),
+
+        ("pq-devnet", []) => object(
+            [
+                ("listen", string()),
+                ("entryPoint", address()),
+                ("bundler", address()),
+            ],
+            [],
+        ),
+
+        ("pq-escrow", ["deposit"]) => object(
+            [
+                ("escrow", hash()),
+                ("keyId", address()),
+                ("threshold", integer()),
+                (
+                    "guardians",
+                    array(object(
+                        [
+                            ("index", integer()),
+                            ("label", string()),
+                            ("guardian", hash()),
+                        ],
+                        [],
+                    )),
+                ),
+                ("path", string()),
+            ],
+            [],
+        ),
+        ("pq-escrow", ["release"]) => object(
+            [
+                ("escrow", hash()),
+                ("index", integer()),
+                ("recipient", hash()),
+                ("path", string()),
+            ],
+            [],
+        ),
+        ("pq-escrow", ["recover"]) => object(
+            [
+                ("keyId", address()),
+                ("transcript", any()),
+                ("path", string()),
+            ],
+            [],
+        ),
+        ("pq-escrow", ["verify"]) => object([("valid", boolean()), ("transcript", any())], []),
+
+        ("pq-keygen", []) => any_of([
+            keygen(),
+            object(
+                [
+                    ("count", integer()),
+                    ("index", string()),
+                    ("elapsedMs", integer()),
+                ],
+                [],
+            ),
+        ]),
+        ("pq-keygen", ["ceremony", "commit"]) => commitment(),
+        ("pq-keygen", ["ceremony", "finalize"]) => extend(
+            keygen(),
+            [
+                ("ceremony", string()),
+                ("participants", integer()),
+                ("transcript", string()),
+            ],
+        ),
+        ("pq-keygen", ["ceremony", "verify"]) => object(
+            [
+                ("valid", boolean()),
+                ("problems", array(string())),
+                ("ceremony", string()),
+                ("keyId", address()),
+                ("fingerprint", hash()),
+                ("participants", array(commitment())),
+            ],
+            [],
+        ),
+
+        ("pq-release", ["attest"]) => object(
+            [
+                ("releaseKey", hash()),
+                ("release", string()),
+                (
+                    "artifacts",
+                    array(object(
+                        [("name", string()), ("size", integer()), ("sha256", hash())],
+                        [],
+                    )),
+                ),
+                ("path", string()),
+            ],
+            [],
+        ),
+        ("pq-release", ["sign-registry"]) => object(
+            [
+                ("releaseKey", hash()),
+                ("release", string()),
+                ("chains", array(integer())),
+                ("path", string()),
+            ],
+            [],
+        ),
+        ("pq-release", ["check-registry"]) => object(
+            [
+                ("valid", boolean()),
+                ("release", string()),
+                ("chainId", integer()),
+                (
+                    "contracts",
+                    array(object(
+                        [
+                            ("role", string()),
+                            ("address", address()),
+                            ("expected", hash()),
+                            ("status", one_of(&["ok", "mismatch", "missing"])),
+                        ],
+                        [("found", hash())],
+                    )),
+                ),
+            ],
+            [],
+        ),
+        ("pq-release", ["verify"]) => any_of([
+            object([("valid", boolean()), ("reason", string())], []),
+            object(
+                [
+                    ("valid", boolean()),
+                    ("releaseKey", hash()),
+                    ("release", string()),
+                    (
+                        "files",
+                        array(object(
+                            [
+                                ("name", string()),
+                                ("status", one_of(&["ok", "mismatch", "missing", "unlisted"])),
+                            ],
+                            [("sha256", hash())],
+                        )),
+                    ),
+                ],
+                [],
+            ),
+        ]),
+
+        ("pq-replay", []) => object(
+            [
+                ("transaction", hash()),
+                ("blockNumber", integer()),
+                ("chainId", integer()),
+                ("entryPoint", address()),
+                ("module", address()),
+                ("beneficiary", address()),
+                ("included", boolean()),
+                (
+                    "ops",
+                    array(object(
+                        [
+                            ("index", integer()),
+                            ("sender", address()),
+                            ("nonce", quantity()),
+                            ("userOpHash", hash()),
+                            ("loggedHash", nullable(hash())),
+                            ("success", nullable(boolean())),
+                            ("key", nullable(hash())),
+                            (
+                                "verdict",
+                                one_of(&["valid", "invalid", "valid-delegated", "no-key"]),
+                            ),
+                        ],
+                        [("divergence", string())],
+                    )),
+                ),
+            ],
+            [],
+        ),
+
+        ("pq-sign", [] | ["personal" | "siwe"]) => signature(),
+
+        ("pq-signerd", []) => object(
+            [
+                ("listening", string()),
+                ("tls", boolean()),
+                ("mtls", boolean()),
+                ("authenticated", boolean()),
+                ("accounts", array(string())),
+            ],
+            [],
+        ),
+
+        ("pq-threshold-coordinator", ["deal"]) => object(
+            [
+                ("address", address()),
+                ("fingerprint", hash()),
+                ("threshold", integer()),
+                ("parties", integer()),
+                ("path", string()),
+            ],
+            [],
+        ),
+        ("pq-threshold-coordinator", ["start"]) => object(
+            [
+                ("id", hash()),
+                ("group", hash()),
+                ("message", bytes()),
+                ("signers", array(integer())),
+                ("attempts", integer()),
+            ],
+            [],
+        ),
+        ("pq-threshold-coordinator", ["combine"]) => signature(),
+        ("pq-threshold-signer", ["commit" | "reveal" | "respond"]) => path(),
+
+        ("pq-userop", ["hash"]) => object([("userOpHash", hash())], []),
+        ("pq-userop", ["check"]) => object(
+            [
+                ("ok", boolean()),
+                (
+                    "findings",
+                    array(object(
+                        [
+                            ("severity", one_of(&["warning", "error"])),
+                            ("check", string()),
+                            ("message", string()),
+                        ],
+                        [("code", string())],
+                    )),
+                ),
+            ],
+            [],
+        ),
+        ("pq-userop", ["estimate"]) => object(
+            [
+                ("preVerificationGas", quantity()),
+                ("source", one_of(&["bundler", "local"])),
+                ("local", any()),
+                (
+                    "bundler",
+                    nullable(object(
+                        [
+                            ("preVerificationGas", quantity()),
+                            ("verificationGasLimit", quantity()),
+                            ("callGasLimit", quantity()),
+                            ("paymasterVerificationGasLimit", nullable(quantity())),
+                            ("paymasterPostOpGasLimit", nullable(quantity())),
+                        ],
+                        [],
+                    )),
+                ),
+                (
+                    "fees",
+                    nullable(object(
+                        [
+                            ("baseFee", integer()),
+                            ("maxPriorityFeePerGas", integer()),
+                            ("maxFeePerGas", integer()),
+                        ],
+                        [],
+                    )),
+                ),
+                (
+                    "cost",
+                    object(
+                        [
+                            ("gas", integer()),
+                            ("currentWei", integer()),
+                            ("maxWei", integer()),
+                        ],
+                        [],
+                    ),
+                ),
+                ("costUsd", nullable(number())),
+                ("maxCostUsd", nullable(number())),
+            ],
+            [],
+        ),
+        ("pq-userop", ["analyze"]) => object(
+            [
+                (
+                    "fields",
+                    array(object(
+                        [
+                            ("field", string()),
+                            ("bytes", integer()),
+                            ("encodedBytes", integer()),
+                            ("zeroBytes", integer()),
+                            ("calldataGas", integer()),
+                        ],
+                        [],
+                    )),
+                ),
+                ("encodedBytes", integer()),
+                ("zeroBytes", integer()),
+                ("calldataGas", integer()),
+                ("floor", boolean()),
+                ("placeholderSignature", boolean()),
+                ("preVerificationGas", integer()),
+                (
+                    "suggestions",
+                    array(object(
+                        [("field", string()), ("message", string())],
+                        [("savesGas", integer())],
+                    )),
+                ),
+            ],
+            [],
+        ),
+        ("pq-userop", ["rules"]) => object(
+            [
+                ("userOpHash", hash()),
+                (
+                    "violations",
+                    array(object(
+                        [
+                            ("rule", string()),
+                            ("entity", one_of(&["factory", "sender", "paymaster"])),
+                            ("contract", address()),
+                            ("opcode", string()),
+                            ("detail", string()),
+                        ],
+                        [],
+                    )),
+                ),
+            ],
+            [],
+        ),
+        ("pq-userop", ["submit"]) => any_of([
+            object([("userOpHash", hash())], []),
+            rejected(),
+            extend(status(), [("bundle", bundle())]),
+            array(queued()),
+        ]),
+        ("pq-userop", ["enqueue"]) => object(
+            [
+                ("id", json!({ "type": "integer" })),
+                ("userOpHash", hash()),
+                ("validAfter", nullable(integer())),
+            ],
+            [],
+        ),
+        ("pq-userop", ["bump"]) => any_of([
+            object(
+                [
+                    ("id", json!({ "type": "integer" })),
+                    ("replaces", hash()),
+                    ("userOpHash", hash()),
+                ],
+                [],
+            ),
+            rejected(),
+        ]),
+        ("pq-userop", ["queue"]) => array(queued()),
+        ("pq-userop", ["batch" | "send"]) => any_of([
+            array(object(
+                [
+                    ("account", string()),
+                    ("sender", address()),
+                    ("userOpHash", hash()),
+                ],
+                [("error", diagnostic()), ("status", status())],
+            )),
+            array(object(
+                [
+                    ("token", nullable(address())),
+                    ("holder", address()),
+                    ("allowance", boolean()),
+                    ("symbol", string()),
+                    ("decimals", integer()),
+                    ("needed", quantity()),
+                    ("available", quantity()),
+                ],
+                [],
+            )),
+        ]),
+        ("pq-userop", ["status"]) => {
+            let mut status = status();
+            status["properties"]["confirmation"] = confirmation();
+            status
+        }
+        ("pq-userop", ["bundlers"]) => array(object(
+            [
+                ("url", string()),
+                ("chainId", nullable(integer())),
+                ("entryPoints", array(address())),
+                ("latencyMs", nullable(integer())),
+                ("problem", nullable(string())),
+            ],
+            [],
+        )),
+
+        ("pq-verify", []) => any_of([
+            verification(),
+            object(
+                [
+                    (
+                        "results",
+                        array(object(
+                            [
+                                ("index", integer()),
+                                ("valid", nullable(boolean())),
+                                ("expected", boolean()),
+                                ("passed", boolean()),
+                            ],
+                            [("name", string()), ("error", string())],
+                        )),
+                    ),
+                    (
+                        "summary",
+                        object(
+                            [
+                                ("total", integer()),
+                                ("passed", integer()),
+                                ("failed", integer()),
+                                ("errors", integer()),
+                                ("elapsedMs", integer()),
+                                ("perSecond", number()),
+                            ],
+                            [("cache", any())],
+                        ),
+                    ),
+                ],
+                [],
+            ),
+        ]),
+        ("pq-verify", ["personal"]) => verification(),
+
+        // One line per event.
+        ("pq-watch", []) => object(
+            [
+                ("account", address()),
+                ("blockNumber", integer()),
+                ("transactionHash", hash()),
+                ("severity", one_of(&["info", "alert"])),
+                (
+                    "event",
+                    one_of(&[
+                        "user-operation",
+                        "module-installed",
+                        "module-uninstalled",
+                        "ownership-transferred",
+                    ]),
+                ),
+            ],
+            [
+                ("reason", string()),
+                ("userOpHash", hash()),
+                ("nonce", quantity()),
+                ("success", boolean()),
+                ("actualGasCost", quantity()),
+                ("revertReason", bytes()),
+                ("moduleType", integer()),
+                ("module", address()),
+                ("previousOwner", address()),
+                ("newOwner", address()),
+            ],
+        ),
+
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{Address, B256, U256};
+
+    use crate::bundler::{State, UserOpStatus};
+    use crate::output::{SignOutput, VerifyOutput};
+
+    /// Whether `value` fits `schema`, for the keywords [`output`] uses
+    /// (patterns aside).
+    fn fits(schema: &Value, value: &Value) -> bool {
+        if let Some(variants) = schema["anyOf"].as_array() {
+            return variants.iter().any(|s| fits(s, value));
+        }
+        if let Some(expected) = schema.get("const") {
+            return expected == value;
+        }
+        if let Some(values) = schema["enum"].as_array() {
+            return values.contains(value);
+        }
+        match schema["type"].as_str() {
+            None => true,
+            Some("null") => value.is_null(),
+            Some("boolean") => value.is_boolean(),
+            Some("string") => value.is_string(),
+            Some("number") => value.is_number(),
+            Some("integer") => value.is_u64() || value.is_i64(),
+            Some("array") => value
+                .as_array()
+                .is_some_and(|items| items.iter().all(|item| fits(&schema["items"], item))),
+            Some("object") => value.as_object().is_some_and(|fields| {
+                let required = schema["required"].as_array().into_iter().flatten();
+                required
+                    .clone()
+                    .all(|name| fields.contains_key(name.as_str().unwrap()))
+                    && fields
+                        .iter()
+                        .all(|(name, field)| match &schema["properties"][name] {
+                            Value::Null => schema["additionalProperties"]
+                                .as_object()
+                                .is_none_or(|_| fits(&schema["additionalProperties"], field)),
+                            property => fits(property, field),
+                        })
+            }),
+            Some(other) => panic!("unexpected type {other}"),
+        }
+    }
+
+    fn emitted(value: impl serde::Serialize) -> Value {
+        serde_json::from_str(&stamp(&serde_json::to_string(&value).unwrap())).unwrap()
+    }
+
+    #[test]
+    fn outputs_fit_their_schemas() {
+        let verify = output("pq-verify", &[]).unwrap();
+        assert!(fits(&verify, &emitted(VerifyOutput::invalid("bad"))));
+        assert!(
+            !fits(
+                &verify,
+                &serde_json::to_value(VerifyOutput::valid()).unwrap()
+            ),
+            "schemaVersion is required"
+        );
+        let sign = output("pq-sign", &["siwe"]).unwrap();
+        let mut signed = SignOutput::ml_dsa_65(&[1; 4]);
+        signed.message = Some("hi".into());
+        assert!(fits(&sign, &emitted(signed)));
+        assert!(fits(&sign, &emitted(json!({ "error": "no key" }))));
+
+        let status = UserOpStatus {
+            user_op_hash: B256::repeat_byte(1),
+            state: State::Included,
+            transaction_hash: Some(B256::repeat_byte(2)),
+            block_number: Some(U256::from(16)),
+            success: Some(true),
+            actual_gas_used: Some(U256::from(5)),
+            actual_gas_cost: None,
+            revert_reason: None,
+            timed_out: false,
+        };
+        let mut out = serde_json::to_value(&status).unwrap();
+        assert!(fits(
+            &output("pq-userop", &["status"]).unwrap(),
+            &emitted(&out)
+        ));
+        out["bundle"] = json!({
+            "transactionHash": B256::repeat_byte(3),
+            "from": Address::repeat_byte(4),
+            "beneficiary": Address::repeat_byte(4),
+            "nonce": 7, "gasLimit": 120000, "maxFeePerGas": 2,
+        });
+        assert!(fits(
+            &output("pq-userop", &["submit"]).unwrap(),
+            &emitted(out)
+        ));
+
+        let sign_op = output("pq-userop", &["sign"]).unwrap();
+        let op = crate::userop::PackedUserOperation {
+            sender: Address::repeat_byte(1),
+            nonce: U256::ZERO,
+            init_code: vec![],
+            call_data: vec![],
+            account_gas_limits: B256::ZERO,
+            pre_verification_gas: U256::ZERO,
+            gas_fees: B256::ZERO,
+            paymaster_and_data: vec![],
+            signature: vec![0xff; 4],
+        };
+        assert!(
+            fits(
+                &sign_op,
+                &serde_json::to_value(crate::userop::RpcUserOperation::from(op)).unwrap()
+            ),
+            "the signed op is printed as it is"
+        );
+        assert!(output("pq-acvp", &["run"]).is_none());
+    }
+
+    #[test]
+    fn stamp_versions_objects_and_array_items() {
+        assert_eq!(
+            stamp(r#"{"valid":true}"#),
+            r#"{"valid":true,"schemaVersion":1}"#
+        );
+        assert_eq!(stamp("{}"), r#"{"schemaVersion":1}"#);
+        assert_eq!(
+            stamp(r#"[{"a":{"b":[{}]},"c":"}\"{"},2,{}]"#),
+            r#"[{"a":{"b":[{}]},"c":"}\"{","schemaVersion":1},2,{"schemaVersion":1}]"#
+        );
+        assert_eq!(stamp("[]"), "[]");
+    }
+}
@@ -0,0 +1,323 @@
+//! Signature schemes by name, envelope ID and key/signature lengths, so a
+//! verifier can work out which one it was handed.
+//!
+//! [`SchemeRegistry::default`] knows ML-DSA-44, -65 and -87. Their key and
+//! signature lengths all differ, so a bare key and signature identify their
+//! scheme. [`SchemeRegistry::detect`] tries that first, then reads the input
+//! as a versioned, ABI or packed envelope, whose header or length names the
+//! scheme. Only ML-DSA-65 has an envelope scheme ID so far, since the
+//! validator understands no other. [`SchemeRegistry::register`] refuses a
+//! scheme whose name, ID or lengths would make detection ambiguous.
+
+use std::fmt;
+
+use ml_dsa::{EncodedVerifyingKey, MlDsa44, MlDsa65, MlDsa87, Signature, VerifyingKey};
+
+use crate::envelope::{self, Envelope, SCHEME_ML_DSA_65, SignatureEnvelope};
+use crate::error::{Result, WalletError};
+use crate::strict::EnvelopeLayout;
+
+/// A signature scheme a verifier can be dispatched to.
+pub trait Scheme: Send + Sync {
+    /// Name as printed and in JSON, e.g. `ml-dsa-65`.
+    fn name(&self) -> &'static str;
+    /// Envelope scheme ID, if the validator assigns one.
+    fn id(&self) -> Option<u8>;
+    fn public_key_len(&self) -> usize;
+    fn signature_len(&self) -> usize;
+    /// Verify `signature` over `message` with the empty context.
+    fn verify(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<bool>;
+}
+
+impl fmt::Debug for dyn Scheme + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+fn check_len(what: &'static str, expected: usize, actual: usize) -> Result<()> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(WalletError::InvalidLength {
+            what,
+            expected,
+            actual,
+        })
+    }
+}
+
+macro_rules! ml_dsa_scheme {
+    ($(#[$doc:meta])* $ty:ident, $params:ty, $name:literal, $id:expr, $pk:literal, $sig:literal) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+        pub struct $ty;
+
+        impl Scheme for $ty {
+            fn name(&self) -> &'static str {
+                $name
+            }
+
+            fn id(&self) -> Option<u8> {
+                $id
+            }
+
+            fn public_key_len(&self) -> usize {
+                $pk
+            }
+
+            fn signature_len(&self) -> usize {
+                $sig
+            }
+
+            fn verify(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<bool> {
+                check_len("public key", $pk, public_key.len())?;
+                check_len("signature", $sig, signature.len())?;
+                let pk = EncodedVerifyingKey::<$params>::try_from(public_key)
+                    .expect("length checked");
+                let Ok(sig) = Signature::<$params>::try_from(signature) else {
+                    return Ok(false);
+                };
+                Ok(VerifyingKey::<$params>::decode(&pk).verify_with_context(message, &[], &sig))
+            }
+        }
+    };
+}
+
+ml_dsa_scheme!(
+    /// ML-DSA-44 (NIST category 2).
+    MlDsa44Scheme, MlDsa44, "ml-dsa-44", None, 1312, 2420
+);
+ml_dsa_scheme!(
+    /// ML-DSA-65 (category 3), what the validator verifies.
+    MlDsa65Scheme, MlDsa65, "ml-dsa-65", Some(SCHEME_ML_DSA_65), 1952, 3309
+);
+ml_dsa_scheme!(
+    /// ML-DSA-87 (category 5).
+    MlDsa87Scheme, MlDsa87, "ml-dsa-87", None, 2592, 4627
+);
+
+/// What [`SchemeRegistry::detect`] found.
+#[derive(Debug)]
+pub struct Detected<'a> {
+    pub scheme: &'a dyn Scheme,
+    /// The bare signature, out of its envelope if it had one.
+    pub signature: Vec<u8>,
+    /// `None` for a bare signature.
+    pub envelope: Option<EnvelopeLayout>,
+}
+
+/// The schemes a verifier can dispatch to.
+pub struct SchemeRegistry {
+    schemes: Vec<Box<dyn Scheme>>,
+}
+
+impl Default for SchemeRegistry {
+    fn default() -> Self {
+        let mut registry = SchemeRegistry::empty();
+        for scheme in [
+            Box::new(MlDsa44Scheme) as Box<dyn Scheme>,
+            Box::new(MlDsa65Scheme),
+            Box::new(MlDsa87Scheme),
+        ] {
+            registry
+                .register(scheme)
+                .expect("built-in schemes are distinct");
+        }
+        registry
+    }
+}
+
+impl SchemeRegistry {
+    pub fn empty() -> Self {
+        SchemeRegistry {
+            schemes: Vec::new(),
+        }
+    }
+
+    /// Add a scheme, unless its name, ID or lengths clash with one already
+    /// registered.
+    pub fn register(&mut self, scheme: Box<dyn Scheme>) -> Result<()> {
+        for known in &self.schemes {
+            let clash = if known.name() == scheme.name() {
+                "name"
+            } else if known.id().is_some() && known.id() == scheme.id() {
+                "scheme ID"
+            } else if (known.public_key_len(), known.signature_len())
+                == (scheme.public_key_len(), scheme.signature_len())
+            {
+                "key and signature lengths"
+            } else {
+                continue;
+            };
+            return Err(WalletError::Config(format!(
+                "scheme {} has the same {clash} as {}",
+                scheme.name(),
+                known.name()
+            )));
+        }
+        self.schemes.push(scheme);
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Scheme> {
+        self.schemes.iter().map(|s| s.as_ref())
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&dyn Scheme> {
+        self.iter().find(|s| s.name() == name)
+    }
+
+    pub fn by_id(&self, id: u8) -> Option<&dyn Scheme> {
+        self.iter().find(|s| s.id() == Some(id))
+    }
+
+    pub fn by_lengths(&self, public_key_len: usize, signature_len: usize) -> Option<&dyn Scheme> {
+        self.iter()
+            .find(|s| (s.public_key_len(), s.signature_len()) == (public_key_len, signature_len))
+    }
+
+    fn opened(
+        &self,
+        public_key: &[u8],
+        opened: SignatureEnvelope,
+        layout: EnvelopeLayout,
+    ) -> Result<Detected<'_>> {
+        let scheme = self
+            .by_id(opened.scheme_id)
+            .ok_or(WalletError::UnknownScheme(opened.scheme_id))?;
+        if opened.public_key != public_key {
+            return Err(WalletError::InvalidEnvelope(format!(
+                "{layout} envelope carries a different public key"
+            )));
+        }
+        Ok(Detected {
+            scheme,
+            signature: opened.signature,
+            envelope: Some(layout),
+        })
+    }
+
+    /// Which scheme `public_key` and `data`, a bare signature or an
+    /// envelope around one, belong to.
+    pub fn detect(&self, public_key: &[u8], data: &[u8]) -> Result<Detected<'_>> {
+        if let Some(scheme) = self.by_lengths(public_key.len(), data.len()) {
+            return Ok(Detected {
+                scheme,
+                signature: data.to_vec(),
+                envelope: None,
+            });
+        }
+        match data.first() {
+            // A version byte; 0x00 starts an unversioned ABI envelope.
+            Some(1..) => {
+                if let Ok(env) = Envelope::decode(data) {
+                    return self.opened(public_key, env.pq().clone(), EnvelopeLayout::Versioned);
+                }
+            }
+            _ => {
+                if let Ok(env) = envelope::decode_signature_envelope(data) {
+                    return self.opened(public_key, env, EnvelopeLayout::Abi);
+                }
+            }
+        }
+        if data.len() == envelope::PACKED_ML_DSA_65_LEN {
+            let env = envelope::decode_signature_envelope_packed(data)?;
+            return self.opened(public_key, env, EnvelopeLayout::Packed);
+        }
+        let known: Vec<String> = self
+            .iter()
+            .map(|s| {
+                format!(
+                    "{} ({}/{})",
+                    s.name(),
+                    s.public_key_len(),
+                    s.signature_len()
+                )
+            })
+            .collect();
+        Err(WalletError::InvalidEnvelope(format!(
+            "no known scheme has a {}-byte key and a {}-byte signature or envelope; known: {}",
+            public_key.len(),
+            data.len(),
+            known.join(", ")
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::SEED_LEN;
+    use ml_dsa::{KeyGen, signature::Signer};
+
+    fn signed<P>(message: &[u8]) -> (Vec<u8>, Vec<u8>)
+    where
+        P: ml_dsa::MlDsaParams + KeyGen<KeyPair = ml_dsa::KeyPair<P>>,
+    {
+        let kp = P::from_seed(&[9u8; SEED_LEN].into());
+        let sig: Signature<P> = kp.signing_key().sign(message);
+        (kp.verifying_key().encode().to_vec(), sig.encode().to_vec())
+    }
+
+    #[test]
+    fn detects_each_parameter_set_from_lengths() {
+        let mut registry = SchemeRegistry::default();
+        for (name, (pk, sig)) in [
+            ("ml-dsa-44", signed::<MlDsa44>(b"msg")),
+            ("ml-dsa-65", signed::<MlDsa65>(b"msg")),
+            ("ml-dsa-87", signed::<MlDsa87>(b"msg")),
+        ] {
+            let found = registry.detect(&pk, &sig).unwrap();
+            assert_eq!(found.scheme.name(), name);
+            assert_eq!(found.envelope, None);
+            assert!(found.scheme.verify(&pk, b"msg", &found.signature).unwrap());
+            assert!(
+                !found
+                    .scheme
+                    .verify(&pk, b"other", &found.signature)
+                    .unwrap()
+            );
+        }
+
+        let (pk44, _) = signed::<MlDsa44>(b"msg");
+        let (_, sig65) = signed::<MlDsa65>(b"msg");
+        assert!(registry.detect(&pk44, &sig65).is_err());
+        assert!(registry.register(Box::new(MlDsa65Scheme)).is_err());
+    }
+
+    #[test]
+    fn reads_the_scheme_from_an_envelope() {
+        let registry = SchemeRegistry::default();
+        let (pk, sig) = signed::<MlDsa65>(b"msg");
+        for (data, layout) in [
+            (
+                envelope::encode_signature_envelope(&pk, &sig, SCHEME_ML_DSA_65).unwrap(),
+                EnvelopeLayout::Abi,
+            ),
+            (
+                envelope::encode_signature_envelope_packed(&pk, &sig).unwrap(),
+                EnvelopeLayout::Packed,
+            ),
+            (
+                Envelope::V1(SignatureEnvelope {
+                    scheme_id: SCHEME_ML_DSA_65,
+                    public_key: pk.clone(),
+                    signature: sig.clone(),
+                })
+                .encode()
+                .unwrap(),
+                EnvelopeLayout::Versioned,
+            ),
+        ] {
+            let found = registry.detect(&pk, &data).unwrap();
+            assert_eq!(found.scheme.name(), "ml-dsa-65");
+            assert_eq!(found.envelope, Some(layout));
+            assert_eq!(found.signature, sig);
+        }
+
+        let (other, _) = signed::<MlDsa87>(b"msg");
+        let packed = envelope::encode_signature_envelope_packed(&pk, &sig).unwrap();
+        assert!(registry.detect(&other[..1952], &packed).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+//! Sending UserOperations without a bundler, for `pq-userop submit
+//! --self-bundle`.
+//!
+//! Public bundlers cap op sizes, and an ML-DSA-65 op with its 3,309-byte
+//! signature can be over the cap. The CLI can then be its own bundler:
+//! [`send`] wraps the ops in `EntryPoint.handleOps(ops, beneficiary)`, signs
+//! that as an EIP-1559 transaction from a funded EOA and broadcasts it with
+//! `eth_sendRawTransaction`. The EOA pays the transaction's gas up front and
+//! the EntryPoint refunds it from the ops' prefund to `beneficiary`, so
+//! with any other beneficiary the EOA is out of pocket. An EOA delegated
+//! under EIP-7702 sends transactions with its own key as before, so it can
+//! be the sender too. [`wait`] then reads the op's `UserOperationEvent` from
+//! the transaction receipt, as a bundler's receipt would report it.
+//!
+//! Nothing here simulates: a `handleOps` that reverts still costs the
+//! sender gas, so simulate the op first.
+
+use std::time::{Duration, Instant};
+
+use alloy_primitives::{Address, B256, Bytes, U64, U128, U256};
+use k256::ecdsa::SigningKey;
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::bundler::{State, TransactionReceipt, UserOpStatus, UserOperationReceipt};
+use crate::delegation;
+use crate::deploy::Eip1559Transaction;
+use crate::error::{Result, WalletError};
+use crate::gas::cost;
+use crate::rpc::Transport;
+use crate::simulate;
+use crate::userop::PackedUserOperation;
+
+/// A `handleOps` transaction this process sent.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bundle {
+    pub transaction_hash: B256,
+    pub from: Address,
+    pub beneficiary: Address,
+    pub nonce: u64,
+    pub gas_limit: u64,
+    pub max_fee_per_gas: u128,
+}
+
+/// The unsigned `handleOps(ops, beneficiary)` transaction from `from`, with
+/// its gas estimated by `node` (plus a fifth) and fees from
+/// [`cost::suggest_fees`].
+pub fn transaction<T: Transport>(
+    node: &T,
+    chain_id: u64,
+    ops: &[PackedUserOperation],
+    entry_point: Address,
+    beneficiary: Address,
+    from: Address,
+) -> Result<Eip1559Transaction> {
+    let data = Bytes::from(simulate::encode_handle_ops(ops, beneficiary));
+    let nonce: U64 = node.call("eth_getTransactionCount", json!([from, "pending"]))?;
+    let estimate: U64 = node.call(
+        "eth_estimateGas",
+        json!([{ "from": from, "to": entry_point, "data": data }]),
+    )?;
+    let fees = cost::suggest_fees(node)?;
+    Ok(Eip1559Transaction {
+        chain_id: U256::from(chain_id),
+        nonce,
+        max_priority_fee_per_gas: U128::from(fees.max_priority_fee_per_gas),
+        max_fee_per_gas: U128::from(fees.max_fee_per_gas),
+        // Headroom for state that changes before inclusion.
+        gas_limit: U64::from(estimate.to::<u64>() * 6 / 5),
+        to: entry_point,
+        value: U256::ZERO,
+        data,
+    })
+}
+
+/// Sign the [`transaction`] for `ops` with `key` and broadcast it.
+pub fn send<T: Transport>(
+    node: &T,
+    chain_id: u64,
+    ops: &[PackedUserOperation],
+    entry_point: Address,
+    beneficiary: Address,
+    key: &SigningKey,
+) -> Result<Bundle> {
+    let from = delegation::address_of(key);
+    let transaction = transaction(node, chain_id, ops, entry_point, beneficiary, from)?;
+    let raw = transaction.sign(key)?;
+    let transaction_hash: B256 = node.call("eth_sendRawTransaction", json!([raw]))?;
+    tracing::debug!(%transaction_hash, %from, ops = ops.len(), "sent handleOps");
+    Ok(Bundle {
+        transaction_hash,
+        from,
+        beneficiary,
+        nonce: transaction.nonce.to::<u64>(),
+        gas_limit: transaction.gas_limit.to::<u64>(),
+        max_fee_per_gas: transaction.max_fee_per_gas.to::<u128>(),
+    })
+}
+
+/// Poll `node` every `interval` until `bundle` is mined or `timeout`
+/// elapses, then report `user_op_hash` from the receipt. A bundle still
+/// unmined at the timeout is `Pending` and `timed_out`.
+pub fn wait<T: Transport>(
+    node: &T,
+    bundle: &Bundle,
+    entry_point: Address,
+    user_op_hash: B256,
+    timeout: Duration,
+    interval: Duration,
+) -> Result<UserOpStatus> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let receipt = node.request(
+            "eth_getTransactionReceipt",
+            json!([bundle.transaction_hash]),
+        )?;
+        if !receipt.is_null() {
+            return outcome(receipt, bundle, entry_point, user_op_hash);
+        }
+        if Instant::now() >= deadline {
+            return Ok(UserOpStatus {
+                user_op_hash,
+                state: State::Pending,
+                transaction_hash: Some(bundle.transaction_hash),
+                block_number: None,
+                success: None,
+                actual_gas_used: None,
+                actual_gas_cost: None,
+                revert_reason: None,
+                timed_out: true,
+            });
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+fn outcome(
+    receipt: Value,
+    bundle: &Bundle,
+    entry_point: Address,
+    user_op_hash: B256,
+) -> Result<UserOpStatus> {
+    if receipt["status"] == json!("0x0") {
+        return Err(WalletError::Rpc(format!(
+            "bundle {} reverted; the sender paid its gas",
+            bundle.transaction_hash
+        )));
+    }
+    let receipt: TransactionReceipt = serde_json::from_value(receipt)
+        .map_err(|e| WalletError::Rpc(format!("eth_getTransactionReceipt: {e}")))?;
+    UserOpStatus::from_receipt(
+        user_op_hash,
+        &UserOperationReceipt {
+            user_op_hash,
+            entry_point,
+            receipt,
+            logs: Vec::new(),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::LogData;
+    use alloy_sol_types::SolEvent;
+    use std::sync::Mutex;
+
+    use crate::bundler::UserOperationEvent;
+    use crate::userop::ENTRY_POINT_V07 as EP;
+
+    /// A node that mines every raw transaction it is sent.
+    #[derive(Default)]
+    struct Node {
+        sent: Mutex<Vec<Bytes>>,
+    }
+
+    impl Transport for Node {
+        fn request(&self, method: &str, params: Value) -> Result<Value> {
+            let hash = B256::repeat_byte(0xAA);
+            Ok(match method {
+                "eth_getTransactionCount" => json!("0x7"),
+                "eth_estimateGas" => json!("0x186a0"),
+                "eth_getBlockByNumber" => json!({ "baseFeePerGas": "0x3b9aca00" }),
+                "eth_maxPriorityFeePerGas" => json!("0x1"),
+                "eth_sendRawTransaction" => {
+                    let raw: Bytes = serde_json::from_value(params[0].clone()).unwrap();
+                    self.sent.lock().unwrap().push(raw);
+                    json!(B256::repeat_byte(0xEE))
+                }
+                "eth_getTransactionReceipt" => {
+                    let event = UserOperationEvent {
+                        userOpHash: hash,
+                        sender: Address::repeat_byte(1),
+                        paymaster: Address::ZERO,
+                        nonce: U256::ZERO,
+                        success: true,
+                        actualGasCost: U256::from(5),
+                        actualGasUsed: U256::from(6),
+                    };
+                    let LogData { data, .. } = event.encode_log_data();
+                    let topics: Vec<B256> =
+                        event.encode_topics().into_iter().map(|t| t.0).collect();
+                    json!({
+                        "status": "0x1",
+                        "transactionHash": params[0],
+                        "blockNumber": "0x10",
+                        "logs": [{ "address": EP, "topics": topics, "data": data }],
+                    })
+                }
+                other => panic!("unexpected {other}"),
+            })
+        }
+    }
+
+    #[test]
+    fn sends_handle_ops_and_reads_the_event() {
+        let node = Node::default();
+        let key = delegation::signing_key_from_hex(&"11".repeat(32)).unwrap();
+        let op = PackedUserOperation {
+            sender: Address::repeat_byte(1),
+            nonce: U256::ZERO,
+            init_code: vec![],
+            call_data: vec![],
+            account_gas_limits: B256::ZERO,
+            pre_verification_gas: U256::ZERO,
+            gas_fees: B256::ZERO,
+            paymaster_and_data: vec![],
+            signature: vec![0xff; 3309],
+        };
+        let beneficiary = Address::repeat_byte(9);
+        let bundle = send(&node, 1, std::slice::from_ref(&op), EP, beneficiary, &key).unwrap();
+        assert_eq!(bundle.from, delegation::address_of(&key));
+        assert_eq!((bundle.nonce, bundle.gas_limit), (7, 120_000));
+        let sent = node.sent.lock().unwrap()[0].clone();
+        let data = simulate::encode_handle_ops(&[op], beneficiary);
+        assert!(sent.windows(data.len()).any(|w| w == data));
+
+        let status = wait(
+            &node,
+            &bundle,
+            EP,
+            B256::repeat_byte(0xAA),
+            Duration::ZERO,
+            Duration::ZERO,
+        )
+        .unwrap();
+        assert_eq!(status.state, State::Included);
+        assert_eq!(status.success, Some(true));
+        assert_eq!(status.transaction_hash, Some(B256::repeat_byte(0xEE)));
+    }
+}
@@ -0,0 +1,133 @@
+//! Shamir secret sharing over GF(2^8), for [`crate::escrow`].
+//!
+//! Each byte of the secret is the constant term of its own random
+//! polynomial of degree `threshold - 1`; share `x` holds every polynomial's
+//! value at `x`. Any `threshold` shares interpolate the secret back at zero,
+//! and fewer say nothing about it. Arithmetic uses the AES field polynomial
+//! and runs without secret-dependent branches or table lookups.
+
+use zeroize::Zeroizing;
+
+use crate::error::{Result, WalletError};
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::Escrow(reason.into())
+}
+
+/// One share: the evaluation point (1..=255) and the value there.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Share {
+    pub index: u8,
+    pub value: Zeroizing<Vec<u8>>,
+}
+
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        let carry = (a >> 7).wrapping_neg();
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    product
+}
+
+/// a^254, which is a^-1 for non-zero a.
+fn inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut power = a;
+    for bit in 0..8 {
+        if (254 >> bit) & 1 == 1 {
+            result = mul(result, power);
+        }
+        power = mul(power, power);
+    }
+    result
+}
+
+/// Split `secret` into `shares` shares, any `threshold` of which recover it.
+pub fn split(secret: &[u8], threshold: u8, shares: u8) -> Result<Vec<Share>> {
+    if threshold == 0 || threshold > shares {
+        return Err(err(format!(
+            "threshold must be 1..={shares}, not {threshold}"
+        )));
+    }
+    let mut out: Vec<Share> = (1..=shares)
+        .map(|index| Share {
+            index,
+            value: Zeroizing::new(vec![0; secret.len()]),
+        })
+        .collect();
+    let mut coefficients = Zeroizing::new(vec![0u8; threshold as usize]);
+    for (i, &byte) in secret.iter().enumerate() {
+        coefficients[0] = byte;
+        for c in &mut coefficients[1..] {
+            *c = rand::random();
+        }
+        for share in &mut out {
+            // Horner's rule, highest coefficient first.
+            share.value[i] = coefficients
+                .iter()
+                .rev()
+                .fold(0, |acc, &c| mul(acc, share.index) ^ c);
+        }
+    }
+    Ok(out)
+}
+
+/// Interpolate the secret from `shares`, which must have distinct non-zero
+/// indices and values of one length. With fewer shares than the threshold
+/// the result is unrelated to the secret.
+pub fn combine(shares: &[Share]) -> Result<Zeroizing<Vec<u8>>> {
+    let Some(first) = shares.first() else {
+        return Err(err("no shares"));
+    };
+    for (i, share) in shares.iter().enumerate() {
+        if share.index == 0 {
+            return Err(err("share index 0 is the secret itself"));
+        }
+        if share.value.len() != first.value.len() {
+            return Err(err("shares have different lengths"));
+        }
+        if shares[..i].iter().any(|s| s.index == share.index) {
+            return Err(err(format!("share {} given twice", share.index)));
+        }
+    }
+    let mut secret = Zeroizing::new(vec![0u8; first.value.len()]);
+    for share in shares {
+        // Lagrange basis at zero: product of x_j / (x_j - x_i); minus is xor.
+        let basis = shares
+            .iter()
+            .filter(|other| other.index != share.index)
+            .fold(1, |acc, other| {
+                mul(acc, mul(other.index, inv(other.index ^ share.index)))
+            });
+        for (byte, &value) in secret.iter_mut().zip(share.value.iter()) {
+            *byte ^= mul(basis, value);
+        }
+    }
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn any_threshold_of_the_shares_recover_the_secret() {
+        assert_eq!(mul(0x57, 0x83), 0xc1);
+        assert!((1..=255).all(|a| mul(a, inv(a)) == 1));
+
+        let secret: Vec<u8> = (0..32).collect();
+        let shares = split(&secret, 3, 5).unwrap();
+        for picked in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+            let subset: Vec<_> = picked.iter().map(|&i| shares[i].clone()).collect();
+            assert_eq!(*combine(&subset).unwrap(), secret);
+        }
+        assert_ne!(*combine(&shares[..2]).unwrap(), secret);
+        assert!(combine(&[shares[0].clone(), shares[0].clone()]).is_err());
+        assert!(split(&secret, 6, 5).is_err() && split(&secret, 0, 5).is_err());
+        let single = split(&secret, 1, 2).unwrap();
+        assert_eq!(*single[1].value, secret);
+    }
+}
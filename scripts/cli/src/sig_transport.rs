@@ -0,0 +1,374 @@
+//! Getting a 3,309-byte signature to the validator on calldata-constrained
+//! chains.
+//!
+//! - **inline**: the signature is `userOp.signature`, as today.
+//! - **chunked**: the signature is written ahead of time to a
+//!   content-addressed `SignatureStore` in `storeChunk` calls small enough
+//!   for the chain's limits, and the op carries a reference. Anyone can send
+//!   the writes (from a cheap session-key account, say): the store keys
+//!   chunks by `keccak256(signature)`, so a bad chunk just fails the final
+//!   hash check.
+//! - **blob**: the signature rides in an EIP-4844 blob and the op carries
+//!   the same kind of reference, for verifiers that resolve commitments
+//!   revealed through blobs.
+//!
+//! References are `tag || keccak256(sig) || len:u16` (plus `count:u8` for
+//! chunks) and are never 3,309 bytes long, so they can't be mistaken for
+//! an inline signature.
+
+use std::fmt;
+use std::str::FromStr;
+
+use alloy_primitives::{Address, B256, Bytes, keccak256};
+use alloy_sol_types::{SolCall, sol};
+
+use crate::calldata::Call;
+use crate::error::{Result, WalletError};
+
+/// First byte of a chunked reference.
+pub const TAG_CHUNKED: u8 = 0xC1;
+
+/// First byte of a blob reference.
+pub const TAG_BLOB: u8 = 0xB1;
+
+/// Default chunk payload size.
+pub const DEFAULT_CHUNK_SIZE: usize = 1024;
+
+/// Bytes in an EIP-4844 blob: 4096 field elements of 32 bytes.
+pub const BLOB_LEN: usize = 4096 * 32;
+
+/// Usable bytes per field element; the high byte is zero to stay below
+/// the BLS12-381 modulus.
+const FIELD_ELEMENT_DATA: usize = 31;
+
+sol! {
+    /// Content-addressed signature store the chunked transport writes to.
+    function storeChunk(bytes32 commitment, uint8 index, uint8 count, bytes data);
+}
+
+/// How the signature reaches the validator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SigTransport {
+    Inline,
+    Chunked { chunk_size: usize },
+    Blob,
+}
+
+impl fmt::Display for SigTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SigTransport::Inline => f.write_str("inline"),
+            SigTransport::Chunked { chunk_size } if *chunk_size == DEFAULT_CHUNK_SIZE => {
+                f.write_str("chunked")
+            }
+            SigTransport::Chunked { chunk_size } => write!(f, "chunked:{chunk_size}"),
+            SigTransport::Blob => f.write_str("blob"),
+        }
+    }
+}
+
+impl FromStr for SigTransport {
+    type Err = WalletError;
+
+    /// `inline`, `blob`, `chunked` or `chunked:SIZE`.
+    fn from_str(s: &str) -> Result<Self> {
+        const USAGE: &str = "signature transport must be inline, blob, chunked or chunked:SIZE";
+        match s.split_once(':') {
+            None => match s {
+                "inline" => Ok(SigTransport::Inline),
+                "blob" => Ok(SigTransport::Blob),
+                "chunked" => Ok(SigTransport::Chunked {
+                    chunk_size: DEFAULT_CHUNK_SIZE,
+                }),
+                _ => Err(WalletError::Usage(USAGE)),
+            },
+            Some(("chunked", size)) => match size.parse() {
+                Ok(chunk_size) if chunk_size > 0 => Ok(SigTransport::Chunked { chunk_size }),
+                _ => Err(WalletError::Usage(USAGE)),
+            },
+            Some(_) => Err(WalletError::Usage(USAGE)),
+        }
+    }
+}
+
+/// A decoded `userOp.signature`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Reference {
+    Inline(Vec<u8>),
+    Chunked {
+        commitment: B256,
+        len: u16,
+        count: u8,
+    },
+    Blob {
+        commitment: B256,
+        len: u16,
+    },
+}
+
+impl Reference {
+    /// Encode as `userOp.signature` bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Reference::Inline(sig) => sig.clone(),
+            Reference::Chunked {
+                commitment,
+                len,
+                count,
+            } => {
+                let mut out = vec![TAG_CHUNKED];
+                out.extend_from_slice(commitment.as_slice());
+                out.extend_from_slice(&len.to_be_bytes());
+                out.push(*count);
+                out
+            }
+            Reference::Blob { commitment, len } => {
+                let mut out = vec![TAG_BLOB];
+                out.extend_from_slice(commitment.as_slice());
+                out.extend_from_slice(&len.to_be_bytes());
+                out
+            }
+        }
+    }
+
+    /// Decode `userOp.signature`. Anything that isn't exactly a chunked or
+    /// blob reference is an inline signature.
+    pub fn decode(field: &[u8]) -> Reference {
+        let commitment = || B256::from_slice(&field[1..33]);
+        let len = || u16::from_be_bytes([field[33], field[34]]);
+        match (field.first(), field.len()) {
+            (Some(&TAG_CHUNKED), 36) => Reference::Chunked {
+                commitment: commitment(),
+                len: len(),
+                count: field[35],
+            },
+            (Some(&TAG_BLOB), 35) => Reference::Blob {
+                commitment: commitment(),
+                len: len(),
+            },
+            _ => Reference::Inline(field.to_vec()),
+        }
+    }
+}
+
+/// What [`pack`] produces: the op's signature field plus whatever must be
+/// published before the op is sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packed {
+    pub reference: Reference,
+    /// Chunk payloads, in index order (chunked only).
+    pub chunks: Vec<Vec<u8>>,
+    /// Blob contents (blob only).
+    pub blob: Option<Vec<u8>>,
+}
+
+fn length_u16(sig: &[u8]) -> Result<u16> {
+    u16::try_from(sig.len()).map_err(|_| WalletError::InvalidLength {
+        what: "signature for transport",
+        expected: u16::MAX as usize,
+        actual: sig.len(),
+    })
+}
+
+pub fn pack(transport: SigTransport, sig: &[u8]) -> Result<Packed> {
+    let commitment = keccak256(sig);
+    Ok(match transport {
+        SigTransport::Inline => Packed {
+            reference: Reference::Inline(sig.to_vec()),
+            chunks: Vec::new(),
+            blob: None,
+        },
+        SigTransport::Chunked { chunk_size } => {
+            let chunks: Vec<Vec<u8>> = sig.chunks(chunk_size.max(1)).map(<[u8]>::to_vec).collect();
+            let count = u8::try_from(chunks.len())
+                .map_err(|_| WalletError::Usage("chunk size too small: more than 255 chunks"))?;
+            Packed {
+                reference: Reference::Chunked {
+                    commitment,
+                    len: length_u16(sig)?,
+                    count,
+                },
+                chunks,
+                blob: None,
+            }
+        }
+        SigTransport::Blob => Packed {
+            reference: Reference::Blob {
+                commitment,
+                len: length_u16(sig)?,
+            },
+            chunks: Vec::new(),
+            blob: Some(encode_blob(sig)?),
+        },
+    })
+}
+
+/// `storeChunk` calls to `store` that publish `packed`'s chunks.
+pub fn chunk_calls(store: Address, packed: &Packed) -> Vec<Call> {
+    let Reference::Chunked {
+        commitment, count, ..
+    } = packed.reference
+    else {
+        return Vec::new();
+    };
+    packed
+        .chunks
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| Call {
+            target: store,
+            value: Default::default(),
+            data: storeChunkCall {
+                commitment,
+                index: index as u8,
+                count,
+                data: Bytes::copy_from_slice(chunk),
+            }
+            .abi_encode(),
+        })
+        .collect()
+}
+
+fn check_commitment(reference: &Reference, sig: Vec<u8>) -> Result<Vec<u8>> {
+    let (commitment, len) = match reference {
+        Reference::Inline(_) => return Ok(sig),
+        Reference::Chunked {
+            commitment, len, ..
+        }
+        | Reference::Blob { commitment, len } => (*commitment, *len),
+    };
+    if sig.len() != len as usize {
+        return Err(WalletError::InvalidLength {
+            what: "reassembled signature",
+            expected: len as usize,
+            actual: sig.len(),
+        });
+    }
+    if keccak256(&sig) != commitment {
+        return Err(WalletError::InvalidEnvelope(
+            "reassembled signature does not match its commitment".into(),
+        ));
+    }
+    Ok(sig)
+}
+
+/// Recover the signature from a reference and the published chunks
+/// (chunked) or blob (blob), checking it against the commitment.
+pub fn unpack(reference: &Reference, chunks: &[Vec<u8>], blob: Option<&[u8]>) -> Result<Vec<u8>> {
+    let sig = match reference {
+        Reference::Inline(sig) => sig.clone(),
+        Reference::Chunked { count, .. } => {
+            if chunks.len() != *count as usize {
+                return Err(WalletError::InvalidEnvelope(format!(
+                    "expected {count} chunks, got {}",
+                    chunks.len()
+                )));
+            }
+            chunks.concat()
+        }
+        Reference::Blob { len, .. } => {
+            let blob = blob.ok_or_else(|| WalletError::InvalidEnvelope("blob missing".into()))?;
+            decode_blob(blob, *len as usize)?
+        }
+    };
+    check_commitment(reference, sig)
+}
+
+/// Lay `data` out in blob field elements, 31 bytes each, zero-padded.
+pub fn encode_blob(data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() > BLOB_LEN / 32 * FIELD_ELEMENT_DATA {
+        return Err(WalletError::InvalidLength {
+            what: "blob payload",
+            expected: BLOB_LEN / 32 * FIELD_ELEMENT_DATA,
+            actual: data.len(),
+        });
+    }
+    let mut blob = vec![0u8; BLOB_LEN];
+    for (element, chunk) in blob.chunks_mut(32).zip(data.chunks(FIELD_ELEMENT_DATA)) {
+        element[1..1 + chunk.len()].copy_from_slice(chunk);
+    }
+    Ok(blob)
+}
+
+/// Inverse of [`encode_blob`], returning the first `len` payload bytes.
+pub fn decode_blob(blob: &[u8], len: usize) -> Result<Vec<u8>> {
+    if blob.len() != BLOB_LEN {
+        return Err(WalletError::InvalidLength {
+            what: "blob",
+            expected: BLOB_LEN,
+            actual: blob.len(),
+        });
+    }
+    let mut out: Vec<u8> = blob
+        .chunks(32)
+        .flat_map(|e| e[1..].iter().copied())
+        .collect();
+    if len > out.len() {
+        return Err(WalletError::InvalidEnvelope(
+            "blob shorter than reference".into(),
+        ));
+    }
+    out.truncate(len);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::SIGNATURE_LEN;
+
+    fn sig() -> Vec<u8> {
+        (0..SIGNATURE_LEN).map(|i| (i * 13) as u8).collect()
+    }
+
+    #[test]
+    fn chunked_round_trips_and_checks_commitment() {
+        let sig = sig();
+        let packed = pack("chunked:1000".parse().unwrap(), &sig).unwrap();
+        assert_eq!(packed.chunks.len(), 4);
+        let field = packed.reference.encode();
+        assert_eq!(field.len(), 36);
+        assert_eq!(Reference::decode(&field), packed.reference);
+        assert_eq!(
+            unpack(&packed.reference, &packed.chunks, None).unwrap(),
+            sig
+        );
+
+        let mut tampered = packed.chunks.clone();
+        tampered[2][0] ^= 1;
+        assert!(unpack(&packed.reference, &tampered, None).is_err());
+        assert!(unpack(&packed.reference, &packed.chunks[..3], None).is_err());
+
+        let calls = chunk_calls(Address::repeat_byte(0x55), &packed);
+        assert_eq!(calls.len(), 4);
+        let first = storeChunkCall::abi_decode(&calls[0].data).unwrap();
+        assert_eq!((first.index, first.count), (0, 4));
+        assert_eq!(first.commitment, keccak256(&sig));
+    }
+
+    #[test]
+    fn blob_round_trips() {
+        let sig = sig();
+        let packed = pack(SigTransport::Blob, &sig).unwrap();
+        let blob = packed.blob.as_deref().unwrap();
+        assert_eq!(blob.len(), BLOB_LEN);
+        // Every field element keeps its high byte clear.
+        assert!(blob.chunks(32).all(|e| e[0] == 0));
+        assert_eq!(unpack(&packed.reference, &[], Some(blob)).unwrap(), sig);
+        assert!(unpack(&packed.reference, &[], None).is_err());
+    }
+
+    #[test]
+    fn inline_is_untouched_and_never_confused_with_a_reference() {
+        let mut sig = sig();
+        sig[0] = TAG_CHUNKED;
+        let packed = pack(SigTransport::Inline, &sig).unwrap();
+        assert_eq!(packed.reference.encode(), sig);
+        assert_eq!(Reference::decode(&sig), Reference::Inline(sig.clone()));
+        assert!("chunked:0".parse::<SigTransport>().is_err());
+        assert_eq!(
+            "chunked".parse::<SigTransport>().unwrap().to_string(),
+            "chunked"
+        );
+    }
+}
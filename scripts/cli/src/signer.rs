@@ -0,0 +1,250 @@
+//! An ML-DSA-65 signing key expanded once and kept in one heap block.
+//!
+//! `SigningKey::<MlDsa65>` has the secret vectors, their NTT forms and the
+//! expanded matrix in about 50 KB. Expanding one per signature costs
+//! time, and every move of the struct is a copy of those bytes. Keeping
+//! it in a `Vec`, `Arc::new` of a stack value or a `clone()` leaves one
+//! more copy behind. [`PqSigner`] expands the key straight into a pinned
+//! box. It never clones or moves the key afterwards, and lends it out by
+//! reference. Moving the signer moves only the pointer. On drop it runs
+//! the key's own `zeroize`, which skips the NTT forms of the secret
+//! vectors, and then zeroes every byte of the block before freeing it. The
+//! expansion itself still runs in a stack frame, which this does not wipe.
+
+use std::fmt;
+use std::mem::{ManuallyDrop, MaybeUninit};
+use std::pin::Pin;
+
+use ml_dsa::signature::Signer as _;
+use ml_dsa::{MlDsa65, Signature, SigningKey, VerifyingKey};
+use rand::CryptoRng;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::error::{Result, WalletError};
+use crate::keys::SEED_LEN;
+
+pub struct PqSigner {
+    key: Pin<Box<ManuallyDrop<SigningKey<MlDsa65>>>>,
+}
+
+impl PqSigner {
+    /// Expand `seed` (ML-DSA.KeyGen_internal). The caller keeps, and
+    /// wipes, the seed.
+    pub fn from_seed(seed: &[u8; SEED_LEN]) -> Self {
+        PqSigner {
+            key: Box::pin(ManuallyDrop::new(SigningKey::from_seed(&(*seed).into()))),
+        }
+    }
+
+    pub fn signing_key(&self) -> &SigningKey<MlDsa65> {
+        &self.key
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey<MlDsa65> {
+        self.key.verifying_key()
+    }
+
+    /// Deterministic pure ML-DSA over `message` with an empty context, the
+    /// signature `pq-sign` produces.
+    pub fn sign(&self, message: &[u8]) -> Signature<MlDsa65> {
+        self.key.sign(message)
+    }
+
+    /// ML-DSA.Sign with context `ctx` and per-signature randomness `rnd`,
+    /// as [`crate::backend::Backend::sign`] takes them.
+    pub fn sign_with(&self, message: &[u8], ctx: &[u8], rnd: &[u8; 32]) -> Result<Vec<u8>> {
+        if ctx.len() > 255 {
+            return Err(WalletError::InvalidLength {
+                what: "context",
+                expected: 255,
+                actual: ctx.len(),
+            });
+        }
+        // M' = 0 || |ctx| || ctx || M  (FIPS 204, Algorithm 2)
+        let header = [0u8, ctx.len() as u8];
+        let sig = self
+            .key
+            .sign_internal(&[&header, ctx, message], &(*rnd).into());
+        Ok(sig.encode().to_vec())
+    }
+
+    /// Hedged ML-DSA.Sign, with `rnd` drawn from `rng`. A seeded rng makes
+    /// the signature reproducible, as ACVP sigGen expects.
+    pub fn sign_with_rng<R: CryptoRng + ?Sized>(
+        &self,
+        message: &[u8],
+        ctx: &[u8],
+        rng: &mut R,
+    ) -> Result<Vec<u8>> {
+        let mut rnd = Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(rnd.as_mut());
+        self.sign_with(message, ctx, &rnd)
+    }
+}
+
+impl Drop for PqSigner {
+    fn drop(&mut self) {
+        // SAFETY: the key is dropped here and nowhere else, and never used
+        // again. Afterwards its block is only overwritten, as bytes that
+        // `MaybeUninit` allows to be anything.
+        unsafe {
+            let key = Pin::get_unchecked_mut(self.key.as_mut());
+            ManuallyDrop::drop(key);
+            std::slice::from_raw_parts_mut(
+                (key as *mut ManuallyDrop<SigningKey<MlDsa65>>).cast::<MaybeUninit<u8>>(),
+                size_of::<SigningKey<MlDsa65>>(),
+            )
+            .zeroize();
+        }
+    }
+}
+
+impl fmt::Debug for PqSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PqSigner").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::{Cell, RefCell};
+
+    /// Allocations a tracking thread can have live at once.
+    const SLOTS: usize = 512;
+
+    /// Records the live allocations of threads that opt in. It counts the
+    /// blocks freed while they still hold `NEEDLE`, and the key-sized
+    /// blocks freed without being zeroed. Other threads (the rest of the
+    /// test suite) only pay for a thread-local read.
+    struct Tracking;
+
+    thread_local! {
+        static TRACKING: Cell<bool> = const { Cell::new(false) };
+        static NEEDLE: Cell<[u8; 32]> = const { Cell::new([0; 32]) };
+        static LIVE: RefCell<[(usize, usize); SLOTS]> = const { RefCell::new([(0, 0); SLOTS]) };
+        static TRACKED: Cell<usize> = const { Cell::new(0) };
+        static OVERFLOWED: Cell<bool> = const { Cell::new(false) };
+        static FREED_UNWIPED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn contains(block: &[u8], needle: &[u8; 32]) -> bool {
+        block.windows(32).any(|w| w == needle)
+    }
+
+    unsafe impl GlobalAlloc for Tracking {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = unsafe { System.alloc(layout) };
+            if !ptr.is_null() && TRACKING.try_with(Cell::get).unwrap_or(false) {
+                LIVE.with_borrow_mut(|live| match live.iter_mut().find(|s| s.0 == 0) {
+                    Some(slot) => {
+                        *slot = (ptr as usize, layout.size());
+                        TRACKED.set(TRACKED.get() + 1);
+                    }
+                    None => OVERFLOWED.set(true),
+                });
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            if TRACKED.try_with(Cell::get).unwrap_or(0) > 0 {
+                LIVE.with_borrow_mut(|live| {
+                    if let Some(slot) = live.iter_mut().find(|s| s.0 == ptr as usize) {
+                        // SAFETY: the block is still allocated, and `size`
+                        // bytes long.
+                        let block = unsafe { std::slice::from_raw_parts(ptr, slot.1) };
+                        let key_sized = slot.1 == size_of::<SigningKey<MlDsa65>>();
+                        if contains(block, &NEEDLE.get())
+                            || key_sized && block.iter().any(|b| *b != 0)
+                        {
+                            FREED_UNWIPED.set(FREED_UNWIPED.get() + 1);
+                        }
+                        *slot = (0, 0);
+                        TRACKED.set(TRACKED.get() - 1);
+                    }
+                });
+            }
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Tracking = Tracking;
+
+    /// Live allocations of this thread that hold `NEEDLE`.
+    fn live_holders() -> usize {
+        LIVE.with_borrow(|live| {
+            live.iter()
+                .filter(|(ptr, _)| *ptr != 0)
+                .filter(|(ptr, size)| {
+                    // SAFETY: tracked blocks are live until dealloc clears
+                    // their slot, and only this thread frees them.
+                    let block = unsafe { std::slice::from_raw_parts(*ptr as *const u8, *size) };
+                    contains(block, &NEEDLE.get())
+                })
+                .count()
+        })
+    }
+
+    #[test]
+    fn the_expanded_key_lives_in_one_allocation_and_is_wiped() {
+        let seed = [0x5a; SEED_LEN];
+        // K, the 32-byte signing secret at bytes 32..64 of the encoded key,
+        // from an expansion made on the stack before tracking starts.
+        #[allow(deprecated)]
+        let reference = crate::keys::keygen_from_seed(&seed)
+            .signing_key()
+            .to_expanded();
+        let needle: [u8; 32] = reference[32..64].try_into().unwrap();
+        NEEDLE.set(needle);
+        TRACKING.set(true);
+
+        let signer = PqSigner::from_seed(&seed);
+        // Moving the signer around must not copy the key.
+        let signers = vec![signer];
+        let signer = signers.into_iter().next().unwrap();
+        let vk = signer.verifying_key();
+        for i in 0..8u8 {
+            assert!(vk.verify_with_context(&[i], &[], &signer.sign(&[i])));
+            signer.sign_with(&[i], b"ctx", &[i; 32]).unwrap();
+        }
+        let holders = live_holders();
+        drop(signer);
+        TRACKING.set(false);
+
+        assert!(!OVERFLOWED.get(), "raise SLOTS");
+        assert_eq!(holders, 1, "the secret is in {holders} live allocations");
+        assert_eq!(FREED_UNWIPED.get(), 0, "a block was freed unwiped");
+    }
+
+    #[test]
+    #[cfg(feature = "fips204")]
+    fn matches_the_backend_signatures() {
+        use crate::backend::Backend;
+        let seed = [3u8; SEED_LEN];
+        let signer = PqSigner::from_seed(&seed);
+        let msg = [0xAB_u8; 32];
+        for rnd in [[0u8; 32], [7u8; 32]] {
+            assert_eq!(
+                signer.sign_with(&msg, b"ctx", &rnd).unwrap(),
+                Backend::Fips204.sign(&seed, &msg, b"ctx", &rnd).unwrap()
+            );
+        }
+        assert_eq!(
+            signer.sign(&msg).encode().to_vec(),
+            signer.sign_with(&msg, &[], &[0u8; 32]).unwrap()
+        );
+        assert!(signer.sign_with(&msg, &[0; 256], &[0; 32]).is_err());
+
+        use rand::SeedableRng;
+        let rng = || rand::rngs::StdRng::seed_from_u64(9);
+        assert_eq!(
+            signer.sign_with_rng(&msg, b"ctx", &mut rng()).unwrap(),
+            Backend::Fips204
+                .sign_with_rng(&seed, &msg, b"ctx", &mut rng())
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! What the ML-DSA message is when signing a UserOperation.
+//!
+//! The deployed validator decides how a userOp becomes the bytes it checks
+//! the signature over, and the signer has to agree with it exactly:
+//!
+//! - [`SigningScheme::HashAsMessage`] (the default, and what
+//!   `PQValidatorModule` does) signs the 32-byte userOpHash as a pure
+//!   ML-DSA message.
+//! - [`SigningScheme::RawMessage`] signs the whole serialized op
+//!   ([`serialize_op`]) as a pure ML-DSA message.
+//! - [`SigningScheme::Prehash`] signs the serialized op with HashML-DSA
+//!   (FIPS 204, Algorithm 4): the message is `PH(op)`, domain-separated
+//!   from pure signatures by a leading `1` and the hash's OID.
+//!
+//! All three use the empty context and deterministic signing. The scheme is
+//! chosen per chain: `--signing-scheme`, `signing_scheme` under a
+//! `[networks.*]` entry in `pq-wallet.toml`, or a keystore account's
+//! per-chain `signingSchemes`.
+
+use std::fmt;
+
+use alloy_primitives::{Address, B256, Bytes, U256};
+use alloy_sol_types::SolValue;
+use ml_dsa::{MlDsa65, Signature, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::error::WalletError;
+use crate::userop::{self, PackedUserOperation};
+
+/// DER-encoded OIDs of the HashML-DSA pre-hash functions (FIPS 204, §5.4).
+const OID_SHA256: [u8; 11] = [
+    0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01,
+];
+const OID_SHA512: [u8; 11] = [
+    0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03,
+];
+
+/// Pre-hash function for [`SigningScheme::Prehash`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreHash {
+    Sha256,
+    Sha512,
+}
+
+impl PreHash {
+    fn oid(self) -> &'static [u8] {
+        match self {
+            PreHash::Sha256 => &OID_SHA256,
+            PreHash::Sha512 => &OID_SHA512,
+        }
+    }
+
+    fn digest(self, message: &[u8]) -> Vec<u8> {
+        match self {
+            PreHash::Sha256 => Sha256::digest(message).to_vec(),
+            PreHash::Sha512 => Sha512::digest(message).to_vec(),
+        }
+    }
+}
+
+/// How a UserOperation becomes the ML-DSA message.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SigningScheme {
+    #[default]
+    HashAsMessage,
+    Prehash(PreHash),
+    RawMessage,
+}
+
+impl fmt::Display for SigningScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SigningScheme::HashAsMessage => "hash",
+            SigningScheme::Prehash(PreHash::Sha256) => "prehash-sha256",
+            SigningScheme::Prehash(PreHash::Sha512) => "prehash-sha512",
+            SigningScheme::RawMessage => "raw",
+        })
+    }
+}
+
+impl std::str::FromStr for SigningScheme {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self, WalletError> {
+        match s {
+            "hash" | "hash-as-message" => Ok(SigningScheme::HashAsMessage),
+            "prehash-sha256" => Ok(SigningScheme::Prehash(PreHash::Sha256)),
+            "prehash" | "prehash-sha512" => Ok(SigningScheme::Prehash(PreHash::Sha512)),
+            "raw" | "raw-message" => Ok(SigningScheme::RawMessage),
+            other => Err(WalletError::Config(format!(
+                "unknown signing scheme {other:?} (expected hash, prehash-sha256, \
+                 prehash-sha512 or raw)"
+            ))),
+        }
+    }
+}
+
+impl TryFrom<String> for SigningScheme {
+    type Error = WalletError;
+
+    fn try_from(s: String) -> Result<Self, WalletError> {
+        s.parse()
+    }
+}
+
+impl From<SigningScheme> for String {
+    fn from(scheme: SigningScheme) -> Self {
+        scheme.to_string()
+    }
+}
+
+/// The serialized op the raw and prehash schemes sign: every field but the
+/// signature, in full, bound to the EntryPoint and chain —
+/// `abi.encode(sender, nonce, initCode, callData, accountGasLimits,
+/// preVerificationGas, gasFees, paymasterAndData, entryPoint, chainId)`.
+pub fn serialize_op(op: &PackedUserOperation, entry_point: Address, chain_id: U256) -> Vec<u8> {
+    (
+        op.sender,
+        op.nonce,
+        Bytes::copy_from_slice(&op.init_code),
+        Bytes::copy_from_slice(&op.call_data),
+        op.account_gas_limits,
+        op.pre_verification_gas,
+        op.gas_fees,
+        Bytes::copy_from_slice(&op.paymaster_and_data),
+        entry_point,
+        chain_id,
+    )
+        .abi_encode_params()
+}
+
+impl SigningScheme {
+    /// `M'` as FIPS 204 feeds it to ML-DSA.Sign_internal.
+    fn formatted_message(
+        self,
+        op: &PackedUserOperation,
+        entry_point: Address,
+        chain_id: U256,
+    ) -> Vec<u8> {
+        // Pure ML-DSA with an empty context: 0 || 0 || M.
+        let mut out = vec![0u8, 0];
+        match self {
+            SigningScheme::HashAsMessage => {
+                out.extend(userop::compute_user_op_hash(op, entry_point, chain_id).as_slice())
+            }
+            SigningScheme::RawMessage => out.extend(serialize_op(op, entry_point, chain_id)),
+            SigningScheme::Prehash(ph) => {
+                out[0] = 1;
+                out.extend(ph.oid());
+                out.extend(ph.digest(&serialize_op(op, entry_point, chain_id)));
+            }
+        }
+        out
+    }
+
+    /// Sign `op` the way a validator expecting this scheme verifies it.
+    pub fn sign(
+        self,
+        sk: &SigningKey<MlDsa65>,
+        op: &PackedUserOperation,
+        entry_point: Address,
+        chain_id: U256,
+    ) -> Signature<MlDsa65> {
+        let message = self.formatted_message(op, entry_point, chain_id);
+        sk.sign_internal(&[&message], &B256::ZERO.0.into())
+    }
+
+    /// Verify `sig` over `op` under this scheme.
+    pub fn verify(
+        self,
+        vk: &VerifyingKey<MlDsa65>,
+        op: &PackedUserOperation,
+        entry_point: Address,
+        chain_id: U256,
+        sig: &Signature<MlDsa65>,
+    ) -> bool {
+        vk.verify_internal(&self.formatted_message(op, entry_point, chain_id), sig)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "fips204")]
+    use crate::keys;
+    #[cfg(feature = "fips204")]
+    use fips204::ml_dsa_65;
+    #[cfg(feature = "fips204")]
+    use fips204::traits::{SerDes, Verifier as _};
+    #[cfg(feature = "fips204")]
+    use ml_dsa::signature::Signer;
+
+    #[cfg(feature = "fips204")]
+    fn op() -> PackedUserOperation {
+        PackedUserOperation {
+            sender: Address::repeat_byte(0x11),
+            nonce: U256::from(7),
+            init_code: vec![],
+            call_data: vec![0xb6, 0x1d, 0x27, 0xf6],
+            account_gas_limits: B256::repeat_byte(0x01),
+            pre_verification_gas: U256::from(60_000),
+            gas_fees: B256::repeat_byte(0x02),
+            paymaster_and_data: vec![],
+            signature: vec![],
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fips204")]
+    fn schemes_match_their_reference_constructions() {
+        let kp = keys::keygen_from_seed(&[5; 32]);
+        let (sk, vk) = (kp.signing_key(), kp.verifying_key());
+        let (op, ep, chain) = (op(), userop::ENTRY_POINT_V07, U256::from(1));
+        let pk = ml_dsa_65::PublicKey::try_from_bytes(vk.encode().into()).unwrap();
+
+        // Hash-as-message is what `pq-userop sign` has always produced.
+        let hash = userop::compute_user_op_hash(&op, ep, chain);
+        let sig = SigningScheme::HashAsMessage.sign(sk, &op, ep, chain);
+        assert_eq!(sig, sk.sign(hash.as_slice()));
+
+        let raw = SigningScheme::RawMessage.sign(sk, &op, ep, chain);
+        let serialized = serialize_op(&op, ep, chain);
+        assert!(vk.verify_with_context(&serialized, &[], &raw));
+        // Ten head words, three length words and callData's one-word body.
+        assert_eq!(serialized.len(), 32 * (10 + 3 + 1));
+
+        // HashML-DSA, checked against fips204's independent implementation.
+        for (ph, fips) in [
+            (PreHash::Sha256, fips204::Ph::SHA256),
+            (PreHash::Sha512, fips204::Ph::SHA512),
+        ] {
+            let scheme = SigningScheme::Prehash(ph);
+            let sig = scheme.sign(sk, &op, ep, chain);
+            let bytes: [u8; keys::SIGNATURE_LEN] = sig.encode().into();
+            assert!(pk.hash_verify(&serialized, &bytes, &[], &fips));
+            assert!(scheme.verify(vk, &op, ep, chain, &sig));
+            // A prehash signature is not a pure signature over anything.
+            assert!(!SigningScheme::RawMessage.verify(vk, &op, ep, chain, &sig));
+        }
+    }
+
+    #[test]
+    fn schemes_parse_and_serialize_by_name() {
+        for scheme in [
+            SigningScheme::HashAsMessage,
+            SigningScheme::Prehash(PreHash::Sha256),
+            SigningScheme::Prehash(PreHash::Sha512),
+            SigningScheme::RawMessage,
+        ] {
+            assert_eq!(scheme.to_string().parse::<SigningScheme>().unwrap(), scheme);
+            let json = serde_json::to_string(&scheme).unwrap();
+            assert_eq!(
+                serde_json::from_str::<SigningScheme>(&json).unwrap(),
+                scheme
+            );
+        }
+        assert_eq!(
+            "prehash".parse::<SigningScheme>().unwrap(),
+            SigningScheme::Prehash(PreHash::Sha512)
+        );
+        assert!("sha3".parse::<SigningScheme>().is_err());
+    }
+}
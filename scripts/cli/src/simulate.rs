@@ -0,0 +1,521 @@
+//! Pre-submission simulation of a UserOperation against a node.
+//!
+//! Two strategies, both plain `eth_call`s:
+//!
+//! - [`simulate_handle_ops`] calls `EntryPoint.handleOps([op], beneficiary)`
+//!   directly. It needs nothing but the deployed EntryPoint and surfaces the
+//!   same `FailedOp` reverts a bundler would hit (`AA23`, `AA24 signature
+//!   error`, paymaster `AA3x`, ...).
+//! - [`simulate_validation`] runs v0.7's `EntryPointSimulations
+//!   .simulateValidation` by overriding the EntryPoint's code in the call's
+//!   state overrides. It additionally returns the decoded validation data,
+//!   which is how v0.7 reports a bad signature without reverting.
+//!
+//! Either way a revert is decoded into a [`Revert`] so the CLI can print a
+//! readable reason.
+
+use std::fmt;
+
+use alloy_primitives::{Address, B256, Bytes, U64, U256};
+use alloy_sol_types::{SolCall, SolInterface, SolValue, sol};
+use serde::Serialize;
+
+use crate::error::{Result, WalletError};
+use crate::rpc::{self, Transport};
+use crate::userop::{self, PackedUserOperation};
+
+pub(crate) mod abi {
+    alloy_sol_types::sol! {
+        struct PackedUserOperation {
+            address sender;
+            uint256 nonce;
+            bytes initCode;
+            bytes callData;
+            bytes32 accountGasLimits;
+            uint256 preVerificationGas;
+            bytes32 gasFees;
+            bytes paymasterAndData;
+            bytes signature;
+        }
+
+        struct ReturnInfo {
+            uint256 preOpGas;
+            uint256 prefund;
+            uint256 accountValidationData;
+            uint256 paymasterValidationData;
+            bytes paymasterContext;
+        }
+
+        struct StakeInfo {
+            uint256 stake;
+            uint256 unstakeDelaySec;
+        }
+
+        struct AggregatorStakeInfo {
+            address aggregator;
+            StakeInfo stakeInfo;
+        }
+
+        struct ValidationResult {
+            ReturnInfo returnInfo;
+            StakeInfo senderInfo;
+            StakeInfo factoryInfo;
+            StakeInfo paymasterInfo;
+            AggregatorStakeInfo aggregatorInfo;
+        }
+
+        struct UserOpsPerAggregator {
+            PackedUserOperation[] userOps;
+            address aggregator;
+            bytes signature;
+        }
+
+        function handleOps(PackedUserOperation[] ops, address beneficiary);
+        function handleAggregatedOps(UserOpsPerAggregator[] opsPerAggregator, address beneficiary);
+        function getUserOpHash(PackedUserOperation userOp) returns (bytes32);
+        function getNonce(address sender, uint192 key) returns (uint256 nonce);
+        function simulateValidation(PackedUserOperation userOp) returns (ValidationResult);
+
+        /// IAggregator.
+        function validateUserOpSignature(PackedUserOperation userOp) returns (bytes sigForUserOp);
+        function aggregateSignatures(PackedUserOperation[] userOps) returns (bytes aggregatedSignature);
+    }
+}
+
+sol! {
+    /// Reverts the EntryPoint and simulations contract use.
+    interface EntryPointErrors {
+        error FailedOp(uint256 opIndex, string reason);
+        error FailedOpWithRevert(uint256 opIndex, string reason, bytes inner);
+        error SignatureValidationFailed(address aggregator);
+        error PostOpReverted(bytes returnData);
+        error Error(string message);
+        error Panic(uint256 code);
+    }
+}
+
+impl From<&PackedUserOperation> for abi::PackedUserOperation {
+    fn from(op: &PackedUserOperation) -> Self {
+        abi::PackedUserOperation {
+            sender: op.sender,
+            nonce: op.nonce,
+            initCode: op.init_code.clone().into(),
+            callData: op.call_data.clone().into(),
+            accountGasLimits: op.account_gas_limits,
+            preVerificationGas: op.pre_verification_gas,
+            gasFees: op.gas_fees,
+            paymasterAndData: op.paymaster_and_data.clone().into(),
+            signature: op.signature.clone().into(),
+        }
+    }
+}
+
+/// `abi.encode(op)`: the op as it appears inside `handleOps` calldata.
+pub fn encode_user_op(op: &PackedUserOperation) -> Vec<u8> {
+    abi::PackedUserOperation::from(op).abi_encode()
+}
+
+/// `handleOps(ops, beneficiary)` calldata.
+pub fn encode_handle_ops(ops: &[PackedUserOperation], beneficiary: Address) -> Vec<u8> {
+    abi::handleOpsCall {
+        ops: ops.iter().map(Into::into).collect(),
+        beneficiary,
+    }
+    .abi_encode()
+}
+
+/// A decoded simulation revert.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Revert {
+    /// `FailedOp`/`FailedOpWithRevert`; `reason` starts with the AAxx code.
+    FailedOp {
+        op_index: U256,
+        reason: String,
+        inner: Option<Bytes>,
+    },
+    SignatureValidationFailed {
+        aggregator: Address,
+    },
+    PostOpReverted {
+        data: Bytes,
+    },
+    /// Solidity `Error(string)`.
+    Error {
+        message: String,
+    },
+    /// Solidity `Panic(uint256)`.
+    Panic {
+        code: U256,
+    },
+    /// Revert data we have no ABI for.
+    Unknown {
+        data: Bytes,
+    },
+}
+
+impl Revert {
+    pub fn decode(data: &[u8]) -> Self {
+        use EntryPointErrors::EntryPointErrorsErrors as E;
+        match E::abi_decode(data) {
+            Ok(E::FailedOp(e)) => Revert::FailedOp {
+                op_index: e.opIndex,
+                reason: e.reason,
+                inner: None,
+            },
+            Ok(E::FailedOpWithRevert(e)) => Revert::FailedOp {
+                op_index: e.opIndex,
+                reason: e.reason,
+                inner: Some(e.inner),
+            },
+            Ok(E::SignatureValidationFailed(e)) => Revert::SignatureValidationFailed {
+                aggregator: e.aggregator,
+            },
+            Ok(E::PostOpReverted(e)) => Revert::PostOpReverted { data: e.returnData },
+            Ok(E::Error(e)) => Revert::Error { message: e.message },
+            Ok(E::Panic(e)) => Revert::Panic { code: e.code },
+            Err(_) => Revert::Unknown {
+                data: Bytes::copy_from_slice(data),
+            },
+        }
+    }
+
+    /// The `AAxx` code for EntryPoint failures.
+    pub fn aa_code(&self) -> Option<&str> {
+        match self {
+            Revert::FailedOp { reason, .. }
+                if reason.len() >= 4
+                    && reason.starts_with("AA")
+                    && reason.as_bytes()[2..4].iter().all(u8::is_ascii_digit) =>
+            {
+                Some(&reason[..4])
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Revert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Revert::FailedOp {
+                reason,
+                inner: Some(inner),
+                ..
+            } => write!(f, "{reason} (inner revert {inner})"),
+            Revert::FailedOp { reason, .. } => write!(f, "{reason}"),
+            Revert::SignatureValidationFailed { aggregator } => {
+                write!(f, "signature validation failed (aggregator {aggregator})")
+            }
+            Revert::PostOpReverted { data } => write!(f, "paymaster postOp reverted: {data}"),
+            Revert::Error { message } => write!(f, "reverted: {message}"),
+            Revert::Panic { code } => write!(f, "panic 0x{code:x}"),
+            Revert::Unknown { data } if data.is_empty() => write!(f, "reverted without data"),
+            Revert::Unknown { data } => write!(f, "reverted with {data}"),
+        }
+    }
+}
+
+/// Unpacked `validationData` word (account or paymaster).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationData {
+    /// `address(1)` means the signature check failed.
+    pub aggregator: Address,
+    pub valid_until: u64,
+    pub valid_after: u64,
+}
+
+impl ValidationData {
+    /// `aggregator (160) | validUntil (48) | validAfter (48)`, low bits first.
+    pub fn unpack(word: U256) -> Self {
+        let bytes = word.to_be_bytes::<32>();
+        let be48 = |b: &[u8]| b.iter().fold(0u64, |acc, x| (acc << 8) | u64::from(*x));
+        ValidationData {
+            aggregator: Address::from_slice(&bytes[12..]),
+            valid_until: be48(&bytes[6..12]),
+            valid_after: be48(&bytes[..6]),
+        }
+    }
+
+    pub fn signature_failed(&self) -> bool {
+        self.aggregator == Address::with_last_byte(1)
+    }
+}
+
+/// What `simulateValidation` returned.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationSummary {
+    pub pre_op_gas: U256,
+    pub prefund: U256,
+    pub account: ValidationData,
+    pub paymaster: ValidationData,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum Simulation {
+    Ok {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        validation: Option<ValidationSummary>,
+    },
+    Reverted {
+        revert: Revert,
+    },
+}
+
+impl Simulation {
+    /// Why the op would be rejected, if it would. A successful
+    /// `simulateValidation` with a failed signature counts as rejected.
+    pub fn rejection(&self) -> Option<String> {
+        match self {
+            Simulation::Reverted { revert } => Some(revert.to_string()),
+            Simulation::Ok {
+                validation: Some(v),
+            } if v.account.signature_failed() => Some("AA24 signature error".to_string()),
+            Simulation::Ok {
+                validation: Some(v),
+            } if v.paymaster.signature_failed() => Some("AA34 signature error".to_string()),
+            Simulation::Ok { .. } => None,
+        }
+    }
+}
+
+pub(crate) fn eth_call<T: Transport>(
+    rpc: &T,
+    to: Address,
+    data: Vec<u8>,
+    overrides: Option<serde_json::Value>,
+) -> Result<std::result::Result<Bytes, Revert>> {
+    let call = serde_json::json!({ "to": to, "data": Bytes::from(data) });
+    let params = match overrides {
+        Some(o) => serde_json::json!([call, "latest", o]),
+        None => serde_json::json!([call, "latest"]),
+    };
+    match rpc.call::<Bytes>("eth_call", params) {
+        Ok(ret) => Ok(Ok(ret)),
+        Err(e) => match rpc::revert_data(&e) {
+            Some(data) => Ok(Err(Revert::decode(&data))),
+            // A revert without data still says "execution reverted".
+            None if matches!(&e, WalletError::RpcError { message, .. } if message.contains("revert")) => {
+                Ok(Err(Revert::Unknown { data: Bytes::new() }))
+            }
+            None => Err(e),
+        },
+    }
+}
+
+/// `eth_call` `handleOps([op], beneficiary)` on the deployed EntryPoint.
+#[tracing::instrument(
+    level = "debug",
+    name = "simulate",
+    skip_all,
+    fields(kind = "handleOps")
+)]
+pub fn simulate_handle_ops<T: Transport>(
+    rpc: &T,
+    op: &PackedUserOperation,
+    entry_point: Address,
+    beneficiary: Address,
+) -> Result<Simulation> {
+    let data = encode_handle_ops(std::slice::from_ref(op), beneficiary);
+    Ok(match eth_call(rpc, entry_point, data, None)? {
+        Ok(_) => Simulation::Ok { validation: None },
+        Err(revert) => Simulation::Reverted { revert },
+    })
+}
+
+/// `simulateValidation(op)` with the EntryPoint's code replaced by
+/// `simulations_code` (the deployed bytecode of v0.7 `EntryPointSimulations`).
+#[tracing::instrument(
+    level = "debug",
+    name = "simulate",
+    skip_all,
+    fields(kind = "simulateValidation")
+)]
+pub fn simulate_validation<T: Transport>(
+    rpc: &T,
+    op: &PackedUserOperation,
+    entry_point: Address,
+    simulations_code: &[u8],
+) -> Result<Simulation> {
+    let data = abi::simulateValidationCall { userOp: op.into() }.abi_encode();
+    let overrides = serde_json::json!({
+        entry_point.to_string(): { "code": Bytes::copy_from_slice(simulations_code) }
+    });
+    Ok(match eth_call(rpc, entry_point, data, Some(overrides))? {
+        Err(revert) => Simulation::Reverted { revert },
+        Ok(ret) => {
+            let result = abi::simulateValidationCall::abi_decode_returns(&ret).map_err(|e| {
+                WalletError::Rpc(format!("simulateValidation: undecodable result: {e}"))
+            })?;
+            Simulation::Ok {
+                validation: Some(ValidationSummary {
+                    pre_op_gas: result.returnInfo.preOpGas,
+                    prefund: result.returnInfo.prefund,
+                    account: ValidationData::unpack(result.returnInfo.accountValidationData),
+                    paymaster: ValidationData::unpack(result.returnInfo.paymasterValidationData),
+                }),
+            }
+        }
+    })
+}
+
+/// `EntryPoint.getUserOpHash(op)`, as the deployed EntryPoint computes it.
+pub fn onchain_user_op_hash<T: Transport>(
+    rpc: &T,
+    op: &PackedUserOperation,
+    entry_point: Address,
+) -> Result<B256> {
+    let data = abi::getUserOpHashCall { userOp: op.into() }.abi_encode();
+    match eth_call(rpc, entry_point, data, None)? {
+        Ok(ret) => abi::getUserOpHashCall::abi_decode_returns(&ret)
+            .map_err(|e| WalletError::Rpc(format!("getUserOpHash: undecodable result: {e}"))),
+        Err(revert) => Err(WalletError::Rpc(format!("getUserOpHash: {revert}"))),
+    }
+}
+
+/// `EntryPoint.getNonce(sender, 0)`: the next nonce for the default key.
+pub fn get_nonce<T: Transport>(rpc: &T, entry_point: Address, sender: Address) -> Result<U256> {
+    let data = abi::getNonceCall {
+        sender,
+        key: Default::default(),
+    }
+    .abi_encode();
+    match eth_call(rpc, entry_point, data, None)? {
+        Ok(ret) => abi::getNonceCall::abi_decode_returns(&ret)
+            .map_err(|e| WalletError::Rpc(format!("getNonce: undecodable result: {e}"))),
+        Err(revert) => Err(WalletError::Rpc(format!("getNonce: {revert}"))),
+    }
+}
+
+/// Check [`userop::compute_user_op_hash`] against the EntryPoint on `rpc`,
+/// which must be on `chain_id`. Returns the agreed hash; a mismatch means
+/// the local packing is wrong for this EntryPoint.
+pub fn cross_check_user_op_hash<T: Transport>(
+    rpc: &T,
+    op: &PackedUserOperation,
+    entry_point: Address,
+    chain_id: u64,
+) -> Result<B256> {
+    // getUserOpHash hashes in block.chainid, so a node on another chain
+    // would report a mismatch that says nothing about the packing.
+    let node_chain = rpc.call::<U64>("eth_chainId", serde_json::json!([]))?;
+    if node_chain != U64::from(chain_id) {
+        return Err(WalletError::Rpc(format!(
+            "node is on chain {node_chain}, not {chain_id}"
+        )));
+    }
+    let local = userop::compute_user_op_hash(op, entry_point, U256::from(chain_id));
+    let onchain = onchain_user_op_hash(rpc, op, entry_point)?;
+    if local != onchain {
+        return Err(WalletError::InvalidUserOp(format!(
+            "userOpHash mismatch: computed {local}, EntryPoint {entry_point} returned {onchain}"
+        )));
+    }
+    Ok(local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_sol_types::SolError;
+    use serde_json::{Value, json};
+
+    struct Reverting(Value);
+
+    impl Transport for Reverting {
+        fn request(&self, method: &str, _params: Value) -> Result<Value> {
+            Err(WalletError::RpcError {
+                method: method.to_string(),
+                code: 3,
+                message: "execution reverted".into(),
+                data: Some(self.0.clone()),
+            })
+        }
+    }
+
+    fn op() -> PackedUserOperation {
+        serde_json::from_value(json!({
+            "sender": "0x1111111111111111111111111111111111111111",
+            "nonce": "0x0",
+            "callData": "0x",
+            "callGasLimit": "0x0",
+            "verificationGasLimit": "0x0",
+            "preVerificationGas": "0x0",
+            "maxFeePerGas": "0x0",
+            "maxPriorityFeePerGas": "0x0",
+            "signature": "0x"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn decodes_failed_op_revert() {
+        let data = EntryPointErrors::FailedOp {
+            opIndex: U256::ZERO,
+            reason: "AA24 signature error".into(),
+        }
+        .abi_encode();
+        let rpc = Reverting(json!(Bytes::from(data)));
+        let sim = simulate_handle_ops(&rpc, &op(), crate::userop::ENTRY_POINT_V07, Address::ZERO)
+            .unwrap();
+        let Simulation::Reverted { revert } = &sim else {
+            panic!("{sim:?}");
+        };
+        assert_eq!(revert.aa_code(), Some("AA24"));
+        assert_eq!(sim.rejection().as_deref(), Some("AA24 signature error"));
+    }
+
+    /// A node on chain 1 whose EntryPoint returns `hash`.
+    struct Node {
+        hash: B256,
+    }
+
+    impl Transport for Node {
+        fn request(&self, method: &str, _params: Value) -> Result<Value> {
+            Ok(match method {
+                "eth_chainId" => json!("0x1"),
+                _ => json!(Bytes::from(self.hash.abi_encode())),
+            })
+        }
+    }
+
+    #[test]
+    fn cross_checks_user_op_hash_against_the_entry_point() {
+        let entry_point = crate::userop::ENTRY_POINT_V07;
+        let local = userop::compute_user_op_hash(&op(), entry_point, U256::from(1));
+        let agreeing = Node { hash: local };
+        assert_eq!(
+            cross_check_user_op_hash(&agreeing, &op(), entry_point, 1).unwrap(),
+            local
+        );
+        let err = cross_check_user_op_hash(&agreeing, &op(), entry_point, 10).unwrap_err();
+        assert!(
+            err.to_string().contains("node is on chain 1, not 10"),
+            "{err}"
+        );
+        let disagreeing = Node { hash: B256::ZERO };
+        let err = cross_check_user_op_hash(&disagreeing, &op(), entry_point, 1).unwrap_err();
+        assert!(err.to_string().contains("userOpHash mismatch"), "{err}");
+    }
+
+    #[test]
+    fn unpacks_validation_data() {
+        let mut word = [0u8; 32];
+        word[..6].copy_from_slice(&[0, 0, 0, 0, 0, 5]); // validAfter
+        word[6..12].copy_from_slice(&[0, 0, 0, 0, 0, 9]); // validUntil
+        word[31] = 1; // SIG_VALIDATION_FAILED
+        let v = ValidationData::unpack(U256::from_be_bytes(word));
+        assert_eq!((v.valid_after, v.valid_until), (5, 9));
+        assert!(v.signature_failed());
+    }
+
+    #[test]
+    fn unknown_revert_data_is_kept() {
+        let revert = Revert::decode(&[0xde, 0xad, 0xbe, 0xef]);
+        assert!(matches!(revert, Revert::Unknown { .. }));
+        assert_eq!(revert.to_string(), "reverted with 0xdeadbeef");
+    }
+}
@@ -0,0 +1,520 @@
+//! Sign-In with Ethereum (EIP-4361) for PQ smart accounts.
+//!
+//! A [`Message`] is built and rendered exactly as the EIP-4361 ABNF lays it
+//! out, and parsed back the same way. The account holds no ECDSA key, so it
+//! signs in through ERC-1271: the message is hashed as an EIP-191 personal
+//! message and the ML-DSA-65 key signs the hash `PQValidatorModule` binds
+//! it to in `isValidSignatureWithSender` (see [`crate::message`]). The
+//! bare signature is what the account's `isValidSignature` takes.
+//!
+//! A service embeds a [`Verifier`]: it checks the message is for its
+//! domain and chain, carries the nonce it issued and is inside its validity
+//! window, then verifies the signature under the account's key, given
+//! directly or read from the module's storage.
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::{FromStr, Split};
+
+use alloy_primitives::{Address, B256, U64};
+use ml_dsa::{MlDsa65, Signature, SigningKey, VerifyingKey};
+
+use crate::error::{Result, WalletError};
+use crate::keys::PUBLIC_KEY_LEN;
+use crate::message::{Erc1271Binding, PersonalMessage};
+use crate::replay;
+use crate::rpc::Transport;
+
+const HEADER: &str = " wants you to sign in with your Ethereum account:";
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::Siwe(reason.into())
+}
+
+/// An EIP-4361 message. Timestamps are RFC 3339 strings, kept as written
+/// so a parsed message renders back to the text that was signed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    /// RFC 3986 authority asking for the sign-in, with an optional scheme.
+    pub domain: String,
+    /// The smart account signing in.
+    pub address: Address,
+    pub statement: Option<String>,
+    pub uri: String,
+    pub version: String,
+    pub chain_id: u64,
+    pub nonce: String,
+    pub issued_at: String,
+    pub expiration_time: Option<String>,
+    pub not_before: Option<String>,
+    pub request_id: Option<String>,
+    pub resources: Vec<String>,
+}
+
+impl Message {
+    /// A version 1 message issued now, with a fresh [`nonce`].
+    pub fn new(domain: &str, address: Address, uri: &str, chain_id: u64) -> Self {
+        Message {
+            domain: domain.to_string(),
+            address,
+            statement: None,
+            uri: uri.to_string(),
+            version: "1".to_string(),
+            chain_id,
+            nonce: nonce(),
+            issued_at: timestamp(crate::attestation::now()),
+            expiration_time: None,
+            not_before: None,
+            request_id: None,
+            resources: Vec::new(),
+        }
+    }
+
+    /// The context `module` checks a signature for this account in, when
+    /// `caller` asks.
+    pub fn binding(&self, module: Address, caller: Address) -> Erc1271Binding {
+        Erc1271Binding {
+            module,
+            chain_id: self.chain_id,
+            account: self.address,
+            sender: caller,
+        }
+    }
+
+    /// The 32-byte ML-DSA message for the rendered text.
+    pub fn signed_hash(&self, module: Address, caller: Address) -> B256 {
+        signed_hash(&self.to_string(), self.binding(module, caller))
+    }
+
+    /// Sign the rendered message through the account's ERC-1271 path.
+    pub fn sign(&self, sk: &SigningKey<MlDsa65>, module: Address, caller: Address) -> Vec<u8> {
+        use ml_dsa::signature::Signer;
+        let hash = self.signed_hash(module, caller);
+        sk.sign(hash.as_slice()).encode().to_vec()
+    }
+
+    /// Check the fields against what the service expects, at `now`.
+    fn check(&self, verifier: &Verifier, nonce: &str, now: u64) -> Result<()> {
+        if self.domain != verifier.domain {
+            return Err(err(format!("message is for {}", self.domain)));
+        }
+        if self.chain_id != verifier.chain_id {
+            return Err(err(format!("message is for chain {}", self.chain_id)));
+        }
+        if self.nonce != nonce {
+            return Err(err("nonce does not match the one issued"));
+        }
+        if let Some(expiry) = &self.expiration_time
+            && parse_timestamp(expiry)? <= now
+        {
+            return Err(err(format!("message expired at {expiry}")));
+        }
+        if let Some(start) = &self.not_before
+            && parse_timestamp(start)? > now
+        {
+            return Err(err(format!("message is not valid before {start}")));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}{HEADER}", self.domain)?;
+        writeln!(f, "{}", self.address.to_checksum(None))?;
+        writeln!(f)?;
+        if let Some(statement) = &self.statement {
+            writeln!(f, "{statement}")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "URI: {}", self.uri)?;
+        writeln!(f, "Version: {}", self.version)?;
+        writeln!(f, "Chain ID: {}", self.chain_id)?;
+        writeln!(f, "Nonce: {}", self.nonce)?;
+        write!(f, "Issued At: {}", self.issued_at)?;
+        if let Some(expiry) = &self.expiration_time {
+            write!(f, "\nExpiration Time: {expiry}")?;
+        }
+        if let Some(start) = &self.not_before {
+            write!(f, "\nNot Before: {start}")?;
+        }
+        if let Some(id) = &self.request_id {
+            write!(f, "\nRequest ID: {id}")?;
+        }
+        if !self.resources.is_empty() {
+            write!(f, "\nResources:")?;
+            for resource in &self.resources {
+                write!(f, "\n- {resource}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Take the next line if it starts with `tag`.
+fn tagged<'a>(lines: &mut Peekable<Split<'a, char>>, tag: &str) -> Option<&'a str> {
+    let value = lines.peek()?.strip_prefix(tag)?;
+    lines.next();
+    Some(value)
+}
+
+impl FromStr for Message {
+    type Err = WalletError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let mut lines = text.split('\n').peekable();
+        let domain = lines
+            .next()
+            .and_then(|line| line.strip_suffix(HEADER))
+            .filter(|domain| !domain.is_empty())
+            .ok_or_else(|| err("missing the sign-in line"))?;
+        let address = lines
+            .next()
+            .and_then(|line| Address::parse_checksummed(line, None).ok())
+            .ok_or_else(|| err("address must be EIP-55 checksummed"))?;
+        let blank = |line: Option<&str>| match line {
+            Some("") => Ok(()),
+            _ => Err(err("expected a blank line")),
+        };
+        blank(lines.next())?;
+        let statement = match lines.next() {
+            Some("") => None,
+            Some(statement) => {
+                blank(lines.next())?;
+                Some(statement.to_string())
+            }
+            None => return Err(err("message ends early")),
+        };
+        let mut required = |tag: &str| {
+            tagged(&mut lines, tag)
+                .map(str::to_string)
+                .ok_or_else(|| err(format!("missing {}", tag.trim_end_matches(": "))))
+        };
+        let uri = required("URI: ")?;
+        let version = required("Version: ")?;
+        let chain_id = required("Chain ID: ")?;
+        let nonce = required("Nonce: ")?;
+        let issued_at = required("Issued At: ")?;
+        let mut optional = |tag: &str| tagged(&mut lines, tag).map(str::to_string);
+        let expiration_time = optional("Expiration Time: ");
+        let not_before = optional("Not Before: ");
+        let request_id = optional("Request ID: ");
+        let mut resources = Vec::new();
+        if tagged(&mut lines, "Resources:") == Some("") {
+            while let Some(resource) = tagged(&mut lines, "- ") {
+                resources.push(resource.to_string());
+            }
+        }
+        if let Some(line) = lines.next() {
+            return Err(err(format!("unexpected line {line:?}")));
+        }
+
+        if version != "1" {
+            return Err(err(format!("unsupported version {version}")));
+        }
+        let chain_id = chain_id
+            .parse()
+            .map_err(|_| err(format!("bad chain ID {chain_id}")))?;
+        if nonce.len() < 8 || !nonce.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return Err(err("nonce must be at least 8 alphanumeric characters"));
+        }
+        for time in [
+            Some(&issued_at),
+            expiration_time.as_ref(),
+            not_before.as_ref(),
+        ] {
+            time.map(|t| parse_timestamp(t)).transpose()?;
+        }
+        Ok(Message {
+            domain: domain.to_string(),
+            address,
+            statement,
+            uri,
+            version,
+            chain_id,
+            nonce,
+            issued_at,
+            expiration_time,
+            not_before,
+            request_id,
+            resources,
+        })
+    }
+}
+
+/// The hash the ML-DSA key signs for `text`.
+fn signed_hash(text: &str, binding: Erc1271Binding) -> B256 {
+    PersonalMessage {
+        message: text.as_bytes().to_vec(),
+        binding: Some(binding),
+    }
+    .signed_hash()
+}
+
+/// 16 random alphanumeric characters.
+pub fn nonce() -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+    let mut out = String::with_capacity(16);
+    while out.len() < 16 {
+        // Rejecting the top of the byte range keeps every character equally
+        // likely.
+        let b = rand::random::<u8>();
+        if usize::from(b) < 4 * ALPHABET.len() {
+            out.push(char::from(ALPHABET[usize::from(b) % ALPHABET.len()]));
+        }
+    }
+    out
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// `secs` since the Unix epoch as an RFC 3339 UTC timestamp.
+pub fn timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let time = secs % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Seconds since the Unix epoch of an RFC 3339 timestamp. Fractional
+/// seconds are dropped.
+pub fn parse_timestamp(text: &str) -> Result<u64> {
+    let bad = || err(format!("bad timestamp {text:?}"));
+    let b = text.as_bytes();
+    let num = |range: std::ops::Range<usize>| -> Result<i64> {
+        let digits = text.get(range).ok_or_else(bad)?;
+        if !digits.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(bad());
+        }
+        digits.parse().map_err(|_| bad())
+    };
+    if b.len() < 20
+        || b[4] != b'-'
+        || b[7] != b'-'
+        || !matches!(b[10], b'T' | b't')
+        || b[13] != b':'
+        || b[16] != b':'
+    {
+        return Err(bad());
+    }
+    let (year, month, day) = (num(0..4)?, num(5..7)?, num(8..10)?);
+    let (hour, minute, second) = (num(11..13)?, num(14..16)?, num(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return Err(bad());
+    }
+    // A leap second counts as the last second of its minute.
+    let second = second.min(59);
+    let mut rest = &text[19..];
+    if let Some(frac) = rest.strip_prefix('.') {
+        let digits = frac.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            return Err(bad());
+        }
+        rest = &frac[digits..];
+    }
+    let offset = match rest.as_bytes() {
+        [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+            let (h, m) = (
+                num(text.len() - 5..text.len() - 3)?,
+                num(text.len() - 2..text.len())?,
+            );
+            if h > 23 || m > 59 {
+                return Err(bad());
+            }
+            let offset = h * 3600 + m * 60;
+            if *sign == b'+' { offset } else { -offset }
+        }
+        _ => return Err(bad()),
+    };
+    let secs =
+        days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    u64::try_from(secs).map_err(|_| bad())
+}
+
+/// What a service accepts sign-ins for.
+#[derive(Clone, Debug)]
+pub struct Verifier {
+    pub domain: String,
+    pub chain_id: u64,
+    /// The `PQValidatorModule` the accounts sign in through.
+    pub module: Address,
+    /// The `msg.sender` of the service's `isValidSignature` call: zero for
+    /// an `eth_call` with no `from`.
+    pub caller: Address,
+}
+
+impl Verifier {
+    pub fn new(domain: &str, chain_id: u64, module: Address) -> Self {
+        Verifier {
+            domain: domain.to_string(),
+            chain_id,
+            module,
+            caller: Address::ZERO,
+        }
+    }
+
+    /// Accept `text`, signed with `signature` by the ML-DSA-65 key `key`,
+    /// if it carries the `nonce` the service issued and is valid at `now`.
+    pub fn verify(
+        &self,
+        text: &str,
+        nonce: &str,
+        signature: &[u8],
+        key: &[u8; PUBLIC_KEY_LEN],
+        now: u64,
+    ) -> Result<Message> {
+        let message: Message = text.parse()?;
+        message.check(self, nonce, now)?;
+        let hash = signed_hash(text, message.binding(self.module, self.caller));
+        let vk = VerifyingKey::<MlDsa65>::decode(&(*key).into());
+        let valid = Signature::<MlDsa65>::try_from(signature)
+            .is_ok_and(|sig| vk.verify_with_context(hash.as_slice(), &[], &sig));
+        if !valid {
+            return Err(err(format!(
+                "signature does not verify under {}'s key",
+                message.address
+            )));
+        }
+        Ok(message)
+    }
+
+    /// [`Self::verify`] under the key the module holds for the account at
+    /// the node's head.
+    pub fn verify_on_chain<T: Transport>(
+        &self,
+        node: &T,
+        text: &str,
+        nonce: &str,
+        signature: &[u8],
+        now: u64,
+    ) -> Result<Message> {
+        let message: Message = text.parse()?;
+        let head = node
+            .call::<U64>("eth_blockNumber", serde_json::json!([]))?
+            .to::<u64>();
+        let key = replay::committed_key(node, self.module, message.address, head)?
+            .ok_or_else(|| err(format!("{} has no key in the module", message.address)))?;
+        let key = <[u8; PUBLIC_KEY_LEN]>::try_from(key.as_slice())
+            .map_err(|_| err(format!("{}'s key is not ML-DSA-65", message.address)))?;
+        self.verify(text, nonce, signature, &key, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys;
+
+    const EXAMPLE: &str = "example.com wants you to sign in with your Ethereum account:
+0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2
+
+I accept the ExampleOrg Terms of Service: https://example.com/tos
+
+URI: https://example.com/login
+Version: 1
+Chain ID: 1
+Nonce: 32891756
+Issued At: 2021-09-30T16:25:24Z
+Resources:
+- ipfs://bafybeiemxf5abjwjbikoz4mc3a3dla6ual3jsgpdr4cjr3oz3evfyavhwq/
+- https://example.com/my-web2-claim.json";
+
+    #[test]
+    fn parses_and_renders_the_spec_example() {
+        let message: Message = EXAMPLE.parse().unwrap();
+        assert_eq!(message.domain, "example.com");
+        assert_eq!(message.nonce, "32891756");
+        assert_eq!(message.resources.len(), 2);
+        assert_eq!(message.to_string(), EXAMPLE);
+        assert_eq!(parse_timestamp(&message.issued_at).unwrap(), 1_633_019_124);
+        assert_eq!(timestamp(1_633_019_124), message.issued_at);
+        assert_eq!(
+            parse_timestamp("2021-09-30T18:25:24.5+02:00").unwrap(),
+            1_633_019_124
+        );
+
+        let bare = Message {
+            statement: None,
+            resources: Vec::new(),
+            ..message
+        };
+        assert!(bare.to_string().contains("Cc2\n\n\nURI: "));
+        assert_eq!(bare.to_string().parse::<Message>().unwrap(), bare);
+        assert!(
+            EXAMPLE
+                .replace("0xC02aaA", "0xc02aaa")
+                .parse::<Message>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn verifies_sessions_for_the_service_only() {
+        let kp = keys::keygen_from_seed(&[27u8; 32]);
+        let pk = kp.verifying_key().encode();
+        let pk: &[u8; PUBLIC_KEY_LEN] = pk.as_slice().try_into().unwrap();
+        let module = Address::repeat_byte(0x77);
+        let mut message = Message::new(
+            "app.example",
+            Address::repeat_byte(0x42),
+            "https://app.example/login",
+            8453,
+        );
+        message.expiration_time = Some(timestamp(2_000_000_000));
+        let text = message.to_string();
+        let sig = message.sign(kp.signing_key(), module, Address::ZERO);
+
+        let verifier = Verifier::new("app.example", 8453, module);
+        let now = crate::attestation::now();
+        let accepted = verifier.verify(&text, &message.nonce, &sig, pk, now);
+        assert_eq!(accepted.unwrap(), message);
+
+        let rejected = [
+            verifier.verify(&text, "otherNonce1", &sig, pk, now),
+            verifier.verify(&text, &message.nonce, &sig, pk, 2_000_000_000),
+            Verifier::new("evil.example", 8453, module).verify(
+                &text,
+                &message.nonce,
+                &sig,
+                pk,
+                now,
+            ),
+            Verifier::new("app.example", 8453, Address::repeat_byte(1)).verify(
+                &text,
+                &message.nonce,
+                &sig,
+                pk,
+                now,
+            ),
+            verifier.verify(
+                &text.replace("Version: 1", "Version: 1\n"),
+                &message.nonce,
+                &sig,
+                pk,
+                now,
+            ),
+        ];
+        assert!(rejected.iter().all(Result::is_err));
+    }
+}
@@ -0,0 +1,400 @@
+//! Whole-host state bundles, for replacing a signer host in one step.
+//!
+//! [`Contents::collect`] gathers what a host keeps next to the keystore:
+//! the encrypted accounts, the address book, the delegation certificates
+//! issued from it, the policy spend ledger, the pending-op queue and the
+//! `pq-wallet.toml` chain config. [`StateBundle::seal`] encrypts them as one
+//! archive to the new host's ML-KEM-768 key, as [`crate::backup`] does for a
+//! single seed, under `keccak256("pq-wallet/state-bundle/v1" ||
+//! sharedSecret)`. The header fields are the AEAD's associated data, and each
+//! file also carries its keccak256, so a bundle that opens is the bundle
+//! that was sealed.
+//!
+//! [`Contents::restore`] writes the files into a staging directory first and
+//! reads each one back with the loader that will use it, so a file this
+//! build cannot read is refused before anything in the keystore changes. It
+//! refuses to replace existing files unless told to. The account files stay
+//! encrypted under their own passwords throughout; a TPM-sealed account
+//! still opens only on the TPM it was sealed to.
+
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::{B256, keccak256};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ml_kem::array::Array;
+use ml_kem::{Decapsulate, Encapsulate, ml_kem_768};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::attestation;
+use crate::backup::{self, KEM_CIPHERTEXT_LEN, KEM_ML_KEM_768, KEM_PUBLIC_KEY_LEN, KEM_SEED_LEN};
+use crate::config::{CONFIG_FILE, Config};
+use crate::contacts::AddressBook;
+use crate::ct;
+use crate::error::{Result, WalletError};
+use crate::keystore::{self, Keystore};
+use crate::policy::SpendLedger;
+use crate::robot::DelegationStore;
+
+pub const STATE_BUNDLE_VERSION: u32 = 1;
+
+/// Domain separator for the wrapping key.
+pub const STATE_BUNDLE_DOMAIN: &[u8] = b"pq-wallet/state-bundle/v1";
+
+const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::Backup(reason.into())
+}
+
+/// What a file in the bundle is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    Account,
+    AddressBook,
+    Delegations,
+    SpendLedger,
+    Queue,
+    Config,
+}
+
+impl Kind {
+    /// The kind of the file at `path`, relative to the keystore root.
+    fn of(path: &str) -> Option<Self> {
+        match path {
+            "contacts.json" => Some(Kind::AddressBook),
+            "delegations.json" => Some(Kind::Delegations),
+            "policy-spend.json" => Some(Kind::SpendLedger),
+            "queue.sqlite" => Some(Kind::Queue),
+            CONFIG_FILE => Some(Kind::Config),
+            _ => {
+                let name = path.strip_prefix("accounts/")?.strip_suffix(".json")?;
+                keystore::validate_name(name).ok()?;
+                Some(Kind::Account)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct File {
+    /// Relative to the keystore root, with `/` separators.
+    pub path: String,
+    pub kind: Kind,
+    /// keccak256 of `data`.
+    pub digest: B256,
+    /// Hex.
+    pub data: String,
+}
+
+/// The files in a bundle.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Contents {
+    pub created_at: u64,
+    pub files: Vec<File>,
+}
+
+impl Contents {
+    /// The state kept in `root`, plus the config file at `config` if it
+    /// lives elsewhere. Files that do not exist are left out.
+    pub fn collect(root: &Path, config: Option<&Path>) -> Result<Self> {
+        let mut paths: Vec<(String, PathBuf)> = Vec::new();
+        let accounts = Keystore::open(root).accounts_dir();
+        if accounts.is_dir() {
+            let entries = std::fs::read_dir(&accounts)
+                .map_err(|e| WalletError::Io(accounts.display().to_string(), e))?;
+            for entry in entries {
+                let path = entry
+                    .map_err(|e| WalletError::Io(accounts.display().to_string(), e))?
+                    .path();
+                if let Some(name) = path.file_name().and_then(|n| n.to_str())
+                    && name.ends_with(".json")
+                {
+                    paths.push((format!("accounts/{name}"), path));
+                }
+            }
+        }
+        for name in [
+            "contacts.json",
+            "delegations.json",
+            "policy-spend.json",
+            "queue.sqlite",
+        ] {
+            paths.push((name.to_string(), root.join(name)));
+        }
+        paths.push((
+            CONFIG_FILE.to_string(),
+            config.map_or_else(|| root.join(CONFIG_FILE), Path::to_path_buf),
+        ));
+        paths.sort();
+
+        let mut files = Vec::new();
+        for (relative, path) in paths {
+            let data = match std::fs::read(&path) {
+                Ok(data) => data,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(WalletError::Io(path.display().to_string(), e)),
+            };
+            let kind = Kind::of(&relative)
+                .ok_or_else(|| err(format!("{relative} is not a keystore file")))?;
+            files.push(File {
+                path: relative,
+                kind,
+                digest: keccak256(&data),
+                data: ct::encode_hex(&data),
+            });
+        }
+        Ok(Contents {
+            created_at: attestation::now(),
+            files,
+        })
+    }
+
+    /// Check every file's path and digest.
+    fn check(&self) -> Result<()> {
+        for file in &self.files {
+            if Kind::of(&file.path) != Some(file.kind) {
+                return Err(err(format!("unexpected file {} in the bundle", file.path)));
+            }
+            if keccak256(ct::decode_hex(&file.data)?) != file.digest {
+                return Err(err(format!("{} does not match its digest", file.path)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the files into `root`, after checking that each one loads.
+    /// Existing files are only replaced with `overwrite`.
+    pub fn restore(&self, root: &Path, overwrite: bool) -> Result<Vec<PathBuf>> {
+        self.check()?;
+        let targets: Vec<PathBuf> = self.files.iter().map(|f| root.join(&f.path)).collect();
+        if !overwrite && let Some(existing) = targets.iter().find(|path| path.exists()) {
+            return Err(err(format!("{} already exists", existing.display())));
+        }
+        let staging = root.join(format!(".import-{}", std::process::id()));
+        let staged = self.stage(&staging);
+        let result = staged.and_then(|()| {
+            for (file, target) in self.files.iter().zip(&targets) {
+                if let Some(dir) = target.parent() {
+                    std::fs::create_dir_all(dir)
+                        .map_err(|e| WalletError::Io(dir.display().to_string(), e))?;
+                }
+                std::fs::rename(staging.join(&file.path), target)
+                    .map_err(|e| WalletError::Io(target.display().to_string(), e))?;
+            }
+            Ok(targets)
+        });
+        std::fs::remove_dir_all(&staging).ok();
+        result
+    }
+
+    /// Write the files under `staging` and read each back.
+    fn stage(&self, staging: &Path) -> Result<()> {
+        let store = Keystore::open(staging);
+        for file in &self.files {
+            let path = staging.join(&file.path);
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)
+                    .map_err(|e| WalletError::Io(dir.display().to_string(), e))?;
+            }
+            let data = ct::decode_hex(&file.data)?;
+            keystore::write_private(&path, &data)?;
+            let loaded = match file.kind {
+                Kind::Account => {
+                    let name = file.path["accounts/".len()..].trim_end_matches(".json");
+                    store.load(name).map(drop)
+                }
+                Kind::AddressBook => AddressBook::open(&path).map(drop),
+                Kind::Delegations => DelegationStore::load(&path).map(drop),
+                Kind::SpendLedger => SpendLedger::load(&path).map(drop),
+                Kind::Config => Config::load(&path).map(drop),
+                Kind::Queue if data.starts_with(SQLITE_MAGIC) => Ok(()),
+                Kind::Queue => Err(err("queue.sqlite is not an SQLite database")),
+            };
+            loaded.map_err(|e| err(format!("{} does not load: {e}", file.path)))?;
+        }
+        Ok(())
+    }
+}
+
+fn wrapping_key(shared: &[u8]) -> Zeroizing<[u8; 32]> {
+    let mut preimage = Zeroizing::new(STATE_BUNDLE_DOMAIN.to_vec());
+    preimage.extend_from_slice(shared);
+    Zeroizing::new(keccak256(preimage.as_slice()).0)
+}
+
+/// [`Contents`] sealed to a host's KEM key, as `pq-account export-bundle`
+/// writes it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateBundle {
+    pub version: u32,
+    pub kem: String,
+    pub cipher: String,
+    pub created_at: u64,
+    /// Files in the bundle, to show before opening it.
+    pub file_count: usize,
+    /// [`backup::kem_fingerprint`] of the recipient's encapsulation key.
+    pub recipient: B256,
+    /// Hex, ML-KEM-768 ciphertext.
+    pub encapsulation: String,
+    /// Hex, 12 bytes.
+    pub nonce: String,
+    /// Hex; the contents' JSON plus 16-byte tag.
+    pub ciphertext: String,
+}
+
+impl StateBundle {
+    pub fn seal(contents: &Contents, recipient: &[u8; KEM_PUBLIC_KEY_LEN]) -> Result<Self> {
+        let ek = ml_kem_768::EncapsulationKey::new(&Array::from(*recipient))
+            .map_err(|_| err("not an ML-KEM-768 encapsulation key"))?;
+        let (encapsulation, shared) = ek.encapsulate_with_rng(&mut rand::rng());
+        let key = wrapping_key(&shared);
+        let nonce: [u8; 12] = rand::random();
+        let mut bundle = StateBundle {
+            version: STATE_BUNDLE_VERSION,
+            kem: KEM_ML_KEM_768.to_string(),
+            cipher: "chacha20poly1305".to_string(),
+            created_at: contents.created_at,
+            file_count: contents.files.len(),
+            recipient: backup::kem_fingerprint(recipient),
+            encapsulation: ct::encode_hex(&encapsulation),
+            nonce: ct::encode_hex(&nonce),
+            ciphertext: String::new(),
+        };
+        let plaintext = Zeroizing::new(serde_json::to_vec(contents).expect("contents serialize"));
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: &bundle.aad()?,
+                },
+            )
+            .map_err(|_| err("encryption failed"))?;
+        bundle.ciphertext = ct::encode_hex(&ciphertext);
+        Ok(bundle)
+    }
+
+    /// The header, without the ciphertext, as canonical JSON.
+    fn aad(&self) -> Result<Vec<u8>> {
+        let header = StateBundle {
+            ciphertext: String::new(),
+            ..self.clone()
+        };
+        crate::canonical_json::to_vec(&header)
+    }
+
+    /// Decrypt with the recipient's decapsulation seed and check every
+    /// file's digest.
+    pub fn open(&self, kem_seed: &[u8; KEM_SEED_LEN]) -> Result<Contents> {
+        if self.version != STATE_BUNDLE_VERSION || self.kem != KEM_ML_KEM_768 {
+            return Err(err(format!(
+                "unsupported state bundle v{} ({})",
+                self.version, self.kem
+            )));
+        }
+        if backup::kem_fingerprint(&backup::kem_public_key(kem_seed)) != self.recipient {
+            return Err(err(format!(
+                "bundle is sealed to {}, not this KEM key",
+                self.recipient
+            )));
+        }
+        let encapsulation: [u8; KEM_CIPHERTEXT_LEN] =
+            ct::decode_hex_array("KEM ciphertext", &self.encapsulation)?;
+        let nonce: [u8; 12] = ct::decode_hex_array("nonce", &self.nonce)?;
+        let ciphertext = ct::decode_hex(&self.ciphertext)?;
+        let dk = ml_kem_768::DecapsulationKey::from_seed(Array::from(*kem_seed));
+        let shared = dk.decapsulate(&Array::from(encapsulation));
+        let key = wrapping_key(&shared);
+        let plaintext = Zeroizing::new(
+            ChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+                .decrypt(
+                    Nonce::from_slice(&nonce),
+                    Payload {
+                        msg: &ciphertext,
+                        aad: &self.aad()?,
+                    },
+                )
+                .map_err(|_| err("bundle does not decrypt (corrupted or tampered)"))?,
+        );
+        let contents: Contents =
+            serde_json::from_slice(&plaintext).map_err(|e| err(format!("bundle contents: {e}")))?;
+        if contents.files.len() != self.file_count || contents.created_at != self.created_at {
+            return Err(err("bundle header does not match its contents"));
+        }
+        contents.check()?;
+        Ok(contents)
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).map_err(|e| err(format!("invalid state bundle: {e}")))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("bundle serializes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keystore::{Account, KdfParams};
+
+    fn dir(tag: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pq-state-{tag}-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    #[test]
+    fn moves_a_host_and_refuses_tampering() {
+        let old = dir("old");
+        let kdf = KdfParams {
+            m_cost: 64,
+            t_cost: 1,
+            p_cost: 1,
+        };
+        let account = Account::encrypt("alice", &[4u8; 32], b"pw", kdf).unwrap();
+        Keystore::open(&old).insert(&account).unwrap();
+        std::fs::write(old.join("delegations.json"), r#"{"issued":{}}"#).unwrap();
+        std::fs::write(old.join(CONFIG_FILE), "chain_id = 421614\n").unwrap();
+        std::fs::write(old.join("audit.jsonl"), "not moved\n").unwrap();
+
+        let kem_seed = [8u8; KEM_SEED_LEN];
+        let contents = Contents::collect(&old, None).unwrap();
+        let paths: Vec<_> = contents.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["accounts/alice.json", "delegations.json", CONFIG_FILE]
+        );
+        let sealed = StateBundle::seal(&contents, &backup::kem_public_key(&kem_seed)).unwrap();
+        let read = StateBundle::from_json(sealed.to_json().as_bytes()).unwrap();
+        assert!(read.open(&[9u8; KEM_SEED_LEN]).is_err());
+        let mut relabelled = read.clone();
+        relabelled.created_at += 1;
+        assert!(relabelled.open(&kem_seed).is_err());
+
+        let new = dir("new");
+        let opened = read.open(&kem_seed).unwrap();
+        assert_eq!(opened.restore(&new, false).unwrap().len(), 3);
+        let moved = Keystore::open(&new).load("alice").unwrap();
+        assert_eq!(moved.public_key().unwrap(), account.public_key().unwrap());
+        assert!(!new.join("audit.jsonl").exists());
+        assert!(opened.restore(&new, false).is_err());
+        opened.restore(&new, true).unwrap();
+
+        // A file that does not load leaves the target untouched.
+        let mut broken = opened.clone();
+        let bad = b"not json";
+        broken.files[1].data = ct::encode_hex(bad);
+        broken.files[1].digest = keccak256(bad);
+        let empty = dir("empty");
+        assert!(broken.restore(&empty, false).is_err());
+        assert!(!empty.join("accounts").exists());
+    }
+}
@@ -0,0 +1,467 @@
+//! Per-account usage numbers for `pq-account stats`, from the audit log
+//! and the bundlers' receipts.
+//!
+//! Every UserOperation an account signs is in the audit log with its hash,
+//! chain and signing time ([`signed_ops`]). [`observe`] asks the bundlers
+//! on that chain about each hash: the first one that knows it is taken to
+//! be the one it was submitted to, and its receipt gives the outcome, the
+//! gas paid and, from `eth_getUserOperationByHash`, the native value the
+//! calls sent. With a node for the chain, the inclusion block's timestamp
+//! less the signing time is the op's inclusion latency. [`summarize`]
+//! totals the observations overall, per bundler and per [`Period`], and
+//! [`Stats::to_csv`] writes the per-period rows for a spreadsheet.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Write as _};
+
+use alloy_primitives::{Address, B256, U64, U256};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::audit::Entry;
+use crate::bundler::{self, State};
+use crate::calldata;
+use crate::ct;
+use crate::error::Result;
+use crate::rpc::Transport;
+use crate::siwe;
+
+/// Tools whose audit entries are signed UserOperations.
+const USER_OP_TOOLS: [&str; 2] = ["pq-userop sign", "pq-signerd"];
+
+/// How [`summarize`] buckets ops by signing time (UTC).
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Day,
+    /// Weeks starting on Monday.
+    Week,
+    #[default]
+    Month,
+}
+
+impl Period {
+    /// The period `secs` falls in: `2026-10-14`, the week's Monday, or
+    /// `2026-10`.
+    pub fn label(self, secs: u64) -> String {
+        match self {
+            Period::Day => siwe::timestamp(secs)[..10].to_string(),
+            Period::Week => {
+                // 1970-01-01 was a Thursday.
+                let day = secs / 86_400;
+                let monday = day.saturating_sub((day + 3) % 7);
+                siwe::timestamp(monday * 86_400)[..10].to_string()
+            }
+            Period::Month => siwe::timestamp(secs)[..7].to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Period::Day => "day",
+            Period::Week => "week",
+            Period::Month => "month",
+        })
+    }
+}
+
+/// An op the account signed, from its audit entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signed {
+    pub user_op_hash: B256,
+    pub chain_id: u64,
+    pub signed_at: u64,
+}
+
+/// The UserOperations `key_id` signed, oldest first. An op signed more than
+/// once counts once, from the first time.
+pub fn signed_ops(entries: &[Entry], key_id: Address) -> Vec<Signed> {
+    let mut seen = HashSet::new();
+    entries
+        .iter()
+        .filter(|e| e.record.key_id == key_id && USER_OP_TOOLS.contains(&e.record.tool.as_str()))
+        .filter_map(|e| {
+            Some(Signed {
+                user_op_hash: e.record.digest?,
+                chain_id: e.record.chain_id?,
+                signed_at: e.timestamp,
+            })
+        })
+        .filter(|op| seen.insert((op.user_op_hash, op.chain_id)))
+        .collect()
+}
+
+/// A JSON-RPC endpoint and the chain it serves.
+pub struct Endpoint<T> {
+    /// How the endpoint is shown in the report.
+    pub label: String,
+    pub chain_id: u64,
+    pub rpc: T,
+}
+
+impl<T: Transport> Endpoint<T> {
+    /// Ask `rpc` which chain it serves.
+    pub fn connect(label: impl Into<String>, rpc: T) -> Result<Self> {
+        let chain_id = rpc.call::<U64>("eth_chainId", json!([]))?.to::<u64>();
+        Ok(Endpoint {
+            label: label.into(),
+            chain_id,
+            rpc,
+        })
+    }
+}
+
+/// What became of a signed op.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Observed {
+    pub user_op_hash: B256,
+    pub chain_id: u64,
+    pub signed_at: u64,
+    /// The bundler that knows the op, if any does.
+    pub bundler: Option<String>,
+    pub state: State,
+    /// `UserOperationEvent.success`, once included.
+    pub success: Option<bool>,
+    pub gas_used: U256,
+    /// Wei the account (or its paymaster) paid.
+    pub gas_cost: U256,
+    /// Seconds from signing to the block that included the op.
+    pub latency: Option<u64>,
+    /// Native value the op's calls sent, in wei.
+    pub value: U256,
+}
+
+#[derive(Deserialize)]
+struct Block {
+    timestamp: U64,
+}
+
+/// Native value sent by the calls in the op `bundler` returns for `hash`.
+fn value_sent<T: Transport>(bundler: &T, hash: B256) -> Result<U256> {
+    let found: Option<Value> = bundler.call("eth_getUserOperationByHash", json!([hash]))?;
+    let call_data = found
+        .as_ref()
+        .and_then(|f| f["userOperation"]["callData"].as_str())
+        .and_then(|hex| ct::decode_hex(hex).ok());
+    Ok(call_data
+        .and_then(|data| calldata::decode_execute(&data))
+        .map_or(U256::ZERO, |calls| calls.iter().map(|c| c.value).sum()))
+}
+
+/// Look `op` up on `bundlers`, in order, skipping those on other chains.
+/// `nodes` date the inclusion block for the latency.
+pub fn observe<T: Transport>(
+    op: &Signed,
+    bundlers: &[Endpoint<T>],
+    nodes: &[Endpoint<T>],
+) -> Result<Observed> {
+    let mut observed = Observed {
+        user_op_hash: op.user_op_hash,
+        chain_id: op.chain_id,
+        signed_at: op.signed_at,
+        bundler: None,
+        state: State::Unknown,
+        success: None,
+        gas_used: U256::ZERO,
+        gas_cost: U256::ZERO,
+        latency: None,
+        value: U256::ZERO,
+    };
+    for bundler in bundlers.iter().filter(|b| b.chain_id == op.chain_id) {
+        let status = bundler::status(&bundler.rpc, op.user_op_hash)?;
+        if status.state == State::Unknown {
+            continue;
+        }
+        observed.bundler = Some(bundler.label.clone());
+        observed.state = status.state;
+        if status.state == State::Included {
+            observed.success = status.success;
+            observed.gas_used = status.actual_gas_used.unwrap_or_default();
+            observed.gas_cost = status.actual_gas_cost.unwrap_or_default();
+            observed.value = value_sent(&bundler.rpc, op.user_op_hash)?;
+            let node = nodes.iter().find(|n| n.chain_id == op.chain_id);
+            if let (Some(node), Some(number)) = (node, status.block_number) {
+                let block: Option<Block> = node
+                    .rpc
+                    .call("eth_getBlockByNumber", json!([U64::from(number), false]))?;
+                observed.latency =
+                    block.map(|b| b.timestamp.to::<u64>().saturating_sub(op.signed_at));
+            }
+        }
+        break;
+    }
+    Ok(observed)
+}
+
+/// Counts and sums over a set of ops.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Totals {
+    pub signed: u64,
+    /// Known to a bundler.
+    pub submitted: u64,
+    pub included: u64,
+    /// Included, but the op reverted.
+    pub failed: u64,
+    pub gas_used: U256,
+    pub gas_cost: U256,
+    pub value: U256,
+}
+
+impl Totals {
+    fn add(&mut self, op: &Observed) {
+        self.signed += 1;
+        self.submitted += u64::from(op.state != State::Unknown);
+        self.included += u64::from(op.state == State::Included);
+        self.failed += u64::from(op.success == Some(false));
+        self.gas_used += op.gas_used;
+        self.gas_cost += op.gas_cost;
+        self.value += op.value;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundlerStats {
+    pub bundler: String,
+    #[serde(flatten)]
+    pub totals: Totals,
+    /// Mean seconds from signing to inclusion, over the ops a node dated.
+    pub average_latency: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeriodStats {
+    pub period: String,
+    #[serde(flatten)]
+    pub totals: Totals,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    pub account: String,
+    #[serde(flatten)]
+    pub totals: Totals,
+    pub bundlers: Vec<BundlerStats>,
+    pub period: Period,
+    pub periods: Vec<PeriodStats>,
+    pub ops: Vec<Observed>,
+}
+
+/// Total `ops` for `account`, per bundler (in order of first use) and per
+/// `period`.
+pub fn summarize(account: &str, ops: Vec<Observed>, period: Period) -> Stats {
+    let mut totals = Totals::default();
+    let mut bundlers: Vec<(BundlerStats, Vec<u64>)> = Vec::new();
+    let mut periods: BTreeMap<String, Totals> = BTreeMap::new();
+    for op in &ops {
+        totals.add(op);
+        periods
+            .entry(period.label(op.signed_at))
+            .or_default()
+            .add(op);
+        let Some(label) = &op.bundler else {
+            continue;
+        };
+        let at = match bundlers.iter().position(|(b, _)| b.bundler == *label) {
+            Some(at) => at,
+            None => {
+                bundlers.push((
+                    BundlerStats {
+                        bundler: label.clone(),
+                        totals: Totals::default(),
+                        average_latency: None,
+                    },
+                    Vec::new(),
+                ));
+                bundlers.len() - 1
+            }
+        };
+        let (stats, latencies) = &mut bundlers[at];
+        stats.totals.add(op);
+        latencies.extend(op.latency);
+    }
+    let bundlers = bundlers
+        .into_iter()
+        .map(|(mut stats, latencies)| {
+            if !latencies.is_empty() {
+                let sum: u64 = latencies.iter().sum();
+                stats.average_latency = Some(sum as f64 / latencies.len() as f64);
+            }
+            stats
+        })
+        .collect();
+    Stats {
+        account: account.to_string(),
+        totals,
+        bundlers,
+        period,
+        periods: periods
+            .into_iter()
+            .map(|(period, totals)| PeriodStats { period, totals })
+            .collect(),
+        ops,
+    }
+}
+
+impl Stats {
+    /// One row per period and a `total` row, with a header. Gas cost and
+    /// value are in wei.
+    pub fn to_csv(&self) -> String {
+        let mut out =
+            String::from("period,signed,submitted,included,failed,gasUsed,gasCostWei,valueWei\n");
+        let rows = self
+            .periods
+            .iter()
+            .map(|p| (p.period.as_str(), &p.totals))
+            .chain([("total", &self.totals)]);
+        for (period, t) in rows {
+            writeln!(
+                out,
+                "{period},{},{},{},{},{},{},{}",
+                t.signed, t.submitted, t.included, t.failed, t.gas_used, t.gas_cost, t.value
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_sol_types::SolEvent;
+
+    use crate::audit::Record;
+    use crate::bundler::UserOperationEvent;
+    use crate::calldata::Call;
+    use crate::userop::ENTRY_POINT_V07;
+
+    /// A bundler on chain 1 that has included one op, and a node that
+    /// mined it at 1,000.
+    struct Bundler {
+        included: B256,
+    }
+
+    impl Transport for Bundler {
+        fn request(&self, method: &str, params: Value) -> Result<Value> {
+            let known = params[0] == json!(self.included);
+            Ok(match method {
+                "eth_getUserOperationReceipt" if known => {
+                    let event = UserOperationEvent {
+                        userOpHash: self.included,
+                        sender: Address::repeat_byte(3),
+                        paymaster: Address::ZERO,
+                        nonce: U256::ZERO,
+                        success: false,
+                        actualGasCost: U256::from(1_000_000_000u64),
+                        actualGasUsed: U256::from(100_000),
+                    };
+                    let topics: Vec<B256> =
+                        event.encode_topics().into_iter().map(|t| t.0).collect();
+                    json!({
+                        "userOpHash": self.included,
+                        "entryPoint": ENTRY_POINT_V07,
+                        "receipt": {
+                            "transactionHash": B256::repeat_byte(9),
+                            "blockNumber": "0x10",
+                            "logs": [{
+                                "address": ENTRY_POINT_V07,
+                                "topics": topics,
+                                "data": event.encode_log_data().data,
+                            }],
+                        },
+                    })
+                }
+                "eth_getUserOperationByHash" if known => json!({
+                    "entryPoint": ENTRY_POINT_V07,
+                    "userOperation": { "callData": format!("0x{}", ct::encode_hex(&calldata::execute(&[Call {
+                        target: Address::repeat_byte(2),
+                        value: U256::from(5),
+                        data: vec![],
+                    }]))) },
+                }),
+                "eth_getBlockByNumber" => json!({ "timestamp": "0x3e8" }),
+                _ => Value::Null,
+            })
+        }
+    }
+
+    fn entry(tool: &str, key_id: Address, hash: u8, timestamp: u64) -> Entry {
+        Entry {
+            seq: 0,
+            timestamp,
+            record: Record {
+                tool: tool.into(),
+                account: None,
+                key_id,
+                digest: Some(B256::repeat_byte(hash)),
+                chain_id: Some(1),
+                signature: B256::ZERO,
+                summary: vec![],
+                backend: "rustcrypto".into(),
+            },
+            prev: B256::ZERO,
+            hash: B256::ZERO,
+        }
+    }
+
+    #[test]
+    fn totals_ops_per_bundler_and_period() {
+        let key = Address::repeat_byte(1);
+        let day = 86_400;
+        let entries = [
+            entry("pq-userop sign", key, 1, 400),
+            entry("pq-userop sign", key, 1, 500),
+            entry("pq-signerd", key, 2, 40 * day),
+            entry("pq-sign", key, 3, 600),
+            entry("pq-userop sign", Address::ZERO, 4, 700),
+        ];
+        let signed = signed_ops(&entries, key);
+        assert_eq!(signed.len(), 2);
+        assert_eq!(signed[0].signed_at, 400);
+
+        let endpoint = |label: &str| Endpoint {
+            label: label.into(),
+            chain_id: 1,
+            rpc: Bundler {
+                included: B256::repeat_byte(1),
+            },
+        };
+        let bundlers = [endpoint("alto")];
+        let ops = signed
+            .iter()
+            .map(|op| observe(op, &bundlers, &[endpoint("node")]).unwrap())
+            .collect();
+        let stats = summarize("alice", ops, Period::Month);
+
+        assert_eq!(
+            (
+                stats.totals.signed,
+                stats.totals.submitted,
+                stats.totals.failed
+            ),
+            (2, 1, 1)
+        );
+        assert_eq!(stats.totals.gas_cost, U256::from(1_000_000_000u64));
+        assert_eq!(stats.totals.value, U256::from(5));
+        assert_eq!(stats.bundlers.len(), 1);
+        assert_eq!(stats.bundlers[0].average_latency, Some(600.0));
+        let periods: Vec<_> = stats.periods.iter().map(|p| p.period.as_str()).collect();
+        assert_eq!(periods, ["1970-01", "1970-02"]);
+
+        let csv = stats.to_csv();
+        assert_eq!(csv.lines().count(), 4);
+        assert!(csv.ends_with("total,2,1,1,1,100000,1000000000,5\n"));
+
+        // 1970-01-05 was a Monday.
+        assert_eq!(Period::Week.label(4 * day), "1970-01-05");
+        assert_eq!(Period::Week.label(10 * day + 5), "1970-01-05");
+        assert_eq!(Period::Day.label(10 * day), "1970-01-11");
+    }
+}
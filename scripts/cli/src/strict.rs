@@ -0,0 +1,324 @@
+//! Strict encoding checks for ML-DSA-65 signatures (`pq-verify --strict`).
+//!
+//! The on-chain verifier decodes a signature with FIPS 204 `sigDecode`
+//! before verifying it, and refuses anything that is not exactly 3,309
+//! canonically encoded bytes. [`check_signature`] applies the same rules
+//! and names the one that failed, so a signature is accepted off-chain only
+//! if the chain would accept its encoding too:
+//!
+//! - the response `z` has every coefficient below `γ1 − β` in magnitude;
+//! - the hint's per-polynomial counts never decrease and stay within `ω`;
+//! - each polynomial's hint indices are strictly increasing;
+//! - the unused hint slots are zero.
+//!
+//! Together these make the encoding unique: a signature that passes
+//! re-encodes to the same bytes, so it cannot be altered without failing
+//! to decode. [`check_envelope`] additionally requires an envelope to be
+//! exactly one pk and signature, with nothing after them, and a versioned
+//! envelope to have a version this build knows.
+
+use std::fmt;
+
+use ml_dsa::{MlDsa65, Signature};
+
+use crate::envelope::{self, Envelope, PACKED_ML_DSA_65_LEN};
+use crate::error::WalletError;
+use crate::keys::{PUBLIC_KEY_LEN, SIGNATURE_LEN};
+
+/// ML-DSA-65 parameters (FIPS 204, table 1).
+const LAMBDA_BYTES: usize = 48;
+const L: usize = 5;
+const K: usize = 6;
+const OMEGA: usize = 55;
+const GAMMA1: i32 = 1 << 19;
+const BETA: i32 = 196;
+const Z_BYTES: usize = L * 256 * 20 / 8;
+
+/// The first rule a signature or envelope breaks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    Length {
+        actual: usize,
+    },
+    /// Coefficient `index` of `z` (across all polynomials) is out of range.
+    ZOutOfRange {
+        index: usize,
+        value: i32,
+    },
+    /// The hint count for polynomial `poly` is below the previous one or
+    /// above `ω`.
+    HintCount {
+        poly: usize,
+    },
+    /// Hint indices for polynomial `poly` are not strictly increasing.
+    HintOrder {
+        poly: usize,
+    },
+    /// A hint slot past the last count is nonzero.
+    HintPadding,
+    /// Bytes after the envelope's signature.
+    Trailing {
+        extra: usize,
+    },
+    /// The ABI envelope does not re-encode to the same bytes.
+    NonCanonicalEnvelope(String),
+    /// A versioned envelope with a version byte this build does not know.
+    UnsupportedVersion(u8),
+    /// The envelope carries a different public key than expected.
+    KeyMismatch,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Length { actual } => {
+                write!(f, "signature is {actual} bytes, expected {SIGNATURE_LEN}")
+            }
+            Violation::ZOutOfRange { index, value } => write!(
+                f,
+                "z coefficient {index} is {value}, outside ±{}",
+                GAMMA1 - BETA - 1
+            ),
+            Violation::HintCount { poly } => {
+                write!(
+                    f,
+                    "hint count for polynomial {poly} is out of order or past ω"
+                )
+            }
+            Violation::HintOrder { poly } => {
+                write!(
+                    f,
+                    "hint indices for polynomial {poly} are not strictly increasing"
+                )
+            }
+            Violation::HintPadding => write!(f, "unused hint slots are not zero"),
+            Violation::Trailing { extra } => {
+                write!(f, "{extra} trailing bytes after the signature")
+            }
+            Violation::NonCanonicalEnvelope(reason) => {
+                write!(f, "non-canonical envelope: {reason}")
+            }
+            Violation::UnsupportedVersion(version) => {
+                write!(f, "{}", WalletError::UnsupportedEnvelopeVersion(*version))
+            }
+            Violation::KeyMismatch => write!(f, "envelope carries a different public key"),
+        }
+    }
+}
+
+/// How an envelope lays out the key and signature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnvelopeLayout {
+    /// `abi.encode(uint8 schemeId, bytes pubKey, bytes sig)`.
+    Abi,
+    /// `pubKey || sig`.
+    Packed,
+    /// `version || body`; see [`envelope::Envelope`].
+    Versioned,
+}
+
+impl std::str::FromStr for EnvelopeLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "abi" => Ok(EnvelopeLayout::Abi),
+            "packed" => Ok(EnvelopeLayout::Packed),
+            "versioned" => Ok(EnvelopeLayout::Versioned),
+            other => Err(format!(
+                "unknown envelope {other:?} (expected abi, packed or versioned)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for EnvelopeLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EnvelopeLayout::Abi => "abi",
+            EnvelopeLayout::Packed => "packed",
+            EnvelopeLayout::Versioned => "versioned",
+        })
+    }
+}
+
+/// Check that `sig` is a canonically encoded ML-DSA-65 signature.
+pub fn check_signature(sig: &[u8]) -> Result<(), Violation> {
+    if sig.len() != SIGNATURE_LEN {
+        return Err(Violation::Length { actual: sig.len() });
+    }
+    let z = &sig[LAMBDA_BYTES..LAMBDA_BYTES + Z_BYTES];
+    // 20-bit little-endian packing: two coefficients per five bytes.
+    for (pair, b) in z.chunks_exact(5).enumerate() {
+        let v0 = b[0] as i32 | (b[1] as i32) << 8 | ((b[2] & 0x0f) as i32) << 16;
+        let v1 = (b[2] >> 4) as i32 | (b[3] as i32) << 4 | (b[4] as i32) << 12;
+        for (offset, v) in [v0, v1].into_iter().enumerate() {
+            let value = GAMMA1 - v;
+            if value.abs() >= GAMMA1 - BETA {
+                return Err(Violation::ZOutOfRange {
+                    index: 2 * pair + offset,
+                    value,
+                });
+            }
+        }
+    }
+    let (indices, counts) = sig[LAMBDA_BYTES + Z_BYTES..].split_at(OMEGA);
+    let mut start = 0;
+    for (poly, &end) in counts.iter().enumerate() {
+        let end = end as usize;
+        if end < start || end > OMEGA {
+            return Err(Violation::HintCount { poly });
+        }
+        if !indices[start..end].windows(2).all(|w| w[0] < w[1]) {
+            return Err(Violation::HintOrder { poly });
+        }
+        start = end;
+    }
+    if indices[start..].iter().any(|&i| i != 0) {
+        return Err(Violation::HintPadding);
+    }
+    debug_assert_eq!(counts.len(), K);
+    // The rules above are sigDecode's; the library must agree.
+    debug_assert!(Signature::<MlDsa65>::try_from(sig).is_ok());
+    Ok(())
+}
+
+/// Split an envelope into its signature, checking it holds exactly
+/// `expected_pk` (if given) and one canonical signature.
+pub fn check_envelope(
+    data: &[u8],
+    layout: EnvelopeLayout,
+    expected_pk: Option<&[u8]>,
+) -> Result<Vec<u8>, Violation> {
+    let decoded = match layout {
+        EnvelopeLayout::Packed => {
+            if data.len() > PACKED_ML_DSA_65_LEN {
+                return Err(Violation::Trailing {
+                    extra: data.len() - PACKED_ML_DSA_65_LEN,
+                });
+            }
+            envelope::decode_signature_envelope_packed(data).map_err(|_| Violation::Length {
+                actual: data.len().saturating_sub(PUBLIC_KEY_LEN),
+            })?
+        }
+        EnvelopeLayout::Abi => {
+            let decoded = envelope::decode_signature_envelope(data)
+                .map_err(|e| Violation::NonCanonicalEnvelope(e.to_string()))?;
+            let canonical = envelope::encode_signature_envelope(
+                &decoded.public_key,
+                &decoded.signature,
+                decoded.scheme_id,
+            )
+            .map_err(|e| Violation::NonCanonicalEnvelope(e.to_string()))?;
+            if data.len() > canonical.len() && data.starts_with(&canonical) {
+                return Err(Violation::Trailing {
+                    extra: data.len() - canonical.len(),
+                });
+            }
+            if data != canonical {
+                return Err(Violation::NonCanonicalEnvelope(
+                    "does not re-encode to the same bytes".into(),
+                ));
+            }
+            decoded
+        }
+        // Envelope::decode is already exact.
+        EnvelopeLayout::Versioned => match Envelope::decode(data) {
+            Ok(decoded) => decoded.pq().clone(),
+            Err(WalletError::UnsupportedEnvelopeVersion(v)) => {
+                return Err(Violation::UnsupportedVersion(v));
+            }
+            Err(e) => return Err(Violation::NonCanonicalEnvelope(e.to_string())),
+        },
+    };
+    if expected_pk.is_some_and(|pk| pk != decoded.public_key) {
+        return Err(Violation::KeyMismatch);
+    }
+    check_signature(&decoded.signature)?;
+    Ok(decoded.signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys;
+    use ml_dsa::signature::Signer;
+
+    fn signed() -> (Vec<u8>, Vec<u8>) {
+        let kp = keys::keygen_from_seed(&[9u8; 32]);
+        let sig = kp.signing_key().sign(b"strict").encode().to_vec();
+        (kp.verifying_key().encode().to_vec(), sig)
+    }
+
+    #[test]
+    fn rejects_each_non_canonical_encoding() {
+        let (_, sig) = signed();
+        assert_eq!(check_signature(&sig), Ok(()));
+
+        let mut long = sig.clone();
+        long.push(0);
+        assert_eq!(
+            check_signature(&long),
+            Err(Violation::Length { actual: 3310 })
+        );
+
+        let hint = LAMBDA_BYTES + Z_BYTES;
+        let mut padded = sig.clone();
+        padded[hint + OMEGA - 1] = 1;
+        let mut counts = sig.clone();
+        counts[hint + OMEGA] = OMEGA as u8 + 1;
+        let mut z = sig.clone();
+        // v = 0 is z = γ1, past the bound.
+        z[LAMBDA_BYTES..LAMBDA_BYTES + 3].fill(0);
+        z[LAMBDA_BYTES + 2] &= 0xf0;
+        for (bad, expected) in [
+            (padded, Violation::HintPadding),
+            (counts, Violation::HintCount { poly: 0 }),
+            (
+                z,
+                Violation::ZOutOfRange {
+                    index: 0,
+                    value: GAMMA1,
+                },
+            ),
+        ] {
+            let got = check_signature(&bad).unwrap_err();
+            assert!(
+                std::mem::discriminant(&got) == std::mem::discriminant(&expected),
+                "{got:?}"
+            );
+            // Whatever strict mode rejects, the verifier cannot decode.
+            assert!(Signature::<MlDsa65>::try_from(bad.as_slice()).is_err());
+        }
+    }
+
+    #[test]
+    fn envelopes_must_be_exact() {
+        let (pk, sig) = signed();
+        let packed = envelope::encode_signature_envelope_packed(&pk, &sig).unwrap();
+        assert_eq!(
+            check_envelope(&packed, EnvelopeLayout::Packed, Some(&pk)),
+            Ok(sig.clone())
+        );
+        let mut trailing = packed.clone();
+        trailing.extend([0; 4]);
+        assert_eq!(
+            check_envelope(&trailing, EnvelopeLayout::Packed, None),
+            Err(Violation::Trailing { extra: 4 })
+        );
+        assert_eq!(
+            check_envelope(&packed, EnvelopeLayout::Packed, Some(&[0; PUBLIC_KEY_LEN])),
+            Err(Violation::KeyMismatch)
+        );
+
+        let abi =
+            envelope::encode_signature_envelope(&pk, &sig, envelope::SCHEME_ML_DSA_65).unwrap();
+        assert_eq!(
+            check_envelope(&abi, EnvelopeLayout::Abi, Some(&pk)),
+            Ok(sig)
+        );
+        let mut trailing = abi.clone();
+        trailing.extend([0; 32]);
+        assert!(check_envelope(&trailing, EnvelopeLayout::Abi, None).is_err());
+    }
+}
@@ -0,0 +1,691 @@
+//! Experimental t-of-n threshold ML-DSA-65, behind `unstable-threshold`.
+//!
+//! Research-grade. The protocol and its security argument follow the
+//! threshold-ML-DSA literature, but nothing here has been reviewed. Do not
+//! put it in front of real funds.
+//!
+//! The output is an ordinary FIPS 204 signature under an ordinary ML-DSA-65
+//! public key, so validators need no changes. No party ever holds the
+//! signing key `s1`:
+//!
+//! - **Replicated secret sharing.** `s1` and `s2` are sums of ternary
+//!   sub-shares, one sub-share for every set of `n - t + 1` parties. Each
+//!   sub-share is held by the parties in its set, so any `t` parties
+//!   between them hold all of them. In a session, each sub-share is used by
+//!   the lowest-numbered active signer that holds it.
+//! - **Key generation** is a trusted dealer ([`deal`]). Run it offline and
+//!   keep only the share files. The sum of the sub-shares has coefficients
+//!   of at most [`ring::ETA`], so the standard ML-DSA-65 bounds still hold.
+//! - **Signing** takes three rounds per session. Each session runs
+//!   `attempts` independent instances side by side, so one session almost
+//!   always yields a signature despite rejection sampling.
+//!   1. Each signer samples `y_i` in a box of width `2γ₁/t`, computes
+//!      `w_i = A·y_i`, and commits to `keccak256(w_i)` ([`KeyShare::commit`]).
+//!   2. Once every commitment is in, each signer reveals `w_i`
+//!      ([`SignerState::reveal`]).
+//!   3. Each signer checks the reveals against the commitments, then
+//!      derives `c̃ = H(μ ‖ w1Encode(HighBits(Σ w_i)))` itself, so the
+//!      coordinator cannot choose the challenge. It returns
+//!      `z_i = y_i + c·s1_i` only where `‖z_i‖∞ < γ₁/t - τ·m_i`
+//!      ([`SignerState::respond`]). This local rejection keeps each `z_i`
+//!      independent of the signer's share, the same argument as
+//!      single-party ML-DSA.
+//! - **The coordinator** ([`combine`]) sums the partials. It recovers
+//!   `w - c·s2` as `A·z - c·t` from the public `t = t1·2^d + t0` (the group
+//!   key publishes `t0`). It applies the remaining FIPS 204 checks, computes
+//!   the hints, and verifies the result with both backends before
+//!   returning it.
+
+pub mod ring;
+
+use std::path::Path;
+
+use alloy_primitives::{B256, Bytes, keccak256};
+use rand::{Rng, RngExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+use crate::error::{Result, WalletError};
+use crate::keys::{PUBLIC_KEY_LEN, SIGNATURE_LEN};
+use ring::{BETA, CTILDE_LEN, D, GAMMA1, GAMMA2, K, L, Matrix, N, OMEGA, Poly, Q, TAU, inf_norm};
+
+/// Domain separator for commitments.
+pub const DOMAIN: &[u8] = b"pq-wallet/threshold/v1";
+/// Parallel instances per session unless the coordinator asks otherwise.
+pub const DEFAULT_ATTEMPTS: u16 = 32;
+pub const MAX_ATTEMPTS: u16 = 256;
+/// Largest group [`deal`] accepts.
+pub const MAX_PARTIES: u8 = 8;
+
+/// Bits per packed `w` coefficient (`q < 2^23`).
+const W_BITS: u32 = 23;
+/// Bits per packed `y` or `z` coefficient.
+const Z_BITS: u32 = 20;
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::Threshold(reason.into())
+}
+
+/// Read one protocol message (or share, or state) from a JSON file.
+pub fn load<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let bytes = std::fs::read(path).map_err(|e| WalletError::Io(path.display().to_string(), e))?;
+    serde_json::from_slice(&bytes).map_err(|e| err(format!("{}: {e}", path.display())))
+}
+
+/// Write a protocol message as JSON. Shares and signer state hold secrets
+/// and are written owner-only, like keystore entries.
+pub fn save<T: Serialize>(path: &Path, value: &T, secret: bool) -> Result<()> {
+    let json = serde_json::to_string_pretty(value).expect("threshold messages serialize");
+    if secret {
+        crate::keystore::write_private(path, json.as_bytes())
+    } else {
+        std::fs::write(path, json).map_err(|e| WalletError::Io(path.display().to_string(), e))
+    }
+}
+
+/// The group's public data: a plain ML-DSA-65 public key plus `t0`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupKey {
+    pub threshold: u8,
+    pub parties: u8,
+    /// Standard 1,952-byte public key.
+    pub public_key: Bytes,
+    /// The low bits of `t`, 13-bit packed. Part of the secret key in
+    /// single-party ML-DSA; the coordinator needs it for hints.
+    pub t0: Bytes,
+}
+
+struct Expanded {
+    matrix: Matrix,
+    /// `t` in the NTT domain.
+    t_hat: Vec<Poly>,
+    t0_hat: Vec<Poly>,
+    tr: [u8; 64],
+}
+
+impl GroupKey {
+    /// keccak256 of the public key; sessions name their group by it.
+    pub fn fingerprint(&self) -> B256 {
+        keccak256(&self.public_key)
+    }
+
+    pub fn public_key(&self) -> Result<[u8; PUBLIC_KEY_LEN]> {
+        self.public_key
+            .as_ref()
+            .try_into()
+            .map_err(|_| WalletError::InvalidLength {
+                what: "group public key",
+                expected: PUBLIC_KEY_LEN,
+                actual: self.public_key.len(),
+            })
+    }
+
+    fn expand(&self) -> Result<Expanded> {
+        let pk = self.public_key()?;
+        let (rho, t1) = ring::pk_decode(&pk).ok_or_else(|| err("malformed public key"))?;
+        let t0 = ring::unpack_centered(&self.t0, 1 << (D - 1), D, K)
+            .ok_or_else(|| err("malformed t0"))?;
+        let t: Vec<Poly> = t1
+            .iter()
+            .zip(&t0)
+            .map(|(hi, lo)| {
+                std::array::from_fn(|i| ring::reduce(((hi[i] as i64) << D) + lo[i] as i64))
+            })
+            .collect();
+        let mut tr = [0u8; 64];
+        ring::shake256(&[&pk], &mut tr);
+        Ok(Expanded {
+            matrix: Matrix::expand(&rho),
+            t_hat: ring::ntt_vec(&t),
+            t0_hat: ring::ntt_vec(&t0),
+            tr,
+        })
+    }
+}
+
+/// One ternary piece of `s1`, held by every party in `holders`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubShare {
+    pub holders: Vec<u8>,
+    /// 2-bit packed, as `1 - s`.
+    pub s1: Bytes,
+}
+
+/// What one party keeps: its sub-shares and the group key.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyShare {
+    pub party: u8,
+    pub group: GroupKey,
+    pub sub_shares: Vec<SubShare>,
+}
+
+/// Every set of `n - t + 1` parties, in lexicographic order.
+pub fn holder_sets(threshold: u8, parties: u8) -> Vec<Vec<u8>> {
+    let size = (parties - threshold + 1) as u32;
+    let mut sets: Vec<Vec<u8>> = (0u32..1 << parties)
+        .filter(|mask| mask.count_ones() == size)
+        .map(|mask| (1..=parties).filter(|p| mask >> (p - 1) & 1 == 1).collect())
+        .collect();
+    sets.sort();
+    sets
+}
+
+fn ternary<R: Rng>(rng: &mut R, polys: usize) -> Vec<Poly> {
+    (0..polys)
+        .map(|_| std::array::from_fn(|_| ring::reduce(rng.random_range(-1..=1))))
+        .collect()
+}
+
+fn sum(vectors: impl IntoIterator<Item = Vec<Poly>>, polys: usize) -> Vec<Poly> {
+    vectors.into_iter().fold(vec![[0; N]; polys], |acc, v| {
+        acc.iter().zip(&v).map(|(a, b)| ring::add(a, b)).collect()
+    })
+}
+
+/// Trusted-dealer key generation for a `threshold`-of-`parties` group.
+pub fn deal<R: Rng>(threshold: u8, parties: u8, rng: &mut R) -> Result<(GroupKey, Vec<KeyShare>)> {
+    if threshold == 0 || threshold > parties || parties > MAX_PARTIES {
+        return Err(err(format!(
+            "need 1 <= threshold <= parties <= {MAX_PARTIES}, got {threshold}-of-{parties}"
+        )));
+    }
+    let sets = holder_sets(threshold, parties);
+    if sets.len() > ring::ETA as usize {
+        return Err(err(format!(
+            "{threshold}-of-{parties} needs {} sub-shares; at most {} keep s1 within η",
+            sets.len(),
+            ring::ETA
+        )));
+    }
+    let rho: [u8; 32] = rng.random();
+    let s1_parts: Vec<Vec<Poly>> = sets.iter().map(|_| ternary(rng, L)).collect();
+    let s2 = sum(sets.iter().map(|_| ternary(rng, K)), K);
+    let s1 = sum(s1_parts.iter().cloned(), L);
+
+    let matrix = Matrix::expand(&rho);
+    let t: Vec<Poly> = ring::inv_ntt_vec(&matrix.mul(&ring::ntt_vec(&s1)))
+        .iter()
+        .zip(&s2)
+        .map(|(a, b)| ring::add(a, b))
+        .collect();
+    let (t1, t0): (Vec<Poly>, Vec<Poly>) = t
+        .iter()
+        .map(|p| {
+            let split: [(i32, i32); N] = std::array::from_fn(|i| ring::power2round(p[i]));
+            (
+                split.map(|(hi, _)| hi),
+                split.map(|(_, lo)| ring::reduce(lo as i64)),
+            )
+        })
+        .unzip();
+    let group = GroupKey {
+        threshold,
+        parties,
+        public_key: ring::pk_encode(&rho, &t1).into(),
+        t0: ring::pack_centered(&t0, 1 << (D - 1), D).into(),
+    };
+    let shares = (1..=parties)
+        .map(|party| KeyShare {
+            party,
+            group: group.clone(),
+            sub_shares: sets
+                .iter()
+                .zip(&s1_parts)
+                .filter(|(holders, _)| holders.contains(&party))
+                .map(|(holders, s1)| SubShare {
+                    holders: holders.clone(),
+                    s1: ring::pack_centered(s1, 1, 2).into(),
+                })
+                .collect(),
+        })
+        .collect();
+    Ok((group, shares))
+}
+
+/// What the coordinator announces to start a signing session.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub id: B256,
+    /// [`GroupKey::fingerprint`].
+    pub group: B256,
+    /// Signed as pure ML-DSA with an empty context, like `pq-sign`.
+    pub message: Bytes,
+    /// Exactly `threshold` distinct parties, ascending.
+    pub signers: Vec<u8>,
+    pub attempts: u16,
+}
+
+impl Session {
+    pub fn new(
+        group: &GroupKey,
+        message: &[u8],
+        mut signers: Vec<u8>,
+        attempts: u16,
+    ) -> Result<Self> {
+        signers.sort_unstable();
+        let session = Session {
+            id: B256::from(rand::random::<[u8; 32]>()),
+            group: group.fingerprint(),
+            message: Bytes::copy_from_slice(message),
+            signers,
+            attempts,
+        };
+        session.check(group)?;
+        Ok(session)
+    }
+
+    fn check(&self, group: &GroupKey) -> Result<()> {
+        if self.group != group.fingerprint() {
+            return Err(err(format!("session is for group {}", self.group)));
+        }
+        let distinct = self.signers.windows(2).all(|w| w[0] < w[1]);
+        if !distinct
+            || self.signers.len() != group.threshold as usize
+            || self.signers.iter().any(|&p| p == 0 || p > group.parties)
+        {
+            return Err(err(format!(
+                "signers must be {} distinct parties in 1..={}",
+                group.threshold, group.parties
+            )));
+        }
+        if self.attempts == 0 || self.attempts > MAX_ATTEMPTS {
+            return Err(err(format!("attempts must be in 1..={MAX_ATTEMPTS}")));
+        }
+        Ok(())
+    }
+
+    /// Per-signer bound on `y_i`: the signers' boxes sum to at most γ₁.
+    fn gamma(&self) -> i32 {
+        GAMMA1 / self.signers.len() as i32
+    }
+
+    fn mu(&self, tr: &[u8; 64]) -> [u8; 64] {
+        let mut mu = [0u8; 64];
+        // M' = 0 || |ctx| || ctx || M with an empty context.
+        ring::shake256(&[tr, &[0, 0], &self.message], &mut mu);
+        mu
+    }
+}
+
+/// Round 1 output.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Commitment {
+    pub session: B256,
+    pub party: u8,
+    pub digest: B256,
+}
+
+/// Round 2 output: `w_i` for every attempt.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reveal {
+    pub session: B256,
+    pub party: u8,
+    pub w: Bytes,
+}
+
+/// Round 3 output: `z_i` for each attempt that passed local rejection.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Partial {
+    pub session: B256,
+    pub party: u8,
+    pub z: Vec<Option<Bytes>>,
+}
+
+/// A signer's secret state between rounds. The nonces in it must be used
+/// at most once, so callers delete it after [`SignerState::respond`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignerState {
+    pub session: Session,
+    pub party: u8,
+    /// `y_i` for every attempt, packed.
+    pub y: Bytes,
+    pub w: Bytes,
+    /// Everyone's round 1 commitments, once [`SignerState::reveal`] saw them.
+    #[serde(default)]
+    pub commitments: Vec<Commitment>,
+}
+
+fn commit_digest(session: &B256, party: u8, w: &[u8]) -> B256 {
+    keccak256([DOMAIN, session.as_slice(), &[party], w].concat())
+}
+
+/// Sub-shares `party` uses in this session, summed, and how many there are.
+fn session_secret(share: &KeyShare, signers: &[u8]) -> Result<(Vec<Poly>, usize)> {
+    let mut used = Vec::new();
+    for sub in &share.sub_shares {
+        let user = sub
+            .holders
+            .iter()
+            .copied()
+            .filter(|p| signers.contains(p))
+            .min();
+        if user == Some(share.party) {
+            let s1 = ring::unpack_centered(&sub.s1, 1, 2, L)
+                .ok_or_else(|| err("malformed sub-share"))?;
+            used.push(s1);
+        }
+    }
+    let count = used.len();
+    Ok((sum(used, L), count))
+}
+
+/// `c̃` for one attempt.
+fn challenge(mu: &[u8; 64], w: &[Poly]) -> [u8; CTILDE_LEN] {
+    let w1: Vec<Poly> = w.iter().map(|p| p.map(ring::high_bits)).collect();
+    let mut c_tilde = [0u8; CTILDE_LEN];
+    ring::shake256(&[mu, &ring::w1_encode(&w1)], &mut c_tilde);
+    c_tilde
+}
+
+/// Σ w_i per attempt, after checking there is one reveal per signer.
+fn aggregate_w(session: &Session, reveals: &[Reveal]) -> Result<Vec<Vec<Poly>>> {
+    let mut parties: Vec<u8> = reveals.iter().map(|r| r.party).collect();
+    parties.sort_unstable();
+    if parties != session.signers || reveals.iter().any(|r| r.session != session.id) {
+        return Err(err("need exactly one reveal per signer for this session"));
+    }
+    let attempts = session.attempts as usize;
+    let mut total = vec![vec![[0; N]; K]; attempts];
+    for reveal in reveals {
+        let flat = ring::unpack(&reveal.w, W_BITS, attempts * K * N)
+            .filter(|v| v.iter().all(|&c| (c as i32) < Q))
+            .ok_or_else(|| err(format!("malformed reveal from party {}", reveal.party)))?;
+        for (a, attempt) in total.iter_mut().enumerate() {
+            for (i, poly) in attempt.iter_mut().enumerate() {
+                let offset = (a * K + i) * N;
+                let w: Poly = std::array::from_fn(|j| flat[offset + j] as i32);
+                *poly = ring::add(poly, &w);
+            }
+        }
+    }
+    Ok(total)
+}
+
+impl KeyShare {
+    /// Round 1: sample nonces and commit to `w_i`.
+    pub fn commit<R: Rng>(
+        &self,
+        session: &Session,
+        rng: &mut R,
+    ) -> Result<(SignerState, Commitment)> {
+        session.check(&self.group)?;
+        if !session.signers.contains(&self.party) {
+            return Err(err(format!(
+                "party {} is not a signer in this session",
+                self.party
+            )));
+        }
+        let gamma = session.gamma();
+        let matrix = self.group.expand()?.matrix;
+        let mut y = Vec::with_capacity(session.attempts as usize * L);
+        let mut w = Vec::with_capacity(session.attempts as usize * K);
+        for _ in 0..session.attempts {
+            let attempt: Vec<Poly> = (0..L)
+                .map(|_| {
+                    std::array::from_fn(|_| {
+                        ring::reduce(rng.random_range(-gamma + 1..=gamma) as i64)
+                    })
+                })
+                .collect();
+            w.extend(ring::inv_ntt_vec(&matrix.mul(&ring::ntt_vec(&attempt))));
+            y.extend(attempt);
+        }
+        let w = ring::pack(w.iter().flat_map(|p| p.iter()).map(|&c| c as u32), W_BITS);
+        let commitment = Commitment {
+            session: session.id,
+            party: self.party,
+            digest: commit_digest(&session.id, self.party, &w),
+        };
+        let state = SignerState {
+            session: session.clone(),
+            party: self.party,
+            y: ring::pack_centered(&y, gamma, Z_BITS).into(),
+            w: w.into(),
+            commitments: Vec::new(),
+        };
+        Ok((state, commitment))
+    }
+}
+
+impl SignerState {
+    /// Round 2: reveal `w_i` once every signer has committed.
+    pub fn reveal(&mut self, commitments: &[Commitment]) -> Result<Reveal> {
+        let mut commitments = commitments.to_vec();
+        commitments.sort_by_key(|c| c.party);
+        let parties: Vec<u8> = commitments.iter().map(|c| c.party).collect();
+        if parties != self.session.signers
+            || commitments.iter().any(|c| c.session != self.session.id)
+        {
+            return Err(err(
+                "need exactly one commitment per signer for this session",
+            ));
+        }
+        let own = commitments.iter().find(|c| c.party == self.party).unwrap();
+        if own.digest != commit_digest(&self.session.id, self.party, &self.w) {
+            return Err(err("our own commitment was altered"));
+        }
+        self.commitments = commitments;
+        Ok(Reveal {
+            session: self.session.id,
+            party: self.party,
+            w: self.w.clone(),
+        })
+    }
+
+    /// Round 3: check the reveals, derive each challenge and answer with
+    /// the partial signatures that pass local rejection.
+    pub fn respond(&self, share: &KeyShare, reveals: &[Reveal]) -> Result<Partial> {
+        if share.party != self.party {
+            return Err(err("share and state are for different parties"));
+        }
+        if self.commitments.is_empty() {
+            return Err(err("reveal before responding"));
+        }
+        for reveal in reveals {
+            let committed = self.commitments.iter().find(|c| c.party == reveal.party);
+            if committed.map(|c| c.digest)
+                != Some(commit_digest(&self.session.id, reveal.party, &reveal.w))
+            {
+                return Err(err(format!(
+                    "party {}'s reveal does not match its commitment",
+                    reveal.party
+                )));
+            }
+        }
+        let w = aggregate_w(&self.session, reveals)?;
+        let group = share.group.expand()?;
+        let mu = self.session.mu(&group.tr);
+        let (s1, used) = session_secret(share, &self.session.signers)?;
+        let s1_hat = ring::ntt_vec(&s1);
+        let gamma = self.session.gamma();
+        let bound = gamma - (TAU * used) as i32;
+        let y = ring::unpack_centered(&self.y, gamma, Z_BITS, self.session.attempts as usize * L)
+            .ok_or_else(|| err("corrupt signer state"))?;
+
+        let z = w
+            .iter()
+            .zip(y.chunks(L))
+            .map(|(w, y)| {
+                let c_hat = ring::ntt(&ring::sample_in_ball(&challenge(&mu, w)));
+                let z: Vec<Poly> = ring::scale(&c_hat, &s1_hat)
+                    .iter()
+                    .zip(y)
+                    .map(|(cs1, y)| ring::add(cs1, y))
+                    .collect();
+                (inf_norm(&z) < bound).then(|| ring::pack_centered(&z, GAMMA1, Z_BITS).into())
+            })
+            .collect();
+        Ok(Partial {
+            session: self.session.id,
+            party: self.party,
+            z,
+        })
+    }
+}
+
+/// Coordinator: combine the first attempt every signer answered and that
+/// passes the FIPS 204 checks into a standard signature.
+pub fn combine(
+    group: &GroupKey,
+    session: &Session,
+    reveals: &[Reveal],
+    partials: &[Partial],
+) -> Result<[u8; SIGNATURE_LEN]> {
+    session.check(group)?;
+    let mut parties: Vec<u8> = partials.iter().map(|p| p.party).collect();
+    parties.sort_unstable();
+    if parties != session.signers
+        || partials
+            .iter()
+            .any(|p| p.session != session.id || p.z.len() != session.attempts as usize)
+    {
+        return Err(err("need exactly one partial per signer for this session"));
+    }
+    let w = aggregate_w(session, reveals)?;
+    let expanded = group.expand()?;
+    let mu = session.mu(&expanded.tr);
+    let pk = group.public_key()?;
+
+    'attempt: for (a, w) in w.iter().enumerate() {
+        let mut z = vec![[0; N]; L];
+        for partial in partials {
+            let Some(z_i) = &partial.z[a] else {
+                continue 'attempt;
+            };
+            let z_i = ring::unpack_centered(z_i, GAMMA1, Z_BITS, L)
+                .ok_or_else(|| err(format!("malformed partial from party {}", partial.party)))?;
+            z = z
+                .iter()
+                .zip(&z_i)
+                .map(|(acc, p)| ring::add(acc, p))
+                .collect();
+        }
+        if inf_norm(&z) >= GAMMA1 - BETA {
+            continue;
+        }
+        let c_tilde = challenge(&mu, w);
+        let c_hat = ring::ntt(&ring::sample_in_ball(&c_tilde));
+        // w - c·s2 = A·z - c·t.
+        let az = expanded.matrix.mul(&ring::ntt_vec(&z));
+        let w_cs2: Vec<Poly> = az
+            .iter()
+            .zip(&expanded.t_hat)
+            .map(|(az, t)| ring::inv_ntt(&ring::sub(az, &ring::pointwise(&c_hat, t))))
+            .collect();
+        let r0_ok = w_cs2
+            .iter()
+            .flat_map(|p| p.iter())
+            .all(|&c| ring::low_bits(c).abs() < GAMMA2 - BETA);
+        let high_ok = w_cs2
+            .iter()
+            .zip(w)
+            .all(|(a, b)| (0..N).all(|i| ring::high_bits(a[i]) == ring::high_bits(b[i])));
+        if !r0_ok || !high_ok {
+            continue;
+        }
+        let ct0 = ring::scale(&c_hat, &expanded.t0_hat);
+        if inf_norm(&ct0) >= GAMMA2 {
+            continue;
+        }
+        let hints: Vec<[bool; N]> = ct0
+            .iter()
+            .zip(&w_cs2)
+            .map(|(ct0, r)| {
+                std::array::from_fn(|i| {
+                    ring::make_hint(Q - ct0[i], ring::reduce(r[i] as i64 + ct0[i] as i64))
+                })
+            })
+            .collect();
+        if hints.iter().flatten().filter(|&&h| h).count() > OMEGA {
+            continue;
+        }
+        let sig: [u8; SIGNATURE_LEN] = ring::sig_encode(&c_tilde, &z, &hints)
+            .try_into()
+            .expect("signature encoding is 3309 bytes");
+        for backend in Backend::ALL {
+            if !backend.verify(&pk, &session.message, &[], &sig) {
+                return Err(err(format!(
+                    "combined signature does not verify under {backend}"
+                )));
+            }
+        }
+        return Ok(sig);
+    }
+    Err(err(format!(
+        "none of the {} attempts passed rejection sampling; start a new session",
+        session.attempts
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::*;
+
+    fn run(shares: &[KeyShare], signers: &[u8], rng: &mut StdRng) -> Result<[u8; SIGNATURE_LEN]> {
+        let group = &shares[0].group;
+        let session = Session::new(group, b"threshold test", signers.to_vec(), DEFAULT_ATTEMPTS)?;
+        let active: Vec<&KeyShare> = signers.iter().map(|&p| &shares[p as usize - 1]).collect();
+        let (mut states, commitments): (Vec<_>, Vec<_>) = active
+            .iter()
+            .map(|s| s.commit(&session, rng).unwrap())
+            .unzip();
+        let reveals: Vec<_> = states
+            .iter_mut()
+            .map(|s| s.reveal(&commitments).unwrap())
+            .collect();
+        let partials: Vec<_> = states
+            .iter()
+            .zip(&active)
+            .map(|(state, share)| state.respond(share, &reveals).unwrap())
+            .collect();
+        combine(group, &session, &reveals, &partials)
+    }
+
+    #[test]
+    fn two_of_three_signs_with_every_pair() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let (group, shares) = deal(2, 3, &mut rng).unwrap();
+        assert_eq!(holder_sets(2, 3), [vec![1, 2], vec![1, 3], vec![2, 3]]);
+        assert!(shares.iter().all(|s| s.sub_shares.len() == 2));
+        assert!(
+            inf_norm(&ring::unpack_centered(&group.t0, 1 << (D - 1), D, K).unwrap())
+                <= 1 << (D - 1)
+        );
+        for signers in [[1, 2], [1, 3], [2, 3]] {
+            run(&shares, &signers, &mut rng).unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_rounds_and_bad_groups() {
+        let mut rng = StdRng::seed_from_u64(8);
+        assert!(deal(3, 4, &mut rng).is_err());
+        let (group, shares) = deal(2, 3, &mut rng).unwrap();
+        assert!(Session::new(&group, b"m", vec![1], 4).is_err());
+        assert!(Session::new(&group, b"m", vec![1, 1], 4).is_err());
+
+        let session = Session::new(&group, b"m", vec![1, 3], 4).unwrap();
+        let (mut a, ca) = shares[0].commit(&session, &mut rng).unwrap();
+        let (mut b, cb) = shares[2].commit(&session, &mut rng).unwrap();
+        assert!(shares[1].commit(&session, &mut rng).is_err());
+        let commitments = [ca, cb];
+        let ra = a.reveal(&commitments).unwrap();
+        let mut rb = b.reveal(&commitments).unwrap();
+        let mut forged = rb.w.to_vec();
+        forged[0] ^= 1;
+        rb.w = forged.into();
+        assert!(a.respond(&shares[0], &[ra, rb]).is_err());
+    }
+}
@@ -0,0 +1,392 @@
+//! The ML-DSA-65 arithmetic (FIPS 204) the threshold protocol needs and the
+//! `ml-dsa` crate keeps private: the NTT, ExpandA, rounding, hints and the
+//! public key and signature encodings.
+//!
+//! Coefficients are `i32`s reduced into `[0, q)` unless a function says it
+//! takes or returns centered values. Nothing here is constant time.
+
+use sha3::digest::{ExtendableOutput, Update, XofReader};
+use sha3::{Shake128, Shake256};
+
+pub const N: usize = 256;
+pub const Q: i32 = 8_380_417;
+pub const D: u32 = 13;
+pub const K: usize = 6;
+pub const L: usize = 5;
+pub const TAU: usize = 49;
+pub const ETA: i32 = 4;
+pub const BETA: i32 = TAU as i32 * ETA;
+pub const GAMMA1: i32 = 1 << 19;
+pub const GAMMA2: i32 = (Q - 1) / 32;
+pub const OMEGA: usize = 55;
+/// Length of the commitment hash c̃ (λ/4).
+pub const CTILDE_LEN: usize = 48;
+
+const ZETA: i64 = 1753;
+/// 256⁻¹ mod q.
+const N_INV: i64 = 8_347_681;
+
+pub type Poly = [i32; N];
+
+const fn pow_mod(mut base: i64, mut exp: u32) -> i64 {
+    let mut acc = 1;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            acc = acc * base % Q as i64;
+        }
+        base = base * base % Q as i64;
+        exp >>= 1;
+    }
+    acc
+}
+
+/// ζ^brv₈(k), in the order the NTT consumes them.
+const ZETAS: [i64; N] = {
+    let mut z = [0; N];
+    let mut k = 0;
+    while k < N {
+        z[k] = pow_mod(ZETA, (k as u8).reverse_bits() as u32);
+        k += 1;
+    }
+    z
+};
+
+/// `a mod q` in `[0, q)`.
+pub fn reduce(a: i64) -> i32 {
+    a.rem_euclid(Q as i64) as i32
+}
+
+/// `r mod± m`: the representative in `(-m/2, m/2]`.
+fn mod_pm(r: i32, m: i32) -> i32 {
+    let r0 = r.rem_euclid(m);
+    if r0 > m / 2 { r0 - m } else { r0 }
+}
+
+/// Centered representative of a coefficient mod q.
+pub fn center(a: i32) -> i32 {
+    mod_pm(a, Q)
+}
+
+/// ‖·‖∞ of a vector, on centered coefficients.
+pub fn inf_norm(v: &[Poly]) -> i32 {
+    v.iter()
+        .flat_map(|p| p.iter())
+        .map(|&c| center(c).abs())
+        .max()
+        .unwrap_or(0)
+}
+
+/// FIPS 204 Algorithm 41.
+pub fn ntt(a: &Poly) -> Poly {
+    let mut w = a.map(|c| c.rem_euclid(Q) as i64);
+    let (mut k, mut len) = (0, 128);
+    while len >= 1 {
+        for start in (0..N).step_by(2 * len) {
+            k += 1;
+            let z = ZETAS[k];
+            for j in start..start + len {
+                let t = z * w[j + len] % Q as i64;
+                w[j + len] = (w[j] - t).rem_euclid(Q as i64);
+                w[j] = (w[j] + t) % Q as i64;
+            }
+        }
+        len /= 2;
+    }
+    w.map(|c| c as i32)
+}
+
+/// FIPS 204 Algorithm 42.
+pub fn inv_ntt(a: &Poly) -> Poly {
+    let mut w = a.map(|c| c.rem_euclid(Q) as i64);
+    let (mut k, mut len) = (N, 1);
+    while len < N {
+        for start in (0..N).step_by(2 * len) {
+            k -= 1;
+            let z = Q as i64 - ZETAS[k];
+            for j in start..start + len {
+                let t = w[j];
+                w[j] = (t + w[j + len]) % Q as i64;
+                w[j + len] = z * (t - w[j + len]).rem_euclid(Q as i64) % Q as i64;
+            }
+        }
+        len *= 2;
+    }
+    w.map(|c| (c * N_INV % Q as i64) as i32)
+}
+
+pub fn add(a: &Poly, b: &Poly) -> Poly {
+    std::array::from_fn(|i| reduce(a[i] as i64 + b[i] as i64))
+}
+
+pub fn sub(a: &Poly, b: &Poly) -> Poly {
+    std::array::from_fn(|i| reduce(a[i] as i64 - b[i] as i64))
+}
+
+/// Pointwise product of two NTT-domain polynomials.
+pub fn pointwise(a: &Poly, b: &Poly) -> Poly {
+    std::array::from_fn(|i| reduce(a[i] as i64 * b[i] as i64))
+}
+
+pub fn ntt_vec(v: &[Poly]) -> Vec<Poly> {
+    v.iter().map(ntt).collect()
+}
+
+pub fn inv_ntt_vec(v: &[Poly]) -> Vec<Poly> {
+    v.iter().map(inv_ntt).collect()
+}
+
+/// `c · v` for NTT-domain `c_hat` and `v_hat`, returned in the normal domain.
+pub fn scale(c_hat: &Poly, v_hat: &[Poly]) -> Vec<Poly> {
+    v_hat
+        .iter()
+        .map(|p| inv_ntt(&pointwise(c_hat, p)))
+        .collect()
+}
+
+/// The public matrix Â (NTT domain), FIPS 204 Algorithm 32.
+pub struct Matrix(Vec<[Poly; L]>);
+
+impl Matrix {
+    pub fn expand(rho: &[u8; 32]) -> Self {
+        Matrix(
+            (0..K)
+                .map(|r| std::array::from_fn(|s| rej_ntt_poly(rho, s as u8, r as u8)))
+                .collect(),
+        )
+    }
+
+    /// `Â · v̂`, NTT domain in and out.
+    pub fn mul(&self, v_hat: &[Poly]) -> Vec<Poly> {
+        self.0
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .zip(v_hat)
+                    .fold([0; N], |acc, (a, v)| add(&acc, &pointwise(a, v)))
+            })
+            .collect()
+    }
+}
+
+/// FIPS 204 Algorithm 30.
+fn rej_ntt_poly(rho: &[u8; 32], s: u8, r: u8) -> Poly {
+    let mut xof = Shake128::default();
+    xof.update(rho);
+    xof.update(&[s, r]);
+    let mut reader = xof.finalize_xof();
+    let mut a = [0; N];
+    let mut j = 0;
+    let mut buf = [0u8; 3];
+    while j < N {
+        reader.read(&mut buf);
+        let z = buf[0] as i32 | (buf[1] as i32) << 8 | ((buf[2] & 0x7f) as i32) << 16;
+        if z < Q {
+            a[j] = z;
+            j += 1;
+        }
+    }
+    a
+}
+
+/// SHAKE256 to `out.len()` bytes over the concatenated inputs.
+pub fn shake256(inputs: &[&[u8]], out: &mut [u8]) {
+    let mut xof = Shake256::default();
+    for input in inputs {
+        xof.update(input);
+    }
+    xof.finalize_xof().read(out);
+}
+
+/// FIPS 204 Algorithm 29.
+pub fn sample_in_ball(c_tilde: &[u8]) -> Poly {
+    let mut xof = Shake256::default();
+    xof.update(c_tilde);
+    let mut reader = xof.finalize_xof();
+    let mut signs = [0u8; 8];
+    reader.read(&mut signs);
+    let signs = u64::from_le_bytes(signs);
+    let mut c = [0; N];
+    let mut byte = [0u8; 1];
+    for (bit, i) in (N - TAU..N).enumerate() {
+        let j = loop {
+            reader.read(&mut byte);
+            if byte[0] as usize <= i {
+                break byte[0] as usize;
+            }
+        };
+        c[i] = c[j];
+        c[j] = if signs >> bit & 1 == 1 { Q - 1 } else { 1 };
+    }
+    c
+}
+
+/// FIPS 204 Algorithm 35: `(r1, r0)` with `r = r1·2^d + r0`.
+pub fn power2round(r: i32) -> (i32, i32) {
+    let r = r.rem_euclid(Q);
+    let r0 = mod_pm(r, 1 << D);
+    ((r - r0) >> D, r0)
+}
+
+/// FIPS 204 Algorithm 36.
+pub fn decompose(r: i32) -> (i32, i32) {
+    let r = r.rem_euclid(Q);
+    let r0 = mod_pm(r, 2 * GAMMA2);
+    if r - r0 == Q - 1 {
+        (0, r0 - 1)
+    } else {
+        ((r - r0) / (2 * GAMMA2), r0)
+    }
+}
+
+pub fn high_bits(r: i32) -> i32 {
+    decompose(r).0
+}
+
+pub fn low_bits(r: i32) -> i32 {
+    decompose(r).1
+}
+
+/// FIPS 204 Algorithm 39: whether adding `z` to `r` changes its high bits.
+pub fn make_hint(z: i32, r: i32) -> bool {
+    high_bits(r) != high_bits(reduce(r as i64 + z as i64))
+}
+
+/// Pack `values` into `bits`-wide little-endian fields.
+pub fn pack(values: impl IntoIterator<Item = u32>, bits: u32) -> Vec<u8> {
+    let mut out = Vec::new();
+    let (mut acc, mut filled) = (0u64, 0u32);
+    for v in values {
+        acc |= (v as u64) << filled;
+        filled += bits;
+        while filled >= 8 {
+            out.push(acc as u8);
+            acc >>= 8;
+            filled -= 8;
+        }
+    }
+    if filled > 0 {
+        out.push(acc as u8);
+    }
+    out
+}
+
+/// Inverse of [`pack`]; `None` if `bytes` is not exactly `count` fields.
+pub fn unpack(bytes: &[u8], bits: u32, count: usize) -> Option<Vec<u32>> {
+    if bytes.len() != (count * bits as usize).div_ceil(8) {
+        return None;
+    }
+    let mask = (1u64 << bits) - 1;
+    let mut out = Vec::with_capacity(count);
+    let (mut acc, mut filled) = (0u64, 0u32);
+    let mut input = bytes.iter();
+    while out.len() < count {
+        while filled < bits {
+            acc |= (*input.next()? as u64) << filled;
+            filled += 8;
+        }
+        out.push((acc & mask) as u32);
+        acc >>= bits;
+        filled -= bits;
+    }
+    Some(out)
+}
+
+/// Pack a vector of centered coefficients in `[-a, b]` as `b - c`, the way
+/// FIPS 204 `BitPack` does.
+pub fn pack_centered(v: &[Poly], b: i32, bits: u32) -> Vec<u8> {
+    pack(
+        v.iter()
+            .flat_map(|p| p.iter())
+            .map(|&c| (b - center(c)) as u32),
+        bits,
+    )
+}
+
+/// Inverse of [`pack_centered`], returning coefficients reduced mod q.
+pub fn unpack_centered(bytes: &[u8], b: i32, bits: u32, polys: usize) -> Option<Vec<Poly>> {
+    let flat = unpack(bytes, bits, polys * N)?;
+    Some(
+        flat.chunks(N)
+            .map(|chunk| std::array::from_fn(|i| reduce(b as i64 - chunk[i] as i64)))
+            .collect(),
+    )
+}
+
+/// `w1Encode`: four bits per high-bits coefficient.
+pub fn w1_encode(w1: &[Poly]) -> Vec<u8> {
+    pack(w1.iter().flat_map(|p| p.iter()).map(|&c| c as u32), 4)
+}
+
+/// `pkEncode(ρ, t1)`.
+pub fn pk_encode(rho: &[u8; 32], t1: &[Poly]) -> Vec<u8> {
+    let mut pk = rho.to_vec();
+    pk.extend(pack(
+        t1.iter().flat_map(|p| p.iter()).map(|&c| c as u32),
+        10,
+    ));
+    pk
+}
+
+/// `pkDecode`: `(ρ, t1)`.
+pub fn pk_decode(pk: &[u8]) -> Option<([u8; 32], Vec<Poly>)> {
+    let rho: [u8; 32] = pk.get(..32)?.try_into().ok()?;
+    let flat = unpack(&pk[32..], 10, K * N)?;
+    let t1 = flat
+        .chunks(N)
+        .map(|chunk| std::array::from_fn(|i| chunk[i] as i32))
+        .collect();
+    Some((rho, t1))
+}
+
+/// `sigEncode(c̃, z, h)`.
+pub fn sig_encode(c_tilde: &[u8; CTILDE_LEN], z: &[Poly], h: &[[bool; N]]) -> Vec<u8> {
+    let mut sig = c_tilde.to_vec();
+    sig.extend(pack_centered(z, GAMMA1, 20));
+    let mut hints = vec![0u8; OMEGA + K];
+    let mut index = 0;
+    for (i, poly) in h.iter().enumerate() {
+        for (j, &set) in poly.iter().enumerate() {
+            if set {
+                hints[index] = j as u8;
+                index += 1;
+            }
+        }
+        hints[OMEGA + i] = index as u8;
+    }
+    sig.extend(hints);
+    sig
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ntt_round_trips_and_multiplies_negacyclically() {
+        let mut a = [0; N];
+        a[1] = 1;
+        let mut b = [0; N];
+        b[N - 1] = 1;
+        assert_eq!(inv_ntt(&ntt(&a)), a);
+        // X · X^255 = X^256 = -1.
+        let product = inv_ntt(&pointwise(&ntt(&a), &ntt(&b)));
+        assert_eq!(product[0], Q - 1);
+        assert!(product[1..].iter().all(|&c| c == 0));
+    }
+
+    #[test]
+    fn rounding_and_packing() {
+        for r in [0, 1, 4096, 4097, GAMMA2, Q - 1, Q / 2] {
+            let (r1, r0) = power2round(r);
+            assert_eq!(reduce(((r1 as i64) << D) + r0 as i64), r);
+            let (h1, l0) = decompose(r);
+            assert_eq!(reduce(h1 as i64 * 2 * GAMMA2 as i64 + l0 as i64), r);
+            assert!((0..16).contains(&h1));
+        }
+        let values = [0u32, 1, 0xfffff, 12345, 7];
+        assert_eq!(unpack(&pack(values, 20), 20, 5).unwrap(), values);
+        assert!(unpack(&[0; 3], 20, 5).is_none());
+        let c = sample_in_ball(&[0; CTILDE_LEN]);
+        assert_eq!(c.iter().filter(|&&x| x != 0).count(), TAU);
+    }
+}
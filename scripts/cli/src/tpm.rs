@@ -0,0 +1,308 @@
+//! TPM 2.0-sealed keystore entries for server signers (`tpm` feature).
+//!
+//! `pq-account new --keystore-backend tpm` seals the entry's content key
+//! (see [`crate::platform`]) into a TPM keyed-hash object under the owner
+//! hierarchy's storage key. The object can only be unsealed through a PCR
+//! policy: the selected PCRs (`--pcrs`, default `0,2,4,7` in the SHA-256
+//! bank: firmware, option ROMs, boot loader and Secure Boot state) must hold
+//! the values they held at sealing. A host that booted different firmware, a
+//! different boot chain or with Secure Boot turned off cannot unseal it, and
+//! neither can any other TPM.
+//!
+//! The entry records the PCR selection as the wrapped key's `alias`, e.g.
+//! `sha256:0,2,4,7`, and the sealed object as its `key`. The TPM is found
+//! through `$TPM2TOOLS_TCTI` (or `$TCTI`) as for tpm2-tools, else
+//! `device:/dev/tpmrm0`.
+//!
+//! Needs the tpm2-tss libraries (`libtss2-dev`) to build.
+
+use std::str::FromStr;
+
+use sha2::{Digest as _, Sha256};
+use tss_esapi::attributes::{ObjectAttributesBuilder, SessionAttributesBuilder};
+use tss_esapi::constants::SessionType;
+use tss_esapi::handles::{KeyHandle, SessionHandle};
+use tss_esapi::interface_types::algorithm::{HashingAlgorithm, PublicAlgorithm};
+use tss_esapi::interface_types::key_bits::RsaKeyBits;
+use tss_esapi::interface_types::resource_handles::Hierarchy;
+use tss_esapi::interface_types::session_handles::PolicySession;
+use tss_esapi::structures::{
+    Digest, KeyedHashScheme, PcrSelectionList, PcrSelectionListBuilder, PcrSlot, Private, Public,
+    PublicBuilder, PublicKeyedHashParameters, RsaExponent, SensitiveData, SymmetricDefinition,
+    SymmetricDefinitionObject,
+};
+use tss_esapi::traits::{Marshall, UnMarshall};
+use tss_esapi::{Context, TctiNameConf};
+use zeroize::Zeroizing;
+
+use crate::error::{Result, WalletError};
+use crate::platform::{PlatformKeystore, TPM};
+
+/// PCRs sealed to unless `--pcrs` says otherwise.
+pub const DEFAULT_PCRS: &str = "0,2,4,7";
+
+fn tpm_err(what: &str) -> impl FnOnce(tss_esapi::Error) -> WalletError + '_ {
+    move |e| WalletError::Keystore(format!("tpm: {what}: {e}"))
+}
+
+/// SHA-256 PCR indices, as in `--pcrs 0,2,4,7` or an alias
+/// `sha256:0,2,4,7`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pcrs(Vec<u8>);
+
+impl FromStr for Pcrs {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let list = s.strip_prefix("sha256:").unwrap_or(s);
+        let mut pcrs = list
+            .split(',')
+            .map(|n| n.trim().parse::<u8>().ok().filter(|&n| n < 24))
+            .collect::<Option<Vec<_>>>()
+            .filter(|pcrs| !pcrs.is_empty() && pcrs.len() <= 8)
+            .ok_or_else(|| {
+                WalletError::Config(format!(
+                    "invalid PCR list {s:?} (expected up to 8 of 0-23, e.g. 0,2,4,7)"
+                ))
+            })?;
+        pcrs.sort_unstable();
+        pcrs.dedup();
+        Ok(Pcrs(pcrs))
+    }
+}
+
+impl std::fmt::Display for Pcrs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list: Vec<String> = self.0.iter().map(u8::to_string).collect();
+        write!(f, "sha256:{}", list.join(","))
+    }
+}
+
+impl Pcrs {
+    fn selection(&self) -> Result<PcrSelectionList> {
+        let slots = self
+            .0
+            .iter()
+            .map(|&n| PcrSlot::try_from(1u32 << n).map_err(tpm_err("PCR slot")))
+            .collect::<Result<Vec<_>>>()?;
+        PcrSelectionListBuilder::new()
+            .with_selection(HashingAlgorithm::Sha256, &slots)
+            .build()
+            .map_err(tpm_err("PCR selection"))
+    }
+}
+
+/// The host's TPM.
+pub struct Tpm {
+    tcti: TctiNameConf,
+}
+
+impl Tpm {
+    pub fn from_environment() -> Result<Self> {
+        let tcti = match TctiNameConf::from_environment_variable() {
+            Ok(tcti) => tcti,
+            Err(_) => TctiNameConf::from_str("device:/dev/tpmrm0").map_err(tpm_err("TCTI"))?,
+        };
+        Ok(Tpm { tcti })
+    }
+
+    fn context(&self) -> Result<Context> {
+        Context::new(self.tcti.clone()).map_err(tpm_err("open"))
+    }
+
+    /// The owner hierarchy's storage key, recreated identically each time.
+    fn storage_key(context: &mut Context) -> Result<KeyHandle> {
+        let template = tss_esapi::utils::create_restricted_decryption_rsa_public(
+            SymmetricDefinitionObject::AES_128_CFB,
+            RsaKeyBits::Rsa2048,
+            RsaExponent::default(),
+        )
+        .map_err(tpm_err("storage key template"))?;
+        context
+            .execute_with_nullauth_session(|ctx| {
+                ctx.create_primary(Hierarchy::Owner, template, None, None, None, None)
+            })
+            .map(|primary| primary.key_handle)
+            .map_err(tpm_err("storage key"))
+    }
+
+    /// A policy session bound to `pcrs`. In a trial session the PCR values
+    /// are `expected`; in a real one they are the TPM's current values.
+    fn pcr_policy(
+        context: &mut Context,
+        pcrs: &Pcrs,
+        session_type: SessionType,
+        expected: Option<Digest>,
+    ) -> Result<(PolicySession, Digest)> {
+        let session = context
+            .start_auth_session(
+                None,
+                None,
+                None,
+                session_type,
+                SymmetricDefinition::AES_128_CFB,
+                HashingAlgorithm::Sha256,
+            )
+            .map_err(tpm_err("policy session"))?
+            .ok_or_else(|| WalletError::Keystore("tpm: no policy session".to_string()))?;
+        let (attributes, mask) = SessionAttributesBuilder::new()
+            .with_decrypt(true)
+            .with_encrypt(true)
+            .build();
+        context
+            .tr_sess_set_attributes(session, attributes, mask)
+            .map_err(tpm_err("policy session"))?;
+        let session = PolicySession::try_from(session).map_err(tpm_err("policy session"))?;
+        context
+            .policy_pcr(session, expected.unwrap_or_default(), pcrs.selection()?)
+            .map_err(tpm_err("PolicyPCR"))?;
+        let digest = context
+            .policy_get_digest(session)
+            .map_err(tpm_err("policy digest"))?;
+        Ok((session, digest))
+    }
+
+    /// SHA-256 over the current values of `pcrs`, in index order.
+    fn pcr_digest(context: &mut Context, pcrs: &Pcrs) -> Result<Digest> {
+        let selection = pcrs.selection()?;
+        let (_, _, values) = context
+            .execute_without_session(|ctx| ctx.pcr_read(selection))
+            .map_err(tpm_err("PCR read"))?;
+        let mut hash = Sha256::new();
+        for value in values.value() {
+            hash.update(value.value());
+        }
+        Digest::try_from(hash.finalize().to_vec()).map_err(tpm_err("PCR digest"))
+    }
+}
+
+/// `public` and `private` of the sealed object, each behind a big-endian
+/// `u16` length.
+fn encode_sealed(public: &Public, private: &Private) -> Result<Vec<u8>> {
+    let public = public.marshall().map_err(tpm_err("marshal"))?;
+    let mut out = Vec::with_capacity(4 + public.len() + private.len());
+    for part in [&public[..], private.value()] {
+        out.extend_from_slice(&(part.len() as u16).to_be_bytes());
+        out.extend_from_slice(part);
+    }
+    Ok(out)
+}
+
+fn decode_sealed(blob: &[u8]) -> Result<(Public, Private)> {
+    let bad = || WalletError::Keystore("tpm: malformed sealed object".to_string());
+    let mut parts = Vec::with_capacity(2);
+    let mut rest = blob;
+    for _ in 0..2 {
+        let (len, tail) = rest.split_first_chunk::<2>().ok_or_else(bad)?;
+        let len = u16::from_be_bytes(*len) as usize;
+        if tail.len() < len {
+            return Err(bad());
+        }
+        parts.push(&tail[..len]);
+        rest = &tail[len..];
+    }
+    let public = Public::unmarshall(parts[0]).map_err(tpm_err("unmarshal"))?;
+    let private = Private::try_from(parts[1].to_vec()).map_err(tpm_err("unmarshal"))?;
+    Ok((public, private))
+}
+
+impl PlatformKeystore for Tpm {
+    fn name(&self) -> &str {
+        TPM
+    }
+
+    /// `alias` is the PCR selection to seal to.
+    fn wrap(&self, alias: &str, key: &[u8; 32]) -> Result<Vec<u8>> {
+        let pcrs: Pcrs = alias.parse()?;
+        let mut context = self.context()?;
+        let current = Self::pcr_digest(&mut context, &pcrs)?;
+        let (trial, policy) =
+            Self::pcr_policy(&mut context, &pcrs, SessionType::Trial, Some(current))?;
+        context
+            .flush_context(SessionHandle::from(trial).into())
+            .map_err(tpm_err("flush"))?;
+        let attributes = ObjectAttributesBuilder::new()
+            .with_fixed_tpm(true)
+            .with_fixed_parent(true)
+            .with_no_da(true)
+            .with_admin_with_policy(true)
+            // Only the PCR policy authorizes unsealing.
+            .with_user_with_auth(false)
+            .build()
+            .map_err(tpm_err("object attributes"))?;
+        let template = PublicBuilder::new()
+            .with_public_algorithm(PublicAlgorithm::KeyedHash)
+            .with_name_hashing_algorithm(HashingAlgorithm::Sha256)
+            .with_object_attributes(attributes)
+            .with_auth_policy(policy)
+            .with_keyed_hash_parameters(PublicKeyedHashParameters::new(KeyedHashScheme::Null))
+            .with_keyed_hash_unique_identifier(Default::default())
+            .build()
+            .map_err(tpm_err("sealed object template"))?;
+        let parent = Self::storage_key(&mut context)?;
+        let data = SensitiveData::try_from(key.to_vec()).map_err(tpm_err("sealed data"))?;
+        let sealed = context
+            .execute_with_nullauth_session(|ctx| {
+                ctx.create(parent, template, None, Some(data), None, None)
+            })
+            .map_err(tpm_err("seal"));
+        context.flush_context(parent.into()).ok();
+        let sealed = sealed?;
+        encode_sealed(&sealed.out_public, &sealed.out_private)
+    }
+
+    fn unwrap(&self, alias: &str, wrapped: &[u8], _reason: &str) -> Result<Zeroizing<[u8; 32]>> {
+        let pcrs: Pcrs = alias.parse()?;
+        let (public, private) = decode_sealed(wrapped)?;
+        let mut context = self.context()?;
+        let (session, current) = Self::pcr_policy(&mut context, &pcrs, SessionType::Policy, None)?;
+        if current != *public.auth_policy() {
+            context
+                .flush_context(SessionHandle::from(session).into())
+                .ok();
+            return Err(WalletError::Keystore(format!(
+                "tpm: PCRs {pcrs} no longer hold the values the seed was sealed to; \
+                 the host's firmware, boot chain or Secure Boot state has changed \
+                 (restore it, or re-import the seed from a backup)"
+            )));
+        }
+        let parent = Self::storage_key(&mut context)?;
+        let object = context
+            .execute_with_nullauth_session(|ctx| ctx.load(parent, private, public))
+            .map_err(tpm_err("load sealed object"));
+        context.flush_context(parent.into()).ok();
+        let object = object?;
+        let data = context
+            .execute_with_session(Some(session.into()), |ctx| ctx.unseal(object.into()))
+            .map_err(tpm_err("unseal"));
+        context.flush_context(object.into()).ok();
+        context
+            .flush_context(SessionHandle::from(session).into())
+            .ok();
+        let data = data?;
+        let data = Zeroizing::new(data.value().to_vec());
+        let key: [u8; 32] = data[..]
+            .try_into()
+            .map_err(|_| WalletError::InvalidLength {
+                what: "sealed key",
+                expected: 32,
+                actual: data.len(),
+            })?;
+        Ok(Zeroizing::new(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pcr_selections() {
+        let pcrs: Pcrs = "7, 0,4,2,4".parse().unwrap();
+        assert_eq!(pcrs.to_string(), "sha256:0,2,4,7");
+        assert_eq!(pcrs.to_string().parse::<Pcrs>().unwrap(), pcrs);
+        for bad in ["", "24", "a", "0,1,2,3,4,5,6,7,8"] {
+            assert!(bad.parse::<Pcrs>().is_err(), "{bad:?}");
+        }
+    }
+}
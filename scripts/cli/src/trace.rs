@@ -0,0 +1,202 @@
+//! Decoding what a traced call moved: native value and ERC-20/721
+//! `Transfer` events.
+//!
+//! Two trace shapes are read. `debug_traceCall` with geth's `callTracer`
+//! (`withLog: true`) gives a [`CallFrame`] tree, where native value is the
+//! `value` of each `CALL` and tokens are the frames' logs. `eth_simulateV1`
+//! with `traceTransfers: true` gives flat logs, and reports native value as
+//! ERC-20 `Transfer` logs from the pseudo-token [`NATIVE_PSEUDO_TOKEN`].
+//! Reverted frames are skipped along with everything under them, since their
+//! effects are undone.
+
+use alloy_primitives::{Address, B256, Bytes, U256, address, b256};
+use serde::Deserialize;
+
+/// `keccak256("Transfer(address,address,uint256)")`, shared by ERC-20 and
+/// ERC-721.
+pub const TRANSFER_TOPIC: B256 =
+    b256!("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef");
+
+/// The address `eth_simulateV1` emits native-value `Transfer` logs from.
+pub const NATIVE_PSEUDO_TOKEN: Address = address!("EeeeeEeeeEeEeeEeEeEeeEEEeeeeEeeeeeeeEEeE");
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Log {
+    pub address: Address,
+    pub topics: Vec<B256>,
+    #[serde(default)]
+    pub data: Bytes,
+}
+
+/// One frame of `callTracer` output.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub from: Address,
+    #[serde(default)]
+    pub to: Option<Address>,
+    #[serde(default)]
+    pub value: Option<U256>,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub logs: Vec<Log>,
+    #[serde(default)]
+    pub calls: Vec<CallFrame>,
+}
+
+/// What moved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Asset {
+    Native,
+    Erc20(Address),
+    Erc721 { token: Address, id: U256 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transfer {
+    pub asset: Asset,
+    pub from: Address,
+    pub to: Address,
+    /// 1 for an ERC-721.
+    pub amount: U256,
+}
+
+fn topic_address(topic: &B256) -> Address {
+    Address::from_word(*topic)
+}
+
+/// The transfer a log records, if it is a `Transfer` event.
+pub fn decode_log(log: &Log) -> Option<Transfer> {
+    let [topic0, from, to, rest @ ..] = log.topics.as_slice() else {
+        return None;
+    };
+    if *topic0 != TRANSFER_TOPIC {
+        return None;
+    }
+    let (from, to) = (topic_address(from), topic_address(to));
+    match rest {
+        [] if log.data.len() == 32 => Some(Transfer {
+            asset: if log.address == NATIVE_PSEUDO_TOKEN {
+                Asset::Native
+            } else {
+                Asset::Erc20(log.address)
+            },
+            from,
+            to,
+            amount: U256::from_be_slice(&log.data),
+        }),
+        [id] if log.data.is_empty() => Some(Transfer {
+            asset: Asset::Erc721 {
+                token: log.address,
+                id: U256::from_be_bytes(id.0),
+            },
+            from,
+            to,
+            amount: U256::from(1),
+        }),
+        _ => None,
+    }
+}
+
+/// The transfers in `logs`, in order.
+pub fn decode_logs(logs: &[Log]) -> Vec<Transfer> {
+    logs.iter().filter_map(decode_log).collect()
+}
+
+impl CallFrame {
+    /// Every transfer in this frame and the frames under it that did not
+    /// revert: the native value of each `CALL`, then each frame's logs.
+    pub fn transfers(&self) -> Vec<Transfer> {
+        let mut out = Vec::new();
+        self.collect(&mut out);
+        out
+    }
+
+    fn collect(&self, out: &mut Vec<Transfer>) {
+        if self.error.is_some() {
+            return;
+        }
+        if let (Some(to), Some(value)) = (self.to, self.value)
+            && !value.is_zero()
+            && matches!(
+                self.kind.as_str(),
+                "CALL" | "CREATE" | "CREATE2" | "SELFDESTRUCT"
+            )
+        {
+            out.push(Transfer {
+                asset: Asset::Native,
+                from: self.from,
+                to,
+                amount: value,
+            });
+        }
+        out.extend(decode_logs(&self.logs));
+        for call in &self.calls {
+            call.collect(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_erc20_erc721_and_native_transfers_but_not_reverted_ones() {
+        let (a, b, token) = (
+            Address::repeat_byte(0xaa),
+            Address::repeat_byte(0xbb),
+            Address::repeat_byte(0x70),
+        );
+        let frame: CallFrame = serde_json::from_value(serde_json::json!({
+            "type": "CALL", "from": a, "to": b, "value": "0x5",
+            "logs": [
+                { "address": token, "topics": [TRANSFER_TOPIC, a.into_word(), b.into_word()],
+                  "data": Bytes::from(U256::from(7).to_be_bytes_vec()) },
+                { "address": token,
+                  "topics": [TRANSFER_TOPIC, b.into_word(), a.into_word(), B256::with_last_byte(9)],
+                  "data": "0x" },
+            ],
+            "calls": [{
+                "type": "CALL", "from": b, "to": a, "value": "0x64", "error": "execution reverted",
+            }],
+        }))
+        .unwrap();
+        assert_eq!(
+            frame.transfers(),
+            [
+                Transfer {
+                    asset: Asset::Native,
+                    from: a,
+                    to: b,
+                    amount: U256::from(5)
+                },
+                Transfer {
+                    asset: Asset::Erc20(token),
+                    from: a,
+                    to: b,
+                    amount: U256::from(7)
+                },
+                Transfer {
+                    asset: Asset::Erc721 {
+                        token,
+                        id: U256::from(9)
+                    },
+                    from: b,
+                    to: a,
+                    amount: U256::from(1)
+                },
+            ]
+        );
+
+        let native = Log {
+            address: NATIVE_PSEUDO_TOKEN,
+            topics: vec![TRANSFER_TOPIC, a.into_word(), b.into_word()],
+            data: U256::from(5).to_be_bytes_vec().into(),
+        };
+        assert_eq!(decode_log(&native).unwrap().asset, Asset::Native);
+    }
+}
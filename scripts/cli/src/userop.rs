@@ -1,10 +1,24 @@
-use alloy_primitives::{Address, B256, U256, keccak256};
+use alloc::format;
+use alloc::vec::Vec;
+
+use alloy_primitives::{Address, B256, Bytes, U256, address, keccak256};
 use alloy_sol_types::SolValue;
+use serde::{Deserialize, Serialize};
+
+use crate::error::WalletError;
+
+/// Canonical EntryPoint v0.7 deployment.
+pub const ENTRY_POINT_V07: Address = address!("0x0000000071727De22E5E9d8BAf0edAc6f37da032");
 
 /// ERC-4337 v0.7 PackedUserOperation fields.
 ///
 /// See EntryPoint v0.7 source:
 /// https://github.com/eth-infinitism/account-abstraction/blob/v0.7.0/contracts/interfaces/PackedUserOperation.sol
+///
+/// Serializes to and from the unpacked v0.7 JSON-RPC form that bundlers take
+/// in `eth_sendUserOperation` (see [`RpcUserOperation`]).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "RpcUserOperation", try_from = "RpcUserOperation")]
 pub struct PackedUserOperation {
     pub sender: Address,
     pub nonce: U256,
@@ -17,6 +31,193 @@ pub struct PackedUserOperation {
     pub signature: Vec<u8>,
 }
 
+/// Two 128-bit values packed into one word, high half first, as in
+/// `accountGasLimits` (verificationGasLimit, callGasLimit) and `gasFees`
+/// (maxPriorityFeePerGas, maxFeePerGas).
+pub fn pack_u128_pair(high: u128, low: u128) -> B256 {
+    let mut out = [0u8; 32];
+    out[..16].copy_from_slice(&high.to_be_bytes());
+    out[16..].copy_from_slice(&low.to_be_bytes());
+    B256::from(out)
+}
+
+/// Inverse of [`pack_u128_pair`].
+pub fn unpack_u128_pair(word: B256) -> (u128, u128) {
+    let high = u128::from_be_bytes(word[..16].try_into().unwrap());
+    let low = u128::from_be_bytes(word[16..].try_into().unwrap());
+    (high, low)
+}
+
+impl PackedUserOperation {
+    pub fn verification_gas_limit(&self) -> u128 {
+        unpack_u128_pair(self.account_gas_limits).0
+    }
+
+    pub fn call_gas_limit(&self) -> u128 {
+        unpack_u128_pair(self.account_gas_limits).1
+    }
+
+    pub fn max_priority_fee_per_gas(&self) -> u128 {
+        unpack_u128_pair(self.gas_fees).0
+    }
+
+    pub fn max_fee_per_gas(&self) -> u128 {
+        unpack_u128_pair(self.gas_fees).1
+    }
+
+    /// (paymasterVerificationGasLimit, paymasterPostOpGasLimit), zero
+    /// without a paymaster.
+    pub fn paymaster_gas_limits(&self) -> (u128, u128) {
+        let pd = &self.paymaster_and_data;
+        if pd.len() < 52 {
+            return (0, 0);
+        }
+        (
+            u128::from_be_bytes(pd[20..36].try_into().unwrap()),
+            u128::from_be_bytes(pd[36..52].try_into().unwrap()),
+        )
+    }
+}
+
+/// The v0.7 JSON-RPC shape of a UserOperation: `initCode` split into
+/// `factory`/`factoryData`, `paymasterAndData` split into its four parts,
+/// gas words unpacked, and every integer a `0x`-prefixed hex quantity.
+/// Absent optional parts are `null`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcUserOperation {
+    pub sender: Address,
+    pub nonce: U256,
+    #[serde(default)]
+    pub factory: Option<Address>,
+    #[serde(default)]
+    pub factory_data: Option<Bytes>,
+    pub call_data: Bytes,
+    pub call_gas_limit: U256,
+    pub verification_gas_limit: U256,
+    pub pre_verification_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+    #[serde(default)]
+    pub paymaster: Option<Address>,
+    #[serde(default)]
+    pub paymaster_verification_gas_limit: Option<U256>,
+    #[serde(default)]
+    pub paymaster_post_op_gas_limit: Option<U256>,
+    #[serde(default)]
+    pub paymaster_data: Option<Bytes>,
+    pub signature: Bytes,
+}
+
+impl From<PackedUserOperation> for RpcUserOperation {
+    fn from(op: PackedUserOperation) -> Self {
+        let (factory, factory_data) = if op.init_code.len() >= 20 {
+            (
+                Some(Address::from_slice(&op.init_code[..20])),
+                Some(Bytes::copy_from_slice(&op.init_code[20..])),
+            )
+        } else {
+            (None, None)
+        };
+        let (paymaster, pm_verification, pm_post_op, pm_data) = if op.paymaster_and_data.len() >= 52
+        {
+            let pd = &op.paymaster_and_data;
+            (
+                Some(Address::from_slice(&pd[..20])),
+                Some(U256::from_be_slice(&pd[20..36])),
+                Some(U256::from_be_slice(&pd[36..52])),
+                Some(Bytes::copy_from_slice(&pd[52..])),
+            )
+        } else {
+            (None, None, None, None)
+        };
+        let (verification_gas_limit, call_gas_limit) = unpack_u128_pair(op.account_gas_limits);
+        let (max_priority_fee_per_gas, max_fee_per_gas) = unpack_u128_pair(op.gas_fees);
+        RpcUserOperation {
+            sender: op.sender,
+            nonce: op.nonce,
+            factory,
+            factory_data,
+            call_data: op.call_data.into(),
+            call_gas_limit: U256::from(call_gas_limit),
+            verification_gas_limit: U256::from(verification_gas_limit),
+            pre_verification_gas: op.pre_verification_gas,
+            max_fee_per_gas: U256::from(max_fee_per_gas),
+            max_priority_fee_per_gas: U256::from(max_priority_fee_per_gas),
+            paymaster,
+            paymaster_verification_gas_limit: pm_verification,
+            paymaster_post_op_gas_limit: pm_post_op,
+            paymaster_data: pm_data,
+            signature: op.signature.into(),
+        }
+    }
+}
+
+fn to_u128(field: &str, value: U256) -> Result<u128, WalletError> {
+    u128::try_from(value)
+        .map_err(|_| WalletError::InvalidUserOp(format!("{field} does not fit in 128 bits")))
+}
+
+impl TryFrom<RpcUserOperation> for PackedUserOperation {
+    type Error = WalletError;
+
+    fn try_from(op: RpcUserOperation) -> Result<Self, WalletError> {
+        let init_code = match (op.factory, op.factory_data) {
+            (Some(factory), data) => {
+                let mut code = factory.to_vec();
+                code.extend_from_slice(data.as_deref().map_or(&[][..], |d| &d[..]));
+                code
+            }
+            (None, Some(data)) if !data.is_empty() => {
+                return Err(WalletError::InvalidUserOp(
+                    "factoryData set without factory".into(),
+                ));
+            }
+            (None, _) => Vec::new(),
+        };
+        let paymaster_and_data = match op.paymaster {
+            Some(paymaster) => {
+                let mut pd = paymaster.to_vec();
+                for (field, value) in [
+                    (
+                        "paymasterVerificationGasLimit",
+                        op.paymaster_verification_gas_limit,
+                    ),
+                    ("paymasterPostOpGasLimit", op.paymaster_post_op_gas_limit),
+                ] {
+                    let gas = to_u128(field, value.unwrap_or_default())?;
+                    pd.extend_from_slice(&gas.to_be_bytes());
+                }
+                pd.extend_from_slice(op.paymaster_data.as_deref().map_or(&[][..], |d| &d[..]));
+                pd
+            }
+            None if op.paymaster_data.as_ref().is_some_and(|d| !d.is_empty()) => {
+                return Err(WalletError::InvalidUserOp(
+                    "paymasterData set without paymaster".into(),
+                ));
+            }
+            None => Vec::new(),
+        };
+        Ok(PackedUserOperation {
+            sender: op.sender,
+            nonce: op.nonce,
+            init_code,
+            call_data: op.call_data.into(),
+            account_gas_limits: pack_u128_pair(
+                to_u128("verificationGasLimit", op.verification_gas_limit)?,
+                to_u128("callGasLimit", op.call_gas_limit)?,
+            ),
+            pre_verification_gas: op.pre_verification_gas,
+            gas_fees: pack_u128_pair(
+                to_u128("maxPriorityFeePerGas", op.max_priority_fee_per_gas)?,
+                to_u128("maxFeePerGas", op.max_fee_per_gas)?,
+            ),
+            paymaster_and_data,
+            signature: op.signature.into(),
+        })
+    }
+}
+
 /// Compute the userOpHash exactly as EntryPoint v0.7 does.
 ///
 /// The EntryPoint computes this in two steps:
@@ -44,13 +245,12 @@ pub fn compute_user_op_hash(
 
     // Step 2: hash packed data with entry_point and chain_id
     let packed_hash = keccak256(&packed);
-    keccak256(&(packed_hash, entry_point, chain_id).abi_encode())
+    keccak256((packed_hash, entry_point, chain_id).abi_encode())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use alloy_primitives::address;
 
     #[test]
     fn test_empty_userop_hash_is_deterministic() {
@@ -73,5 +273,72 @@ mod tests {
         assert_eq!(hash1, hash2, "same inputs must produce same hash");
     }
 
-    // TODO: Write another test that makes sure that our output is the same as the library's way of packing
+    #[test]
+    fn rpc_json_roundtrips_and_splits_packed_fields() {
+        let json = serde_json::json!({
+            "sender": "0x1111111111111111111111111111111111111111",
+            "nonce": "0x1",
+            "factory": "0x2222222222222222222222222222222222222222",
+            "factoryData": "0xabcd",
+            "callData": "0x",
+            "callGasLimit": "0x186a0",
+            "verificationGasLimit": "0x1e8480",
+            "preVerificationGas": "0x186a0",
+            "maxFeePerGas": "0x3b9aca00",
+            "maxPriorityFeePerGas": "0x1",
+            "paymaster": null,
+            "paymasterVerificationGasLimit": null,
+            "paymasterPostOpGasLimit": null,
+            "paymasterData": null,
+            "signature": "0x"
+        });
+        let op: PackedUserOperation = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(op.init_code.len(), 22);
+        assert_eq!(op.verification_gas_limit(), 2_000_000);
+        assert_eq!(op.call_gas_limit(), 100_000);
+        // Same layout e2e-test.sh builds with printf '0x%032x%032x'.
+        assert_eq!(
+            op.account_gas_limits,
+            "0x000000000000000000000000001e8480000000000000000000000000000186a0"
+                .parse::<B256>()
+                .unwrap()
+        );
+        assert_eq!(op.max_fee_per_gas(), 1_000_000_000);
+        assert!(op.paymaster_and_data.is_empty());
+        assert_eq!(serde_json::to_value(&op).unwrap(), json);
+    }
+
+    #[test]
+    fn rpc_json_packs_paymaster_fields() {
+        let mut op: RpcUserOperation = serde_json::from_value(serde_json::json!({
+            "sender": "0x1111111111111111111111111111111111111111",
+            "nonce": "0x0",
+            "callData": "0x",
+            "callGasLimit": "0x0",
+            "verificationGasLimit": "0x0",
+            "preVerificationGas": "0x0",
+            "maxFeePerGas": "0x0",
+            "maxPriorityFeePerGas": "0x0",
+            "paymaster": "0x3333333333333333333333333333333333333333",
+            "paymasterVerificationGasLimit": "0x10",
+            "paymasterPostOpGasLimit": "0x20",
+            "paymasterData": "0xff",
+            "signature": "0x"
+        }))
+        .unwrap();
+        let packed = PackedUserOperation::try_from(op.clone()).unwrap();
+        assert_eq!(packed.paymaster_and_data.len(), 20 + 16 + 16 + 1);
+        assert_eq!(packed.paymaster_and_data[35], 0x10);
+        assert_eq!(packed.paymaster_and_data[51], 0x20);
+        assert_eq!(RpcUserOperation::from(packed), op);
+
+        op.call_gas_limit = U256::from(u128::MAX) + U256::from(1);
+        assert!(matches!(
+            PackedUserOperation::try_from(op),
+            Err(WalletError::InvalidUserOp(_))
+        ));
+    }
+
+    // `pq-userop hash --verify-onchain` checks this packing against a live
+    // EntryPoint's getUserOpHash (`simulate::cross_check_user_op_hash`).
 }
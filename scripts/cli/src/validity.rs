@@ -0,0 +1,224 @@
+//! Time-boxed signatures: a validity window bound into what gets signed.
+//!
+//! With `pq-userop sign --valid-for 1h` the ML-DSA message is not the bare
+//! userOpHash but
+//!
+//! ```text
+//! keccak256(userOpHash || chainId || validAfter || validUntil)
+//! ```
+//!
+//! packed as `bytes32 || uint256 || uint48 || uint48`, and the signature
+//! field is prefixed with the window, `validAfter (6) || validUntil (6) ||
+//! signature`. The validator recomputes the hash from the prefix and returns
+//! the window in its `validationData`, so the EntryPoint refuses the op
+//! outside it. No nonce bookkeeping is needed on the contract side: a
+//! leaked signature simply stops working at `validUntil`.
+//!
+//! `--valid-after 24h` time-locks the signature the same way: the window
+//! opens a day from now, so the EntryPoint refuses the op until then, which
+//! leaves a day to notice and cancel a withdrawal. Without `--valid-for` a
+//! time-locked window never closes. The queue holds such ops back until
+//! the window opens ([`crate::queue`]).
+
+use std::time::Duration;
+
+use alloy_primitives::{B256, U256, keccak256};
+
+use crate::error::{Result, WalletError};
+
+/// Largest timestamp a `uint48` holds.
+pub const MAX_TIMESTAMP: u64 = (1 << 48) - 1;
+
+/// Bytes the window adds in front of the signature.
+pub const PREFIX_LEN: usize = 12;
+
+/// `[valid_after, valid_until]`, in seconds since the epoch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidityWindow {
+    pub valid_after: u64,
+    pub valid_until: u64,
+}
+
+impl ValidityWindow {
+    pub fn new(valid_after: u64, valid_until: u64) -> Result<Self> {
+        if valid_until > MAX_TIMESTAMP {
+            return Err(WalletError::InvalidUserOp(format!(
+                "validUntil {valid_until} does not fit in 48 bits"
+            )));
+        }
+        if valid_after >= valid_until {
+            return Err(WalletError::InvalidUserOp(format!(
+                "validAfter {valid_after} is not before validUntil {valid_until}"
+            )));
+        }
+        Ok(ValidityWindow {
+            valid_after,
+            valid_until,
+        })
+    }
+
+    /// Valid from `now` for `duration`.
+    pub fn starting_at(now: u64, duration: Duration) -> Result<Self> {
+        Self::new(now, now.saturating_add(duration.as_secs()))
+    }
+
+    /// Valid from `delay` after `now`, for `duration` or with no end.
+    pub fn time_locked(now: u64, delay: Duration, duration: Option<Duration>) -> Result<Self> {
+        let valid_after = now.saturating_add(delay.as_secs());
+        let valid_until = match duration {
+            Some(duration) => valid_after.saturating_add(duration.as_secs()),
+            None => MAX_TIMESTAMP,
+        };
+        Self::new(valid_after, valid_until)
+    }
+
+    /// The window of a bare windowed signature field, if `field` is one.
+    /// Fields wrapped again (a verifier envelope, a co-signature) are not
+    /// recognized.
+    pub fn of_field(field: &[u8]) -> Option<Self> {
+        if field.len() != PREFIX_LEN + crate::keys::SIGNATURE_LEN {
+            return None;
+        }
+        Self::unwrap(field).ok().map(|(window, _)| window)
+    }
+
+    pub fn is_open(&self, now: u64) -> bool {
+        self.valid_after <= now && now < self.valid_until
+    }
+
+    /// The 32 bytes signed instead of the userOpHash.
+    pub fn signed_hash(&self, user_op_hash: B256, chain_id: U256) -> B256 {
+        let mut packed = Vec::with_capacity(32 + 32 + PREFIX_LEN);
+        packed.extend_from_slice(user_op_hash.as_slice());
+        packed.extend_from_slice(&chain_id.to_be_bytes::<32>());
+        packed.extend_from_slice(&self.prefix());
+        keccak256(packed)
+    }
+
+    /// `validAfter || validUntil`, six big-endian bytes each.
+    pub fn prefix(&self) -> [u8; PREFIX_LEN] {
+        let mut out = [0u8; PREFIX_LEN];
+        out[..6].copy_from_slice(&self.valid_after.to_be_bytes()[2..]);
+        out[6..].copy_from_slice(&self.valid_until.to_be_bytes()[2..]);
+        out
+    }
+
+    /// Prefix `signature` (the field the validator would otherwise get)
+    /// with the window.
+    pub fn wrap(&self, signature: &[u8]) -> Vec<u8> {
+        let mut out = self.prefix().to_vec();
+        out.extend_from_slice(signature);
+        out
+    }
+
+    /// Split a field produced by [`ValidityWindow::wrap`].
+    pub fn unwrap(field: &[u8]) -> Result<(Self, &[u8])> {
+        if field.len() < PREFIX_LEN {
+            return Err(WalletError::InvalidLength {
+                what: "windowed signature",
+                expected: PREFIX_LEN,
+                actual: field.len(),
+            });
+        }
+        let be48 = |b: &[u8]| b.iter().fold(0u64, |acc, x| (acc << 8) | u64::from(*x));
+        let window = Self::new(be48(&field[..6]), be48(&field[6..PREFIX_LEN]))?;
+        Ok((window, &field[PREFIX_LEN..]))
+    }
+
+    /// The `validationData` the validator returns for a good signature:
+    /// `validAfter (48) | validUntil (48) | aggregator (160)`, high bits
+    /// first, with no aggregator.
+    pub fn validation_data(&self) -> U256 {
+        (U256::from(self.valid_after) << 208) | (U256::from(self.valid_until) << 160)
+    }
+}
+
+/// Parse `90s`, `15m`, `1h`, `7d` or a bare number of seconds.
+pub fn parse_duration(s: &str) -> std::result::Result<Duration, String> {
+    let s = s.trim();
+    let (digits, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let n: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid duration {s:?} (expected e.g. 90s, 15m, 1h, 7d)"))?;
+    let scale = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        other => {
+            return Err(format!(
+                "unknown duration unit {other:?} (expected s, m, h or d)"
+            ));
+        }
+    };
+    n.checked_mul(scale)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration {s:?} is too long"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::ValidationData;
+
+    #[test]
+    fn window_binds_into_the_hash_and_prefix() {
+        let window = ValidityWindow::starting_at(1_700_000_000, Duration::from_secs(3600)).unwrap();
+        assert_eq!(window.valid_until, 1_700_003_600);
+        let hash = B256::repeat_byte(0xab);
+        let chain = U256::from(421614);
+
+        let mut packed = hash.to_vec();
+        packed.extend(chain.to_be_bytes::<32>());
+        packed.extend(&[0, 0, 0x65, 0x53, 0xf1, 0x00]);
+        packed.extend(&[0, 0, 0x65, 0x53, 0xff, 0x10]);
+        assert_eq!(window.signed_hash(hash, chain), keccak256(&packed));
+        // Every input moves the hash.
+        assert_ne!(
+            window.signed_hash(hash, U256::from(1)),
+            window.signed_hash(hash, chain)
+        );
+        let later = ValidityWindow::new(window.valid_after, window.valid_until + 1).unwrap();
+        assert_ne!(
+            later.signed_hash(hash, chain),
+            window.signed_hash(hash, chain)
+        );
+
+        let field = window.wrap(&[7; 3309]);
+        assert_eq!(field.len(), PREFIX_LEN + 3309);
+        let (decoded, sig) = ValidityWindow::unwrap(&field).unwrap();
+        assert_eq!((decoded, sig.len()), (window, 3309));
+
+        let data = ValidationData::unpack(window.validation_data());
+        assert_eq!(
+            (data.valid_after, data.valid_until),
+            (window.valid_after, window.valid_until)
+        );
+        assert!(!data.signature_failed());
+
+        let locked = ValidityWindow::time_locked(1_000, Duration::from_secs(86_400), None).unwrap();
+        assert_eq!(
+            (locked.valid_after, locked.valid_until),
+            (87_400, MAX_TIMESTAMP)
+        );
+        assert!(!locked.is_open(87_399) && locked.is_open(87_400));
+        let field = locked.wrap(&[7; 3309]);
+        assert_eq!(ValidityWindow::of_field(&field), Some(locked));
+        assert_eq!(ValidityWindow::of_field(&field[1..]), None);
+
+        assert!(ValidityWindow::new(10, 10).is_err());
+        assert!(ValidityWindow::new(0, MAX_TIMESTAMP + 1).is_err());
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(604_800)));
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert!(parse_duration("1w").is_err());
+        assert!(parse_duration("h").is_err());
+    }
+}
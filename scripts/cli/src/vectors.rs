@@ -0,0 +1,570 @@
+//! Test-vector generation for the on-chain verifier.
+//!
+//! Each generated case is a `(seed, pk, msg, sig, expected_result)` tuple.
+//! Every valid case is paired with a deliberately corrupted copy so that
+//! consumers exercise both the accept and reject paths of `verify()`.
+//! [`generate_categorized`] instead follows each valid case with one
+//! negative per [`Corruption::CATEGORIES`], each labeled with the way the
+//! verifier should reject it.
+
+use std::ops::Range;
+
+use ml_dsa::signature::Signer;
+use ml_dsa::{MlDsa65, Signature};
+use rand::{Rng, RngExt};
+use serde::Serialize;
+
+use alloy_primitives::{Address, U256};
+
+use crate::calldata::{self, Call};
+use crate::ct;
+use crate::keys::{self, SEED_LEN, SIGNATURE_LEN};
+use crate::userop::{self, ENTRY_POINT_V07, PackedUserOperation};
+
+/// Byte ranges of an ML-DSA-65 signature's components: the commitment hash
+/// `c̃`, the response `z` and the hint `h`.
+const C_TILDE: Range<usize> = 0..48;
+const Z: Range<usize> = 48..3248;
+const HINT: Range<usize> = 3248..SIGNATURE_LEN;
+
+/// Context string for [`Corruption::WrongContext`]; the verifier signs and
+/// verifies with the empty context.
+const WRONG_CONTEXT: &[u8] = b"pq-vectors/wrong-context";
+
+/// How a negative vector was derived from its valid counterpart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Corruption {
+    /// One bit of the signature flipped at a random offset.
+    SignatureBitFlip,
+    /// One bit of the signed hash flipped at a random offset.
+    MessageBitFlip,
+    /// Signature checked against an unrelated public key.
+    WrongPublicKey,
+    /// One bit of the commitment hash `c̃` flipped.
+    CTildeBitFlip,
+    /// One bit of the response `z` flipped.
+    ZBitFlip,
+    /// One bit of the hint section flipped.
+    HintBitFlip,
+    /// The same message signed under a non-empty context string.
+    WrongContext,
+    /// The signature cut short at a random length.
+    TruncatedSignature,
+    /// The valid signature presented for a different, random hash.
+    ReusedSignature,
+}
+
+impl Corruption {
+    const ALL: [Corruption; 3] = [
+        Corruption::SignatureBitFlip,
+        Corruption::MessageBitFlip,
+        Corruption::WrongPublicKey,
+    ];
+
+    /// The categories [`generate_categorized`] covers, one negative each.
+    pub const CATEGORIES: [Corruption; 8] = [
+        Corruption::CTildeBitFlip,
+        Corruption::ZBitFlip,
+        Corruption::HintBitFlip,
+        Corruption::MessageBitFlip,
+        Corruption::WrongContext,
+        Corruption::TruncatedSignature,
+        Corruption::WrongPublicKey,
+        Corruption::ReusedSignature,
+    ];
+}
+
+/// How the verifier is expected to reject a negative vector.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rejection {
+    /// The signature does not decode; `verify()` reverts `InvalidSignature()`.
+    MalformedSignature,
+    /// The signature decodes but does not verify; `verify()` returns false.
+    VerificationFailed,
+}
+
+impl Rejection {
+    fn of(signature: &[u8]) -> Self {
+        match Signature::<MlDsa65>::try_from(signature) {
+            Ok(_) => Rejection::VerificationFailed,
+            Err(_) => Rejection::MalformedSignature,
+        }
+    }
+}
+
+/// A single verifier test case.
+#[derive(Clone, Debug)]
+pub struct TestVector {
+    pub seed: [u8; SEED_LEN],
+    pub public_key: Vec<u8>,
+    pub message: [u8; 32],
+    pub signature: Vec<u8>,
+    pub expected: bool,
+    /// `None` for valid cases.
+    pub corruption: Option<Corruption>,
+    /// `None` for valid cases.
+    pub rejection: Option<Rejection>,
+}
+
+/// Generate `count` valid vectors, each followed by one corrupted negative
+/// case, so the result has `2 * count` entries.
+pub fn generate<R: Rng>(count: usize, rng: &mut R) -> Vec<TestVector> {
+    let mut out = Vec::with_capacity(count * 2);
+    for i in 0..count {
+        let valid = valid_vector(rng);
+        let corruption = Corruption::ALL[i % Corruption::ALL.len()];
+        let negative = corrupt(&valid, corruption, rng);
+        out.push(valid);
+        out.push(negative);
+    }
+    out
+}
+
+/// Generate `count` valid vectors, each followed by one negative case per
+/// [`Corruption::CATEGORIES`] entry.
+pub fn generate_categorized<R: Rng>(count: usize, rng: &mut R) -> Vec<TestVector> {
+    let mut out = Vec::with_capacity(count * (1 + Corruption::CATEGORIES.len()));
+    for _ in 0..count {
+        let valid = valid_vector(rng);
+        let negatives: Vec<_> = Corruption::CATEGORIES
+            .iter()
+            .map(|&corruption| corrupt(&valid, corruption, rng))
+            .collect();
+        out.push(valid);
+        out.extend(negatives);
+    }
+    out
+}
+
+fn flip_bit<R: Rng>(bytes: &mut [u8], range: Range<usize>, rng: &mut R) {
+    let idx = rng.random_range(range);
+    bytes[idx] ^= 1 << rng.random_range(0..8);
+}
+
+fn valid_vector<R: Rng>(rng: &mut R) -> TestVector {
+    let seed: [u8; SEED_LEN] = rng.random();
+    let message: [u8; 32] = rng.random();
+    let kp = keys::keygen_from_seed(&seed);
+    let sig = kp.signing_key().sign(&message);
+    TestVector {
+        seed,
+        public_key: kp.verifying_key().encode().to_vec(),
+        message,
+        signature: sig.encode().to_vec(),
+        expected: true,
+        corruption: None,
+        rejection: None,
+    }
+}
+
+fn corrupt<R: Rng>(valid: &TestVector, corruption: Corruption, rng: &mut R) -> TestVector {
+    let mut v = valid.clone();
+    v.expected = false;
+    v.corruption = Some(corruption);
+    match corruption {
+        Corruption::SignatureBitFlip => {
+            let len = v.signature.len();
+            flip_bit(&mut v.signature, 0..len, rng);
+        }
+        Corruption::CTildeBitFlip => flip_bit(&mut v.signature, C_TILDE, rng),
+        Corruption::ZBitFlip => flip_bit(&mut v.signature, Z, rng),
+        Corruption::HintBitFlip => flip_bit(&mut v.signature, HINT, rng),
+        Corruption::MessageBitFlip => flip_bit(&mut v.message, 0..32, rng),
+        Corruption::WrongContext => {
+            v.signature = keys::keygen_from_seed(&v.seed)
+                .signing_key()
+                .sign_deterministic(&v.message, WRONG_CONTEXT)
+                .expect("context is under 256 bytes")
+                .encode()
+                .to_vec();
+        }
+        Corruption::TruncatedSignature => {
+            let len = rng.random_range(0..v.signature.len());
+            v.signature.truncate(len);
+        }
+        Corruption::ReusedSignature => v.message = rng.random(),
+        Corruption::WrongPublicKey => {
+            let other: [u8; SEED_LEN] = rng.random();
+            v.seed = other;
+            v.public_key = keys::keygen_from_seed(&other)
+                .verifying_key()
+                .encode()
+                .to_vec();
+        }
+    }
+    v.rejection = Some(Rejection::of(&v.signature));
+    v
+}
+
+fn hex0x(bytes: &[u8]) -> String {
+    format!("0x{}", ct::encode_hex(bytes))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonVector {
+    index: usize,
+    seed: String,
+    public_key: String,
+    message: String,
+    signature: String,
+    expected_result: bool,
+    corruption: Option<Corruption>,
+    rejection: Option<Rejection>,
+}
+
+/// Fields are declared in alphabetical order on purpose: Foundry's
+/// `vm.parseJson` + `abi.decode` maps object keys onto struct members
+/// alphabetically, so the Solidity struct must be
+/// `struct Vector { bool expected; bytes message; bytes publicKey; bytes seed; bytes signature; }`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FoundryVector {
+    expected: bool,
+    message: String,
+    public_key: String,
+    seed: String,
+    signature: String,
+}
+
+/// [`FoundryVector`] plus its labels, still in alphabetical order:
+/// `struct Vector { string category; bool expected; bytes message;
+/// bytes publicKey; string rejection; bytes seed; bytes signature; }`.
+/// Valid cases have an empty `category` and `rejection`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FoundryLabeledVector {
+    category: String,
+    expected: bool,
+    message: String,
+    public_key: String,
+    rejection: String,
+    seed: String,
+    signature: String,
+}
+
+/// The snake_case name a label serializes to, or `""` for `None`.
+fn label<T: Serialize>(value: Option<T>) -> String {
+    match value.map(serde_json::to_value) {
+        Some(Ok(serde_json::Value::String(s))) => s,
+        _ => String::new(),
+    }
+}
+
+/// Render vectors as a JSON array with camelCase keys and labels.
+pub fn to_json(vectors: &[TestVector]) -> String {
+    let rows: Vec<JsonVector> = vectors
+        .iter()
+        .enumerate()
+        .map(|(index, v)| JsonVector {
+            index,
+            seed: hex0x(&v.seed),
+            public_key: hex0x(&v.public_key),
+            message: hex0x(&v.message),
+            signature: hex0x(&v.signature),
+            expected_result: v.expected,
+            corruption: v.corruption,
+            rejection: v.rejection,
+        })
+        .collect();
+    serde_json::to_string_pretty(&rows).expect("vectors serialize")
+}
+
+/// Render vectors as `{"vectors": [...]}` shaped for `vm.parseJson`.
+pub fn to_foundry_json(vectors: &[TestVector]) -> String {
+    let rows: Vec<FoundryVector> = vectors
+        .iter()
+        .map(|v| FoundryVector {
+            expected: v.expected,
+            message: hex0x(&v.message),
+            public_key: hex0x(&v.public_key),
+            seed: hex0x(&v.seed),
+            signature: hex0x(&v.signature),
+        })
+        .collect();
+    serde_json::to_string_pretty(&serde_json::json!({ "vectors": rows }))
+        .expect("vectors serialize")
+}
+
+/// Render vectors as `{"vectors": [...]}` with their corruption category and
+/// expected rejection, for suites that assert on each category.
+pub fn to_foundry_labeled_json(vectors: &[TestVector]) -> String {
+    let rows: Vec<FoundryLabeledVector> = vectors
+        .iter()
+        .map(|v| FoundryLabeledVector {
+            category: label(v.corruption),
+            expected: v.expected,
+            message: hex0x(&v.message),
+            public_key: hex0x(&v.public_key),
+            rejection: label(v.rejection),
+            seed: hex0x(&v.seed),
+            signature: hex0x(&v.signature),
+        })
+        .collect();
+    serde_json::to_string_pretty(&serde_json::json!({ "vectors": rows }))
+        .expect("vectors serialize")
+}
+
+/// Render the first valid vector as `PK_HEX=`/`MSG_HASH=`/`SIG_HEX=` lines for
+/// `eval` in a shell, matching what the old `gen_test_data` binary printed.
+pub fn to_env(vectors: &[TestVector]) -> String {
+    let v = vectors
+        .iter()
+        .find(|v| v.expected)
+        .expect("at least one valid vector");
+    format!(
+        "PK_HEX={}\nMSG_HASH={}\nSIG_HEX={}\n",
+        hex0x(&v.public_key),
+        hex0x(&v.message),
+        hex0x(&v.signature)
+    )
+}
+
+/// Chain the [`to_rust`] user operations are hashed for.
+const FIXTURE_CHAIN_ID: u64 = 31337;
+
+/// The op [`to_rust`] derives from a valid vector: a 1-wei call from the
+/// key's address, signed over its userOpHash with the vector's seed.
+fn fixture_user_op(v: &TestVector, nonce: u64) -> (PackedUserOperation, [u8; 32]) {
+    let mut op = PackedUserOperation {
+        sender: keys::address(&v.public_key),
+        nonce: U256::from(nonce),
+        init_code: vec![],
+        call_data: calldata::execute(&[Call {
+            target: Address::repeat_byte(0x42),
+            value: U256::from(1),
+            data: vec![],
+        }]),
+        account_gas_limits: userop::pack_u128_pair(2_000_000, 100_000),
+        pre_verification_gas: U256::from(100_000),
+        gas_fees: userop::pack_u128_pair(1_000_000_000, 2_000_000_000),
+        paymaster_and_data: vec![],
+        signature: vec![],
+    };
+    let hash = userop::compute_user_op_hash(&op, ENTRY_POINT_V07, U256::from(FIXTURE_CHAIN_ID));
+    op.signature = keys::keygen_from_seed(&v.seed)
+        .signing_key()
+        .sign(hash.as_slice())
+        .encode()
+        .to_vec();
+    (op, hash.0)
+}
+
+/// A byte string literal, `*b"..."` for a fixed-size array, wrapped at 32
+/// bytes a line.
+fn bytes_literal(bytes: &[u8], array: bool) -> String {
+    let mut out = String::from(if array { "*b\"" } else { "b\"" });
+    for (i, chunk) in bytes.chunks(32).enumerate() {
+        if i > 0 {
+            out.push_str("\\\n            ");
+        }
+        for b in chunk {
+            out.push_str(&format!("\\x{b:02x}"));
+        }
+    }
+    out.push('"');
+    out
+}
+
+const RUST_HEADER: &str = r#"//! ML-DSA-65 fixtures generated by `pq-vectors generate --format rust`.
+//!
+//! Do not edit. Regenerate with the same `--count`, `--categorized` and
+//! `--rng-seed` so every service compiles in the same bytes.
+#![allow(dead_code)]
+
+/// One verifier case.
+#[derive(Clone, Copy, Debug)]
+pub struct Vector {
+    pub seed: [u8; 32],
+    pub public_key: &'static [u8],
+    /// The signed 32-byte hash.
+    pub message: [u8; 32],
+    pub signature: &'static [u8],
+    pub expected: bool,
+    /// snake_case corruption category; empty for valid cases.
+    pub corruption: &'static str,
+    /// snake_case expected rejection; empty for valid cases.
+    pub rejection: &'static str,
+}
+
+/// An ERC-4337 v0.7 PackedUserOperation signed with a valid vector's key.
+#[derive(Clone, Copy, Debug)]
+pub struct UserOp {
+    /// Index into [`VECTORS`] of the signing key.
+    pub vector: usize,
+    pub chain_id: u64,
+    pub entry_point: [u8; 20],
+    pub sender: [u8; 20],
+    pub nonce: [u8; 32],
+    pub init_code: &'static [u8],
+    pub call_data: &'static [u8],
+    pub account_gas_limits: [u8; 32],
+    pub pre_verification_gas: [u8; 32],
+    pub gas_fees: [u8; 32],
+    pub paymaster_and_data: &'static [u8],
+    pub signature: &'static [u8],
+    pub user_op_hash: [u8; 32],
+}
+"#;
+
+/// Render vectors as a standalone Rust module: `VECTORS`, plus `USER_OPS`
+/// with one op signed by each valid vector's key, as `const` arrays that
+/// need no dependencies to compile.
+pub fn to_rust(vectors: &[TestVector]) -> String {
+    let mut out = String::from(RUST_HEADER);
+    out.push_str(&format!(
+        "\npub const VECTORS: [Vector; {}] = [\n",
+        vectors.len()
+    ));
+    for v in vectors {
+        out.push_str(&format!(
+            "    Vector {{\n        seed: {},\n        public_key: {},\n        message: {},\n        signature: {},\n        expected: {},\n        corruption: {:?},\n        rejection: {:?},\n    }},\n",
+            bytes_literal(&v.seed, true),
+            bytes_literal(&v.public_key, false),
+            bytes_literal(&v.message, true),
+            bytes_literal(&v.signature, false),
+            v.expected,
+            label(v.corruption),
+            label(v.rejection),
+        ));
+    }
+    out.push_str("];\n");
+
+    let valid: Vec<_> = vectors
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| v.expected)
+        .collect();
+    out.push_str(&format!(
+        "\npub const USER_OPS: [UserOp; {}] = [\n",
+        valid.len()
+    ));
+    for (nonce, (index, v)) in valid.into_iter().enumerate() {
+        let (op, hash) = fixture_user_op(v, nonce as u64);
+        out.push_str(&format!(
+            "    UserOp {{\n        vector: {index},\n        chain_id: {FIXTURE_CHAIN_ID},\n        entry_point: {},\n        sender: {},\n        nonce: {},\n        init_code: {},\n        call_data: {},\n        account_gas_limits: {},\n        pre_verification_gas: {},\n        gas_fees: {},\n        paymaster_and_data: {},\n        signature: {},\n        user_op_hash: {},\n    }},\n",
+            bytes_literal(ENTRY_POINT_V07.as_slice(), true),
+            bytes_literal(op.sender.as_slice(), true),
+            bytes_literal(&op.nonce.to_be_bytes::<32>(), true),
+            bytes_literal(&op.init_code, false),
+            bytes_literal(&op.call_data, false),
+            bytes_literal(op.account_gas_limits.as_slice(), true),
+            bytes_literal(&op.pre_verification_gas.to_be_bytes::<32>(), true),
+            bytes_literal(op.gas_fees.as_slice(), true),
+            bytes_literal(&op.paymaster_and_data, false),
+            bytes_literal(&op.signature, false),
+            bytes_literal(&hash, true),
+        ));
+    }
+    out.push_str("];\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ml_dsa::{VerifyingKey, signature::Verifier};
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn verifies(v: &TestVector) -> bool {
+        let pk_arr: [u8; keys::PUBLIC_KEY_LEN] = v.public_key.clone().try_into().unwrap();
+        let pk = VerifyingKey::<MlDsa65>::decode(&pk_arr.into());
+        match Signature::<MlDsa65>::try_from(v.signature.as_slice()) {
+            Ok(sig) => pk.verify(&v.message, &sig).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    #[test]
+    fn generated_vectors_match_expected_result() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let vectors = generate(3, &mut rng);
+        assert_eq!(vectors.len(), 6);
+        for v in &vectors {
+            assert_eq!(verifies(v), v.expected, "corruption {:?}", v.corruption);
+        }
+        let kinds: Vec<_> = vectors.iter().filter_map(|v| v.corruption).collect();
+        assert_eq!(kinds, Corruption::ALL);
+    }
+
+    #[test]
+    fn categorized_negatives_carry_their_rejection() {
+        let vectors = generate_categorized(2, &mut StdRng::seed_from_u64(3));
+        assert_eq!(vectors.len(), 2 * (1 + Corruption::CATEGORIES.len()));
+        for v in &vectors {
+            assert_eq!(verifies(v), v.expected, "corruption {:?}", v.corruption);
+            let malformed = Signature::<MlDsa65>::try_from(v.signature.as_slice()).is_err();
+            match v.corruption {
+                None => assert_eq!(v.rejection, None),
+                Some(Corruption::TruncatedSignature) => {
+                    assert_eq!(v.rejection, Some(Rejection::MalformedSignature))
+                }
+                // c̃ is never range-checked, so it always decodes.
+                Some(Corruption::CTildeBitFlip | Corruption::WrongContext) => {
+                    assert_eq!(v.rejection, Some(Rejection::VerificationFailed))
+                }
+                Some(_) => assert_eq!(
+                    v.rejection == Some(Rejection::MalformedSignature),
+                    malformed
+                ),
+            }
+        }
+        let kinds: Vec<_> = vectors[1..=8].iter().filter_map(|v| v.corruption).collect();
+        assert_eq!(kinds, Corruption::CATEGORIES);
+
+        let out = to_foundry_labeled_json(&vectors[..2]);
+        assert!(out.contains("\"category\": \"\""));
+        assert!(out.contains("\"category\": \"c_tilde_bit_flip\""));
+        assert!(out.contains("\"rejection\": \"verification_failed\""));
+    }
+
+    #[test]
+    fn seeded_generation_is_reproducible() {
+        let a = generate(1, &mut StdRng::seed_from_u64(9));
+        let b = generate(1, &mut StdRng::seed_from_u64(9));
+        assert_eq!(to_json(&a), to_json(&b));
+    }
+
+    #[test]
+    fn rust_fixtures_embed_vectors_and_signed_ops() {
+        let vectors = generate(1, &mut StdRng::seed_from_u64(5));
+        let out = to_rust(&vectors);
+        assert!(out.contains("pub const VECTORS: [Vector; 2] = ["));
+        assert!(out.contains("pub const USER_OPS: [UserOp; 1] = ["));
+        assert!(out.contains(&format!(
+            "message: {},",
+            bytes_literal(&vectors[1].message, true)
+        )));
+        assert_eq!(bytes_literal(&[0xab, 1], false), "b\"\\xab\\x01\"");
+
+        let (op, hash) = fixture_user_op(&vectors[0], 0);
+        let signed = TestVector {
+            message: hash,
+            signature: op.signature.clone(),
+            ..vectors[0].clone()
+        };
+        assert!(verifies(&signed));
+        assert!(out.contains(&format!(
+            "signature: {},",
+            bytes_literal(&op.signature, false)
+        )));
+    }
+
+    #[test]
+    fn foundry_keys_are_alphabetical() {
+        let vectors = generate(1, &mut StdRng::seed_from_u64(2));
+        let out = to_foundry_json(&vectors);
+        let keys = [
+            "\"expected\"",
+            "\"message\"",
+            "\"publicKey\"",
+            "\"seed\"",
+            "\"signature\"",
+        ];
+        let positions: Vec<usize> = keys.iter().map(|k| out.find(k).unwrap()).collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+    }
+}
@@ -0,0 +1,403 @@
+//! Verification results remembered across runs, for indexers that verify
+//! the same signatures again when they reprocess blocks.
+//!
+//! An entry is keyed by the keccak256 of the public key, of the signed
+//! message and of the signature, and records whether that signature
+//! verified. Verification is a pure function of those three, so a hit
+//! stands in for the check. The `capacity` most recently used entries
+//! stay in memory. Every new result is also appended to the cache file,
+//! `$PQ_WALLET_VERIFY_CACHE` or `verify-cache.bin` in the keystore root,
+//! which is read back on open. Once the file holds twice `capacity`
+//! records it is rewritten with only the entries still in memory.
+//!
+//! Whoever can write the file can make a forged signature pass, so it is
+//! created readable and writable by the owner only. Treat it like the
+//! keystore, and pass `--no-cache` where the file is not trusted.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use alloy_primitives::{B256, keccak256};
+use serde::Serialize;
+
+use crate::error::{Result, WalletError};
+use crate::keystore::Keystore;
+
+/// Overrides the cache file's location.
+pub const VERIFY_CACHE_ENV: &str = "PQ_WALLET_VERIFY_CACHE";
+
+/// Entries kept in memory, about 25 MiB.
+pub const DEFAULT_CAPACITY: usize = 100_000;
+
+const MAGIC: &[u8; 8] = b"PQVC\x00\x00\x00\x01";
+const RECORD_LEN: usize = 97;
+
+/// What a verification is looked up by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub key_id: B256,
+    pub message: B256,
+    pub signature: B256,
+}
+
+impl CacheKey {
+    /// `message_hash` is the keccak256 of the message the scheme signs:
+    /// the 32-byte hash itself in hash mode, the raw input otherwise.
+    pub fn new(public_key: &[u8], message_hash: B256, signature: &[u8]) -> Self {
+        CacheKey {
+            key_id: keccak256(public_key),
+            message: message_hash,
+            signature: keccak256(signature),
+        }
+    }
+
+    fn encode(&self, valid: bool) -> [u8; RECORD_LEN] {
+        let mut record = [0u8; RECORD_LEN];
+        record[..32].copy_from_slice(self.key_id.as_slice());
+        record[32..64].copy_from_slice(self.message.as_slice());
+        record[64..96].copy_from_slice(self.signature.as_slice());
+        record[96] = valid as u8;
+        record
+    }
+
+    fn decode(record: &[u8]) -> Option<(Self, bool)> {
+        let key = CacheKey {
+            key_id: B256::from_slice(&record[..32]),
+            message: B256::from_slice(&record[32..64]),
+            signature: B256::from_slice(&record[64..96]),
+        };
+        match record[96] {
+            0 => Some((key, false)),
+            1 => Some((key, true)),
+            _ => None,
+        }
+    }
+}
+
+/// Lookups since the cache was opened.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_rate: f64,
+}
+
+impl CacheStats {
+    fn new(hits: u64, misses: u64) -> Self {
+        let lookups = hits + misses;
+        CacheStats {
+            hits,
+            misses,
+            hit_rate: if lookups == 0 {
+                0.0
+            } else {
+                hits as f64 / lookups as f64
+            },
+        }
+    }
+
+    /// Lookups made after `earlier` was taken from the same cache.
+    pub fn since(&self, earlier: &CacheStats) -> CacheStats {
+        CacheStats::new(self.hits - earlier.hits, self.misses - earlier.misses)
+    }
+}
+
+struct Disk {
+    path: PathBuf,
+    file: File,
+    records: usize,
+}
+
+struct State {
+    /// Entry to (result, last use).
+    entries: HashMap<CacheKey, (bool, u64)>,
+    /// Last use to entry, oldest first.
+    order: BTreeMap<u64, CacheKey>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+    disk: Option<Disk>,
+}
+
+impl State {
+    fn touch(&mut self, key: CacheKey, valid: bool) {
+        self.clock += 1;
+        if let Some((_, used)) = self.entries.insert(key, (valid, self.clock)) {
+            self.order.remove(&used);
+        }
+        self.order.insert(self.clock, key);
+    }
+
+    fn evict(&mut self, capacity: usize) {
+        while self.entries.len() > capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+pub struct VerifyCache {
+    capacity: usize,
+    state: Mutex<State>,
+}
+
+fn io_err(path: &Path) -> impl Fn(std::io::Error) -> WalletError + '_ {
+    move |e| WalletError::Io(path.display().to_string(), e)
+}
+
+fn open_private(path: &Path, options: &mut OpenOptions) -> Result<File> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path).map_err(io_err(path))
+}
+
+impl VerifyCache {
+    /// A cache that forgets everything when dropped.
+    pub fn in_memory(capacity: usize) -> Self {
+        VerifyCache {
+            capacity: capacity.max(1),
+            state: Mutex::new(State {
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                clock: 0,
+                hits: 0,
+                misses: 0,
+                disk: None,
+            }),
+        }
+    }
+
+    /// `$PQ_WALLET_VERIFY_CACHE`, else `verify-cache.bin` in the keystore
+    /// root.
+    pub fn default_path() -> Result<PathBuf> {
+        match std::env::var_os(VERIFY_CACHE_ENV) {
+            Some(path) => Ok(PathBuf::from(path)),
+            None => Ok(Keystore::default_root()?.join("verify-cache.bin")),
+        }
+    }
+
+    pub fn open_default(capacity: usize) -> Result<Self> {
+        Self::open(Self::default_path()?, capacity)
+    }
+
+    /// Load the cache file at `path`, creating it if missing. A torn last
+    /// record from an interrupted append is dropped.
+    pub fn open(path: impl Into<PathBuf>, capacity: usize) -> Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(io_err(dir))?;
+        }
+        let mut file = open_private(
+            &path,
+            OpenOptions::new().read(true).append(true).create(true),
+        )?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).map_err(io_err(&path))?;
+
+        let cache = Self::in_memory(capacity);
+        let mut records = 0;
+        {
+            let mut state = cache.state();
+            if bytes.is_empty() {
+                file.write_all(MAGIC).map_err(io_err(&path))?;
+            } else {
+                let body = bytes.strip_prefix(MAGIC).ok_or_else(|| {
+                    WalletError::VerifyCache(format!("{} is not a cache file", path.display()))
+                })?;
+                for record in body.chunks_exact(RECORD_LEN) {
+                    let (key, valid) = CacheKey::decode(record).ok_or_else(|| {
+                        WalletError::VerifyCache(format!("{}: bad record", path.display()))
+                    })?;
+                    state.touch(key, valid);
+                    state.evict(cache.capacity);
+                    records += 1;
+                }
+            }
+            state.disk = Some(Disk {
+                path,
+                file,
+                records,
+            });
+            let torn = !bytes.is_empty() && !(bytes.len() - MAGIC.len()).is_multiple_of(RECORD_LEN);
+            if torn || records > 2 * cache.capacity {
+                compact(&mut state)?;
+            }
+        }
+        Ok(cache)
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The remembered result for `key`, counted as a hit or a miss.
+    pub fn get(&self, key: &CacheKey) -> Option<bool> {
+        let mut state = self.state();
+        match state.entries.get(key) {
+            Some(&(valid, _)) => {
+                state.hits += 1;
+                state.touch(*key, valid);
+                Some(valid)
+            }
+            None => {
+                state.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Remember `valid` for `key`, in memory and in the file if there is
+    /// one.
+    pub fn insert(&self, key: CacheKey, valid: bool) -> Result<()> {
+        let mut state = self.state();
+        if state.entries.contains_key(&key) {
+            state.touch(key, valid);
+            return Ok(());
+        }
+        state.touch(key, valid);
+        state.evict(self.capacity);
+        let capacity = self.capacity;
+        let Some(disk) = state.disk.as_mut() else {
+            return Ok(());
+        };
+        disk.file
+            .write_all(&key.encode(valid))
+            .map_err(io_err(&disk.path))?;
+        disk.records += 1;
+        if disk.records > 2 * capacity {
+            compact(&mut state)?;
+        }
+        Ok(())
+    }
+
+    /// The cached result for `key`, else run `verify` and remember what it
+    /// returns. The flag is whether the result came from the cache. A
+    /// result that cannot be written to the file is logged, not an error.
+    pub fn verify_with(
+        &self,
+        key: CacheKey,
+        verify: impl FnOnce() -> Result<bool>,
+    ) -> Result<(bool, bool)> {
+        if let Some(valid) = self.get(&key) {
+            return Ok((valid, true));
+        }
+        let valid = verify()?;
+        if let Err(e) = self.insert(key, valid) {
+            tracing::warn!("{e}");
+        }
+        Ok((valid, false))
+    }
+
+    pub fn len(&self) -> usize {
+        self.state().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let state = self.state();
+        CacheStats::new(state.hits, state.misses)
+    }
+}
+
+/// Rewrite the file with the in-memory entries, oldest first, through a
+/// temporary file so a crash leaves the old one intact.
+fn compact(state: &mut State) -> Result<()> {
+    let Some(disk) = state.disk.as_mut() else {
+        return Ok(());
+    };
+    let tmp = disk.path.with_extension("tmp");
+    let mut out = Vec::with_capacity(MAGIC.len() + state.order.len() * RECORD_LEN);
+    out.extend_from_slice(MAGIC);
+    for key in state.order.values() {
+        out.extend_from_slice(&key.encode(state.entries[key].0));
+    }
+    let mut file = open_private(
+        &tmp,
+        OpenOptions::new().write(true).create(true).truncate(true),
+    )?;
+    file.write_all(&out).map_err(io_err(&tmp))?;
+    file.sync_all().map_err(io_err(&tmp))?;
+    std::fs::rename(&tmp, &disk.path).map_err(io_err(&disk.path))?;
+    disk.file = open_private(&disk.path, OpenOptions::new().append(true))?;
+    disk.records = state.order.len();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(n: u8) -> CacheKey {
+        CacheKey::new(&[n; 1952], B256::repeat_byte(n), &[n; 3309])
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_and_counts_hits() {
+        let cache = VerifyCache::in_memory(2);
+        cache.insert(key(1), true).unwrap();
+        cache.insert(key(2), false).unwrap();
+        assert_eq!(cache.get(&key(1)), Some(true));
+        cache.insert(key(3), true).unwrap();
+        assert_eq!(cache.get(&key(2)), None);
+        assert_eq!(cache.get(&key(3)), Some(true));
+
+        let mut ran = false;
+        let (valid, cached) = cache
+            .verify_with(key(1), || {
+                ran = true;
+                Ok(false)
+            })
+            .unwrap();
+        assert!(valid && cached && !ran);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (3, 1));
+        assert_eq!(stats.hit_rate, 0.75);
+    }
+
+    #[test]
+    fn results_survive_reopening_and_compaction() {
+        let path =
+            std::env::temp_dir().join(format!("pq-cli-{}-verify-cache.bin", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let cache = VerifyCache::open(&path, 3).unwrap();
+            for n in 0..10 {
+                cache.insert(key(n), n % 2 == 0).unwrap();
+            }
+        }
+        // Compacted to the last three whenever the file passed six records.
+        let len = std::fs::metadata(&path).unwrap().len() as usize;
+        assert!(len <= MAGIC.len() + 6 * RECORD_LEN, "{len} bytes");
+
+        let cache = VerifyCache::open(&path, 3).unwrap();
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get(&key(8)), Some(true));
+        assert_eq!(cache.get(&key(9)), Some(false));
+        assert_eq!(cache.get(&key(6)), None);
+
+        // A torn append is dropped, not an error.
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&[1, 2, 3])
+            .unwrap();
+        assert_eq!(VerifyCache::open(&path, 3).unwrap().len(), 3);
+
+        std::fs::write(&path, b"not a cache").unwrap();
+        assert!(VerifyCache::open(&path, 3).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
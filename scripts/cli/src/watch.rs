@@ -0,0 +1,458 @@
+//! On-chain monitoring for a smart account (`pq-watch`).
+//!
+//! A [`Watcher`] polls a node with `eth_getLogs` for two sets of logs: the
+//! EntryPoint's `UserOperationEvent` and `UserOperationRevertReason` for ops
+//! the account sent, and the account's own ERC-7579 `ModuleInstalled` and
+//! `ModuleUninstalled` and `OwnershipTransferred` events. Each log becomes a
+//! [`WatchEvent`]. These are raised to [`Severity::Alert`]:
+//!
+//! - a failed op, with its revert reason when the EntryPoint logged one;
+//! - a module install that is not on the expected list;
+//! - any validator uninstall, which is how a PQ account's key is rotated
+//!   out;
+//! - an ownership transfer.
+//!
+//! Everything else is [`Severity::Info`]. Each poll reads at most
+//! [`MAX_BLOCK_RANGE`] blocks, so a watcher started far behind catches up
+//! over several polls instead of one query the node refuses.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use alloy_primitives::{Address, B256, Bytes, U64, U256};
+use alloy_sol_types::{SolEvent, sol};
+use serde::{Deserialize, Serialize};
+
+use crate::bundler::{UserOperationEvent, UserOperationRevertReason};
+use crate::calldata::ModuleType;
+use crate::error::{Result, WalletError};
+use crate::rpc::Transport;
+
+/// Most blocks one poll asks the node for.
+pub const MAX_BLOCK_RANGE: u64 = 2_000;
+
+sol! {
+    /// ERC-7579 `IERC7579Account.ModuleInstalled`.
+    event ModuleInstalled(uint256 moduleTypeId, address module);
+    /// ERC-7579 `IERC7579Account.ModuleUninstalled`.
+    event ModuleUninstalled(uint256 moduleTypeId, address module);
+    /// OpenZeppelin `Ownable.OwnershipTransferred`, for accounts with an owner.
+    event OwnershipTransferred(address indexed previousOwner, address indexed newOwner);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    Info,
+    Alert,
+}
+
+/// What happened.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(
+    tag = "event",
+    rename_all = "kebab-case",
+    rename_all_fields = "camelCase"
+)]
+pub enum EventKind {
+    UserOperation {
+        user_op_hash: B256,
+        nonce: U256,
+        success: bool,
+        actual_gas_cost: U256,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        revert_reason: Option<Bytes>,
+    },
+    ModuleInstalled {
+        module_type: u64,
+        module: Address,
+    },
+    ModuleUninstalled {
+        module_type: u64,
+        module: Address,
+    },
+    OwnershipTransferred {
+        previous_owner: Address,
+        new_owner: Address,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchEvent {
+    pub account: Address,
+    pub block_number: u64,
+    pub transaction_hash: B256,
+    pub severity: Severity,
+    #[serde(flatten)]
+    pub kind: EventKind,
+    /// Why it is an alert.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl WatchEvent {
+    /// One line for a terminal.
+    pub fn summary(&self) -> String {
+        let what = match &self.kind {
+            EventKind::UserOperation {
+                user_op_hash,
+                nonce,
+                success,
+                ..
+            } => format!(
+                "userOp {user_op_hash} (nonce {nonce}) {}",
+                if *success { "succeeded" } else { "failed" }
+            ),
+            EventKind::ModuleInstalled {
+                module_type,
+                module,
+            } => format!(
+                "installed {} module {module}",
+                module_type_name(*module_type)
+            ),
+            EventKind::ModuleUninstalled {
+                module_type,
+                module,
+            } => format!(
+                "uninstalled {} module {module}",
+                module_type_name(*module_type)
+            ),
+            EventKind::OwnershipTransferred {
+                previous_owner,
+                new_owner,
+            } => format!("ownership moved from {previous_owner} to {new_owner}"),
+        };
+        match &self.reason {
+            Some(reason) => format!("block {}: {what}: {reason}", self.block_number),
+            None => format!("block {}: {what}", self.block_number),
+        }
+    }
+}
+
+fn module_type_name(id: u64) -> String {
+    match id.to_string().parse::<ModuleType>() {
+        Ok(module_type) => module_type.to_string(),
+        Err(_) => format!("type-{id}"),
+    }
+}
+
+/// A log as `eth_getLogs` returns it.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RpcLog {
+    address: Address,
+    topics: Vec<B256>,
+    data: Bytes,
+    block_number: U64,
+    transaction_hash: B256,
+    log_index: U64,
+}
+
+pub struct Watcher {
+    pub account: Address,
+    pub entry_point: Address,
+    /// Modules whose install is not an alert.
+    pub expected_modules: BTreeSet<Address>,
+    /// First block not read yet.
+    pub next_block: u64,
+    /// The node's head at the last poll.
+    pub head: u64,
+}
+
+impl Watcher {
+    pub fn new(
+        account: Address,
+        entry_point: Address,
+        expected_modules: BTreeSet<Address>,
+        from_block: u64,
+    ) -> Self {
+        Watcher {
+            account,
+            entry_point,
+            expected_modules,
+            next_block: from_block,
+            head: 0,
+        }
+    }
+
+    /// Whether the last poll read up to the node's head.
+    pub fn caught_up(&self) -> bool {
+        self.next_block > self.head
+    }
+
+    /// The events in the blocks since the last poll, oldest first.
+    pub fn poll<T: Transport>(&mut self, node: &T) -> Result<Vec<WatchEvent>> {
+        let head = node
+            .call::<U64>("eth_blockNumber", serde_json::json!([]))?
+            .to::<u64>();
+        self.head = head;
+        if head < self.next_block {
+            return Ok(Vec::new());
+        }
+        let to = head.min(self.next_block + MAX_BLOCK_RANGE - 1);
+        let range = |address: Address, topics: serde_json::Value| {
+            serde_json::json!([{
+                "address": address,
+                "fromBlock": format!("{:#x}", self.next_block),
+                "toBlock": format!("{to:#x}"),
+                "topics": topics,
+            }])
+        };
+        let sender = self.account.into_word();
+        let mut logs: Vec<RpcLog> = node.call(
+            "eth_getLogs",
+            range(
+                self.entry_point,
+                serde_json::json!([
+                    [
+                        UserOperationEvent::SIGNATURE_HASH,
+                        UserOperationRevertReason::SIGNATURE_HASH
+                    ],
+                    null,
+                    sender
+                ]),
+            ),
+        )?;
+        logs.extend(node.call::<Vec<RpcLog>>(
+            "eth_getLogs",
+            range(
+                self.account,
+                serde_json::json!([[
+                    ModuleInstalled::SIGNATURE_HASH,
+                    ModuleUninstalled::SIGNATURE_HASH,
+                    OwnershipTransferred::SIGNATURE_HASH
+                ]]),
+            ),
+        )?);
+        logs.sort_by_key(|log| (log.block_number, log.log_index));
+        let events = self.classify(&logs)?;
+        self.next_block = to + 1;
+        Ok(events)
+    }
+
+    fn classify(&self, logs: &[RpcLog]) -> Result<Vec<WatchEvent>> {
+        let decode_err = |name: &str, e: alloy_sol_types::Error| {
+            WalletError::Rpc(format!("{name}: undecodable log: {e}"))
+        };
+        let topics = |log: &RpcLog| log.topics.clone().into_iter();
+        // The EntryPoint logs the revert reason just before the event.
+        let mut reasons = BTreeMap::new();
+        for log in logs.iter().filter(|log| log.address == self.entry_point) {
+            if log.topics.first() == Some(&UserOperationRevertReason::SIGNATURE_HASH) {
+                let e = UserOperationRevertReason::decode_raw_log(topics(log), &log.data)
+                    .map_err(|e| decode_err("UserOperationRevertReason", e))?;
+                reasons.insert(e.userOpHash, e.revertReason);
+            }
+        }
+        let mut events = Vec::new();
+        for log in logs {
+            let event = |severity, kind, reason: Option<String>| WatchEvent {
+                account: self.account,
+                block_number: log.block_number.to(),
+                transaction_hash: log.transaction_hash,
+                severity,
+                kind,
+                reason,
+            };
+            let Some(&topic) = log.topics.first() else {
+                continue;
+            };
+            if log.address == self.entry_point && topic == UserOperationEvent::SIGNATURE_HASH {
+                let e = UserOperationEvent::decode_raw_log(topics(log), &log.data)
+                    .map_err(|e| decode_err("UserOperationEvent", e))?;
+                if e.sender != self.account {
+                    continue;
+                }
+                let (severity, reason) = if e.success {
+                    (Severity::Info, None)
+                } else {
+                    (Severity::Alert, Some("the op reverted".to_string()))
+                };
+                events.push(event(
+                    severity,
+                    EventKind::UserOperation {
+                        user_op_hash: e.userOpHash,
+                        nonce: e.nonce,
+                        success: e.success,
+                        actual_gas_cost: e.actualGasCost,
+                        revert_reason: reasons.get(&e.userOpHash).cloned(),
+                    },
+                    reason,
+                ));
+            } else if log.address != self.account {
+                continue;
+            } else if topic == ModuleInstalled::SIGNATURE_HASH {
+                let e = ModuleInstalled::decode_raw_log(topics(log), &log.data)
+                    .map_err(|e| decode_err("ModuleInstalled", e))?;
+                let (severity, reason) = if self.expected_modules.contains(&e.module) {
+                    (Severity::Info, None)
+                } else {
+                    (Severity::Alert, Some("not an expected module".to_string()))
+                };
+                events.push(event(
+                    severity,
+                    EventKind::ModuleInstalled {
+                        module_type: e.moduleTypeId.saturating_to(),
+                        module: e.module,
+                    },
+                    reason,
+                ));
+            } else if topic == ModuleUninstalled::SIGNATURE_HASH {
+                let e = ModuleUninstalled::decode_raw_log(topics(log), &log.data)
+                    .map_err(|e| decode_err("ModuleUninstalled", e))?;
+                let module_type = e.moduleTypeId.saturating_to();
+                let (severity, reason) = if module_type == ModuleType::Validator.id() {
+                    (Severity::Alert, Some("a validator was removed".to_string()))
+                } else {
+                    (Severity::Info, None)
+                };
+                events.push(event(
+                    severity,
+                    EventKind::ModuleUninstalled {
+                        module_type,
+                        module: e.module,
+                    },
+                    reason,
+                ));
+            } else if topic == OwnershipTransferred::SIGNATURE_HASH {
+                let e = OwnershipTransferred::decode_raw_log(topics(log), &log.data)
+                    .map_err(|e| decode_err("OwnershipTransferred", e))?;
+                events.push(event(
+                    Severity::Alert,
+                    EventKind::OwnershipTransferred {
+                        previous_owner: e.previousOwner,
+                        new_owner: e.newOwner,
+                    },
+                    Some("the account's owner changed".to_string()),
+                ));
+            }
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::userop::ENTRY_POINT_V07;
+    use alloy_sol_types::SolValue;
+    use serde_json::{Value, json};
+    use std::cell::RefCell;
+
+    struct Node {
+        head: u64,
+        ranges: RefCell<Vec<(String, String)>>,
+        entry_point_logs: Value,
+        account_logs: Value,
+    }
+
+    impl Transport for Node {
+        fn request(&self, method: &str, params: Value) -> Result<Value> {
+            match method {
+                "eth_blockNumber" => Ok(json!(format!("{:#x}", self.head))),
+                "eth_getLogs" => {
+                    let filter = &params[0];
+                    self.ranges.borrow_mut().push((
+                        filter["fromBlock"].as_str().unwrap().to_string(),
+                        filter["toBlock"].as_str().unwrap().to_string(),
+                    ));
+                    if filter["address"] == json!(ENTRY_POINT_V07) {
+                        Ok(self.entry_point_logs.clone())
+                    } else {
+                        Ok(self.account_logs.clone())
+                    }
+                }
+                other => panic!("unexpected {other}"),
+            }
+        }
+    }
+
+    #[test]
+    fn raises_alerts_for_failures_and_unexpected_changes() {
+        let account = Address::repeat_byte(0xaa);
+        let expected = Address::repeat_byte(0x01);
+        let stranger = Address::repeat_byte(0x02);
+        let op_hash = B256::repeat_byte(0x0f);
+        let log = |address: Address, block: u64, index: u64, topics: Vec<B256>, data: Vec<u8>| {
+            json!({
+                "address": address,
+                "topics": topics,
+                "data": Bytes::from(data),
+                "blockNumber": format!("{block:#x}"),
+                "transactionHash": B256::repeat_byte(block as u8),
+                "logIndex": format!("{index:#x}"),
+            })
+        };
+        let module_log = |sig: B256, block, module: Address| {
+            log(
+                account,
+                block,
+                0,
+                vec![sig],
+                (U256::from(1), module).abi_encode_params(),
+            )
+        };
+        let node = Node {
+            head: 5_000,
+            ranges: RefCell::new(vec![]),
+            entry_point_logs: json!([
+                log(
+                    ENTRY_POINT_V07,
+                    7,
+                    2,
+                    vec![
+                        UserOperationEvent::SIGNATURE_HASH,
+                        op_hash,
+                        account.into_word(),
+                        B256::ZERO
+                    ],
+                    (U256::from(3), false, U256::from(100), U256::from(50)).abi_encode_params(),
+                ),
+                log(
+                    ENTRY_POINT_V07,
+                    7,
+                    1,
+                    vec![
+                        UserOperationRevertReason::SIGNATURE_HASH,
+                        op_hash,
+                        account.into_word()
+                    ],
+                    (U256::from(3), Bytes::from_static(b"no")).abi_encode_params(),
+                ),
+            ]),
+            account_logs: json!([
+                module_log(ModuleInstalled::SIGNATURE_HASH, 5, expected),
+                module_log(ModuleInstalled::SIGNATURE_HASH, 6, stranger),
+                module_log(ModuleUninstalled::SIGNATURE_HASH, 8, expected),
+            ]),
+        };
+        let mut watcher = Watcher::new(account, ENTRY_POINT_V07, [expected].into(), 0);
+        let events = watcher.poll(&node).unwrap();
+        let severities: Vec<_> = events
+            .iter()
+            .map(|e| (e.block_number, e.severity))
+            .collect();
+        assert_eq!(
+            severities,
+            [
+                (5, Severity::Info),
+                (6, Severity::Alert),
+                (7, Severity::Alert),
+                (8, Severity::Alert),
+            ]
+        );
+        let EventKind::UserOperation { revert_reason, .. } = &events[2].kind else {
+            panic!("not a userOp event");
+        };
+        assert_eq!(revert_reason.as_ref().map(|r| &r[..]), Some(&b"no"[..]));
+        assert_eq!(
+            serde_json::to_value(&events[1]).unwrap()["event"],
+            "module-installed"
+        );
+
+        // A long way behind: one bounded range per poll.
+        assert_eq!(watcher.next_block, MAX_BLOCK_RANGE);
+        assert!(!watcher.caught_up());
+        assert_eq!(node.ranges.borrow()[0], ("0x0".into(), "0x7cf".into()));
+    }
+}
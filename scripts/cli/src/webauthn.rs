@@ -0,0 +1,274 @@
+//! Passkey co-signing: a WebAuthn assertion from a platform authenticator
+//! (P-256) alongside the ML-DSA signature on each userOp.
+//!
+//! The passkey signs `authenticatorData || sha256(clientDataJSON)`, and the
+//! challenge inside `clientDataJSON` must be the base64url userOpHash, so
+//! both keys commit to the same op. [`co_signature`] packs the result the
+//! way the dual validator decodes it:
+//!
+//! ```text
+//! abi.encode(WebAuthnAuth auth, bytes pqSignature)
+//! ```
+//!
+//! `WebAuthnAuth` is the struct Solady's and Coinbase's `WebAuthn.sol`
+//! verify (RIP-7212 or a Solidity P-256 fallback), with `s` normalized to
+//! the lower half of the curve order as they require.
+
+use alloy_primitives::{B256, Bytes, U256};
+use alloy_sol_types::{SolValue, sol};
+use base64ct::{Base64UrlUnpadded, Encoding};
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::{Result, WalletError};
+
+sol! {
+    /// `WebAuthn.WebAuthnAuth`.
+    struct WebAuthnAuth {
+        bytes authenticatorData;
+        string clientDataJSON;
+        uint256 challengeIndex;
+        uint256 typeIndex;
+        uint256 r;
+        uint256 s;
+    }
+}
+
+/// authenticatorData flag: user present.
+pub const FLAG_UP: u8 = 0x01;
+/// authenticatorData flag: user verified (biometric or PIN).
+pub const FLAG_UV: u8 = 0x04;
+
+/// rpIdHash (32) + flags (1) + signCount (4).
+const MIN_AUTHENTICATOR_DATA_LEN: usize = 37;
+
+fn err(reason: impl Into<String>) -> WalletError {
+    WalletError::WebAuthn(reason.into())
+}
+
+fn b64url(field: &str, value: &str) -> Result<Vec<u8>> {
+    // Some clients pad; the spec form does not.
+    Base64UrlUnpadded::decode_vec(value.trim_end_matches('='))
+        .map_err(|_| err(format!("{field} is not base64url")))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResponseJson {
+    authenticator_data: String,
+    #[serde(rename = "clientDataJSON")]
+    client_data_json: String,
+    signature: String,
+}
+
+/// `PublicKeyCredential.toJSON()` output, or just its `response` member.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AssertionJson {
+    Credential {
+        #[serde(default)]
+        id: Option<String>,
+        response: ResponseJson,
+    },
+    Response(ResponseJson),
+}
+
+/// A decoded `AuthenticatorAssertionResponse`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assertion {
+    pub credential_id: Option<String>,
+    pub authenticator_data: Vec<u8>,
+    pub client_data_json: String,
+    /// DER-encoded ECDSA signature.
+    pub signature: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+}
+
+impl Assertion {
+    /// Parse an assertion JSON file.
+    pub fn from_json(bytes: &[u8]) -> Result<Self> {
+        let parsed: AssertionJson = serde_json::from_slice(bytes)
+            .map_err(|e| err(format!("invalid assertion JSON: {e}")))?;
+        let (credential_id, response) = match parsed {
+            AssertionJson::Credential { id, response } => (id, response),
+            AssertionJson::Response(response) => (None, response),
+        };
+        let client_data = b64url("clientDataJSON", &response.client_data_json)?;
+        Ok(Assertion {
+            credential_id,
+            authenticator_data: b64url("authenticatorData", &response.authenticator_data)?,
+            client_data_json: String::from_utf8(client_data)
+                .map_err(|_| err("clientDataJSON is not UTF-8"))?,
+            signature: b64url("signature", &response.signature)?,
+        })
+    }
+
+    /// The bytes the authenticator signed.
+    pub fn signed_message(&self) -> Vec<u8> {
+        let mut msg = self.authenticator_data.clone();
+        msg.extend_from_slice(&Sha256::digest(self.client_data_json.as_bytes()));
+        msg
+    }
+
+    fn signature_low_s(&self) -> Result<Signature> {
+        let sig =
+            Signature::from_der(&self.signature).map_err(|_| err("signature is not DER ECDSA"))?;
+        Ok(sig.normalize_s().unwrap_or(sig))
+    }
+
+    /// Check that this assertion was made over `user_op_hash` with the user
+    /// present (and verified, with `require_uv`), and return the on-chain
+    /// form.
+    pub fn check(&self, user_op_hash: B256, require_uv: bool) -> Result<WebAuthnAuth> {
+        let data = &self.authenticator_data;
+        if data.len() < MIN_AUTHENTICATOR_DATA_LEN {
+            return Err(err(format!(
+                "authenticatorData is {} bytes, need at least {MIN_AUTHENTICATOR_DATA_LEN}",
+                data.len()
+            )));
+        }
+        let flags = data[32];
+        if flags & FLAG_UP == 0 {
+            return Err(err("user-present flag is not set"));
+        }
+        if require_uv && flags & FLAG_UV == 0 {
+            return Err(err("user-verified flag is not set"));
+        }
+
+        let client: ClientData = serde_json::from_str(&self.client_data_json)
+            .map_err(|e| err(format!("invalid clientDataJSON: {e}")))?;
+        if client.kind != "webauthn.get" {
+            return Err(err(format!(
+                "clientDataJSON type is {:?}, expected \"webauthn.get\"",
+                client.kind
+            )));
+        }
+        let challenge = b64url("challenge", &client.challenge)?;
+        if challenge != user_op_hash.as_slice() {
+            return Err(err(format!(
+                "challenge 0x{} is not the userOpHash {user_op_hash}",
+                hex::encode(&challenge)
+            )));
+        }
+
+        // The contract re-checks both substrings at these offsets rather
+        // than parsing JSON.
+        let json = &self.client_data_json;
+        let type_index = json
+            .find(r#""type":"webauthn.get""#)
+            .ok_or_else(|| err("clientDataJSON type is not in compact form"))?;
+        let challenge_index = json
+            .find(r#""challenge":""#)
+            .ok_or_else(|| err("clientDataJSON challenge is not in compact form"))?;
+
+        let sig = self.signature_low_s()?;
+        let (r, s) = sig.split_bytes();
+        Ok(WebAuthnAuth {
+            authenticatorData: Bytes::copy_from_slice(data),
+            clientDataJSON: json.clone(),
+            challengeIndex: U256::from(challenge_index),
+            typeIndex: U256::from(type_index),
+            r: U256::from_be_slice(&r),
+            s: U256::from_be_slice(&s),
+        })
+    }
+
+    /// Verify the P-256 signature under the passkey's public key.
+    pub fn verify(&self, key: &VerifyingKey) -> Result<bool> {
+        Ok(key
+            .verify(&self.signed_message(), &self.signature_low_s()?)
+            .is_ok())
+    }
+}
+
+/// Parse a SEC1 P-256 public key (hex, compressed or uncompressed).
+pub fn verifying_key_from_hex(hex: &str) -> Result<VerifyingKey> {
+    let bytes = crate::ct::decode_hex(hex)?;
+    VerifyingKey::from_sec1_bytes(&bytes).map_err(|_| err("not a SEC1 P-256 public key"))
+}
+
+/// `userOp.signature` for the dual validator.
+pub fn co_signature(auth: &WebAuthnAuth, pq_signature: &[u8]) -> Vec<u8> {
+    (auth.clone(), Bytes::copy_from_slice(pq_signature)).abi_encode_params()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::SigningKey;
+    use p256::ecdsa::signature::Signer;
+
+    fn assertion(key: &SigningKey, challenge: B256, flags: u8) -> Assertion {
+        let mut authenticator_data = Sha256::digest(b"wallet.example").to_vec();
+        authenticator_data.push(flags);
+        authenticator_data.extend(7u32.to_be_bytes());
+        let client_data_json = format!(
+            r#"{{"type":"webauthn.get","challenge":"{}","origin":"https://wallet.example","crossOrigin":false}}"#,
+            Base64UrlUnpadded::encode_string(challenge.as_slice())
+        );
+        let mut a = Assertion {
+            credential_id: None,
+            authenticator_data,
+            client_data_json,
+            signature: Vec::new(),
+        };
+        let sig: Signature = key.sign(&a.signed_message());
+        a.signature = sig.to_der().as_bytes().to_vec();
+        a
+    }
+
+    #[test]
+    fn checks_challenge_flags_and_signature() {
+        let key = SigningKey::from_slice(&[0x11; 32]).unwrap();
+        let hash = B256::repeat_byte(0xab);
+        let a = assertion(&key, hash, FLAG_UP | FLAG_UV);
+        let auth = a.check(hash, true).unwrap();
+        assert_eq!(auth.typeIndex, U256::from(1));
+        assert_eq!(auth.challengeIndex, U256::from(23));
+        let low_s = Signature::from_scalars(auth.r.to_be_bytes::<32>(), auth.s.to_be_bytes::<32>());
+        assert!(low_s.unwrap().normalize_s().is_none());
+        assert!(a.verify(key.verifying_key()).unwrap());
+
+        assert!(a.check(B256::ZERO, false).is_err());
+        let present_only = assertion(&key, hash, FLAG_UP);
+        assert!(present_only.check(hash, false).is_ok());
+        assert!(present_only.check(hash, true).is_err());
+        let other = SigningKey::from_slice(&[0x22; 32]).unwrap();
+        assert!(!a.verify(other.verifying_key()).unwrap());
+    }
+
+    #[test]
+    fn parses_credential_json_and_packs_co_signature() {
+        let key = SigningKey::from_slice(&[0x11; 32]).unwrap();
+        let hash = B256::repeat_byte(0x01);
+        let a = assertion(&key, hash, FLAG_UP);
+        let enc = |b: &[u8]| Base64UrlUnpadded::encode_string(b);
+        let json = serde_json::json!({
+            "id": "cred-1",
+            "type": "public-key",
+            "response": {
+                "authenticatorData": enc(&a.authenticator_data),
+                "clientDataJSON": enc(a.client_data_json.as_bytes()),
+                "signature": enc(&a.signature),
+                "userHandle": null,
+            },
+        });
+        let parsed = Assertion::from_json(json.to_string().as_bytes()).unwrap();
+        assert_eq!(parsed.credential_id.as_deref(), Some("cred-1"));
+        assert_eq!(parsed.client_data_json, a.client_data_json);
+
+        let auth = parsed.check(hash, false).unwrap();
+        let packed = co_signature(&auth, &[9u8; 3309]);
+        let (decoded, pq) = <(WebAuthnAuth, Bytes)>::abi_decode_params(&packed).unwrap();
+        assert_eq!(decoded.r, auth.r);
+        assert_eq!(pq.len(), 3309);
+    }
+}
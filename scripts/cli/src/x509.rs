@@ -0,0 +1,303 @@
+//! X.509 certificates (RFC 5280) and PKCS#10 requests (RFC 2986) for
+//! ML-DSA-65 keys, signed with the key itself (RFC 9881).
+//!
+//! This lets a signer component's TLS identity be the wallet key. The
+//! subject key identifier is the key's address, the same key ID the audit
+//! log and keystore use. A self-signed certificate is an end-entity
+//! certificate (`cA` false) for both `serverAuth` and `clientAuth`, with the
+//! DNS names as subjectAltName. A CSR asks for the same extensions, so a CA
+//! can issue the equivalent certificate.
+//!
+//! The signature algorithm is the bare `id-ml-dsa-65` OID, and the
+//! signature is pure ML-DSA with an empty context over the DER
+//! `TBSCertificate` or `CertificationRequestInfo`.
+
+use ml_dsa::{MlDsa65, Signature, SigningKey, VerifyingKey};
+
+use crate::error::{Result, WalletError};
+use crate::keys::{self, PUBLIC_KEY_LEN};
+use crate::pkix::{
+    self, BIT_STRING, INTEGER, OCTET_STRING, SEQUENCE, expect_end, read_algorithm_identifier,
+    read_tlv, tlv,
+};
+
+/// PEM label of a certificate.
+pub const CERTIFICATE_LABEL: &str = "CERTIFICATE";
+/// PEM label of a PKCS#10 request.
+pub const CSR_LABEL: &str = "CERTIFICATE REQUEST";
+
+const SET: u8 = 0x31;
+const BOOLEAN: u8 = 0x01;
+const UTF8_STRING: u8 = 0x0c;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const DNS_NAME: u8 = 0x82;
+
+const OID_COMMON_NAME: [u8; 5] = [0x06, 0x03, 0x55, 0x04, 0x03];
+const OID_SUBJECT_KEY_ID: [u8; 5] = [0x06, 0x03, 0x55, 0x1d, 0x0e];
+const OID_KEY_USAGE: [u8; 5] = [0x06, 0x03, 0x55, 0x1d, 0x0f];
+const OID_SUBJECT_ALT_NAME: [u8; 5] = [0x06, 0x03, 0x55, 0x1d, 0x11];
+const OID_BASIC_CONSTRAINTS: [u8; 5] = [0x06, 0x03, 0x55, 0x1d, 0x13];
+const OID_EXT_KEY_USAGE: [u8; 5] = [0x06, 0x03, 0x55, 0x1d, 0x25];
+const OID_SERVER_AUTH: [u8; 10] = [0x06, 0x08, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x01];
+const OID_CLIENT_AUTH: [u8; 10] = [0x06, 0x08, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x02];
+/// 1.2.840.113549.1.9.14 (PKCS#9 `extensionRequest`).
+const OID_EXTENSION_REQUEST: [u8; 11] = [
+    0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x0e,
+];
+
+/// Who the certificate names.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identity {
+    pub common_name: String,
+    pub dns_names: Vec<String>,
+}
+
+fn x509_err(reason: impl Into<String>) -> WalletError {
+    WalletError::InvalidEnvelope(format!("X.509: {}", reason.into()))
+}
+
+fn concat(parts: &[&[u8]]) -> Vec<u8> {
+    parts.concat()
+}
+
+fn name(common_name: &str) -> Vec<u8> {
+    let attribute = tlv(
+        SEQUENCE,
+        &concat(&[&OID_COMMON_NAME, &tlv(UTF8_STRING, common_name.as_bytes())]),
+    );
+    tlv(SEQUENCE, &tlv(SET, &attribute))
+}
+
+fn extension(oid: &[u8], critical: bool, value: &[u8]) -> Vec<u8> {
+    let critical = if critical {
+        tlv(BOOLEAN, &[0xff])
+    } else {
+        Vec::new()
+    };
+    tlv(
+        SEQUENCE,
+        &concat(&[oid, &critical, &tlv(OCTET_STRING, value)]),
+    )
+}
+
+/// The extensions of an end-entity mTLS certificate for `pk`.
+fn extensions(pk: &[u8; PUBLIC_KEY_LEN], id: &Identity) -> Vec<u8> {
+    let mut out = concat(&[
+        // cA defaults to FALSE, so the SEQUENCE is empty.
+        &extension(&OID_BASIC_CONSTRAINTS, true, &tlv(SEQUENCE, &[])),
+        // digitalSignature only: one bit, seven unused.
+        &extension(&OID_KEY_USAGE, true, &tlv(BIT_STRING, &[0x07, 0x80])),
+        &extension(
+            &OID_EXT_KEY_USAGE,
+            false,
+            &tlv(SEQUENCE, &concat(&[&OID_SERVER_AUTH, &OID_CLIENT_AUTH])),
+        ),
+        &extension(
+            &OID_SUBJECT_KEY_ID,
+            false,
+            &tlv(OCTET_STRING, keys::address(pk).as_slice()),
+        ),
+    ]);
+    if !id.dns_names.is_empty() {
+        let names: Vec<u8> = id
+            .dns_names
+            .iter()
+            .flat_map(|dns| tlv(DNS_NAME, dns.as_bytes()))
+            .collect();
+        out.extend(extension(
+            &OID_SUBJECT_ALT_NAME,
+            false,
+            &tlv(SEQUENCE, &names),
+        ));
+    }
+    tlv(SEQUENCE, &out)
+}
+
+/// Days since the epoch to (year, month, day), proleptic Gregorian.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// UTCTime through 2049, GeneralizedTime after, as RFC 5280 §4.1.2.5 asks.
+fn time(unix: u64) -> Result<Vec<u8>> {
+    let secs = i64::try_from(unix).map_err(|_| x509_err("time out of range"))?;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let rem = secs.rem_euclid(86_400);
+    let clock = format!(
+        "{month:02}{day:02}{:02}{:02}{:02}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    );
+    match year {
+        ..2050 => Ok(tlv(
+            UTC_TIME,
+            format!("{:02}{clock}", year % 100).as_bytes(),
+        )),
+        2050..=9999 => Ok(tlv(GENERALIZED_TIME, format!("{year}{clock}").as_bytes())),
+        _ => Err(x509_err("time is past 9999")),
+    }
+}
+
+/// Append the algorithm and signature to a signed structure's body.
+fn sign(sk: &SigningKey<MlDsa65>, body: Vec<u8>) -> Vec<u8> {
+    use ml_dsa::signature::Signer;
+
+    let signature = sk.sign(&body).encode();
+    let mut bits = vec![0u8];
+    bits.extend_from_slice(&signature);
+    tlv(
+        SEQUENCE,
+        &concat(&[
+            &body,
+            &pkix::algorithm_identifier(),
+            &tlv(BIT_STRING, &bits),
+        ]),
+    )
+}
+
+/// A self-signed certificate for `sk`'s key, valid from `not_before` to
+/// `not_after` (Unix seconds). `serial` must be positive and at most 20
+/// bytes, without leading zeros.
+pub fn self_signed(
+    sk: &SigningKey<MlDsa65>,
+    id: &Identity,
+    serial: &[u8],
+    not_before: u64,
+    not_after: u64,
+) -> Result<Vec<u8>> {
+    if serial.is_empty() || serial.len() > 20 || serial[0] == 0 || serial[0] & 0x80 != 0 {
+        return Err(x509_err(
+            "serial must be 1 to 20 bytes, positive and minimal",
+        ));
+    }
+    if not_after <= not_before {
+        return Err(x509_err("certificate would expire before it is valid"));
+    }
+    let pk: [u8; PUBLIC_KEY_LEN] = sk.verifying_key().encode().into();
+    let subject = name(&id.common_name);
+    let tbs = tlv(
+        SEQUENCE,
+        &concat(&[
+            // [0] EXPLICIT version v3 (2).
+            &tlv(0xa0, &tlv(INTEGER, &[2])),
+            &tlv(INTEGER, serial),
+            &pkix::algorithm_identifier(),
+            &subject,
+            &tlv(SEQUENCE, &concat(&[&time(not_before)?, &time(not_after)?])),
+            &subject,
+            &pkix::public_key_to_der(&pk),
+            &tlv(0xa3, &extensions(&pk, id)),
+        ]),
+    );
+    Ok(sign(sk, tbs))
+}
+
+/// A PKCS#10 request for `sk`'s key, asking for the same extensions as
+/// [`self_signed`].
+pub fn csr(sk: &SigningKey<MlDsa65>, id: &Identity) -> Vec<u8> {
+    let pk: [u8; PUBLIC_KEY_LEN] = sk.verifying_key().encode().into();
+    let extension_request = tlv(
+        SEQUENCE,
+        &concat(&[&OID_EXTENSION_REQUEST, &tlv(SET, &extensions(&pk, id))]),
+    );
+    let info = tlv(
+        SEQUENCE,
+        &concat(&[
+            &tlv(INTEGER, &[0]),
+            &name(&id.common_name),
+            &pkix::public_key_to_der(&pk),
+            // [0] IMPLICIT SET OF Attribute.
+            &tlv(0xa0, &extension_request),
+        ]),
+    );
+    sign(sk, info)
+}
+
+/// Whether a certificate or CSR's signature verifies under `pk`. Only the
+/// outer structure is parsed.
+pub fn verify(der: &[u8], pk: &[u8; PUBLIC_KEY_LEN]) -> Result<bool> {
+    let (outer, rest) = read_tlv(der, SEQUENCE)?;
+    expect_end(rest)?;
+    let (_, after_body) = read_tlv(outer, SEQUENCE)?;
+    let body = &outer[..outer.len() - after_body.len()];
+    let (bits, rest) = read_tlv(read_algorithm_identifier(after_body)?, BIT_STRING)?;
+    expect_end(rest)?;
+    let signature = match bits.split_first() {
+        Some((0, sig)) => Signature::<MlDsa65>::try_from(sig)
+            .map_err(|_| x509_err("signature is not an ML-DSA-65 signature"))?,
+        _ => return Err(x509_err("signature BIT STRING has unused bits")),
+    };
+    let vk = VerifyingKey::<MlDsa65>::decode(&(*pk).into());
+    Ok(vk.verify_with_context(body, &[], &signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::SEED_LEN;
+
+    fn identity() -> Identity {
+        Identity {
+            common_name: "signer".to_string(),
+            dns_names: vec!["signer.internal".to_string()],
+        }
+    }
+
+    #[test]
+    fn certificates_and_requests_verify_under_their_key() {
+        let sk = SigningKey::<MlDsa65>::from_seed(&[3u8; SEED_LEN].into());
+        let pk: [u8; PUBLIC_KEY_LEN] = sk.verifying_key().encode().into();
+        let other: [u8; PUBLIC_KEY_LEN] = SigningKey::<MlDsa65>::from_seed(&[4u8; SEED_LEN].into())
+            .verifying_key()
+            .encode()
+            .into();
+
+        let cert = self_signed(
+            &sk,
+            &identity(),
+            &[0x01, 0x02],
+            1_700_000_000,
+            1_800_000_000,
+        )
+        .unwrap();
+        assert!(verify(&cert, &pk).unwrap());
+        assert!(!verify(&cert, &other).unwrap());
+        // The SubjectPublicKeyInfo and key ID are in there verbatim.
+        let spki = pkix::public_key_to_der(&pk);
+        assert!(cert.windows(spki.len()).any(|w| w == spki));
+        assert!(cert.windows(20).any(|w| w == keys::address(&pk).as_slice()));
+
+        let request = csr(&sk, &identity());
+        assert!(verify(&request, &pk).unwrap());
+        let mut tampered = request.clone();
+        let cn = tampered.windows(6).position(|w| w == b"signer").unwrap();
+        tampered[cn] = b'S';
+        assert!(!verify(&tampered, &pk).unwrap());
+
+        assert!(self_signed(&sk, &identity(), &[0x80], 0, 1).is_err());
+    }
+
+    #[test]
+    fn times_switch_to_generalized_time_in_2050() {
+        assert_eq!(time(0).unwrap(), tlv(UTC_TIME, b"700101000000Z"));
+        assert_eq!(
+            time(1_709_210_096).unwrap(),
+            tlv(UTC_TIME, b"240229123456Z")
+        );
+        assert_eq!(
+            time(2_524_608_000).unwrap(),
+            tlv(GENERALIZED_TIME, b"20500101000000Z")
+        );
+    }
+}
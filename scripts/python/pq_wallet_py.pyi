@@ -0,0 +1,48 @@
+from typing import Any, Optional, Union
+
+ENTRY_POINT_V07: str
+
+class WalletError(Exception): ...
+class InvalidHexError(WalletError): ...
+class InvalidLengthError(WalletError): ...
+class InvalidEnvelopeError(WalletError): ...
+class UnknownSchemeError(WalletError): ...
+class UnsupportedEnvelopeVersionError(WalletError): ...
+class BackendError(WalletError): ...
+class InvalidUserOpError(WalletError): ...
+
+UserOp = Union[str, dict[str, Any]]
+Call = tuple[str, int, bytes]
+
+def keygen_from_seed(seed: bytes) -> bytes: ...
+def sign(seed: bytes, message: bytes, context: Optional[bytes] = None) -> bytes: ...
+def verify(
+    public_key: bytes, message: bytes, signature: bytes, context: Optional[bytes] = None
+) -> bool: ...
+def user_op_hash(user_op: UserOp, chain_id: int, entry_point: Optional[str] = None) -> bytes: ...
+def encode_envelope(public_key: bytes, signature: bytes, layout: str = "abi") -> bytes: ...
+def decode_envelope(data: bytes, layout: str = "abi") -> Envelope: ...
+
+class Envelope:
+    version: Optional[int]
+    scheme_id: int
+    public_key: bytes
+    signature: bytes
+    ecdsa_signature: Optional[bytes]
+    owner_signature: Optional[bytes]
+
+class Pipeline:
+    def __init__(
+        self, chain_id: int, entry_point: Optional[str] = None, workers: Optional[int] = None
+    ) -> None: ...
+    def gas(
+        self,
+        *,
+        verification_gas_limit: Optional[int] = None,
+        call_gas_limit: Optional[int] = None,
+        pre_verification_gas: Optional[int] = None,
+        max_priority_fee_per_gas: Optional[int] = None,
+        max_fee_per_gas: Optional[int] = None,
+    ) -> Pipeline: ...
+    def account(self, sender: str, seed: bytes, next_nonce: Optional[int] = None) -> Pipeline: ...
+    def run(self, intents: list[tuple[str, list[Call]]]) -> list[dict[str, Any]]: ...
@@ -0,0 +1,419 @@
+//! Python bindings for the pq-cli library: the `pq_wallet_py` module.
+//!
+//! Build a wheel with `maturin build --release` in this directory, or
+//! install into the active virtualenv with `maturin develop`. The crate is
+//! kept out of the Cargo workspace so the workspace builds without a Python
+//! toolchain.
+//!
+//! Keys, signatures, envelopes and hashes are `bytes`; addresses are `0x`
+//! hex strings; UserOperations are the v0.7 JSON-RPC shape `pq-userop sign`
+//! writes, as a `str` or a `dict`. Signing is the deterministic pure
+//! ML-DSA-65 `pq-sign` does, through the RustCrypto backend. Every failure
+//! raises a subclass of `WalletError` named after the library's
+//! `WalletError` variant (`InvalidLength` raises `InvalidLengthError`).
+
+use std::str::FromStr;
+
+use alloy_primitives::{Address, U256};
+use pq_cli::backend::Backend;
+use pq_cli::calldata::Call;
+use pq_cli::envelope::{self, Envelope, SCHEME_ML_DSA_65, SignatureEnvelope};
+use pq_cli::error::WalletError as Error;
+use pq_cli::keys::SEED_LEN;
+use pq_cli::pipeline::{self, Intent};
+use pq_cli::strict::EnvelopeLayout;
+use pq_cli::userop::{self, PackedUserOperation, RpcUserOperation};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+use zeroize::Zeroizing;
+
+create_exception!(
+    pq_wallet_py,
+    WalletError,
+    PyException,
+    "An error from the pq-cli library."
+);
+create_exception!(
+    pq_wallet_py,
+    InvalidHexError,
+    WalletError,
+    "Input was not valid hexadecimal."
+);
+create_exception!(
+    pq_wallet_py,
+    InvalidLengthError,
+    WalletError,
+    "A byte string had the wrong length for its role."
+);
+create_exception!(
+    pq_wallet_py,
+    InvalidEnvelopeError,
+    WalletError,
+    "Signature envelope bytes could not be decoded."
+);
+create_exception!(
+    pq_wallet_py,
+    UnknownSchemeError,
+    WalletError,
+    "Scheme ID not known to this build."
+);
+create_exception!(
+    pq_wallet_py,
+    UnsupportedEnvelopeVersionError,
+    WalletError,
+    "Versioned envelope whose version byte this build does not know."
+);
+create_exception!(
+    pq_wallet_py,
+    BackendError,
+    WalletError,
+    "The ML-DSA backend reported a failure."
+);
+create_exception!(
+    pq_wallet_py,
+    InvalidUserOpError,
+    WalletError,
+    "UserOperation fields are inconsistent or out of range."
+);
+
+/// `e` as the exception of its variant, or as the base `WalletError` for
+/// variants nothing here raises.
+fn raise(e: Error) -> PyErr {
+    let message = e.to_string();
+    match e {
+        Error::InvalidHex => InvalidHexError::new_err(message),
+        Error::InvalidLength { .. } => InvalidLengthError::new_err(message),
+        Error::InvalidEnvelope(_) => InvalidEnvelopeError::new_err(message),
+        Error::UnknownScheme(_) => UnknownSchemeError::new_err(message),
+        Error::UnsupportedEnvelopeVersion(_) => UnsupportedEnvelopeVersionError::new_err(message),
+        Error::Backend(_) => BackendError::new_err(message),
+        Error::InvalidUserOp(_) => InvalidUserOpError::new_err(message),
+        _ => WalletError::new_err(message),
+    }
+}
+
+fn seed(bytes: &[u8]) -> PyResult<Zeroizing<[u8; SEED_LEN]>> {
+    let seed = <[u8; SEED_LEN]>::try_from(bytes).map_err(|_| {
+        raise(Error::InvalidLength {
+            what: "seed",
+            expected: SEED_LEN,
+            actual: bytes.len(),
+        })
+    })?;
+    Ok(Zeroizing::new(seed))
+}
+
+fn address(s: &str) -> PyResult<Address> {
+    Address::from_str(s).map_err(|_| raise(Error::InvalidHex))
+}
+
+/// A Python `int` as a `uint256`; out of range raises `OverflowError`.
+fn u256(value: &Bound<'_, PyAny>) -> PyResult<U256> {
+    let bytes = value.call_method1("to_bytes", (32, "big"))?;
+    Ok(U256::from_be_slice(bytes.downcast::<PyBytes>()?.as_bytes()))
+}
+
+fn user_op(op: &Bound<'_, PyAny>) -> PyResult<PackedUserOperation> {
+    let json: String = match op.extract() {
+        Ok(json) => json,
+        Err(_) => op
+            .py()
+            .import("json")?
+            .call_method1("dumps", (op,))?
+            .extract()?,
+    };
+    let op: RpcUserOperation =
+        serde_json::from_str(&json).map_err(|e| raise(Error::InvalidUserOp(e.to_string())))?;
+    PackedUserOperation::try_from(op).map_err(raise)
+}
+
+fn user_op_dict<'py>(py: Python<'py>, op: PackedUserOperation) -> PyResult<Bound<'py, PyAny>> {
+    let json =
+        serde_json::to_string(&RpcUserOperation::from(op)).expect("UserOperation serializes");
+    py.import("json")?.call_method1("loads", (json,))
+}
+
+/// The 1,952-byte ML-DSA-65 public key for a 32-byte seed.
+#[pyfunction]
+fn keygen_from_seed<'py>(py: Python<'py>, seed: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    let seed = self::seed(seed)?;
+    Ok(PyBytes::new(py, &Backend::RustCrypto.public_key(&seed)))
+}
+
+/// The 3,309-byte signature over `message` by the key for `seed`.
+#[pyfunction]
+#[pyo3(signature = (seed, message, context = None))]
+fn sign<'py>(
+    py: Python<'py>,
+    seed: &[u8],
+    message: &[u8],
+    context: Option<&[u8]>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let seed = self::seed(seed)?;
+    let context = context.unwrap_or_default();
+    let signature = py
+        .allow_threads(|| Backend::RustCrypto.sign(&seed, message, context, &[0; 32]))
+        .map_err(raise)?;
+    Ok(PyBytes::new(py, &signature))
+}
+
+/// Whether `signature` is `public_key`'s over `message`. Malformed keys
+/// and signatures verify as `False`.
+#[pyfunction]
+#[pyo3(signature = (public_key, message, signature, context = None))]
+fn verify(
+    py: Python<'_>,
+    public_key: &[u8],
+    message: &[u8],
+    signature: &[u8],
+    context: Option<&[u8]>,
+) -> bool {
+    let context = context.unwrap_or_default();
+    py.allow_threads(|| Backend::RustCrypto.verify(public_key, message, context, signature))
+}
+
+/// The EntryPoint v0.7 userOpHash of `user_op` on `chain_id`.
+#[pyfunction]
+#[pyo3(signature = (user_op, chain_id, entry_point = None))]
+fn user_op_hash<'py>(
+    py: Python<'py>,
+    user_op: &Bound<'py, PyAny>,
+    chain_id: u64,
+    entry_point: Option<&str>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let op = self::user_op(user_op)?;
+    let entry_point = entry_point.map_or(Ok(userop::ENTRY_POINT_V07), address)?;
+    let hash = userop::compute_user_op_hash(&op, entry_point, U256::from(chain_id));
+    Ok(PyBytes::new(py, hash.as_slice()))
+}
+
+/// `public_key` and `signature` in an envelope: `abi`, `packed` or
+/// `versioned` (V1), as `pq-sign --envelope` writes them.
+#[pyfunction]
+#[pyo3(signature = (public_key, signature, layout = "abi"))]
+fn encode_envelope<'py>(
+    py: Python<'py>,
+    public_key: &[u8],
+    signature: &[u8],
+    layout: &str,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let layout = EnvelopeLayout::from_str(layout).map_err(PyValueError::new_err)?;
+    let encoded = match layout {
+        EnvelopeLayout::Abi => {
+            envelope::encode_signature_envelope(public_key, signature, SCHEME_ML_DSA_65)
+        }
+        EnvelopeLayout::Packed => envelope::encode_signature_envelope_packed(public_key, signature),
+        EnvelopeLayout::Versioned => Envelope::V1(SignatureEnvelope {
+            scheme_id: SCHEME_ML_DSA_65,
+            public_key: public_key.to_vec(),
+            signature: signature.to_vec(),
+        })
+        .encode(),
+    };
+    Ok(PyBytes::new(py, &encoded.map_err(raise)?))
+}
+
+/// A decoded signature envelope.
+#[pyclass(name = "Envelope", module = "pq_wallet_py", frozen, get_all)]
+struct DecodedEnvelope {
+    /// 1 to 3 for the `versioned` layout, `None` for `abi` and `packed`.
+    version: Option<u8>,
+    scheme_id: u8,
+    /// In a V3 envelope, the robot key.
+    public_key: Py<PyBytes>,
+    signature: Py<PyBytes>,
+    /// The V2 ECDSA co-signature, `r || s || v`.
+    ecdsa_signature: Option<Py<PyBytes>>,
+    /// The V3 owner key's signature over the delegation.
+    owner_signature: Option<Py<PyBytes>>,
+}
+
+/// Decode `data` as an envelope in `layout`.
+#[pyfunction]
+#[pyo3(signature = (data, layout = "abi"))]
+fn decode_envelope(py: Python<'_>, data: &[u8], layout: &str) -> PyResult<DecodedEnvelope> {
+    let layout = EnvelopeLayout::from_str(layout).map_err(PyValueError::new_err)?;
+    let bytes = |b: &[u8]| PyBytes::new(py, b).unbind();
+    let (version, pq, ecdsa, owner) = match layout {
+        EnvelopeLayout::Abi => (None, envelope::decode_signature_envelope(data), None, None),
+        EnvelopeLayout::Packed => (
+            None,
+            envelope::decode_signature_envelope_packed(data),
+            None,
+            None,
+        ),
+        EnvelopeLayout::Versioned => {
+            let env = Envelope::decode(data).map_err(raise)?;
+            let (ecdsa, owner) = match &env {
+                Envelope::V1(_) => (None, None),
+                Envelope::V2(v2) => (Some(bytes(&v2.ecdsa_signature)), None),
+                Envelope::V3(v3) => (None, Some(bytes(&v3.owner_signature))),
+            };
+            (Some(env.version()), Ok(env.pq().clone()), ecdsa, owner)
+        }
+    };
+    let pq = pq.map_err(raise)?;
+    Ok(DecodedEnvelope {
+        version,
+        scheme_id: pq.scheme_id,
+        public_key: bytes(&pq.public_key),
+        signature: bytes(&pq.signature),
+        ecdsa_signature: ecdsa,
+        owner_signature: owner,
+    })
+}
+
+/// Bulk op building and signing on a thread pool (the library's
+/// `Pipeline`). Configure it, add each sender with `account`, then `run`.
+#[pyclass(module = "pq_wallet_py")]
+struct Pipeline {
+    chain_id: u64,
+    entry_point: Address,
+    gas: pipeline::Gas,
+    workers: Option<usize>,
+    accounts: Vec<(Address, Zeroizing<[u8; SEED_LEN]>, U256)>,
+}
+
+#[pymethods]
+impl Pipeline {
+    /// Ops for `chain_id` through `entry_point` (EntryPoint v0.7 by
+    /// default), on `workers` threads (one per CPU by default).
+    #[new]
+    #[pyo3(signature = (chain_id, entry_point = None, workers = None))]
+    fn new(chain_id: u64, entry_point: Option<&str>, workers: Option<usize>) -> PyResult<Self> {
+        Ok(Pipeline {
+            chain_id,
+            entry_point: entry_point.map_or(Ok(userop::ENTRY_POINT_V07), address)?,
+            gas: pipeline::Gas::default(),
+            workers,
+            accounts: Vec::new(),
+        })
+    }
+
+    /// Set the gas fields every op gets; those not given keep their value.
+    #[pyo3(signature = (
+        *,
+        verification_gas_limit = None,
+        call_gas_limit = None,
+        pre_verification_gas = None,
+        max_priority_fee_per_gas = None,
+        max_fee_per_gas = None,
+    ))]
+    fn gas<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        verification_gas_limit: Option<u128>,
+        call_gas_limit: Option<u128>,
+        pre_verification_gas: Option<Bound<'py, PyAny>>,
+        max_priority_fee_per_gas: Option<u128>,
+        max_fee_per_gas: Option<u128>,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let gas = &mut slf.gas;
+        gas.verification_gas_limit = verification_gas_limit.unwrap_or(gas.verification_gas_limit);
+        gas.call_gas_limit = call_gas_limit.unwrap_or(gas.call_gas_limit);
+        if let Some(pvg) = pre_verification_gas {
+            gas.pre_verification_gas = u256(&pvg)?;
+        }
+        gas.max_priority_fee_per_gas =
+            max_priority_fee_per_gas.unwrap_or(gas.max_priority_fee_per_gas);
+        gas.max_fee_per_gas = max_fee_per_gas.unwrap_or(gas.max_fee_per_gas);
+        Ok(slf)
+    }
+
+    /// Sign `sender`'s ops with `seed`, numbering them from `next_nonce`
+    /// (the EntryPoint's `getNonce` for the sender).
+    #[pyo3(signature = (sender, seed, next_nonce = None))]
+    fn account<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        sender: &str,
+        seed: &[u8],
+        next_nonce: Option<Bound<'py, PyAny>>,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let nonce = next_nonce.as_ref().map_or(Ok(U256::ZERO), u256)?;
+        let account = (address(sender)?, self::seed(seed)?, nonce);
+        slf.accounts.push(account);
+        Ok(slf)
+    }
+
+    /// Build and sign an op per `(sender, calls)` intent, each call a
+    /// `(target, value, data)` tuple. Returns `{index, user_op_hash,
+    /// user_op}` dicts, in nonce order per sender; raises the first error.
+    fn run<'py>(
+        &self,
+        py: Python<'py>,
+        intents: Vec<(String, Vec<(String, Bound<'py, PyAny>, Vec<u8>)>)>,
+    ) -> PyResult<Bound<'py, PyList>> {
+        let intents = intents
+            .into_iter()
+            .map(|(sender, calls)| {
+                let calls = calls
+                    .into_iter()
+                    .map(|(target, value, data)| {
+                        Ok(Call {
+                            target: address(&target)?,
+                            value: u256(&value)?,
+                            data,
+                        })
+                    })
+                    .collect::<PyResult<_>>()?;
+                Ok(Intent {
+                    sender: address(&sender)?,
+                    calls,
+                })
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let mut builder = pipeline::Pipeline::new(self.chain_id)
+            .entry_point(self.entry_point)
+            .gas(self.gas);
+        if let Some(workers) = self.workers {
+            builder = builder.workers(workers);
+        }
+        for (sender, seed, nonce) in &self.accounts {
+            builder = builder.account(*sender, seed, *nonce);
+        }
+        let signed: Vec<_> = py.allow_threads(|| builder.run(intents).collect());
+        let out = PyList::empty(py);
+        for result in signed {
+            let signed = result.map_err(raise)?;
+            let item = PyDict::new(py);
+            item.set_item("index", signed.index)?;
+            item.set_item(
+                "user_op_hash",
+                PyBytes::new(py, signed.user_op_hash.as_slice()),
+            )?;
+            item.set_item("user_op", user_op_dict(py, signed.op)?)?;
+            out.append(item)?;
+        }
+        Ok(out)
+    }
+}
+
+#[pymodule]
+fn pq_wallet_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("WalletError", py.get_type::<WalletError>())?;
+    m.add("InvalidHexError", py.get_type::<InvalidHexError>())?;
+    m.add("InvalidLengthError", py.get_type::<InvalidLengthError>())?;
+    m.add(
+        "InvalidEnvelopeError",
+        py.get_type::<InvalidEnvelopeError>(),
+    )?;
+    m.add("UnknownSchemeError", py.get_type::<UnknownSchemeError>())?;
+    m.add(
+        "UnsupportedEnvelopeVersionError",
+        py.get_type::<UnsupportedEnvelopeVersionError>(),
+    )?;
+    m.add("BackendError", py.get_type::<BackendError>())?;
+    m.add("InvalidUserOpError", py.get_type::<InvalidUserOpError>())?;
+    m.add("ENTRY_POINT_V07", userop::ENTRY_POINT_V07.to_string())?;
+    m.add_class::<DecodedEnvelope>()?;
+    m.add_class::<Pipeline>()?;
+    m.add_function(wrap_pyfunction!(keygen_from_seed, m)?)?;
+    m.add_function(wrap_pyfunction!(sign, m)?)?;
+    m.add_function(wrap_pyfunction!(verify, m)?)?;
+    m.add_function(wrap_pyfunction!(user_op_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(encode_envelope, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_envelope, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,44 @@
+import pytest
+
+import pq_wallet_py as pq
+
+SEED = bytes(range(32))
+SENDER = "0x" + "11" * 20
+
+
+def test_sign_verify_and_envelopes():
+    pk = pq.keygen_from_seed(SEED)
+    assert len(pk) == 1952
+    sig = pq.sign(SEED, b"hello")
+    assert len(sig) == 3309
+    assert sig == pq.sign(SEED, b"hello"), "signing is deterministic"
+    assert pq.verify(pk, b"hello", sig)
+    assert not pq.verify(pk, b"hullo", sig)
+
+    for layout in ("abi", "packed", "versioned"):
+        env = pq.decode_envelope(pq.encode_envelope(pk, sig, layout), layout)
+        assert (env.public_key, env.signature) == (pk, sig)
+    assert pq.decode_envelope(pq.encode_envelope(pk, sig, "versioned"), "versioned").version == 1
+
+    with pytest.raises(pq.InvalidLengthError):
+        pq.keygen_from_seed(SEED[:31])
+    with pytest.raises(pq.InvalidEnvelopeError):
+        pq.decode_envelope(b"\x01", "versioned")
+    with pytest.raises(ValueError):
+        pq.encode_envelope(pk, sig, "cbor")
+
+
+def test_pipeline_signs_ops_that_hash_back():
+    pipeline = pq.Pipeline(421614, workers=2).gas(max_fee_per_gas=10**9).account(SENDER, SEED, 7)
+    ops = pipeline.run([(SENDER, [("0x" + "22" * 20, 1, b"")])] * 2)
+    assert [op["user_op"]["nonce"] for op in ops] == ["0x7", "0x8"]
+    pk = pq.keygen_from_seed(SEED)
+    for op in ops:
+        assert pq.user_op_hash(op["user_op"], 421614) == op["user_op_hash"]
+        signature = bytes.fromhex(op["user_op"]["signature"][2:])
+        assert pq.verify(pk, op["user_op_hash"], signature)
+
+    with pytest.raises(pq.WalletError):
+        pipeline.run([("0x" + "33" * 20, [])])
+    with pytest.raises(pq.InvalidUserOpError):
+        pq.user_op_hash({"sender": SENDER}, 421614)
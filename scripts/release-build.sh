@@ -0,0 +1,56 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+# ============================================================
+# Reproducible release build of the pq-cli binaries
+# Builds with fixed paths, timestamps and codegen settings from Cargo.lock,
+# so two builders at the same commit get the same bytes. Given a release
+# key, signs a manifest of the binaries with pq-release attest, and the
+# deployment registry with pq-release sign-registry.
+# Usage: ./scripts/release-build.sh [--key release/sk.bin | --account NAME]
+# ============================================================
+
+PROJECT_ROOT="$(cd "$(dirname "$0")/.." && pwd)"
+cd "$PROJECT_ROOT"
+
+if [ ! -f Cargo.lock ]; then
+  echo "error: Cargo.lock is missing; build from the lockfile the release was cut with" >&2
+  exit 1
+fi
+
+TARGET="${TARGET:-$(rustc -vV | sed -n 's/^host: //p')}"
+COMMIT=$(git rev-parse HEAD)
+if [ -n "$(git status --porcelain --untracked-files=no)" ]; then
+  echo "warning: the tree has uncommitted changes; the build will not match $COMMIT" >&2
+fi
+
+# Nothing host-specific may reach the binaries: timestamps come from the
+# commit, and source paths are rewritten to fixed prefixes.
+SOURCE_DATE_EPOCH=$(git log -1 --format=%ct)
+export SOURCE_DATE_EPOCH
+export CARGO_INCREMENTAL=0
+export CARGO_PROFILE_RELEASE_CODEGEN_UNITS=1
+export CARGO_PROFILE_RELEASE_DEBUG=0
+export CARGO_PROFILE_RELEASE_STRIP=symbols
+export RUSTFLAGS="--remap-path-prefix=$PROJECT_ROOT=/build \
+--remap-path-prefix=${CARGO_HOME:-$HOME/.cargo}=/cargo \
+--remap-path-prefix=$(rustc --print sysroot)=/rustc"
+
+cargo build --release --locked --target "$TARGET" -p pq-cli --bins
+
+OUT="$PROJECT_ROOT/target/$TARGET/release"
+mapfile -t BINS < <(find "$OUT" -maxdepth 1 -type f -name 'pq-*' -perm -u+x | sort)
+
+echo "Built from $COMMIT with $(rustc --version), Cargo.lock $(sha256sum Cargo.lock | cut -d' ' -f1)"
+(cd "$OUT" && sha256sum "${BINS[@]##*/}")
+
+if [ "$#" -gt 0 ]; then
+  "$OUT/pq-release" attest "$@" \
+    --commit "$COMMIT" \
+    --target "$TARGET" \
+    --output "$OUT/release.json" \
+    "${BINS[@]}"
+  "$OUT/pq-release" sign-registry "$@" \
+    --registry scripts/cli/registry.json \
+    --output "$OUT/registry.json"
+fi